@@ -0,0 +1,30 @@
+struct Params {
+    // 这一帧的权重, 1 / 累积的帧数
+    weight: f32,
+    _pad0: f32,
+    _pad1: f32,
+    _pad2: f32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var current: texture_2d<f32>;
+// 历史用 rgba32float 存, 几千帧平均下来 16 位浮点不够
+@group(0) @binding(2) var history: texture_2d<f32>;
+@group(0) @binding(3) var next_history: texture_storage_2d<rgba32float, write>;
+@group(0) @binding(4) var result: texture_storage_2d<rgba16float, write>;
+
+@compute @workgroup_size(8, 8)
+fn accumulate(@builtin(global_invocation_id) id: vec3u) {
+    let size = textureDimensions(current);
+    if any(id.xy >= size) {
+        return;
+    }
+    let frame = textureLoad(current, id.xy, 0);
+    // 重置后的第一帧不读历史, 里面是什么都不管
+    var average = frame;
+    if params.weight < 1.0 {
+        average = mix(textureLoad(history, id.xy, 0), frame, params.weight);
+    }
+    textureStore(next_history, id.xy, average);
+    textureStore(result, id.xy, average);
+}
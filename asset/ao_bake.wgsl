@@ -0,0 +1,135 @@
+struct AoBakeParams {
+    // 地面那块和 lightmap 一样
+    center: vec2f,
+    extent: f32,
+    samples: u32,
+    ground_resolution: u32,
+    face_resolution: u32,
+    radius: f32,
+}
+struct StaticBox {
+    center: vec4f,
+    half_size: vec4f,
+    albedo: vec4f,
+}
+struct StaticGeometry {
+    count: u32,
+    lightmap: vec4f,
+    ao: vec4f,
+    boxes: array<StaticBox, 16>,
+}
+
+@group(0) @binding(0) var<uniform> params: AoBakeParams;
+@group(0) @binding(1) var<uniform> static_geometry: StaticGeometry;
+// 上面是地面, 下面每个盒子一行, 每个面一格; r 是遮蔽后剩下的比例
+@group(0) @binding(2) var ao_map: texture_storage_2d<rgba16float, write>;
+
+const PI: f32 = 3.14159265;
+
+struct BoxHit {
+    // < 0 = 没打中
+    t: f32,
+    normal: vec3f,
+    index: u32,
+}
+
+// 和 sky.wgsl 里的一样, slab 求交
+fn trace_boxes(origin: vec3f, dir: vec3f) -> BoxHit {
+    var hit = BoxHit(-1.0, vec3(0.0), 0u);
+    let inv = 1.0 / dir;
+    for (var i = 0u; i < static_geometry.count; i++) {
+        let b = static_geometry.boxes[i];
+        let t0 = (b.center.xyz - b.half_size.xyz - origin) * inv;
+        let t1 = (b.center.xyz + b.half_size.xyz - origin) * inv;
+        let near = min(t0, t1);
+        let far = max(t0, t1);
+        let t_near = max(max(near.x, near.y), near.z);
+        let t_far = min(min(far.x, far.y), far.z);
+        if t_near > 0.0 && t_near <= t_far && (hit.t < 0.0 || t_near < hit.t) {
+            hit.t = t_near;
+            hit.normal = -sign(dir) * step(vec3(t_near), near);
+            hit.index = i;
+        }
+    }
+    return hit;
+}
+
+// 面的顺序 +X -X +Y -Y +Z -Z, 每个面上 uv 用哪两个轴, 和 sky.wgsl 一致
+fn face_axes(face: u32) -> vec2u {
+    switch face / 2u {
+        case 0u: { return vec2(2u, 1u); }
+        case 1u: { return vec2(0u, 2u); }
+        default: { return vec2(0u, 1u); }
+    }
+}
+
+fn hash(p: vec3u) -> f32 {
+    var h = p.x * 747796405u + p.y * 2891336453u + p.z * 277803737u;
+    h = ((h >> ((h >> 28u) + 4u)) ^ h) * 277803737u;
+    h = (h >> 22u) ^ h;
+    return f32(h) / 4294967295.0;
+}
+
+fn orthonormal(n: vec3f) -> mat3x3<f32> {
+    let t = normalize(cross(n, select(vec3(0.0, 1.0, 0.0), vec3(1.0, 0.0, 0.0), abs(n.y) > 0.99)));
+    return mat3x3(t, cross(n, t), n);
+}
+
+fn occluded(origin: vec3f, dir: vec3f) -> bool {
+    let hit = trace_boxes(origin, dir);
+    if hit.t > 0.0 && hit.t < params.radius {
+        return true;
+    }
+    // 盒子侧面往下的射线会被地面挡住
+    return dir.y < 0.0 && -origin.y / dir.y < params.radius;
+}
+
+fn ambient_occlusion(p: vec3f, n: vec3f, texel: vec2u) -> f32 {
+    let basis = orthonormal(n);
+    var open = 0.0;
+    for (var s = 0u; s < params.samples; s++) {
+        let u = vec2(hash(vec3(texel, s * 2u)), hash(vec3(texel, s * 2u + 1u)));
+        let r = sqrt(u.x);
+        let phi = 2.0 * PI * u.y;
+        let dir = basis * vec3(r * cos(phi), r * sin(phi), sqrt(1.0 - u.x));
+        if !occluded(p + n * 0.001, dir) {
+            open += 1.0;
+        }
+    }
+    return open / f32(params.samples);
+}
+
+@compute @workgroup_size(8, 8)
+fn bake(@builtin(global_invocation_id) id: vec3u) {
+    let size = textureDimensions(ao_map);
+    if any(id.xy >= size) {
+        return;
+    }
+    let ground = params.ground_resolution;
+    let tile = params.face_resolution;
+    var ao = 1.0;
+    if id.y < ground {
+        if id.x < ground {
+            let uv = (vec2f(id.xy) + 0.5) / f32(ground);
+            let xz = params.center + (uv - 0.5) * params.extent;
+            ao = ambient_occlusion(vec3(xz.x, 0.0, xz.y), vec3(0.0, 1.0, 0.0), id.xy);
+        }
+    } else {
+        let index = (id.y - ground) / tile;
+        let face = id.x / tile;
+        if index < static_geometry.count && face < 6u {
+            let b = static_geometry.boxes[index];
+            let uv = (vec2f(vec2(id.x % tile, (id.y - ground) % tile)) + 0.5) / f32(tile);
+            let axis = face / 2u;
+            let side = select(-1.0, 1.0, face % 2u == 0u);
+            var normal = vec3(0.0);
+            normal[axis] = side;
+            let axes = face_axes(face);
+            var local = normal * b.half_size.xyz;
+            local[axes.x] = (uv.x - 0.5) * 2.0 * b.half_size[axes.x];
+            local[axes.y] = (uv.y - 0.5) * 2.0 * b.half_size[axes.y];
+            ao = ambient_occlusion(b.center.xyz + local, normal, id.xy);
+        }
+    }
+    textureStore(ao_map, id.xy, vec4(ao, ao, ao, 1.0));
+}
@@ -0,0 +1,100 @@
+struct Params {
+    min_log_lum: f32,
+    log_lum_range: f32,
+    dt: f32,
+    // 适应速度, 越大越快
+    speed: f32,
+    min_ev: f32,
+    max_ev: f32,
+    // 只平均这两个百分位之间的像素, 去掉最暗和最亮的
+    low_percent: f32,
+    high_percent: f32,
+}
+
+struct ExposureState {
+    ev: f32,
+    avg_lum: f32,
+    _pad0: f32,
+    _pad1: f32,
+}
+
+@group(0) @binding(0) var hdr_texture: texture_2d<f32>;
+@group(0) @binding(1) var<storage, read_write> histogram: array<atomic<u32>, 256>;
+@group(0) @binding(2) var<storage, read_write> state: ExposureState;
+@group(0) @binding(3) var<uniform> params: Params;
+
+fn luminance(c: vec3f) -> f32 {
+    return dot(c, vec3(0.2126, 0.7152, 0.0722));
+}
+
+// bin 0 is reserved for (almost) black pixels
+fn bin_of(lum: f32) -> u32 {
+    if lum < 0.0001 {
+        return 0u;
+    }
+    let t = clamp((log2(lum) - params.min_log_lum) / params.log_lum_range, 0.0, 1.0);
+    return u32(t * 254.0 + 1.0);
+}
+
+var<workgroup> local_bins: array<atomic<u32>, 256>;
+
+@compute @workgroup_size(16, 16)
+fn build_histogram(
+    @builtin(global_invocation_id) gid: vec3u,
+    @builtin(local_invocation_index) lid: u32,
+) {
+    atomicStore(&local_bins[lid], 0u);
+    workgroupBarrier();
+
+    let size = textureDimensions(hdr_texture);
+    if gid.x < size.x && gid.y < size.y {
+        let color = textureLoad(hdr_texture, vec2i(gid.xy), 0).rgb;
+        atomicAdd(&local_bins[bin_of(luminance(color))], 1u);
+    }
+    workgroupBarrier();
+
+    atomicAdd(&histogram[lid], atomicLoad(&local_bins[lid]));
+}
+
+var<workgroup> bins: array<u32, 256>;
+
+@compute @workgroup_size(256)
+fn average_histogram(@builtin(local_invocation_index) lid: u32) {
+    bins[lid] = atomicLoad(&histogram[lid]);
+    // clear for the next frame
+    atomicStore(&histogram[lid], 0u);
+    workgroupBarrier();
+
+    if lid != 0u {
+        return;
+    }
+    var total = 0u;
+    for (var i = 0u; i < 256u; i++) {
+        total += bins[i];
+    }
+    let low = f32(total) * params.low_percent;
+    let high = f32(total) * params.high_percent;
+
+    var seen = f32(bins[0]);
+    var sum = 0.0;
+    var count = 0.0;
+    for (var i = 1u; i < 256u; i++) {
+        let n = f32(bins[i]);
+        // the part of this bin that falls between the two percentiles
+        let inside = max(min(seen + n, high) - max(seen, low), 0.0);
+        seen += n;
+        sum += inside * (f32(i) + 0.5);
+        count += inside;
+    }
+
+    var avg_log = params.min_log_lum;
+    if count > 0.0 {
+        avg_log = (sum / count - 1.0) / 254.0 * params.log_lum_range + params.min_log_lum;
+    }
+    let avg_lum = exp2(avg_log);
+    // 把平均亮度拉到 18% 中灰
+    let target_ev = clamp(log2(0.18 / avg_lum), params.min_ev, params.max_ev);
+    let k = 1.0 - exp(-params.dt * params.speed);
+    state.ev = state.ev + (target_ev - state.ev) * k;
+    state.avg_lum = avg_lum;
+}
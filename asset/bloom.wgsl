@@ -0,0 +1,89 @@
+// 泛光: 亮部提取后一级级缩小, 再一级级放大加回去, 最后叠加到 HDR 目标上
+struct BloomUniform {
+    threshold: f32,
+    // 阈值附近的软过渡宽度, 0 是硬切
+    knee: f32,
+    intensity: f32,
+    // 放大时 tent 滤波的半径, 以源 texel 计
+    radius: f32,
+}
+@group(0) @binding(0) var source: texture_2d<f32>;
+@group(0) @binding(1) var source_sampler: sampler;
+@group(0) @binding(2) var<uniform> bloom: BloomUniform;
+
+struct FullscreenOut {
+    @builtin(position) pos: vec4f,
+    // 左上角是 0
+    @location(0) uv: vec2f,
+}
+
+@vertex
+fn fullscreen_vs(@builtin(vertex_index) vid: u32) -> FullscreenOut {
+    let uv = vec2f(f32((vid << 1u) & 2u), f32(vid & 2u));
+    var out: FullscreenOut;
+    out.pos = vec4f(uv * 2.0 - 1.0, 0.0, 1.0);
+    out.uv = vec2(uv.x, 1.0 - uv.y);
+    return out;
+}
+
+fn sample(uv: vec2f) -> vec3f {
+    return textureSampleLevel(source, source_sampler, uv, 0.0).rgb;
+}
+
+// 13 个采样点的缩小 (Jimenez 2014), 比 2x2 平均少闪烁
+fn downsample13(uv: vec2f) -> vec3f {
+    let t = 1.0 / vec2f(textureDimensions(source));
+    let a = sample(uv + t * vec2(-2.0, -2.0));
+    let b = sample(uv + t * vec2(0.0, -2.0));
+    let c = sample(uv + t * vec2(2.0, -2.0));
+    let d = sample(uv + t * vec2(-2.0, 0.0));
+    let e = sample(uv);
+    let f = sample(uv + t * vec2(2.0, 0.0));
+    let g = sample(uv + t * vec2(-2.0, 2.0));
+    let h = sample(uv + t * vec2(0.0, 2.0));
+    let i = sample(uv + t * vec2(2.0, 2.0));
+    let j = sample(uv + t * vec2(-1.0, -1.0));
+    let k = sample(uv + t * vec2(1.0, -1.0));
+    let l = sample(uv + t * vec2(-1.0, 1.0));
+    let m = sample(uv + t * vec2(1.0, 1.0));
+    return e * 0.125 + (a + c + g + i) * 0.03125 + (b + d + f + h) * 0.0625 + (j + k + l + m) * 0.125;
+}
+
+// 软阈值: 低于 threshold - knee 的全丢, 到 threshold + knee 之间二次过渡
+fn bright_pass(color: vec3f) -> vec3f {
+    let brightness = max(color.r, max(color.g, color.b));
+    let soft = clamp(brightness - bloom.threshold + bloom.knee, 0.0, 2.0 * bloom.knee);
+    let soft_weight = soft * soft / (4.0 * bloom.knee + 1e-4);
+    let weight = max(soft_weight, brightness - bloom.threshold) / max(brightness, 1e-4);
+    return color * weight;
+}
+
+@fragment
+fn prefilter_fs(in: FullscreenOut) -> @location(0) vec4f {
+    // NaN 和无穷大会扩散到整个 mip 链
+    let color = min(downsample13(in.uv), vec3(65000.0));
+    return vec4(bright_pass(color), 1.0);
+}
+
+@fragment
+fn downsample_fs(in: FullscreenOut) -> @location(0) vec4f {
+    return vec4(downsample13(in.uv), 1.0);
+}
+
+// 3x3 tent, 混合模式把它加到目标 mip 已有的缩小结果上
+@fragment
+fn upsample_fs(in: FullscreenOut) -> @location(0) vec4f {
+    let t = bloom.radius / vec2f(textureDimensions(source));
+    var sum = sample(in.uv) * 4.0;
+    sum += (sample(in.uv + t * vec2(0.0, -1.0)) + sample(in.uv + t * vec2(-1.0, 0.0))
+        + sample(in.uv + t * vec2(1.0, 0.0)) + sample(in.uv + t * vec2(0.0, 1.0))) * 2.0;
+    sum += sample(in.uv + t * vec2(-1.0, -1.0)) + sample(in.uv + t * vec2(1.0, -1.0))
+        + sample(in.uv + t * vec2(-1.0, 1.0)) + sample(in.uv + t * vec2(1.0, 1.0));
+    return vec4(sum / 16.0, 1.0);
+}
+
+// 加到 HDR 目标上
+@fragment
+fn composite_fs(in: FullscreenOut) -> @location(0) vec4f {
+    return vec4(sample(in.uv) * bloom.intensity, 0.0);
+}
@@ -0,0 +1,109 @@
+// 鸟群 (boids): 每个线程一只, 看邻居算分离, 对齐, 聚集三个力, 结果写进下一帧的状态
+// 同时把朝向速度方向的变换写进圆锥网格的实例缓冲, 网格直接从那里画
+
+// 和 boids.rs 的 BoidParams 一样
+struct BoidParams {
+    count: u32,
+    dt: f32,
+    // 看得见邻居的距离
+    view_radius: f32,
+    // 比这更近就互相推开
+    separation_radius: f32,
+    separation: f32,
+    alignment: f32,
+    cohesion: f32,
+    max_speed: f32,
+    // 鸟群待着的盒子
+    center: vec3f,
+    size: f32,
+    extent: vec3f,
+    min_speed: f32,
+    color: vec4f,
+}
+
+struct Boid {
+    position: vec3f,
+    velocity: vec3f,
+}
+
+// 和 instance.rs 的 InstanceRaw 一样
+struct Instance {
+    model: mat4x4f,
+    color: vec4f,
+}
+
+@group(0) @binding(0) var<uniform> params: BoidParams;
+// 上一帧的状态, 读; 这一帧的写到另一个缓冲, 两个缓冲每帧交换
+@group(0) @binding(1) var<storage, read> boids_in: array<Boid>;
+@group(0) @binding(2) var<storage, read_write> boids_out: array<Boid>;
+@group(0) @binding(3) var<storage, read_write> instances: array<Instance>;
+
+@compute @workgroup_size(64)
+fn update(@builtin(global_invocation_id) gid: vec3u) {
+    let index = gid.x;
+    if index >= params.count {
+        return;
+    }
+    let me = boids_in[index];
+
+    var separation = vec3(0.0);
+    var heading = vec3(0.0);
+    var center = vec3(0.0);
+    var neighbors = 0u;
+    // 每只都看所有的鸟, O(n²), 几千只还行
+    for (var i = 0u; i < params.count; i++) {
+        if i == index {
+            continue;
+        }
+        let other = boids_in[i];
+        let offset = other.position - me.position;
+        let distance = length(offset);
+        if distance >= params.view_radius {
+            continue;
+        }
+        heading += other.velocity;
+        center += other.position;
+        neighbors++;
+        if distance < params.separation_radius && distance > 1e-4 {
+            // 越近推得越狠
+            separation -= offset / (distance * distance);
+        }
+    }
+
+    var acceleration = separation * params.separation;
+    if neighbors > 0u {
+        let n = f32(neighbors);
+        acceleration += (heading / n - me.velocity) * params.alignment;
+        acceleration += (center / n - me.position) * params.cohesion;
+    }
+    // 快飞出盒子的时候往回拐
+    let local = (me.position - params.center) / params.extent;
+    acceleration -= sign(local) * max(abs(local) - 0.8, vec3(0.0)) * params.max_speed * 4.0;
+
+    var velocity = me.velocity + acceleration * params.dt;
+    let speed = length(velocity);
+    var forward = vec3(0.0, 0.0, 1.0);
+    if speed > 1e-4 {
+        forward = velocity / speed;
+    }
+    velocity = forward * clamp(speed, params.min_speed, params.max_speed);
+    let position = me.position + velocity * params.dt;
+    boids_out[index] = Boid(position, velocity);
+
+    // 圆锥的尖朝 +z, 转到速度方向; 缩放各个方向一样
+    var reference = vec3(0.0, 1.0, 0.0);
+    if abs(forward.y) > 0.99 {
+        reference = vec3(1.0, 0.0, 0.0);
+    }
+    let side = normalize(cross(reference, forward));
+    let up = cross(forward, side);
+    instances[index] = Instance(
+        mat4x4f(
+            vec4(side * params.size, 0.0),
+            vec4(up * params.size, 0.0),
+            vec4(forward * params.size, 0.0),
+            vec4(position, 1.0),
+        ),
+        params.color,
+    );
+}
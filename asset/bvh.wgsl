@@ -0,0 +1,117 @@
+// BVH 遍历, 用它的 shader #include 这个文件; 那边自己声明
+//   triangles: array<Triangle>
+//   bvh_nodes: array<BvhNode>
+// 两个 storage buffer
+
+struct Triangle {
+    v0: vec4f,
+    v1: vec4f,
+    v2: vec4f,
+    albedo: vec4f,
+}
+// count == 0 是内部节点, 子节点在 left_first 和 left_first + 1;
+// 否则是叶子, 管 left_first 开始的 count 个三角形
+struct BvhNode {
+    min: vec3f,
+    left_first: u32,
+    max: vec3f,
+    count: u32,
+}
+
+const NO_HIT: f32 = 1e30;
+const BVH_STACK_SIZE: u32 = 32u;
+
+struct BvhHit {
+    // 没打中是 NO_HIT
+    t: f32,
+    triangle: u32,
+}
+
+// Möller–Trumbore, 返回 t, 没打中是 NO_HIT
+fn intersect_triangle(origin: vec3f, dir: vec3f, tri: Triangle) -> f32 {
+    let e1 = tri.v1.xyz - tri.v0.xyz;
+    let e2 = tri.v2.xyz - tri.v0.xyz;
+    let p = cross(dir, e2);
+    let det = dot(e1, p);
+    if abs(det) < 1e-8 {
+        return NO_HIT;
+    }
+    let inv_det = 1.0 / det;
+    let s = origin - tri.v0.xyz;
+    let u = dot(s, p) * inv_det;
+    if u < 0.0 || u > 1.0 {
+        return NO_HIT;
+    }
+    let q = cross(s, e1);
+    let v = dot(dir, q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return NO_HIT;
+    }
+    let t = dot(e2, q) * inv_det;
+    return select(NO_HIT, t, t > 1e-4);
+}
+
+// slab 求交, 返回进入的 t; 在 t_max 之外或者没打中是 NO_HIT
+fn intersect_node(origin: vec3f, inv_dir: vec3f, node: BvhNode, t_max: f32) -> f32 {
+    let t0 = (node.min - origin) * inv_dir;
+    let t1 = (node.max - origin) * inv_dir;
+    let t_near = max(max(min(t0.x, t1.x), min(t0.y, t1.y)), min(t0.z, t1.z));
+    let t_far = min(min(max(t0.x, t1.x), max(t0.y, t1.y)), max(t0.z, t1.z));
+    if t_near > t_far || t_far < 0.0 || t_near >= t_max {
+        return NO_HIT;
+    }
+    return t_near;
+}
+
+// 最近的交点, 只找比 t_max 更近的; 先走近的子节点, 远的压栈
+fn bvh_trace(origin: vec3f, dir: vec3f, t_max: f32) -> BvhHit {
+    var hit = BvhHit(t_max, 0u);
+    let inv_dir = 1.0 / dir;
+    if intersect_node(origin, inv_dir, bvh_nodes[0], hit.t) >= NO_HIT {
+        return BvhHit(NO_HIT, 0u);
+    }
+    var stack: array<u32, BVH_STACK_SIZE>;
+    var top = 0u;
+    var index = 0u;
+    loop {
+        let node = bvh_nodes[index];
+        if node.count > 0u {
+            for (var i = node.left_first; i < node.left_first + node.count; i++) {
+                let t = intersect_triangle(origin, dir, triangles[i]);
+                if t < hit.t {
+                    hit = BvhHit(t, i);
+                }
+            }
+        } else {
+            let left = node.left_first;
+            var t_first = intersect_node(origin, inv_dir, bvh_nodes[left], hit.t);
+            var t_second = intersect_node(origin, inv_dir, bvh_nodes[left + 1u], hit.t);
+            var first = left;
+            var second = left + 1u;
+            if t_second < t_first {
+                let t = t_first;
+                t_first = t_second;
+                t_second = t;
+                first = left + 1u;
+                second = left;
+            }
+            if t_first < NO_HIT {
+                if t_second < NO_HIT && top < BVH_STACK_SIZE {
+                    stack[top] = second;
+                    top++;
+                }
+                index = first;
+                continue;
+            }
+        }
+        if top == 0u {
+            break;
+        }
+        top--;
+        index = stack[top];
+    }
+    if hit.t >= t_max {
+        hit.t = NO_HIT;
+    }
+    return hit;
+}
@@ -0,0 +1,6 @@
+// 和 camera.rs 的 CameraUniform 一样, 用 #include "camera.wgsl" 拼进着色器
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+    inv_view_proj: mat4x4<f32>,
+    view_position: vec4<f32>,
+};
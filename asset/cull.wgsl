@@ -0,0 +1,57 @@
+// 视锥剔除: 每个线程一个实例, 包围球在视锥里的实例挤到 culled 前面, 数量加到间接绘制参数的 instance_count
+// instance_count 在这之前由 CPU 清成 0
+
+// 和 instance.rs 的 InstanceRaw 一样
+struct Instance {
+    model: mat4x4f,
+    color: vec4f,
+}
+
+// 和 wgpu 的 DrawIndexedIndirectArgs 一样, 20 字节
+struct DrawArgs {
+    index_count: u32,
+    instance_count: atomic<u32>,
+    first_index: u32,
+    base_vertex: i32,
+    first_instance: u32,
+}
+
+struct Frustum {
+    // 法线朝里, 单位长度
+    planes: array<vec4f, 6>,
+}
+
+struct MeshCull {
+    // 网格自己的包围球, 实例变换之前
+    center: vec3f,
+    radius: f32,
+    mesh_index: u32,
+    instance_count: u32,
+    _pad0: u32,
+    _pad1: u32,
+}
+
+@group(0) @binding(0) var<uniform> frustum: Frustum;
+@group(0) @binding(1) var<uniform> params: MeshCull;
+@group(0) @binding(2) var<storage, read> instances: array<Instance>;
+@group(0) @binding(3) var<storage, read_write> culled: array<Instance>;
+@group(0) @binding(4) var<storage, read_write> draws: array<DrawArgs>;
+
+@compute @workgroup_size(64)
+fn cull(@builtin(global_invocation_id) id: vec3u) {
+    if id.x >= params.instance_count {
+        return;
+    }
+    let instance = instances[id.x];
+    let center = (instance.model * vec4(params.center, 1.0)).xyz;
+    // 各个方向缩放一样
+    let radius = params.radius * length(instance.model[0].xyz);
+    for (var i = 0u; i < 6u; i++) {
+        let plane = frustum.planes[i];
+        if dot(plane.xyz, center) + plane.w < -radius {
+            return;
+        }
+    }
+    let slot = atomicAdd(&draws[params.mesh_index].instance_count, 1u);
+    culled[slot] = instance;
+}
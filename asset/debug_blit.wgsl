@@ -0,0 +1,31 @@
+struct Params {
+    output_size: vec2f,
+    _pad: vec2f,
+}
+
+@group(0) @binding(0) var source: texture_2d<f32>;
+@group(0) @binding(1) var<uniform> params: Params;
+
+struct FullscreenOut {
+    @builtin(position) pos: vec4f,
+}
+
+@vertex
+fn fullscreen_vs(@builtin(vertex_index) vid: u32) -> FullscreenOut {
+    let uv = vec2f(f32((vid << 1u) & 2u), f32(vid & 2u));
+    var out: FullscreenOut;
+    out.pos = vec4f(uv * 2.0 - 1.0, 0.0, 1.0);
+    return out;
+}
+
+// 把任意一张中间纹理拉伸到窗口, 最近点采样, 看到的就是原始像素
+// HDR 的值超过 1 会截断, 负数和 NaN 标成品红
+@fragment
+fn blit_fs(in: FullscreenOut) -> @location(0) vec4f {
+    let size = vec2f(textureDimensions(source));
+    let p = vec2i(in.pos.xy / params.output_size * size);
+    let c = textureLoad(source, min(p, vec2i(size) - 1), 0);
+    // NaN 跟谁比都是 false
+    let valid = all(c.rgb >= vec3(0.0)) && all(c.rgb <= vec3(1e30));
+    return select(vec4(1.0, 0.0, 1.0, 1.0), vec4(saturate(c.rgb), 1.0), valid);
+}
@@ -0,0 +1,94 @@
+// 延迟渲染的光照 pass: 全屏三角形, 从 G-buffer 读表面, 加到几何 pass 写好的自发光上
+// lights 的声明 (storage 数组, 或者没有 storage buffer 时的 uniform 数组) 由 deferred.rs 接在后面
+#include "lighting.wgsl"
+
+// 只有延迟渲染画的点光源, 到 range 衰减到 0
+struct LocalLight {
+    position: vec3f,
+    range: f32,
+    color: vec3f,
+    intensity: f32,
+}
+
+struct DeferredParams {
+    light_count: u32,
+    _pad0: u32,
+    _pad1: u32,
+    _pad2: u32,
+}
+
+@group(2) @binding(0) var surface_texture: texture_2d<f32>;
+// NDC 深度
+@group(2) @binding(1) var depth_texture: texture_2d<f32>;
+// 这两个是 mesh.wgsl 的 deferred_fs 打包的 4x8 unorm
+@group(2) @binding(2) var albedo_texture: texture_2d<u32>;
+@group(2) @binding(3) var material_texture: texture_2d<u32>;
+@group(2) @binding(4) var<uniform> params: DeferredParams;
+
+@vertex
+fn fullscreen_vs(@builtin(vertex_index) vid: u32) -> @builtin(position) vec4f {
+    let uv = vec2f(f32((vid << 1u) & 2u), f32(vid & 2u));
+    return vec4f(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+fn local_light(local: LocalLight, world_position: vec3f, normal: vec3f, albedo: vec3f, metallic: f32, roughness: f32, kind: u32) -> vec3f {
+    let to_light = local.position - world_position;
+    let distance2 = max(dot(to_light, to_light), 1e-4);
+    // 平方反比, 再乘一个到 range 平滑降到 0 的窗口, 范围外的光就不用算
+    let window = saturate(1.0 - pow(distance2 / (local.range * local.range), 2.0));
+    let radiance = local.color * local.intensity * window * window / distance2;
+    let l = to_light * inverseSqrt(distance2);
+    let to_eye = normalize(camera.view_position.xyz - world_position);
+    var color: vec3f;
+    switch kind {
+        case 0u: {
+            color = albedo * radiance * max(dot(normal, l), 0.0);
+        }
+        case 1u: {
+            color = blinn_phong(normal, l, to_eye, radiance, albedo);
+        }
+        default: {
+            color = cook_torrance(normal, l, to_eye, radiance, albedo, metallic, roughness);
+        }
+    }
+    return color;
+}
+
+// 加色混合, alpha 不变
+@fragment
+fn lighting_fs(@builtin(position) pos: vec4f) -> @location(0) vec4f {
+    let texel = vec2i(pos.xy);
+    let surface_material = unpack4x8unorm(textureLoad(material_texture, texel, 0).r);
+    // 光线投射的场景, 天空和混合的网格都不是延迟着色的
+    if surface_material.a == 0.0 {
+        discard;
+    }
+    let normal = textureLoad(surface_texture, texel, 0).xyz;
+    let depth = textureLoad(depth_texture, texel, 0).r;
+    let packed_albedo = unpack4x8unorm(textureLoad(albedo_texture, texel, 0).r);
+    let albedo = vec4(packed_albedo.rgb * packed_albedo.rgb, packed_albedo.a);
+    let size = vec2f(textureDimensions(depth_texture));
+    let ndc = vec2(pos.x / size.x * 2.0 - 1.0, 1.0 - pos.y / size.y * 2.0);
+    let world = camera.inv_view_proj * vec4(ndc, depth, 1.0);
+    let world_position = world.xyz / world.w;
+    let metallic = surface_material.r;
+    let roughness = surface_material.g;
+    let kind = u32(round(surface_material.b * 2.0));
+
+    var color: vec3f;
+    switch kind {
+        case 0u: {
+            color = shade_mesh(world_position, normal, albedo.rgb);
+        }
+        case 1u: {
+            color = shade_lit(world_position, normal, albedo.rgb);
+        }
+        default: {
+            color = shade_pbr(world_position, normal, albedo.rgb, metallic, roughness, albedo.a);
+        }
+    }
+    for (var i = 0u; i < params.light_count; i++) {
+        color += local_light(lights[i], world_position, normal, albedo.rgb, metallic, roughness, kind);
+    }
+    return vec4(color, 0.0);
+}
@@ -0,0 +1,87 @@
+struct Params {
+    // 这一轮 À-Trous 的采样间隔, 1 2 4 8 ...
+    step: u32,
+    iteration: u32,
+    // 最后一轮乘回 albedo
+    last: u32,
+    color_sigma: f32,
+    normal_sigma: f32,
+    depth_sigma: f32,
+    _pad0: f32,
+    _pad1: f32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var source: texture_2d<f32>;
+// 第一次打中的表面: albedo, 法线 + 到相机的距离 (天空是 0)
+@group(0) @binding(2) var albedo: texture_2d<f32>;
+@group(0) @binding(3) var normal_depth: texture_2d<f32>;
+@group(0) @binding(4) var destination: texture_storage_2d<rgba16float, write>;
+
+// B3 样条, 5x5 核
+const KERNEL = array<f32, 3>(0.375, 0.25, 0.0625);
+
+fn luminance(c: vec3f) -> f32 {
+    return dot(c, vec3(0.2126, 0.7152, 0.0722));
+}
+
+// 第一轮先除掉 albedo, 只滤光照, 纹理细节不会被糊掉
+fn load(p: vec2i) -> vec3f {
+    let color = textureLoad(source, p, 0).rgb;
+    if params.iteration == 0u {
+        return color / max(textureLoad(albedo, p, 0).rgb, vec3(0.01));
+    }
+    return color;
+}
+
+@compute @workgroup_size(8, 8)
+fn denoise(@builtin(global_invocation_id) id: vec3u) {
+    let size = vec2i(textureDimensions(source));
+    let p = vec2i(id.xy);
+    if any(p >= size) {
+        return;
+    }
+    let center = load(p);
+    let guide = textureLoad(normal_depth, p, 0);
+    let surface_albedo = textureLoad(albedo, p, 0).rgb;
+    // 天空不滤
+    if guide.w <= 0.0 {
+        let color = select(center, center * surface_albedo, params.last == 1u);
+        textureStore(destination, p, vec4(color, 1.0));
+        return;
+    }
+
+    let center_luminance = luminance(center);
+    // 越往后间隔越大, 颜色的容差跟着收紧
+    let color_sigma = params.color_sigma * exp2(-f32(params.iteration));
+    // 要动态下标, 放到 var 里
+    var kernel_weights = KERNEL;
+    var sum = vec3(0.0);
+    var weight_sum = 0.0;
+    for (var y = -2; y <= 2; y++) {
+        for (var x = -2; x <= 2; x++) {
+            let q = p + vec2(x, y) * i32(params.step);
+            if any(q < vec2(0)) || any(q >= size) {
+                continue;
+            }
+            let sample_guide = textureLoad(normal_depth, q, 0);
+            if sample_guide.w <= 0.0 {
+                continue;
+            }
+            let color = load(q);
+            let kernel = kernel_weights[abs(x)] * kernel_weights[abs(y)];
+            let w_normal = pow(max(dot(guide.xyz, sample_guide.xyz), 0.0), params.normal_sigma);
+            let w_depth = exp(-abs(guide.w - sample_guide.w) / (params.depth_sigma * guide.w * f32(params.step) + 1e-4));
+            let l = luminance(color);
+            let w_color = exp(-abs(center_luminance - l) / (color_sigma * (center_luminance + l) * 0.5 + 1e-4));
+            let w = kernel * w_normal * w_depth * w_color;
+            sum += color * w;
+            weight_sum += w;
+        }
+    }
+    var filtered = sum / max(weight_sum, 1e-6);
+    if params.last == 1u {
+        filtered *= surface_albedo;
+    }
+    textureStore(destination, p, vec4(filtered, 1.0));
+}
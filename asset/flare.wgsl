@@ -0,0 +1,121 @@
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+    inv_view_proj: mat4x4<f32>,
+    view_position: vec4<f32>,
+};
+struct SkyUniform {
+    sun_direction: vec3f,
+    turbidity: f32,
+    moon_direction: vec3f,
+    intensity: f32,
+    moon_radius: f32,
+    star_brightness: f32,
+    sun_radius: f32,
+}
+struct FlareParams {
+    intensity: f32,
+    aspect: f32,
+    _pad0: f32,
+    _pad1: f32,
+}
+
+@group(0) @binding(0) var<uniform> camera: CameraUniform;
+@group(0) @binding(1) var<uniform> sky: SkyUniform;
+// scene depth, 1.0 = nothing in front of the sky
+@group(0) @binding(2) var depth_texture: texture_2d<f32>;
+@group(0) @binding(3) var<uniform> params: FlareParams;
+
+struct FullscreenOut {
+    @builtin(position) pos: vec4f,
+    @location(0) uv: vec2f,
+}
+
+@vertex
+fn fullscreen_vs(@builtin(vertex_index) vid: u32) -> FullscreenOut {
+    let uv = vec2f(f32((vid << 1u) & 2u), f32(vid & 2u));
+    var out: FullscreenOut;
+    out.pos = vec4f(uv * 2.0 - 1.0, 0.0, 1.0);
+    out.uv = vec2f(uv.x, 1.0 - uv.y);
+    return out;
+}
+
+// xy: sun position in uv, z: 1 when the sun is in front of the camera
+fn sun_screen() -> vec3f {
+    let clip = camera.view_proj * vec4(normalize(sky.sun_direction), 0.0);
+    if clip.w <= 0.0 {
+        return vec3(0.);
+    }
+    let ndc = clip.xy / clip.w;
+    return vec3(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5, 1.0);
+}
+
+// 在太阳周围取 5x5 个深度, 看有多少是天空
+fn sun_visibility(sun_uv: vec2f) -> f32 {
+    let size = vec2f(textureDimensions(depth_texture));
+    var visible = 0.0;
+    for (var y = -2; y <= 2; y++) {
+        for (var x = -2; x <= 2; x++) {
+            let p = sun_uv + vec2f(f32(x), f32(y)) * 0.004;
+            if all(p >= vec2(0.)) && all(p <= vec2(1.)) {
+                let texel = vec2i(p * (size - 1.0));
+                if textureLoad(depth_texture, texel, 0).r >= 1.0 {
+                    visible += 1.0;
+                }
+            }
+        }
+    }
+    return visible / 25.0;
+}
+
+fn aspect_corrected(d: vec2f) -> vec2f {
+    return vec2(d.x * params.aspect, d.y);
+}
+
+fn ghost(uv: vec2f, center: vec2f, radius: f32) -> f32 {
+    let d = length(aspect_corrected(uv - center));
+    return smoothstep(radius, radius * 0.7, d);
+}
+
+@fragment
+fn flare_fs(in: FullscreenOut) -> @location(0) vec4f {
+    let sun = sun_screen();
+    let elevation_fade = smoothstep(-0.02, 0.05, normalize(sky.sun_direction).y);
+    if sun.z == 0.0 || elevation_fade <= 0.0 {
+        return vec4(0.);
+    }
+    let visibility = sun_visibility(sun.xy) * elevation_fade;
+    if visibility <= 0.0 {
+        return vec4(0.);
+    }
+
+    let d = aspect_corrected(in.uv - sun.xy);
+    let dist = length(d);
+    var color = vec3(0.);
+
+    // 太阳周围的眩光和星芒
+    color += vec3(1.0, 0.9, 0.75) * 0.02 / (dist * 30.0 + 0.02);
+    let angle = atan2(d.y, d.x);
+    color += vec3(1.0, 0.95, 0.85) * pow(abs(cos(angle * 4.0)), 60.0) * exp(-dist * 10.0) * 0.4;
+
+    // 沿着 太阳 -> 屏幕中心 的直线排列的鬼影
+    let to_center = vec2(0.5) - sun.xy;
+    var offsets = array<f32, 5>(0.4, 0.7, 1.1, 1.45, 1.9);
+    var radii = array<f32, 5>(0.03, 0.06, 0.02, 0.09, 0.05);
+    var tints = array<vec3f, 5>(
+        vec3(0.4, 0.6, 1.0),
+        vec3(0.3, 1.0, 0.5),
+        vec3(1.0, 0.6, 0.3),
+        vec3(0.6, 0.4, 1.0),
+        vec3(1.0, 0.9, 0.5),
+    );
+    for (var i = 0; i < 5; i++) {
+        let center = sun.xy + to_center * offsets[i];
+        color += tints[i] * ghost(in.uv, center, radii[i]) * 0.03;
+    }
+
+    // 大光环
+    let halo_dist = length(aspect_corrected(in.uv - (sun.xy + to_center * 1.0)));
+    color += vec3(0.5, 0.7, 1.0) * smoothstep(0.02, 0.0, abs(halo_dist - 0.35)) * 0.01;
+
+    return vec4(color * visibility * params.intensity, 0.0);
+}
@@ -0,0 +1,156 @@
+// FXAA 3.11 quality 的简化版: 找出边的方向, 沿边两头搜到尽头, 按离尽头多远往边的另一侧偏移采样
+struct Params {
+    // 局部对比度低于 最亮 * edge_threshold 的不处理
+    edge_threshold: f32,
+    // 暗处的下限, 免得噪点也被当成边
+    edge_threshold_min: f32,
+    // 0 到 1, 子像素锯齿的模糊程度
+    subpixel: f32,
+    search_steps: u32,
+    // 视图是 sRGB 的, 读出来已经是线性光, luma 换成 gamma 2 再算
+    perceptual: u32,
+    // 1: luma 已经由 luma_fs 写进 alpha
+    luma_in_alpha: u32,
+    _pad0: u32,
+    _pad1: u32,
+    // 每一步沿边走多少 texel, 4 个一组
+    steps: array<vec4f, 3>,
+}
+
+@group(0) @binding(0) var source: texture_2d<f32>;
+@group(0) @binding(1) var source_sampler: sampler;
+@group(0) @binding(2) var<uniform> params: Params;
+
+struct FullscreenOut {
+    @builtin(position) pos: vec4f,
+    // 左上角是 0
+    @location(0) uv: vec2f,
+}
+
+@vertex
+fn fullscreen_vs(@builtin(vertex_index) vid: u32) -> FullscreenOut {
+    let uv = vec2f(f32((vid << 1u) & 2u), f32(vid & 2u));
+    var out: FullscreenOut;
+    out.pos = vec4f(uv * 2.0 - 1.0, 0.0, 1.0);
+    out.uv = vec2(uv.x, 1.0 - uv.y);
+    return out;
+}
+
+fn luma(c: vec3f) -> f32 {
+    let p = select(c, sqrt(max(c, vec3(0.0))), params.perceptual == 1u);
+    return dot(p, vec3(0.299, 0.587, 0.114));
+}
+
+fn luma_at(uv: vec2f) -> f32 {
+    let c = textureSampleLevel(source, source_sampler, uv, 0.0);
+    return select(luma(c.rgb), c.a, params.luma_in_alpha == 1u);
+}
+
+// 先把 luma 算好放进 alpha, 后面每次采样就不用再算
+@fragment
+fn luma_fs(in: FullscreenOut) -> @location(0) vec4f {
+    let c = textureSampleLevel(source, source_sampler, in.uv, 0.0).rgb;
+    return vec4(c, luma(c));
+}
+
+@fragment
+fn fxaa_fs(in: FullscreenOut) -> @location(0) vec4f {
+    let texel = 1.0 / vec2f(textureDimensions(source));
+    let uv = in.uv;
+    let center = textureSampleLevel(source, source_sampler, uv, 0.0);
+    // alpha 里放的是 luma 时原来的 alpha 已经没了
+    let alpha = select(center.a, 1.0, params.luma_in_alpha == 1u);
+    let luma_m = select(luma(center.rgb), center.a, params.luma_in_alpha == 1u);
+    let luma_n = luma_at(uv + vec2(0.0, -1.0) * texel);
+    let luma_s = luma_at(uv + vec2(0.0, 1.0) * texel);
+    let luma_w = luma_at(uv + vec2(-1.0, 0.0) * texel);
+    let luma_e = luma_at(uv + vec2(1.0, 0.0) * texel);
+    let luma_max = max(luma_m, max(max(luma_n, luma_s), max(luma_w, luma_e)));
+    let luma_min = min(luma_m, min(min(luma_n, luma_s), min(luma_w, luma_e)));
+    let range = luma_max - luma_min;
+    if range < max(params.edge_threshold_min, luma_max * params.edge_threshold) {
+        return vec4(center.rgb, alpha);
+    }
+    let luma_nw = luma_at(uv + vec2(-1.0, -1.0) * texel);
+    let luma_ne = luma_at(uv + vec2(1.0, -1.0) * texel);
+    let luma_sw = luma_at(uv + vec2(-1.0, 1.0) * texel);
+    let luma_se = luma_at(uv + vec2(1.0, 1.0) * texel);
+
+    // 3x3 平均和中心差得越多, 越像单个像素的锯齿
+    let average = (2.0 * (luma_n + luma_s + luma_w + luma_e) + luma_nw + luma_ne + luma_sw + luma_se) / 12.0;
+    let subpixel_a = saturate(abs(average - luma_m) / range);
+    let subpixel_b = (-2.0 * subpixel_a + 3.0) * subpixel_a * subpixel_a;
+    let subpixel_offset = subpixel_b * subpixel_b * params.subpixel;
+
+    // 横边是上下变化大
+    let edge_h = abs(luma_nw + luma_ne - 2.0 * luma_n) + 2.0 * abs(luma_w + luma_e - 2.0 * luma_m)
+        + abs(luma_sw + luma_se - 2.0 * luma_s);
+    let edge_v = abs(luma_nw + luma_sw - 2.0 * luma_w) + 2.0 * abs(luma_n + luma_s - 2.0 * luma_m)
+        + abs(luma_ne + luma_se - 2.0 * luma_e);
+    let horizontal = edge_h >= edge_v;
+
+    // 边在中心和梯度大的那一侧之间
+    let luma_pos = select(luma_e, luma_s, horizontal);
+    let luma_neg = select(luma_w, luma_n, horizontal);
+    let gradient_pos = abs(luma_pos - luma_m);
+    let gradient_neg = abs(luma_neg - luma_m);
+    var step_length = select(texel.x, texel.y, horizontal);
+    var luma_side = luma_pos;
+    var gradient = gradient_pos;
+    if gradient_neg >= gradient_pos {
+        step_length = -step_length;
+        luma_side = luma_neg;
+        gradient = gradient_neg;
+    }
+    var edge_uv = uv;
+    if horizontal {
+        edge_uv.y += step_length * 0.5;
+    } else {
+        edge_uv.x += step_length * 0.5;
+    }
+    let edge_step = select(vec2(0.0, texel.y), vec2(texel.x, 0.0), horizontal);
+    let edge_luma = (luma_m + luma_side) * 0.5;
+    let gradient_threshold = gradient * 0.25;
+
+    // 沿边两头走, 直到 luma 和边上的差得够多
+    var uv_pos = edge_uv;
+    var uv_neg = edge_uv;
+    var delta_pos = 0.0;
+    var delta_neg = 0.0;
+    var done_pos = false;
+    var done_neg = false;
+    for (var i = 0u; i < params.search_steps; i++) {
+        let stride = params.steps[i / 4u][i % 4u];
+        if !done_pos {
+            uv_pos += edge_step * stride;
+            delta_pos = luma_at(uv_pos) - edge_luma;
+            done_pos = abs(delta_pos) >= gradient_threshold;
+        }
+        if !done_neg {
+            uv_neg -= edge_step * stride;
+            delta_neg = luma_at(uv_neg) - edge_luma;
+            done_neg = abs(delta_neg) >= gradient_threshold;
+        }
+        if done_pos && done_neg {
+            break;
+        }
+    }
+
+    let distance_pos = select(uv_pos.y - uv.y, uv_pos.x - uv.x, horizontal);
+    let distance_neg = select(uv.y - uv_neg.y, uv.x - uv_neg.x, horizontal);
+    let nearer_pos = distance_pos < distance_neg;
+    let nearest = min(distance_pos, distance_neg);
+    let span = distance_pos + distance_neg;
+    // 近的那头往中心的反方向变, 才是这一侧的边
+    let delta_end = select(delta_neg, delta_pos, nearer_pos);
+    let good_span = (delta_end < 0.0) != (luma_m - edge_luma < 0.0);
+    let edge_offset = select(0.0, 0.5 - nearest / span, good_span);
+    let offset = max(edge_offset, subpixel_offset);
+    var final_uv = uv;
+    if horizontal {
+        final_uv.y += offset * step_length;
+    } else {
+        final_uv.x += offset * step_length;
+    }
+    return vec4(textureSampleLevel(source, source_sampler, final_uv, 0.0).rgb, alpha);
+}
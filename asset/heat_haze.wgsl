@@ -0,0 +1,77 @@
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+    inv_view_proj: mat4x4<f32>,
+    view_position: vec4<f32>,
+};
+
+struct HeatHazeParams {
+    // 已经乘上了白天的程度, 0 就是没有扭曲
+    strength: f32,
+    time: f32,
+    // 噪声顺着风飘
+    wind: vec2f,
+}
+
+@group(0) @binding(0) var scene_color: texture_2d<f32>;
+@group(0) @binding(1) var scene_sampler: sampler;
+@group(0) @binding(2) var<uniform> camera: CameraUniform;
+@group(0) @binding(3) var<uniform> params: HeatHazeParams;
+
+struct FullscreenOut {
+    @builtin(position) pos: vec4f,
+    @location(0) uv: vec2f,
+    @location(1) ndc: vec2f,
+}
+
+@vertex
+fn fullscreen_vs(@builtin(vertex_index) vid: u32) -> FullscreenOut {
+    let uv = vec2f(f32((vid << 1u) & 2u), f32(vid & 2u));
+    var out: FullscreenOut;
+    out.pos = vec4f(uv * 2.0 - 1.0, 0.0, 1.0);
+    out.ndc = uv * 2.0 - 1.0;
+    out.uv = vec2f(uv.x, 1.0 - uv.y);
+    return out;
+}
+
+fn hash2(p: vec2f) -> f32 {
+    return fract(sin(dot(p, vec2(127.1, 311.7))) * 43758.5453);
+}
+
+fn value_noise(p: vec2f) -> f32 {
+    let i = floor(p);
+    let f = fract(p);
+    let u = f * f * (3.0 - 2.0 * f);
+    let a = hash2(i);
+    let b = hash2(i + vec2(1.0, 0.0));
+    let c = hash2(i + vec2(0.0, 1.0));
+    let d = hash2(i + vec2(1.0, 1.0));
+    return mix(mix(a, b, u.x), mix(c, d, u.x), u.y);
+}
+
+// 热空气往上升, 噪声往上滚, 再被风吹歪一点
+fn shimmer(p: vec2f) -> vec2f {
+    let drift = vec2(-params.wind.x * 0.2, 2.0) * params.time;
+    let q = p + drift;
+    let e = 0.05;
+    let n = value_noise(q);
+    let dx = value_noise(q + vec2(e, 0.0)) - n;
+    let dy = value_noise(q + vec2(0.0, e)) - n;
+    return vec2(dx, dy) / e;
+}
+
+@fragment
+fn heat_haze_fs(in: FullscreenOut) -> @location(0) vec4f {
+    // 只在远处地面和地平线附近扭曲: 贴着地面看过去的光线穿过的热空气最多
+    let far = camera.inv_view_proj * vec4(in.ndc, 1.0, 1.0);
+    let dir = normalize(far.xyz / far.w - camera.view_position.xyz);
+    let near_horizon = exp(-abs(dir.y) * 25.0);
+    let weight = near_horizon * params.strength;
+    if weight < 0.001 {
+        return textureSampleLevel(scene_color, scene_sampler, in.uv, 0.0);
+    }
+
+    let dims = vec2f(textureDimensions(scene_color));
+    let p = in.uv * vec2(dims.x / dims.y, 1.0) * 40.0;
+    let offset = (shimmer(p) * 0.6 + shimmer(p * 2.3 + 17.0) * 0.4) * weight * 0.002;
+    return textureSampleLevel(scene_color, scene_sampler, in.uv + offset, 0.0);
+}
@@ -0,0 +1,188 @@
+// 把等距柱状投影的 HDR 环境图烘焙成基于图像的光照: 环境 cubemap (带 mip 链), 漫反射的辐照度,
+// 按粗糙度预过滤的高光 mip, 和 split-sum 的 BRDF 查找表. 载入时画一次, 每个 pass 画一个面的一级 mip
+struct BakeParams {
+    face: u32,
+    // 预过滤这一级 mip 的粗糙度
+    roughness: f32,
+    // 源 cubemap 一个面的边长, 预过滤按样本的概率密度选源的 mip
+    source_size: f32,
+    // 辐照度卷积读源的哪一级 mip
+    source_lod: f32,
+}
+@group(0) @binding(0) var<uniform> params: BakeParams;
+// Rgba32Float 不能过滤, 自己做双线性
+@group(0) @binding(1) var equirect: texture_2d<f32>;
+@group(0) @binding(2) var source: texture_cube<f32>;
+@group(0) @binding(3) var source_sampler: sampler;
+
+const PI: f32 = 3.14159265;
+
+struct VertexOut {
+    @builtin(position) pos: vec4f,
+    // [-1, 1], v 向下
+    @location(0) uv: vec2f,
+}
+
+@vertex
+fn bake_vs(@builtin(vertex_index) vid: u32) -> VertexOut {
+    let ndc = vec2(f32(vid & 1u) * 4.0 - 1.0, f32(vid >> 1u) * 4.0 - 1.0);
+    var out: VertexOut;
+    out.pos = vec4(ndc, 0.0, 1.0);
+    out.uv = vec2(ndc.x, -ndc.y);
+    return out;
+}
+
+// 和 cube 采样同一套约定, v 向下
+fn texel_direction(face: u32, u: f32, v: f32) -> vec3f {
+    switch face {
+        case 0u: { return vec3(1.0, -v, -u); }
+        case 1u: { return vec3(-1.0, -v, u); }
+        case 2u: { return vec3(u, 1.0, v); }
+        case 3u: { return vec3(u, -1.0, -v); }
+        case 4u: { return vec3(u, -v, 1.0); }
+        default: { return vec3(-u, -v, -1.0); }
+    }
+}
+
+fn equirect_texel(x: i32, y: i32, size: vec2i) -> vec3f {
+    // 经度方向绕回去, 纬度方向夹住
+    let wrapped = vec2((x % size.x + size.x) % size.x, clamp(y, 0, size.y - 1));
+    return textureLoad(equirect, wrapped, 0).rgb;
+}
+
+// 经度从 -X 开始绕 y 轴, 图的第一行是天顶
+@fragment
+fn equirect_fs(in: VertexOut) -> @location(0) vec4f {
+    let dir = normalize(texel_direction(params.face, in.uv.x, in.uv.y));
+    let size = vec2i(textureDimensions(equirect));
+    let uv = vec2(atan2(dir.z, dir.x) / (2.0 * PI) + 0.5, acos(clamp(dir.y, -1.0, 1.0)) / PI);
+    let coord = uv * vec2f(size) - 0.5;
+    let base = floor(coord);
+    let f = coord - base;
+    let x = i32(base.x);
+    let y = i32(base.y);
+    let top = mix(equirect_texel(x, y, size), equirect_texel(x + 1, y, size), f.x);
+    let bottom = mix(equirect_texel(x, y + 1, size), equirect_texel(x + 1, y + 1, size), f.x);
+    return vec4(mix(top, bottom, f.y), 1.0);
+}
+
+// 下一级 mip: 源只绑了上一级, 新 texel 的中心正好落在上一级 2x2 个 texel 中间, 双线性就是平均
+@fragment
+fn downsample_fs(in: VertexOut) -> @location(0) vec4f {
+    let dir = texel_direction(params.face, in.uv.x, in.uv.y);
+    return vec4(textureSampleLevel(source, source_sampler, dir, 0.0).rgb, 1.0);
+}
+
+// 半球上的余弦加权平均. 场景的漫反射不除以 PI (见 sky.wgsl), 所以这里存的是 E / PI:
+// 均匀的环境光 L 卷积出来还是 L
+@fragment
+fn irradiance_fs(in: VertexOut) -> @location(0) vec4f {
+    let normal = normalize(texel_direction(params.face, in.uv.x, in.uv.y));
+    var up = vec3(0.0, 1.0, 0.0);
+    if abs(normal.y) > 0.999 {
+        up = vec3(1.0, 0.0, 0.0);
+    }
+    let right = normalize(cross(up, normal));
+    up = cross(normal, right);
+
+    let phi_steps = 64u;
+    let theta_steps = 16u;
+    var sum = vec3(0.0);
+    for (var i = 0u; i < phi_steps; i++) {
+        let phi = (f32(i) + 0.5) / f32(phi_steps) * 2.0 * PI;
+        for (var j = 0u; j < theta_steps; j++) {
+            let theta = (f32(j) + 0.5) / f32(theta_steps) * 0.5 * PI;
+            let local = vec3(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+            let dir = local.x * right + local.y * up + local.z * normal;
+            sum += textureSampleLevel(source, source_sampler, dir, params.source_lod).rgb * cos(theta) * sin(theta);
+        }
+    }
+    return vec4(PI * sum / f32(phi_steps * theta_steps), 1.0);
+}
+
+// Hammersley 点集, 低差异的 [0, 1)^2
+fn hammersley(i: u32, count: u32) -> vec2f {
+    return vec2(f32(i) / f32(count), f32(reverseBits(i)) * 2.3283064365386963e-10);
+}
+
+// 按 GGX 分布采样半程向量, 和 mesh.wgsl 一样 alpha = roughness^2
+fn importance_sample_ggx(xi: vec2f, normal: vec3f, roughness: f32) -> vec3f {
+    let a = roughness * roughness;
+    let phi = 2.0 * PI * xi.x;
+    let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    let h = vec3(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+    var up = vec3(0.0, 0.0, 1.0);
+    if abs(normal.z) > 0.999 {
+        up = vec3(1.0, 0.0, 0.0);
+    }
+    let tangent = normalize(cross(up, normal));
+    let bitangent = cross(normal, tangent);
+    return normalize(tangent * h.x + bitangent * h.y + normal * h.z);
+}
+
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a2 = pow(roughness, 4.0);
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+const SAMPLES: u32 = 256u;
+
+// split-sum 的第一半: 假设视线就是法线, 按 GGX 预过滤. 样本的立体角比源 texel 大时读更粗的 mip, 免得亮点变成噪点
+@fragment
+fn prefilter_fs(in: VertexOut) -> @location(0) vec4f {
+    let normal = normalize(texel_direction(params.face, in.uv.x, in.uv.y));
+    let texel_solid_angle = 4.0 * PI / (6.0 * params.source_size * params.source_size);
+    var sum = vec3(0.0);
+    var weight = 0.0;
+    for (var i = 0u; i < SAMPLES; i++) {
+        let h = importance_sample_ggx(hammersley(i, SAMPLES), normal, params.roughness);
+        let l = normalize(2.0 * dot(normal, h) * h - normal);
+        let n_dot_l = dot(normal, l);
+        if n_dot_l > 0.0 {
+            let n_dot_h = max(dot(normal, h), 0.0);
+            // 视线等于法线时 h . v 就是 n . h
+            let pdf = distribution_ggx(n_dot_h, params.roughness) / 4.0 + 1e-4;
+            let sample_solid_angle = 1.0 / (f32(SAMPLES) * pdf + 1e-4);
+            var lod = 0.0;
+            if params.roughness > 0.0 {
+                lod = max(0.5 * log2(sample_solid_angle / texel_solid_angle), 0.0);
+            }
+            sum += textureSampleLevel(source, source_sampler, l, lod).rgb * n_dot_l;
+            weight += n_dot_l;
+        }
+    }
+    return vec4(sum / max(weight, 1e-4), 1.0);
+}
+
+// Schlick-GGX, 环境光用 k = r^2 / 2
+fn geometry_smith(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    let k = roughness * roughness / 2.0;
+    return n_dot_v / (n_dot_v * (1.0 - k) + k) * n_dot_l / (n_dot_l * (1.0 - k) + k);
+}
+
+// split-sum 的第二半: x 是 n . v, y 是粗糙度 (第一行是 0); 存 F0 的系数和偏移
+@fragment
+fn brdf_fs(in: VertexOut) -> @location(0) vec2f {
+    let n_dot_v = max((in.uv.x + 1.0) * 0.5, 1e-3);
+    let roughness = (in.uv.y + 1.0) * 0.5;
+    let v = vec3(sqrt(1.0 - n_dot_v * n_dot_v), 0.0, n_dot_v);
+    let normal = vec3(0.0, 0.0, 1.0);
+    var scale = 0.0;
+    var bias = 0.0;
+    for (var i = 0u; i < SAMPLES; i++) {
+        let h = importance_sample_ggx(hammersley(i, SAMPLES), normal, roughness);
+        let l = normalize(2.0 * dot(v, h) * h - v);
+        let n_dot_l = max(l.z, 0.0);
+        if n_dot_l > 0.0 {
+            let n_dot_h = max(h.z, 0.0);
+            let v_dot_h = max(dot(v, h), 0.0);
+            let visibility = geometry_smith(n_dot_v, n_dot_l, roughness) * v_dot_h / (n_dot_h * n_dot_v);
+            let fresnel = pow(1.0 - v_dot_h, 5.0);
+            scale += (1.0 - fresnel) * visibility;
+            bias += fresnel * visibility;
+        }
+    }
+    return vec2(scale, bias) / f32(SAMPLES);
+}
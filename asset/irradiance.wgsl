@@ -0,0 +1,93 @@
+struct SkyUniform {
+    sun_direction: vec3f,
+    turbidity: f32,
+    moon_direction: vec3f,
+    intensity: f32,
+    moon_radius: f32,
+    star_brightness: f32,
+    sun_radius: f32,
+}
+struct BakeParams {
+    probe: u32,
+    _pad0: u32,
+    _pad1: u32,
+    _pad2: u32,
+}
+
+// 探针位置拍下来的 cubemap, 按 +X -X +Y -Y +Z -Z 六层
+@group(0) @binding(0) var faces: texture_2d_array<f32>;
+@group(0) @binding(1) var<uniform> sky: SkyUniform;
+@group(0) @binding(2) var<uniform> params: BakeParams;
+// 每个探针 9 个 L2 球谐系数, rgb
+@group(0) @binding(3) var<storage, read_write> coefficients: array<vec4f>;
+
+const THREADS: u32 = 64u;
+
+var<workgroup> partial: array<array<vec3f, 9>, 64>;
+
+// 和 cube 采样同一套约定, v 向下
+fn texel_direction(face: u32, u: f32, v: f32) -> vec3f {
+    switch face {
+        case 0u: { return vec3(1.0, -v, -u); }
+        case 1u: { return vec3(-1.0, -v, u); }
+        case 2u: { return vec3(u, 1.0, v); }
+        case 3u: { return vec3(u, -1.0, -v); }
+        case 4u: { return vec3(u, -v, 1.0); }
+        default: { return vec3(-u, -v, -1.0); }
+    }
+}
+
+fn sh_basis(d: vec3f) -> array<f32, 9> {
+    return array<f32, 9>(
+        0.282095,
+        0.488603 * d.y,
+        0.488603 * d.z,
+        0.488603 * d.x,
+        1.092548 * d.x * d.y,
+        1.092548 * d.y * d.z,
+        0.315392 * (3.0 * d.z * d.z - 1.0),
+        1.092548 * d.x * d.z,
+        0.546274 * (d.x * d.x - d.y * d.y),
+    );
+}
+
+// 一个工作组投影一个探针: 每个线程累加一部分像素, 最后前 9 个线程各加一个系数
+@compute @workgroup_size(64)
+fn project(@builtin(local_invocation_index) thread: u32) {
+    let size = textureDimensions(faces).x;
+    let texels = size * size * 6u;
+    // 太阳已经是直射光了, 从天空里去掉, 不然地面会被照两次
+    let sun_cos = cos(max(sky.sun_radius, 0.01) * 2.0);
+
+    var sum: array<vec3f, 9>;
+    for (var i = 0u; i < 9u; i++) {
+        sum[i] = vec3(0.0);
+    }
+    for (var i = thread; i < texels; i += THREADS) {
+        let face = i / (size * size);
+        let xy = vec2(i % size, i / size % size);
+        let uv = (vec2f(xy) + 0.5) / f32(size) * 2.0 - 1.0;
+        let unnormalized = texel_direction(face, uv.x, uv.y);
+        let dir = normalize(unnormalized);
+        // 立体角: 越靠近面的角落, 一个像素覆盖的方向越少
+        let weight = 4.0 / (f32(size * size) * pow(dot(unnormalized, unnormalized), 1.5));
+        var radiance = textureLoad(faces, xy, face, 0).rgb;
+        if dot(dir, normalize(sky.sun_direction)) > sun_cos {
+            radiance = vec3(0.0);
+        }
+        var basis = sh_basis(dir);
+        for (var c = 0u; c < 9u; c++) {
+            sum[c] += radiance * basis[c] * weight;
+        }
+    }
+    partial[thread] = sum;
+    workgroupBarrier();
+
+    if thread < 9u {
+        var total = vec3(0.0);
+        for (var t = 0u; t < THREADS; t++) {
+            total += partial[t][thread];
+        }
+        coefficients[params.probe * 9u + thread] = vec4(total, 0.0);
+    }
+}
@@ -0,0 +1,210 @@
+// 网格的光照, 正向的材质 shader 和延迟的光照 pass 共用; 它们 #include 这个文件
+#include "camera.wgsl"
+
+struct SkyUniform {
+    sun_direction: vec3f,
+    turbidity: f32,
+    moon_direction: vec3f,
+    intensity: f32,
+    moon_radius: f32,
+    star_brightness: f32,
+    sun_radius: f32,
+    cloud_coverage: f32,
+    light_color: vec3f,
+    light_intensity: f32,
+    light_direction: vec3f,
+    cloud_height: f32,
+    ambient: vec3f,
+}
+@group(1) @binding(0) var<uniform> camera: CameraUniform;
+
+@group(0) @binding(1) var<uniform> sky: SkyUniform;
+
+// 点光源和 Blinn-Phong 的系数, lit 和 pbr 的着色用; intensity 为 0 表示关掉
+struct Light {
+    position: vec3f,
+    intensity: f32,
+    color: vec3f,
+    ambient: f32,
+    diffuse: f32,
+    specular: f32,
+    shininess: f32,
+}
+@group(3) @binding(0) var<uniform> light: Light;
+
+// 太阳 (或月亮) 的阴影贴图, enabled 为 0 表示没有
+struct Shadow {
+    view_proj: mat4x4<f32>,
+    bias: f32,
+    normal_offset: f32,
+    texel_size: f32,
+    enabled: f32,
+}
+@group(3) @binding(1) var<uniform> shadow: Shadow;
+@group(3) @binding(2) var shadow_map: texture_depth_2d;
+@group(3) @binding(3) var shadow_sampler: sampler_comparison;
+
+// HDR 环境图的基于图像的光照, 只有 pbr 的着色用; enabled 为 0 表示没有, 用天空的平均环境光
+struct Ibl {
+    intensity: f32,
+    // 粗糙度 1 对应的预过滤 mip
+    max_lod: f32,
+    enabled: f32,
+}
+@group(3) @binding(4) var<uniform> ibl: Ibl;
+// 存的是 E / PI, 直接乘反照率
+@group(3) @binding(5) var irradiance_map: texture_cube<f32>;
+@group(3) @binding(6) var prefiltered_map: texture_cube<f32>;
+// x 是 n . v, y 是粗糙度; 存 F0 的系数和偏移
+@group(3) @binding(7) var brdf_lut: texture_2d<f32>;
+@group(3) @binding(8) var ibl_sampler: sampler;
+
+// 1 是照得到, 3x3 PCF 让边缘柔一点; 阴影贴图外面算照得到
+fn sun_shadow(world_position: vec3f, normal: vec3f) -> f32 {
+    if shadow.enabled == 0.0 {
+        return 1.0;
+    }
+    let clip = shadow.view_proj * vec4(world_position + normal * shadow.normal_offset, 1.0);
+    let ndc = clip.xyz / clip.w;
+    let uv = ndc.xy * vec2(0.5, -0.5) + 0.5;
+    if any(uv < vec2(0.0)) || any(uv > vec2(1.0)) || ndc.z > 1.0 {
+        return 1.0;
+    }
+    var lit = 0.0;
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let offset = vec2(f32(x), f32(y)) * shadow.texel_size;
+            lit += textureSampleCompareLevel(shadow_map, shadow_sampler, uv + offset, ndc.z - shadow.bias);
+        }
+    }
+    return lit / 9.0;
+}
+
+// 一个光源的 Blinn-Phong 漫反射 + 高光, radiance 是照到这点的光
+fn blinn_phong(normal: vec3f, to_light: vec3f, to_eye: vec3f, radiance: vec3f, albedo: vec3f) -> vec3f {
+    let n_dot_l = dot(normal, to_light);
+    if n_dot_l <= 0.0 {
+        return vec3(0.0);
+    }
+    let half_vector = normalize(to_light + to_eye);
+    let specular = light.specular * pow(max(dot(normal, half_vector), 0.0), light.shininess);
+    return radiance * (albedo * light.diffuse * n_dot_l + specular);
+}
+
+const PI: f32 = 3.14159265;
+
+// GGX 法线分布
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a2 = pow(roughness, 4.0);
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+// Schlick-GGX 的几何遮蔽, 直接光用 k = (r + 1)^2 / 8
+fn geometry_schlick_ggx(n_dot_x: f32, k: f32) -> f32 {
+    return n_dot_x / (n_dot_x * (1.0 - k) + k);
+}
+
+fn fresnel_schlick(cos_theta: f32, f0: vec3f) -> vec3f {
+    return f0 + (1.0 - f0) * pow(1.0 - cos_theta, 5.0);
+}
+
+// 环境光没有单一的半程向量, 粗糙的表面边缘不该亮到 1
+fn fresnel_schlick_roughness(cos_theta: f32, f0: vec3f, roughness: f32) -> vec3f {
+    return f0 + (max(vec3(1.0 - roughness), f0) - f0) * pow(1.0 - cos_theta, 5.0);
+}
+
+// split-sum 的环境光: 漫反射读辐照度, 高光按粗糙度读预过滤的 mip 再乘 BRDF 查找表
+fn image_based_light(normal: vec3f, to_eye: vec3f, albedo: vec3f, metallic: f32, roughness: f32) -> vec3f {
+    let n_dot_v = max(dot(normal, to_eye), 1e-4);
+    let f0 = mix(vec3(0.04), albedo, metallic);
+    let fresnel = fresnel_schlick_roughness(n_dot_v, f0, roughness);
+    let irradiance = textureSampleLevel(irradiance_map, ibl_sampler, normal, 0.0).rgb;
+    let diffuse = (1.0 - fresnel) * (1.0 - metallic) * albedo * irradiance;
+    let reflected = reflect(-to_eye, normal);
+    let prefiltered = textureSampleLevel(prefiltered_map, ibl_sampler, reflected, roughness * ibl.max_lod).rgb;
+    let brdf = textureSampleLevel(brdf_lut, ibl_sampler, vec2(n_dot_v, roughness), 0.0).rg;
+    let specular = prefiltered * (fresnel * brdf.x + brdf.y);
+    return (diffuse + specular) * ibl.intensity;
+}
+
+// 一个光源的 Cook-Torrance, radiance 是照到这点的光
+// 场景里直射光的漫反射不除以 PI (见 sky.wgsl), 这里也不除, 高光乘 PI 保持同样的比例
+fn cook_torrance(normal: vec3f, to_light: vec3f, to_eye: vec3f, radiance: vec3f, albedo: vec3f, metallic: f32, roughness: f32) -> vec3f {
+    let n_dot_l = dot(normal, to_light);
+    if n_dot_l <= 0.0 {
+        return vec3(0.0);
+    }
+    let n_dot_v = max(dot(normal, to_eye), 1e-4);
+    let half_vector = normalize(to_light + to_eye);
+    let f0 = mix(vec3(0.04), albedo, metallic);
+    let fresnel = fresnel_schlick(max(dot(half_vector, to_eye), 0.0), f0);
+    let k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+    let geometry = geometry_schlick_ggx(n_dot_v, k) * geometry_schlick_ggx(n_dot_l, k);
+    let distribution = distribution_ggx(max(dot(normal, half_vector), 0.0), roughness);
+    let specular = distribution * geometry * fresnel / (4.0 * n_dot_v * n_dot_l);
+    let diffuse = (1.0 - fresnel) * (1.0 - metallic) * albedo;
+    return (diffuse + specular * PI) * radiance * n_dot_l;
+}
+
+// 下面三个是材质 shader 的光照, 不含自发光; 正向的片元和延迟的光照 pass 算的一样
+
+// 只有太阳的漫反射和环境光
+fn shade_mesh(world_position: vec3f, normal: vec3f, albedo: vec3f) -> vec3f {
+    let diffuse = max(dot(normal, sky.light_direction), 0.0) * sun_shadow(world_position, normal);
+    return albedo * (sky.light_color * sky.light_intensity * diffuse + sky.ambient);
+}
+
+// 太阳和点光源都算 Blinn-Phong
+fn shade_lit(world_position: vec3f, normal: vec3f, albedo: vec3f) -> vec3f {
+    let to_eye = normalize(camera.view_position.xyz - world_position);
+    var color = albedo * sky.ambient * light.ambient;
+    color += blinn_phong(
+        normal,
+        sky.light_direction,
+        to_eye,
+        sky.light_color * sky.light_intensity * sun_shadow(world_position, normal),
+        albedo,
+    );
+    let to_light = light.position - world_position;
+    let distance2 = max(dot(to_light, to_light), 1e-4);
+    color += blinn_phong(
+        normal,
+        to_light * inverseSqrt(distance2),
+        to_eye,
+        light.color * light.intensity / distance2,
+        albedo,
+    );
+    return color;
+}
+
+// glTF 的 metallic-roughness, 太阳和点光源, 有环境图时环境光来自它
+fn shade_pbr(world_position: vec3f, normal: vec3f, albedo: vec3f, metallic: f32, roughness: f32, ao: f32) -> vec3f {
+    let to_eye = normalize(camera.view_position.xyz - world_position);
+    var ambient = albedo * (1.0 - metallic) * sky.ambient;
+    if ibl.enabled != 0.0 {
+        ambient = image_based_light(normal, to_eye, albedo, metallic, roughness);
+    }
+    var color = ambient * light.ambient * ao;
+    color += cook_torrance(
+        normal,
+        sky.light_direction,
+        to_eye,
+        sky.light_color * sky.light_intensity * sun_shadow(world_position, normal),
+        albedo,
+        metallic,
+        roughness,
+    );
+    let to_light = light.position - world_position;
+    let distance2 = max(dot(to_light, to_light), 1e-4);
+    color += cook_torrance(
+        normal,
+        to_light * inverseSqrt(distance2),
+        to_eye,
+        light.color * light.intensity / distance2,
+        albedo,
+        metallic,
+        roughness,
+    );
+    return color;
+}
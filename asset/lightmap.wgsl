@@ -0,0 +1,170 @@
+struct BakeParams {
+    center: vec2f,
+    extent: f32,
+    samples: u32,
+    light_direction: vec3f,
+    sun_radius: f32,
+    light_color: vec3f,
+    ambient: vec3f,
+}
+struct StaticBox {
+    center: vec4f,
+    half_size: vec4f,
+    albedo: vec4f,
+}
+struct StaticGeometry {
+    count: u32,
+    lightmap: vec4f,
+    ao: vec4f,
+    boxes: array<StaticBox, 16>,
+    lightmap_charts: array<vec4f, 96>,
+}
+// 盒子面展开后的一块: rect 是贴图里的 x, y, 宽, 高; 一个 texel 沿 x/y 在平面上走 texel_x/texel_y
+struct Chart {
+    rect: vec4u,
+    origin: vec4f,
+    texel_x: vec4f,
+    texel_y: vec4f,
+    normal: vec4f,
+}
+struct Charts {
+    count: u32,
+    charts: array<Chart, 96>,
+}
+
+@group(0) @binding(0) var<uniform> params: BakeParams;
+@group(0) @binding(1) var<uniform> static_geometry: StaticGeometry;
+@group(0) @binding(2) var<uniform> charts: Charts;
+// 上面一块正方形是地面, 下面是盒子面的图集
+// rgb: 间接光 (和 ambient 同量纲), a: 太阳可见度
+@group(0) @binding(3) var lightmap: texture_storage_2d<rgba16float, write>;
+
+const PI: f32 = 3.14159265;
+
+struct BoxHit {
+    // < 0 = 没打中
+    t: f32,
+    normal: vec3f,
+    index: u32,
+}
+
+// 和 sky.wgsl 里的一样, slab 求交
+fn trace_boxes(origin: vec3f, dir: vec3f) -> BoxHit {
+    var hit = BoxHit(-1.0, vec3(0.0), 0u);
+    let inv = 1.0 / dir;
+    for (var i = 0u; i < static_geometry.count; i++) {
+        let b = static_geometry.boxes[i];
+        let t0 = (b.center.xyz - b.half_size.xyz - origin) * inv;
+        let t1 = (b.center.xyz + b.half_size.xyz - origin) * inv;
+        let near = min(t0, t1);
+        let far = max(t0, t1);
+        let t_near = max(max(near.x, near.y), near.z);
+        let t_far = min(min(far.x, far.y), far.z);
+        if t_near > 0.0 && t_near <= t_far && (hit.t < 0.0 || t_near < hit.t) {
+            hit.t = t_near;
+            hit.normal = -sign(dir) * step(vec3(t_near), near);
+            hit.index = i;
+        }
+    }
+    return hit;
+}
+
+fn hash(p: vec3u) -> f32 {
+    var h = p.x * 747796405u + p.y * 2891336453u + p.z * 277803737u;
+    h = ((h >> ((h >> 28u) + 4u)) ^ h) * 277803737u;
+    h = (h >> 22u) ^ h;
+    return f32(h) / 4294967295.0;
+}
+
+fn orthonormal(n: vec3f) -> mat3x3<f32> {
+    let t = normalize(cross(n, select(vec3(0.0, 1.0, 0.0), vec3(1.0, 0.0, 0.0), abs(n.y) > 0.99)));
+    return mat3x3(t, cross(n, t), n);
+}
+
+// 阴影射线在太阳圆盘里随机偏一点, 出软阴影
+fn sun_visibility(p: vec3f, u: vec2f) -> f32 {
+    let l = normalize(params.light_direction);
+    if l.y <= 0.0 {
+        return 0.0;
+    }
+    let r = params.sun_radius * sqrt(u.x);
+    let phi = 2.0 * PI * u.y;
+    let dir = normalize(orthonormal(l) * vec3(r * cos(phi), r * sin(phi), 1.0));
+    return select(1.0, 0.0, trace_boxes(p, dir).t > 0.0);
+}
+
+// 地面的颜色, sky.wgsl 里的泥土去掉噪声
+const SOIL: vec3f = vec3(0.1005, 0.0732, 0.0509);
+
+// 一次弹射: 打中盒子或地面就取它被直射光 + 环境光照亮的颜色, 都没打中就是天空 (ambient)
+fn bounce(origin: vec3f, dir: vec3f, u: vec2f) -> vec3f {
+    let hit = trace_boxes(origin, dir);
+    let ground_t = select(-1.0, -origin.y / dir.y, dir.y < 0.0 && origin.y > 0.0);
+    var albedo = SOIL;
+    var normal = vec3(0.0, 1.0, 0.0);
+    var t = ground_t;
+    if hit.t > 0.0 && (ground_t < 0.0 || hit.t < ground_t) {
+        albedo = static_geometry.boxes[hit.index].albedo.rgb;
+        normal = hit.normal;
+        t = hit.t;
+    }
+    if t < 0.0 {
+        return params.ambient;
+    }
+    let q = origin + dir * t + normal * 0.001;
+    let n_dot_l = max(dot(normal, normalize(params.light_direction)), 0.0);
+    let direct = params.light_color * n_dot_l * sun_visibility(q, u);
+    return albedo * (direct + params.ambient);
+}
+
+// 积分一个点: (间接光, 太阳可见度)
+fn integrate(p: vec3f, normal: vec3f, id: vec2u) -> vec4f {
+    let basis = orthonormal(normal);
+    var indirect = vec3(0.0);
+    var visibility = 0.0;
+    for (var s = 0u; s < params.samples; s++) {
+        let u = vec4(
+            hash(vec3(id, s * 4u)),
+            hash(vec3(id, s * 4u + 1u)),
+            hash(vec3(id, s * 4u + 2u)),
+            hash(vec3(id, s * 4u + 3u)),
+        );
+        // 余弦加权的半球采样, 平均值直接就是辐照度 / PI
+        let r = sqrt(u.x);
+        let phi = 2.0 * PI * u.y;
+        let dir = basis * vec3(r * cos(phi), r * sin(phi), sqrt(1.0 - u.x));
+        indirect += bounce(p, dir, u.zw);
+        // 背光的面太阳照不到
+        visibility += sun_visibility(p, u.zw) * f32(dot(normal, params.light_direction) > 0.0);
+    }
+    let n = f32(params.samples);
+    return vec4(indirect / n, visibility / n);
+}
+
+// 上面的正方形每个 texel 是地面 (y = 0) 上的一个点, 法线朝上; 下面的在某个盒子面上
+@compute @workgroup_size(8, 8)
+fn bake(@builtin(global_invocation_id) id: vec3u) {
+    let size = textureDimensions(lightmap);
+    if any(id.xy >= size) {
+        return;
+    }
+    if id.y < size.x {
+        let uv = (vec2f(id.xy) + 0.5) / f32(size.x);
+        let xz = params.center + (uv - 0.5) * params.extent;
+        textureStore(lightmap, id.xy, integrate(vec3(xz.x, 0.001, xz.y), vec3(0.0, 1.0, 0.0), id.xy));
+        return;
+    }
+    for (var i = 0u; i < charts.count; i++) {
+        let chart = charts.charts[i];
+        let local = id.xy - chart.rect.xy;
+        if all(id.xy >= chart.rect.xy) && all(local < chart.rect.zw) {
+            let texel = vec2f(local) + 0.5;
+            let n = chart.normal.xyz;
+            let p = chart.origin.xyz + chart.texel_x.xyz * texel.x + chart.texel_y.xyz * texel.y + n * 0.001;
+            textureStore(lightmap, id.xy, integrate(p, n, id.xy));
+            return;
+        }
+    }
+    // 不属于任何一块
+    textureStore(lightmap, id.xy, vec4(0.0));
+}
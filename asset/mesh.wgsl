@@ -0,0 +1,189 @@
+// 光栅化的网格, 画在光线投射的场景之后, 同一个 pass, 靠深度缓冲互相遮挡
+// 相机, 天空和 group 3 的光照在 lighting.wgsl 里
+#include "lighting.wgsl"
+
+// 材质, 和 glTF 的 metallic-roughness 一样: 系数乘贴图, 没有贴图的槽位是 1x1 白色 (法线是平的)
+struct MaterialParams {
+    base_color: vec4f,
+    emissive: vec3f,
+    metallic: f32,
+    roughness: f32,
+    normal_scale: f32,
+    occlusion_strength: f32,
+    // alpha 小于它的片元丢掉, 0 表示不裁剪
+    alpha_cutoff: f32,
+}
+@group(2) @binding(0) var<uniform> material: MaterialParams;
+@group(2) @binding(1) var material_sampler: sampler;
+@group(2) @binding(2) var base_color_texture: texture_2d<f32>;
+// g 是粗糙度, b 是金属度
+@group(2) @binding(3) var metallic_roughness_texture: texture_2d<f32>;
+@group(2) @binding(4) var normal_texture: texture_2d<f32>;
+// r 是环境光遮蔽
+@group(2) @binding(5) var occlusion_texture: texture_2d<f32>;
+
+// 混合的材质用贴图的 alpha, 不透明的写 1 (透明窗口靠 alpha 合成)
+override alpha_blend: bool = false;
+
+// 地面是 1, 盒子从 2 开始最多 16 个, 网格排在后面
+const MESH_ID: u32 = 18u;
+
+struct VertexIn {
+    @location(0) position: vec3f,
+    @location(1) normal: vec3f,
+    @location(2) uv: vec2f,
+    // w 是副切线相对 normal x tangent 的方向
+    @location(3) tangent: vec4f,
+}
+
+// 每个实例一份: 模型矩阵按列, 颜色乘在基础色上
+struct InstanceIn {
+    @location(4) model_0: vec4f,
+    @location(5) model_1: vec4f,
+    @location(6) model_2: vec4f,
+    @location(7) model_3: vec4f,
+    @location(8) color: vec4f,
+}
+
+struct VertexOut {
+    @builtin(position) pos: vec4f,
+    @location(0) normal: vec3f,
+    @location(1) uv: vec2f,
+    @location(2) world_position: vec3f,
+    @location(3) tangent: vec4f,
+    @location(4) color: vec4f,
+}
+
+@vertex
+fn mesh_vs(in: VertexIn, instance: InstanceIn) -> VertexOut {
+    let model = mat4x4(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+    let world_position = model * vec4(in.position, 1.0);
+    var out: VertexOut;
+    out.pos = camera.view_proj * world_position;
+    // 缩放各轴一样, 法线和切线直接用模型矩阵转, 片元里再归一化
+    out.normal = (model * vec4(in.normal, 0.0)).xyz;
+    out.uv = in.uv;
+    out.world_position = world_position.xyz;
+    out.tangent = vec4((model * vec4(in.tangent.xyz, 0.0)).xyz, in.tangent.w);
+    out.color = instance.color;
+    return out;
+}
+
+struct SceneOut {
+    @location(0) color: vec4f,
+    @location(1) surface: vec4f,
+    @location(2) depth: f32,
+    @location(3) id: u32,
+}
+
+fn scene_out(in: VertexOut, normal: vec3f, color: vec3f, alpha: f32) -> SceneOut {
+    if alpha < material.alpha_cutoff {
+        discard;
+    }
+    var out: SceneOut;
+    if alpha_blend {
+        out.color = vec4(color, alpha);
+    } else {
+        out.color = vec4(color, 1.0);
+    }
+    out.surface = vec4(normal, 0.0);
+    out.depth = in.pos.z;
+    out.id = MESH_ID;
+    return out;
+}
+
+fn base_color(in: VertexOut) -> vec4f {
+    return material.base_color * in.color * textureSample(base_color_texture, material_sampler, in.uv);
+}
+
+// 法线贴图扰动后的法线, 切线空间用顶点的 TBN; 没有切线的顶点不扰动, 双面材质的背面翻过来
+fn surface_normal(in: VertexOut, front_facing: bool) -> vec3f {
+    // 采样要在统一控制流里, 先于分支
+    var mapped = textureSample(normal_texture, material_sampler, in.uv).xyz * 2.0 - 1.0;
+    mapped = vec3(mapped.xy * material.normal_scale, mapped.z);
+    var normal = normalize(in.normal);
+    let tangent = in.tangent.xyz - normal * dot(normal, in.tangent.xyz);
+    if dot(tangent, tangent) > 1e-8 {
+        let t = normalize(tangent);
+        let bitangent = cross(normal, t) * in.tangent.w;
+        normal = normalize(mat3x3(t, bitangent, normal) * mapped);
+    }
+    if !front_facing {
+        normal = -normal;
+    }
+    return normal;
+}
+
+// 粗糙度和金属度, 太光滑的高光只有一个点, 也会除以接近 0 的数
+fn metallic_roughness(uv: vec2f) -> vec2f {
+    let texel = textureSample(metallic_roughness_texture, material_sampler, uv);
+    return vec2(clamp(material.metallic * texel.b, 0.0, 1.0), clamp(material.roughness * texel.g, 0.04, 1.0));
+}
+
+fn ambient_occlusion(uv: vec2f) -> f32 {
+    let occlusion = textureSample(occlusion_texture, material_sampler, uv).r;
+    return mix(1.0, occlusion, material.occlusion_strength);
+}
+
+@fragment
+fn mesh_fs(in: VertexOut) -> SceneOut {
+    let normal = normalize(in.normal);
+    let albedo = base_color(in);
+    let color = shade_mesh(in.world_position, normal, albedo.rgb) + material.emissive;
+    return scene_out(in, normal, color, albedo.a);
+}
+
+@fragment
+fn lit_fs(in: VertexOut, @builtin(front_facing) front_facing: bool) -> SceneOut {
+    let normal = surface_normal(in, front_facing);
+    let albedo = base_color(in);
+    let color = shade_lit(in.world_position, normal, albedo.rgb) + material.emissive;
+    return scene_out(in, normal, color, albedo.a);
+}
+
+@fragment
+fn pbr_fs(in: VertexOut, @builtin(front_facing) front_facing: bool) -> SceneOut {
+    let normal = surface_normal(in, front_facing);
+    let albedo = base_color(in);
+    let material_mr = metallic_roughness(in.uv);
+    let ao = ambient_occlusion(in.uv);
+    let color = shade_pbr(in.world_position, normal, albedo.rgb, material_mr.x, material_mr.y, ao) + material.emissive;
+    return scene_out(in, normal, color, albedo.a);
+}
+
+// 延迟渲染的几何 pass 用哪种光照, 和 MaterialShader 一样: 0 mesh, 1 lit, 2 pbr
+override shading: u32 = 0u;
+
+struct DeferredOut {
+    // 只有自发光, 光照 pass 再加上去
+    @location(0) color: vec4f,
+    @location(1) surface: vec4f,
+    @location(2) depth: f32,
+    @location(3) id: u32,
+    // 每个 8 位打包进 u32, 整个 pass 才不超过每像素 32 字节的限制
+    // 反照率开平方 (gamma 2, 暗处精度够), a 是环境光遮蔽
+    @location(4) albedo: u32,
+    // 金属度, 粗糙度, shading / 2; a 为 1 标出延迟着色的像素
+    @location(5) material: u32,
+}
+
+// 不透明的材质在延迟渲染里只写表面, 光照在 deferred.wgsl 的全屏 pass 里一起算
+@fragment
+fn deferred_fs(in: VertexOut, @builtin(front_facing) front_facing: bool) -> DeferredOut {
+    // mesh_fs 不用法线贴图, 也不翻背面
+    let normal = select(surface_normal(in, front_facing), normalize(in.normal), shading == 0u);
+    let albedo = base_color(in);
+    let material_mr = metallic_roughness(in.uv);
+    let ao = ambient_occlusion(in.uv);
+    if albedo.a < material.alpha_cutoff {
+        discard;
+    }
+    var out: DeferredOut;
+    out.color = vec4(material.emissive, 1.0);
+    out.surface = vec4(normal, 0.0);
+    out.depth = in.pos.z;
+    out.id = MESH_ID;
+    out.albedo = pack4x8unorm(vec4(sqrt(albedo.rgb), ao));
+    out.material = pack4x8unorm(vec4(material_mr, f32(shading) / 2.0, 1.0));
+    return out;
+}
@@ -0,0 +1,128 @@
+// 粒子系统: 计算着色器发射, 积分, 老化; 渲染时每个粒子一个朝向相机的面片, 加法混合
+// 粒子缓冲同时是渲染的实例顶点缓冲, 不用再绑一次只读存储
+
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+    inv_view_proj: mat4x4<f32>,
+    view_position: vec4<f32>,
+};
+
+// 和 particles.rs 的 ParticleParams 一样
+struct ParticleParams {
+    emitter: vec3f,
+    dt: f32,
+    gravity: vec3f,
+    lifetime: f32,
+    // HDR, 已经乘了强度
+    color: vec3f,
+    size: f32,
+    // 环形缓冲: 本帧从 emit_start 开始的 emit_count 个粒子重新发射
+    emit_start: u32,
+    emit_count: u32,
+    particle_count: u32,
+    seed: u32,
+    speed: f32,
+    // 发射锥的张开程度, 0 = 笔直向上
+    spread: f32,
+    time: f32,
+    _pad: f32,
+}
+
+// age >= lifetime 就是死的, 全零的缓冲一开始全都是死的
+struct Particle {
+    position: vec3f,
+    age: f32,
+    velocity: vec3f,
+    lifetime: f32,
+}
+
+@group(0) @binding(0) var<uniform> camera: CameraUniform;
+@group(0) @binding(1) var<uniform> params: ParticleParams;
+@group(1) @binding(0) var<storage, read_write> particles: array<Particle>;
+
+fn hash1(n: f32) -> f32 {
+    return fract(sin(n * 12.9898) * 43758.5453);
+}
+
+// ---- 计算: 发射, 积分, 老化 ----
+
+@compute @workgroup_size(64)
+fn update(@builtin(global_invocation_id) gid: vec3u) {
+    let index = gid.x;
+    if index >= params.particle_count {
+        return;
+    }
+    var p = particles[index];
+    // 离本帧发射起点的距离, 绕一圈
+    let slot = (index + params.particle_count - params.emit_start) % params.particle_count;
+    if slot < params.emit_count {
+        let seed = f32(index) * 0.618 + params.time * 7.13 + f32(params.seed % 1024u) * 17.31;
+        let direction = normalize(vec3(
+            (hash1(seed) * 2.0 - 1.0) * params.spread,
+            1.0,
+            (hash1(seed + 1.7) * 2.0 - 1.0) * params.spread,
+        ));
+        p.position = params.emitter;
+        p.velocity = direction * params.speed * (0.75 + 0.5 * hash1(seed + 3.1));
+        // 一帧里发射的粒子分散在这一帧的时间里, 不会一团一团的
+        p.age = hash1(seed + 4.9) * params.dt;
+        p.lifetime = params.lifetime * (0.5 + 0.5 * hash1(seed + 5.3));
+    } else if p.age < p.lifetime {
+        p.velocity += params.gravity * params.dt;
+        p.position += p.velocity * params.dt;
+        p.age += params.dt;
+    }
+    particles[index] = p;
+}
+
+// ---- 渲染 ----
+
+struct VertexOut {
+    @builtin(position) pos: vec4f,
+    @location(0) uv: vec2f,
+    @location(1) fade: f32,
+}
+
+@vertex
+fn particle_vs(
+    @builtin(vertex_index) vid: u32,
+    @location(0) position_age: vec4f,
+    @location(1) velocity_lifetime: vec4f,
+) -> VertexOut {
+    var out: VertexOut;
+    let age = position_age.w;
+    let lifetime = velocity_lifetime.w;
+    // 死掉的粒子扔到裁剪空间外面
+    if age >= lifetime {
+        out.pos = vec4(0.0, 0.0, 2.0, 1.0);
+        return out;
+    }
+    let position = position_age.xyz;
+    let to_camera = normalize(camera.view_position.xyz - position);
+    // 正对着上下看的时候换个参考轴
+    var reference = vec3(0.0, 1.0, 0.0);
+    if abs(to_camera.y) > 0.99 {
+        reference = vec3(1.0, 0.0, 0.0);
+    }
+    let side = normalize(cross(reference, to_camera));
+    let up = cross(to_camera, side);
+
+    var corners = array<vec2f, 6>(
+        vec2(-1.0, -1.0), vec2(1.0, -1.0), vec2(-1.0, 1.0),
+        vec2(-1.0, 1.0), vec2(1.0, -1.0), vec2(1.0, 1.0),
+    );
+    let corner = corners[vid];
+    let world = position + (side * corner.x + up * corner.y) * params.size;
+    out.pos = camera.view_proj * vec4(world, 1.0);
+    out.uv = corner;
+    // 出生时最亮, 慢慢变暗
+    out.fade = 1.0 - age / max(lifetime, 1e-4);
+    return out;
+}
+
+@fragment
+fn particle_fs(in: VertexOut) -> @location(0) vec4f {
+    // 软边圆点, 加法混合所以只输出颜色
+    let glow = smoothstep(1.0, 0.0, length(in.uv));
+    return vec4(params.color * glow * in.fade, 0.0);
+}
@@ -0,0 +1,231 @@
+#include "camera.wgsl"
+#include "bvh.wgsl"
+
+struct SkyUniform {
+    sun_direction: vec3f,
+    turbidity: f32,
+    moon_direction: vec3f,
+    intensity: f32,
+    moon_radius: f32,
+    star_brightness: f32,
+    sun_radius: f32,
+    cloud_coverage: f32,
+    light_color: vec3f,
+    light_intensity: f32,
+    light_direction: vec3f,
+    cloud_height: f32,
+    ambient: vec3f,
+    snow_extent: f32,
+    cloud_offset: vec2f,
+    ground_wetness: f32,
+    water: vec4f,
+}
+struct Params {
+    width: u32,
+    height: u32,
+    frame: u32,
+    samples: u32,
+    max_bounces: u32,
+    // 固定的随机种子, 确定性模式下两次运行结果一致
+    seed: u32,
+    // spheres 里有效的个数, 没有球时缓冲里放了一个占位的
+    sphere_count: u32,
+    _pad: u32,
+}
+
+// 和 path_tracer.rs 的 SphereData 一样
+struct Sphere {
+    center_radius: vec4f,
+    albedo: vec4f,
+    // 自发光, 线性
+    emission: vec4f,
+}
+
+@group(0) @binding(0) var<uniform> camera: CameraUniform;
+@group(0) @binding(1) var<uniform> sky: SkyUniform;
+@group(0) @binding(2) var<uniform> params: Params;
+@group(0) @binding(3) var<storage, read> triangles: array<Triangle>;
+// 相机位置拍的环境 cubemap, 光线飞出场景时当天空用
+@group(0) @binding(4) var environment: texture_cube<f32>;
+@group(0) @binding(5) var environment_sampler: sampler;
+@group(0) @binding(6) var output: texture_storage_2d<rgba16float, write>;
+// 三角形按 BVH 叶子的顺序排好了
+@group(0) @binding(7) var<storage, read> bvh_nodes: array<BvhNode>;
+// 给降噪用: 像素中心那条射线第一次打中的 albedo, 法线 + 距离 (天空 albedo 是 1, 距离是 0)
+@group(0) @binding(8) var first_albedo: texture_storage_2d<rgba8unorm, write>;
+@group(0) @binding(9) var first_normal_depth: texture_storage_2d<rgba16float, write>;
+@group(0) @binding(10) var<storage, read> spheres: array<Sphere>;
+
+const PI: f32 = 3.14159265;
+
+struct Hit {
+    t: f32,
+    normal: vec3f,
+    albedo: vec3f,
+    emission: vec3f,
+}
+
+fn hash(p: vec3u) -> f32 {
+    var h = p.x * 747796405u + p.y * 2891336453u + p.z * 277803737u;
+    h = ((h >> ((h >> 28u) + 4u)) ^ h) * 277803737u;
+    h = (h >> 22u) ^ h;
+    return f32(h) / 4294967295.0;
+}
+
+struct Rng {
+    pixel: vec2u,
+    index: u32,
+}
+
+fn next(rng: ptr<function, Rng>) -> f32 {
+    (*rng).index += 1u;
+    return hash(vec3((*rng).pixel, (*rng).index));
+}
+
+fn orthonormal(n: vec3f) -> mat3x3<f32> {
+    let t = normalize(cross(n, select(vec3(0.0, 1.0, 0.0), vec3(1.0, 0.0, 0.0), abs(n.y) > 0.99)));
+    return mat3x3(t, cross(n, t), n);
+}
+
+// 光线第一次从外面 (或者从里面) 碰到球的距离, 没碰到是 NO_HIT
+fn intersect_sphere(origin: vec3f, dir: vec3f, sphere: Sphere) -> f32 {
+    let oc = origin - sphere.center_radius.xyz;
+    let b = dot(oc, dir);
+    let c = dot(oc, oc) - sphere.center_radius.w * sphere.center_radius.w;
+    let h = b * b - c;
+    if h < 0.0 {
+        return NO_HIT;
+    }
+    var t = -b - sqrt(h);
+    if t < 1e-4 {
+        t = -b + sqrt(h);
+    }
+    return select(NO_HIT, t, t >= 1e-4);
+}
+
+// 地面 (y = 0) 加上 BVH 里的三角形和球
+fn trace(origin: vec3f, dir: vec3f) -> Hit {
+    var hit = Hit(NO_HIT, vec3(0.0), vec3(0.0), vec3(0.0));
+    if dir.y < 0.0 && origin.y > 0.0 {
+        hit.t = -origin.y / dir.y;
+        hit.normal = vec3(0.0, 1.0, 0.0);
+        hit.albedo = pow(vec3(0.35, 0.3, 0.25), vec3(2.2));
+    }
+    let closest = bvh_trace(origin, dir, hit.t);
+    if closest.t < NO_HIT {
+        let tri = triangles[closest.triangle];
+        hit.t = closest.t;
+        let n = normalize(cross(tri.v1.xyz - tri.v0.xyz, tri.v2.xyz - tri.v0.xyz));
+        hit.normal = select(n, -n, dot(n, dir) > 0.0);
+        hit.albedo = tri.albedo.rgb;
+    }
+    for (var i = 0u; i < params.sphere_count; i++) {
+        let sphere = spheres[i];
+        let t = intersect_sphere(origin, dir, sphere);
+        if t < hit.t {
+            hit.t = t;
+            let n = (origin + dir * t - sphere.center_radius.xyz) / sphere.center_radius.w;
+            hit.normal = select(n, -n, dot(n, dir) > 0.0);
+            hit.albedo = sphere.albedo.rgb;
+            hit.emission = sphere.emission.rgb;
+        }
+    }
+    return hit;
+}
+
+fn occluded(origin: vec3f, dir: vec3f) -> bool {
+    return trace(origin, dir).t < NO_HIT;
+}
+
+// 主光的直射, 阴影射线在光源圆盘里随机偏一点出软阴影
+fn direct_light(p: vec3f, n: vec3f, rng: ptr<function, Rng>) -> vec3f {
+    let l = normalize(sky.light_direction);
+    let n_dot_l = dot(n, l);
+    if n_dot_l <= 0.0 || l.y <= 0.0 {
+        return vec3(0.0);
+    }
+    let r = sky.sun_radius * sqrt(next(rng));
+    let phi = 2.0 * PI * next(rng);
+    let dir = normalize(orthonormal(l) * vec3(r * cos(phi), r * sin(phi), 1.0));
+    if occluded(p, dir) {
+        return vec3(0.0);
+    }
+    return sky.light_color * sky.light_intensity * n_dot_l;
+}
+
+fn escaped(dir: vec3f, bounce: u32) -> vec3f {
+    var radiance = textureSampleLevel(environment, environment_sampler, dir, 0.0).rgb;
+    // 弹射后的光线看到的太阳已经在直射里算过了
+    if bounce > 0u && dot(dir, normalize(sky.light_direction)) > cos(max(sky.sun_radius, 0.01) * 2.0) {
+        radiance = vec3(0.0);
+    }
+    return radiance;
+}
+
+fn radiance(origin: vec3f, direction: vec3f, rng: ptr<function, Rng>) -> vec3f {
+    var o = origin;
+    var dir = direction;
+    var throughput = vec3(1.0);
+    var color = vec3(0.0);
+    for (var bounce = 0u; bounce <= params.max_bounces; bounce++) {
+        let hit = trace(o, dir);
+        if hit.t >= NO_HIT {
+            color += throughput * escaped(dir, bounce);
+            break;
+        }
+        let p = o + dir * hit.t + hit.normal * 0.001;
+        // 发光的球: 被路径碰到才算, 没有专门往它采样
+        color += throughput * hit.emission;
+        // 漫反射, 亮度约定和光栅化的 shade 一致: albedo * (直射 + 环境)
+        throughput *= hit.albedo;
+        color += throughput * direct_light(p, hit.normal, rng);
+
+        // 余弦加权的半球采样, 权重正好抵消
+        let u = vec2(next(rng), next(rng));
+        let r = sqrt(u.x);
+        let phi = 2.0 * PI * u.y;
+        o = p;
+        dir = orthonormal(hit.normal) * vec3(r * cos(phi), r * sin(phi), sqrt(1.0 - u.x));
+
+        // 俄罗斯轮盘, 前两次弹射不砍
+        if bounce >= 2u {
+            let survive = clamp(max(throughput.r, max(throughput.g, throughput.b)), 0.05, 1.0);
+            if next(rng) > survive {
+                break;
+            }
+            throughput /= survive;
+        }
+    }
+    return color;
+}
+
+fn view_direction(pixel: vec2f) -> vec3f {
+    let uv = pixel / vec2f(f32(params.width), f32(params.height));
+    let ndc = vec2(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+    let far = camera.inv_view_proj * vec4(ndc, 1.0, 1.0);
+    return normalize(far.xyz / far.w - camera.view_position.xyz);
+}
+
+// 一个线程一个像素, 一次跑完整条路径
+@compute @workgroup_size(8, 8)
+fn trace_paths(@builtin(global_invocation_id) id: vec3u) {
+    if id.x >= params.width || id.y >= params.height {
+        return;
+    }
+    var rng = Rng(id.xy, params.frame * 4096u + params.seed * 0x9e3779b9u);
+    let origin = camera.view_position.xyz;
+    var sum = vec3(0.0);
+    for (var s = 0u; s < params.samples; s++) {
+        let jitter = vec2(next(&rng), next(&rng));
+        sum += radiance(origin, view_direction(vec2f(id.xy) + jitter), &rng);
+    }
+    let first = trace(origin, view_direction(vec2f(id.xy) + 0.5));
+    if first.t < NO_HIT {
+        textureStore(first_albedo, id.xy, vec4(first.albedo, 1.0));
+        textureStore(first_normal_depth, id.xy, vec4(first.normal, first.t));
+    } else {
+        textureStore(first_albedo, id.xy, vec4(1.0));
+        textureStore(first_normal_depth, id.xy, vec4(0.0));
+    }
+    textureStore(output, id.xy, vec4(sum / f32(max(params.samples, 1u)), 1.0));
+}
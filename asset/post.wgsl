@@ -0,0 +1,68 @@
+// 色调映射之后的全屏后处理, 每个效果一个入口, 读上一个 pass 的输出
+struct Params {
+    // 0 到 1
+    strength: f32,
+    // 秒, 给会动的效果
+    time: f32,
+    _pad0: f32,
+    _pad1: f32,
+}
+@group(0) @binding(0) var source: texture_2d<f32>;
+@group(0) @binding(1) var source_sampler: sampler;
+@group(0) @binding(2) var<uniform> params: Params;
+
+struct FullscreenOut {
+    @builtin(position) pos: vec4f,
+    // 左上角是 0
+    @location(0) uv: vec2f,
+}
+
+@vertex
+fn fullscreen_vs(@builtin(vertex_index) vid: u32) -> FullscreenOut {
+    let uv = vec2f(f32((vid << 1u) & 2u), f32(vid & 2u));
+    var out: FullscreenOut;
+    out.pos = vec4f(uv * 2.0 - 1.0, 0.0, 1.0);
+    out.uv = vec2(uv.x, 1.0 - uv.y);
+    return out;
+}
+
+// 四角压暗
+@fragment
+fn vignette_fs(in: FullscreenOut) -> @location(0) vec4f {
+    let color = textureSample(source, source_sampler, in.uv);
+    let d = length(in.uv - 0.5) * 1.41421356;
+    let falloff = 1.0 - params.strength * smoothstep(0.4, 1.0, d);
+    return vec4(color.rgb * falloff, color.a);
+}
+
+// 按 Rec. 709 亮度去色
+@fragment
+fn grayscale_fs(in: FullscreenOut) -> @location(0) vec4f {
+    let color = textureSample(source, source_sampler, in.uv);
+    let luma = dot(color.rgb, vec3(0.2126, 0.7152, 0.0722));
+    return vec4(mix(color.rgb, vec3(luma), params.strength), color.a);
+}
+
+// 红和蓝往屏幕边缘错开, 越靠外越明显
+@fragment
+fn chromatic_aberration_fs(in: FullscreenOut) -> @location(0) vec4f {
+    let offset = (in.uv - 0.5) * params.strength * 0.01;
+    let color = textureSample(source, source_sampler, in.uv);
+    let r = textureSample(source, source_sampler, in.uv + offset).r;
+    let b = textureSample(source, source_sampler, in.uv - offset).b;
+    return vec4(r, color.g, b, color.a);
+}
+
+fn hash(p: vec2f) -> f32 {
+    return fract(sin(dot(p, vec2(12.9898, 78.233))) * 43758.5453);
+}
+
+// 每帧都换的胶片颗粒, 暗部更明显
+@fragment
+fn film_grain_fs(in: FullscreenOut) -> @location(0) vec4f {
+    let color = textureSample(source, source_sampler, in.uv);
+    let noise = hash(in.pos.xy + fract(params.time) * 1000.0) - 0.5;
+    let luma = dot(color.rgb, vec3(0.2126, 0.7152, 0.0722));
+    let grain = noise * params.strength * 0.2 * (1.0 - luma * 0.5);
+    return vec4(max(color.rgb + grain, vec3(0.0)), color.a);
+}
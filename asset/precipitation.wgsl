@@ -0,0 +1,191 @@
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+    inv_view_proj: mat4x4<f32>,
+    view_position: vec4<f32>,
+};
+
+struct PrecipitationParams {
+    camera_position: vec3f,
+    dt: f32,
+    wind: vec3f,
+    // 0..1, fraction of the particles that are alive
+    intensity: f32,
+    box_size: f32,
+    fall_speed: f32,
+    time: f32,
+    streak_length: f32,
+    light: vec3f,
+    aspect: f32,
+    ambient: vec3f,
+    particle_count: u32,
+    flake_size: f32,
+    // 1 = 雪: 圆形雪花, 慢慢飘
+    snow: u32,
+    // 重新生成位置用的随机种子
+    seed: u32,
+}
+
+struct Particle {
+    position: vec3f,
+    seed: f32,
+    velocity: vec3f,
+    _pad: f32,
+}
+
+@group(0) @binding(0) var<uniform> camera: CameraUniform;
+@group(0) @binding(1) var<uniform> params: PrecipitationParams;
+// 计算着色器可写, 顶点着色器只能只读, 所以分成两个绑定
+@group(1) @binding(0) var<storage, read_write> particles: array<Particle>;
+@group(1) @binding(1) var<storage, read> particles_in: array<Particle>;
+
+fn hash1(n: f32) -> f32 {
+    return fract(sin(n * 12.9898) * 43758.5453);
+}
+
+fn active_count() -> u32 {
+    return u32(f32(params.particle_count) * clamp(params.intensity, 0.0, 1.0));
+}
+
+// ---- 计算: 下落, 在相机周围的盒子里循环 ----
+
+@compute @workgroup_size(64)
+fn update(@builtin(global_invocation_id) gid: vec3u) {
+    let index = gid.x;
+    if index >= params.particle_count {
+        return;
+    }
+    var p = particles[index];
+    let half_box = params.box_size * 0.5;
+
+    p.velocity = vec3(0.0, -params.fall_speed, 0.0) + params.wind;
+    if params.snow == 1u {
+        // 雪花左右摇摆, 每片的相位不同
+        let phase = p.seed * 40.0;
+        p.velocity += vec3(sin(params.time * 1.3 + phase), 0.0, cos(params.time * 1.1 + phase * 1.7)) * 0.4;
+    }
+    p.position += p.velocity * params.dt;
+
+    // 横向出了盒子就从另一边绕回来, 盒子跟着相机走
+    let offset = p.position.xz - params.camera_position.xz;
+    p.position = vec3(
+        params.camera_position.x + (fract(offset.x / params.box_size + 0.5) - 0.5) * params.box_size,
+        p.position.y,
+        params.camera_position.z + (fract(offset.y / params.box_size + 0.5) - 0.5) * params.box_size,
+    );
+
+    // 落地或者太低了就在盒子顶上重新生成
+    if p.position.y < 0.0 || p.position.y < params.camera_position.y - half_box {
+        let seed = f32(index) * 0.618 + params.time + f32(params.seed % 1024u) * 17.31;
+        p.seed = hash1(seed);
+        p.position = vec3(
+            params.camera_position.x + (hash1(seed + 1.3) - 0.5) * params.box_size,
+            max(params.camera_position.y, 0.0) + half_box * (0.5 + hash1(seed + 2.7)),
+            params.camera_position.z + (hash1(seed + 4.1) - 0.5) * params.box_size,
+        );
+    }
+    particles[index] = p;
+}
+
+// ---- 渲染: 雨是沿速度方向拉长的面片, 雪是朝向相机的小圆片 ----
+
+struct StreakOut {
+    @builtin(position) pos: vec4f,
+    @location(0) uv: vec2f,
+}
+
+@vertex
+fn streak_vs(@builtin(vertex_index) vid: u32, @builtin(instance_index) iid: u32) -> StreakOut {
+    var out: StreakOut;
+    if iid >= active_count() {
+        out.pos = vec4(0.0, 0.0, 2.0, 1.0);
+        return out;
+    }
+    let p = particles_in[iid];
+    let to_camera = normalize(camera.view_position.xyz - p.position);
+
+    var corners = array<vec2f, 6>(
+        vec2(-1.0, 0.0), vec2(1.0, 0.0), vec2(-1.0, 1.0),
+        vec2(-1.0, 1.0), vec2(1.0, 0.0), vec2(1.0, 1.0),
+    );
+    let corner = corners[vid];
+    var world: vec3f;
+    if params.snow == 1u {
+        let side = normalize(cross(vec3(0.0, 1.0, 0.0), to_camera));
+        let up = cross(to_camera, side);
+        let size = params.flake_size * (0.6 + 0.8 * p.seed);
+        world = p.position + (side * corner.x + up * (corner.y * 2.0 - 1.0)) * size;
+    } else {
+        let axis = normalize(p.velocity);
+        let side = normalize(cross(axis, to_camera));
+        let width = 0.008;
+        world = p.position + side * corner.x * width - axis * corner.y * params.streak_length;
+    }
+    out.pos = camera.view_proj * vec4(world, 1.0);
+    out.uv = corner;
+    return out;
+}
+
+@fragment
+fn streak_fs(in: StreakOut) -> @location(0) vec4f {
+    if params.snow == 1u {
+        // 软边圆片, 雪是白的, 比雨亮得多
+        let r = length(vec2(in.uv.x, in.uv.y * 2.0 - 1.0));
+        let alpha = smoothstep(1.0, 0.4, r) * 0.8;
+        let color = params.ambient * 2.0 + params.light * 0.3;
+        return vec4(color * alpha, alpha);
+    }
+    // 中间亮两边淡, 尾巴渐隐
+    let alpha = (1.0 - abs(in.uv.x)) * (1.0 - in.uv.y) * 0.35;
+    let color = params.ambient * 1.5 + params.light * 0.05;
+    return vec4(color * alpha, alpha);
+}
+
+// ---- 屏幕上的水滴 ----
+
+struct FullscreenOut {
+    @builtin(position) pos: vec4f,
+    @location(0) uv: vec2f,
+}
+
+@vertex
+fn fullscreen_vs(@builtin(vertex_index) vid: u32) -> FullscreenOut {
+    let uv = vec2f(f32((vid << 1u) & 2u), f32(vid & 2u));
+    var out: FullscreenOut;
+    out.pos = vec4f(uv * 2.0 - 1.0, 0.0, 1.0);
+    out.uv = vec2f(uv.x, 1.0 - uv.y);
+    return out;
+}
+
+fn hash2(p: vec2f) -> f32 {
+    return fract(sin(dot(p, vec2(127.1, 311.7))) * 43758.5453);
+}
+
+// x: coverage, y: highlight
+fn droplet_layer(uv: vec2f, scale: f32) -> vec2f {
+    let p = vec2(uv.x * params.aspect, uv.y) * scale;
+    let cell = floor(p);
+    let f = fract(p) - 0.5;
+    let h = hash2(cell);
+    if h < 0.55 {
+        return vec2(0.);
+    }
+    // 每颗水滴出现, 慢慢变干, 再换个位置出现
+    let life = fract(params.time * (0.05 + 0.1 * h) + h * 7.0);
+    let center = (vec2(hash2(cell + 1.0), hash2(cell + 2.0)) - 0.5) * 0.5;
+    let radius = 0.12 + 0.15 * hash2(cell + 3.0);
+    let d = length(f - center);
+    let coverage = smoothstep(radius, radius * 0.75, d) * (1.0 - life);
+    let highlight = smoothstep(radius * 0.4, 0.0, length(f - center - vec2(-0.3, -0.3) * radius)) * (1.0 - life);
+    return vec2(coverage, highlight);
+}
+
+@fragment
+fn droplets_fs(in: FullscreenOut) -> @location(0) vec4f {
+    let a = droplet_layer(in.uv, 6.0);
+    let b = droplet_layer(in.uv + vec2(0.37, 0.11), 11.0);
+    let coverage = max(a.x, b.x) * params.intensity;
+    let highlight = max(a.y, b.y) * params.intensity;
+    let color = params.ambient * 0.3 + (params.light + params.ambient) * highlight * 0.2;
+    let alpha = coverage * 0.3;
+    return vec4(color * alpha, alpha);
+}
@@ -0,0 +1,43 @@
+struct Params {
+    // 每个输出像素对应多少个场景像素, 大于 1 是超采样, 小于 1 是降分辨率
+    scale: vec2f,
+    _pad: vec2f,
+}
+
+@group(0) @binding(0) var scene: texture_2d<f32>;
+@group(0) @binding(1) var<uniform> params: Params;
+
+struct FullscreenOut {
+    @builtin(position) pos: vec4f,
+}
+
+@vertex
+fn fullscreen_vs(@builtin(vertex_index) vid: u32) -> FullscreenOut {
+    let uv = vec2f(f32((vid << 1u) & 2u), f32(vid & 2u));
+    var out: FullscreenOut;
+    out.pos = vec4f(uv * 2.0 - 1.0, 0.0, 1.0);
+    return out;
+}
+
+// 帐篷滤波, 半径是一个输出像素: 比直接平均盒子少锯齿, 又不像 Lanczos 那样在高光边上振铃
+// 放大的时候半径不小于一个场景像素, 就成了双线性
+@fragment
+fn resample_fs(in: FullscreenOut) -> @location(0) vec4f {
+    let size = vec2i(textureDimensions(scene));
+    let radius = max(params.scale, vec2(1.0));
+    let center = in.pos.xy * params.scale;
+    let base = vec2i(floor(center - radius));
+    let taps = vec2i(ceil(radius * 2.0)) + 1;
+    var sum = vec4(0.0);
+    var weight_sum = 0.0;
+    for (var y = 0; y < taps.y; y++) {
+        for (var x = 0; x < taps.x; x++) {
+            let q = base + vec2(x, y);
+            let d = (vec2f(q) + 0.5 - center) / radius;
+            let w = max(1.0 - abs(d.x), 0.0) * max(1.0 - abs(d.y), 0.0);
+            sum += textureLoad(scene, clamp(q, vec2(0), size - 1), 0) * w;
+            weight_sum += w;
+        }
+    }
+    return sum / max(weight_sum, 1e-6);
+}
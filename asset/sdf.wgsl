@@ -0,0 +1,114 @@
+// 有符号距离场 (SDF) 的基本形状和运算, 光线步进着色器 #include 这个文件
+// 形状都在自己的局部空间, 以原点为中心: 先把点移到形状的空间再调用
+// 距离是精确的或者偏小 (下界), 步进不会穿过表面
+
+// ---- 形状 ----
+
+fn sd_sphere(p: vec3f, radius: f32) -> f32 {
+    return length(p) - radius;
+}
+
+fn sd_box(p: vec3f, half_size: vec3f) -> f32 {
+    let q = abs(p) - half_size;
+    return length(max(q, vec3(0.0))) + min(max(q.x, max(q.y, q.z)), 0.0);
+}
+
+// 大小不变, 棱角磨圆
+fn sd_round_box(p: vec3f, half_size: vec3f, radius: f32) -> f32 {
+    return sd_box(p, half_size - vec3(radius)) - radius;
+}
+
+// 躺在 xz 平面上, radii.x 是环的半径, radii.y 是管子的半径
+fn sd_torus(p: vec3f, radii: vec2f) -> f32 {
+    let q = vec2(length(p.xz) - radii.x, p.y);
+    return length(q) - radii.y;
+}
+
+// a 到 b 的线段加上半径
+fn sd_capsule(p: vec3f, a: vec3f, b: vec3f, radius: f32) -> f32 {
+    let pa = p - a;
+    let ba = b - a;
+    let h = clamp(dot(pa, ba) / dot(ba, ba), 0.0, 1.0);
+    return length(pa - ba * h) - radius;
+}
+
+// 沿 y 轴
+fn sd_cylinder(p: vec3f, half_height: f32, radius: f32) -> f32 {
+    let d = abs(vec2(length(p.xz), p.y)) - vec2(radius, half_height);
+    return min(max(d.x, d.y), 0.0) + length(max(d, vec2(0.0)));
+}
+
+// normal 要单位长度, 平面在原点沿法线 -height 的地方
+fn sd_plane(p: vec3f, normal: vec3f, height: f32) -> f32 {
+    return dot(p, normal) + height;
+}
+
+// ---- 组合 ----
+
+fn op_union(a: f32, b: f32) -> f32 {
+    return min(a, b);
+}
+
+// 从 a 里挖掉 b
+fn op_subtract(a: f32, b: f32) -> f32 {
+    return max(a, -b);
+}
+
+fn op_intersect(a: f32, b: f32) -> f32 {
+    return max(a, b);
+}
+
+// k 是接缝圆滑的宽度, 和距离同一个单位
+fn op_smooth_union(a: f32, b: f32, k: f32) -> f32 {
+    let h = clamp(0.5 + 0.5 * (b - a) / k, 0.0, 1.0);
+    return mix(b, a, h) - k * h * (1.0 - h);
+}
+
+fn op_smooth_subtract(a: f32, b: f32, k: f32) -> f32 {
+    let h = clamp(0.5 - 0.5 * (a + b) / k, 0.0, 1.0);
+    return mix(a, -b, h) + k * h * (1.0 - h);
+}
+
+fn op_smooth_intersect(a: f32, b: f32, k: f32) -> f32 {
+    let h = clamp(0.5 - 0.5 * (b - a) / k, 0.0, 1.0);
+    return mix(b, a, h) + k * h * (1.0 - h);
+}
+
+// 带材质的并集: x 是距离, y 是材质编号, 留近的那个
+fn op_union_id(a: vec2f, b: vec2f) -> vec2f {
+    if a.x < b.x {
+        return a;
+    }
+    return b;
+}
+
+// ---- 修饰: 作用在距离上 ----
+
+// 整体胖 radius
+fn op_round(d: f32, radius: f32) -> f32 {
+    return d - radius;
+}
+
+// 变成厚 thickness 的壳
+fn op_onion(d: f32, thickness: f32) -> f32 {
+    return abs(d) - thickness;
+}
+
+// ---- 空间变换: 作用在点上, 结果再交给形状 ----
+
+// 每 spacing 重复一次, 无限多个
+fn op_repeat(p: vec3f, spacing: vec3f) -> vec3f {
+    return p - spacing * round(p / spacing);
+}
+
+// 每个轴上只重复 -count..count 个
+fn op_repeat_limited(p: vec3f, spacing: vec3f, count: vec3f) -> vec3f {
+    return p - spacing * clamp(round(p / spacing), -count, count);
+}
+
+// 绕 y 轴转 angle (弧度), 形状转的方向和它相反
+fn op_rotate_y(p: vec3f, angle: f32) -> vec3f {
+    let c = cos(angle);
+    let s = sin(angle);
+    return vec3(c * p.x + s * p.z, p.y, -s * p.x + c * p.z);
+}
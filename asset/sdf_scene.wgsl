@@ -0,0 +1,159 @@
+// 光线步进的 SDF 场景, 替代显示通道: 每个像素从相机射一条光线, 沿着距离场往前走到表面
+// 形状和运算在 sdf.wgsl 里
+#include "sdf.wgsl"
+#include "camera.wgsl"
+
+// 和 sdf_scene.rs 的 SdfUniform 一样
+struct SdfUniform {
+    // 目标的像素大小
+    resolution: vec2f,
+    time: f32,
+    max_steps: u32,
+    // 朝向太阳, 单位长度
+    sun_direction: vec3f,
+    max_distance: f32,
+    // 已经乘了强度
+    sun_color: vec3f,
+    // 软阴影的 k, 越大越硬
+    shadow_hardness: f32,
+    ambient: vec3f,
+    _pad: f32,
+}
+
+@group(0) @binding(0) var<uniform> camera: CameraUniform;
+@group(0) @binding(1) var<uniform> sdf: SdfUniform;
+
+const MATERIAL_GROUND: f32 = 0.0;
+const MATERIAL_BLOB: f32 = 1.0;
+const MATERIAL_RING: f32 = 2.0;
+const MATERIAL_PILLAR: f32 = 3.0;
+
+// x: 距离, y: 材质
+fn map(p: vec3f) -> vec2f {
+    var scene = vec2(sd_plane(p, vec3(0.0, 1.0, 0.0), 0.0), MATERIAL_GROUND);
+
+    // 圆角盒子上融进一个上下浮动的球, 再挖掉一个小球
+    let center = vec3(0.0, 1.0, -6.0);
+    let bob = sin(sdf.time) * 0.3;
+    var blob = op_smooth_union(
+        sd_round_box(op_rotate_y(p - center, sdf.time * 0.5), vec3(0.7), 0.1),
+        sd_sphere(p - center - vec3(0.0, 0.9 + bob, 0.0), 0.6),
+        0.4,
+    );
+    blob = op_smooth_subtract(blob, sd_sphere(p - center - vec3(0.8, 0.0, 0.8), 0.5), 0.1);
+    scene = op_union_id(scene, vec2(blob, MATERIAL_BLOB));
+
+    let ring = sd_torus(p - vec3(-3.0, 0.35, -6.0), vec2(0.9, 0.3));
+    scene = op_union_id(scene, vec2(ring, MATERIAL_RING));
+
+    // 两排柱子
+    let pillars = op_repeat_limited(p - vec3(0.0, 1.5, -10.0), vec3(3.0, 0.0, 4.0), vec3(3.0, 0.0, 1.0));
+    scene = op_union_id(scene, vec2(sd_cylinder(pillars, 1.5, 0.3), MATERIAL_PILLAR));
+    return scene;
+}
+
+// 四面体取样的梯度, 比六次中心差分少两次
+fn normal_at(p: vec3f) -> vec3f {
+    let e = vec2(1.0, -1.0) * 0.0008;
+    return normalize(
+        e.xyy * map(p + e.xyy).x + e.yyx * map(p + e.yyx).x + e.yxy * map(p + e.yxy).x + e.xxx * map(p + e.xxx).x
+    );
+}
+
+// 沿光线离表面最近的时候有多近, 越近越暗
+fn soft_shadow(origin: vec3f, direction: vec3f) -> f32 {
+    var light = 1.0;
+    var t = 0.02;
+    for (var i = 0; i < 48; i++) {
+        let d = map(origin + direction * t).x;
+        light = min(light, sdf.shadow_hardness * d / t);
+        t += clamp(d, 0.02, 0.5);
+        if light < 0.001 || t > sdf.max_distance {
+            break;
+        }
+    }
+    return clamp(light, 0.0, 1.0);
+}
+
+// 沿法线走几步, 离表面比该有的近就是被遮住了
+fn ambient_occlusion(p: vec3f, n: vec3f) -> f32 {
+    var occlusion = 0.0;
+    var weight = 1.0;
+    for (var i = 1; i <= 5; i++) {
+        let h = 0.03 * f32(i * i);
+        occlusion += (h - map(p + n * h).x) * weight;
+        weight *= 0.7;
+    }
+    return clamp(1.0 - 1.5 * occlusion, 0.0, 1.0);
+}
+
+fn albedo(material: f32, p: vec3f) -> vec3f {
+    if material == MATERIAL_BLOB {
+        return vec3(0.8, 0.25, 0.2);
+    }
+    if material == MATERIAL_RING {
+        return vec3(0.9, 0.7, 0.3);
+    }
+    if material == MATERIAL_PILLAR {
+        return vec3(0.6);
+    }
+    // 棋盘格地面
+    let checker = (i32(floor(p.x)) + i32(floor(p.z))) & 1;
+    return select(vec3(0.25), vec3(0.45), checker == 0);
+}
+
+fn background(direction: vec3f) -> vec3f {
+    let up = clamp(direction.y * 0.5 + 0.5, 0.0, 1.0);
+    let sun = pow(max(dot(direction, sdf.sun_direction), 0.0), 256.0);
+    return mix(sdf.ambient * 0.5, sdf.ambient * 2.0, up) + sdf.sun_color * sun;
+}
+
+struct VertexOut {
+    @builtin(position) pos: vec4f,
+}
+
+@vertex
+fn sdf_vs(@builtin(vertex_index) vid: u32) -> VertexOut {
+    let uv = vec2f(f32((vid << 1u) & 2u), f32(vid & 2u));
+    var out: VertexOut;
+    out.pos = vec4f(uv * 2.0 - 1.0, 0.0, 1.0);
+    return out;
+}
+
+@fragment
+fn sdf_fs(in: VertexOut) -> @location(0) vec4f {
+    let uv = in.pos.xy / sdf.resolution;
+    let ndc = vec2(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+    let far = camera.inv_view_proj * vec4(ndc, 1.0, 1.0);
+    let origin = camera.view_position.xyz;
+    let direction = normalize(far.xyz / far.w - origin);
+
+    var t = 0.0;
+    var hit = vec2(-1.0);
+    for (var i = 0u; i < sdf.max_steps; i++) {
+        let d = map(origin + direction * t);
+        // 越远容许的误差越大, 大约一个像素
+        if d.x < 0.0005 * t {
+            hit = vec2(t, d.y);
+            break;
+        }
+        t += d.x;
+        if t > sdf.max_distance {
+            break;
+        }
+    }
+    if hit.x < 0.0 {
+        return vec4(background(direction), 1.0);
+    }
+
+    let p = origin + direction * hit.x;
+    let n = normal_at(p);
+    let diffuse = max(dot(n, sdf.sun_direction), 0.0);
+    let shadow = soft_shadow(p + n * 0.002, sdf.sun_direction);
+    let occlusion = ambient_occlusion(p, n);
+    var color = albedo(hit.y, p) * (sdf.sun_color * diffuse * shadow + sdf.ambient * occlusion);
+    // 远处雾化到背景
+    let fog = 1.0 - exp(-0.0015 * hit.x * hit.x);
+    color = mix(color, background(direction), fog);
+    return vec4(color, 1.0);
+}
@@ -0,0 +1,51 @@
+// Shadertoy 的外壳, 拼在 toy 着色器前面, toy 只要写 mainImage:
+//
+//     fn mainImage(fragColor: ptr<function, vec4f>, fragCoord: vec2f) {
+//         let uv = fragCoord / iResolution.xy;
+//         *fragColor = vec4(0.5 + 0.5 * cos(iTime + uv.xyx + vec3(0.0, 2.0, 4.0)), 1.0);
+//     }
+//
+// GLSL 的 out 参数在 WGSL 里是指针, 其余的 iResolution / iTime / iMouse 等照常用
+
+// 和 shadertoy.rs 的 ShadertoyUniform 一样
+struct Shadertoy {
+    // 像素, z 是像素的宽高比, 总是 1
+    resolution: vec3f,
+    time: f32,
+    // 像素, 从左下角算, 符号的意思和 Shadertoy 一样
+    mouse: vec4f,
+    time_delta: f32,
+    frame: i32,
+    _pad0: u32,
+    _pad1: u32,
+}
+
+// group 0 是相机, toy 用不到
+@group(1) @binding(0) var<uniform> shadertoy: Shadertoy;
+
+// 每个片元开头从 uniform 抄过来, toy 里就能直接用 Shadertoy 的名字
+var<private> iResolution: vec3f;
+var<private> iTime: f32;
+var<private> iTimeDelta: f32;
+var<private> iFrame: i32;
+var<private> iMouse: vec4f;
+
+@vertex
+fn shadertoy_vs(@builtin(vertex_index) vid: u32) -> @builtin(position) vec4f {
+    // 盖住整个屏幕的三角形
+    let uv = vec2f(f32((vid << 1u) & 2u), f32(vid & 2u));
+    return vec4f(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn shadertoy_fs(@builtin(position) position: vec4f) -> @location(0) vec4f {
+    iResolution = shadertoy.resolution;
+    iTime = shadertoy.time;
+    iTimeDelta = shadertoy.time_delta;
+    iFrame = shadertoy.frame;
+    iMouse = shadertoy.mouse;
+    var color = vec4f(0.0, 0.0, 0.0, 1.0);
+    // Shadertoy 的 y 朝上
+    mainImage(&color, vec2f(position.x, shadertoy.resolution.y - position.y));
+    return color;
+}
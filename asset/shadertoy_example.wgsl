@@ -0,0 +1,6 @@
+// Shadertoy 新建着色器时的那个例子, 换成了 WGSL; 用 --shadertoy asset/shadertoy_example.wgsl 运行
+fn mainImage(fragColor: ptr<function, vec4f>, fragCoord: vec2f) {
+    let uv = fragCoord / iResolution.xy;
+    let col = 0.5 + 0.5 * cos(iTime + uv.xyx + vec3(0.0, 2.0, 4.0));
+    *fragColor = vec4(col, 1.0);
+}
@@ -0,0 +1,22 @@
+// 从平行光看过去的网格深度, 只有顶点着色器, 没有颜色输出
+struct ShadowUniform {
+    view_proj: mat4x4<f32>,
+    bias: f32,
+    normal_offset: f32,
+    texel_size: f32,
+    enabled: f32,
+}
+@group(0) @binding(0) var<uniform> shadow: ShadowUniform;
+
+// 实例的模型矩阵按列, 和 mesh.wgsl 一样
+@vertex
+fn shadow_vs(
+    @location(0) position: vec3f,
+    @location(4) model_0: vec4f,
+    @location(5) model_1: vec4f,
+    @location(6) model_2: vec4f,
+    @location(7) model_3: vec4f,
+) -> @builtin(position) vec4f {
+    let model = mat4x4(model_0, model_1, model_2, model_3);
+    return shadow.view_proj * model * vec4(position, 1.0);
+}
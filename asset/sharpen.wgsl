@@ -0,0 +1,61 @@
+struct Params {
+    // 0 到 1, 越大越锐
+    strength: f32,
+    // 视图是 sRGB 的, 读出来已经是线性光, 换成 gamma 2 再算
+    perceptual: u32,
+    _pad0: f32,
+    _pad1: f32,
+}
+
+@group(0) @binding(0) var source: texture_2d<f32>;
+@group(0) @binding(1) var<uniform> params: Params;
+
+struct FullscreenOut {
+    @builtin(position) pos: vec4f,
+}
+
+@vertex
+fn fullscreen_vs(@builtin(vertex_index) vid: u32) -> FullscreenOut {
+    let uv = vec2f(f32((vid << 1u) & 2u), f32(vid & 2u));
+    var out: FullscreenOut;
+    out.pos = vec4f(uv * 2.0 - 1.0, 0.0, 1.0);
+    return out;
+}
+
+fn load(p: vec2i) -> vec3f {
+    let size = vec2i(textureDimensions(source));
+    let c = textureLoad(source, clamp(p, vec2(0), size - 1), 0).rgb;
+    return select(c, sqrt(max(c, vec3(0.0))), params.perceptual == 1u);
+}
+
+// CAS: 按 3x3 邻域的对比度决定锐化多少, 已经很锐的边少加, 平的地方多加
+// a b c
+// d e f
+// g h i
+@fragment
+fn cas_fs(in: FullscreenOut) -> @location(0) vec4f {
+    let p = vec2i(in.pos.xy);
+    let a = load(p + vec2(-1, -1));
+    let b = load(p + vec2(0, -1));
+    let c = load(p + vec2(1, -1));
+    let d = load(p + vec2(-1, 0));
+    let e = load(p);
+    let f = load(p + vec2(1, 0));
+    let g = load(p + vec2(-1, 1));
+    let h = load(p + vec2(0, 1));
+    let i = load(p + vec2(1, 1));
+
+    // 十字和对角各取一次, 加起来让范围更平滑
+    var mn = min(min(min(d, e), min(f, b)), h);
+    mn += min(mn, min(min(a, c), min(g, i)));
+    var mx = max(max(max(d, e), max(f, b)), h);
+    mx += max(mx, max(max(a, c), max(g, i)));
+
+    // HDR 输出可能超过 1, 上限跟着最亮的走
+    let peak = max(2.0, max(mx.r, max(mx.g, mx.b)));
+    let amp = sqrt(saturate(min(mn, vec3(peak) - mx) / max(mx, vec3(1e-5))));
+    let w = amp * (-1.0 / mix(8.0, 5.0, saturate(params.strength)));
+    let color = (w * (b + d + f + h) + e) / (1.0 + 4.0 * w);
+    let clamped = max(color, vec3(0.0));
+    return vec4(select(clamped, clamped * clamped, params.perceptual == 1u), 1.0);
+}
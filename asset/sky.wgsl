@@ -1,56 +1,730 @@
 struct Uniforms {
     width: u32,
     height: u32,
+    sky_alpha: f32,
+    premultiplied: u32,
+    // 调试视图, 0 是正常画面
+    view_mode: u32,
+    // 深度视图里算作最远的距离 (相机的远平面)
+    depth_range: f32,
+    // 启动以来的秒数, 和距上一帧的秒数, 给要动起来的着色器用
+    time: f32,
+    delta_time: f32,
+    // 和 Shadertoy 的 iMouse 一样, 窗口像素, 从左上角算:
+    // xy 是按住左键时的位置, z 是点下去的 x (松开后为负), w 是点下去的 y (点下那一帧之后为负)
+    mouse: vec4f,
+}
+#include "camera.wgsl"
+struct SkyUniform {
+    sun_direction: vec3f,
+    turbidity: f32,
+    moon_direction: vec3f,
+    intensity: f32,
+    // radians
+    moon_radius: f32,
+    star_brightness: f32,
+    sun_radius: f32,
+    cloud_coverage: f32,
+    // 场景主光 (白天是太阳, 晚上是月亮)
+    light_color: vec3f,
+    light_intensity: f32,
+    light_direction: vec3f,
+    cloud_height: f32,
+    ambient: vec3f,
+    snow_extent: f32,
+    cloud_offset: vec2f,
+    // 0..1, 下雨时地面变湿变光滑
+    ground_wetness: f32,
+    // 水塘: xy 圆心 (世界 xz), z 半径, w 开关
+    water: vec4f,
 }
-struct CameraUniform {
-    view_proj: mat4x4<f32>,
-};
 @group(1) @binding(0) // 1.
 var<uniform> camera: CameraUniform;
 
 @group(0) @binding(0) var<uniform> uniforms : Uniforms;
+@group(0) @binding(1) var<uniform> sky : SkyUniform;
+// 积雪厚度, 世界空间, 以原点为中心
+@group(0) @binding(2) var snow_cover: texture_2d<f32>;
+@group(0) @binding(3) var snow_sampler: sampler;
+// 镜像相机画出来的平面反射, 按屏幕 uv 采样; 1x1 的占位图表示这个 pass 里没有反射可用
+@group(0) @binding(4) var planar_reflection: texture_2d<f32>;
+
+// 漫反射探针网格, 每个探针 9 个 L2 球谐系数; intensity 为 0 表示没烘焙, 用平的 ambient
+struct IrradianceGrid {
+    origin: vec3f,
+    spacing: f32,
+    counts: vec3<u32>,
+    intensity: f32,
+    coefficients: array<vec4f, 576>,
+}
+@group(0) @binding(5) var<uniform> irradiance: IrradianceGrid;
 
-alias TriangleVertices = array<vec2f, 6>;
-var<private> vertices : TriangleVertices = TriangleVertices(
-    vec2f(-1.0, 1.0),
-    vec2f(-1.0, -1.0),
-    vec2f(1.0, 1.0),
-    vec2f(1.0, 1.0),
-    vec2f(-1.0, -1.0),
-    vec2f(1.0, -1.0),
-);
+// 场景里不动的盒子, 和地面一起做光线求交
+struct StaticBox {
+    center: vec4f,
+    half_size: vec4f,
+    albedo: vec4f,
+}
+struct StaticGeometry {
+    count: u32,
+    // 地面 lightmap: xy 中心 (世界 xz), z 边长, w 开关
+    lightmap: vec4f,
+    // 烘焙的 AO: x 地面分辨率, y 每个盒子面的分辨率, z 开关; 地面范围和 lightmap 一样
+    ao: vec4f,
+    boxes: array<StaticBox, 16>,
+    // 每个盒子的每个面在 lightmap 里的 x, y, 宽, 高 (texel), 面上的轴和 AO 一样; 没烘焙的宽高是 0
+    lightmap_charts: array<vec4f, 96>,
+}
+@group(0) @binding(6) var<uniform> static_geometry: StaticGeometry;
+// 离线烘焙的地面光照: rgb 间接光, a 太阳可见度
+// 上面一块正方形是地面, 下面是盒子面的图集
+@group(0) @binding(7) var lightmap: texture_2d<f32>;
+// 上面是地面, 下面每个盒子一行, 每个面一格
+@group(0) @binding(8) var ao_map: texture_2d<f32>;
+
+const PI: f32 = 3.14159265;
 
 struct Ray {
     origin: vec3f,
     direction: vec3f,
 }
 
+struct DisplayOut {
+    @builtin(position) pos: vec4f,
+    @location(0) ndc: vec2f,
+}
+
+// 全屏三角形, 天空跟着相机转
 @vertex
-fn display_vs(@builtin(vertex_index) vid: u32) -> @builtin(position) vec4f {
-    return camera.view_proj * vec4<f32>(vertices[vid], 0.0, 1.0);
+fn display_vs(@builtin(vertex_index) vid: u32) -> DisplayOut {
+    let uv = vec2f(f32((vid << 1u) & 2u), f32(vid & 2u));
+    var out: DisplayOut;
+    out.ndc = uv * 2.0 - 1.0;
+    out.pos = vec4f(out.ndc, 0.0, 1.0);
+    return out;
 }
 
-fn sky_color(ray: Ray) -> vec3f {
-    let t = 0.5 * (normalize(ray.direction).y + 1.0);
-    // 对于白色增长是，正k=1的反比下向偏蓝色的方向渐变
-    return (1. - t) * vec3(1.) + t * vec3(0.3, 0.5, 1.0);
+// 颜色常量是按 sRGB 挑的, 光照计算前先转回线性
+fn srgb_to_linear(c: vec3f) -> vec3f {
+    let lower = c / 12.92;
+    let higher = pow((c + 0.055) / 1.055, vec3(2.4));
+    return select(higher, lower, c < vec3(0.04045));
 }
-@fragment
-fn display_fs(@builtin(position) pos: vec4f) -> @location(0) vec4f {
 
-    let aspect_ratio = f32(uniforms.width) / f32(uniforms.height);
+// ---- Preetham 1999, "A Practical Analytic Model for Daylight" ----
+// channels are (Y, x, y)
+
+struct Perez {
+    a: vec3f,
+    b: vec3f,
+    c: vec3f,
+    d: vec3f,
+    e: vec3f,
+}
+
+fn perez_coefficients(t: f32) -> Perez {
+    var p: Perez;
+    p.a = vec3(0.1787 * t - 1.4630, -0.0193 * t - 0.2592, -0.0167 * t - 0.2608);
+    p.b = vec3(-0.3554 * t + 0.4275, -0.0665 * t + 0.0008, -0.0950 * t + 0.0092);
+    p.c = vec3(-0.0227 * t + 5.3251, -0.0004 * t + 0.2125, -0.0079 * t + 0.2102);
+    p.d = vec3(0.1206 * t - 2.5771, -0.0641 * t - 0.8989, -0.0441 * t - 1.6537);
+    p.e = vec3(-0.0670 * t + 0.3703, -0.0033 * t + 0.0452, -0.0109 * t + 0.0529);
+    return p;
+}
+
+fn perez(p: Perez, cos_theta: f32, gamma: f32, cos_gamma: f32) -> vec3f {
+    return (1.0 + p.a * exp(p.b / cos_theta)) * (1.0 + p.c * exp(p.d * gamma) + p.e * cos_gamma * cos_gamma);
+}
+
+fn zenith_yxy(t: f32, theta_s: f32) -> vec3f {
+    let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+    let big_y = (4.0453 * t - 4.9710) * tan(chi) - 0.2155 * t + 2.4192;
+    let th = vec4(theta_s * theta_s * theta_s, theta_s * theta_s, theta_s, 1.0);
+    let x = t * t * dot(vec4(0.00166, -0.00375, 0.00209, 0.0), th)
+        + t * dot(vec4(-0.02903, 0.06377, -0.03202, 0.00394), th)
+        + dot(vec4(0.11693, -0.21196, 0.06052, 0.25886), th);
+    let y = t * t * dot(vec4(0.00275, -0.00610, 0.00317, 0.0), th)
+        + t * dot(vec4(-0.04214, 0.08970, -0.04153, 0.00516), th)
+        + dot(vec4(0.15346, -0.26756, 0.06670, 0.26688), th);
+    return vec3(big_y, x, y);
+}
+
+fn yxy_to_linear_srgb(c: vec3f) -> vec3f {
+    let xyz = vec3(c.y / c.z * c.x, c.x, (1.0 - c.y - c.z) / c.z * c.x);
+    return vec3(
+        dot(vec3(3.2406, -1.5372, -0.4986), xyz),
+        dot(vec3(-0.9689, 1.8758, 0.0415), xyz),
+        dot(vec3(0.0557, -0.2040, 1.0570), xyz),
+    );
+}
+
+fn sun_direction() -> vec3f {
+    return normalize(sky.sun_direction);
+}
+
+fn sky_radiance(dir: vec3f) -> vec3f {
+    let sun = sun_direction();
+    // 模型只对地平线以上的太阳有效, 太阳落下后整体淡出
+    let theta_s = acos(clamp(sun.y, 0.01, 1.0));
+    let cos_theta = max(dir.y, 0.001);
+    let cos_gamma = clamp(dot(dir, sun), -1.0, 1.0);
+    let gamma = acos(cos_gamma);
+
+    let p = perez_coefficients(sky.turbidity);
+    let zenith = zenith_yxy(sky.turbidity, theta_s);
+    var yxy = zenith * perez(p, cos_theta, gamma, cos_gamma) / perez(p, 1.0, theta_s, cos(theta_s));
+    yxy.x = yxy.x * sky.intensity * smoothstep(-0.12, 0.02, sun.y);
+    return max(yxy_to_linear_srgb(yxy), vec3(0.));
+}
+
+// ---- 太阳圆盘 ----
+
+fn sun_disk(dir: vec3f) -> vec3f {
+    let sun = sun_direction();
+    let angle = acos(clamp(dot(dir, sun), -1.0, 1.0));
+    if angle > sky.sun_radius {
+        return vec3(0.);
+    }
+    // 临边昏暗: 线性定律, 边缘 mu -> 0
+    let r = angle / sky.sun_radius;
+    let mu = sqrt(1.0 - r * r);
+    let limb = 1.0 - 0.6 * (1.0 - mu);
+    // 太阳越低穿过的大气越厚, 越红
+    let air_mass = min(1.0 / max(sun.y + 0.03, 0.001), 40.0);
+    let transmittance = exp(-vec3(0.02, 0.05, 0.12) * sky.turbidity * air_mass * 0.3);
+    let edge = smoothstep(1.0, 0.95, r);
+    return vec3(1.0, 0.96, 0.9) * limb * transmittance * edge * sky.intensity * 2000.0;
+}
+
+// ---- 夜空: 星星和月亮 ----
+
+fn hash3(p: vec3f) -> f32 {
+    return fract(sin(dot(p, vec3(127.1, 311.7, 74.7))) * 43758.5453);
+}
+
+// 0 while the sun is up, 1 once it is well below the horizon
+fn night_factor() -> f32 {
+    return 1.0 - smoothstep(-0.15, 0.02, sun_direction().y);
+}
+
+fn stars(dir: vec3f) -> vec3f {
+    // 把方向放大到一个大球上, 每个格子最多一颗星
+    let p = dir * 300.0;
+    let cell = floor(p);
+    if hash3(cell) < 0.992 {
+        return vec3(0.);
+    }
+    let jitter = vec3(hash3(cell + 1.7), hash3(cell + 3.1), hash3(cell + 5.3)) - 0.5;
+    let d = length(p - (cell + 0.5 + jitter * 0.6));
+    let brightness = pow(hash3(cell + 9.2), 6.0) * 3.0 + 0.1;
+    let tint = mix(vec3(0.7, 0.8, 1.0), vec3(1.0, 0.85, 0.7), hash3(cell + 2.3));
+    // 微微闪烁留给以后有时间 uniform 的时候
+    return tint * brightness * smoothstep(0.3, 0.0, d) * 0.05 * sky.star_brightness;
+}
+
+fn moon(dir: vec3f) -> vec3f {
+    let moon_dir = normalize(sky.moon_direction);
+    let cos_angle = dot(dir, moon_dir);
+    let angle = acos(clamp(cos_angle, -1.0, 1.0));
+    let glow = vec3(0.6, 0.7, 1.0) * 0.002 * exp(-angle * 8.0) * step(0.0, moon_dir.y);
+    if angle > sky.moon_radius {
+        return glow;
+    }
+    // 在月亮的切平面上重建球面法线, 用太阳方向算月相
+    let up = select(vec3(0., 1., 0.), vec3(1., 0., 0.), abs(moon_dir.y) > 0.99);
+    let tangent = normalize(cross(up, moon_dir));
+    let bitangent = cross(moon_dir, tangent);
+    let local = vec2(dot(dir, tangent), dot(dir, bitangent)) / sin(sky.moon_radius);
+    let z = sqrt(max(1.0 - dot(local, local), 0.0));
+    let normal = local.x * tangent + local.y * bitangent + z * -moon_dir;
+    let lit = max(dot(normal, sun_direction()), 0.0);
+    let maria = 0.75 + 0.25 * hash3(floor(vec3(local * 6.0, 0.0)));
+    let edge = smoothstep(1.0, 0.97, length(local));
+    return (vec3(0.9, 0.9, 0.85) * 0.08 * lit * maria + vec3(0.002)) * edge + glow;
+}
+
+fn night_sky(dir: vec3f) -> vec3f {
+    let horizon_fade = smoothstep(0.0, 0.15, dir.y);
+    return vec3(0.0004, 0.0006, 0.0015) + stars(dir) * horizon_fade + moon(dir);
+}
+
+// ---- 云层: 固定高度上的一层 fbm 噪声 ----
+
+fn hash2(p: vec2f) -> f32 {
+    return fract(sin(dot(p, vec2(127.1, 311.7))) * 43758.5453);
+}
+
+fn value_noise(p: vec2f) -> f32 {
+    let i = floor(p);
+    let f = fract(p);
+    let u = f * f * (3.0 - 2.0 * f);
+    return mix(
+        mix(hash2(i), hash2(i + vec2(1., 0.)), u.x),
+        mix(hash2(i + vec2(0., 1.)), hash2(i + vec2(1., 1.)), u.x),
+        u.y,
+    );
+}
+
+fn fbm(p: vec2f) -> f32 {
+    var value = 0.0;
+    var amplitude = 0.5;
+    var q = p;
+    for (var i = 0; i < 5; i++) {
+        value += amplitude * value_noise(q);
+        q = q * 2.03 + vec2(17.1, 9.2);
+        amplitude *= 0.5;
+    }
+    return value;
+}
+
+// cloud density at a point of the cloud layer, 0..1
+fn cloud_density(xz: vec2f) -> f32 {
+    let n = fbm((xz - sky.cloud_offset) * 0.0012);
+    let threshold = 1.0 - sky.cloud_coverage;
+    return smoothstep(threshold - 0.1, threshold + 0.25, n);
+}
+
+// how much light reaches `world_pos` through the cloud layer, 1 = unshadowed
+fn cloud_shadow(world_pos: vec3f) -> f32 {
+    let to_light = normalize(sky.light_direction);
+    if to_light.y <= 0.01 || world_pos.y >= sky.cloud_height {
+        return 1.0;
+    }
+    let p = world_pos + to_light * ((sky.cloud_height - world_pos.y) / to_light.y);
+    return 1.0 - 0.85 * cloud_density(p.xz);
+}
+
+fn clouds(ray: Ray, dir: vec3f, background: vec3f) -> vec3f {
+    if dir.y <= 0.0 {
+        return background;
+    }
+    let t = (sky.cloud_height - ray.origin.y) / dir.y;
+    let p = ray.origin + dir * t;
+    let density = cloud_density(p.xz);
+    let light = sky.light_color * sky.light_intensity;
+    // 云越厚底部越暗
+    let cloud = light * 0.25 * (1.0 - 0.6 * density) + sky.ambient * 0.8;
+    // 远处的云淡进地平线
+    let fade = exp(-t * 0.00003);
+    return mix(background, cloud, density * fade);
+}
+
+// ---- 地面: y = 0 的平面, 受主光和云影照亮 ----
+
+// 朝上的面才积雪, 贴图范围外没有雪
+fn snow_amount(p: vec3f, normal: vec3f) -> f32 {
+    let uv = p.xz / sky.snow_extent + 0.5;
+    if any(uv < vec2(0.0)) || any(uv > vec2(1.0)) {
+        return 0.0;
+    }
+    let depth = textureSampleLevel(snow_cover, snow_sampler, uv, 0.0).r;
+    return smoothstep(0.0, 0.6, depth) * smoothstep(0.5, 0.9, normal.y);
+}
+
+// 一个探针在法线方向上的辐照度 / PI, 和 sky.ambient 同一个量纲
+fn probe_irradiance(probe: u32, n: vec3f) -> vec3f {
+    let base = probe * 9u;
+    let c = &irradiance.coefficients;
+    // 系数已经乘上了余弦卷积 (Ramamoorthi & Hanrahan 2001)
+    var e = (*c)[base].rgb * 0.886227;
+    e += ((*c)[base + 1u].rgb * n.y + (*c)[base + 2u].rgb * n.z + (*c)[base + 3u].rgb * n.x) * 1.023328;
+    e += ((*c)[base + 4u].rgb * n.x * n.y + (*c)[base + 5u].rgb * n.y * n.z + (*c)[base + 7u].rgb * n.x * n.z) * 0.858086;
+    e += (*c)[base + 6u].rgb * 0.247708 * (3.0 * n.z * n.z - 1.0);
+    e += (*c)[base + 8u].rgb * 0.429043 * (n.x * n.x - n.y * n.y);
+    return max(e, vec3(0.0)) / PI;
+}
+
+// 网格里三线性插值, 网格外贴着边上的探针
+fn ambient(p: vec3f, n: vec3f) -> vec3f {
+    if irradiance.intensity <= 0.0 {
+        return sky.ambient;
+    }
+    let counts = irradiance.counts;
+    let last = vec3f(counts - 1u);
+    let g = clamp((p - irradiance.origin) / irradiance.spacing, vec3(0.0), last);
+    let base = vec3<u32>(min(floor(g), max(last - 1.0, vec3(0.0))));
+    let f = g - vec3f(base);
+    var sum = vec3(0.0);
+    for (var corner = 0u; corner < 8u; corner++) {
+        let offset = vec3((corner & 1u), (corner >> 1u) & 1u, (corner >> 2u) & 1u);
+        let cell = min(base + offset, counts - 1u);
+        let w = select(1.0 - f, f, offset == vec3(1u));
+        let probe = cell.x + counts.x * (cell.y + counts.y * cell.z);
+        sum += probe_irradiance(probe, n) * w.x * w.y * w.z;
+    }
+    return sum * irradiance.intensity;
+}
+
+// ---- 静态盒子 ----
+
+struct BoxHit {
+    // < 0 = 没打中
+    t: f32,
+    normal: vec3f,
+    index: u32,
+}
 
-    //-1 的作用仅仅是为了达到的是 1920 下的最后一个点是 1919 才对的位置
-    var uv = pos.xy / vec2f(f32(uniforms.width - 1u), f32(uniforms.height - 1u));
+// slab 求交, 取最近的盒子
+fn trace_boxes(origin: vec3f, dir: vec3f) -> BoxHit {
+    var hit = BoxHit(-1.0, vec3(0.0), 0u);
+    let inv = 1.0 / dir;
+    for (var i = 0u; i < static_geometry.count; i++) {
+        let b = static_geometry.boxes[i];
+        let t0 = (b.center.xyz - b.half_size.xyz - origin) * inv;
+        let t1 = (b.center.xyz + b.half_size.xyz - origin) * inv;
+        let near = min(t0, t1);
+        let far = max(t0, t1);
+        let t_near = max(max(near.x, near.y), near.z);
+        let t_far = min(min(far.x, far.y), far.z);
+        if t_near > 0.0 && t_near <= t_far && (hit.t < 0.0 || t_near < hit.t) {
+            hit.t = t_near;
+            // 进入的那个面: near 里最大的轴
+            hit.normal = -sign(dir) * step(vec3(t_near), near);
+            hit.index = i;
+        }
+    }
+    return hit;
+}
 
-    //uv 为比率下的宽高占比了调整坐标位置再翻转y
-    uv = (2. * uv - vec2(1.)) * vec2(aspect_ratio, -1.);
+fn box_shadow(p: vec3f) -> f32 {
+    return select(1.0, 0.0, trace_boxes(p, normalize(sky.light_direction)).t > 0.0);
+}
 
-    let origin = vec3(0.);
-    let focus_distance = 1.;
+// 面的顺序 +X -X +Y -Y +Z -Z, 每个面上 uv 用哪两个轴
+fn face_axes(face: u32) -> vec2u {
+    switch face / 2u {
+        case 0u: { return vec2(2u, 1u); }
+        case 1u: { return vec2(0u, 2u); }
+        default: { return vec2(0u, 1u); }
+    }
+}
 
-    let direction = vec3(uv, -focus_distance);
-    let ray = Ray(origin, direction);
+fn face_index(n: vec3f) -> u32 {
+    if abs(n.x) > 0.5 {
+        return select(1u, 0u, n.x > 0.0);
+    }
+    if abs(n.y) > 0.5 {
+        return select(3u, 2u, n.y > 0.0);
+    }
+    return select(5u, 4u, n.z > 0.0);
+}
+
+// 在 AO 图里按像素坐标取
+fn sample_ao(pixel: vec2f) -> f32 {
+    return textureSampleLevel(ao_map, snow_sampler, pixel / vec2f(textureDimensions(ao_map)), 0.0).r;
+}
+
+fn ground_ao(p: vec3f) -> f32 {
+    if static_geometry.ao.z < 0.5 {
+        return 1.0;
+    }
+    let info = static_geometry.lightmap;
+    let uv = (p.xz - info.xy) / info.z + 0.5;
+    if any(uv < vec2(0.0)) || any(uv > vec2(1.0)) {
+        return 1.0;
+    }
+    let resolution = static_geometry.ao.x;
+    return sample_ao(clamp(uv * resolution, vec2(0.5), vec2(resolution - 0.5)));
+}
+
+fn box_ao(index: u32, p: vec3f, n: vec3f) -> f32 {
+    if static_geometry.ao.z < 0.5 {
+        return 1.0;
+    }
+    let b = static_geometry.boxes[index];
+    let face = face_index(n);
+    let axes = face_axes(face);
+    let local = p - b.center.xyz;
+    let half_size = b.half_size.xyz;
+    let uv = vec2(local[axes.x] / half_size[axes.x], local[axes.y] / half_size[axes.y]) * 0.5 + 0.5;
+    let tile = static_geometry.ao.y;
+    // 别采到隔壁的格子
+    let in_tile = clamp(uv * tile, vec2(0.5), vec2(tile - 0.5));
+    return sample_ao(vec2(f32(face), static_geometry.ao.x / tile + f32(index)) * tile + in_tile);
+}
+
+// 地面上烘焙过的位置返回 (间接光, 太阳可见度), 没有就是 w < 0
+fn baked_lighting(p: vec3f) -> vec4f {
+    let info = static_geometry.lightmap;
+    if info.w < 0.5 {
+        return vec4(-1.0);
+    }
+    let uv = (p.xz - info.xy) / info.z + 0.5;
+    if any(uv < vec2(0.0)) || any(uv > vec2(1.0)) {
+        return vec4(-1.0);
+    }
+    // 地面那块是正方形, 别采到下面的图集
+    let dims = vec2f(textureDimensions(lightmap));
+    let texel = clamp(uv * dims.x, vec2(0.5), vec2(dims.x - 0.5));
+    return textureSampleLevel(lightmap, snow_sampler, texel / dims, 0.0);
+}
+
+// 盒子面上烘焙过的 (间接光, 太阳可见度), 没有就是 w < 0
+fn box_baked_lighting(index: u32, p: vec3f, n: vec3f) -> vec4f {
+    let face = face_index(n);
+    let chart = static_geometry.lightmap_charts[index * 6u + face];
+    if static_geometry.lightmap.w < 0.5 || chart.w <= 0.0 {
+        return vec4(-1.0);
+    }
+    let b = static_geometry.boxes[index];
+    let axes = face_axes(face);
+    let local = p - b.center.xyz;
+    let half_size = b.half_size.xyz;
+    let uv = vec2(local[axes.x] / half_size[axes.x], local[axes.y] / half_size[axes.y]) * 0.5 + 0.5;
+    let texel = chart.xy + saturate(uv) * chart.zw;
+    return textureSampleLevel(lightmap, snow_sampler, texel / vec2f(textureDimensions(lightmap)), 0.0);
+}
+
+// 场景里一个像素的结果: 颜色之外, 后面的屏幕空间 pass 还要法线/光滑度/深度
+struct Surface {
+    color: vec3f,
+    normal: vec3f,
+    smoothness: f32,
+    depth: f32,
+    // 下面这些只给调试视图用
+    albedo: vec3f,
+    // 到相机的距离, 天空是 -1
+    distance: f32,
+    // 太阳能照到的比例, 盒子的阴影乘云的阴影
+    shadow: f32,
+    // 这个点的光照用到了几样东西: 太阳, 探针/烘焙的环境光, AO, 反射
+    lights: f32,
+    // 像素检查器用的物体编号: 0 天空, 1 地面, 2 + i 第 i 个盒子
+    id: u32,
+}
+
+fn water_mask(p: vec3f) -> f32 {
+    if sky.water.w < 0.5 {
+        return 0.0;
+    }
+    let d = distance(p.xz, sky.water.xy);
+    return smoothstep(sky.water.z, sky.water.z - 0.5, d);
+}
+
+fn water_reflection(screen_uv: vec2f, reflected: vec3f) -> vec3f {
+    if textureDimensions(planar_reflection).x <= 1u {
+        return sky_radiance(reflected) + night_sky(reflected) * night_factor();
+    }
+    return textureSampleLevel(planar_reflection, snow_sampler, screen_uv, 0.0).rgb;
+}
+
+fn fog_visibility(t: f32) -> f32 {
+    return exp(-t * 0.002);
+}
+
+// 空气透视: 越远越接近地平线的天空颜色
+fn aerial_perspective(color: vec3f, dir: vec3f, t: f32) -> vec3f {
+    let horizon = vec3(dir.x, 0.0, dir.z);
+    let haze = sky_radiance(horizon) + night_sky(horizon) * night_factor();
+    return mix(color, haze, 1.0 - fog_visibility(t));
+}
 
-    return vec4(sky_color(ray), 1.);
+fn ndc_depth(p: vec3f) -> f32 {
+    let clip = camera.view_proj * vec4(p, 1.0);
+    return clamp(clip.z / clip.w, 0.0, 0.999999);
+}
+
+fn ground(ray: Ray, dir: vec3f, screen_uv: vec2f) -> Surface {
+    let t = -ray.origin.y / dir.y;
+    let p = ray.origin + dir * t;
+    let normal = vec3(0.0, 1.0, 0.0);
+    let variation = 0.85 + 0.3 * value_noise(p.xz * 0.5);
+    let soil = srgb_to_linear(vec3(0.35, 0.3, 0.25)) * variation;
+    let snow = snow_amount(p, normal);
+    // 湿地面颜色变深, 低洼处积水最光滑; 雪盖住的地方不反光
+    let puddles = smoothstep(0.45, 0.7, value_noise(p.xz * 0.15));
+    let wet = sky.ground_wetness * (1.0 - snow);
+    let albedo = mix(soil * mix(1.0, 0.55, wet), vec3(0.85, 0.88, 0.92), snow);
+    // lightmap 里有的就用烘焙的阴影和间接光, 否则实时算
+    let baked = baked_lighting(p);
+    var ambient_light = ambient(p, normal) * ground_ao(p);
+    var visibility = box_shadow(p + normal * 0.001);
+    if baked.w >= 0.0 {
+        ambient_light = baked.rgb;
+        visibility = baked.w;
+    }
+    let n_dot_l = max(normalize(sky.light_direction).y, 0.0);
+    let direct = sky.light_color * sky.light_intensity * n_dot_l * cloud_shadow(p) * visibility;
+    var lit = albedo * (direct + ambient_light);
+
+    // 水面: 小波纹扰动法线, 反射和水体颜色按菲涅尔混合
+    let water = water_mask(p);
+    if water > 0.0 {
+        let q = p.xz * 2.0 + sky.cloud_offset * 0.02;
+        let ripple = vec2(value_noise(q), value_noise(q + 7.3)) - 0.5;
+        let n = normalize(vec3(ripple.x * 0.15, 1.0, ripple.y * 0.15));
+        let reflection = water_reflection(screen_uv + ripple * 0.01, reflect(dir, n));
+        let fresnel = 0.02 + 0.98 * pow(1.0 - clamp(dot(-dir, n), 0.0, 1.0), 5.0);
+        let body = srgb_to_linear(vec3(0.05, 0.12, 0.15)) * (direct * 0.3 + ambient_light);
+        lit = mix(lit, mix(body, reflection, fresnel), water);
+    }
+    let fog = fog_visibility(t);
+
+    var out: Surface;
+    out.color = aerial_perspective(lit, dir, t);
+    out.albedo = albedo;
+    out.distance = t;
+    out.shadow = cloud_shadow(p) * visibility;
+    out.lights = f32(n_dot_l * out.shadow > 0.0) + f32(baked.w >= 0.0 || irradiance.intensity > 0.0)
+        + f32(static_geometry.ao.z >= 0.5) + f32(water > 0.0);
+    out.normal = normal;
+    // 远处被雾盖住的反射也跟着淡掉
+    // 水面已经有平面反射了, 不再让 SSR 叠一次
+    out.smoothness = wet * mix(0.6, 0.95, puddles) * fog * (1.0 - water);
+    out.depth = ndc_depth(p);
+    out.id = 1u;
+    return out;
+}
+
+fn box_surface(ray: Ray, dir: vec3f, hit: BoxHit) -> Surface {
+    let p = ray.origin + dir * hit.t;
+    let albedo = static_geometry.boxes[hit.index].albedo.rgb;
+    let l = normalize(sky.light_direction);
+    let n_dot_l = max(dot(hit.normal, l), 0.0);
+    // lightmap 里有的就用烘焙的阴影和间接光, 否则实时算
+    let baked = box_baked_lighting(hit.index, p, hit.normal);
+    var visibility = box_shadow(p + hit.normal * 0.001);
+    var ambient_light = ambient(p, hit.normal) * box_ao(hit.index, p, hit.normal);
+    if baked.w >= 0.0 {
+        ambient_light = baked.rgb;
+        visibility = baked.w;
+    }
+    let shadow = visibility * cloud_shadow(p);
+    let direct = sky.light_color * sky.light_intensity * n_dot_l * shadow;
+
+    var out: Surface;
+    out.color = aerial_perspective(albedo * (direct + ambient_light), dir, hit.t);
+    out.albedo = albedo;
+    out.distance = hit.t;
+    out.shadow = shadow;
+    out.lights = f32(n_dot_l * shadow > 0.0) + f32(baked.w >= 0.0 || irradiance.intensity > 0.0)
+        + f32(static_geometry.ao.z >= 0.5);
+    out.normal = hit.normal;
+    out.smoothness = 0.0;
+    out.depth = ndc_depth(p);
+    out.id = 2u + hit.index;
+    return out;
+}
+
+fn shade(ray: Ray, screen_uv: vec2f) -> Surface {
+    let dir = normalize(ray.direction);
+    let hit = trace_boxes(ray.origin, dir);
+    let ground_hit = dir.y < 0.0 && ray.origin.y > 0.0;
+    if hit.t > 0.0 && (!ground_hit || hit.t < -ray.origin.y / dir.y) {
+        return box_surface(ray, dir, hit);
+    }
+    if ground_hit {
+        return ground(ray, dir, screen_uv);
+    }
+    let background = sky_radiance(dir) + sun_disk(dir) + night_sky(dir) * night_factor();
+    var out: Surface;
+    out.color = clouds(ray, dir, background);
+    out.depth = 1.0;
+    out.distance = -1.0;
+    return out;
+}
+
+// 视线穿过了几个表面: 这里整个场景是一个全屏三角形, 没有真正的 overdraw, 光线求交时碰到的表面数就是对应的开销
+fn surface_count(ray: Ray) -> f32 {
+    let dir = normalize(ray.direction);
+    let inv = 1.0 / dir;
+    var count = f32(dir.y < 0.0 && ray.origin.y > 0.0);
+    for (var i = 0u; i < static_geometry.count; i++) {
+        let b = static_geometry.boxes[i];
+        let t0 = (b.center.xyz - b.half_size.xyz - ray.origin) * inv;
+        let t1 = (b.center.xyz + b.half_size.xyz - ray.origin) * inv;
+        let t_near = max(max(min(t0.x, t1.x), min(t0.y, t1.y)), min(t0.z, t1.z));
+        let t_far = min(min(max(t0.x, t1.x), max(t0.y, t1.y)), max(t0.z, t1.z));
+        count += f32(t_near > 0.0 && t_near <= t_far);
+    }
+    return count;
+}
+
+// 蓝 -> 绿 -> 红
+fn heat(x: f32) -> vec3f {
+    let v = saturate(x) * 2.0 - 1.0;
+    return saturate(vec3(v, 1.0 - abs(v), -v));
+}
+
+// 调试视图的颜色是直接要显示的值, 转成线性的, tonemap 直通时编码回去就是原值
+fn debug_color(surface: Surface, ray: Ray) -> vec3f {
+    let sky_pixel = surface.distance < 0.0;
+    var value = vec3(0.0);
+    switch uniforms.view_mode {
+        case 1u: {
+            value = select(surface.albedo, vec3(0.0), sky_pixel);
+        }
+        case 2u: {
+            value = select(surface.normal * 0.5 + 0.5, vec3(0.0), sky_pixel);
+        }
+        case 3u: {
+            value = vec3(select(saturate(surface.distance / uniforms.depth_range), 1.0, sky_pixel));
+        }
+        case 4u: {
+            // 光线投射的表面只有光滑度, 没有金属度, 绿色通道留空
+            value = select(vec3(1.0 - surface.smoothness, 0.0, 0.0), vec3(0.0), sky_pixel);
+        }
+        case 5u: {
+            value = heat(surface_count(ray) / 4.0);
+        }
+        case 6u: {
+            // 光线投射的阴影是逐像素求交的, 不是网格的阴影贴图, 直接看太阳的可见度
+            value = select(mix(vec3(0.1, 0.1, 0.4), vec3(1.0, 0.9, 0.6), surface.shadow), vec3(0.0), sky_pixel);
+        }
+        case 7u: {
+            value = select(heat(surface.lights / 4.0), vec3(0.0), sky_pixel);
+        }
+        default: {
+            return surface.color;
+        }
+    }
+    return srgb_to_linear(value);
+}
+
+fn view_ray(ndc: vec2f) -> Ray {
+    let far = camera.inv_view_proj * vec4(ndc, 1.0, 1.0);
+    let origin = camera.view_position.xyz;
+    return Ray(origin, far.xyz / far.w - origin);
+}
+
+fn ndc_to_uv(ndc: vec2f) -> vec2f {
+    return vec2(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+}
+
+struct SceneOut {
+    @location(0) color: vec4f,
+    // 世界空间法线 + 光滑度, 天空是 0
+    @location(1) surface: vec4f,
+    @location(2) depth: f32,
+    @location(3) id: u32,
+    // 同一个深度写进深度缓冲, 之后光栅化的几何体才能和光线投射的场景互相遮挡
+    @builtin(frag_depth) frag_depth: f32,
+}
+
+@fragment
+fn display_fs(in: DisplayOut) -> SceneOut {
+    let ray = view_ray(in.ndc);
+    var surface = shade(ray, ndc_to_uv(in.ndc));
+    if uniforms.view_mode != 0u {
+        surface.color = debug_color(surface, ray);
+    }
+
+    var out: SceneOut;
+    let alpha = uniforms.sky_alpha;
+    if uniforms.premultiplied == 1u {
+        out.color = vec4(surface.color * alpha, alpha);
+    } else {
+        out.color = vec4(surface.color, alpha);
+    }
+    out.surface = vec4(surface.normal, surface.smoothness);
+    out.depth = surface.depth;
+    out.frag_depth = surface.depth;
+    out.id = surface.id;
+    return out;
+}
+
+// 只要颜色, 用来画环境 cubemap
+@fragment
+fn env_fs(in: DisplayOut) -> @location(0) vec4f {
+    return vec4(shade(view_ray(in.ndc), ndc_to_uv(in.ndc)).color, 1.0);
 }
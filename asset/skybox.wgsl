@@ -0,0 +1,50 @@
+// 用六张图的 cubemap 代替程序化的天空, 全屏三角形画在远平面上
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+    inv_view_proj: mat4x4<f32>,
+    view_position: vec4<f32>,
+};
+@group(1) @binding(0) var<uniform> camera: CameraUniform;
+
+struct SkyboxUniform {
+    intensity: f32,
+}
+@group(2) @binding(0) var<uniform> skybox: SkyboxUniform;
+@group(2) @binding(1) var skybox_texture: texture_cube<f32>;
+@group(2) @binding(2) var skybox_sampler: sampler;
+
+struct VertexOut {
+    @builtin(position) pos: vec4f,
+    @location(0) ndc: vec2f,
+}
+
+@vertex
+fn skybox_vs(@builtin(vertex_index) vid: u32) -> VertexOut {
+    let ndc = vec2(f32(vid & 1u) * 4.0 - 1.0, f32(vid >> 1u) * 4.0 - 1.0);
+    var out: VertexOut;
+    // z = 1 是远平面, 深度测试 LessEqual 只留下光线投射没打中东西的像素
+    out.pos = vec4(ndc, 1.0, 1.0);
+    out.ndc = ndc;
+    return out;
+}
+
+// 天空只写 HDR 颜色, G-buffer 留着光线投射写的
+struct SkyboxOut {
+    @location(0) color: vec4f,
+    @location(1) surface: vec4f,
+    @location(2) depth: f32,
+    @location(3) id: u32,
+}
+
+@fragment
+fn skybox_fs(in: VertexOut) -> SkyboxOut {
+    let near = camera.inv_view_proj * vec4(in.ndc, 0.0, 1.0);
+    let far = camera.inv_view_proj * vec4(in.ndc, 1.0, 1.0);
+    let dir = normalize(far.xyz / far.w - near.xyz / near.w);
+    var out: SkyboxOut;
+    out.color = vec4(textureSample(skybox_texture, skybox_sampler, dir).rgb * skybox.intensity, 1.0);
+    out.surface = vec4(0.0);
+    out.depth = 1.0;
+    out.id = 0u;
+    return out;
+}
@@ -0,0 +1,46 @@
+struct SnowCoverParams {
+    // 世界空间 xz, 贴图覆盖 center ± extent/2
+    center: vec2f,
+    extent: f32,
+    dt: f32,
+    // 每秒积雪增加量, 不下雪的时候是负的 (融化)
+    rate: f32,
+    time: f32,
+    _pad: vec2f,
+}
+
+@group(0) @binding(0) var<uniform> params: SnowCoverParams;
+@group(0) @binding(1) var previous: texture_2d<f32>;
+@group(0) @binding(2) var next: texture_storage_2d<rgba16float, write>;
+
+fn hash2(p: vec2f) -> f32 {
+    return fract(sin(dot(p, vec2(127.1, 311.7))) * 43758.5453);
+}
+
+fn value_noise(p: vec2f) -> f32 {
+    let i = floor(p);
+    let f = fract(p);
+    let u = f * f * (3.0 - 2.0 * f);
+    let a = hash2(i);
+    let b = hash2(i + vec2(1.0, 0.0));
+    let c = hash2(i + vec2(0.0, 1.0));
+    let d = hash2(i + vec2(1.0, 1.0));
+    return mix(mix(a, b, u.x), mix(c, d, u.x), u.y);
+}
+
+// r: 积雪厚度 0..1
+@compute @workgroup_size(8, 8)
+fn accumulate(@builtin(global_invocation_id) gid: vec3u) {
+    let size = textureDimensions(next);
+    if gid.x >= size.x || gid.y >= size.y {
+        return;
+    }
+    let texel = vec2i(gid.xy);
+    let uv = (vec2f(gid.xy) + 0.5) / vec2f(size);
+    let world = params.center + (uv - 0.5) * params.extent;
+
+    // 积得不均匀: 有的地方先白, 融化时也先露出来
+    let variation = 0.5 + value_noise(world * 0.3) + 0.5 * value_noise(world * 1.7);
+    let amount = textureLoad(previous, texel, 0).r + params.rate * params.dt * variation;
+    textureStore(next, texel, vec4(clamp(amount, 0.0, 1.0), 0.0, 0.0, 1.0));
+}
@@ -0,0 +1,194 @@
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+    inv_view_proj: mat4x4<f32>,
+    view_position: vec4<f32>,
+};
+
+struct SsrParams {
+    max_steps: u32,
+    // 世界空间, 米
+    max_distance: f32,
+    thickness: f32,
+    min_smoothness: f32,
+    intensity: f32,
+    _pad0: f32,
+    _pad1: f32,
+    _pad2: f32,
+}
+
+@group(0) @binding(0) var scene_color: texture_2d<f32>;
+@group(0) @binding(1) var linear_sampler: sampler;
+@group(0) @binding(2) var surface: texture_2d<f32>;
+@group(0) @binding(3) var scene_depth: texture_2d<f32>;
+@group(0) @binding(4) var environment: texture_cube<f32>;
+@group(0) @binding(5) var<uniform> camera: CameraUniform;
+@group(0) @binding(6) var<uniform> params: SsrParams;
+
+// 最多 4 个反射探针, 没用到的槽位绑的是全局环境图
+struct ReflectionProbes {
+    position: array<vec4f, 4>,
+    // 盒子的半边长, 米
+    extent: array<vec4f, 4>,
+    count: u32,
+}
+
+@group(0) @binding(7) var probe0: texture_cube<f32>;
+@group(0) @binding(8) var probe1: texture_cube<f32>;
+@group(0) @binding(9) var probe2: texture_cube<f32>;
+@group(0) @binding(10) var probe3: texture_cube<f32>;
+@group(0) @binding(11) var<uniform> probes: ReflectionProbes;
+
+struct FullscreenOut {
+    @builtin(position) pos: vec4f,
+    @location(0) uv: vec2f,
+}
+
+@vertex
+fn fullscreen_vs(@builtin(vertex_index) vid: u32) -> FullscreenOut {
+    let uv = vec2f(f32((vid << 1u) & 2u), f32(vid & 2u));
+    var out: FullscreenOut;
+    out.pos = vec4f(uv * 2.0 - 1.0, 0.0, 1.0);
+    out.uv = vec2f(uv.x, 1.0 - uv.y);
+    return out;
+}
+
+fn uv_to_ndc(uv: vec2f) -> vec2f {
+    return vec2(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+}
+
+fn world_position(uv: vec2f, depth: f32) -> vec3f {
+    let p = camera.inv_view_proj * vec4(uv_to_ndc(uv), depth, 1.0);
+    return p.xyz / p.w;
+}
+
+fn load_depth(uv: vec2f) -> f32 {
+    let size = vec2f(textureDimensions(scene_depth));
+    let texel = clamp(vec2i(uv * size), vec2i(0), vec2i(size) - 1);
+    return textureLoad(scene_depth, texel, 0).r;
+}
+
+fn hash2(p: vec2f) -> f32 {
+    return fract(sin(dot(p, vec2(127.1, 311.7))) * 43758.5453);
+}
+
+// 包含 p 的探针里离得最近的那个, -1 = 不在任何探针里
+fn nearest_probe(p: vec3f) -> i32 {
+    var best = -1;
+    var best_distance = 1e30;
+    for (var i = 0u; i < probes.count; i++) {
+        let offset = p - probes.position[i].xyz;
+        if any(abs(offset) > probes.extent[i].xyz) {
+            continue;
+        }
+        let d = dot(offset, offset);
+        if d < best_distance {
+            best_distance = d;
+            best = i32(i);
+        }
+    }
+    return best;
+}
+
+// 视差校正: 反射射线打到探针盒子的哪里, 就从探针中心往那里看
+fn parallax_corrected(probe: i32, p: vec3f, dir: vec3f) -> vec3f {
+    let center = probes.position[probe].xyz;
+    let extent = probes.extent[probe].xyz;
+    let to_max = (center + extent - p) / dir;
+    let to_min = (center - extent - p) / dir;
+    let far = max(to_max, to_min);
+    let t = min(min(far.x, far.y), far.z);
+    return p + dir * t - center;
+}
+
+fn sample_probe(probe: i32, dir: vec3f) -> vec3f {
+    switch probe {
+        case 0: { return textureSampleLevel(probe0, linear_sampler, dir, 0.0).rgb; }
+        case 1: { return textureSampleLevel(probe1, linear_sampler, dir, 0.0).rgb; }
+        case 2: { return textureSampleLevel(probe2, linear_sampler, dir, 0.0).rgb; }
+        case 3: { return textureSampleLevel(probe3, linear_sampler, dir, 0.0).rgb; }
+        default: { return textureSampleLevel(environment, linear_sampler, dir, 0.0).rgb; }
+    }
+}
+
+fn sample_environment(probe: i32, p: vec3f, dir: vec3f) -> vec3f {
+    if probe < 0 {
+        return sample_probe(probe, dir);
+    }
+    return sample_probe(probe, parallax_corrected(probe, p, dir));
+}
+
+// 环境兜底: 粗糙一点就在反射方向周围多取几个点, 假装是模糊过的 cubemap
+fn environment_glossy(p: vec3f, dir: vec3f, roughness: f32) -> vec3f {
+    let probe = nearest_probe(p);
+    let tangent = normalize(cross(dir, select(vec3(0.0, 1.0, 0.0), vec3(1.0, 0.0, 0.0), abs(dir.y) > 0.99)));
+    let bitangent = cross(dir, tangent);
+    let spread = roughness * 0.3;
+    var sum = sample_environment(probe, p, dir);
+    sum += sample_environment(probe, p, normalize(dir + tangent * spread));
+    sum += sample_environment(probe, p, normalize(dir - tangent * spread));
+    sum += sample_environment(probe, p, normalize(dir + bitangent * spread));
+    sum += sample_environment(probe, p, normalize(dir - bitangent * spread));
+    return sum / 5.0;
+}
+
+// x,y: 命中的 uv, z: 命中置信度 (0 = 没打中)
+fn march(origin: vec3f, dir: vec3f, jitter: f32) -> vec3f {
+    let step = params.max_distance / f32(params.max_steps);
+    for (var i = 0u; i < params.max_steps; i++) {
+        let p = origin + dir * step * (f32(i) + jitter);
+        let clip = camera.view_proj * vec4(p, 1.0);
+        if clip.w <= 0.0 {
+            return vec3(0.0);
+        }
+        let ndc = clip.xyz / clip.w;
+        let uv = vec2(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+        if any(uv < vec2(0.0)) || any(uv > vec2(1.0)) {
+            return vec3(0.0);
+        }
+        let depth = load_depth(uv);
+        if depth >= 1.0 {
+            continue;
+        }
+        // 在射线后面但不太远 = 打中, 用到相机的距离比较, 单位是米
+        let eye = camera.view_position.xyz;
+        let behind = distance(p, eye) - distance(world_position(uv, depth), eye);
+        if behind > 0.0 && behind < params.thickness {
+            // 靠近屏幕边缘的命中淡出, 避免反射突然断掉
+            let edge = min(min(uv.x, 1.0 - uv.x), min(uv.y, 1.0 - uv.y));
+            return vec3(uv, smoothstep(0.0, 0.1, edge));
+        }
+    }
+    return vec3(0.0);
+}
+
+// 叠加到场景上的反射, 用 additive blend 画
+@fragment
+fn ssr_fs(in: FullscreenOut) -> @location(0) vec4f {
+    let size = vec2f(textureDimensions(surface));
+    let texel = vec2i(in.uv * size);
+    let s = textureLoad(surface, texel, 0);
+    let smoothness = s.w;
+    if smoothness < params.min_smoothness {
+        return vec4(0.0);
+    }
+    let depth = textureLoad(scene_depth, texel, 0).r;
+    let normal = normalize(s.xyz);
+    let p = world_position(in.uv, depth);
+    let view = normalize(p - camera.view_position.xyz);
+    let dir = reflect(view, normal);
+
+    let roughness = 1.0 - smoothness;
+    let jitter = hash2(in.pos.xy);
+    let hit = march(p + normal * 0.02, dir, jitter);
+    let env = environment_glossy(p, dir, roughness);
+    var reflection = env;
+    if hit.z > 0.0 {
+        let hit_color = textureSampleLevel(scene_color, linear_sampler, hit.xy, 0.0).rgb;
+        reflection = mix(env, hit_color, hit.z);
+    }
+
+    // 水的 F0 大约 0.02
+    let cos_theta = clamp(dot(-view, normal), 0.0, 1.0);
+    let fresnel = 0.02 + 0.98 * pow(1.0 - cos_theta, 5.0);
+    return vec4(reflection * fresnel * smoothness * params.intensity, 0.0);
+}
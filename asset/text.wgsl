@@ -0,0 +1,64 @@
+struct Screen {
+    size: vec2f,
+    _pad: vec2f,
+}
+
+@group(0) @binding(0) var<uniform> screen: Screen;
+// 每个字形 5x7 = 35 位, 占两个 u32, 96 个字形从空格开始
+@group(0) @binding(1) var<uniform> font: array<vec4u, 48>;
+
+const SOLID: u32 = 0xffffffffu;
+
+struct Instance {
+    // 像素, 左上角和宽高
+    @location(0) rect: vec4f,
+    @location(1) color: vec4f,
+    @location(2) glyph: u32,
+}
+
+struct VsOut {
+    @builtin(position) pos: vec4f,
+    @location(0) uv: vec2f,
+    @location(1) color: vec4f,
+    @location(2) @interpolate(flat) glyph: u32,
+}
+
+@vertex
+fn text_vs(@builtin(vertex_index) vid: u32, instance: Instance) -> VsOut {
+    // 两个三角形拼成一个矩形
+    var corners = array<vec2f, 6>(
+        vec2f(0.0, 0.0),
+        vec2f(1.0, 0.0),
+        vec2f(0.0, 1.0),
+        vec2f(0.0, 1.0),
+        vec2f(1.0, 0.0),
+        vec2f(1.0, 1.0),
+    );
+    let corner = corners[vid];
+    let p = instance.rect.xy + corner * instance.rect.zw;
+    var out: VsOut;
+    out.pos = vec4f(p / screen.size * vec2f(2.0, -2.0) + vec2f(-1.0, 1.0), 0.0, 1.0);
+    out.uv = corner;
+    out.color = instance.color;
+    out.glyph = instance.glyph;
+    return out;
+}
+
+@fragment
+fn text_fs(in: VsOut) -> @location(0) vec4f {
+    if in.glyph == SOLID {
+        return in.color;
+    }
+    // 单元格 6x9: 字形右边空一列, 上下各空一行
+    let cell = vec2i(floor(in.uv * vec2f(6.0, 9.0)));
+    if cell.x >= 5 || cell.y < 1 || cell.y >= 8 {
+        discard;
+    }
+    let bit = u32((cell.y - 1) * 5 + cell.x);
+    let index = in.glyph * 2u + bit / 32u;
+    let word = font[index / 4u][index % 4u];
+    if ((word >> (bit % 32u)) & 1u) == 0u {
+        discard;
+    }
+    return in.color;
+}
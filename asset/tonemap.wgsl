@@ -0,0 +1,110 @@
+struct TonemapUniform {
+    // 0: sdr, tonemap into 0..1; 1: hdr surface, scene referred linear output
+    output_mode: u32,
+    // scale from scene 1.0 to the hdr surface, paper white nits / 80
+    paper_white: f32,
+    encode_srgb: u32,
+    debug_encoding: u32,
+    // 用户的显示器校正
+    gamma: f32,
+    brightness: f32,
+    contrast: f32,
+    // 曝光, 单位是档 (EV)
+    exposure_ev: f32,
+    auto_exposure_ev: f32,
+    // 调试视图: 不曝光不压缩, 原样输出
+    passthrough: u32,
+    // 0: ACES, 1: Reinhard
+    curve: u32,
+    _pad: u32,
+}
+
+@group(0) @binding(0) var hdr_texture: texture_2d<f32>;
+@group(0) @binding(1) var hdr_sampler: sampler;
+@group(0) @binding(2) var<uniform> params: TonemapUniform;
+
+struct FullscreenOut {
+    @builtin(position) pos: vec4f,
+    @location(0) uv: vec2f,
+}
+
+// one triangle covering the whole screen
+@vertex
+fn fullscreen_vs(@builtin(vertex_index) vid: u32) -> FullscreenOut {
+    let uv = vec2f(f32((vid << 1u) & 2u), f32(vid & 2u));
+    var out: FullscreenOut;
+    out.pos = vec4f(uv * 2.0 - 1.0, 0.0, 1.0);
+    out.uv = vec2f(uv.x, 1.0 - uv.y);
+    return out;
+}
+
+// Narkowicz 的 ACES 拟合
+fn aces_fitted(x: vec3f) -> vec3f {
+    let a = 2.51;
+    let b = 0.03;
+    let c = 2.43;
+    let d = 0.59;
+    let e = 0.14;
+    return clamp((x * (a * x + b)) / (x * (c * x + d) + e), vec3(0.), vec3(1.));
+}
+
+fn reinhard(x: vec3f) -> vec3f {
+    return x / (1.0 + x);
+}
+
+fn linear_to_srgb(c: vec3f) -> vec3f {
+    let lower = c * 12.92;
+    let higher = 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055;
+    return select(higher, lower, c < vec3(0.0031308));
+}
+
+// gamma / contrast / brightness in display space, contrast pivots around middle grey
+fn display_adjust(c: vec3f) -> vec3f {
+    var color = pow(max(c, vec3(0.)), vec3(1.0 / params.gamma));
+    color = (color - 0.5) * params.contrast + 0.5 + params.brightness;
+    return max(color, vec3(0.));
+}
+
+@fragment
+fn tonemap_fs(in: FullscreenOut) -> @location(0) vec4f {
+    let hdr = textureSample(hdr_texture, hdr_sampler, in.uv);
+    if params.passthrough == 1u {
+        var color = saturate(hdr.rgb);
+        if params.output_mode == 1u {
+            color *= params.paper_white;
+        } else if params.encode_srgb == 1u {
+            color = linear_to_srgb(color);
+        }
+        return vec4(color, 1.0);
+    }
+    var color = hdr.rgb * exp2(params.exposure_ev + params.auto_exposure_ev);
+
+    if params.debug_encoding == 1u {
+        // 非法输入: NaN / 负数 / 超出 f16 范围
+        if any(color != color) || any(color < vec3(0.)) || any(color > vec3(65000.)) {
+            return vec4(1., 0., 1., 1.);
+        }
+        if abs(in.uv.x - 0.5) < 0.001 {
+            return vec4(1., 1., 0., 1.);
+        }
+        // 左半边: 如果 sRGB 编码的数据没解码就当线性用, 会是这样(发灰发白)
+        if in.uv.x < 0.5 {
+            color = linear_to_srgb(color);
+        }
+    }
+
+    if params.output_mode == 1u {
+        color = display_adjust(color) * params.paper_white;
+    } else {
+        if params.curve == 1u {
+            color = reinhard(max(color, vec3(0.)));
+        } else {
+            color = aces_fitted(color);
+        }
+        color = clamp(display_adjust(color), vec3(0.), vec3(1.));
+        if params.encode_srgb == 1u {
+            color = linear_to_srgb(color);
+        }
+    }
+    return vec4(color, hdr.a);
+}
@@ -0,0 +1,166 @@
+struct Params {
+    // 每个输出像素对应多少个输入像素
+    scale: vec2f,
+    // RCAS 锐化强度, 1 最锐
+    sharpness: f32,
+    // 视图是 sRGB 的, 读出来已经是线性光, 换成 gamma 2 再滤
+    perceptual: u32,
+}
+
+@group(0) @binding(0) var source: texture_2d<f32>;
+@group(0) @binding(1) var<uniform> params: Params;
+
+struct FullscreenOut {
+    @builtin(position) pos: vec4f,
+}
+
+@vertex
+fn fullscreen_vs(@builtin(vertex_index) vid: u32) -> FullscreenOut {
+    let uv = vec2f(f32((vid << 1u) & 2u), f32(vid & 2u));
+    var out: FullscreenOut;
+    out.pos = vec4f(uv * 2.0 - 1.0, 0.0, 1.0);
+    return out;
+}
+
+fn load(p: vec2i) -> vec3f {
+    let size = vec2i(textureDimensions(source));
+    let c = textureLoad(source, clamp(p, vec2(0), size - 1), 0).rgb;
+    return select(c, sqrt(max(c, vec3(0.0))), params.perceptual == 1u);
+}
+
+fn store(c: vec3f) -> vec4f {
+    return vec4(select(c, c * c, params.perceptual == 1u), 1.0);
+}
+
+// 近似亮度的两倍, 只用来找边的方向
+fn luma(c: vec3f) -> f32 {
+    return c.b * 0.5 + (c.r * 0.5 + c.g);
+}
+
+// 一个双线性象限对方向和边长的贡献
+// 十字: a 在上, b 在左, c 在中, d 在右, e 在下
+fn easu_set(w: f32, a: f32, b: f32, c: f32, d: f32, e: f32) -> vec3f {
+    let dir_x = d - b;
+    let len_x = saturate(abs(dir_x) / max(max(abs(d - c), abs(c - b)), 1e-5));
+    let dir_y = e - a;
+    let len_y = saturate(abs(dir_y) / max(max(abs(e - c), abs(c - a)), 1e-5));
+    return vec3(dir_x, dir_y, len_x * len_x + len_y * len_y) * w;
+}
+
+// 沿边方向拉伸的近似 Lanczos2 核, 返回 (颜色 * 权重, 权重)
+fn easu_tap(off: vec2f, dir: vec2f, len2: vec2f, lob: f32, clp: f32, c: vec3f) -> vec4f {
+    var v = vec2(off.x * dir.x + off.y * dir.y, off.x * -dir.y + off.y * dir.x);
+    v *= len2;
+    let d2 = min(dot(v, v), clp);
+    var wb = 2.0 / 5.0 * d2 - 1.0;
+    var wa = lob * d2 - 1.0;
+    wb *= wb;
+    wa *= wa;
+    wb = 25.0 / 16.0 * wb - (25.0 / 16.0 - 1.0);
+    let w = wb * wa;
+    return vec4(c * w, w);
+}
+
+// EASU: 12 个点, 先按亮度梯度估计边的方向和强度, 再沿边拉长滤波核
+//      b c
+//    e f g h
+//    i j k l
+//      n o
+@fragment
+fn easu_fs(in: FullscreenOut) -> @location(0) vec4f {
+    var pp = in.pos.xy * params.scale - 0.5;
+    let fp = floor(pp);
+    pp -= fp;
+    let p = vec2i(fp);
+
+    let b = load(p + vec2(0, -1));
+    let c = load(p + vec2(1, -1));
+    let e = load(p + vec2(-1, 0));
+    let f = load(p);
+    let g = load(p + vec2(1, 0));
+    let h = load(p + vec2(2, 0));
+    let i = load(p + vec2(-1, 1));
+    let j = load(p + vec2(0, 1));
+    let k = load(p + vec2(1, 1));
+    let l = load(p + vec2(2, 1));
+    let n = load(p + vec2(0, 2));
+    let o = load(p + vec2(1, 2));
+
+    let lb = luma(b);
+    let lc = luma(c);
+    let le = luma(e);
+    let lf = luma(f);
+    let lg = luma(g);
+    let lh = luma(h);
+    let li = luma(i);
+    let lj = luma(j);
+    let lk = luma(k);
+    let ll = luma(l);
+    let ln = luma(n);
+    let lo = luma(o);
+
+    let acc = easu_set((1.0 - pp.x) * (1.0 - pp.y), lb, le, lf, lg, lj)
+        + easu_set(pp.x * (1.0 - pp.y), lc, lf, lg, lh, lk)
+        + easu_set((1.0 - pp.x) * pp.y, lf, li, lj, lk, ln)
+        + easu_set(pp.x * pp.y, lg, lj, lk, ll, lo);
+
+    var dir = acc.xy;
+    let dir_r = dot(dir, dir);
+    // 没有方向就当成横的
+    if dir_r < 1.0 / 32768.0 {
+        dir = vec2(1.0, 0.0);
+    } else {
+        dir *= inverseSqrt(dir_r);
+    }
+    var len = acc.z * 0.5;
+    len *= len;
+    let stretch = dot(dir, dir) / max(abs(dir.x), abs(dir.y));
+    let len2 = vec2(1.0 + (stretch - 1.0) * len, 1.0 - 0.5 * len);
+    let lob = 0.5 + ((1.0 / 4.0 - 0.04) - 0.5) * len;
+    let clp = 1.0 / lob;
+
+    let sum = easu_tap(vec2(0.0, -1.0) - pp, dir, len2, lob, clp, b)
+        + easu_tap(vec2(1.0, -1.0) - pp, dir, len2, lob, clp, c)
+        + easu_tap(vec2(-1.0, 1.0) - pp, dir, len2, lob, clp, i)
+        + easu_tap(vec2(0.0, 1.0) - pp, dir, len2, lob, clp, j)
+        + easu_tap(vec2(0.0, 0.0) - pp, dir, len2, lob, clp, f)
+        + easu_tap(vec2(-1.0, 0.0) - pp, dir, len2, lob, clp, e)
+        + easu_tap(vec2(1.0, 1.0) - pp, dir, len2, lob, clp, k)
+        + easu_tap(vec2(2.0, 1.0) - pp, dir, len2, lob, clp, l)
+        + easu_tap(vec2(2.0, 0.0) - pp, dir, len2, lob, clp, h)
+        + easu_tap(vec2(1.0, 0.0) - pp, dir, len2, lob, clp, g)
+        + easu_tap(vec2(1.0, 2.0) - pp, dir, len2, lob, clp, o)
+        + easu_tap(vec2(0.0, 2.0) - pp, dir, len2, lob, clp, n);
+    // 限制在最近四个点的范围里, 负瓣不会振铃
+    let lo_c = min(min(f, g), min(j, k));
+    let hi_c = max(max(f, g), max(j, k));
+    return store(clamp(sum.rgb / sum.a, lo_c, hi_c));
+}
+
+// RCAS 能加的最大负瓣
+const RCAS_LIMIT: f32 = 0.25 - 1.0 / 16.0;
+
+// RCAS: 十字形五个点, 锐化量按不让结果超出邻居范围来算
+//   b
+// d e f
+//   h
+@fragment
+fn rcas_fs(in: FullscreenOut) -> @location(0) vec4f {
+    let p = vec2i(in.pos.xy);
+    let b = load(p + vec2(0, -1));
+    let d = load(p + vec2(-1, 0));
+    let e = load(p);
+    let f = load(p + vec2(1, 0));
+    let h = load(p + vec2(0, 1));
+
+    let mn4 = min(min(b, d), min(f, h));
+    let mx4 = max(max(b, d), max(f, h));
+    // SDR 的上限是 1, HDR 输出可能更亮, 就用最亮的那个
+    let peak = vec3(max(1.0, max(max(mx4.r, mx4.g), max(mx4.b, max(e.r, max(e.g, e.b))))));
+    let hit_min = min(mn4, e) / max(4.0 * mx4, vec3(1e-5));
+    let hit_max = (peak - max(mx4, e)) / min(4.0 * min(mn4, e) - 4.0 * peak, vec3(-1e-5));
+    let lobe_rgb = max(-hit_min, hit_max);
+    let lobe = max(-RCAS_LIMIT, min(max(lobe_rgb.r, max(lobe_rgb.g, lobe_rgb.b)), 0.0)) * params.sharpness;
+    let color = (lobe * (b + d + f + h) + e) / (4.0 * lobe + 1.0);
+    return store(color);
+}
@@ -1,132 +1,776 @@
-use std::borrow::Cow;
+use std::{
+    borrow::Cow,
+    cell::{Cell, RefCell},
+    collections::HashSet,
+    sync::Mutex,
+    time::Instant,
+};
 
+use anyhow::{anyhow, bail};
+use cgmath::{EuclideanSpace, Point3};
 use wgpu::{
-    util::{DeviceExt, RenderEncoder},
-    BindGroup, BindGroupDescriptor, BindGroupLayout, BlendState, Buffer, BufferBinding,
-    BufferDescriptor, BufferUsages, FragmentState, FrontFace, PipelineCompilationOptions,
-    PipelineLayout, PipelineLayoutDescriptor, PolygonMode, PrimitiveState, PrimitiveTopology,
-    RenderPipeline, RenderPipelineDescriptor, VertexState,
+    util::{DeviceExt, DrawIndexedIndirectArgs},
+    BindGroup, BindGroupLayout, Buffer, BufferUsages, FrontFace, PipelineCompilationOptions,
+    PipelineLayoutDescriptor, PolygonMode, PrimitiveState, PrimitiveTopology, RenderPipeline,
+    RenderPipelineDescriptor, VertexState,
 };
 
+#[cfg(feature = "post")]
+use crate::bloom::Bloom;
+#[cfg(feature = "ui")]
+use crate::debug_overlay::{self, DebugOverlay};
+#[cfg(feature = "post")]
+use crate::flare::Flare;
+#[cfg(feature = "post")]
+use crate::heat_haze::HeatHaze;
+#[cfg(feature = "path_tracing")]
+use crate::path_tracer::PathTracer;
+#[cfg(feature = "post")]
+use crate::post_chain::{self, PostChain};
+#[cfg(feature = "ui")]
+use crate::text::TextRenderer;
 use crate::{
-    camera::{Camera, CameraUniform},
-    GfxState,
+    asset_loader::{AssetLoader, DecodedTexture, Priority},
+    auto_exposure::{AutoExposure, ExposureInputs},
+    bindings::BindingsBuilder,
+    boids::{self, Boids},
+    camera::CameraUniform,
+    compute::{self, ComputeJob, ComputeStage},
+    config::RenderConfig,
+    debug_blit::DebugBlit,
+    debug_view::ViewMode,
+    deferred::{Deferred, RenderPath},
+    depth_buffer::DepthBuffer,
+    device_poll::SubmittedWork,
+    dynamic_resolution::{DynamicResolution, DynamicResolutionSettings},
+    environment::EnvironmentMap,
+    error,
+    frame_context::{FrameContext, FrameRing, FRAMES_IN_FLIGHT},
+    frame_stats::{self, CountedPass, CullStats, FrameStats},
+    frustum::{Aabb, Frustum},
+    fxaa::Fxaa,
+    gbuffer::GBuffer,
+    gpu_culling::{CullMesh, GpuCulling},
+    gpu_factory_builder::CustomDraw,
+    gpu_timer::GpuTimer,
+    ibl::HdrEnvironment,
+    indirect::IndirectDraws,
+    instance::{self, Instance},
+    irradiance::IrradianceGrid,
+    light::Light,
+    lightmap::BakedTexture,
+    material::{Blend, MaterialDesc, MaterialShader, MaterialTextures, Materials},
+    model,
+    particles::Particles,
+    pipeline_cache::PipelineCache,
+    pixel_inspector::PixelInspector,
+    planar_reflection::PlanarReflection,
+    profiler::{self, profile_scope, PlotName},
+    readback::ReadbackQueue,
+    reflection_probe::ReflectionProbes,
+    registry::{
+        BindGroupLayoutHandle, ComputePipelineHandle, MeshDraw, MeshHandle, PipelineHandle,
+        ResourceRegistry,
+    },
+    render_graph::{Pass, RenderGraph, TexturePool, TransientDesc},
+    render_scale::RenderScale,
+    scene::Scene,
+    sdf_scene::SdfScene,
+    shadertoy::{self, ShadertoyUniform},
+    shadow_map::ShadowMap,
+    sharpen::Sharpener,
+    skybox::Skybox,
+    ssr::{Ssr, SsrInputs},
+    static_geometry::StaticGeometryUniform,
+    surface,
+    texture::ImageTexture,
+    tonemap::{Tonemap, TonemapUniform, HDR_FORMAT},
+    upscale::{UpscaleQuality, Upscaler},
+    vertex::{self, Index, MeshVertex},
+    weather::{Precipitation, SnowCover, WeatherKind},
+    wgsl_preprocessor,
+    wind::WindUniform,
+    GfxState, GpuContext, UserEvent,
 };
+
+// names the baked maps are requested and uploaded under
+const LIGHTMAP_ASSET: &str = "lightmap";
+const AO_MAP_ASSET: &str = "AO map";
+#[cfg(feature = "ui")]
+const BUFFER_POOL_BLOCK_SIZE: u64 = 1 << 20;
+/// What the factory registers its own resources under, taken for custom pipelines and
+/// their bindings.
+pub const RESERVED_NAMES: [&str; 6] = [
+    "sky",
+    "display",
+    "first uniform",
+    "scene",
+    "mirror scene",
+    "camera",
+];
 pub struct GpuFactory {
-    pub bind_group: Vec<BindGroup>,
-    pub bind_group_layout: Vec<BindGroupLayout>,
-    pub pipeline: Vec<RenderPipeline>,
-    pub vertex_buffer: Vec<Buffer>,
-    pub index_buffer: Vec<Buffer>,
-    pub uniform_buffer: Vec<Buffer>,
-    pub pipeline_layout: Vec<PipelineLayout>,
-    pub shader: Vec<wgpu::ShaderModule>,
+    // the display pass's resources, the compute jobs' pipelines and the meshes drawn
+    // after the ray cast, by the handles below and those `add_mesh` returns
+    pub registry: ResourceRegistry,
+    scene_layout: BindGroupLayoutHandle,
+    display_pipeline: PipelineHandle,
+    // each frame in flight's uniform buffer and scene bind groups, taken in turn
+    frames: FrameRing,
+    camera_layout: BindGroupLayoutHandle,
+    // builds the materials' and the custom pipelines, their shaders and layouts into the
+    // registry once for everything asking for an equal one, with hits and misses to show
+    // for it; the passes build their few pipelines themselves
+    pub pipeline_cache: PipelineCache,
+    // the pipelines `app.factory_builder` adds, drawn over the scene
+    pub custom_draws: Vec<CustomDraw>,
+    // GPGPU work outside the renderer's own passes
+    pub compute_jobs: Vec<ComputeJob>,
+    // the meshes' draw arguments from a buffer, one per mesh in the order they were
+    // added, with `indirect_draws` on
+    pub mesh_draws: Option<IndirectDraws>,
+    // with `gpu_culling` on, what packs the instances in view for those draws
+    pub gpu_culling: Option<GpuCulling>,
+    pub materials: Materials,
+    pub light: Light,
+    pub shadow_map: ShadowMap,
+    // None for the procedural sky
+    pub skybox: Option<Skybox>,
+    // the PBR models' image based lighting, a placeholder without an image
+    pub environment_map: HdrEnvironment,
     pub camera_uniform: CameraUniform,
+    // the camera, sky and wind buffers are one each, not one per frame in flight: a frame
+    // writes them with copies in its "uniform uploads" commands, which the queue runs
+    // after everything the frames before submitted has read them. Only their staging
+    // memory is per frame, in its `FrameContext`'s belt
     pub camera_buffer: Buffer,
     pub camera_bind_group: BindGroup,
+    pub sky_buffer: Buffer,
+    // only built when the adapter granted POLYGON_MODE_LINE
+    pub wireframe_pipeline: Option<RenderPipeline>,
+    pub wireframe: bool,
+    pub tonemap: Tonemap,
+    // None where compute isn't available
+    pub auto_exposure: Option<AutoExposure>,
+    #[cfg(feature = "post")]
+    pub flare: Flare,
+    // blooms the HDR target in place, follows it through resizes
+    #[cfg(feature = "post")]
+    pub bloom: Bloom,
+    // normal/smoothness and depth of the scene pass
+    pub gbuffer: GBuffer,
+    // depth attachment of the scene pass, at the scene size
+    pub depth_buffer: DepthBuffer,
+    // albedo/material targets and the lighting pass of the deferred path, None on the
+    // forward path
+    pub deferred: Option<Deferred>,
+    // sky only pipeline rendering into the environment map faces
+    pub environment_pipeline: RenderPipeline,
+    pub environment: EnvironmentMap,
+    pub reflection_probes: ReflectionProbes,
+    pub ssr: Ssr,
+    pub planar_reflection: PlanarReflection,
+    pub irradiance: IrradianceGrid,
+    // boxes the sky shader ray casts, fixed for the scene's lifetime; the baked map flags
+    // change once their files are loaded
+    pub static_geometry_buffer: Buffer,
+    pub lightmap: BakedTexture,
+    pub ao_map: BakedTexture,
+    // decodes the baked maps off the render thread and uploads them over several frames
+    pub asset_loader: AssetLoader,
+    // the scene's flock, steering the instances of its cone mesh; None unless the scene
+    // has one and compute is available
+    pub boids: Option<Boids>,
+    // rain particles, None where compute isn't available
+    pub precipitation: Option<Precipitation>,
+    // the scene's particle fountain, None where compute isn't available
+    pub particles: Option<Particles>,
+    pub snow_cover: SnowCover,
+    // shared wind state for anything that moves with it, written like the camera buffer
+    pub wind_buffer: Buffer,
+    #[cfg(feature = "post")]
+    pub heat_haze: HeatHaze,
+    // replaces the display pass while path_tracing is on, None where compute isn't available
+    #[cfg(feature = "path_tracing")]
+    pub path_tracer: Option<PathTracer>,
+    #[cfg(feature = "path_tracing")]
+    pub path_tracing: bool,
+    // ray marches signed distance fields in place of the display pass while sdf_mode is on
+    // and path tracing isn't
+    pub sdf_scene: SdfScene,
+    pub sdf_mode: bool,
+    // renders the scene at another size than the window and resamples it, when asked for
+    pub render_scale: RenderScale,
+    /// requested SSAA factor, the render scale may settle on less
+    pub supersample: u32,
+    // GPU time of a frame, None without encoder timestamps
+    pub gpu_timer: Option<GpuTimer>,
+    pub dynamic_resolution: DynamicResolution,
+    // antialiases the post chain's output, when asked for
+    pub fxaa: Fxaa,
+    pub antialias: bool,
+    // runs the post chain below window size and upscales its output, when asked for
+    pub upscaler: Upscaler,
+    pub upscale_quality: UpscaleQuality,
+    // the scene's fullscreen effects between the tonemap and the upscaler
+    #[cfg(feature = "post")]
+    pub post_chain: PostChain,
+    // CAS on the post chain's output, when asked for
+    pub sharpener: Sharpener,
+    pub sharpen: bool,
+    // intermediate targets of the render graph, kept from one frame to the next
+    pub texture_pool: TexturePool,
+    // record runs of thread safe graph passes when `parallel_encoding` is on, kept for the
+    // factory's life; None when its threads couldn't be started
+    pub encoders: Option<rayon::ThreadPool>,
+    /// vertex, index and uniform regions for what's built again every frame, like the
+    /// text; regions taken for the frame come back after its submit
+    #[cfg(feature = "ui")]
+    pub buffer_pool: RefCell<crate::buffer_pool::BufferPool>,
+    // optional graph passes switched off from the hotkeys, and the one they act on
+    pub disabled_passes: HashSet<&'static str>,
+    pub selected_pass: Option<&'static str>,
+    // graph texture shown instead of the final image
+    pub debug_texture: Option<&'static str>,
+    // what the display pass shows instead of the lit scene
+    pub view_mode: ViewMode,
+    pub debug_blit: DebugBlit,
+    // reads back the scene pixel under the cursor each frame, when switched on
+    pub pixel_inspector: PixelInspector,
+    pub inspect_pixel: bool,
+    // debug UI text over the final image
+    #[cfg(feature = "ui")]
+    pub text: TextRenderer,
+    #[cfg(feature = "ui")]
+    pub debug_overlay: DebugOverlay,
+    // readbacks that finish on a later frame instead of waiting on the GPU
+    pub readbacks: ReadbackQueue,
+    // how many frames the GPU is behind
+    pub submitted_work: SubmittedWork,
+    // CPU milliseconds the last frame's graph took to record, to compare parallel
+    // encoding against recording on one thread
+    pub encode_ms: Cell<f32>,
+    // what the last finished frame recorded
+    stats: Cell<FrameStats>,
+    cull_stats: Cell<CullStats>,
+    // what last frame's graph had to choose from
+    optional_passes: RefCell<Vec<&'static str>>,
+    graph_textures: RefCell<Vec<&'static str>>,
+}
+
+/// The graph's names for where the scene and the post chain draw this frame, picked by
+/// `GpuFactory::frame_targets` before any pass goes in.
+#[derive(Clone, Copy)]
+struct FrameTargets {
+    /// the HDR target, or a transient at the render scale's size
+    scene: &'static str,
+    tonemap_output: &'static str,
+    /// where the scene's post chain ends
+    #[cfg(feature = "post")]
+    post_output: &'static str,
+    /// the luma pass's output when that's on
+    fxaa_input: &'static str,
+    fxaa_output: &'static str,
+    sharpen_input: &'static str,
+    /// the surface's
+    size: (u32, u32),
 }
 
 impl GpuFactory {
-    pub fn new(app: &GfxState) -> Self {
-        let code = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/asset/sky.wgsl"));
-        let shader = app
+    /// Builds every pipeline and GPU resource, with the pipelines `app.factory_builder`
+    /// adds. A shader or pipeline that doesn't validate fails here rather than on the
+    /// first frame that uses it.
+    pub fn new(app: &GfxState) -> error::Result<Self> {
+        app.gpu.scoped(|| {
+            let shader = Self::load_shader(&app.gpu.device, "sky.wgsl", &[])?;
+            let mut gpu_factory = Self::create(app, shader)?;
+            gpu_factory.load_models(&app.gpu, &app.scene);
+            gpu_factory.add_boids(app);
+            gpu_factory.custom_draws = app.factory_builder.build_pipelines(
+                &app.gpu.device,
+                &app.gpu.queue,
+                &mut gpu_factory.registry,
+                &mut gpu_factory.pipeline_cache,
+                gpu_factory.camera_layout,
+            );
+            Ok::<_, error::Error>(gpu_factory)
+        })?
+    }
+
+    /// A shader module from `asset/<file>` with its includes and `defines` resolved (see
+    /// `Preprocessed`). One that doesn't preprocess or parse is an error naming the file
+    /// and line it went wrong in.
+    pub fn load_shader(
+        device: &wgpu::Device,
+        file: &str,
+        defines: &[&str],
+    ) -> error::Result<wgpu::ShaderModule> {
+        let code = wgsl_preprocessor::code(file, defines)?;
+        Ok(device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(file),
+            source: wgpu::ShaderSource::Wgsl(Cow::Owned(code)),
+        }))
+    }
+
+    fn create(app: &GfxState, shader: wgpu::ShaderModule) -> error::Result<Self> {
+        let uniform_data = TheFirstUniformBuffer::new(app, ViewMode::Final);
+        let uniform_buffers = [(); FRAMES_IN_FLIGHT].map(|_| {
+            app.gpu
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("first buffer"),
+                    contents: bytemuck::bytes_of(&uniform_data),
+                    // COPY_SRC for the console's `buffer`
+                    usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
+                })
+        });
+
+        let sky_buffer = app
+            .gpu
             .device
-            .create_shader_module(wgpu::ShaderModuleDescriptor {
-                label: None,
-                source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(code)),
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Sky Buffer"),
+                contents: bytemuck::bytes_of(
+                    &app.scene
+                        .sky_uniform(&app.frame.sun_light, app.frame.cloud_offset),
+                ),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
             });
-        let uniform_data = TheFirstUniformBuffer {
-            width: app.surface_config.width,
-            height: app.surface_config.height,
-        };
-        let uniform_buffer: Buffer = app.device.create_buffer(&BufferDescriptor {
-            label: Some("first buffer"),
-            size: std::mem::size_of::<TheFirstUniformBuffer>() as u64,
-            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
-            mapped_at_creation: true,
-        });
-        {
-            uniform_buffer
-                .slice(..)
-                .get_mapped_range_mut()
-                .copy_from_slice(bytemuck::bytes_of(&uniform_data));
-            uniform_buffer.unmap();
-        }
-
-        let bind_group_layout =
-            app.device
-                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                    label: None,
-                    entries: &[wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    }],
+
+        let snow_cover = SnowCover::new(app);
+        let scene = &app.scene;
+        // stand ins until the baked files are decoded and uploaded, a few rows a frame
+        let lightmap = BakedTexture::placeholder(&app.gpu.device, &app.gpu.queue, LIGHTMAP_ASSET);
+        let ao_map = BakedTexture::placeholder(&app.gpu.device, &app.gpu.queue, AO_MAP_ASSET);
+        let mut asset_loader = AssetLoader::new(app.config.render.asset_upload_kb as u64 * 1024);
+        if scene.lightmap.enabled {
+            BakedTexture::request(
+                &mut asset_loader,
+                LIGHTMAP_ASSET,
+                &scene.lightmap.path,
+                Priority::Visible,
+            );
+        }
+        if scene.ao.enabled {
+            BakedTexture::request(
+                &mut asset_loader,
+                AO_MAP_ASSET,
+                &scene.ao.path,
+                Priority::Background,
+            );
+        }
+        let mut static_geometry = StaticGeometryUniform::new(&scene.static_boxes);
+        static_geometry.lightmap = scene.lightmap.uniform(lightmap.baked);
+        static_geometry.ao = scene.ao.uniform(ao_map.baked);
+        let static_geometry_buffer =
+            app.gpu
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Static Geometry Buffer"),
+                    contents: bytemuck::bytes_of(&static_geometry),
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
                 });
-        let bind_group = app.device.create_bind_group(&BindGroupDescriptor {
-            label: None,
-            layout: &bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: wgpu::BindingResource::Buffer(BufferBinding {
-                    buffer: &uniform_buffer,
-                    offset: 0,
-                    size: None,
-                }),
-            }],
-        });
 
         let mut camera_uniform = CameraUniform::new();
-        camera_uniform.update_view_proj(&app.camera);
+        camera_uniform.update_view_proj(&app.frame.camera);
         let camera_buffer = app
+            .gpu
             .device
             .create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: Some("Camera Buffer"),
                 contents: bytemuck::cast_slice(&[camera_uniform]),
                 usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
             });
-        let camera_bind_group_layout =
-            app.device
-                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                    entries: &[wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::VERTEX,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    }],
-                    label: Some("camera_bind_group_layout"),
-                });
-        let camera_bind_group = app.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &camera_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: camera_buffer.as_entire_binding(),
-            }],
-            label: Some("camera_bind_group"),
+        let wind_buffer = app
+            .gpu
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Wind Buffer"),
+                contents: bytemuck::bytes_of(&app.scene.wind.uniform(app.frame.time)),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+        let (camera_bind_group_layout, camera_bind_group) = BindingsBuilder::new()
+            .uniform(
+                0,
+                wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                &camera_buffer,
+            )
+            .build(&app.gpu.device, "camera");
+        let planar_reflection = PlanarReflection::new(
+            &app.gpu.device,
+            &camera_bind_group_layout,
+            app.window.surface_config.width,
+            app.window.surface_config.height,
+            app.scene.water.reflection_scale,
+        );
+        let irradiance = IrradianceGrid::new(app, &camera_bind_group_layout, &sky_buffer);
+        let scene_bindings = |uniform_buffer, reflection_view| {
+            Self::scene_bindings(
+                uniform_buffer,
+                &sky_buffer,
+                &snow_cover,
+                reflection_view,
+                &irradiance,
+                &static_geometry_buffer,
+                &lightmap,
+                &ao_map,
+            )
+        };
+        let bind_group_layout = scene_bindings(&uniform_buffers[0], &planar_reflection.view)
+            .layout(&app.gpu.device, "scene");
+        let bind_groups = uniform_buffers.each_ref().map(|uniform_buffer| {
+            (
+                scene_bindings(uniform_buffer, &planar_reflection.view).bind_group(
+                    &app.gpu.device,
+                    "scene",
+                    &bind_group_layout,
+                ),
+                scene_bindings(uniform_buffer, &planar_reflection.placeholder_view).bind_group(
+                    &app.gpu.device,
+                    "mirror scene",
+                    &bind_group_layout,
+                ),
+            )
         });
         let pipeline_layout = app
+            .gpu
             .device
             .create_pipeline_layout(&PipelineLayoutDescriptor {
                 label: None,
                 bind_group_layouts: &[&bind_group_layout, &camera_bind_group_layout],
                 push_constant_ranges: &[],
             });
-        let pipeline = app
-            .device
-            .create_render_pipeline(&RenderPipelineDescriptor {
+        let (pipeline, wireframe_pipeline, environment_pipeline) = Self::sky_pipelines(
+            &app.gpu.device,
+            &shader,
+            &pipeline_layout,
+            app.gpu.features.wireframe(),
+        );
+        let shadow_map = ShadowMap::new(&app.gpu.device, &app.scene.shadows);
+        let environment_map = HdrEnvironment::new(&app.gpu.device, &app.gpu.queue, &app.scene.ibl)
+            .unwrap_or_else(|e| {
+                println!(
+                    "Environment map left out, lighting with the sky's ambient: {:#}",
+                    e
+                );
+                HdrEnvironment::placeholder(&app.gpu.device)
+            });
+        let skybox = Skybox::new(
+            &app.gpu.device,
+            &app.gpu.queue,
+            &app.scene.skybox,
+            &bind_group_layout,
+            &camera_bind_group_layout,
+        )
+        .unwrap_or_else(|e| {
+            println!("Skybox left out, drawing the procedural sky: {:#}", e);
+            None
+        })
+        .or_else(|| {
+            (environment_map.loaded() && app.scene.ibl.background).then(|| {
+                Skybox::from_cube(
+                    &app.gpu.device,
+                    &environment_map.environment_view,
+                    &environment_map.sampler,
+                    &bind_group_layout,
+                    &camera_bind_group_layout,
+                )
+            })
+        });
+        let light = Light::new(
+            &app.gpu.device,
+            &app.scene.point_light,
+            &app.frame.camera,
+            &shadow_map,
+            &environment_map,
+        );
+        let deferred_path = match app.config.render.render_path {
+            RenderPath::Forward => false,
+            RenderPath::Deferred if Deferred::supported(&app.gpu.limits) => true,
+            RenderPath::Deferred => {
+                println!("Too few color attachments for the deferred path, rendering forward");
+                false
+            }
+        };
+        let mesh_draws = match app.config.render.indirect_draws {
+            false => None,
+            true if app.gpu.features.indirect_execution => Some(IndirectDraws::new(
+                &app.gpu.device,
+                app.gpu.features.multi_draw_indirect(),
+                &[],
+            )),
+            true => {
+                println!("No indirect draws on this device, drawing the meshes directly");
+                None
+            }
+        };
+        let gpu_culling = match app.config.render.gpu_culling {
+            false => None,
+            true if mesh_draws.is_some() && app.gpu.budget.compute => {
+                Some(GpuCulling::new(&app.gpu.device))
+            }
+            true => {
+                println!("GPU culling needs indirect draws and compute, drawing every instance");
+                None
+            }
+        };
+        let mut registry = ResourceRegistry::default();
+        let mut pipeline_cache = PipelineCache::default();
+        let materials = Materials::new(
+            &app.gpu.device,
+            &app.gpu.queue,
+            &mut registry,
+            &mut pipeline_cache,
+            [
+                &bind_group_layout,
+                &camera_bind_group_layout,
+                &light.bind_group_layout,
+            ],
+            deferred_path,
+        )?;
+        let environment = EnvironmentMap::new(&app.gpu.device, &camera_bind_group_layout);
+        let reflection_probes = ReflectionProbes::new(
+            &app.gpu.device,
+            &camera_bind_group_layout,
+            &app.scene.reflection_probes,
+        );
+
+        let tonemap = Tonemap::new(app);
+        let auto_exposure = AutoExposure::new(app, &tonemap.hdr.view);
+        #[cfg(feature = "post")]
+        let bloom = Bloom::new(
+            &app.gpu.device,
+            &tonemap.hdr.view,
+            tonemap.hdr.width,
+            tonemap.hdr.height,
+        );
+        let gbuffer = GBuffer::new(
+            &app.gpu.device,
+            app.window.surface_config.width,
+            app.window.surface_config.height,
+        );
+        let depth_buffer = DepthBuffer::new(
+            &app.gpu.device,
+            app.window.surface_config.width,
+            app.window.surface_config.height,
+        );
+        let deferred = deferred_path
+            .then(|| {
+                Deferred::new(
+                    &app.gpu.device,
+                    &app.gpu.budget,
+                    &gbuffer,
+                    &bind_group_layout,
+                    &camera_bind_group_layout,
+                    &light.bind_group_layout,
+                    app.window.surface_config.width,
+                    app.window.surface_config.height,
+                )
+            })
+            .transpose()?;
+        #[cfg(feature = "post")]
+        let flare = Flare::new(app, &camera_buffer, &sky_buffer, &gbuffer.depth_view);
+        let ssr = Ssr::new(
+            app,
+            &SsrInputs {
+                gbuffer: &gbuffer,
+                environment_view: &environment.view,
+                probes: &reflection_probes,
+                camera_buffer: &camera_buffer,
+            },
+        );
+        let precipitation = Precipitation::new(app, &camera_buffer);
+        let particles = Particles::new(app, &camera_buffer);
+        let sdf_scene = SdfScene::new(&app.gpu.device, &camera_buffer)?;
+        #[cfg(feature = "post")]
+        let heat_haze = HeatHaze::new(app, &camera_buffer);
+        #[cfg(feature = "path_tracing")]
+        let path_tracer = PathTracer::new(
+            app,
+            &camera_buffer,
+            &sky_buffer,
+            &environment.view,
+            &app.scene.static_boxes,
+            &app.scene.path_tracer.spheres,
+        )?;
+
+        registry.add_named("sky", shader);
+        registry.add_named("display", pipeline_layout);
+        // the first frame's under the plain names
+        let frames = uniform_buffers
+            .into_iter()
+            .zip(bind_groups)
+            .enumerate()
+            .map(|(i, (uniform_buffer, (scene, mirror)))| {
+                let context = FrameContext::new(
+                    registry.add(uniform_buffer),
+                    registry.add(scene),
+                    registry.add(mirror),
+                );
+                if i == 0 {
+                    registry.name("first uniform", context.uniform_buffer);
+                    registry.name("scene", context.scene_bind_group);
+                    registry.name("mirror scene", context.mirror_bind_group);
+                }
+                context
+            })
+            .collect();
+        let mut factory = Self {
+            scene_layout: registry.add_named("scene", bind_group_layout),
+            display_pipeline: registry.add_named("display", pipeline),
+            frames: FrameRing::new(frames),
+            camera_layout: registry.add_named("camera", camera_bind_group_layout),
+            registry,
+            pipeline_cache,
+            custom_draws: vec![],
+            compute_jobs: vec![],
+            mesh_draws,
+            gpu_culling,
+            materials,
+            light,
+            shadow_map,
+            skybox,
+            environment_map,
+            camera_uniform,
+            camera_buffer,
+            camera_bind_group,
+            sky_buffer,
+            wireframe_pipeline,
+            wireframe: false,
+            tonemap,
+            auto_exposure,
+            #[cfg(feature = "post")]
+            flare,
+            #[cfg(feature = "post")]
+            bloom,
+            gbuffer,
+            depth_buffer,
+            deferred,
+            environment_pipeline,
+            environment,
+            reflection_probes,
+            ssr,
+            planar_reflection,
+            irradiance,
+            static_geometry_buffer,
+            lightmap,
+            ao_map,
+            asset_loader,
+            boids: None,
+            precipitation,
+            particles,
+            snow_cover,
+            wind_buffer,
+            #[cfg(feature = "post")]
+            heat_haze,
+            #[cfg(feature = "path_tracing")]
+            path_tracer,
+            #[cfg(feature = "path_tracing")]
+            path_tracing: false,
+            sdf_scene,
+            sdf_mode: false,
+            render_scale: RenderScale::new(&app.gpu.device, app.gpu.budget.max_texture_size),
+            supersample: app.config.render.supersample,
+            gpu_timer: GpuTimer::new(&app.gpu.device, &app.gpu.queue, &app.gpu.features),
+            dynamic_resolution: DynamicResolution::default(),
+            upscaler: Upscaler::new(
+                &app.gpu.device,
+                surface::output_view_format(&app.window.surface_config),
+            ),
+            upscale_quality: app.config.render.upscale.quality,
+            fxaa: Fxaa::new(
+                &app.gpu.device,
+                surface::output_view_format(&app.window.surface_config),
+            ),
+            antialias: app.config.render.fxaa.enabled,
+            #[cfg(feature = "post")]
+            post_chain: PostChain::new(
+                &app.gpu.device,
+                surface::output_view_format(&app.window.surface_config),
+                &app.scene.post,
+            ),
+            sharpener: Sharpener::new(
+                &app.gpu.device,
+                surface::output_view_format(&app.window.surface_config),
+            ),
+            sharpen: app.config.render.sharpen.enabled,
+            texture_pool: TexturePool::default(),
+            encoders: match rayon::ThreadPoolBuilder::new()
+                .thread_name(|i| format!("graph encoder {}", i))
+                .build()
+            {
+                Ok(encoders) => Some(encoders),
+                Err(e) => {
+                    println!("Parallel encoding disabled: {}", e);
+                    None
+                }
+            },
+            #[cfg(feature = "ui")]
+            buffer_pool: RefCell::new(crate::buffer_pool::BufferPool::new(
+                &app.gpu.device,
+                "buffer pool",
+                BufferUsages::VERTEX | BufferUsages::INDEX | BufferUsages::UNIFORM,
+                BUFFER_POOL_BLOCK_SIZE,
+            )),
+            disabled_passes: HashSet::new(),
+            selected_pass: None,
+            debug_texture: None,
+            view_mode: ViewMode::Final,
+            debug_blit: DebugBlit::new(
+                &app.gpu.device,
+                surface::output_view_format(&app.window.surface_config),
+            ),
+            pixel_inspector: PixelInspector::new(&app.gpu.device),
+            inspect_pixel: false,
+            #[cfg(feature = "ui")]
+            text: TextRenderer::new(
+                &app.gpu.device,
+                surface::output_view_format(&app.window.surface_config),
+                2.0,
+            ),
+            #[cfg(feature = "ui")]
+            debug_overlay: DebugOverlay::default(),
+            readbacks: {
+                // the proxy is only locked to send from whichever thread polled
+                let event_proxy = Mutex::new(app.window.event_proxy.clone());
+                ReadbackQueue::new(move || {
+                    if let Ok(Some(event_proxy)) = event_proxy.lock().as_deref() {
+                        let _ = event_proxy.send_event(UserEvent::ReadbackReady);
+                    }
+                })
+            },
+            submitted_work: SubmittedWork::default(),
+            encode_ms: Cell::new(0.0),
+            stats: Cell::new(FrameStats::default()),
+            cull_stats: Cell::new(CullStats::default()),
+            optional_passes: RefCell::new(vec![]),
+            graph_textures: RefCell::new(vec![]),
+        };
+        // everything above was sized for the window, the scene and post targets change from here
+        let surface_config = &app.window.surface_config;
+        factory
+            .render_scale
+            .resize(1.0, surface_config.width, surface_config.height);
+        if factory.supersample > 1 || factory.upscale_quality != UpscaleQuality::Off {
+            factory.resize(&app.gpu.device, &app.window.surface_config);
+        }
+        Ok(factory)
+    }
+
+    /// The pipelines drawn with the sky shader: the display pass, its wireframe where the
+    /// adapter draws lines, and the environment map's faces.
+    fn sky_pipelines(
+        device: &wgpu::Device,
+        shader: &wgpu::ShaderModule,
+        layout: &wgpu::PipelineLayout,
+        wireframe: bool,
+    ) -> (RenderPipeline, Option<RenderPipeline>, RenderPipeline) {
+        let [gbuffer_surface, gbuffer_depth, gbuffer_id] = GBuffer::color_targets();
+        let make_pipeline = |polygon_mode: PolygonMode| {
+            device.create_render_pipeline(&RenderPipelineDescriptor {
                 label: None,
-                layout: Some(&pipeline_layout),
+                layout: Some(layout),
                 vertex: VertexState {
-                    module: &shader,
+                    module: shader,
                     entry_point: "display_vs",
                     buffers: &[],
                     compilation_options: PipelineCompilationOptions::default(),
@@ -134,86 +778,2000 @@ impl GpuFactory {
                 primitive: PrimitiveState {
                     topology: PrimitiveTopology::TriangleList,
                     front_face: FrontFace::Ccw,
-                    polygon_mode: PolygonMode::Fill,
+                    polygon_mode,
                     ..Default::default()
                 },
                 fragment: Some(wgpu::FragmentState {
-                    module: &shader,
+                    module: shader,
                     entry_point: "display_fs",
-                    targets: &[Some(wgpu::ColorTargetState {
-                        format: wgpu::TextureFormat::Bgra8UnormSrgb,
-                        blend: None,
-                        write_mask: wgpu::ColorWrites::ALL,
-                    })],
+                    targets: &[
+                        Some(wgpu::ColorTargetState {
+                            format: HDR_FORMAT,
+                            blend: None,
+                            write_mask: wgpu::ColorWrites::ALL,
+                        }),
+                        gbuffer_surface.clone(),
+                        gbuffer_depth.clone(),
+                        gbuffer_id.clone(),
+                    ],
                     compilation_options: PipelineCompilationOptions::default(),
                 }),
-                depth_stencil: None,
+                depth_stencil: Some(DepthBuffer::depth_stencil_state()),
                 multisample: wgpu::MultisampleState::default(),
                 multiview: None,
+            })
+        };
+        let environment_pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("environment pipeline"),
+            layout: Some(layout),
+            vertex: VertexState {
+                module: shader,
+                entry_point: "display_vs",
+                buffers: &[],
+                compilation_options: PipelineCompilationOptions::default(),
+            },
+            primitive: PrimitiveState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: "env_fs",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: HDR_FORMAT,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: PipelineCompilationOptions::default(),
+            }),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+        (
+            make_pipeline(PolygonMode::Fill),
+            wireframe.then(|| make_pipeline(PolygonMode::Line)),
+            environment_pipeline,
+        )
+    }
+
+    // group 0 over the uniforms of the frame being recorded
+    fn scene_bind_group(&self) -> &BindGroup {
+        &self.registry[self.frames.current().scene_bind_group]
+    }
+
+    fn mirror_bind_group(&self) -> &BindGroup {
+        &self.registry[self.frames.current().mirror_bind_group]
+    }
+
+    /// Makes every frame's scene bind groups again, after what they point at changed.
+    fn rebuild_scene_bind_groups(&mut self, device: &wgpu::Device) {
+        let mut replaced = vec![];
+        for context in self.frames.iter() {
+            let scene_bindings = |reflection_view| {
+                Self::scene_bindings(
+                    &self.registry[context.uniform_buffer],
+                    &self.sky_buffer,
+                    &self.snow_cover,
+                    reflection_view,
+                    &self.irradiance,
+                    &self.static_geometry_buffer,
+                    &self.lightmap,
+                    &self.ao_map,
+                )
+            };
+            let layout = &self.registry[self.scene_layout];
+            let scene =
+                scene_bindings(&self.planar_reflection.view).bind_group(device, "scene", layout);
+            let mirror = scene_bindings(&self.planar_reflection.placeholder_view).bind_group(
+                device,
+                "mirror scene",
+                layout,
+            );
+            replaced.push((context.scene_bind_group, scene));
+            replaced.push((context.mirror_bind_group, mirror));
+        }
+        for (handle, bind_group) in replaced {
+            self.registry.replace(handle, bind_group);
+        }
+    }
+
+    /// Group 0 of the display pass and the meshes, with the planar reflection seen through
+    /// `reflection_view`.
+    #[allow(clippy::too_many_arguments)]
+    fn scene_bindings<'a>(
+        uniform_buffer: &'a Buffer,
+        sky_buffer: &'a Buffer,
+        snow_cover: &'a SnowCover,
+        reflection_view: &'a wgpu::TextureView,
+        irradiance: &'a IrradianceGrid,
+        static_geometry_buffer: &'a Buffer,
+        lightmap: &'a BakedTexture,
+        ao_map: &'a BakedTexture,
+    ) -> BindingsBuilder<'a> {
+        let fragment = wgpu::ShaderStages::FRAGMENT;
+        BindingsBuilder::new()
+            .uniform(0, fragment, uniform_buffer)
+            .uniform(1, fragment, sky_buffer)
+            .texture(2, fragment, &snow_cover.view)
+            .sampler(3, fragment, &snow_cover.sampler)
+            .texture(4, fragment, reflection_view)
+            .uniform(5, fragment, &irradiance.uniform_buffer)
+            .uniform(6, fragment, static_geometry_buffer)
+            .texture(7, fragment, &lightmap.view)
+            .texture(8, fragment, &ao_map.view)
+    }
+
+    /// Uploads an indexed triangle list for the display pass to draw with `material`, one
+    /// of `materials`, from the next frame on. It's drawn once where its vertices are
+    /// until `set_instance_buffer` says otherwise.
+    pub fn add_mesh<I: Index>(
+        &mut self,
+        device: &wgpu::Device,
+        vertices: &[MeshVertex],
+        indices: &[I],
+        material: usize,
+    ) -> MeshHandle {
+        let instance = Instance::default();
+        let bounds = Aabb::from_points(vertices.iter().map(|vertex| Point3::from(vertex.position)))
+            .unwrap_or(Aabb {
+                min: Point3::origin(),
+                max: Point3::origin(),
             });
+        let mesh = MeshDraw {
+            vertex_buffer: self.registry.add(vertex::create_vertex_buffer(
+                device,
+                "mesh vertices",
+                vertices,
+            )),
+            index_buffer: self.registry.add(vertex::create_index_buffer(
+                device,
+                "mesh indices",
+                indices,
+            )),
+            index_format: I::FORMAT,
+            index_count: indices.len() as u32,
+            instance_buffer: self.registry.add(instance::create_instance_buffer(
+                device,
+                &[instance.to_raw()],
+            )),
+            instance_count: 1,
+            bounds,
+            world_bounds: Some(bounds),
+            material,
+        };
+        self.shadow_map.include(bounds.corners());
+        let mesh = self.registry.add(mesh);
+        self.update_mesh_draws(device);
+        mesh
+    }
 
-        Self {
-            bind_group: vec![bind_group],
-            bind_group_layout: vec![bind_group_layout],
-            pipeline: vec![pipeline],
-            vertex_buffer: vec![],
-            index_buffer: vec![],
-            camera_uniform,
-            camera_buffer,
-            camera_bind_group,
-            uniform_buffer: vec![uniform_buffer],
-            pipeline_layout: vec![pipeline_layout],
-            shader: vec![shader],
+    /// Draws `mesh` once for each of `instances` from the next frame on, in place of where
+    /// it was drawn before. No instances hides it. The app only instances what the GPU
+    /// writes, through `set_instance_buffer`; the tests place theirs here.
+    #[cfg(test)]
+    pub fn set_instances(
+        &mut self,
+        device: &wgpu::Device,
+        mesh: MeshHandle,
+        instances: &[Instance],
+    ) {
+        let raw: Vec<_> = instances.iter().copied().map(Instance::to_raw).collect();
+        // a buffer can't be empty, a hidden mesh keeps one it doesn't read
+        let raw = if raw.is_empty() {
+            vec![Instance::default().to_raw()]
+        } else {
+            raw
+        };
+        let bounds = self.registry[mesh].bounds;
+        let world_bounds = Aabb::from_points(instances.iter().flat_map(|instance| {
+            let model = instance.model();
+            bounds
+                .corners()
+                .map(move |corner| cgmath::Transform::transform_point(&model, corner))
+        }));
+        self.set_instance_buffer(
+            device,
+            mesh,
+            instance::create_instance_buffer(device, &raw),
+            instances.len() as u32,
+            world_bounds,
+        );
+    }
+
+    /// Draws `mesh` once for each of the first `instance_count` `InstanceRaw`s in `buffer`,
+    /// for instances the GPU writes. `world_bounds` has to hold them wherever they go,
+    /// it's what the CPU culling and the shadows go by.
+    pub fn set_instance_buffer(
+        &mut self,
+        device: &wgpu::Device,
+        mesh: MeshHandle,
+        buffer: Buffer,
+        instance_count: u32,
+        world_bounds: Option<Aabb>,
+    ) {
+        let instance_buffer = self.registry[mesh].instance_buffer;
+        self.registry.replace(instance_buffer, buffer);
+        let draw = &mut self.registry[mesh];
+        draw.instance_count = instance_count;
+        draw.world_bounds = world_bounds;
+        self.update_mesh_draws(device);
+        // the light's box only grows, instances moved away still count
+        if let Some(world_bounds) = world_bounds {
+            self.shadow_map.include(world_bounds.corners());
         }
     }
 
-    pub fn render(&self, app: &GfxState) {
-        let mut encoder = app
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                label: Some("render frame"),
+    /// Rewrites `mesh_draws` after the meshes or their instances changed.
+    fn update_mesh_draws(&mut self, device: &wgpu::Device) {
+        let Some(mesh_draws) = self.mesh_draws.as_mut() else {
+            return;
+        };
+        let args: Vec<_> = self
+            .registry
+            .iter::<MeshDraw>()
+            .map(|(_, mesh)| DrawIndexedIndirectArgs {
+                index_count: mesh.index_count,
+                instance_count: mesh.instance_count,
+                first_index: 0,
+                base_vertex: 0,
+                first_instance: 0,
+            })
+            .collect();
+        mesh_draws.set(device, &args);
+        if let Some(gpu_culling) = self.gpu_culling.as_mut() {
+            let meshes = self.registry.iter::<MeshDraw>().map(|(_, mesh)| CullMesh {
+                instance_buffer: &self.registry[mesh.instance_buffer],
+                instance_count: mesh.instance_count,
+                bounds: mesh.bounds,
             });
+            gpu_culling.set_meshes(device, mesh_draws, &args, meshes);
+        }
+    }
 
-        println!("Creating render pass");
-        let frame = app.surface.get_current_texture().unwrap();
-        let render_target = frame
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
+    /// Builds `entry_point` of the WGSL in `code` for `compute_jobs` to dispatch, with
+    /// `layouts` as its groups. None when the device can't run compute.
+    // for embedding programs and GPGPU experiments
+    pub fn add_compute_pipeline(
+        &mut self,
+        gpu: &GpuContext,
+        label: &str,
+        code: &str,
+        entry_point: &str,
+        layouts: &[&BindGroupLayout],
+    ) -> Option<ComputePipelineHandle> {
+        if !gpu.budget.compute {
+            println!("No compute support, {} not built", label);
+            return None;
+        }
+        let pipeline =
+            gpu.scoped(|| compute::create_pipeline(&gpu.device, label, code, entry_point, layouts));
+        match pipeline {
+            Ok(pipeline) => Some(self.registry.add(pipeline)),
+            Err(e) => {
+                println!("{} not built: {}", label, e);
+                None
+            }
+        }
+    }
 
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+    /// Dispatches `job` every frame from the next one on, until it's disabled. Returns its
+    /// index in `compute_jobs`.
+    // for embedding programs and GPGPU experiments
+    pub fn add_compute_job(&mut self, job: ComputeJob) -> usize {
+        self.compute_jobs.push(job);
+        self.compute_jobs.len() - 1
+    }
+
+    /// Imports the scene's models as meshes. A model that can't be read is left out,
+    /// the rest of the scene still shows.
+    fn load_models(&mut self, gpu: &GpuContext, scene: &Scene) {
+        for path in &scene.models {
+            let meshes = match model::import(&Scene::resolve_path(path)) {
+                Ok(meshes) => meshes,
+                Err(e) => {
+                    println!("Model left out: {:#}", e);
+                    continue;
+                }
+            };
+            for mesh in meshes {
+                let imported = &mesh.material;
+                let max_size = gpu.limits.max_texture_dimension_2d;
+                let upload = |decoded: &Option<DecodedTexture>, slot: &str| {
+                    let decoded = decoded.as_ref()?;
+                    if decoded.width > max_size || decoded.height > max_size {
+                        println!(
+                            "{} texture of {} is over {} texels, left out",
+                            slot, mesh.name, max_size
+                        );
+                        return None;
+                    }
+                    Some(ImageTexture::from_decoded(
+                        &gpu.device,
+                        &gpu.queue,
+                        &format!("{} {}", mesh.name, slot),
+                        decoded,
+                    ))
+                };
+                let textures = MaterialTextures {
+                    base_color: upload(&imported.base_color, "base color"),
+                    metallic_roughness: upload(&imported.metallic_roughness, "metallic roughness"),
+                    normal: upload(&imported.normal, "normal"),
+                    occlusion: upload(&imported.occlusion, "occlusion"),
+                };
+                let material = self.materials.add(
+                    &gpu.device,
+                    &gpu.queue,
+                    &mut self.registry,
+                    &mut self.pipeline_cache,
+                    MaterialDesc {
+                        name: mesh.name,
+                        shader: MaterialShader::Pbr,
+                        params: imported.params,
+                        textures,
+                        blend: imported.blend,
+                        cull_mode: (!imported.double_sided).then_some(wgpu::Face::Back),
+                    },
+                );
+                self.add_mesh(&gpu.device, &mesh.vertices, &mesh.indices, material);
+            }
+            println!("Loaded {}", path.display());
+        }
+    }
+
+    /// The scene's flock as instances of a cone mesh, when it has one.
+    fn add_boids(&mut self, app: &GfxState) {
+        let settings = &app.scene.boids;
+        if !settings.enabled {
+            return;
+        }
+        if !app.gpu.budget.compute {
+            println!("Boids disabled: no compute support");
+            return;
+        }
+        let device = &app.gpu.device;
+        let (vertices, indices) = boids::cone(12);
+        let mesh = self.add_mesh(device, &vertices, &indices, Materials::DEFAULT);
+        let (boids, instance_buffer) = Boids::new(device, settings, app.config.deterministic.seed);
+        self.set_instance_buffer(
+            device,
+            mesh,
+            instance_buffer,
+            boids.count(),
+            Some(settings.bounds()),
+        );
+        self.boids = Some(boids);
+    }
+
+    /// Recompiles sky.wgsl as it is in asset/ now and swaps in its pipelines. One that doesn't
+    /// compile is an error and leaves the pipelines drawing now in place.
+    pub fn reload_sky(&mut self, gpu: &GpuContext) -> error::Result<()> {
+        let layout = self
+            .registry
+            .find::<wgpu::PipelineLayout>("display")
+            .expect("the factory registers its display layout");
+        let (shader, (pipeline, wireframe_pipeline, environment_pipeline)) =
+            gpu.scoped(|| {
+                let shader = Self::load_shader(&gpu.device, "sky.wgsl", &[])?;
+                let pipelines = Self::sky_pipelines(
+                    &gpu.device,
+                    &shader,
+                    &self.registry[layout],
+                    gpu.features.wireframe(),
+                );
+                Ok::<_, error::Error>((shader, pipelines))
+            })??;
+        let sky = self
+            .registry
+            .find::<wgpu::ShaderModule>("sky")
+            .expect("the factory registers its sky shader");
+        self.registry.replace(sky, shader);
+        self.registry.replace(self.display_pipeline, pipeline);
+        self.wireframe_pipeline = wireframe_pipeline;
+        self.environment_pipeline = environment_pipeline;
+        // the probes saw the old sky
+        self.irradiance.request_bake();
+        Ok(())
+    }
+
+    /// Recreates the size dependent targets: the post chain's at the upscaler's input size,
+    /// which is the window size unless upscaling, and the scene ones at the render scale
+    /// of that. The intermediate targets in between come from the render graph each frame.
+    pub fn resize(&mut self, device: &wgpu::Device, surface_config: &wgpu::SurfaceConfiguration) {
+        self.upscaler.resize(
+            self.upscale_quality,
+            surface_config.width,
+            surface_config.height,
+        );
+        let (post_width, post_height) = self.upscaler.input_size();
+        self.render_scale.resize(
+            self.supersample as f32 * self.dynamic_resolution.scale(),
+            post_width,
+            post_height,
+        );
+        let (width, height) = self.render_scale.scene_size();
+        self.tonemap.resize(device, post_width, post_height);
+        if let Some(auto_exposure) = self.auto_exposure.as_mut() {
+            auto_exposure.resize(device, &self.tonemap.hdr.view);
+        }
+        #[cfg(feature = "post")]
+        self.bloom
+            .resize(device, &self.tonemap.hdr.view, post_width, post_height);
+        self.gbuffer = GBuffer::new(device, width, height);
+        self.depth_buffer = DepthBuffer::new(device, width, height);
+        if let Some(deferred) = self.deferred.as_mut() {
+            deferred.resize(device, &self.gbuffer, width, height);
+        }
+        // the old reflection target goes away with the bind group that pointed at it
+        self.planar_reflection.resize(device, width, height);
+        self.rebuild_scene_bind_groups(device);
+        #[cfg(feature = "post")]
+        self.flare.resize(
+            device,
+            &self.camera_buffer,
+            &self.sky_buffer,
+            &self.gbuffer.depth_view,
+        );
+        self.ssr.resize(
+            device,
+            &SsrInputs {
+                gbuffer: &self.gbuffer,
+                environment_view: &self.environment.view,
+                probes: &self.reflection_probes,
+                camera_buffer: &self.camera_buffer,
+            },
+            width,
+            height,
+        );
+        #[cfg(feature = "post")]
+        self.heat_haze
+            .resize(device, &self.camera_buffer, post_width, post_height);
+        #[cfg(feature = "path_tracing")]
+        if let Some(path_tracer) = self.path_tracer.as_mut() {
+            path_tracer.resize(
+                device,
+                &self.camera_buffer,
+                &self.sky_buffer,
+                &self.environment.view,
+                width,
+                height,
+            );
+        }
+    }
+
+    /// Whether the path tracer replaces the display pass this frame. Always false without
+    /// the `path_tracing` feature.
+    pub fn path_tracing(&self) -> bool {
+        #[cfg(feature = "path_tracing")]
+        return self.path_tracing && self.path_tracer.is_some();
+        #[cfg(not(feature = "path_tracing"))]
+        false
+    }
+
+    /// The ray cast scene into the HDR target and G-buffer, with the skybox and, on the
+    /// forward path, the meshes.
+    fn render_display(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        app: &GfxState,
+        scene_view: &wgpu::TextureView,
+        visible: &[bool],
+    ) {
+        let [gbuffer_surface, gbuffer_depth, gbuffer_id] = self.gbuffer.color_attachments();
+        let mut render_pass = CountedPass::begin(
+            encoder,
+            &wgpu::RenderPassDescriptor {
                 label: Some("display pass"),
+                color_attachments: &[
+                    Some(wgpu::RenderPassColorAttachment {
+                        view: scene_view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(if app.config.window.transparent {
+                                wgpu::Color::TRANSPARENT
+                            } else {
+                                wgpu::Color::BLACK
+                            }),
+                            store: wgpu::StoreOp::Store,
+                        },
+                    }),
+                    gbuffer_surface,
+                    gbuffer_depth,
+                    gbuffer_id,
+                ],
+                depth_stencil_attachment: Some(self.depth_buffer.attachment()),
+                ..Default::default()
+            },
+        );
+        render_pass.set_pipeline(&self.registry[self.display_pipeline]);
+        if let (true, Some(wireframe_pipeline)) = (self.wireframe, &self.wireframe_pipeline) {
+            render_pass.set_pipeline(wireframe_pipeline);
+        }
+        render_pass.set_bind_group(0, self.scene_bind_group(), &[]);
+        render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
+
+        render_pass.draw(0..3, 0..1);
+        println!("Drawing");
+
+        // groups 0 and 1 above fit every material pipeline too; blended meshes go
+        // over the opaque ones. The deferred path draws them in passes of their own
+        render_pass.set_bind_group(3, &self.light.bind_group, &[]);
+        let mut bound_pipeline = None;
+        for blend in [Blend::Opaque, Blend::Alpha] {
+            // behind the opaque meshes, under the blended ones; debug views keep the
+            // ray cast's sky
+            if let (Blend::Alpha, Some(skybox), ViewMode::Final) =
+                (blend, &self.skybox, self.view_mode)
+            {
+                skybox.draw(&mut render_pass);
+                bound_pipeline = None;
+            }
+            // the debug views only know the ray cast's surfaces, lit meshes would cover them
+            if self.deferred.is_none() && self.view_mode == ViewMode::Final {
+                self.draw_meshes(&mut render_pass, blend, visible, &mut bound_pipeline);
+            }
+        }
+    }
+
+    /// The opaque meshes' surfaces into the G-buffer and the deferred targets, over what
+    /// the display pass left, for `Deferred::render` to light.
+    fn render_deferred_geometry(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        deferred: &Deferred,
+        scene_view: &wgpu::TextureView,
+        visible: &[bool],
+    ) {
+        let [gbuffer_surface, gbuffer_depth, gbuffer_id] = self.gbuffer.load_attachments();
+        let [albedo, material] = deferred.color_attachments();
+        let mut render_pass = CountedPass::begin(
+            encoder,
+            &wgpu::RenderPassDescriptor {
+                label: Some("deferred geometry pass"),
+                color_attachments: &[
+                    Some(wgpu::RenderPassColorAttachment {
+                        view: scene_view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Load,
+                            store: wgpu::StoreOp::Store,
+                        },
+                    }),
+                    gbuffer_surface,
+                    gbuffer_depth,
+                    gbuffer_id,
+                    albedo,
+                    material,
+                ],
+                depth_stencil_attachment: Some(self.depth_buffer.load_attachment()),
+                ..Default::default()
+            },
+        );
+        render_pass.set_bind_group(0, self.scene_bind_group(), &[]);
+        render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
+        render_pass.set_bind_group(3, &self.light.bind_group, &[]);
+        self.draw_meshes(&mut render_pass, Blend::Opaque, visible, &mut None);
+    }
+
+    /// The blended meshes, shaded forward over the lit deferred ones.
+    fn render_blended_meshes(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        scene_view: &wgpu::TextureView,
+        visible: &[bool],
+    ) {
+        let [gbuffer_surface, gbuffer_depth, gbuffer_id] = self.gbuffer.load_attachments();
+        let mut render_pass = CountedPass::begin(
+            encoder,
+            &wgpu::RenderPassDescriptor {
+                label: Some("blended mesh pass"),
+                color_attachments: &[
+                    Some(wgpu::RenderPassColorAttachment {
+                        view: scene_view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Load,
+                            store: wgpu::StoreOp::Store,
+                        },
+                    }),
+                    gbuffer_surface,
+                    gbuffer_depth,
+                    gbuffer_id,
+                ],
+                depth_stencil_attachment: Some(self.depth_buffer.load_attachment()),
+                ..Default::default()
+            },
+        );
+        render_pass.set_bind_group(0, self.scene_bind_group(), &[]);
+        render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
+        render_pass.set_bind_group(3, &self.light.bind_group, &[]);
+        self.draw_meshes(&mut render_pass, Blend::Alpha, visible, &mut None);
+    }
+
+    /// The `visible` meshes whose material blends as `blend`, with groups 0, 1 and 3
+    /// bound. `bound_pipeline` is the material pipeline the pass has set, if any.
+    fn draw_meshes<'a>(
+        &'a self,
+        render_pass: &mut CountedPass<'a>,
+        blend: Blend,
+        visible: &[bool],
+        bound_pipeline: &mut Option<PipelineHandle>,
+    ) {
+        for (handle, mesh) in self.registry.iter::<MeshDraw>() {
+            let i = handle.index();
+            let material = &self.materials.materials[mesh.material];
+            // indirect draws take the instance count from their buffer, which compute
+            // passes may change
+            let hidden = self.mesh_draws.is_none() && mesh.instance_count == 0;
+            if material.blend != blend || hidden || !visible[i] {
+                continue;
+            }
+            if *bound_pipeline != Some(material.pipeline) {
+                render_pass.set_pipeline(&self.registry[self.materials.pipeline(mesh.material)]);
+                *bound_pipeline = Some(material.pipeline);
+            }
+            let (bind_group, offset) = self.materials.bind_group(mesh.material);
+            render_pass.set_bind_group(2, bind_group, &[offset]);
+            render_pass.set_vertex_buffer(0, self.registry[mesh.vertex_buffer].slice(..));
+            let instances = match &self.gpu_culling {
+                Some(gpu_culling) => gpu_culling.culled(i),
+                None => &self.registry[mesh.instance_buffer],
+            };
+            render_pass.set_vertex_buffer(1, instances.slice(..));
+            render_pass.set_index_buffer(
+                self.registry[mesh.index_buffer].slice(..),
+                mesh.index_format,
+            );
+            // every mesh has buffers of its own, so each is a run of one draw
+            match &self.mesh_draws {
+                Some(mesh_draws) => mesh_draws.draw(render_pass, i as u32..i as u32 + 1),
+                None => render_pass.draw_indexed(0..mesh.index_count, 0, 0..mesh.instance_count),
+            }
+        }
+    }
+
+    /// The builder's pipelines over `scene_view`, in the order they were added.
+    fn render_custom_draws(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        scene_view: &wgpu::TextureView,
+    ) {
+        let mut render_pass = CountedPass::begin(
+            encoder,
+            &wgpu::RenderPassDescriptor {
+                label: Some("custom pipelines pass"),
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &render_target,
+                    view: scene_view,
                     resolve_target: None,
                     ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        load: wgpu::LoadOp::Load,
                         store: wgpu::StoreOp::Store,
                     },
                 })],
                 ..Default::default()
+            },
+        );
+        render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+        for draw in &self.custom_draws {
+            render_pass.push_debug_group(draw.name);
+            render_pass.set_pipeline(&self.registry[draw.pipeline]);
+            render_pass.set_bind_group(1, &self.registry[draw.bind_group], &[]);
+            for constants in &draw.draws {
+                if draw.push_constant_size > 0 {
+                    render_pass.set_push_constants(
+                        wgpu::ShaderStages::VERTEX_FRAGMENT,
+                        0,
+                        constants,
+                    );
+                }
+                render_pass.draw(0..draw.vertex_count, 0..1);
+            }
+            render_pass.pop_debug_group();
+        }
+    }
+
+    /// Whether a custom pipeline is a Shadertoy-style shader, which animates every frame.
+    pub fn runs_shadertoy(&self) -> bool {
+        self.custom_draws.iter().any(|draw| draw.shadertoy)
+    }
+
+    /// Writes `value` into the uniform a custom pipeline declared as `name`. Fails when
+    /// there's no such uniform or it's another size than `T`.
+    // for embedding programs, the app has no custom pipelines
+    pub fn write_uniform<T: bytemuck::Pod>(
+        &self,
+        queue: &wgpu::Queue,
+        name: &str,
+        value: &T,
+    ) -> anyhow::Result<()> {
+        let buffer = self
+            .registry
+            .find::<Buffer>(name)
+            .ok_or_else(|| anyhow!("no uniform named {}", name))?;
+        let buffer = &self.registry[buffer];
+        if buffer.size() != std::mem::size_of::<T>() as u64 {
+            bail!(
+                "{} is {} bytes, not the {} written to it",
+                name,
+                buffer.size(),
+                std::mem::size_of::<T>()
+            );
+        }
+        frame_stats::write_buffer(queue, buffer, 0, bytemuck::bytes_of(value));
+        Ok(())
+    }
+
+    /// Draws the custom pipeline `name` once for each of `values`, with the value as its push
+    /// constants: a model matrix, an object id, anything small that would otherwise take a
+    /// uniform buffer per object. Holds until set again, a rebuilt factory draws once. Fails
+    /// when there's no such pipeline or its push constants are another size than `T`.
+    // for embedding programs, the app has no custom pipelines
+    pub fn set_draw_constants<T: bytemuck::Pod>(
+        &mut self,
+        name: &str,
+        values: &[T],
+    ) -> anyhow::Result<()> {
+        let draw = self
+            .custom_draws
+            .iter_mut()
+            .find(|draw| draw.name == name)
+            .ok_or_else(|| anyhow!("no custom pipeline named {}", name))?;
+        if std::mem::size_of::<T>() != draw.push_constant_size as usize {
+            bail!(
+                "{}'s push constants are {} bytes, not {}",
+                name,
+                draw.push_constant_size,
+                std::mem::size_of::<T>()
+            );
+        }
+        draw.draws = values
+            .iter()
+            .map(|value| bytemuck::bytes_of(value).to_vec())
+            .collect();
+        Ok(())
+    }
+
+    /// Which meshes the camera may see, tested on the CPU by their boxes unless the
+    /// indirect draws are culled on the GPU or `cpu_culling` is off. Counted into
+    /// `cull_stats`.
+    fn cull_meshes(&self, render: &RenderConfig) -> Vec<bool> {
+        let meshes = || self.registry.iter::<MeshDraw>().map(|(_, mesh)| mesh);
+        let with_instances = meshes().filter(|mesh| mesh.world_bounds.is_some()).count() as u32;
+        if self.mesh_draws.is_some() || !render.cpu_culling {
+            self.cull_stats.set(CullStats {
+                drawn: with_instances,
+                culled: 0,
             });
-            for pipeline in self.pipeline.iter() {
-                render_pass.set_pipeline(&pipeline);
+            return vec![true; self.registry.len::<MeshDraw>()];
+        }
+        let frustum = Frustum::from_view_proj(&self.camera_uniform.view_proj.into());
+        let visible: Vec<bool> = meshes()
+            .map(|mesh| {
+                mesh.world_bounds
+                    .is_some_and(|bounds| frustum.intersects(&bounds))
+            })
+            .collect();
+        let drawn = visible.iter().filter(|&&visible| visible).count() as u32;
+        self.cull_stats.set(CullStats {
+            drawn,
+            culled: with_instances - drawn,
+        });
+        visible
+    }
+
+    /// Meshes drawn and culled on the CPU last frame.
+    #[cfg(any(feature = "ui", test))]
+    pub fn cull_stats(&self) -> CullStats {
+        self.cull_stats.get()
+    }
+
+    /// Draw calls, buffer writes and the rest of what the last frame recorded.
+    #[cfg(feature = "ui")]
+    pub fn stats(&self) -> FrameStats {
+        self.stats.get()
+    }
+
+    /// Uploads this frame's share of the loading assets and swaps in the baked maps that
+    /// finished, with everything that binds them.
+    pub fn update_assets(&mut self, gpu: &GpuContext, scene: &Scene) {
+        profile_scope!("update assets");
+        let loaded = self.asset_loader.upload(&gpu.device, &gpu.queue);
+        if loaded.is_empty() {
+            return;
+        }
+        for asset in loaded {
+            let command = if asset.name == LIGHTMAP_ASSET {
+                "bake-lightmap"
+            } else {
+                "bake-ao"
+            };
+            let texture = match asset.texture {
+                Ok(texture) => texture,
+                Err(e) => {
+                    println!(
+                        "No {} ({:#}), bake one with `cargo run -- {}`",
+                        asset.name, e, command
+                    );
+                    continue;
+                }
+            };
+            println!(
+                "{} loaded ({}x{})",
+                asset.name,
+                texture.width(),
+                texture.height()
+            );
+            let baked = BakedTexture::loaded(texture);
+            if asset.name == LIGHTMAP_ASSET {
+                self.lightmap = baked;
+            } else {
+                self.ao_map = baked;
             }
-            for (index, bind_group) in self.bind_group.iter().enumerate() {
-                render_pass.set_bind_group(index as u32, &bind_group, &[]);
+        }
+        let progress = self.asset_loader.progress();
+        println!(
+            "Loading assets {}/{} ({:.0}%)",
+            progress.done,
+            progress.total,
+            progress.fraction() * 100.0
+        );
+
+        let mut static_geometry = StaticGeometryUniform::new(&scene.static_boxes);
+        static_geometry.lightmap = scene.lightmap.uniform(self.lightmap.baked);
+        static_geometry.ao = scene.ao.uniform(self.ao_map.baked);
+        if self.lightmap.baked {
+            match scene
+                .lightmap
+                .charts(&scene.static_boxes, self.lightmap.size)
+            {
+                Some(charts) => static_geometry.lightmap_charts = charts,
+                None => println!(
+                    "The lightmap was baked for other boxes or settings, only its ground is used; re-bake it with `cargo run -- bake-lightmap`"
+                ),
             }
+        }
+        frame_stats::write_buffer(
+            &gpu.queue,
+            &self.static_geometry_buffer,
+            0,
+            bytemuck::bytes_of(&static_geometry),
+        );
+        self.rebuild_scene_bind_groups(&gpu.device);
+        // the probes saw the ground without its baked lighting
+        self.irradiance.request_bake();
+    }
 
-            render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
+    /// Records, submits and presents a frame. A surface that can't give one this time skips
+    /// it; running out of memory is an error.
+    pub fn render(&self, app: &GfxState) -> error::Result<()> {
+        profile_scope!("render");
+        let frame_context = self.frames.begin(&app.gpu.device);
+        self.irradiance
+            .bake(app, &self.environment_pipeline, self.mirror_bind_group());
+        let mut encoder = app
+            .gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("render frame"),
+            });
+        // lets the timer's and the queued readbacks from earlier frames land without waiting
+        // on this one
+        app.config.render.poll.begin_frame(&app.gpu.device);
+        self.readbacks.service();
+        let timed = self
+            .gpu_timer
+            .as_ref()
+            .is_some_and(|timer| timer.begin(&mut encoder));
 
-            render_pass.draw(0..6, 0..1);
-            println!("Drawing");
+        println!("Creating render pass");
+        // headless there's no surface, frames go to the offscreen texture and aren't presented
+        let frame = match app.window.surface.as_ref().map(|s| s.get_current_texture()) {
+            Some(Ok(frame)) => Some(frame),
+            Some(Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated)) => {
+                println!("Surface lost or outdated, reconfiguring");
+                if let Some(surface) = &app.window.surface {
+                    surface.configure(&app.gpu.device, &app.window.surface_config);
+                }
+                return Ok(());
+            }
+            Some(Err(wgpu::SurfaceError::OutOfMemory)) => return Err(error::Error::OutOfMemory),
+            Some(Err(e)) => {
+                println!("Failed to acquire next frame: {:?}", e);
+                return Ok(());
+            }
+            None => None,
         };
-        app.queue.write_buffer(
-            &self.camera_buffer,
-            0,
-            bytemuck::cast_slice(&[self.camera_uniform]),
+        let Some(frame_texture) = frame
+            .as_ref()
+            .map(|frame| &frame.texture)
+            .or(app.window.offscreen.as_ref())
+        else {
+            println!("No surface or offscreen target to render to");
+            return Ok(());
+        };
+        let render_target = frame_texture.create_view(&wgpu::TextureViewDescriptor {
+            format: Some(surface::output_view_format(&app.window.surface_config)),
+            ..Default::default()
+        });
+        self.tonemap.write_uniform(&app.gpu.queue);
+        let sky_uniform = app
+            .scene
+            .sky_uniform(&app.frame.sun_light, app.frame.cloud_offset);
+        let visible = self.cull_meshes(&app.config.render);
+        let mut graph = RenderGraph::default();
+        graph.disable(self.disabled_passes.iter().copied());
+        if self.view_mode != ViewMode::Final {
+            // nothing should draw over the debug view
+            graph.disable([
+                "precipitation",
+                "particles",
+                "custom pipelines",
+                "heat haze",
+                "flare",
+                "droplets",
+                "bloom",
+            ]);
+        }
+        let targets = self.frame_targets(&mut graph, app, frame_texture, &render_target);
+        self.add_compute_pass(&mut graph, ComputeStage::BeforeRender);
+        self.add_simulation_passes(&mut graph, app);
+        self.add_scene_passes(&mut graph, app, targets.scene, &visible);
+        self.add_scene_effect_passes(&mut graph, app, targets);
+        self.add_hdr_passes(&mut graph, app);
+        self.add_post_passes(&mut graph, app, targets);
+        #[cfg(feature = "ui")]
+        self.add_ui_pass(&mut graph, app, targets.size);
+        self.add_compute_pass(&mut graph, ComputeStage::AfterRender);
+        *self.optional_passes.borrow_mut() = graph.optional_passes();
+        // the debug blit reads floats, the integer targets can't be shown
+        *self.graph_textures.borrow_mut() = graph
+            .texture_names()
+            .into_iter()
+            .filter(|name| {
+                let sample_type = graph
+                    .texture_format(name)
+                    .and_then(|format| format.sample_type(None, None));
+                !matches!(
+                    sample_type,
+                    Some(wgpu::TextureSampleType::Uint | wgpu::TextureSampleType::Sint)
+                )
+            })
+            .collect();
+        let encode_start = Instant::now();
+        let (mut command_buffers, mut encoder) = {
+            profile_scope!("encode");
+            graph.execute(
+                &app.gpu.device,
+                encoder,
+                &self.texture_pool,
+                self.encoders
+                    .as_ref()
+                    .filter(|_| app.config.render.parallel_encoding),
+                self.gpu_timer
+                    .as_ref()
+                    .filter(|_| timed)
+                    .map(|timer| timer.pass_queries()),
+            )
+        };
+        self.encode_ms
+            .set(encode_start.elapsed().as_secs_f32() * 1000.0);
+        profiler::plot(PlotName::EncodeMs, self.encode_ms.get() as f64);
+        profiler::plot(
+            PlotName::TexturePoolMb,
+            self.texture_pool.bytes() as f64 / (1024.0 * 1024.0),
+        );
+
+        let mut uploads = app
+            .gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("uniform uploads"),
+            });
+        {
+            let mut staging_belt = frame_context.staging_belt();
+            let mut upload = |buffer: &Buffer, data: &[u8]| {
+                frame_stats::write_staged(
+                    &mut staging_belt,
+                    &mut uploads,
+                    &app.gpu.device,
+                    buffer,
+                    data,
+                )
+            };
+            upload(
+                &self.registry[frame_context.uniform_buffer],
+                bytemuck::bytes_of(&TheFirstUniformBuffer::new(app, self.view_mode)),
+            );
+            upload(
+                &self.camera_buffer,
+                bytemuck::bytes_of(&self.camera_uniform),
+            );
+            upload(&self.sky_buffer, bytemuck::bytes_of(&sky_uniform));
+            upload(
+                &self.wind_buffer,
+                bytemuck::bytes_of::<WindUniform>(&app.scene.wind.uniform(app.frame.time)),
+            );
+            if let Some(buffer) = self.registry.find::<Buffer>(shadertoy::UNIFORM_NAME) {
+                let size = self.render_scale.scene_size();
+                upload(
+                    &self.registry[buffer],
+                    bytemuck::bytes_of(&ShadertoyUniform::new(app, size)),
+                );
+            }
+            staging_belt.finish();
+        }
+        self.light
+            .write(&app.gpu.queue, &app.scene.point_light, &app.frame.camera);
+        if let Some(deferred) = &self.deferred {
+            deferred.write_lights(&app.gpu.queue, &app.scene.lights);
+        }
+        self.shadow_map.write_uniform(
+            &app.gpu.queue,
+            &app.scene.shadows,
+            sky_uniform.light_direction,
+        );
+        if let Some(skybox) = &self.skybox {
+            skybox.write_uniform(&app.gpu.queue, &app.scene.skybox);
+        }
+        self.environment_map
+            .write_uniform(&app.gpu.queue, &app.scene.ibl);
+
+        if let (true, Some(timer)) = (timed, &self.gpu_timer) {
+            timer.end(&mut encoder);
+        }
+        command_buffers.push(encoder.finish());
+        // the passes read what the uploads write
+        command_buffers.insert(0, uploads.finish());
+        let submission = {
+            profile_scope!("submit");
+            self.submitted_work.submit(&app.gpu.queue, command_buffers)
+        };
+        self.frames.submitted(submission.clone());
+        #[cfg(feature = "ui")]
+        self.buffer_pool.borrow_mut().end_frame();
+        if let (true, Some(timer)) = (timed, &self.gpu_timer) {
+            timer.request_readback();
+        }
+        self.pixel_inspector.request_readback();
+        // the frame still goes out, the error stops the next one
+        let within_budget = self.finish_stats(&app.config.render);
+        {
+            profile_scope!("present");
+            if let Some(frame) = frame {
+                frame.present();
+            }
+        }
+        app.config
+            .render
+            .poll
+            .end_frame(&app.gpu.device, submission);
+        within_budget
+    }
+
+    /// Brings the frame's textures into the graph and creates the transients between the
+    /// scene and the surface, for the passes added after it to draw through.
+    fn frame_targets<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        app: &GfxState,
+        frame_texture: &'a wgpu::Texture,
+        render_target: &'a wgpu::TextureView,
+    ) -> FrameTargets {
+        let output_format = surface::output_view_format(&app.window.surface_config);
+        let (width, height) = (
+            app.window.surface_config.width,
+            app.window.surface_config.height,
+        );
+        graph.import("hdr", &self.tonemap.hdr.texture, &self.tonemap.hdr.view);
+        graph.import("surface", frame_texture, render_target);
+        // written by the display pass, read by the screen space passes after it; with path
+        // tracing they keep whatever the last displayed frame left
+        graph.import(
+            "gbuffer surface",
+            &self.gbuffer.surface,
+            &self.gbuffer.surface_view,
+        );
+        graph.import(
+            "gbuffer depth",
+            &self.gbuffer.depth,
+            &self.gbuffer.depth_view,
+        );
+        graph.import("gbuffer id", &self.gbuffer.id, &self.gbuffer.id_view);
+        // the scene draws straight into the HDR target unless it's rendered at another size
+        let scene = if self.render_scale.active() {
+            let (width, height) = self.render_scale.scene_size();
+            graph.create(
+                "scene",
+                TransientDesc {
+                    width,
+                    height,
+                    format: HDR_FORMAT,
+                },
+            );
+            "scene"
+        } else {
+            "hdr"
+        };
+        // the post chain ends in the surface, with upscaling and sharpening in between
+        // drawing into each other's inputs when they're on
+        let sharpen_input = if self.sharpen {
+            graph.create(
+                "sharpen input",
+                TransientDesc {
+                    width,
+                    height,
+                    format: output_format,
+                },
+            );
+            "sharpen input"
+        } else {
+            "surface"
+        };
+        let (post_width, post_height) = if self.upscaler.active() {
+            self.upscaler.input_size()
+        } else {
+            (width, height)
+        };
+        let post_output = if self.upscaler.active() {
+            graph.create(
+                "upscale input",
+                TransientDesc {
+                    width: post_width,
+                    height: post_height,
+                    format: output_format,
+                },
+            );
+            graph.create(
+                "easu output",
+                TransientDesc {
+                    width,
+                    height,
+                    format: output_format,
+                },
+            );
+            "upscale input"
+        } else {
+            sharpen_input
+        };
+        // FXAA goes on what the post chain would have finished in, before the upscaler
+        let fxaa_output = post_output;
+        let fxaa_luma_pass = self.antialias && app.config.render.fxaa.luma_pass;
+        let post_output = if self.antialias {
+            let desc = TransientDesc {
+                width: post_width,
+                height: post_height,
+                format: output_format,
+            };
+            graph.create("fxaa input", desc);
+            if fxaa_luma_pass {
+                graph.create("fxaa luma", desc);
+            }
+            "fxaa input"
+        } else {
+            post_output
+        };
+        // the scene's effects go between the tonemap and that, each into the next one's input
+        #[cfg(feature = "post")]
+        let post_passes = self.post_chain.active(&app.scene.post).len();
+        #[cfg(feature = "post")]
+        for input in &post_chain::INPUT_NAMES[..post_passes] {
+            graph.create(
+                input,
+                TransientDesc {
+                    width: post_width,
+                    height: post_height,
+                    format: output_format,
+                },
+            );
+        }
+        #[cfg(feature = "post")]
+        let tonemap_output = post_chain::INPUT_NAMES[..post_passes]
+            .first()
+            .copied()
+            .unwrap_or(post_output);
+        #[cfg(not(feature = "post"))]
+        let tonemap_output = post_output;
+        FrameTargets {
+            scene,
+            tonemap_output,
+            #[cfg(feature = "post")]
+            post_output,
+            fxaa_input: if fxaa_luma_pass {
+                "fxaa luma"
+            } else {
+                "fxaa input"
+            },
+            fxaa_output,
+            sharpen_input,
+            size: (width, height),
+        }
+    }
+
+    fn add_compute_pass<'a>(&'a self, graph: &mut RenderGraph<'a>, stage: ComputeStage) {
+        if !compute::has_jobs(&self.compute_jobs, stage) {
+            return;
+        }
+        let name = match stage {
+            ComputeStage::BeforeRender => "compute before render",
+            ComputeStage::AfterRender => "compute after render",
+        };
+        graph.add_pass(
+            Pass::new(name, move |encoder, _| {
+                compute::encode(encoder, &self.registry, &self.compute_jobs, stage)
+            })
+            .side_effects(),
+        );
+    }
+
+    /// What moves or is captured ahead of the draws: the boids, the GPU culling, the snow
+    /// and the environment the reflections and the path tracer look up.
+    fn add_simulation_passes<'a>(&'a self, graph: &mut RenderGraph<'a>, app: &'a GfxState) {
+        if let Some(boids) = &self.boids {
+            // before the culling and the draws read the instances it writes
+            graph.add_pass(
+                Pass::new("boids", move |encoder, _| {
+                    boids.encode(encoder, &app.gpu.queue, &app.scene.boids, app.frame.dt)
+                })
+                .side_effects()
+                .optional(),
+            );
+        }
+        if let (Some(gpu_culling), Some(mesh_draws)) = (&self.gpu_culling, &self.mesh_draws) {
+            // ahead of every pass that draws the meshes
+            graph.add_pass(
+                Pass::new("frustum cull", move |encoder, _| {
+                    gpu_culling.encode(
+                        encoder,
+                        &app.gpu.queue,
+                        mesh_draws,
+                        &self.camera_uniform.view_proj.into(),
+                    )
+                })
+                .side_effects(),
+            );
+        }
+        graph.add_pass(
+            Pass::new("snow cover", move |encoder, _| {
+                self.snow_cover.encode(encoder, app)
+            })
+            .side_effects()
+            .optional(),
+        );
+        // the path tracer's rays escape into the environment map, so it needs it too
+        if app.scene.ssr.enabled || self.path_tracing() {
+            graph.add_pass(
+                Pass::new("environment capture", move |encoder, _| {
+                    self.environment.capture(
+                        encoder,
+                        &app.gpu.queue,
+                        app.frame.camera.eye,
+                        &self.environment_pipeline,
+                        self.mirror_bind_group(),
+                    );
+                    self.reflection_probes.capture(
+                        encoder,
+                        &app.gpu.queue,
+                        &app.scene.reflection_probes,
+                        &self.environment_pipeline,
+                        self.mirror_bind_group(),
+                    );
+                })
+                .side_effects()
+                .optional(),
+            );
+        }
+    }
+
+    /// The scene into `scene`: path traced, ray marched from the SDF, or the ray cast sky
+    /// and meshes with their shadows, reflections and deferred lighting.
+    fn add_scene_passes<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        app: &'a GfxState,
+        scene: &'static str,
+        visible: &'a [bool],
+    ) {
+        if self.path_tracing() {
+            #[cfg(feature = "path_tracing")]
+            if let Some(path_tracer) = &self.path_tracer {
+                graph.add_pass(
+                    Pass::new("path tracer", move |encoder, resources| {
+                        let sky_uniform = app
+                            .scene
+                            .sky_uniform(&app.frame.sun_light, app.frame.cloud_offset);
+                        path_tracer.render(
+                            encoder,
+                            &app.gpu.queue,
+                            &app.scene.path_tracer,
+                            app.config.deterministic.seed,
+                            // moving the camera or anything in the sky starts the average over
+                            (
+                                bytemuck::bytes_of(&self.camera_uniform),
+                                bytemuck::bytes_of(&sky_uniform),
+                            ),
+                            resources.texture(scene),
+                        )
+                    })
+                    .write(scene),
+                );
+            }
+            return;
+        }
+        if self.sdf_mode {
+            graph.add_pass(
+                Pass::new("sdf scene", move |encoder, resources| {
+                    let target = resources.texture(scene);
+                    self.sdf_scene.render(
+                        encoder,
+                        &app.gpu.queue,
+                        &app.scene.sdf,
+                        &app.frame.sun_light,
+                        app.frame.time,
+                        resources.view(scene),
+                        (target.width(), target.height()),
+                    )
+                })
+                .write(scene),
+            );
+            return;
+        }
+        // meshes are lit by their own shaders, which have no debug views, so a debug view
+        // shows only the ray cast and its shadow map isn't rendered either
+        let meshes = self.view_mode == ViewMode::Final;
+        let shadows = meshes && self.shadow_map.active(&app.scene.shadows);
+        if shadows {
+            graph.import(
+                "shadow map",
+                &self.shadow_map.texture,
+                &self.shadow_map.view,
+            );
+            // blended meshes don't cast shadows
+            let opaque = self.registry.iter::<MeshDraw>().filter(|(_, mesh)| {
+                self.materials.materials[mesh.material].blend == Blend::Opaque
+                    && mesh.instance_count > 0
+            });
+            graph.add_pass(
+                Pass::new("shadow map", move |encoder, _| {
+                    self.shadow_map.render(
+                        encoder,
+                        opaque.map(|(_, mesh)| {
+                            (
+                                &self.registry[mesh.vertex_buffer],
+                                &self.registry[mesh.index_buffer],
+                                mesh.index_format,
+                                mesh.index_count,
+                                &self.registry[mesh.instance_buffer],
+                                mesh.instance_count,
+                            )
+                        }),
+                    )
+                })
+                .write("shadow map"),
+            );
+        }
+        if app.scene.water.enabled {
+            graph.import(
+                "planar reflection",
+                &self.planar_reflection.texture,
+                &self.planar_reflection.view,
+            );
+            graph.add_pass(
+                Pass::new("planar reflection", move |encoder, _| {
+                    self.planar_reflection.render(
+                        encoder,
+                        &app.gpu.queue,
+                        &app.frame.camera,
+                        0.0,
+                        &self.environment_pipeline,
+                        self.mirror_bind_group(),
+                    )
+                })
+                .write("planar reflection"),
+            );
+        }
+        let mut display = Pass::new("display", move |encoder, resources| {
+            self.render_display(encoder, app, resources.view(scene), visible)
+        })
+        .write(scene)
+        .write("gbuffer surface")
+        .write("gbuffer depth")
+        .write("gbuffer id");
+        if shadows {
+            display = display.read("shadow map");
+        }
+        if app.scene.water.enabled {
+            display = display.read("planar reflection");
+        }
+        graph.add_pass(display);
+        // the opaque meshes go into the G-buffer after the ray cast, then everything
+        // they cover is lit at once and the blended ones are drawn forward over that
+        if let Some(deferred) = self.deferred.as_ref().filter(|_| meshes) {
+            graph.import("deferred albedo", &deferred.albedo, &deferred.albedo_view);
+            graph.import(
+                "deferred material",
+                &deferred.material,
+                &deferred.material_view,
+            );
+            graph.add_pass(
+                Pass::new("deferred geometry", move |encoder, resources| {
+                    self.render_deferred_geometry(encoder, deferred, resources.view(scene), visible)
+                })
+                .read(scene)
+                .write(scene)
+                .write("gbuffer surface")
+                .write("gbuffer depth")
+                .write("gbuffer id")
+                .write("deferred albedo")
+                .write("deferred material"),
+            );
+            let lighting = Pass::new("deferred lighting", move |encoder, resources| {
+                deferred.render(
+                    encoder,
+                    resources.view(scene),
+                    self.scene_bind_group(),
+                    &self.camera_bind_group,
+                    &self.light.bind_group,
+                )
+            })
+            .read(scene)
+            .read("gbuffer surface")
+            .read("gbuffer depth")
+            .read("deferred albedo")
+            .read("deferred material")
+            .write(scene);
+            graph.add_pass(if shadows {
+                lighting.read("shadow map")
+            } else {
+                lighting
+            });
+            let blended = self
+                .registry
+                .iter::<MeshDraw>()
+                .any(|(_, mesh)| self.materials.materials[mesh.material].blend == Blend::Alpha);
+            if blended {
+                let blended_meshes = Pass::new("blended meshes", move |encoder, resources| {
+                    self.render_blended_meshes(encoder, resources.view(scene), visible)
+                })
+                .read(scene)
+                .read("gbuffer depth")
+                .write(scene);
+                graph.add_pass(if shadows {
+                    blended_meshes.read("shadow map")
+                } else {
+                    blended_meshes
+                });
+            }
+        }
+        if app.scene.ssr.enabled {
+            graph.add_pass(
+                Pass::new("ssr", move |encoder, resources| {
+                    self.ssr.render(
+                        encoder,
+                        &app.gpu.queue,
+                        &app.scene.ssr,
+                        resources.texture(scene),
+                        resources.view(scene),
+                    )
+                })
+                .read(scene)
+                .read("gbuffer surface")
+                .read("gbuffer depth")
+                .write(scene)
+                .optional(),
+            );
+        }
+    }
+
+    /// What's drawn over the scene at its size, whichever way it was rendered, and the
+    /// pixel inspector reading the result.
+    fn add_scene_effect_passes<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        app: &'a GfxState,
+        targets: FrameTargets,
+    ) {
+        let scene = targets.scene;
+        if let Some(precipitation) = self.precipitation(app) {
+            graph.add_pass(
+                Pass::new("precipitation", move |encoder, resources| {
+                    precipitation.render(encoder, app, resources.view(scene))
+                })
+                .read(scene)
+                .write(scene)
+                .optional(),
+            );
+        }
+        if let Some(particles) = self
+            .particles
+            .as_ref()
+            .filter(|_| app.scene.particles.enabled)
+        {
+            graph.add_pass(
+                Pass::new("particles", move |encoder, resources| {
+                    particles.render(encoder, app, resources.view(scene), &self.depth_buffer)
+                })
+                .read(scene)
+                .write(scene)
+                .optional(),
+            );
+        }
+        if !self.custom_draws.is_empty() {
+            graph.add_pass(
+                Pass::new("custom pipelines", move |encoder, resources| {
+                    self.render_custom_draws(encoder, resources.view(scene))
+                })
+                .read(scene)
+                .write(scene)
+                .optional(),
+            );
+        }
+        // the G-buffer is only written by the display pass, with path tracing its depth and
+        // id are whatever the last displayed frame left
+        if self.inspect_pixel {
+            graph.add_pass(
+                Pass::new("pixel inspector", move |encoder, resources| {
+                    let color = resources.texture(scene);
+                    let pixel = self
+                        .pixel_inspector
+                        .scene_pixel(targets.size, (color.width(), color.height()));
+                    if let Some(pixel) = pixel {
+                        self.pixel_inspector.encode(
+                            encoder,
+                            pixel,
+                            color,
+                            resources.texture("gbuffer depth"),
+                            resources.texture("gbuffer id"),
+                        );
+                    }
+                })
+                .read(scene)
+                .read("gbuffer depth")
+                .read("gbuffer id")
+                .side_effects(),
+            );
+        }
+    }
+
+    /// The scene brought to the HDR target's size, with the lens and weather effects and
+    /// the exposure measured on the result.
+    fn add_hdr_passes<'a>(&'a self, graph: &mut RenderGraph<'a>, app: &'a GfxState) {
+        // the resample only needs thread safe parts of the factory, so it can be encoded in
+        // parallel when that's on
+        let device: &wgpu::Device = &app.gpu.device;
+        let queue: &wgpu::Queue = &app.gpu.queue;
+        let render_scale = &self.render_scale;
+        if self.render_scale.active() {
+            graph.add_pass(
+                Pass::new_send("resample", move |encoder, resources| {
+                    render_scale.resample(
+                        device,
+                        encoder,
+                        queue,
+                        resources.view("scene"),
+                        resources.view("hdr"),
+                    )
+                })
+                .read("scene")
+                .write("hdr"),
+            );
+        }
+        #[cfg(feature = "post")]
+        if app.scene.heat_haze.enabled {
+            graph.add_pass(
+                Pass::new("heat haze", move |encoder, resources| {
+                    self.heat_haze.render(
+                        encoder,
+                        app,
+                        resources.texture("hdr"),
+                        resources.view("hdr"),
+                    )
+                })
+                .read("hdr")
+                .write("hdr")
+                .optional(),
+            );
+        }
+        #[cfg(feature = "post")]
+        let flare_intensity = app.scene.weathered_sky().flare_intensity;
+        // the flare's occlusion test reads the G-buffer depth, which the path tracer and the
+        // SDF scene don't write
+        #[cfg(feature = "post")]
+        if flare_intensity > 0.0 && !self.path_tracing() && !self.sdf_mode {
+            let config = &app.window.surface_config;
+            let aspect = config.width as f32 / config.height.max(1) as f32;
+            graph.add_pass(
+                Pass::new("flare", move |encoder, resources| {
+                    self.flare.render(
+                        encoder,
+                        &app.gpu.queue,
+                        resources.view("hdr"),
+                        flare_intensity,
+                        aspect,
+                    )
+                })
+                .read("gbuffer depth")
+                .write("hdr")
+                .optional(),
+            );
+        }
+        if let (Some(precipitation), WeatherKind::Rain) =
+            (self.precipitation(app), app.scene.weather.kind)
+        {
+            graph.add_pass(
+                Pass::new("droplets", move |encoder, resources| {
+                    precipitation.render_droplets(encoder, resources.view("hdr"))
+                })
+                .write("hdr")
+                .optional(),
+            );
+        }
+        // after everything drawn into the HDR target, so the flare and droplets glow too
+        #[cfg(feature = "post")]
+        if app.scene.bloom.enabled {
+            graph.add_pass(
+                Pass::new("bloom", move |encoder, resources| {
+                    self.bloom.encode(
+                        encoder,
+                        &app.gpu.queue,
+                        &app.scene.bloom,
+                        resources.view("hdr"),
+                    )
+                })
+                .read("hdr")
+                .write("hdr")
+                .optional(),
+            );
+        }
+        if let (Some(auto_exposure), true) = (&self.auto_exposure, app.scene.auto_exposure.enabled)
+        {
+            graph.add_pass(
+                Pass::new("auto exposure", move |encoder, resources| {
+                    let hdr = resources.texture("hdr");
+                    auto_exposure.encode(
+                        encoder,
+                        &app.gpu.queue,
+                        &ExposureInputs {
+                            settings: &app.scene.auto_exposure,
+                            dt: app.frame.dt,
+                            size: (hdr.width(), hdr.height()),
+                            target: &self.tonemap.uniform_buffer,
+                            offset: TonemapUniform::AUTO_EXPOSURE_OFFSET,
+                        },
+                    )
+                })
+                .read("hdr")
+                .side_effects()
+                .optional(),
+            );
+        }
+    }
+
+    /// From the HDR target to the surface: the tonemap, the scene's post chain, FXAA, the
+    /// upscaler, sharpening and the debug blit over all of it.
+    fn add_post_passes<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        app: &'a GfxState,
+        targets: FrameTargets,
+    ) {
+        // these only need thread safe parts of the factory, so they can be encoded in
+        // parallel when that's on
+        let device: &wgpu::Device = &app.gpu.device;
+        let queue: &wgpu::Queue = &app.gpu.queue;
+        let tonemap = &self.tonemap;
+        let upscaler = &self.upscaler;
+        let sharpener = &self.sharpener;
+        let fxaa = &self.fxaa;
+        let debug_blit = &self.debug_blit;
+        let render_settings = &app.config.render;
+        let FrameTargets {
+            tonemap_output,
+            fxaa_input,
+            fxaa_output,
+            sharpen_input,
+            size,
+            ..
+        } = targets;
+        graph.add_pass(
+            Pass::new_send("tonemap", move |encoder, resources| {
+                tonemap.render(encoder, resources.view(tonemap_output))
+            })
+            .read("hdr")
+            .write(tonemap_output),
+        );
+        #[cfg(feature = "post")]
+        {
+            let post_chain = &self.post_chain;
+            let post_passes = post_chain.active(&app.scene.post);
+            let time = app.frame.time;
+            for (position, &index) in post_passes.iter().enumerate() {
+                let settings = &app.scene.post[index];
+                let input = post_chain::INPUT_NAMES[position];
+                let output = post_chain::INPUT_NAMES
+                    .get(position + 1)
+                    .filter(|_| position + 1 < post_passes.len())
+                    .copied()
+                    .unwrap_or(targets.post_output);
+                graph.add_pass(
+                    Pass::new_send(post_chain::PASS_NAMES[index], move |encoder, resources| {
+                        post_chain.encode(
+                            device,
+                            encoder,
+                            queue,
+                            index,
+                            settings,
+                            time,
+                            resources.view(input),
+                            resources.view(output),
+                        )
+                    })
+                    .read(input)
+                    .write(output),
+                );
+            }
+        }
+        if self.antialias {
+            if fxaa_input == "fxaa luma" {
+                graph.add_pass(
+                    Pass::new_send("fxaa luma", move |encoder, resources| {
+                        fxaa.luma(
+                            device,
+                            encoder,
+                            resources.view("fxaa input"),
+                            resources.view("fxaa luma"),
+                        )
+                    })
+                    .read("fxaa input")
+                    .write("fxaa luma"),
+                );
+            }
+            graph.add_pass(
+                Pass::new_send("fxaa", move |encoder, resources| {
+                    fxaa.encode(
+                        device,
+                        encoder,
+                        queue,
+                        &render_settings.fxaa,
+                        resources.view(fxaa_input),
+                        resources.view(fxaa_output),
+                    )
+                })
+                .read(fxaa_input)
+                .write(fxaa_output),
+            );
+        }
+        if self.upscaler.active() {
+            graph.add_pass(
+                Pass::new_send("easu", move |encoder, resources| {
+                    upscaler.easu(
+                        device,
+                        encoder,
+                        queue,
+                        &render_settings.upscale,
+                        resources.view("upscale input"),
+                        resources.view("easu output"),
+                    )
+                })
+                .read("upscale input")
+                .write("easu output"),
+            );
+            graph.add_pass(
+                Pass::new_send("rcas", move |encoder, resources| {
+                    upscaler.rcas(
+                        device,
+                        encoder,
+                        resources.view("easu output"),
+                        resources.view(sharpen_input),
+                    )
+                })
+                .read("easu output")
+                .write(sharpen_input),
+            );
+        }
+        if self.sharpen {
+            graph.add_pass(
+                Pass::new_send("sharpen", move |encoder, resources| {
+                    sharpener.encode(
+                        device,
+                        encoder,
+                        queue,
+                        &render_settings.sharpen,
+                        resources.view("sharpen input"),
+                        resources.view("surface"),
+                    )
+                })
+                .read("sharpen input")
+                .write("surface"),
+            );
+        }
+        // the surface can't be read from, and a texture from an earlier frame may be gone
+        let debug_texture = self
+            .debug_texture
+            .filter(|name| *name != "surface" && graph.has_texture(name));
+        if let Some(debug_texture) = debug_texture {
+            graph.add_pass(
+                Pass::new_send("debug blit", move |encoder, resources| {
+                    debug_blit.encode(
+                        device,
+                        encoder,
+                        queue,
+                        resources.view(debug_texture),
+                        resources.view("surface"),
+                        size,
+                    )
+                })
+                .read(debug_texture)
+                .write("surface"),
+            );
+        }
+    }
+
+    /// The overlay, the shortcut page, the console and any shader error, drawn over the
+    /// finished surface.
+    #[cfg(feature = "ui")]
+    fn add_ui_pass<'a>(&'a self, graph: &mut RenderGraph<'a>, app: &'a GfxState, size: (u32, u32)) {
+        if !(self.debug_overlay.visible
+            || app.shortcuts.page_visible
+            || app.console.open
+            || app.shader_error.is_some())
+        {
+            return;
+        }
+        let (width, height) = size;
+        graph.add_pass(
+            Pass::new("debug ui", move |encoder, resources| {
+                if self.debug_overlay.visible {
+                    self.debug_overlay.draw(&self.text, app, self);
+                }
+                if let Some(shader_error) = &app.shader_error {
+                    debug_overlay::draw_error(
+                        &self.text,
+                        shader_error,
+                        width as f32,
+                        height as f32,
+                    );
+                }
+                if app.shortcuts.page_visible {
+                    app.shortcuts.draw_page(&self.text, width as f32);
+                }
+                // drops over the top 40%, covering the others
+                if app.console.open {
+                    app.console
+                        .draw(&self.text, width as f32, height as f32 * 0.4);
+                }
+                self.text.encode(
+                    &app.gpu.device,
+                    encoder,
+                    &app.gpu.queue,
+                    &mut self.buffer_pool.borrow_mut(),
+                    resources.view("surface"),
+                    size,
+                )
+            })
+            .write("surface"),
         );
+    }
+
+    fn precipitation(&self, app: &GfxState) -> Option<&Precipitation> {
+        self.precipitation
+            .as_ref()
+            .filter(|_| app.scene.weather.precipitating())
+    }
+
+    /// Closes the frame's counters; in benchmark mode anything over budget is fatal.
+    fn finish_stats(&self, render: &RenderConfig) -> error::Result<()> {
+        let stats = frame_stats::take();
+        self.stats.set(stats);
+        profiler::plot(PlotName::DrawCalls, stats.draw_calls as f64);
+        if render.benchmark {
+            let over = stats.over_budget(&render.stats_budget);
+            if !over.is_empty() {
+                return Err(error::Error::OverBudget(over));
+            }
+        }
+        Ok(())
+    }
 
-        let command_buffer = encoder.finish();
-        app.queue.submit(Some(command_buffer));
-        frame.present();
+    /// Moves the selection on to the next of last frame's optional passes.
+    pub fn select_next_pass(&mut self) {
+        let passes = self.optional_passes.borrow();
+        let next = match self.selected_pass {
+            Some(selected) => passes
+                .iter()
+                .position(|pass| *pass == selected)
+                .map_or(0, |index| index + 1),
+            None => 0,
+        };
+        self.selected_pass = passes.get(next % passes.len().max(1)).copied();
+        match self.selected_pass {
+            Some(pass) => println!(
+                "Selected pass: {} ({})",
+                pass,
+                if self.disabled_passes.contains(pass) {
+                    "off"
+                } else {
+                    "on"
+                }
+            ),
+            None => println!("No optional passes to select"),
+        }
+    }
+
+    /// Switches the selected pass off, or back on.
+    pub fn toggle_selected_pass(&mut self) {
+        let Some(pass) = self.selected_pass else {
+            println!("No pass selected");
+            return;
+        };
+        if !self.disabled_passes.remove(pass) {
+            self.disabled_passes.insert(pass);
+        }
+        println!(
+            "Pass {}: {}",
+            pass,
+            if self.disabled_passes.contains(pass) {
+                "off"
+            } else {
+                "on"
+            }
+        );
+    }
+
+    /// Steps the texture shown instead of the final image through last frame's graph
+    /// textures, then back to the final image.
+    pub fn cycle_debug_texture(&mut self) {
+        let textures: Vec<_> = self
+            .graph_textures
+            .borrow()
+            .iter()
+            .copied()
+            .filter(|name| *name != "surface")
+            .collect();
+        let next = match self.debug_texture {
+            Some(current) => textures
+                .iter()
+                .position(|name| *name == current)
+                .map(|index| index + 1),
+            None => Some(0),
+        };
+        self.debug_texture = next.and_then(|index| textures.get(index).copied());
+        println!(
+            "Showing texture: {}",
+            self.debug_texture.unwrap_or("final image")
+        );
+    }
+
+    /// Reads `range` of one of the factory's buffers back to the CPU, for tools, tests and
+    /// the console; see `readback::read_buffer`. The buffer needs COPY_SRC.
+    #[cfg(any(feature = "ui", test))]
+    pub fn read_buffer(
+        &self,
+        app: &GfxState,
+        buffer: crate::registry::BufferHandle,
+        range: std::ops::Range<u64>,
+    ) -> impl std::future::Future<Output = anyhow::Result<Vec<u8>>> + Send + 'static {
+        crate::readback::read_buffer(
+            &app.gpu.device,
+            &app.gpu.queue,
+            &self.registry[buffer],
+            range,
+        )
+    }
+
+    /// Moves the render scale toward the target frame rate, judged by the GPU's frame
+    /// time when it can be measured and by the time between frames otherwise.
+    pub fn adapt_resolution(
+        &mut self,
+        device: &wgpu::Device,
+        surface_config: &wgpu::SurfaceConfiguration,
+        settings: &DynamicResolutionSettings,
+        dt: f32,
+    ) {
+        let frame_ms = self
+            .gpu_timer
+            .as_ref()
+            .and_then(GpuTimer::latest_ms)
+            .unwrap_or(dt * 1000.0);
+        if self.dynamic_resolution.update(settings, frame_ms, dt) {
+            self.resize(device, surface_config);
+        }
     }
 }
 
@@ -222,4 +2780,37 @@ impl GpuFactory {
 struct TheFirstUniformBuffer {
     width: u32,
     height: u32,
+    // < 1.0 only for transparent windows
+    sky_alpha: f32,
+    premultiplied: u32,
+    view_mode: u32,
+    // distance shown as white in the depth view
+    depth_range: f32,
+    // seconds since start and since the previous frame, for shaders that animate
+    time: f32,
+    delta_time: f32,
+    // Shadertoy's iMouse, see `Mouse`
+    mouse: [f32; 4],
+}
+
+impl TheFirstUniformBuffer {
+    fn new(app: &GfxState, view_mode: ViewMode) -> Self {
+        let surface_config = &app.window.surface_config;
+        Self {
+            width: surface_config.width,
+            height: surface_config.height,
+            sky_alpha: if app.config.window.transparent {
+                app.config.window.sky_opacity.clamp(0.0, 1.0)
+            } else {
+                1.0
+            },
+            premultiplied: (surface_config.alpha_mode != wgpu::CompositeAlphaMode::PostMultiplied)
+                as u32,
+            view_mode: view_mode as u32,
+            depth_range: app.frame.camera.zfar,
+            time: app.frame.time,
+            delta_time: app.frame.dt,
+            mouse: app.frame.mouse.uniform(),
+        }
+    }
 }
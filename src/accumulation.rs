@@ -0,0 +1,273 @@
+use std::{
+    cell::Cell,
+    hash::{DefaultHasher, Hash, Hasher},
+};
+
+use serde::{Deserialize, Serialize};
+use wgpu::{
+    BindGroup, BindGroupLayout, Buffer, ComputePipeline, PipelineCompilationOptions, Texture,
+    TextureView,
+};
+
+use crate::{frame_stats, hot_reload::wgsl, tonemap::HDR_FORMAT};
+
+const HISTORY_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba32Float;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AccumulationSettings {
+    pub enabled: bool,
+    /// frames averaged before it turns into a moving average over this many
+    pub max_frames: u32,
+}
+
+impl Default for AccumulationSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_frames: 4096,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct AccumulationParams {
+    weight: f32,
+    _pad: [f32; 3],
+}
+
+/// Averages a texture over the frames since the last reset, for renderers that converge
+/// while nothing changes. Whatever invalidates the image is hashed into a key by the
+/// caller, a different key than last frame starts over.
+pub struct Accumulator {
+    params_buffer: Buffer,
+    // ping-ponged, one is read while the other is written
+    history: [Texture; 2],
+    /// the running average, in the HDR format so it can be copied into the scene target
+    pub result: Texture,
+    bind_group_layout: BindGroupLayout,
+    // [i] reads history[i] and writes history[1 - i]
+    bind_groups: [BindGroup; 2],
+    pipeline: ComputePipeline,
+    read: Cell<usize>,
+    frames: Cell<u32>,
+    key: Cell<u64>,
+}
+
+impl Accumulator {
+    pub fn new(device: &wgpu::Device, current: &TextureView, width: u32, height: u32) -> Self {
+        let code = wgsl!("accumulate.wgsl");
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("accumulation shader"),
+            source: wgpu::ShaderSource::Wgsl(code),
+        });
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("accumulation params"),
+            size: std::mem::size_of::<AccumulationParams>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let (history, result) = Self::create_targets(device, width, height);
+
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let storage_texture_entry = |binding, format| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::StorageTexture {
+                access: wgpu::StorageTextureAccess::WriteOnly,
+                format,
+                view_dimension: wgpu::TextureViewDimension::D2,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("accumulation bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                texture_entry(1),
+                texture_entry(2),
+                storage_texture_entry(3, HISTORY_FORMAT),
+                storage_texture_entry(4, HDR_FORMAT),
+            ],
+        });
+        let bind_groups = Self::create_bind_groups(
+            device,
+            &bind_group_layout,
+            &params_buffer,
+            current,
+            &history,
+            &result,
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("accumulation pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("accumulation pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "accumulate",
+            compilation_options: PipelineCompilationOptions::default(),
+        });
+
+        Self {
+            params_buffer,
+            history,
+            result,
+            bind_group_layout,
+            bind_groups,
+            pipeline,
+            read: Cell::new(0),
+            frames: Cell::new(0),
+            key: Cell::new(0),
+        }
+    }
+
+    fn create_targets(device: &wgpu::Device, width: u32, height: u32) -> ([Texture; 2], Texture) {
+        let create = |label, format| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width: width.max(1),
+                    height: height.max(1),
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::STORAGE_BINDING
+                    | wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_SRC,
+                view_formats: &[],
+            })
+        };
+        (
+            [0, 1].map(|_| create("accumulation history", HISTORY_FORMAT)),
+            create("accumulation result", HDR_FORMAT),
+        )
+    }
+
+    fn create_bind_groups(
+        device: &wgpu::Device,
+        layout: &BindGroupLayout,
+        params_buffer: &Buffer,
+        current: &TextureView,
+        history: &[Texture; 2],
+        result: &Texture,
+    ) -> [BindGroup; 2] {
+        let views = history
+            .each_ref()
+            .map(|texture| texture.create_view(&Default::default()));
+        let result_view = result.create_view(&Default::default());
+        [0, 1].map(|read| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("accumulation bind group"),
+                layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: params_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(current),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::TextureView(&views[read]),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: wgpu::BindingResource::TextureView(&views[1 - read]),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 4,
+                        resource: wgpu::BindingResource::TextureView(&result_view),
+                    },
+                ],
+            })
+        })
+    }
+
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        current: &TextureView,
+        width: u32,
+        height: u32,
+    ) {
+        (self.history, self.result) = Self::create_targets(device, width, height);
+        self.bind_groups = Self::create_bind_groups(
+            device,
+            &self.bind_group_layout,
+            &self.params_buffer,
+            current,
+            &self.history,
+            &self.result,
+        );
+        self.reset();
+    }
+
+    /// Starts over with the next frame.
+    pub fn reset(&self) {
+        self.frames.set(0);
+    }
+
+    /// Folds this frame's `current` into the average in `result`. `key` stands for
+    /// everything the image depends on; when it differs from last frame's, the average
+    /// restarts from this frame.
+    pub fn encode(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        queue: &wgpu::Queue,
+        settings: &AccumulationSettings,
+        key: impl Hash,
+    ) {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let key = hasher.finish();
+        if key != self.key.replace(key) || !settings.enabled {
+            self.reset();
+        }
+        let frames = (self.frames.get() + 1).min(settings.max_frames.max(1));
+        self.frames.set(frames);
+        let params = AccumulationParams {
+            weight: 1.0 / frames as f32,
+            _pad: [0.0; 3],
+        };
+        frame_stats::write_buffer(queue, &self.params_buffer, 0, bytemuck::bytes_of(&params));
+
+        let read = self.read.get();
+        self.read.set(1 - read);
+        let (width, height) = (self.result.width(), self.result.height());
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("accumulation pass"),
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.bind_groups[read], &[]);
+        pass.dispatch_workgroups(width.div_ceil(8), height.div_ceil(8), 1);
+    }
+}
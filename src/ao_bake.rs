@@ -0,0 +1,126 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Config,
+    hot_reload::wgsl,
+    lightmap::{write_file, Baker},
+    scene::Scene,
+    static_geometry::{StaticGeometryUniform, MAX_STATIC_BOXES},
+};
+
+/// Baked ambient occlusion of the static geometry. One texture holds the ground square
+/// (the same one the lightmap covers) on top and below it an atlas with a tile per box
+/// face, a row per box. Occlusion is in r.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AoBakeSettings {
+    pub enabled: bool,
+    /// baked file, relative to the crate directory
+    pub path: PathBuf,
+    /// texels along each side of the ground square
+    pub ground_resolution: u32,
+    /// texels along each side of a box face tile
+    pub face_resolution: u32,
+    /// hemisphere rays per texel
+    pub samples: u32,
+    /// meters, anything further away doesn't occlude
+    pub radius: f32,
+}
+
+impl Default for AoBakeSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            path: PathBuf::from("asset/ao.bin"),
+            ground_resolution: 256,
+            face_resolution: 32,
+            samples: 128,
+            radius: 4.0,
+        }
+    }
+}
+
+impl AoBakeSettings {
+    /// ground resolution, face resolution, in use; as packed into the static geometry
+    /// uniform. The texture size follows from them.
+    pub fn uniform(&self, baked: bool) -> [f32; 4] {
+        [
+            self.ground_resolution as f32,
+            self.face_resolution as f32,
+            (self.enabled && baked) as u32 as f32,
+            0.0,
+        ]
+    }
+
+    fn size(&self) -> (u32, u32) {
+        let width = self.ground_resolution.max(6 * self.face_resolution);
+        let height = self.ground_resolution + MAX_STATIC_BOXES as u32 * self.face_resolution;
+        (width, height)
+    }
+}
+
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct AoBakeParams {
+    center: [f32; 2],
+    extent: f32,
+    samples: u32,
+    ground_resolution: u32,
+    face_resolution: u32,
+    radius: f32,
+    _pad: f32,
+}
+
+/// `cargo run -- bake-ao`: bakes the ambient occlusion of the configured scene's static
+/// geometry without opening a window.
+pub fn bake_command() -> anyhow::Result<()> {
+    let config = Config::load();
+    let scene = Scene::load(&config.scene);
+    pollster::block_on(bake(&scene))
+}
+
+async fn bake(scene: &Scene) -> anyhow::Result<()> {
+    let settings = &scene.ao;
+    let baker = Baker::new().await?;
+    let (width, height) = settings.size();
+    if width > baker.max_size() || height > baker.max_size() {
+        anyhow::bail!("{}x{} AO atlas is too big for the adapter", width, height);
+    }
+    let params = AoBakeParams {
+        center: scene.lightmap.center,
+        extent: scene.lightmap.extent,
+        samples: settings.samples.max(1),
+        ground_resolution: settings.ground_resolution,
+        face_resolution: settings.face_resolution,
+        radius: settings.radius,
+        _pad: 0.0,
+    };
+    let params_buffer = baker.uniform("ao bake params", bytemuck::bytes_of(&params));
+    let geometry_buffer = baker.uniform(
+        "ao bake geometry",
+        bytemuck::bytes_of(&StaticGeometryUniform::new(&scene.static_boxes)),
+    );
+
+    println!(
+        "Baking AO into {}x{}, {} samples, {} static boxes",
+        width,
+        height,
+        params.samples,
+        scene.static_boxes.len()
+    );
+    let code = wgsl!("ao_bake.wgsl");
+    let texels = baker.run(
+        "ao bake",
+        &code,
+        &[&params_buffer, &geometry_buffer],
+        width,
+        height,
+    )?;
+
+    let path = Scene::resolve_path(&settings.path);
+    write_file(&path, width, height, &texels)?;
+    println!("AO written to {}", path.display());
+    Ok(())
+}
@@ -0,0 +1,302 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        mpsc::{self, Receiver, SyncSender},
+        Arc, Condvar, Mutex,
+    },
+};
+
+use anyhow::bail;
+
+use crate::profiler::profile_scope;
+
+// decoded assets waiting for their upload; workers block past this, which bounds how much
+// decoded memory sits around when uploads are behind
+const DECODED_QUEUE_DEPTH: usize = 2;
+const MAX_WORKERS: usize = 4;
+
+/// Which assets go first when several are waiting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    // seen in the first frames, decoded and uploaded before everything else
+    Visible,
+    Background,
+}
+
+/// Texels decoded on a worker, tightly packed rows from the top.
+pub struct DecodedTexture {
+    pub width: u32,
+    pub height: u32,
+    pub format: wgpu::TextureFormat,
+    pub texels: Vec<u8>,
+}
+
+/// An asset whose upload finished, or whose decode failed.
+pub struct LoadedAsset {
+    pub name: String,
+    pub texture: anyhow::Result<wgpu::Texture>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoadProgress {
+    // uploaded or failed
+    pub done: usize,
+    pub total: usize,
+}
+
+impl LoadProgress {
+    pub fn finished(&self) -> bool {
+        self.done == self.total
+    }
+
+    pub fn fraction(&self) -> f32 {
+        if self.total == 0 {
+            1.0
+        } else {
+            self.done as f32 / self.total as f32
+        }
+    }
+}
+
+type Decode = Box<dyn FnOnce() -> anyhow::Result<DecodedTexture> + Send>;
+
+struct Job {
+    name: String,
+    decode: Decode,
+}
+
+#[derive(Default)]
+struct Jobs {
+    visible: VecDeque<Job>,
+    background: VecDeque<Job>,
+    closed: bool,
+}
+
+#[derive(Default)]
+struct Shared {
+    jobs: Mutex<Jobs>,
+    available: Condvar,
+}
+
+// a texture part way through its upload
+struct Upload {
+    name: String,
+    decoded: DecodedTexture,
+    texture: wgpu::Texture,
+    next_row: u32,
+}
+
+/// Decodes assets on a small pool of worker threads and uploads them a few rows at a time
+/// from `upload`, so a big texture arriving never costs a frame more than `upload_budget`
+/// bytes of copies. Dropping it lets the workers finish their current decode and exit.
+pub struct AssetLoader {
+    shared: Arc<Shared>,
+    decoded: Receiver<(String, anyhow::Result<DecodedTexture>)>,
+    uploading: VecDeque<Upload>,
+    // bytes of texels written per call of `upload`
+    pub upload_budget: u64,
+    requested: usize,
+    done: usize,
+}
+
+impl AssetLoader {
+    pub fn new(upload_budget: u64) -> Self {
+        let shared = Arc::new(Shared::default());
+        let (sender, decoded) = mpsc::sync_channel(DECODED_QUEUE_DEPTH);
+        // leave a core to the render thread
+        let workers = std::thread::available_parallelism()
+            .map_or(1, |n| n.get().saturating_sub(1))
+            .clamp(1, MAX_WORKERS);
+        for index in 0..workers {
+            let shared = shared.clone();
+            let sender = sender.clone();
+            std::thread::Builder::new()
+                .name(format!("asset decode {}", index))
+                .spawn(move || work(&shared, &sender))
+                .expect("Failed to spawn an asset decode thread");
+        }
+        Self {
+            shared,
+            decoded,
+            uploading: VecDeque::new(),
+            upload_budget,
+            requested: 0,
+            done: 0,
+        }
+    }
+
+    /// Queues `decode` to run on a worker. Visible requests jump every background one still
+    /// waiting, requests of the same priority go in order.
+    pub fn request(
+        &mut self,
+        name: &str,
+        priority: Priority,
+        decode: impl FnOnce() -> anyhow::Result<DecodedTexture> + Send + 'static,
+    ) {
+        let job = Job {
+            name: name.to_string(),
+            decode: Box::new(decode),
+        };
+        let mut jobs = self.shared.jobs.lock().unwrap();
+        match priority {
+            Priority::Visible => jobs.visible.push_back(job),
+            Priority::Background => jobs.background.push_back(job),
+        }
+        self.requested += 1;
+        self.shared.available.notify_one();
+    }
+
+    pub fn progress(&self) -> LoadProgress {
+        LoadProgress {
+            done: self.done,
+            total: self.requested,
+        }
+    }
+
+    /// Call once a frame. Writes up to `upload_budget` bytes of decoded texels, oldest
+    /// upload first, and returns whatever finished.
+    pub fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<LoadedAsset> {
+        profile_scope!("upload assets");
+        let mut loaded = vec![];
+        let mut budget = self.upload_budget;
+        while budget > 0 {
+            if self.uploading.is_empty() {
+                match self.decoded.try_recv() {
+                    Ok((name, Ok(decoded))) => {
+                        let texture = create_texture(device, &name, &decoded);
+                        self.uploading.push_back(Upload {
+                            name,
+                            decoded,
+                            texture,
+                            next_row: 0,
+                        });
+                    }
+                    Ok((name, Err(e))) => {
+                        loaded.push(LoadedAsset {
+                            name,
+                            texture: Err(e),
+                        });
+                        continue;
+                    }
+                    Err(_) => break,
+                }
+            }
+            let Some(upload) = self.uploading.front_mut() else {
+                break;
+            };
+            let decoded = &upload.decoded;
+            let row_size = decoded.texels.len() as u64 / decoded.height as u64;
+            // at least a row, or a texture with rows over the budget would never finish
+            let rows = (budget / row_size.max(1))
+                .clamp(1, (decoded.height - upload.next_row) as u64) as u32;
+            let start = (upload.next_row as u64 * row_size) as usize;
+            let end = start + (rows as u64 * row_size) as usize;
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &upload.texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d {
+                        x: 0,
+                        y: upload.next_row,
+                        z: 0,
+                    },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                &decoded.texels[start..end],
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(row_size as u32),
+                    rows_per_image: Some(rows),
+                },
+                wgpu::Extent3d {
+                    width: decoded.width,
+                    height: rows,
+                    depth_or_array_layers: 1,
+                },
+            );
+            upload.next_row += rows;
+            budget = budget.saturating_sub(rows as u64 * row_size);
+            if upload.next_row == decoded.height {
+                let upload = self.uploading.pop_front().unwrap();
+                loaded.push(LoadedAsset {
+                    name: upload.name,
+                    texture: Ok(upload.texture),
+                });
+            }
+        }
+        self.done += loaded.len();
+        loaded
+    }
+}
+
+impl Drop for AssetLoader {
+    fn drop(&mut self) {
+        let mut jobs = self.shared.jobs.lock().unwrap();
+        jobs.closed = true;
+        jobs.visible.clear();
+        jobs.background.clear();
+        self.shared.available.notify_all();
+    }
+}
+
+fn work(shared: &Shared, sender: &SyncSender<(String, anyhow::Result<DecodedTexture>)>) {
+    loop {
+        let job = {
+            let mut jobs = shared.jobs.lock().unwrap();
+            loop {
+                if jobs.closed {
+                    return;
+                }
+                if let Some(job) = jobs
+                    .visible
+                    .pop_front()
+                    .or_else(|| jobs.background.pop_front())
+                {
+                    break job;
+                }
+                jobs = shared.available.wait(jobs).unwrap();
+            }
+        };
+        let decoded = {
+            profile_scope!("decode", &job.name);
+            (job.decode)()
+        };
+        let decoded = decoded.and_then(|decoded| {
+            let texel_size = decoded.format.block_copy_size(None).unwrap_or(0);
+            if decoded.width == 0
+                || decoded.height == 0
+                || decoded.texels.len() != (decoded.width * decoded.height * texel_size) as usize
+            {
+                bail!(
+                    "{} bytes don't make a {}x{} {:?} texture",
+                    decoded.texels.len(),
+                    decoded.width,
+                    decoded.height,
+                    decoded.format
+                );
+            }
+            Ok(decoded)
+        });
+        // the loader is gone
+        if sender.send((job.name, decoded)).is_err() {
+            return;
+        }
+    }
+}
+
+fn create_texture(device: &wgpu::Device, name: &str, decoded: &DecodedTexture) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(name),
+        size: wgpu::Extent3d {
+            width: decoded.width,
+            height: decoded.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: decoded.format,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    })
+}
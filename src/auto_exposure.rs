@@ -0,0 +1,262 @@
+use serde::{Deserialize, Serialize};
+use wgpu::{
+    util::DeviceExt, BindGroup, BindGroupLayout, Buffer, ComputePipeline,
+    PipelineCompilationOptions, TextureView,
+};
+
+use crate::{frame_stats, hot_reload::wgsl, GfxState};
+
+/// Per scene auto exposure settings, the manual exposure acts as compensation on top.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutoExposureSettings {
+    pub enabled: bool,
+    /// adaptation rate, 1/s
+    pub speed: f32,
+    pub min_ev: f32,
+    pub max_ev: f32,
+    pub low_percentile: f32,
+    pub high_percentile: f32,
+}
+
+impl Default for AutoExposureSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            speed: 1.5,
+            min_ev: -6.0,
+            max_ev: 6.0,
+            low_percentile: 0.5,
+            high_percentile: 0.95,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct AutoExposureParams {
+    min_log_lum: f32,
+    log_lum_range: f32,
+    dt: f32,
+    speed: f32,
+    min_ev: f32,
+    max_ev: f32,
+    low_percent: f32,
+    high_percent: f32,
+}
+
+const HISTOGRAM_BINS: u64 = 256;
+const MIN_LOG_LUM: f32 = -10.0;
+const MAX_LOG_LUM: f32 = 6.0;
+
+/// What one frame's exposure is adapted from and written to.
+pub struct ExposureInputs<'a> {
+    pub settings: &'a AutoExposureSettings,
+    pub dt: f32,
+    /// of the HDR target
+    pub size: (u32, u32),
+    /// the exposure is copied here at `offset`, a field of the tonemap uniform
+    pub target: &'a Buffer,
+    pub offset: u64,
+}
+
+/// Luminance histogram of the HDR target built in a compute pass, reduced to an
+/// adapted exposure that is copied straight into the tonemap uniform on the GPU.
+pub struct AutoExposure {
+    params_buffer: Buffer,
+    histogram_buffer: Buffer,
+    pub state_buffer: Buffer,
+    bind_group_layout: BindGroupLayout,
+    bind_group: BindGroup,
+    histogram_pipeline: ComputePipeline,
+    average_pipeline: ComputePipeline,
+}
+
+impl AutoExposure {
+    /// None when compute isn't available under the granted limits (webgl2)
+    pub fn new(app: &GfxState, hdr_view: &TextureView) -> Option<Self> {
+        if !app.gpu.budget.compute {
+            println!("Auto exposure disabled: no compute support");
+            return None;
+        }
+        let device = &app.gpu.device;
+        let code = wgsl!("auto_exposure.wgsl");
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("auto exposure shader"),
+            source: wgpu::ShaderSource::Wgsl(code),
+        });
+
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("auto exposure params"),
+            size: std::mem::size_of::<AutoExposureParams>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let histogram_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("luminance histogram"),
+            size: HISTOGRAM_BINS * 4,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let state_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("exposure state"),
+            contents: bytemuck::cast_slice(&[0.0f32; 4]),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        });
+
+        let storage_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("auto exposure bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                storage_entry(1),
+                storage_entry(2),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = Self::create_bind_group(
+            device,
+            &bind_group_layout,
+            hdr_view,
+            &histogram_buffer,
+            &state_buffer,
+            &params_buffer,
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("auto exposure pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let make_pipeline = |entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point,
+                compilation_options: PipelineCompilationOptions::default(),
+            })
+        };
+
+        Some(Self {
+            histogram_pipeline: make_pipeline("build_histogram"),
+            average_pipeline: make_pipeline("average_histogram"),
+            params_buffer,
+            histogram_buffer,
+            state_buffer,
+            bind_group_layout,
+            bind_group,
+        })
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &BindGroupLayout,
+        hdr_view: &TextureView,
+        histogram_buffer: &Buffer,
+        state_buffer: &Buffer,
+        params_buffer: &Buffer,
+    ) -> BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("auto exposure bind group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(hdr_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: histogram_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: state_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: params_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    /// The HDR target was recreated, point at the new one.
+    pub fn resize(&mut self, device: &wgpu::Device, hdr_view: &TextureView) {
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.bind_group_layout,
+            hdr_view,
+            &self.histogram_buffer,
+            &self.state_buffer,
+            &self.params_buffer,
+        );
+    }
+
+    /// Builds the histogram of this frame's HDR target, adapts the exposure and copies
+    /// it to where `inputs` points.
+    pub fn encode(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        queue: &wgpu::Queue,
+        inputs: &ExposureInputs,
+    ) {
+        let ExposureInputs {
+            settings,
+            dt,
+            size,
+            target,
+            offset,
+        } = *inputs;
+        let params = AutoExposureParams {
+            min_log_lum: MIN_LOG_LUM,
+            log_lum_range: MAX_LOG_LUM - MIN_LOG_LUM,
+            dt,
+            speed: settings.speed,
+            min_ev: settings.min_ev,
+            max_ev: settings.max_ev,
+            low_percent: settings.low_percentile,
+            high_percent: settings.high_percentile,
+        };
+        frame_stats::write_buffer(queue, &self.params_buffer, 0, bytemuck::bytes_of(&params));
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("auto exposure pass"),
+                timestamp_writes: None,
+            });
+            compute_pass.set_bind_group(0, &self.bind_group, &[]);
+            compute_pass.set_pipeline(&self.histogram_pipeline);
+            compute_pass.dispatch_workgroups(size.0.div_ceil(16), size.1.div_ceil(16), 1);
+            compute_pass.set_pipeline(&self.average_pipeline);
+            compute_pass.dispatch_workgroups(1, 1, 1);
+        }
+        encoder.copy_buffer_to_buffer(&self.state_buffer, 0, target, offset, 4);
+    }
+}
@@ -0,0 +1,138 @@
+use wgpu::{BindGroup, BindGroupLayout, Buffer, Sampler, ShaderStages, TextureView};
+
+/// A bind group's bindings, each given once with its resource, for both the layout and the
+/// bind group:
+///
+///     let (layout, bind_group) = BindingsBuilder::new()
+///         .uniform(0, ShaderStages::VERTEX, &camera_buffer)
+///         .texture(1, ShaderStages::FRAGMENT, &view)
+///         .sampler(2, ShaderStages::FRAGMENT, &sampler)
+///         .build(device, "camera");
+///
+/// A bind group rebuilt later over new resources reuses the first layout with
+/// `bind_group`.
+#[derive(Default)]
+pub struct BindingsBuilder<'a> {
+    layout_entries: Vec<wgpu::BindGroupLayoutEntry>,
+    entries: Vec<wgpu::BindGroupEntry<'a>>,
+}
+
+// the setters are for the factory and embedding programs, the app itself only uses some
+impl<'a> BindingsBuilder<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn uniform(self, binding: u32, visibility: ShaderStages, buffer: &'a Buffer) -> Self {
+        self.buffer(
+            binding,
+            visibility,
+            wgpu::BufferBindingType::Uniform,
+            buffer,
+        )
+    }
+
+    /// Read write storage can't be seen from the vertex stage.
+    pub fn storage(
+        self,
+        binding: u32,
+        visibility: ShaderStages,
+        buffer: &'a Buffer,
+        read_only: bool,
+    ) -> Self {
+        self.buffer(
+            binding,
+            visibility,
+            wgpu::BufferBindingType::Storage { read_only },
+            buffer,
+        )
+    }
+
+    /// A filterable 2D float texture.
+    pub fn texture(self, binding: u32, visibility: ShaderStages, view: &'a TextureView) -> Self {
+        self.push(
+            binding,
+            visibility,
+            wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            wgpu::BindingResource::TextureView(view),
+        )
+    }
+
+    /// A filtering sampler.
+    pub fn sampler(self, binding: u32, visibility: ShaderStages, sampler: &'a Sampler) -> Self {
+        self.push(
+            binding,
+            visibility,
+            wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            wgpu::BindingResource::Sampler(sampler),
+        )
+    }
+
+    pub fn layout(&self, device: &wgpu::Device, label: &str) -> BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some(&format!("{} layout", label)),
+            entries: &self.layout_entries,
+        })
+    }
+
+    /// A bind group of these resources with `layout`, which has to match them.
+    pub fn bind_group(
+        &self,
+        device: &wgpu::Device,
+        label: &str,
+        layout: &BindGroupLayout,
+    ) -> BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(label),
+            layout,
+            entries: &self.entries,
+        })
+    }
+
+    pub fn build(&self, device: &wgpu::Device, label: &str) -> (BindGroupLayout, BindGroup) {
+        let layout = self.layout(device, label);
+        let bind_group = self.bind_group(device, label, &layout);
+        (layout, bind_group)
+    }
+
+    fn buffer(
+        self,
+        binding: u32,
+        visibility: ShaderStages,
+        ty: wgpu::BufferBindingType,
+        buffer: &'a Buffer,
+    ) -> Self {
+        self.push(
+            binding,
+            visibility,
+            wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            buffer.as_entire_binding(),
+        )
+    }
+
+    fn push(
+        mut self,
+        binding: u32,
+        visibility: ShaderStages,
+        ty: wgpu::BindingType,
+        resource: wgpu::BindingResource<'a>,
+    ) -> Self {
+        self.layout_entries.push(wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty,
+            count: None,
+        });
+        self.entries
+            .push(wgpu::BindGroupEntry { binding, resource });
+        self
+    }
+}
@@ -0,0 +1,369 @@
+use serde::{Deserialize, Serialize};
+use wgpu::{
+    BindGroup, BindGroupLayout, Buffer, PipelineCompilationOptions, RenderPipeline, Sampler,
+    Texture, TextureView,
+};
+
+#[cfg(feature = "ui")]
+use crate::console::{console_var, Console};
+use crate::{
+    frame_stats::{self, CountedPass},
+    hot_reload::wgsl,
+    tonemap::HDR_FORMAT,
+};
+
+/// Mips of the bloom chain at most, the first at half the HDR target's size.
+const MAX_MIPS: u32 = 6;
+
+/// Light bleeding out of the brightest parts of the HDR image, read every frame.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BloomSettings {
+    pub enabled: bool,
+    /// HDR brightness where the bloom starts, before exposure
+    pub threshold: f32,
+    /// width of the soft ramp around the threshold, 0 for a hard cut
+    pub knee: f32,
+    /// how much of the blurred light is added back
+    pub intensity: f32,
+    /// spread of each upsample step, in texels of the mip it reads
+    pub radius: f32,
+}
+
+impl Default for BloomSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold: 1.0,
+            knee: 0.5,
+            intensity: 0.05,
+            radius: 1.0,
+        }
+    }
+}
+
+impl BloomSettings {
+    /// `bloom.*` console variables, written to the uniform every frame.
+    #[cfg(feature = "ui")]
+    pub fn register_console(console: &mut Console) {
+        console_var!(console, "bloom.enabled", "bool", scene.bloom.enabled);
+        console_var!(
+            console,
+            "bloom.threshold",
+            "HDR value",
+            scene.bloom.threshold
+        );
+        console_var!(console, "bloom.knee", "0 hard .. soft", scene.bloom.knee);
+        console_var!(console, "bloom.intensity", "", scene.bloom.intensity);
+        console_var!(console, "bloom.radius", "texels", scene.bloom.radius);
+    }
+}
+
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct BloomUniform {
+    threshold: f32,
+    knee: f32,
+    intensity: f32,
+    radius: f32,
+}
+
+/// Bloom on the HDR target with render passes, so it works without compute: a bright
+/// pass into the first mip of its own half size chain, a 13 tap downsample down the
+/// chain, tent upsamples added back up it, and the first mip added onto the target.
+pub struct Bloom {
+    uniform_buffer: Buffer,
+    sampler: Sampler,
+    bind_group_layout: BindGroupLayout,
+    prefilter_pipeline: RenderPipeline,
+    downsample_pipeline: RenderPipeline,
+    upsample_pipeline: RenderPipeline,
+    composite_pipeline: RenderPipeline,
+    // made again with the HDR target
+    chain: BloomChain,
+}
+
+struct BloomChain {
+    // a view per mip, for reading one while drawing into another
+    mip_views: Vec<TextureView>,
+    // reads the HDR target
+    prefilter_bind_group: BindGroup,
+    // [i] reads mip i
+    mip_bind_groups: Vec<BindGroup>,
+}
+
+impl Bloom {
+    /// `hdr_view` is the HDR target of `width` x `height`.
+    pub fn new(device: &wgpu::Device, hdr_view: &TextureView, width: u32, height: u32) -> Self {
+        let code = wgsl!("bloom.wgsl");
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("bloom shader"),
+            source: wgpu::ShaderSource::Wgsl(code),
+        });
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("bloom uniform"),
+            size: std::mem::size_of::<BloomUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("bloom sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("bloom bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("bloom pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        // color added onto what's there, alpha kept
+        let additive = wgpu::BlendState {
+            color: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::One,
+                dst_factor: wgpu::BlendFactor::One,
+                operation: wgpu::BlendOperation::Add,
+            },
+            alpha: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::Zero,
+                dst_factor: wgpu::BlendFactor::One,
+                operation: wgpu::BlendOperation::Add,
+            },
+        };
+        let make_pipeline = |entry_point: &str, blend: Option<wgpu::BlendState>| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "fullscreen_vs",
+                    buffers: &[],
+                    compilation_options: PipelineCompilationOptions::default(),
+                },
+                primitive: wgpu::PrimitiveState::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point,
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: HDR_FORMAT,
+                        blend,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                    compilation_options: PipelineCompilationOptions::default(),
+                }),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+        let prefilter_pipeline = make_pipeline("prefilter_fs", None);
+        let downsample_pipeline = make_pipeline("downsample_fs", None);
+        let upsample_pipeline = make_pipeline("upsample_fs", Some(additive));
+        let composite_pipeline = make_pipeline("composite_fs", Some(additive));
+
+        let chain = BloomChain::new(
+            device,
+            &bind_group_layout,
+            &sampler,
+            &uniform_buffer,
+            hdr_view,
+            width,
+            height,
+        );
+        Self {
+            uniform_buffer,
+            sampler,
+            bind_group_layout,
+            prefilter_pipeline,
+            downsample_pipeline,
+            upsample_pipeline,
+            composite_pipeline,
+            chain,
+        }
+    }
+
+    /// Follows the HDR target to its new view and size.
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        hdr_view: &TextureView,
+        width: u32,
+        height: u32,
+    ) {
+        self.chain = BloomChain::new(
+            device,
+            &self.bind_group_layout,
+            &self.sampler,
+            &self.uniform_buffer,
+            hdr_view,
+            width,
+            height,
+        );
+    }
+
+    /// Blooms the HDR target, `hdr_view` being the one it was made or resized with.
+    pub fn encode(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        queue: &wgpu::Queue,
+        settings: &BloomSettings,
+        hdr_view: &TextureView,
+    ) {
+        let uniform = BloomUniform {
+            threshold: settings.threshold.max(0.0),
+            knee: settings.knee.max(0.0),
+            intensity: settings.intensity.max(0.0),
+            radius: settings.radius.max(0.0),
+        };
+        frame_stats::write_buffer(queue, &self.uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+        let chain = &self.chain;
+        let mut draw = |label, pipeline, bind_group, target, load| {
+            let mut render_pass = CountedPass::begin(
+                encoder,
+                &wgpu::RenderPassDescriptor {
+                    label: Some(label),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: target,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load,
+                            store: wgpu::StoreOp::Store,
+                        },
+                    })],
+                    ..Default::default()
+                },
+            );
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(0, bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        };
+        let clear = wgpu::LoadOp::Clear(wgpu::Color::BLACK);
+        draw(
+            "bloom prefilter pass",
+            &self.prefilter_pipeline,
+            &chain.prefilter_bind_group,
+            &chain.mip_views[0],
+            clear,
+        );
+        for mip in 1..chain.mip_views.len() {
+            draw(
+                "bloom downsample pass",
+                &self.downsample_pipeline,
+                &chain.mip_bind_groups[mip - 1],
+                &chain.mip_views[mip],
+                clear,
+            );
+        }
+        for mip in (1..chain.mip_views.len()).rev() {
+            draw(
+                "bloom upsample pass",
+                &self.upsample_pipeline,
+                &chain.mip_bind_groups[mip],
+                &chain.mip_views[mip - 1],
+                wgpu::LoadOp::Load,
+            );
+        }
+        draw(
+            "bloom composite pass",
+            &self.composite_pipeline,
+            &chain.mip_bind_groups[0],
+            hdr_view,
+            wgpu::LoadOp::Load,
+        );
+    }
+}
+
+impl BloomChain {
+    fn new(
+        device: &wgpu::Device,
+        layout: &BindGroupLayout,
+        sampler: &Sampler,
+        uniform_buffer: &Buffer,
+        hdr_view: &TextureView,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let (width, height) = ((width / 2).max(1), (height / 2).max(1));
+        let mip_count = (width.min(height).ilog2() + 1).min(MAX_MIPS);
+        let texture: Texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("bloom chain"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: mip_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: HDR_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let mip_views: Vec<TextureView> = (0..mip_count)
+            .map(|mip| {
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("bloom mip view"),
+                    base_mip_level: mip,
+                    mip_level_count: Some(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+        let bind_group = |view: &TextureView| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("bloom bind group"),
+                layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: uniform_buffer.as_entire_binding(),
+                    },
+                ],
+            })
+        };
+        Self {
+            prefilter_bind_group: bind_group(hdr_view),
+            mip_bind_groups: mip_views.iter().map(bind_group).collect(),
+            mip_views,
+        }
+    }
+}
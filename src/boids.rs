@@ -0,0 +1,315 @@
+use std::cell::Cell;
+
+use cgmath::{InnerSpace, Point3, Vector3};
+use serde::{Deserialize, Serialize};
+use wgpu::{util::DeviceExt, BindGroup, Buffer, ComputePipeline};
+
+use crate::{
+    compute::{self, ComputeBinding},
+    frame_stats,
+    frustum::Aabb,
+    hot_reload::wgsl,
+    instance::{self, Instance},
+    vertex::MeshVertex,
+};
+
+/// A flock of cones flying around a box, steered on the GPU by their neighbors.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BoidsSettings {
+    /// fixed when the renderer is built, like the count
+    pub enabled: bool,
+    pub count: u32,
+    /// world space center of the box the flock stays in
+    pub center: [f32; 3],
+    /// half size of that box
+    pub extent: [f32; 3],
+    /// meters, how far a boid sees its neighbors
+    pub view_radius: f32,
+    /// meters, closer than this they push each other away
+    pub separation_radius: f32,
+    /// weights of the three rules
+    pub separation: f32,
+    pub alignment: f32,
+    pub cohesion: f32,
+    /// m/s
+    pub min_speed: f32,
+    pub max_speed: f32,
+    /// length of a cone, meters
+    pub size: f32,
+    /// linear, tints the default material
+    pub color: [f32; 4],
+}
+
+impl Default for BoidsSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            count: 1024,
+            center: [0.0, 4.0, -12.0],
+            extent: [8.0, 3.0, 8.0],
+            view_radius: 1.5,
+            separation_radius: 0.5,
+            separation: 1.5,
+            alignment: 1.0,
+            cohesion: 0.8,
+            min_speed: 1.5,
+            max_speed: 4.0,
+            size: 0.15,
+            color: [0.9, 0.9, 1.0, 1.0],
+        }
+    }
+}
+
+impl BoidsSettings {
+    /// Around every cone that stays in the box.
+    pub fn bounds(&self) -> Aabb {
+        let center = Point3::from(self.center);
+        let extent = Vector3::from(self.extent) + Vector3::new(1.0, 1.0, 1.0) * self.size;
+        Aabb {
+            min: center - extent,
+            max: center + extent,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct BoidParams {
+    count: u32,
+    dt: f32,
+    view_radius: f32,
+    separation_radius: f32,
+    separation: f32,
+    alignment: f32,
+    cohesion: f32,
+    max_speed: f32,
+    center: [f32; 3],
+    size: f32,
+    extent: [f32; 3],
+    min_speed: f32,
+    color: [f32; 4],
+}
+
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct Boid {
+    position: [f32; 3],
+    _pad0: f32,
+    velocity: [f32; 3],
+    _pad1: f32,
+}
+
+/// The boids' compute pass: reads last frame's state from one buffer, writes the next to
+/// the other, and writes each boid as an instance of the cone mesh. The mesh draws from
+/// that instance buffer like any other, so the flock goes through the instancing path,
+/// GPU culling included.
+pub struct Boids {
+    count: u32,
+    params_buffer: Buffer,
+    pipeline: ComputePipeline,
+    // [reading the first state buffer, reading the second]
+    bind_groups: [BindGroup; 2],
+    // which of `bind_groups` this frame uses
+    flip: Cell<bool>,
+}
+
+impl Boids {
+    /// Scatters `settings.count` boids around the box, flying every which way. Returns the
+    /// instance buffer the pass writes, for the cone mesh to draw.
+    pub fn new(device: &wgpu::Device, settings: &BoidsSettings, seed: u32) -> (Self, Buffer) {
+        let count = settings.count.max(1);
+        let mut random = Random(seed.wrapping_mul(2654435761) | 1);
+        let center = Vector3::from(settings.center);
+        let extent = Vector3::from(settings.extent);
+        let speed = (settings.min_speed + settings.max_speed) * 0.5;
+        let boids: Vec<Boid> = (0..count)
+            .map(|_| {
+                let mut offset = || Vector3::new(random.signed(), random.signed(), random.signed());
+                let position = center + offset().zip(extent, |o, e| o * e);
+                let direction = offset();
+                let direction = if direction.magnitude2() > 1e-6 {
+                    direction.normalize()
+                } else {
+                    Vector3::unit_z()
+                };
+                Boid {
+                    position: position.into(),
+                    _pad0: 0.0,
+                    velocity: (direction * speed).into(),
+                    _pad1: 0.0,
+                }
+            })
+            .collect();
+        // pointing anywhere until the first update turns them
+        let instances: Vec<_> = boids
+            .iter()
+            .map(|boid| {
+                Instance {
+                    position: boid.position.into(),
+                    scale: settings.size,
+                    color: settings.color,
+                    ..Default::default()
+                }
+                .to_raw()
+            })
+            .collect();
+        let instance_buffer = instance::create_instance_buffer(device, &instances);
+        let state_buffers = [0, 1].map(|_| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("boids"),
+                contents: bytemuck::cast_slice(&boids),
+                usage: wgpu::BufferUsages::STORAGE,
+            })
+        });
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("boid params"),
+            size: std::mem::size_of::<BoidParams>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let layout = compute::bind_group_layout(
+            device,
+            "boids layout",
+            wgpu::ShaderStages::COMPUTE,
+            &[
+                ComputeBinding::Uniform,
+                ComputeBinding::StorageBuffer { read_only: true },
+                ComputeBinding::StorageBuffer { read_only: false },
+                ComputeBinding::StorageBuffer { read_only: false },
+            ],
+        );
+        let bind_groups = [0, 1].map(|read| {
+            compute::bind_group(
+                device,
+                "boids",
+                &layout,
+                &[
+                    params_buffer.as_entire_binding(),
+                    state_buffers[read].as_entire_binding(),
+                    state_buffers[1 - read].as_entire_binding(),
+                    instance_buffer.as_entire_binding(),
+                ],
+            )
+        });
+        let code = wgsl!("boids.wgsl");
+        let pipeline =
+            compute::create_pipeline(device, "boids update", &code, "update", &[&layout]);
+
+        let boids = Self {
+            count,
+            params_buffer,
+            pipeline,
+            bind_groups,
+            flip: Cell::new(false),
+        };
+        (boids, instance_buffer)
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Steps the flock by `dt` with this frame's `settings`, all but the count.
+    pub fn encode(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        queue: &wgpu::Queue,
+        settings: &BoidsSettings,
+        dt: f32,
+    ) {
+        let params = BoidParams {
+            count: self.count,
+            dt,
+            view_radius: settings.view_radius,
+            separation_radius: settings.separation_radius,
+            separation: settings.separation,
+            alignment: settings.alignment,
+            cohesion: settings.cohesion,
+            max_speed: settings.max_speed,
+            center: settings.center,
+            size: settings.size,
+            extent: settings.extent.map(|e| e.max(1e-3)),
+            min_speed: settings.min_speed.min(settings.max_speed),
+            color: settings.color,
+        };
+        frame_stats::write_buffer(queue, &self.params_buffer, 0, bytemuck::bytes_of(&params));
+        let flip = self.flip.get();
+        self.flip.set(!flip);
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("boids update"),
+            timestamp_writes: None,
+        });
+        compute_pass.set_pipeline(&self.pipeline);
+        compute_pass.set_bind_group(0, &self.bind_groups[flip as usize], &[]);
+        compute_pass.dispatch_workgroups(self.count.div_ceil(64), 1, 1);
+    }
+}
+
+/// A cone of `segments` sides, one unit long with its tip at +z, its base half as wide
+/// as that.
+pub fn cone(segments: u16) -> (Vec<MeshVertex>, Vec<u16>) {
+    let segments = segments.max(3);
+    let (radius, tip, base) = (0.25, 0.5, -0.5);
+    let mut vertices = vec![];
+    let mut indices = vec![];
+    let around = |i: f32| {
+        let angle = i / segments as f32 * std::f32::consts::TAU;
+        (angle.cos(), angle.sin())
+    };
+    let slope = radius / (tip - base);
+    let side_vertex = |(x, y): (f32, f32), position| MeshVertex {
+        position,
+        normal: Vector3::new(x, y, slope).normalize().into(),
+        uv: [0.0; 2],
+        tangent: [-y, x, 0.0, 1.0],
+    };
+    // the sides, each with its own normals so the base edge stays sharp; the tip's
+    // halfway between its side's
+    for i in 0..segments {
+        let (x0, y0) = around(i as f32);
+        let (x1, y1) = around(i as f32 + 1.0);
+        let first = vertices.len() as u16;
+        vertices.extend([
+            side_vertex((x0, y0), [x0 * radius, y0 * radius, base]),
+            side_vertex((x1, y1), [x1 * radius, y1 * radius, base]),
+            side_vertex(around(i as f32 + 0.5), [0.0, 0.0, tip]),
+        ]);
+        indices.extend([first, first + 1, first + 2]);
+    }
+    // the base, facing -z
+    let center = vertices.len() as u16;
+    vertices.push(MeshVertex {
+        position: [0.0, 0.0, base],
+        normal: [0.0, 0.0, -1.0],
+        uv: [0.0; 2],
+        tangent: [1.0, 0.0, 0.0, 1.0],
+    });
+    for i in 0..=segments {
+        let (x, y) = around(i as f32);
+        vertices.push(MeshVertex {
+            position: [x * radius, y * radius, base],
+            normal: [0.0, 0.0, -1.0],
+            uv: [0.0; 2],
+            tangent: [1.0, 0.0, 0.0, 1.0],
+        });
+    }
+    for i in 0..segments {
+        indices.extend([center, center + 2 + i, center + 1 + i]);
+    }
+    (vertices, indices)
+}
+
+// xorshift, enough to scatter the flock
+struct Random(u32);
+
+impl Random {
+    /// -1..1
+    fn signed(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        (self.0 >> 8) as f32 / (1u32 << 23) as f32 - 1.0
+    }
+}
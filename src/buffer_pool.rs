@@ -0,0 +1,179 @@
+use std::ops::Range;
+
+use wgpu::{Buffer, BufferUsages};
+
+use crate::frame_stats;
+
+/// A region of one of a `BufferPool`'s buffers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Allocation {
+    // into the pool's blocks
+    block: usize,
+    pub offset: u64,
+    pub size: u64,
+}
+
+struct Block {
+    buffer: Buffer,
+    // sorted by offset, neighbours merged
+    free: Vec<Range<u64>>,
+}
+
+/// Vertex, index and uniform regions handed out of a few large buffers, so geometry built
+/// again every frame (text, debug lines, particles drawn from the CPU) doesn't make
+/// buffers of its own. Freed regions go back on a free list, merged with the free ones
+/// around them, and the first that fits is taken. A block is only made when none of the
+/// others has room, and none is ever given back.
+///
+/// A region used for a single frame is taken with `alloc_for_frame` and comes back with
+/// `end_frame` once the frame is submitted, the queue's writes into it for the next one
+/// landing after this one's draws.
+pub struct BufferPool {
+    label: &'static str,
+    usage: BufferUsages,
+    block_size: u64,
+    // every region starts on it
+    alignment: u64,
+    blocks: Vec<Block>,
+    frame: Vec<Allocation>,
+}
+
+impl BufferPool {
+    /// Blocks of `block_size` bytes, or one region's size where that's more. `COPY_DST` is
+    /// added to `usage` for `write`.
+    pub fn new(
+        device: &wgpu::Device,
+        label: &'static str,
+        usage: BufferUsages,
+        block_size: u64,
+    ) -> Self {
+        let limits = device.limits();
+        let mut alignment = wgpu::COPY_BUFFER_ALIGNMENT;
+        if usage.contains(BufferUsages::UNIFORM) {
+            alignment = alignment.max(limits.min_uniform_buffer_offset_alignment as u64);
+        }
+        if usage.contains(BufferUsages::STORAGE) {
+            alignment = alignment.max(limits.min_storage_buffer_offset_alignment as u64);
+        }
+        Self {
+            label,
+            usage: usage | BufferUsages::COPY_DST,
+            block_size,
+            alignment,
+            blocks: vec![],
+            frame: vec![],
+        }
+    }
+
+    /// A region of at least `size` bytes, until it's given to `free`.
+    pub fn alloc(&mut self, device: &wgpu::Device, size: u64) -> Allocation {
+        let size = size.max(1).div_ceil(self.alignment) * self.alignment;
+        for (index, block) in self.blocks.iter_mut().enumerate() {
+            if let Some(offset) = Self::take(&mut block.free, size) {
+                return Allocation {
+                    block: index,
+                    offset,
+                    size,
+                };
+            }
+        }
+        let block_size = self.block_size.max(size);
+        self.blocks.push(Block {
+            buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(self.label),
+                size: block_size,
+                usage: self.usage,
+                mapped_at_creation: false,
+            }),
+            // the free list starts as everything past this allocation
+            free: std::iter::once(size..block_size).collect(),
+        });
+        Allocation {
+            block: self.blocks.len() - 1,
+            offset: 0,
+            size,
+        }
+    }
+
+    /// A region of at least `size` bytes, freed by the next `end_frame`.
+    pub fn alloc_for_frame(&mut self, device: &wgpu::Device, size: u64) -> Allocation {
+        let allocation = self.alloc(device, size);
+        self.frame.push(allocation);
+        allocation
+    }
+
+    pub fn free(&mut self, allocation: Allocation) {
+        let free = &mut self.blocks[allocation.block].free;
+        let range = allocation.offset..allocation.offset + allocation.size;
+        let index = free.partition_point(|other| other.start < range.start);
+        free.insert(index, range);
+        // into the one after, then the one before into it
+        if index + 1 < free.len() && free[index].end == free[index + 1].start {
+            free[index].end = free.remove(index + 1).end;
+        }
+        if index > 0 && free[index - 1].end == free[index].start {
+            free[index - 1].end = free.remove(index).end;
+        }
+    }
+
+    /// Frees this frame's regions, once the frame using them is submitted.
+    pub fn end_frame(&mut self) {
+        for allocation in std::mem::take(&mut self.frame) {
+            self.free(allocation);
+        }
+    }
+
+    /// `data` at the start of `allocation`, which it has to fit in.
+    pub fn write(&self, queue: &wgpu::Queue, allocation: Allocation, data: &[u8]) {
+        assert!(data.len() as u64 <= allocation.size);
+        frame_stats::write_buffer(queue, self.buffer(allocation), allocation.offset, data);
+    }
+
+    pub fn buffer(&self, allocation: Allocation) -> &Buffer {
+        &self.blocks[allocation.block].buffer
+    }
+
+    /// The first `size` bytes of `allocation`, for vertex and index buffers.
+    pub fn slice(&self, allocation: Allocation, size: u64) -> wgpu::BufferSlice<'_> {
+        self.buffer(allocation)
+            .slice(allocation.offset..allocation.offset + size.min(allocation.size))
+    }
+
+    /// `allocation` as a uniform or storage binding.
+    pub fn binding(&self, allocation: Allocation) -> wgpu::BindingResource<'_> {
+        wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+            buffer: self.buffer(allocation),
+            offset: allocation.offset,
+            size: wgpu::BufferSize::new(allocation.size),
+        })
+    }
+
+    /// Memory held by the blocks, free or not.
+    pub fn bytes(&self) -> u64 {
+        self.blocks.iter().map(|block| block.buffer.size()).sum()
+    }
+
+    /// Memory handed out and not freed yet.
+    pub fn used_bytes(&self) -> u64 {
+        let free = self
+            .blocks
+            .iter()
+            .flat_map(|block| &block.free)
+            .map(|range| range.end - range.start)
+            .sum::<u64>();
+        self.bytes() - free
+    }
+
+    // the start of the first free range `size` fits in, taken off it
+    fn take(free: &mut Vec<Range<u64>>, size: u64) -> Option<u64> {
+        let index = free
+            .iter()
+            .position(|range| range.end - range.start >= size)?;
+        let offset = free[index].start;
+        free[index].start += size;
+        if free[index].is_empty() {
+            free.remove(index);
+        }
+        Some(offset)
+    }
+}
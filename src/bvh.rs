@@ -0,0 +1,180 @@
+use crate::static_geometry::Triangle;
+
+// triangles per leaf before splitting stops paying off
+const LEAF_SIZE: usize = 4;
+const BINS: usize = 12;
+
+/// One node of the flattened tree. Interior nodes have `count == 0` and their children
+/// at `left_first` and `left_first + 1`, leaves cover `count` triangles from `left_first`.
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct BvhNode {
+    pub min: [f32; 3],
+    pub left_first: u32,
+    pub max: [f32; 3],
+    pub count: u32,
+}
+
+/// A bounding volume hierarchy over triangles, built on the CPU with binned SAH. The
+/// triangles are reordered so every leaf covers a contiguous range.
+pub struct Bvh {
+    pub nodes: Vec<BvhNode>,
+    pub triangles: Vec<Triangle>,
+}
+
+#[derive(Clone, Copy)]
+struct Aabb {
+    min: [f32; 3],
+    max: [f32; 3],
+}
+
+impl Aabb {
+    const EMPTY: Self = Self {
+        min: [f32::INFINITY; 3],
+        max: [f32::NEG_INFINITY; 3],
+    };
+
+    fn grow(&mut self, p: [f32; 3]) {
+        for (axis, v) in p.into_iter().enumerate() {
+            self.min[axis] = self.min[axis].min(v);
+            self.max[axis] = self.max[axis].max(v);
+        }
+    }
+
+    fn merge(&mut self, other: &Aabb) {
+        for axis in 0..3 {
+            self.min[axis] = self.min[axis].min(other.min[axis]);
+            self.max[axis] = self.max[axis].max(other.max[axis]);
+        }
+    }
+
+    fn area(&self) -> f32 {
+        let [x, y, z] = [0, 1, 2].map(|axis| (self.max[axis] - self.min[axis]).max(0.0));
+        2.0 * (x * y + y * z + z * x)
+    }
+}
+
+fn vertices(triangle: &Triangle) -> [[f32; 3]; 3] {
+    [triangle.v0, triangle.v1, triangle.v2].map(|v| [v[0], v[1], v[2]])
+}
+
+fn centroid(triangle: &Triangle) -> [f32; 3] {
+    let [a, b, c] = vertices(triangle);
+    [0, 1, 2].map(|axis| (a[axis] + b[axis] + c[axis]) / 3.0)
+}
+
+fn bounds(triangles: &[Triangle]) -> Aabb {
+    let mut aabb = Aabb::EMPTY;
+    for v in triangles.iter().flat_map(vertices) {
+        aabb.grow(v);
+    }
+    aabb
+}
+
+impl Bvh {
+    pub fn build(mut triangles: Vec<Triangle>) -> Self {
+        let mut nodes = vec![bytemuck::Zeroable::zeroed()];
+        // storage bindings can't be empty: an empty tree is one leaf holding a degenerate
+        // triangle no ray can hit
+        if triangles.is_empty() {
+            nodes[0] = BvhNode {
+                min: [0.0; 3],
+                left_first: 0,
+                max: [0.0; 3],
+                count: 1,
+            };
+            triangles.push(bytemuck::Zeroable::zeroed());
+            return Self { nodes, triangles };
+        }
+
+        // (node, first, count) still to be split
+        let mut stack = vec![(0usize, 0usize, triangles.len())];
+        while let Some((index, first, count)) = stack.pop() {
+            let range = &mut triangles[first..first + count];
+            let aabb = bounds(range);
+            nodes[index] = BvhNode {
+                min: aabb.min,
+                left_first: first as u32,
+                max: aabb.max,
+                count: count as u32,
+            };
+            if count <= LEAF_SIZE {
+                continue;
+            }
+            let Some(split) = split(range, aabb.area() * count as f32) else {
+                continue;
+            };
+            let left = nodes.len();
+            nodes.push(bytemuck::Zeroable::zeroed());
+            nodes.push(bytemuck::Zeroable::zeroed());
+            nodes[index].left_first = left as u32;
+            nodes[index].count = 0;
+            stack.push((left, first, split));
+            stack.push((left + 1, first + split, count - split));
+        }
+        Self { nodes, triangles }
+    }
+}
+
+/// Partitions `triangles` at the cheapest binned SAH plane and returns how many ended up
+/// on the left, or None when no split beats keeping them in one leaf costing `leaf_cost`.
+fn split(triangles: &mut [Triangle], leaf_cost: f32) -> Option<usize> {
+    let mut centroids = Aabb::EMPTY;
+    for triangle in triangles.iter() {
+        centroids.grow(centroid(triangle));
+    }
+    let bin_of = |axis: usize, c: [f32; 3]| {
+        let extent = centroids.max[axis] - centroids.min[axis];
+        let bin = ((c[axis] - centroids.min[axis]) / extent * BINS as f32) as usize;
+        bin.min(BINS - 1)
+    };
+
+    // (cost, axis, first bin on the right)
+    let mut best: Option<(f32, usize, usize)> = None;
+    for axis in 0..3 {
+        if centroids.max[axis] - centroids.min[axis] <= f32::EPSILON {
+            continue;
+        }
+        let mut bins = [(Aabb::EMPTY, 0usize); BINS];
+        for triangle in triangles.iter() {
+            let bin = &mut bins[bin_of(axis, centroid(triangle))];
+            for v in vertices(triangle) {
+                bin.0.grow(v);
+            }
+            bin.1 += 1;
+        }
+        // sweep from the right, then from the left, pricing every plane between bins
+        let mut right_area = [0.0; BINS];
+        let mut right = (Aabb::EMPTY, 0);
+        for plane in (1..BINS).rev() {
+            right.0.merge(&bins[plane].0);
+            right.1 += bins[plane].1;
+            right_area[plane] = right.0.area() * right.1 as f32;
+        }
+        let mut left = (Aabb::EMPTY, 0);
+        for plane in 1..BINS {
+            left.0.merge(&bins[plane - 1].0);
+            left.1 += bins[plane - 1].1;
+            if left.1 == 0 || left.1 == triangles.len() {
+                continue;
+            }
+            let cost = left.0.area() * left.1 as f32 + right_area[plane];
+            if best.is_none_or(|(best_cost, _, _)| cost < best_cost) {
+                best = Some((cost, axis, plane));
+            }
+        }
+    }
+
+    let (cost, axis, plane) = best?;
+    if cost >= leaf_cost {
+        return None;
+    }
+    let mut left = 0;
+    for i in 0..triangles.len() {
+        if bin_of(axis, centroid(&triangles[i])) < plane {
+            triangles.swap(i, left);
+            left += 1;
+        }
+    }
+    Some(left)
+}
@@ -1,6 +1,8 @@
-use winit::{
-    event::{ElementState, KeyEvent, WindowEvent},
-    keyboard::{KeyCode, PhysicalKey},
+use winit::keyboard::KeyCode;
+
+use crate::{
+    render_thread::KeyInput,
+    shortcuts::{Chord, Shortcuts},
 };
 
 pub struct Camera {
@@ -20,7 +22,7 @@ impl Camera {
         // 2.
         let proj = cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar);
         // 3.
-        return OPENGL_TO_WGPU_MATRIX * proj * view;
+        OPENGL_TO_WGPU_MATRIX * proj * view
     }
 }
 
@@ -39,6 +41,9 @@ pub struct CameraUniform {
     // We can't use cgmath with bytemuck directly, so we'll have
     // to convert the Matrix4 into a 4x4 f32 array
     pub view_proj: [[f32; 4]; 4],
+    // for turning screen positions back into world space rays (sky, raymarching)
+    pub inv_view_proj: [[f32; 4]; 4],
+    pub view_position: [f32; 4],
 }
 
 impl CameraUniform {
@@ -46,11 +51,20 @@ impl CameraUniform {
         use cgmath::SquareMatrix;
         Self {
             view_proj: cgmath::Matrix4::identity().into(),
+            inv_view_proj: cgmath::Matrix4::identity().into(),
+            view_position: [0.0; 4],
         }
     }
 
     pub fn update_view_proj(&mut self, camera: &Camera) {
-        self.view_proj = camera.build_view_projection_matrix().into();
+        use cgmath::SquareMatrix;
+        let view_proj = camera.build_view_projection_matrix();
+        self.view_proj = view_proj.into();
+        self.inv_view_proj = view_proj
+            .invert()
+            .unwrap_or(cgmath::Matrix4::identity())
+            .into();
+        self.view_position = camera.eye.to_homogeneous().into();
     }
 }
 
@@ -73,33 +87,36 @@ impl CameraController {
         }
     }
 
-    pub fn process_events(&mut self, event: &KeyEvent) -> bool {
-        match event {
-            KeyEvent {
-                state,
-                physical_key: PhysicalKey::Code(keycode),
-                ..
-            } => {
-                let is_pressed = *state == ElementState::Pressed;
-                match keycode {
-                    KeyCode::KeyW | KeyCode::ArrowUp => {
-                        self.is_forward_pressed = is_pressed;
-                        true
-                    }
-                    KeyCode::KeyA | KeyCode::ArrowLeft => {
-                        self.is_left_pressed = is_pressed;
-                        true
-                    }
-                    KeyCode::KeyS | KeyCode::ArrowDown => {
-                        self.is_backward_pressed = is_pressed;
-                        true
-                    }
-                    KeyCode::KeyD | KeyCode::ArrowRight => {
-                        self.is_right_pressed = is_pressed;
-                        true
-                    }
-                    _ => false,
-                }
+    /// Movement reads held keys directly, these only keep shortcuts off them.
+    pub fn register_shortcuts(shortcuts: &mut Shortcuts) {
+        for (action, keys) in [
+            ("move forward", [KeyCode::KeyW, KeyCode::ArrowUp]),
+            ("move left", [KeyCode::KeyA, KeyCode::ArrowLeft]),
+            ("move back", [KeyCode::KeyS, KeyCode::ArrowDown]),
+            ("move right", [KeyCode::KeyD, KeyCode::ArrowRight]),
+        ] {
+            shortcuts.register_fixed("camera", action, &keys.map(Chord::key));
+        }
+    }
+
+    pub fn process_events(&mut self, event: &KeyInput) -> bool {
+        let is_pressed = event.pressed;
+        match event.key {
+            KeyCode::KeyW | KeyCode::ArrowUp => {
+                self.is_forward_pressed = is_pressed;
+                true
+            }
+            KeyCode::KeyA | KeyCode::ArrowLeft => {
+                self.is_left_pressed = is_pressed;
+                true
+            }
+            KeyCode::KeyS | KeyCode::ArrowDown => {
+                self.is_backward_pressed = is_pressed;
+                true
+            }
+            KeyCode::KeyD | KeyCode::ArrowRight => {
+                self.is_right_pressed = is_pressed;
+                true
             }
             _ => false,
         }
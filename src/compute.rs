@@ -0,0 +1,174 @@
+use std::borrow::Cow;
+
+use wgpu::{BindGroup, BindGroupLayout, ComputePipeline, PipelineCompilationOptions};
+
+use crate::registry::{ComputePipelineHandle, ResourceRegistry};
+
+/// Where a `ComputeJob` goes in the frame's encoder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComputeStage {
+    /// ahead of every render pass, so the frame can draw what it wrote
+    BeforeRender,
+    /// after the frame is drawn, reading what it left
+    AfterRender,
+}
+
+/// What a binding of `bind_group_layout` holds, numbered by its place in the list.
+#[derive(Debug, Clone, Copy)]
+pub enum ComputeBinding {
+    Uniform,
+    StorageBuffer {
+        read_only: bool,
+    },
+    StorageTexture {
+        format: wgpu::TextureFormat,
+        access: wgpu::StorageTextureAccess,
+    },
+    // a filterable 2D float texture for textureLoad / textureSampleLevel
+    Texture,
+    Sampler,
+}
+
+impl ComputeBinding {
+    fn ty(self) -> wgpu::BindingType {
+        match self {
+            Self::Uniform => wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            Self::StorageBuffer { read_only } => wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            Self::StorageTexture { format, access } => wgpu::BindingType::StorageTexture {
+                access,
+                format,
+                view_dimension: wgpu::TextureViewDimension::D2,
+            },
+            Self::Texture => wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            Self::Sampler => wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+        }
+    }
+}
+
+/// A layout of `bindings` at 0, 1, 2... seen from `visibility`. Read write storage
+/// can't be seen from the vertex stage.
+pub fn bind_group_layout(
+    device: &wgpu::Device,
+    label: &str,
+    visibility: wgpu::ShaderStages,
+    bindings: &[ComputeBinding],
+) -> BindGroupLayout {
+    let entries: Vec<_> = bindings
+        .iter()
+        .enumerate()
+        .map(|(binding, ty)| wgpu::BindGroupLayoutEntry {
+            binding: binding as u32,
+            visibility,
+            ty: ty.ty(),
+            count: None,
+        })
+        .collect();
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some(label),
+        entries: &entries,
+    })
+}
+
+/// A bind group of `resources` at 0, 1, 2..., in the order `layout` was made with.
+pub fn bind_group(
+    device: &wgpu::Device,
+    label: &str,
+    layout: &BindGroupLayout,
+    resources: &[wgpu::BindingResource],
+) -> BindGroup {
+    let entries: Vec<_> = resources
+        .iter()
+        .enumerate()
+        .map(|(binding, resource)| wgpu::BindGroupEntry {
+            binding: binding as u32,
+            resource: resource.clone(),
+        })
+        .collect();
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some(label),
+        layout,
+        entries: &entries,
+    })
+}
+
+/// `entry_point` of the WGSL in `code`, with `layouts` as groups 0, 1, 2...
+pub fn create_pipeline(
+    device: &wgpu::Device,
+    label: &str,
+    code: &str,
+    entry_point: &str,
+    layouts: &[&BindGroupLayout],
+) -> ComputePipeline {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some(label),
+        source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(code)),
+    });
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some(label),
+        bind_group_layouts: layouts,
+        push_constant_ranges: &[],
+    });
+    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some(label),
+        layout: Some(&pipeline_layout),
+        module: &shader,
+        entry_point,
+        compilation_options: PipelineCompilationOptions::default(),
+    })
+}
+
+/// A dispatch the factory records every frame while it's enabled, with `bind_groups` as
+/// groups 0, 1, 2...
+pub struct ComputeJob {
+    pub label: &'static str,
+    // from `GpuFactory::add_compute_pipeline`
+    pub pipeline: ComputePipelineHandle,
+    pub bind_groups: Vec<BindGroup>,
+    pub workgroups: [u32; 3],
+    pub stage: ComputeStage,
+    pub enabled: bool,
+}
+
+/// Whether any of `jobs` is enabled at `stage`, to leave its pass out of the frame.
+pub fn has_jobs(jobs: &[ComputeJob], stage: ComputeStage) -> bool {
+    jobs.iter().any(|job| job.enabled && job.stage == stage)
+}
+
+/// Records the enabled `jobs` of `stage` into one compute pass.
+pub fn encode(
+    encoder: &mut wgpu::CommandEncoder,
+    registry: &ResourceRegistry,
+    jobs: &[ComputeJob],
+    stage: ComputeStage,
+) {
+    let jobs = jobs.iter().filter(|job| job.enabled && job.stage == stage);
+    let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+        label: Some(match stage {
+            ComputeStage::BeforeRender => "compute jobs before render",
+            ComputeStage::AfterRender => "compute jobs after render",
+        }),
+        timestamp_writes: None,
+    });
+    for job in jobs {
+        compute_pass.push_debug_group(job.label);
+        compute_pass.set_pipeline(&registry[job.pipeline]);
+        for (index, bind_group) in job.bind_groups.iter().enumerate() {
+            compute_pass.set_bind_group(index as u32, bind_group, &[]);
+        }
+        let [x, y, z] = job.workgroups;
+        compute_pass.dispatch_workgroups(x, y, z);
+        compute_pass.pop_debug_group();
+    }
+}
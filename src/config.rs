@@ -0,0 +1,198 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    deferred::RenderPath, device_poll::PollPolicy, dynamic_resolution::DynamicResolutionSettings,
+    frame_stats::FrameStats, fxaa::FxaaSettings, limits::LimitsProfile, sharpen::SharpenSettings,
+    surface::AlphaMode, upscale::UpscaleSettings,
+};
+
+/// User side settings, read from `config.ron` next to Cargo.toml.
+/// Missing file or missing fields fall back to the defaults.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub limits: LimitsProfile,
+    pub window: WindowConfig,
+    pub display: DisplayConfig,
+    pub render: RenderConfig,
+    /// scene file to load at startup, relative to the crate directory
+    pub scene: PathBuf,
+    /// action name to chord, "Ctrl+Shift+S", over the built in shortcuts
+    pub shortcuts: BTreeMap<String, String>,
+    pub deterministic: DeterministicConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            limits: LimitsProfile::default(),
+            window: WindowConfig::default(),
+            display: DisplayConfig::default(),
+            render: RenderConfig::default(),
+            scene: PathBuf::from("asset/scene.ron"),
+            shortcuts: BTreeMap::new(),
+            deterministic: DeterministicConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindowConfig {
+    /// let the desktop show through wherever the sky isn't opaque
+    pub transparent: bool,
+    pub alpha_mode: AlphaMode,
+    pub sky_opacity: f32,
+    /// use an HDR surface format (Rgba16Float) when the surface offers one
+    pub hdr: bool,
+    pub hdr_paper_white_nits: f32,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        Self {
+            transparent: false,
+            alpha_mode: AlphaMode::Auto,
+            sky_opacity: 1.0,
+            hdr: false,
+            hdr_paper_white_nits: 200.0,
+        }
+    }
+}
+
+impl Config {
+    pub fn path() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("config.ron")
+    }
+
+    pub fn load() -> Self {
+        let path = Self::path();
+        match std::fs::read_to_string(&path) {
+            Ok(text) => match ron::from_str(&text) {
+                Ok(config) => config,
+                Err(e) => {
+                    println!("Bad config {}: {}, using defaults", path.display(), e);
+                    Self::default()
+                }
+            },
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+        std::fs::write(Self::path(), text)?;
+        Ok(())
+    }
+}
+
+/// Fixed frame times and explicit seeds, so two runs feed the GPU the same uniforms frame
+/// for frame: for tests, replays and comparing captures. Also on with `--deterministic`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeterministicConfig {
+    pub enabled: bool,
+    /// seconds every frame advances by, however long it took
+    pub dt: f32,
+    /// mixed into the GPU side random numbers (particle respawns, path tracing); used
+    /// whether or not the mode is on
+    pub seed: u32,
+}
+
+impl Default for DeterministicConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dt: 1.0 / 60.0,
+            seed: 0,
+        }
+    }
+}
+
+/// Final output adjustments for badly calibrated displays, applied at the end of the
+/// tonemap pass.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DisplayConfig {
+    pub gamma: f32,
+    pub brightness: f32,
+    pub contrast: f32,
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        Self {
+            gamma: 1.0,
+            brightness: 0.0,
+            contrast: 1.0,
+        }
+    }
+}
+
+/// Quality settings for how the scene is rendered, independent of the scene itself.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RenderConfig {
+    /// forward or deferred shading of the meshes, fixed when the renderer is built; deferred
+    /// falls back to forward where the device can't take its targets
+    pub render_path: RenderPath,
+    /// SSAA factor per axis, 1 to 4; lowered when the scaled target doesn't fit the limits
+    pub supersample: u32,
+    pub dynamic_resolution: DynamicResolutionSettings,
+    /// FXAA on the post chain's output, before upscaling
+    pub fxaa: FxaaSettings,
+    /// FSR style upscaling of the post chain's output to the window
+    pub upscale: UpscaleSettings,
+    /// contrast adaptive sharpening at the very end, with or without upscaling
+    pub sharpen: SharpenSettings,
+    /// Poll for windowed use, Wait to finish every frame before the next (benchmarks)
+    pub poll: PollPolicy,
+    /// record runs of thread safe render graph passes on the factory's worker threads
+    pub parallel_encoding: bool,
+    /// draw the meshes with their arguments from a GPU buffer, in one multi-draw where the
+    /// device can; fixed when the renderer is built, direct draws where indirect ones
+    /// aren't supported
+    pub indirect_draws: bool,
+    /// skip the meshes whose boxes are out of view; the indirect draws leave that to
+    /// `gpu_culling`
+    pub cpu_culling: bool,
+    /// with `indirect_draws`, frustum cull the mesh instances in a compute pass first;
+    /// fixed when the renderer is built
+    pub gpu_culling: bool,
+    /// KiB of decoded asset texels uploaded per frame while loading
+    pub asset_upload_kb: u32,
+    /// watch asset/ and rebuild the pipelines of a WGSL file when it's saved, windowed only
+    pub hot_reload_shaders: bool,
+    /// redraw every frame even with nothing in the scene moving, for shaders animated by
+    /// the uniforms' time
+    pub continuous_redraw: bool,
+    /// stop with an error on any frame whose stats go over `stats_budget`, for automated
+    /// perf runs
+    pub benchmark: bool,
+    pub stats_budget: FrameStats,
+}
+
+impl Default for RenderConfig {
+    fn default() -> Self {
+        Self {
+            render_path: RenderPath::default(),
+            supersample: 1,
+            dynamic_resolution: DynamicResolutionSettings::default(),
+            fxaa: FxaaSettings::default(),
+            upscale: UpscaleSettings::default(),
+            sharpen: SharpenSettings::default(),
+            poll: PollPolicy::default(),
+            parallel_encoding: true,
+            indirect_draws: false,
+            cpu_culling: true,
+            gpu_culling: false,
+            asset_upload_kb: 1024,
+            hot_reload_shaders: true,
+            continuous_redraw: false,
+            benchmark: false,
+            stats_budget: FrameStats::default_budget(),
+        }
+    }
+}
@@ -0,0 +1,326 @@
+use std::collections::VecDeque;
+
+use anyhow::{anyhow, bail};
+use winit::keyboard::KeyCode;
+
+use crate::{render_thread::KeyInput, text::TextRenderer, GfxState};
+
+const TEXT_COLOR: [f32; 4] = [0.95, 0.95, 0.95, 1.0];
+const INPUT_COLOR: [f32; 4] = [1.0, 0.85, 0.3, 1.0];
+const PANEL_COLOR: [f32; 4] = [0.05, 0.05, 0.08, 0.85];
+// pixels around the console's text
+const MARGIN: f32 = 8.0;
+// output lines kept for scrolling back through
+const MAX_LOG: usize = 256;
+
+/// Runs with the words after the command name; the returned text goes to the log.
+pub type CommandFn = fn(&mut GfxState, &[&str]) -> anyhow::Result<String>;
+
+struct Command {
+    name: &'static str,
+    help: &'static str,
+    run: CommandFn,
+}
+
+/// A setting reachable from `set`, `get` and `toggle`. The getter's text is also what `set`
+/// parses, so `get` output can be pasted back.
+struct Variable {
+    name: &'static str,
+    help: &'static str,
+    get: fn(&GfxState) -> String,
+    set: fn(&mut GfxState, &str) -> anyhow::Result<()>,
+}
+
+/// Registers a variable backed by a field of `GfxState`, parsed with `FromStr`:
+/// `console_var!(console, "sky.turbidity", "haziness", scene.sky.turbidity)`. Fields
+/// read every frame take effect on the next one.
+macro_rules! console_var {
+    ($console:expr, $name:expr, $help:expr, $($field:ident).+) => {
+        $console.variable(
+            $name,
+            $help,
+            |app| app.$($field).+.to_string(),
+            |app, value| {
+                app.$($field).+ = value
+                    .parse()
+                    .map_err(|e| anyhow::anyhow!("bad value {:?}: {}", value, e))?;
+                Ok(())
+            },
+        )
+    };
+}
+pub(crate) use console_var;
+
+/// Quake style drop-down console, opened with the backtick. Subsystems register their
+/// commands and variables at startup; `help` lists them.
+pub struct Console {
+    pub open: bool,
+    input: String,
+    // entered lines, oldest first
+    history: Vec<String>,
+    // which history line Up/Down is on, None while editing a new one
+    history_cursor: Option<usize>,
+    log: VecDeque<String>,
+    commands: Vec<Command>,
+    variables: Vec<Variable>,
+}
+
+impl Default for Console {
+    fn default() -> Self {
+        let mut console = Self {
+            open: false,
+            input: String::new(),
+            history: vec![],
+            history_cursor: None,
+            log: VecDeque::new(),
+            commands: vec![],
+            variables: vec![],
+        };
+        console.command("help", "list commands and variables", help);
+        console.command("set", "set <variable> <value>", set);
+        console.command("get", "get <variable>", get);
+        console.command(
+            "toggle",
+            "toggle <bool variable or shortcut action>",
+            toggle,
+        );
+        console.command("clear", "clear the console", |app, _| {
+            app.console.log.clear();
+            Ok(String::new())
+        });
+        console
+    }
+}
+
+impl Console {
+    pub fn command(&mut self, name: &'static str, help: &'static str, run: CommandFn) {
+        if self.commands.iter().any(|command| command.name == name) {
+            println!("Console command {} registered twice, ignored", name);
+            return;
+        }
+        self.commands.push(Command { name, help, run });
+    }
+
+    /// See `console_var!` for variables that are plain fields.
+    pub fn variable(
+        &mut self,
+        name: &'static str,
+        help: &'static str,
+        get: fn(&GfxState) -> String,
+        set: fn(&mut GfxState, &str) -> anyhow::Result<()>,
+    ) {
+        if self.variables.iter().any(|variable| variable.name == name) {
+            println!("Console variable {} registered twice, ignored", name);
+            return;
+        }
+        self.variables.push(Variable {
+            name,
+            help,
+            get,
+            set,
+        });
+    }
+
+    pub fn print(&mut self, text: &str) {
+        for line in text.lines() {
+            println!("{}", line);
+            if self.log.len() == MAX_LOG {
+                self.log.pop_front();
+            }
+            self.log.push_back(line.to_string());
+        }
+    }
+
+    /// Edits the input line. Returns a line once Enter is pressed.
+    pub fn key(&mut self, event: &KeyInput) -> Option<String> {
+        if !event.pressed {
+            return None;
+        }
+        match event.key {
+            KeyCode::Enter | KeyCode::NumpadEnter => {
+                let line = std::mem::take(&mut self.input);
+                self.history_cursor = None;
+                if line.trim().is_empty() {
+                    return None;
+                }
+                if self.history.last() != Some(&line) {
+                    self.history.push(line.clone());
+                }
+                return Some(line);
+            }
+            KeyCode::Backspace => {
+                self.input.pop();
+            }
+            KeyCode::Escape => self.open = false,
+            KeyCode::ArrowUp => {
+                let cursor = match self.history_cursor {
+                    Some(cursor) => cursor.saturating_sub(1),
+                    None => self.history.len().saturating_sub(1),
+                };
+                if let Some(line) = self.history.get(cursor) {
+                    self.input = line.clone();
+                    self.history_cursor = Some(cursor);
+                }
+            }
+            KeyCode::ArrowDown => {
+                if let Some(cursor) = self.history_cursor {
+                    match self.history.get(cursor + 1) {
+                        Some(line) => {
+                            self.input = line.clone();
+                            self.history_cursor = Some(cursor + 1);
+                        }
+                        None => {
+                            self.input.clear();
+                            self.history_cursor = None;
+                        }
+                    }
+                }
+            }
+            KeyCode::Tab => self.complete(),
+            _ => {
+                // the font only has printable ASCII
+                if let Some(text) = &event.text {
+                    self.input
+                        .extend(text.chars().filter(|c| (' '..='~').contains(c)));
+                }
+            }
+        }
+        None
+    }
+
+    /// Completes the word being typed to the longest prefix shared by the command names,
+    /// or by the variable names after `set`, `get` or `toggle`.
+    fn complete(&mut self) {
+        let (head, word) = match self.input.rsplit_once(' ') {
+            Some((head, word)) => (format!("{} ", head), word.to_string()),
+            None => (String::new(), self.input.clone()),
+        };
+        let names: Vec<&str> = if head.is_empty() {
+            self.commands.iter().map(|command| command.name).collect()
+        } else {
+            self.variables
+                .iter()
+                .map(|variable| variable.name)
+                .collect()
+        };
+        let matches: Vec<&str> = names
+            .into_iter()
+            .filter(|name| name.starts_with(word.as_str()))
+            .collect();
+        let Some(first) = matches.first() else {
+            return;
+        };
+        let shared = matches.iter().fold(first.len(), |len, name| {
+            first
+                .bytes()
+                .zip(name.bytes())
+                .take(len)
+                .take_while(|(a, b)| a == b)
+                .count()
+        });
+        self.input = format!("{}{}", head, &first[..shared]);
+        if matches.len() == 1 {
+            self.input.push(' ');
+        }
+    }
+
+    /// Queues the console over the top `height` pixels of a `width` wide target.
+    pub fn draw(&self, text: &TextRenderer, width: f32, height: f32) {
+        text.rect(0.0, 0.0, width, height, PANEL_COLOR);
+        let input_y = height - MARGIN - text.line_height();
+        let rows = ((input_y - MARGIN) / text.line_height()).max(0.0) as usize;
+        for (row, line) in self.log.iter().rev().take(rows).enumerate() {
+            text.text(
+                MARGIN,
+                input_y - (row + 1) as f32 * text.line_height(),
+                line,
+                TEXT_COLOR,
+            );
+        }
+        text.text(MARGIN, input_y, &format!("> {}_", self.input), INPUT_COLOR);
+    }
+
+    /// Runs one console line against the app and logs it with its output.
+    pub fn run(app: &mut GfxState, line: &str) {
+        app.console.print(&format!("> {}", line));
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((name, args)) = words.split_first() else {
+            return;
+        };
+        let run = app
+            .console
+            .commands
+            .iter()
+            .find(|command| command.name == *name)
+            .map(|command| command.run);
+        let result = match run {
+            Some(run) => run(app, args),
+            None => Err(anyhow!("unknown command {}, try help", name)),
+        };
+        match result {
+            Ok(output) => app.console.print(&output),
+            Err(e) => app.console.print(&format!("error: {:#}", e)),
+        }
+    }
+}
+
+fn help(app: &mut GfxState, _args: &[&str]) -> anyhow::Result<String> {
+    let console = &app.console;
+    let mut lines = vec!["commands:".to_string()];
+    for command in &console.commands {
+        lines.push(format!("  {:<12} {}", command.name, command.help));
+    }
+    lines.push("variables:".to_string());
+    for variable in &console.variables {
+        lines.push(format!("  {:<24} {}", variable.name, variable.help));
+    }
+    Ok(lines.join("\n"))
+}
+
+fn set(app: &mut GfxState, args: &[&str]) -> anyhow::Result<String> {
+    let [name, value @ ..] = args else {
+        bail!("set <variable> <value>");
+    };
+    if value.is_empty() {
+        bail!("set <variable> <value>");
+    }
+    let set = variable(app, name)?.set;
+    set(app, &value.join(" "))?;
+    get(app, &[*name])
+}
+
+fn get(app: &mut GfxState, args: &[&str]) -> anyhow::Result<String> {
+    let [name] = args else {
+        bail!("get <variable>");
+    };
+    let get = variable(app, name)?.get;
+    Ok(format!("{} = {}", name, get(app)))
+}
+
+fn toggle(app: &mut GfxState, args: &[&str]) -> anyhow::Result<String> {
+    if args.is_empty() {
+        bail!("toggle <bool variable or shortcut action>");
+    }
+    let name = args.join(" ");
+    if let Ok(variable) = variable(app, &name) {
+        let (read, write) = (variable.get, variable.set);
+        let value: bool = read(app)
+            .parse()
+            .map_err(|_| anyhow!("{} isn't a bool", name))?;
+        write(app, &(!value).to_string())?;
+        return Ok(format!("{} = {}", name, read(app)));
+    }
+    if app.run_action(&name) {
+        Ok(String::new())
+    } else {
+        bail!("no bool variable or shortcut action {:?}", name)
+    }
+}
+
+fn variable<'a>(app: &'a GfxState, name: &str) -> anyhow::Result<&'a Variable> {
+    app.console
+        .variables
+        .iter()
+        .find(|variable| variable.name == name)
+        .ok_or_else(|| anyhow!("no variable {}", name))
+}
@@ -0,0 +1,334 @@
+// Checks the pure CPU parts of the renderer, the ones that need neither a device nor a
+// shader to run, against scenes whose answers are known by hand.
+
+use std::collections::BTreeSet;
+
+use cgmath::{Point3, Vector3, Vector4};
+use winit::keyboard::KeyCode;
+
+#[cfg(feature = "path_tracing")]
+use crate::{
+    bvh::{Bvh, BvhNode},
+    static_geometry::Triangle,
+};
+use crate::{
+    camera::Camera,
+    config::Config,
+    frustum::{Aabb, Frustum},
+    hot_reload,
+    image_data::ImageData,
+    lightmap::LightmapSettings,
+    shortcuts::Chord,
+    static_geometry::{self, StaticBox},
+    uv_unwrap, GfxState,
+};
+
+/// Three boxes in a row along z, enough triangles that the tree has to split.
+pub fn boxes_in_a_row() -> Vec<StaticBox> {
+    [-4.0, 0.0, 4.0]
+        .into_iter()
+        .enumerate()
+        .map(|(i, z)| StaticBox {
+            center: [0.0, 0.0, z],
+            size: [2.0, 2.0, 2.0],
+            color: [0.25 * (i + 1) as f32; 3],
+        })
+        .collect()
+}
+
+#[cfg(feature = "path_tracing")]
+fn contains(node: &BvhNode, p: [f32; 3]) -> bool {
+    (0..3).all(|axis| node.min[axis] <= p[axis] && p[axis] <= node.max[axis])
+}
+
+#[cfg(feature = "path_tracing")]
+fn vertices(triangle: &Triangle) -> [[f32; 3]; 3] {
+    [triangle.v0, triangle.v1, triangle.v2].map(|v| [v[0], v[1], v[2]])
+}
+
+#[cfg(feature = "path_tracing")]
+#[test]
+fn bvh_leaves_cover_every_triangle_once_inside_their_bounds() {
+    let triangles = static_geometry::triangles(&boxes_in_a_row());
+    let bvh = Bvh::build(triangles.clone());
+    assert_eq!(bvh.triangles.len(), triangles.len());
+    assert!(bvh.nodes.len() > 1, "36 triangles should not fit one leaf");
+
+    let mut covered = vec![0; bvh.triangles.len()];
+    let mut stack = vec![0usize];
+    while let Some(index) = stack.pop() {
+        let node = &bvh.nodes[index];
+        let first = node.left_first as usize;
+        if node.count == 0 {
+            for child in [first, first + 1] {
+                let child_node = &bvh.nodes[child];
+                assert!(contains(node, child_node.min) && contains(node, child_node.max));
+                stack.push(child);
+            }
+            continue;
+        }
+        let leaf = first..first + node.count as usize;
+        for (count, triangle) in covered[leaf.clone()].iter_mut().zip(&bvh.triangles[leaf]) {
+            *count += 1;
+            assert!(vertices(triangle).iter().all(|&v| contains(node, v)));
+        }
+    }
+    assert!(covered.iter().all(|&n| n == 1), "{:?}", covered);
+    // the boxes sit apart along z, the root splits between them
+    let root = &bvh.nodes[0];
+    assert_eq!((root.min, root.max), ([-1.0, -1.0, -5.0], [1.0, 1.0, 5.0]));
+}
+
+#[cfg(feature = "path_tracing")]
+#[test]
+fn bvh_of_nothing_is_one_leaf_no_ray_hits() {
+    let bvh = Bvh::build(vec![]);
+    assert_eq!(bvh.nodes.len(), 1);
+    assert_eq!((bvh.nodes[0].left_first, bvh.nodes[0].count), (0, 1));
+    assert_eq!(vertices(&bvh.triangles[0]), [[0.0; 3]; 3]);
+}
+
+/// At the origin looking down -z, 90 degrees each way.
+fn square_camera() -> Camera {
+    Camera {
+        eye: Point3::new(0.0, 0.0, 0.0),
+        target: Point3::new(0.0, 0.0, -1.0),
+        up: Vector3::unit_y(),
+        aspect: 1.0,
+        fovy: 90.0,
+        znear: 0.1,
+        zfar: 100.0,
+    }
+}
+
+/// Which of the frustum's planes, in the order of `Frustum::planes`, a clip space point
+/// is outside of.
+fn outside(clip: Vector4<f32>) -> [bool; 6] {
+    [
+        clip.x < -clip.w,
+        clip.x > clip.w,
+        clip.y < -clip.w,
+        clip.y > clip.w,
+        clip.z < 0.0,
+        clip.z > clip.w,
+    ]
+}
+
+#[test]
+fn frustum_culls_only_boxes_wholly_outside_one_clip_plane() {
+    let view_proj = square_camera().build_view_projection_matrix();
+    let frustum = Frustum::from_view_proj(&view_proj);
+    let (mut seen, mut culled) = (0, 0);
+    // steps that don't divide the box's size, so some boxes cross each plane
+    for x in (-60..=60).map(|x| x as f32 * 0.7) {
+        for z in (-120..=10).map(|z| z as f32 * 1.3) {
+            let aabb = Aabb {
+                min: Point3::new(x - 0.5, -0.5, z - 0.5),
+                max: Point3::new(x + 0.5, 0.5, z + 0.5),
+            };
+            let corners = aabb
+                .corners()
+                .map(|corner| outside(view_proj * corner.to_homogeneous()));
+            let any_inside = corners.iter().any(|planes| !planes.contains(&true));
+            let all_behind_one = (0..6).any(|plane| corners.iter().all(|planes| planes[plane]));
+            let intersects = frustum.intersects(&aabb);
+            if any_inside {
+                assert!(intersects, "{:?} is in view", aabb);
+                seen += 1;
+            }
+            if all_behind_one {
+                assert!(!intersects, "{:?} is out of view", aabb);
+                culled += 1;
+            }
+        }
+    }
+    assert!(seen > 0 && culled > 0, "{} seen, {} culled", seen, culled);
+    // straight ahead and straight behind
+    let ahead = Aabb {
+        min: Point3::new(-0.5, -0.5, -10.5),
+        max: Point3::new(0.5, 0.5, -9.5),
+    };
+    assert!(frustum.intersects(&ahead));
+    let behind = Aabb {
+        min: Point3::new(-0.5, -0.5, 9.5),
+        max: Point3::new(0.5, 0.5, 10.5),
+    };
+    assert!(!frustum.intersects(&behind));
+}
+
+#[test]
+fn l_toggles_continuous_redraw_over_the_default_shortcuts() {
+    let shortcuts = GfxState::register_shortcuts(&Config::default());
+    assert_eq!(
+        shortcuts.action(Chord::key(KeyCode::KeyL)),
+        Some("continuous redraw")
+    );
+    assert_eq!(shortcuts.action(Chord::ctrl(KeyCode::KeyL)), None);
+}
+
+#[test]
+fn saves_images_as_png_and_exr() {
+    let image = ImageData {
+        width: 2,
+        height: 1,
+        pixels: vec![[0.0, 0.5, 1.0, 1.0], [4.0, -1.0, 0.25, 0.5]],
+    };
+    let dir = std::env::temp_dir().join(format!("image-data-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let png = dir.join("saved.png");
+    image.save_png(&png).unwrap();
+    let saved = image::open(&png).unwrap().into_rgba8();
+    // sRGB encoded, HDR values clamped
+    assert_eq!(saved.get_pixel(0, 0).0, [0, 188, 255, 255]);
+    assert_eq!(saved.get_pixel(1, 0).0, [255, 0, 137, 128]);
+
+    let exr = dir.join("saved.exr");
+    image.save_exr(&exr).unwrap();
+    let saved = image::open(&exr).unwrap().into_rgba32f();
+    let pixels: Vec<[f32; 4]> = saved.pixels().map(|pixel| pixel.0).collect();
+    assert_eq!(pixels, image.pixels);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn reverts_reloaded_shaders_to_the_last_source_that_built() {
+    // a name no shader uses, the sources are shared with the tests running alongside
+    let directory = std::env::temp_dir().join("hot reload test");
+    std::fs::create_dir_all(&directory).unwrap();
+    let path = directory.join("hot_reload_test.wgsl");
+    let save = |code: &str| {
+        std::fs::write(&path, code).unwrap();
+        hot_reload::reload(BTreeSet::from([path.clone()]))
+    };
+    let source = || hot_reload::source("hot_reload_test.wgsl", "baked");
+
+    save("first").revert();
+    assert_eq!(source(), "baked");
+    let first = save("first");
+    assert_eq!(first.files(), "hot_reload_test.wgsl");
+    assert_eq!(source(), "first");
+    save("broken").revert();
+    assert_eq!(source(), "first");
+    first.revert();
+    assert_eq!(source(), "baked");
+}
+
+#[cfg(feature = "ui")]
+#[test]
+fn collects_scopes_for_the_overlay() {
+    use crate::profiler::{profile_scope, Profiler};
+
+    let mut profiler = Profiler::new();
+    assert!(profiler.latest_scopes().is_empty());
+    profiler.toggle();
+    for _ in 0..3 {
+        profiler.new_frame();
+        profile_scope!("overlay outer");
+        {
+            profile_scope!("overlay inner", "data");
+        }
+    }
+    profiler.new_frame();
+    let scopes = profiler.latest_scopes();
+    let outer = scopes
+        .iter()
+        .position(|(_, label, _)| label == "overlay outer")
+        .unwrap();
+    assert_eq!(scopes[outer + 1].0, scopes[outer].0 + 1);
+    assert_eq!(scopes[outer + 1].1, "overlay inner data");
+    profiler.toggle();
+    assert!(profiler.latest_scopes().is_empty());
+}
+
+#[test]
+fn unwraps_a_box_into_a_chart_per_face() {
+    let b = StaticBox {
+        center: [1.0, 0.5, -2.0],
+        size: [2.0, 1.0, 3.0],
+        color: [0.5; 3],
+    };
+    let mesh: Vec<_> = static_geometry::face_triangles(&b)
+        .into_iter()
+        .flatten()
+        .collect();
+    let unwrap = uv_unwrap::unwrap(std::slice::from_ref(&mesh), 4.0, 32).unwrap();
+    assert_eq!(unwrap.charts.len(), 6);
+
+    let rect = |chart: &uv_unwrap::Chart| {
+        let [x, y] = chart.offset;
+        let [w, h] = chart.size;
+        (x, y, x + w, y + h)
+    };
+    for (i, chart) in unwrap.charts.iter().enumerate() {
+        assert_eq!(chart.triangles.len(), 2);
+        let (x0, y0, x1, y1) = rect(chart);
+        assert!(x1 <= unwrap.width && y1 <= unwrap.height);
+        for other in &unwrap.charts[i + 1..] {
+            let (u0, v0, u1, v1) = rect(other);
+            assert!(
+                x1 <= u0 || u1 <= x0 || y1 <= v0 || v1 <= y0,
+                "charts overlap"
+            );
+        }
+        // the corners land inside the padding
+        for p in chart.triangles.iter().flat_map(|&t| mesh[t]) {
+            let [x, y] = chart.texel(p);
+            let pad = uv_unwrap::PADDING as f32 - 1e-3;
+            assert!(
+                x >= x0 as f32 + pad && x <= x1 as f32 - pad,
+                "{} outside",
+                x
+            );
+            assert!(
+                y >= y0 as f32 + pad && y <= y1 as f32 - pad,
+                "{} outside",
+                y
+            );
+        }
+    }
+}
+
+#[test]
+fn unwraps_a_tilted_quad_into_one_chart() {
+    let (a, b, c, d) = (
+        [0.0, 0.0, 0.0],
+        [1.0, 1.0, 0.0],
+        [1.0, 1.0, 1.0],
+        [0.0, 0.0, 1.0],
+    );
+    let unwrap = uv_unwrap::unwrap(&[vec![[a, b, c], [a, c, d]]], 10.0, 64).unwrap();
+    assert_eq!(unwrap.charts.len(), 1);
+    let chart = &unwrap.charts[0];
+    // sqrt(2) by 1 meter, a texel of padding around
+    assert_eq!(chart.size, [15 + 2, 10 + 2]);
+    let [x, y] = chart.texel(c);
+    let [x0, y0] = chart.texel(a);
+    assert!(((x - x0).abs() - 2.0f32.sqrt() * 10.0).abs() < 1e-3);
+    assert!(((y - y0).abs() - 10.0).abs() < 1e-3);
+
+    assert!(uv_unwrap::unwrap(&[vec![[a, b, c], [a, c, d]]], 10.0, 8).is_err());
+}
+
+#[test]
+fn places_the_box_faces_under_the_ground_lightmap() {
+    let boxes = boxes_in_a_row();
+    let settings = LightmapSettings {
+        resolution: 64,
+        texels_per_meter: 4.0,
+        ..LightmapSettings::default()
+    };
+    let unwrap = settings.unwrap(&boxes).unwrap();
+    assert_eq!(unwrap.charts.len(), 18);
+    let size = (64, 64 + unwrap.height);
+    let charts = settings.charts(&boxes, size).unwrap();
+    for chart in &charts[..18] {
+        let [x, y, w, h] = *chart;
+        // a 2 meter face, the padding outside it
+        assert_eq!((w, h), (8.0, 8.0));
+        assert!(x >= 1.0 && x + w <= 63.0);
+        assert!(y >= 65.0 && y + h < size.1 as f32);
+    }
+    assert!(charts[18..].iter().all(|chart| *chart == [0.0; 4]));
+    assert!(settings.charts(&boxes, (64, 64)).is_none());
+}
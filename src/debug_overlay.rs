@@ -0,0 +1,310 @@
+use winit::keyboard::KeyCode;
+
+use crate::{
+    pixel_inspector::describe_id,
+    shortcuts::{Chord, Shortcuts},
+    text::TextRenderer,
+    GfxState,
+    GpuFatory::GpuFactory,
+};
+
+const HEADER_COLOR: [f32; 4] = [1.0, 0.85, 0.3, 1.0];
+const TEXT_COLOR: [f32; 4] = [0.95, 0.95, 0.95, 1.0];
+const PANEL_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.6];
+const ERROR_COLOR: [f32; 4] = [1.0, 0.4, 0.35, 1.0];
+// pixels around the panel's text
+const MARGIN: f32 = 8.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Adapter,
+    Surface,
+    Camera,
+    Timings,
+    Memory,
+    Passes,
+    Scopes,
+}
+
+const SECTIONS: [Section; 7] = [
+    Section::Adapter,
+    Section::Surface,
+    Section::Camera,
+    Section::Timings,
+    Section::Memory,
+    Section::Passes,
+    Section::Scopes,
+];
+
+impl Section {
+    fn title(self) -> &'static str {
+        match self {
+            Section::Adapter => "Adapter",
+            Section::Surface => "Surface",
+            Section::Camera => "Camera",
+            Section::Timings => "Frame timings",
+            Section::Memory => "Memory",
+            Section::Passes => "GPU passes",
+            Section::Scopes => "CPU scopes",
+        }
+    }
+}
+
+/// F3 style overlay over the top left of the window. Each section collapses to its title
+/// line, toggled with its number key while the overlay is up.
+pub struct DebugOverlay {
+    pub visible: bool,
+    expanded: [bool; SECTIONS.len()],
+}
+
+impl Default for DebugOverlay {
+    fn default() -> Self {
+        Self {
+            visible: false,
+            // the adapter and surface rarely change, the rest is what people look for
+            expanded: [false, false, true, true, true, true, true],
+        }
+    }
+}
+
+impl DebugOverlay {
+    pub fn register_shortcuts(shortcuts: &mut Shortcuts) {
+        shortcuts.register("debug overlay", "debug overlay", &[Chord::key(KeyCode::F3)]);
+        for (action, key) in [
+            ("overlay section 1", KeyCode::Digit1),
+            ("overlay section 2", KeyCode::Digit2),
+            ("overlay section 3", KeyCode::Digit3),
+            ("overlay section 4", KeyCode::Digit4),
+            ("overlay section 5", KeyCode::Digit5),
+            ("overlay section 6", KeyCode::Digit6),
+            ("overlay section 7", KeyCode::Digit7),
+        ] {
+            shortcuts.register("debug overlay", action, &[Chord::key(key)]);
+        }
+    }
+
+    /// Expands or collapses the section with 0 based `index`.
+    pub fn toggle_section(&mut self, index: usize) {
+        if let Some(expanded) = self.expanded.get_mut(index) {
+            *expanded = !*expanded;
+        }
+    }
+
+    /// Queues the panel and its text on `text`.
+    pub fn draw(&self, text: &TextRenderer, app: &GfxState, gpu_factory: &GpuFactory) {
+        let mut lines = vec![];
+        for (index, section) in SECTIONS.iter().enumerate() {
+            let expanded = self.expanded[index];
+            lines.push((
+                format!(
+                    "[{}] {} {}",
+                    index + 1,
+                    if expanded { "-" } else { "+" },
+                    section.title()
+                ),
+                HEADER_COLOR,
+            ));
+            if expanded {
+                for line in section_lines(*section, app, gpu_factory) {
+                    lines.push((format!("    {}", line), TEXT_COLOR));
+                }
+            }
+        }
+
+        let columns = lines.iter().map(|(line, _)| line.len()).max().unwrap_or(0);
+        text.rect(
+            0.0,
+            0.0,
+            columns as f32 * text.char_width() + 2.0 * MARGIN,
+            lines.len() as f32 * text.line_height() + 2.0 * MARGIN,
+            PANEL_COLOR,
+        );
+        for (row, (line, color)) in lines.iter().enumerate() {
+            text.text(
+                MARGIN,
+                MARGIN + row as f32 * text.line_height(),
+                line,
+                *color,
+            );
+        }
+    }
+}
+
+/// Queues `message` across the bottom of a `width` by `height` window, wrapped to fit,
+/// for a shader that didn't compile. Shown with or without the overlay.
+pub fn draw_error(text: &TextRenderer, message: &str, width: f32, height: f32) {
+    let columns = (((width - 2.0 * MARGIN) / text.char_width()) as usize).max(1);
+    let lines = message
+        .lines()
+        .flat_map(|line| {
+            let chars = line.chars().collect::<Vec<_>>();
+            // an empty line still takes a row
+            let rows = chars.len().div_ceil(columns).max(1);
+            (0..rows)
+                .map(|row| {
+                    let end = chars.len().min((row + 1) * columns);
+                    chars[(row * columns).min(end)..end]
+                        .iter()
+                        .collect::<String>()
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let panel_height = lines.len() as f32 * text.line_height() + 2.0 * MARGIN;
+    let top = (height - panel_height).max(0.0);
+    text.rect(0.0, top, width, panel_height, PANEL_COLOR);
+    for (row, line) in lines.iter().enumerate() {
+        text.text(
+            MARGIN,
+            top + MARGIN + row as f32 * text.line_height(),
+            line,
+            ERROR_COLOR,
+        );
+    }
+}
+
+fn section_lines(section: Section, app: &GfxState, gpu_factory: &GpuFactory) -> Vec<String> {
+    match section {
+        Section::Adapter => {
+            let info = &app.gpu.adapter_info;
+            vec![
+                info.name.clone(),
+                format!("{:?} {:?}", info.backend, info.device_type),
+                format!("driver {} {}", info.driver, info.driver_info),
+            ]
+        }
+        Section::Surface => {
+            let config = &app.window.surface_config;
+            vec![
+                format!("{}x{} {:?}", config.width, config.height, config.format),
+                format!("view formats {:?}", config.view_formats),
+                format!("{:?}, alpha {:?}", config.present_mode, config.alpha_mode),
+                format!("frame latency {}", config.desired_maximum_frame_latency),
+            ]
+        }
+        Section::Camera => {
+            let camera = &app.frame.camera;
+            vec![
+                format!(
+                    "eye {:.2} {:.2} {:.2}",
+                    camera.eye.x, camera.eye.y, camera.eye.z
+                ),
+                format!(
+                    "target {:.2} {:.2} {:.2}",
+                    camera.target.x, camera.target.y, camera.target.z
+                ),
+                format!(
+                    "fov {:.1}, near {}, far {}",
+                    camera.fovy, camera.znear, camera.zfar
+                ),
+                match gpu_factory.pixel_inspector.latest() {
+                    Some(sample) => format!(
+                        "pixel {:?} {}, depth {:.4}",
+                        sample.pixel,
+                        describe_id(sample.id),
+                        sample.depth
+                    ),
+                    None => "pixel n/a".to_string(),
+                },
+            ]
+        }
+        Section::Timings => {
+            let dt = app.frame.dt;
+            let stats = gpu_factory.stats();
+            let gpu_ms = gpu_factory
+                .gpu_timer
+                .as_ref()
+                .and_then(|timer| timer.latest_ms())
+                .map_or("n/a".to_string(), |ms| format!("{:.2} ms", ms));
+            let (width, height) = gpu_factory.render_scale.scene_size();
+            vec![
+                format!("{:.0} fps, {:.2} ms", 1.0 / dt.max(1e-6), dt * 1000.0),
+                format!(
+                    "continuous redraw {}",
+                    if app.config.render.continuous_redraw {
+                        "on"
+                    } else {
+                        "off"
+                    }
+                ),
+                format!(
+                    "encode {:.2} ms, gpu {}",
+                    gpu_factory.encode_ms.get(),
+                    gpu_ms
+                ),
+                format!(
+                    "{} frames in flight",
+                    gpu_factory.submitted_work.in_flight()
+                ),
+                format!("scene {}x{}", width, height),
+                format!(
+                    "{} draws, {} instances, {} triangles",
+                    stats.draw_calls, stats.instances, stats.triangles
+                ),
+                format!(
+                    "{} pipelines, {} bind groups, {} buffer writes",
+                    stats.pipeline_switches, stats.bind_group_sets, stats.buffer_writes
+                ),
+                format!(
+                    "{} meshes drawn, {} culled",
+                    gpu_factory.cull_stats().drawn,
+                    gpu_factory.cull_stats().culled
+                ),
+                format!(
+                    "pipeline cache {} hits, {} misses",
+                    gpu_factory.pipeline_cache.stats().hits,
+                    gpu_factory.pipeline_cache.stats().misses
+                ),
+            ]
+        }
+        Section::Memory => {
+            let mb = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+            let baked = |texture: &wgpu::Texture| {
+                texture.width() as u64
+                    * texture.height() as u64
+                    * texture.format().block_copy_size(None).unwrap_or(0) as u64
+            };
+            let progress = gpu_factory.asset_loader.progress();
+            vec![
+                format!(
+                    "render graph pool {:.1} MB",
+                    mb(gpu_factory.texture_pool.bytes())
+                ),
+                {
+                    let buffer_pool = gpu_factory.buffer_pool.borrow();
+                    format!(
+                        "buffer pool {:.1} of {:.1} MB",
+                        mb(buffer_pool.used_bytes()),
+                        mb(buffer_pool.bytes())
+                    )
+                },
+                format!(
+                    "baked maps {:.1} MB",
+                    mb(baked(&gpu_factory.lightmap.texture) + baked(&gpu_factory.ao_map.texture))
+                ),
+                format!("assets loaded {}/{}", progress.done, progress.total),
+            ]
+        }
+        Section::Passes => match gpu_factory.gpu_timer.as_ref() {
+            Some(timer) => timer
+                .latest_passes()
+                .into_iter()
+                .map(|(name, ms)| format!("{:<20} {:.3} ms", name, ms))
+                .collect(),
+            None => vec!["no timestamp queries on this adapter".to_string()],
+        },
+        Section::Scopes if !app.profiler.enabled() => {
+            vec!["profiler off, F12 records".to_string()]
+        }
+        // averaged over the recent frames, a thread's scopes under its name
+        Section::Scopes => app
+            .profiler
+            .latest_scopes()
+            .into_iter()
+            .map(|(depth, label, ms)| {
+                let label = format!("{}{}", "  ".repeat(depth), label);
+                format!("{:<28} {:.3} ms", label, ms)
+            })
+            .collect(),
+    }
+}
@@ -0,0 +1,36 @@
+/// What the display pass shows instead of the lit scene, for looking at one input of the
+/// lighting at a time. The values match `view_mode` in sky.wgsl, which only shades the
+/// ray cast scene; meshes aren't drawn in the debug views.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ViewMode {
+    #[default]
+    Final,
+    Albedo,
+    Normals,
+    /// distance to the camera, black at the camera and white at the far plane
+    Depth,
+    /// roughness in red; the ray cast surfaces have no metalness, so green stays empty
+    RoughnessMetallic,
+    /// surfaces along each view ray, the ray cast counterpart of overdraw
+    Overdraw,
+    /// sun visibility from the ray cast's per pixel shadow test and the clouds, not the
+    /// meshes' shadow map
+    Shadows,
+    /// how many lighting terms reach each pixel
+    LightComplexity,
+}
+
+impl ViewMode {
+    pub fn next(self) -> Self {
+        match self {
+            ViewMode::Final => ViewMode::Albedo,
+            ViewMode::Albedo => ViewMode::Normals,
+            ViewMode::Normals => ViewMode::Depth,
+            ViewMode::Depth => ViewMode::RoughnessMetallic,
+            ViewMode::RoughnessMetallic => ViewMode::Overdraw,
+            ViewMode::Overdraw => ViewMode::Shadows,
+            ViewMode::Shadows => ViewMode::LightComplexity,
+            ViewMode::LightComplexity => ViewMode::Final,
+        }
+    }
+}
@@ -0,0 +1,387 @@
+use std::borrow::Cow;
+
+use serde::{Deserialize, Serialize};
+use wgpu::{
+    BindGroup, BindGroupLayout, Buffer, PipelineCompilationOptions, RenderPipeline, Texture,
+    TextureFormat, TextureView,
+};
+
+use crate::{
+    error,
+    frame_stats::{self, CountedPass},
+    gbuffer::GBuffer,
+    light::{LocalLight, LocalLightUniform},
+    limits::RenderBudget,
+    tonemap::HDR_FORMAT,
+    wgsl_preprocessor,
+};
+
+/// albedo in gamma 2 and ambient occlusion, packed 4x8 unorm
+pub const ALBEDO_FORMAT: TextureFormat = TextureFormat::R32Uint;
+/// metallic, roughness, the `MaterialShader` over 2 and 1 where the pixel is deferred,
+/// packed 4x8 unorm
+pub const MATERIAL_FORMAT: TextureFormat = TextureFormat::R32Uint;
+
+// the HDR target, the G-buffer's three and the two above
+const COLOR_ATTACHMENTS: u32 = 6;
+
+/// How the meshes are shaded, chosen when the factory is built.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum RenderPath {
+    /// every opaque mesh lit as it's drawn
+    #[default]
+    Forward,
+    /// opaque meshes write their surfaces into a G-buffer and one fullscreen pass lights
+    /// them all, with the scene's `lights` on top of the sun and the point light. Blended
+    /// meshes are still drawn forward, over the lit result.
+    Deferred,
+}
+
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct DeferredParams {
+    light_count: u32,
+    _pad: [u32; 3],
+}
+
+/// The deferred path's targets next to the G-buffer, and the lighting pass reading them.
+/// The geometry pass reuses the G-buffer's surface normal and depth, so it writes the
+/// display pass's targets and these two.
+pub struct Deferred {
+    pub albedo: Texture,
+    pub albedo_view: TextureView,
+    pub material: Texture,
+    pub material_view: TextureView,
+    max_lights: u32,
+    params_buffer: Buffer,
+    lights_buffer: Buffer,
+    bind_group_layout: BindGroupLayout,
+    bind_group: BindGroup,
+    pipeline: RenderPipeline,
+}
+
+impl Deferred {
+    /// Whether the geometry pass's targets fit the limits.
+    pub fn supported(limits: &wgpu::Limits) -> bool {
+        limits.max_color_attachments >= COLOR_ATTACHMENTS
+    }
+
+    /// The scene, camera and light layouts are the material pipelines' groups 0, 1 and 3,
+    /// which the lighting pass reads too. Up to `budget.max_lights` of the scene's lights
+    /// are drawn.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &wgpu::Device,
+        budget: &RenderBudget,
+        gbuffer: &GBuffer,
+        scene_layout: &BindGroupLayout,
+        camera_layout: &BindGroupLayout,
+        light_layout: &BindGroupLayout,
+        width: u32,
+        height: u32,
+    ) -> error::Result<Self> {
+        let max_lights = budget.max_lights.max(1);
+        // webgl2 has no storage buffers, the lights go in a uniform array of the budget's size
+        let (lights_declaration, lights_binding, lights_usage) = if budget.storage_buffers {
+            (
+                "@group(2) @binding(5) var<storage, read> lights: array<LocalLight>;".to_string(),
+                wgpu::BufferBindingType::Storage { read_only: true },
+                wgpu::BufferUsages::STORAGE,
+            )
+        } else {
+            (
+                format!(
+                    "@group(2) @binding(5) var<uniform> lights: array<LocalLight, {}>;",
+                    max_lights
+                ),
+                wgpu::BufferBindingType::Uniform,
+                wgpu::BufferUsages::UNIFORM,
+            )
+        };
+        // unchecked, `lights` is only declared below
+        let code = wgsl_preprocessor::load("deferred.wgsl", &[]).map_err(error::Error::Shader)?;
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("deferred lighting shader"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Owned(format!(
+                "{}\n{}",
+                code.code, lights_declaration
+            ))),
+        });
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("deferred params"),
+            size: std::mem::size_of::<DeferredParams>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let lights_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("deferred lights"),
+            size: (std::mem::size_of::<LocalLightUniform>() as u32 * max_lights) as u64,
+            usage: lights_usage | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let texture_entry = |binding, sample_type| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let buffer_entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let float = wgpu::TextureSampleType::Float { filterable: false };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("deferred bind group layout"),
+            entries: &[
+                texture_entry(0, float),
+                texture_entry(1, float),
+                texture_entry(2, wgpu::TextureSampleType::Uint),
+                texture_entry(3, wgpu::TextureSampleType::Uint),
+                buffer_entry(4, wgpu::BufferBindingType::Uniform),
+                buffer_entry(5, lights_binding),
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("deferred lighting pipeline layout"),
+            bind_group_layouts: &[
+                scene_layout,
+                camera_layout,
+                &bind_group_layout,
+                light_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("deferred lighting pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "fullscreen_vs",
+                buffers: &[],
+                compilation_options: PipelineCompilationOptions::default(),
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "lighting_fs",
+                // on top of the emissive the geometry pass wrote, leaving its alpha
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: HDR_FORMAT,
+                    blend: Some(wgpu::BlendState {
+                        color: wgpu::BlendComponent {
+                            src_factor: wgpu::BlendFactor::One,
+                            dst_factor: wgpu::BlendFactor::One,
+                            operation: wgpu::BlendOperation::Add,
+                        },
+                        alpha: wgpu::BlendComponent {
+                            src_factor: wgpu::BlendFactor::Zero,
+                            dst_factor: wgpu::BlendFactor::One,
+                            operation: wgpu::BlendOperation::Add,
+                        },
+                    }),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: PipelineCompilationOptions::default(),
+            }),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let (albedo, albedo_view) =
+            Self::create_target(device, "deferred albedo", ALBEDO_FORMAT, width, height);
+        let (material, material_view) =
+            Self::create_target(device, "deferred material", MATERIAL_FORMAT, width, height);
+        let bind_group = Self::create_bind_group(
+            device,
+            &bind_group_layout,
+            gbuffer,
+            &albedo_view,
+            &material_view,
+            &params_buffer,
+            &lights_buffer,
+        );
+        Ok(Self {
+            albedo,
+            albedo_view,
+            material,
+            material_view,
+            max_lights,
+            params_buffer,
+            lights_buffer,
+            bind_group_layout,
+            bind_group,
+            pipeline,
+        })
+    }
+
+    fn create_target(
+        device: &wgpu::Device,
+        label: &str,
+        format: TextureFormat,
+        width: u32,
+        height: u32,
+    ) -> (Texture, TextureView) {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: width.max(1),
+                height: height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        (texture, view)
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &BindGroupLayout,
+        gbuffer: &GBuffer,
+        albedo_view: &TextureView,
+        material_view: &TextureView,
+        params_buffer: &Buffer,
+        lights_buffer: &Buffer,
+    ) -> BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("deferred bind group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&gbuffer.surface_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&gbuffer.depth_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(albedo_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(material_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: lights_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    /// Recreates the targets at the scene size, after the G-buffer they're read with.
+    pub fn resize(&mut self, device: &wgpu::Device, gbuffer: &GBuffer, width: u32, height: u32) {
+        (self.albedo, self.albedo_view) =
+            Self::create_target(device, "deferred albedo", ALBEDO_FORMAT, width, height);
+        (self.material, self.material_view) =
+            Self::create_target(device, "deferred material", MATERIAL_FORMAT, width, height);
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.bind_group_layout,
+            gbuffer,
+            &self.albedo_view,
+            &self.material_view,
+            &self.params_buffer,
+            &self.lights_buffer,
+        );
+    }
+
+    /// The geometry pass's targets after the display pass's, for the material pipelines.
+    pub fn color_targets() -> [Option<wgpu::ColorTargetState>; 2] {
+        [ALBEDO_FORMAT, MATERIAL_FORMAT].map(|format| {
+            Some(wgpu::ColorTargetState {
+                format,
+                blend: None,
+                write_mask: wgpu::ColorWrites::ALL,
+            })
+        })
+    }
+
+    /// Cleared, so pixels the geometry pass doesn't cover are left to the ray cast.
+    pub fn color_attachments(&self) -> [Option<wgpu::RenderPassColorAttachment<'_>>; 2] {
+        [&self.albedo_view, &self.material_view].map(|view| {
+            Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+            })
+        })
+    }
+
+    /// Lights past `budget.max_lights` are left out.
+    pub fn write_lights(&self, queue: &wgpu::Queue, lights: &[LocalLight]) {
+        let lights = &lights[..lights.len().min(self.max_lights as usize)];
+        if !lights.is_empty() {
+            let uniforms: Vec<LocalLightUniform> = lights.iter().map(LocalLight::uniform).collect();
+            frame_stats::write_buffer(
+                queue,
+                &self.lights_buffer,
+                0,
+                bytemuck::cast_slice(&uniforms),
+            );
+        }
+        let params = DeferredParams {
+            light_count: lights.len() as u32,
+            _pad: [0; 3],
+        };
+        frame_stats::write_buffer(queue, &self.params_buffer, 0, bytemuck::bytes_of(&params));
+    }
+
+    /// Adds the lighting of every deferred pixel onto `scene_view`, with the same groups
+    /// 0, 1 and 3 bound as the material pipelines.
+    pub fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        scene_view: &TextureView,
+        scene_bind_group: &BindGroup,
+        camera_bind_group: &BindGroup,
+        light_bind_group: &BindGroup,
+    ) {
+        let mut render_pass = CountedPass::begin(
+            encoder,
+            &wgpu::RenderPassDescriptor {
+                label: Some("deferred lighting pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: scene_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                ..Default::default()
+            },
+        );
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, scene_bind_group, &[]);
+        render_pass.set_bind_group(1, camera_bind_group, &[]);
+        render_pass.set_bind_group(2, &self.bind_group, &[]);
+        render_pass.set_bind_group(3, light_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,287 @@
+use serde::{Deserialize, Serialize};
+use wgpu::{
+    BindGroup, BindGroupLayout, Buffer, ComputePipeline, PipelineCompilationOptions, Texture,
+    TextureView,
+};
+
+use crate::{frame_stats, hot_reload::wgsl, tonemap::HDR_FORMAT};
+
+// each one doubles the footprint, 5 reach 2^5 * 2 = 64 pixels out
+const MAX_ITERATIONS: usize = 5;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DenoiseSettings {
+    pub enabled: bool,
+    /// À-Trous passes, at most 5
+    pub iterations: u32,
+    /// relative luminance difference tolerated in the first pass, halved every pass after
+    pub color_sigma: f32,
+    /// exponent on the normals' dot product, higher keeps edges sharper
+    pub normal_sigma: f32,
+    /// relative depth difference tolerated per pixel of step
+    pub depth_sigma: f32,
+}
+
+impl Default for DenoiseSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            iterations: 5,
+            color_sigma: 4.0,
+            normal_sigma: 64.0,
+            depth_sigma: 0.01,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct DenoiseParams {
+    step: u32,
+    iteration: u32,
+    last: u32,
+    color_sigma: f32,
+    normal_sigma: f32,
+    depth_sigma: f32,
+    _pad: [f32; 2],
+}
+
+/// Edge avoiding À-Trous wavelet filter for the path tracer's output. Lighting is
+/// filtered with the albedo divided out, steered by the first hit's normal and depth,
+/// and ping-pongs between two textures with the sample spacing doubling every pass.
+pub struct Denoiser {
+    // one per pass, the step is baked into each
+    params_buffers: Vec<Buffer>,
+    targets: [Texture; 2],
+    bind_group_layout: BindGroupLayout,
+    // pass i reads the noisy input (i = 0) or targets[(i - 1) % 2] and writes targets[i % 2]
+    bind_groups: Vec<BindGroup>,
+    pipeline: ComputePipeline,
+}
+
+impl Denoiser {
+    pub fn new(
+        device: &wgpu::Device,
+        noisy: &TextureView,
+        albedo: &TextureView,
+        normal_depth: &TextureView,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let code = wgsl!("denoise.wgsl");
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("denoise shader"),
+            source: wgpu::ShaderSource::Wgsl(code),
+        });
+        let params_buffers: Vec<_> = (0..MAX_ITERATIONS)
+            .map(|_| {
+                device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("denoise params"),
+                    size: std::mem::size_of::<DenoiseParams>() as u64,
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                })
+            })
+            .collect();
+        let targets = Self::create_targets(device, width, height);
+
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("denoise bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                texture_entry(1),
+                texture_entry(2),
+                texture_entry(3),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: HDR_FORMAT,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_groups = Self::create_bind_groups(
+            device,
+            &bind_group_layout,
+            &params_buffers,
+            &targets,
+            noisy,
+            albedo,
+            normal_depth,
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("denoise pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("denoise pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "denoise",
+            compilation_options: PipelineCompilationOptions::default(),
+        });
+
+        Self {
+            params_buffers,
+            targets,
+            bind_group_layout,
+            bind_groups,
+            pipeline,
+        }
+    }
+
+    fn create_targets(device: &wgpu::Device, width: u32, height: u32) -> [Texture; 2] {
+        [0, 1].map(|_| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("denoise target"),
+                size: wgpu::Extent3d {
+                    width: width.max(1),
+                    height: height.max(1),
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: HDR_FORMAT,
+                usage: wgpu::TextureUsages::STORAGE_BINDING
+                    | wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_SRC,
+                view_formats: &[],
+            })
+        })
+    }
+
+    fn create_bind_groups(
+        device: &wgpu::Device,
+        layout: &BindGroupLayout,
+        params_buffers: &[Buffer],
+        targets: &[Texture; 2],
+        noisy: &TextureView,
+        albedo: &TextureView,
+        normal_depth: &TextureView,
+    ) -> Vec<BindGroup> {
+        let views = targets
+            .each_ref()
+            .map(|target| target.create_view(&Default::default()));
+        params_buffers
+            .iter()
+            .enumerate()
+            .map(|(pass, params_buffer)| {
+                let source = if pass == 0 {
+                    noisy
+                } else {
+                    &views[(pass - 1) % 2]
+                };
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("denoise bind group"),
+                    layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: params_buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::TextureView(source),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: wgpu::BindingResource::TextureView(albedo),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 3,
+                            resource: wgpu::BindingResource::TextureView(normal_depth),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 4,
+                            resource: wgpu::BindingResource::TextureView(&views[pass % 2]),
+                        },
+                    ],
+                })
+            })
+            .collect()
+    }
+
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        noisy: &TextureView,
+        albedo: &TextureView,
+        normal_depth: &TextureView,
+        width: u32,
+        height: u32,
+    ) {
+        self.targets = Self::create_targets(device, width, height);
+        self.bind_groups = Self::create_bind_groups(
+            device,
+            &self.bind_group_layout,
+            &self.params_buffers,
+            &self.targets,
+            noisy,
+            albedo,
+            normal_depth,
+        );
+    }
+
+    /// Filters the noisy input and returns the texture holding the result.
+    pub fn encode(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        queue: &wgpu::Queue,
+        settings: &DenoiseSettings,
+    ) -> &Texture {
+        let iterations = (settings.iterations as usize).clamp(1, MAX_ITERATIONS);
+        let (width, height) = (self.targets[0].width(), self.targets[0].height());
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("denoise pass"),
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        for iteration in 0..iterations {
+            let params = DenoiseParams {
+                step: 1 << iteration,
+                iteration: iteration as u32,
+                last: (iteration + 1 == iterations) as u32,
+                color_sigma: settings.color_sigma,
+                normal_sigma: settings.normal_sigma,
+                depth_sigma: settings.depth_sigma,
+                _pad: [0.0; 2],
+            };
+            frame_stats::write_buffer(
+                queue,
+                &self.params_buffers[iteration],
+                0,
+                bytemuck::bytes_of(&params),
+            );
+            pass.set_bind_group(0, &self.bind_groups[iteration], &[]);
+            pass.dispatch_workgroups(width.div_ceil(8), height.div_ceil(8), 1);
+        }
+        &self.targets[(iterations - 1) % 2]
+    }
+}
@@ -0,0 +1,73 @@
+use wgpu::{Texture, TextureFormat, TextureView};
+
+/// hardware depth of the display pass, cleared to the far plane
+pub const FORMAT: TextureFormat = TextureFormat::Depth32Float;
+
+/// The display pass's depth attachment. The ray cast writes each pixel's NDC depth into it
+/// (the same value as the G-buffer's depth target), so geometry rasterized into the pass
+/// is tested against the scene and against itself.
+pub struct DepthBuffer {
+    // never read, kept so the texture lives as long as its view
+    _texture: Texture,
+    pub view: TextureView,
+}
+
+impl DepthBuffer {
+    /// At the scene size, the same as the color targets of the pass.
+    pub fn new(device: &wgpu::Device, width: u32, height: u32) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("depth buffer"),
+            size: wgpu::Extent3d {
+                width: width.max(1),
+                height: height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Self {
+            _texture: texture,
+            view,
+        }
+    }
+
+    /// For pipelines drawing into the display pass. Less-equal, so the sky at exactly the
+    /// far plane still passes against the clear value.
+    pub fn depth_stencil_state() -> wgpu::DepthStencilState {
+        wgpu::DepthStencilState {
+            format: FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::LessEqual,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }
+    }
+
+    pub fn attachment(&self) -> wgpu::RenderPassDepthStencilAttachment<'_> {
+        wgpu::RenderPassDepthStencilAttachment {
+            view: &self.view,
+            depth_ops: Some(wgpu::Operations {
+                load: wgpu::LoadOp::Clear(1.0),
+                store: wgpu::StoreOp::Store,
+            }),
+            stencil_ops: None,
+        }
+    }
+
+    /// For passes after the display pass, tested against what it left.
+    pub fn load_attachment(&self) -> wgpu::RenderPassDepthStencilAttachment<'_> {
+        wgpu::RenderPassDepthStencilAttachment {
+            view: &self.view,
+            depth_ops: Some(wgpu::Operations {
+                load: wgpu::LoadOp::Load,
+                store: wgpu::StoreOp::Store,
+            }),
+            stencil_ops: None,
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use std::{
+    cell::Cell,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use serde::{Deserialize, Serialize};
+
+/// When the device gets polled. map_async and on_submitted_work_done callbacks only ever
+/// run from a poll, so everything waiting on one relies on this.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum PollPolicy {
+    /// Poll without blocking at the start of every frame, and while the loop idles with
+    /// readbacks pending. Frames overlap with the GPU.
+    #[default]
+    Poll,
+    /// Also wait for each frame's work right after submitting it, for headless and
+    /// benchmark runs where every frame should be finished before the next one starts.
+    Wait,
+}
+
+impl PollPolicy {
+    /// Call before recording a frame; runs the callbacks of whatever finished since.
+    pub fn begin_frame(self, device: &wgpu::Device) {
+        device.poll(wgpu::Maintain::Poll);
+    }
+
+    /// Call right after submitting a frame.
+    pub fn end_frame(self, device: &wgpu::Device, submission: wgpu::SubmissionIndex) {
+        if self == PollPolicy::Wait {
+            device.poll(wgpu::Maintain::WaitForSubmissionIndex(submission));
+        }
+    }
+}
+
+/// Counts submissions and, from on_submitted_work_done, how many of them the GPU finished.
+pub struct SubmittedWork {
+    submitted: Cell<u64>,
+    // written from the callback, on whatever thread polled
+    completed: Arc<AtomicU64>,
+}
+
+impl Default for SubmittedWork {
+    fn default() -> Self {
+        Self {
+            submitted: Cell::new(0),
+            completed: Arc::new(AtomicU64::new(0)),
+        }
+    }
+}
+
+impl SubmittedWork {
+    /// Submits `commands` and counts them as done once the GPU is through with them.
+    pub fn submit(
+        &self,
+        queue: &wgpu::Queue,
+        commands: impl IntoIterator<Item = wgpu::CommandBuffer>,
+    ) -> wgpu::SubmissionIndex {
+        let submission = queue.submit(commands);
+        let number = self.submitted.get() + 1;
+        self.submitted.set(number);
+        let completed = self.completed.clone();
+        queue.on_submitted_work_done(move || {
+            completed.fetch_max(number, Ordering::SeqCst);
+        });
+        submission
+    }
+
+    /// Submissions the GPU hasn't been seen to finish yet.
+    #[cfg(feature = "ui")]
+    pub fn in_flight(&self) -> u64 {
+        self.submitted.get() - self.completed.load(Ordering::SeqCst)
+    }
+}
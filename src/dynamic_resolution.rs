@@ -0,0 +1,91 @@
+use serde::{Deserialize, Serialize};
+
+// scales are snapped to this so small swings don't recreate the targets every frame
+const STEP: f32 = 0.05;
+// seconds to wait after a change before judging the frame time again
+const COOLDOWN: f32 = 0.5;
+// the scale drops above this fraction of the frame budget and rises below the other
+const OVER_BUDGET: f32 = 0.95;
+const UNDER_BUDGET: f32 = 0.75;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DynamicResolutionSettings {
+    pub enabled: bool,
+    pub target_fps: f32,
+    /// render scale per axis the scene may drop to, and rise to
+    pub min_scale: f32,
+    pub max_scale: f32,
+}
+
+impl Default for DynamicResolutionSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            target_fps: 60.0,
+            min_scale: 0.5,
+            max_scale: 1.0,
+        }
+    }
+}
+
+/// Picks the scene's render scale from how long frames take, lowering it when frames run
+/// over the target frame rate's budget and raising it again when there's room.
+pub struct DynamicResolution {
+    scale: f32,
+    smoothed_ms: Option<f32>,
+    cooldown: f32,
+}
+
+impl Default for DynamicResolution {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            smoothed_ms: None,
+            cooldown: 0.0,
+        }
+    }
+}
+
+impl DynamicResolution {
+    /// Current scale per axis, 1 when disabled.
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Feeds one frame's time in milliseconds, `dt` seconds after the last.
+    /// True when the scale changed and the scene targets need resizing.
+    pub fn update(&mut self, settings: &DynamicResolutionSettings, frame_ms: f32, dt: f32) -> bool {
+        let min_scale = settings.min_scale.clamp(STEP, 1.0);
+        let max_scale = settings.max_scale.max(min_scale);
+        let scale = if settings.enabled {
+            let smoothed = match self.smoothed_ms {
+                Some(smoothed) => smoothed + (frame_ms - smoothed) * (1.0 - (-dt * 4.0).exp()),
+                None => frame_ms,
+            };
+            self.smoothed_ms = Some(smoothed);
+            self.cooldown -= dt;
+            let budget = 1000.0 / settings.target_fps.max(1.0);
+            if self.cooldown > 0.0
+                || (smoothed > budget * UNDER_BUDGET && smoothed < budget * OVER_BUDGET)
+            {
+                self.scale
+            } else {
+                // frame time goes with the pixel count, so with the square of the scale
+                let wanted = self.scale * (budget * 0.85 / smoothed.max(0.01)).sqrt();
+                ((wanted / STEP).round() * STEP).clamp(min_scale, max_scale)
+            }
+        } else {
+            self.smoothed_ms = None;
+            1.0
+        };
+        if scale == self.scale {
+            return false;
+        }
+        self.scale = scale;
+        self.cooldown = COOLDOWN;
+        // frames measured at the old size say nothing about the new one
+        self.smoothed_ms = None;
+        true
+    }
+}
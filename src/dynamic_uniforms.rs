@@ -0,0 +1,119 @@
+use wgpu::{util::DeviceExt, Buffer};
+
+use crate::frame_stats;
+
+/// Values of `T` for many objects in one uniform buffer, each in a slice of its own
+/// starting on the device's `min_uniform_buffer_offset_alignment`. Bound once with
+/// `has_dynamic_offset`, a draw picks its object's slice by offset:
+///
+///     render_pass.set_bind_group(2, &bind_group, &[uniforms.offset(index)]);
+///
+/// rather than each object having a buffer and a bind group of its own. The buffer doubles
+/// when it's full, and the bind groups over it have to be made again.
+pub struct DynamicUniforms<T> {
+    pub buffer: Buffer,
+    label: &'static str,
+    // bytes from one slice to the next
+    stride: u64,
+    capacity: usize,
+    // what's in the buffer, packed, to fill a bigger one with
+    values: Vec<T>,
+}
+
+impl<T: bytemuck::Pod> DynamicUniforms<T> {
+    pub fn new(device: &wgpu::Device, label: &'static str, capacity: usize) -> Self {
+        let stride = Self::stride(device.limits().min_uniform_buffer_offset_alignment);
+        let capacity = capacity.max(1);
+        Self {
+            buffer: Self::create_buffer(device, label, stride, capacity, &[]),
+            label,
+            stride,
+            capacity,
+            values: vec![],
+        }
+    }
+
+    /// The size of `T` rounded up to `alignment`, which wgpu keeps a power of two.
+    pub fn stride(alignment: u32) -> u64 {
+        let alignment = alignment as u64;
+        (std::mem::size_of::<T>() as u64).div_ceil(alignment) * alignment
+    }
+
+    /// Adds `value` after the others, returning its index and whether the buffer had to
+    /// grow for it, which leaves the bind groups over the old one stale.
+    pub fn push(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, value: T) -> (usize, bool) {
+        let index = self.values.len();
+        self.values.push(value);
+        if index < self.capacity {
+            self.write(queue, index, &value);
+            return (index, false);
+        }
+        self.capacity *= 2;
+        self.buffer =
+            Self::create_buffer(device, self.label, self.stride, self.capacity, &self.values);
+        (index, true)
+    }
+
+    /// Changes the value at `index` from the next submit on.
+    pub fn write(&mut self, queue: &wgpu::Queue, index: usize, value: &T) {
+        self.values[index] = *value;
+        frame_stats::write_buffer(
+            queue,
+            &self.buffer,
+            self.offset(index) as u64,
+            bytemuck::bytes_of(value),
+        );
+    }
+
+    /// For `set_bind_group`'s dynamic offsets.
+    pub fn offset(&self, index: usize) -> u32 {
+        (index as u64 * self.stride) as u32
+    }
+
+    /// One `T` of the buffer, moved along it by the dynamic offset.
+    pub fn binding(&self) -> wgpu::BindingResource<'_> {
+        wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+            buffer: &self.buffer,
+            offset: 0,
+            size: Self::size(),
+        })
+    }
+
+    pub fn layout_entry(
+        binding: u32,
+        visibility: wgpu::ShaderStages,
+    ) -> wgpu::BindGroupLayoutEntry {
+        wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: true,
+                min_binding_size: Self::size(),
+            },
+            count: None,
+        }
+    }
+
+    fn size() -> Option<wgpu::BufferSize> {
+        wgpu::BufferSize::new(std::mem::size_of::<T>() as u64)
+    }
+
+    fn create_buffer(
+        device: &wgpu::Device,
+        label: &str,
+        stride: u64,
+        capacity: usize,
+        values: &[T],
+    ) -> Buffer {
+        let mut contents = vec![0; stride as usize * capacity];
+        for (slice, value) in contents.chunks_exact_mut(stride as usize).zip(values) {
+            slice[..std::mem::size_of::<T>()].copy_from_slice(bytemuck::bytes_of(value));
+        }
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(label),
+            contents: &contents,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        })
+    }
+}
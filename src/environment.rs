@@ -0,0 +1,160 @@
+use cgmath::{Matrix4, Point3, Vector3};
+use wgpu::{util::DeviceExt, BindGroup, Buffer, Texture, TextureView};
+
+use crate::{
+    camera::{CameraUniform, OPENGL_TO_WGPU_MATRIX},
+    frame_stats::{self, CountedPass},
+    tonemap::HDR_FORMAT,
+};
+
+pub const ENVIRONMENT_SIZE: u32 = 128;
+
+/// forward and up per cube face, in the +X -X +Y -Y +Z -Z layer order
+const FACES: [(Vector3<f32>, Vector3<f32>); 6] = [
+    (Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0)),
+    (Vector3::new(-1.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0)),
+    (Vector3::new(0.0, 1.0, 0.0), Vector3::new(0.0, 0.0, -1.0)),
+    (Vector3::new(0.0, -1.0, 0.0), Vector3::new(0.0, 0.0, 1.0)),
+    (Vector3::new(0.0, 0.0, 1.0), Vector3::new(0.0, 1.0, 0.0)),
+    (Vector3::new(0.0, 0.0, -1.0), Vector3::new(0.0, 1.0, 0.0)),
+];
+
+/// Cameras for the 6 faces of a cube map around `position`.
+/// Cube faces are addressed left to right as seen from outside, so x is mirrored
+/// compared to a normal camera looking out from the center.
+pub fn face_cameras(position: Point3<f32>) -> [CameraUniform; 6] {
+    use cgmath::SquareMatrix;
+    let mirror = Matrix4::from_nonuniform_scale(-1.0, 1.0, 1.0);
+    let proj = cgmath::perspective(cgmath::Deg(90.0), 1.0, 0.1, 100.0);
+    FACES.map(|(forward, up)| {
+        let view = Matrix4::look_to_rh(position, forward, up);
+        let view_proj = OPENGL_TO_WGPU_MATRIX * mirror * proj * view;
+        CameraUniform {
+            view_proj: view_proj.into(),
+            inv_view_proj: view_proj.invert().unwrap_or(Matrix4::identity()).into(),
+            view_position: position.to_homogeneous().into(),
+        }
+    })
+}
+
+/// The sky around the camera captured into a cube map each frame, for whatever needs
+/// the environment in directions the screen doesn't cover (reflection misses).
+pub struct EnvironmentMap {
+    pub texture: Texture,
+    pub view: TextureView,
+    face_views: Vec<TextureView>,
+    camera_buffers: Vec<Buffer>,
+    camera_bind_groups: Vec<BindGroup>,
+}
+
+impl EnvironmentMap {
+    pub fn new(device: &wgpu::Device, camera_bind_group_layout: &wgpu::BindGroupLayout) -> Self {
+        Self::with_size(device, camera_bind_group_layout, ENVIRONMENT_SIZE)
+    }
+
+    /// `size` texels per face edge.
+    pub fn with_size(
+        device: &wgpu::Device,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        size: u32,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("environment map"),
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 6,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: HDR_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("environment cube view"),
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        });
+        let face_views = (0..6)
+            .map(|layer| {
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("environment face view"),
+                    dimension: Some(wgpu::TextureViewDimension::D2),
+                    base_array_layer: layer,
+                    array_layer_count: Some(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+
+        let camera_buffers: Vec<Buffer> = face_cameras(Point3::new(0.0, 1.0, 0.0))
+            .iter()
+            .map(|camera| {
+                device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("environment face camera"),
+                    contents: bytemuck::bytes_of(camera),
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                })
+            })
+            .collect();
+        let camera_bind_groups = camera_buffers
+            .iter()
+            .map(|buffer| {
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("environment face camera bind group"),
+                    layout: camera_bind_group_layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: buffer.as_entire_binding(),
+                    }],
+                })
+            })
+            .collect();
+
+        Self {
+            texture,
+            view,
+            face_views,
+            camera_buffers,
+            camera_bind_groups,
+        }
+    }
+
+    /// Renders the six faces from `position` with `pipeline`, a sky pipeline whose
+    /// group 0 is `scene_bind_group` and group 1 the camera.
+    pub fn capture(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        queue: &wgpu::Queue,
+        position: Point3<f32>,
+        pipeline: &wgpu::RenderPipeline,
+        scene_bind_group: &BindGroup,
+    ) {
+        for (camera, buffer) in face_cameras(position).iter().zip(&self.camera_buffers) {
+            frame_stats::write_buffer(queue, buffer, 0, bytemuck::bytes_of(camera));
+        }
+        for (face_view, camera_bind_group) in self.face_views.iter().zip(&self.camera_bind_groups) {
+            let mut render_pass = CountedPass::begin(
+                encoder,
+                &wgpu::RenderPassDescriptor {
+                    label: Some("environment face pass"),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: face_view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                            store: wgpu::StoreOp::Store,
+                        },
+                    })],
+                    ..Default::default()
+                },
+            );
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(0, scene_bind_group, &[]);
+            render_pass.set_bind_group(1, camera_bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use std::{fmt, io::Write, path::PathBuf};
+
+/// What stops the app. Everything here is fatal: it's reported with `report_fatal` and the
+/// app shuts down. Problems the app carries on from (a scene that won't load, a failed
+/// screenshot) stay `anyhow` errors, logged where they happen.
+#[derive(Debug)]
+pub enum Error {
+    EventLoop(winit::error::EventLoopError),
+    Window(winit::error::OsError),
+    Surface(wgpu::CreateSurfaceError),
+    NoAdapter,
+    Device(wgpu::RequestDeviceError),
+    // the adapter can't present to the window's surface at all
+    UnsupportedSurface,
+    // a validation error, from building pipelines or from a frame
+    Gpu(String),
+    // a WGSL file that doesn't preprocess or parse, with where in which file
+    Shader(String),
+    OutOfMemory,
+    // the render thread panicked or went away
+    RenderThread,
+    // a benchmark frame went over `render.stats_budget`, with the counters that did
+    OverBudget(Vec<String>),
+    // custom pipelines the builder was given that can't go in the registry
+    Builder(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::EventLoop(e) => write!(f, "can't start the event loop: {}", e),
+            Self::Window(e) => write!(f, "can't open a window: {}", e),
+            Self::Surface(e) => write!(f, "can't create a surface for the window: {}", e),
+            Self::NoAdapter => write!(f, "no suitable GPU adapter found on the system"),
+            Self::Device(e) => write!(f, "can't create the GPU device: {}", e),
+            Self::UnsupportedSurface => write!(f, "the GPU adapter can't present to the window"),
+            Self::Gpu(message) => write!(f, "GPU error: {}", message),
+            Self::Shader(message) => write!(f, "shader error: {}", message),
+            Self::OutOfMemory => write!(f, "out of GPU memory"),
+            Self::RenderThread => write!(f, "the render thread stopped unexpectedly"),
+            Self::OverBudget(counters) => write!(f, "frame over budget: {}", counters.join(", ")),
+            Self::Builder(message) => write!(f, "can't build the custom pipelines: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::EventLoop(e) => Some(e),
+            Self::Window(e) => Some(e),
+            Self::Surface(e) => Some(e),
+            Self::Device(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<winit::error::EventLoopError> for Error {
+    fn from(e: winit::error::EventLoopError) -> Self {
+        Self::EventLoop(e)
+    }
+}
+
+impl From<winit::error::OsError> for Error {
+    fn from(e: winit::error::OsError) -> Self {
+        Self::Window(e)
+    }
+}
+
+impl From<wgpu::CreateSurfaceError> for Error {
+    fn from(e: wgpu::CreateSurfaceError) -> Self {
+        Self::Surface(e)
+    }
+}
+
+impl From<wgpu::RequestDeviceError> for Error {
+    fn from(e: wgpu::RequestDeviceError) -> Self {
+        Self::Device(e)
+    }
+}
+
+impl From<wgpu::Error> for Error {
+    fn from(e: wgpu::Error) -> Self {
+        match e {
+            wgpu::Error::OutOfMemory { .. } => Self::OutOfMemory,
+            e => Self::Gpu(e.to_string()),
+        }
+    }
+}
+
+/// Where `report_fatal` writes, next to the config so it's found after the window is gone.
+pub fn log_path() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fatal_error.log")
+}
+
+/// Tells the user why the app is about to stop: on stderr, and in `log_path` for when
+/// nobody was watching the terminal.
+pub fn report_fatal(error: &Error) {
+    eprintln!("Fatal error: {}", error);
+    let written = std::fs::File::create(log_path())
+        .and_then(|mut file| writeln!(file, "Fatal error: {}\n\n{:#?}", error, error));
+    match written {
+        Ok(()) => eprintln!("Details written to {}", log_path().display()),
+        Err(e) => eprintln!("Couldn't write {}: {}", log_path().display(), e),
+    }
+}
@@ -0,0 +1,69 @@
+use wgpu::{DownlevelFlags, Features};
+
+/// Everything we would like to use but can live without.
+/// Whatever the adapter doesn't support is dropped from the request instead of failing
+/// device creation. Texture compression isn't asked for: every texture is decoded to
+/// uncompressed texels, nothing loads BC, ETC2 or ASTC blocks.
+pub const OPTIONAL_FEATURES: Features = Features::POLYGON_MODE_LINE
+    .union(Features::TIMESTAMP_QUERY)
+    .union(Features::TIMESTAMP_QUERY_INSIDE_ENCODERS)
+    .union(Features::PUSH_CONSTANTS)
+    .union(Features::MULTI_DRAW_INDIRECT);
+
+/// What the adapter actually granted of what we asked for.
+/// Subsystems query this instead of assuming a feature is there.
+#[derive(Debug, Clone, Copy)]
+pub struct GpuFeatures {
+    pub granted: Features,
+    /// draw arguments from a buffer at all, missing on webgl2; a downlevel flag, not a
+    /// feature to ask for
+    pub indirect_execution: bool,
+}
+
+impl GpuFeatures {
+    pub fn negotiate(adapter: &wgpu::Adapter, required: Features) -> Self {
+        let requested = required | OPTIONAL_FEATURES;
+        let mut optional = OPTIONAL_FEATURES & adapter.features();
+        // GL emulates push constants as uniforms and reads them back unaligned
+        if adapter.get_info().backend == wgpu::Backend::Gl {
+            optional.remove(Features::PUSH_CONSTANTS);
+        }
+        let granted = required | optional;
+        let missing = requested.difference(granted);
+        println!("Features granted: {:?}", granted);
+        if !missing.is_empty() {
+            println!("Features not available, falling back: {:?}", missing);
+        }
+        let indirect_execution = adapter
+            .get_downlevel_capabilities()
+            .flags
+            .contains(DownlevelFlags::INDIRECT_EXECUTION);
+        Self {
+            granted,
+            indirect_execution,
+        }
+    }
+
+    pub fn has(&self, features: Features) -> bool {
+        self.granted.contains(features)
+    }
+
+    /// PolygonMode::Line pipelines
+    pub fn wireframe(&self) -> bool {
+        self.has(Features::POLYGON_MODE_LINE)
+    }
+
+    /// CommandEncoder::write_timestamp, for timing whole frames
+    pub fn encoder_timestamps(&self) -> bool {
+        self.has(Features::TIMESTAMP_QUERY | Features::TIMESTAMP_QUERY_INSIDE_ENCODERS)
+    }
+
+    pub fn push_constants(&self) -> bool {
+        self.has(Features::PUSH_CONSTANTS)
+    }
+
+    /// RenderPass::multi_draw_indexed_indirect, many draws from one call
+    pub fn multi_draw_indirect(&self) -> bool {
+        self.indirect_execution && self.has(Features::MULTI_DRAW_INDIRECT)
+    }
+}
@@ -0,0 +1,96 @@
+use std::cell::{Cell, RefCell, RefMut};
+
+use wgpu::util::StagingBelt;
+
+use crate::registry::{BindGroupHandle, BufferHandle};
+
+/// Frames the CPU records ahead of the GPU; the one after waits for the first of them.
+pub const FRAMES_IN_FLIGHT: usize = 2;
+
+// bytes a staging belt hands out at a time, a frame's uniforms fit in one
+const STAGING_CHUNK_SIZE: u64 = 4096;
+
+/// What one of the frames in flight writes and the GPU reads: its own per-frame uniform
+/// buffer and the scene bind groups over it, the staging memory its uploads go through,
+/// and the submission that last used them, which has to be done before they're written
+/// again.
+///
+/// Uniforms written with GPU copies in the frame's own commands, as the camera is, don't
+/// need one each here: the queue orders the copy after the reads of the frames before.
+pub struct FrameContext {
+    /// `TheFirstUniformBuffer`, written whole every frame
+    pub uniform_buffer: BufferHandle,
+    /// group 0 of the display pass and the meshes, over `uniform_buffer`
+    pub scene_bind_group: BindGroupHandle,
+    /// the scene group with the placeholder instead of the planar reflection, for passes
+    /// that render the sky somewhere else than the main view
+    pub mirror_bind_group: BindGroupHandle,
+    staging_belt: RefCell<StagingBelt>,
+    submission: RefCell<Option<wgpu::SubmissionIndex>>,
+}
+
+impl FrameContext {
+    pub fn new(
+        uniform_buffer: BufferHandle,
+        scene_bind_group: BindGroupHandle,
+        mirror_bind_group: BindGroupHandle,
+    ) -> Self {
+        Self {
+            uniform_buffer,
+            scene_bind_group,
+            mirror_bind_group,
+            staging_belt: RefCell::new(StagingBelt::new(STAGING_CHUNK_SIZE)),
+            submission: RefCell::new(None),
+        }
+    }
+
+    /// For `frame_stats::write_staged`, finished before the frame is submitted.
+    pub fn staging_belt(&self) -> RefMut<'_, StagingBelt> {
+        self.staging_belt.borrow_mut()
+    }
+}
+
+/// The `FRAMES_IN_FLIGHT` contexts, each frame taking the next in turn.
+pub struct FrameRing {
+    contexts: Vec<FrameContext>,
+    current: Cell<usize>,
+}
+
+impl FrameRing {
+    pub fn new(contexts: Vec<FrameContext>) -> Self {
+        assert_eq!(contexts.len(), FRAMES_IN_FLIGHT);
+        Self {
+            contexts,
+            current: Cell::new(0),
+        }
+    }
+
+    /// Moves on to the next context, first waiting for the GPU to finish the frame that
+    /// used it `FRAMES_IN_FLIGHT` frames ago.
+    pub fn begin(&self, device: &wgpu::Device) -> &FrameContext {
+        self.current
+            .set((self.current.get() + 1) % FRAMES_IN_FLIGHT);
+        let context = self.current();
+        if let Some(submission) = context.submission.borrow_mut().take() {
+            device.poll(wgpu::Maintain::WaitForSubmissionIndex(submission));
+        }
+        context
+    }
+
+    /// The context of the frame being recorded.
+    pub fn current(&self) -> &FrameContext {
+        &self.contexts[self.current.get()]
+    }
+
+    /// Call with the current frame's submission; its staging memory comes back once the
+    /// GPU is through with it.
+    pub fn submitted(&self, submission: wgpu::SubmissionIndex) {
+        let context = self.current();
+        context.staging_belt.borrow_mut().recall();
+        *context.submission.borrow_mut() = Some(submission);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &FrameContext> {
+        self.contexts.iter()
+    }
+}
@@ -0,0 +1,192 @@
+use std::{
+    ops::{Deref, DerefMut, Range},
+    sync::atomic::{AtomicU32, AtomicU64, Ordering},
+};
+
+use serde::{Deserialize, Serialize};
+use wgpu::util::StagingBelt;
+
+/// What a frame asked of the GPU, counted as it's recorded. Also the shape of the budget
+/// benchmark runs check every frame against.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FrameStats {
+    pub draw_calls: u32,
+    pub instances: u32,
+    // assumes triangle lists, which is all this renderer draws
+    pub triangles: u64,
+    pub buffer_writes: u32,
+    pub bind_group_sets: u32,
+    pub pipeline_switches: u32,
+}
+
+impl FrameStats {
+    /// A budget generous enough for every feature switched on at once.
+    pub fn default_budget() -> Self {
+        Self {
+            draw_calls: 64,
+            instances: 65536,
+            triangles: 1 << 20,
+            buffer_writes: 64,
+            bind_group_sets: 128,
+            pipeline_switches: 64,
+        }
+    }
+
+    /// The counters that went over `budget`, as "name value/budget".
+    pub fn over_budget(&self, budget: &FrameStats) -> Vec<String> {
+        [
+            (
+                "draw calls",
+                self.draw_calls as u64,
+                budget.draw_calls as u64,
+            ),
+            ("instances", self.instances as u64, budget.instances as u64),
+            ("triangles", self.triangles, budget.triangles),
+            (
+                "buffer writes",
+                self.buffer_writes as u64,
+                budget.buffer_writes as u64,
+            ),
+            (
+                "bind group sets",
+                self.bind_group_sets as u64,
+                budget.bind_group_sets as u64,
+            ),
+            (
+                "pipeline switches",
+                self.pipeline_switches as u64,
+                budget.pipeline_switches as u64,
+            ),
+        ]
+        .into_iter()
+        .filter(|(_, value, budget)| value > budget)
+        .map(|(name, value, budget)| format!("{} {}/{}", name, value, budget))
+        .collect()
+    }
+}
+
+/// Meshes the CPU frustum culling let through and skipped in a frame.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CullStats {
+    pub drawn: u32,
+    pub culled: u32,
+}
+
+// process wide: with parallel encoding, graph passes record on worker threads
+static DRAW_CALLS: AtomicU32 = AtomicU32::new(0);
+static INSTANCES: AtomicU32 = AtomicU32::new(0);
+static TRIANGLES: AtomicU64 = AtomicU64::new(0);
+static BUFFER_WRITES: AtomicU32 = AtomicU32::new(0);
+static BIND_GROUP_SETS: AtomicU32 = AtomicU32::new(0);
+static PIPELINE_SWITCHES: AtomicU32 = AtomicU32::new(0);
+
+/// Everything counted since the last call, which starts the next frame's counts.
+pub fn take() -> FrameStats {
+    FrameStats {
+        draw_calls: DRAW_CALLS.swap(0, Ordering::Relaxed),
+        instances: INSTANCES.swap(0, Ordering::Relaxed),
+        triangles: TRIANGLES.swap(0, Ordering::Relaxed),
+        buffer_writes: BUFFER_WRITES.swap(0, Ordering::Relaxed),
+        bind_group_sets: BIND_GROUP_SETS.swap(0, Ordering::Relaxed),
+        pipeline_switches: PIPELINE_SWITCHES.swap(0, Ordering::Relaxed),
+    }
+}
+
+/// `queue.write_buffer`, counted.
+pub fn write_buffer(queue: &wgpu::Queue, buffer: &wgpu::Buffer, offset: u64, data: &[u8]) {
+    BUFFER_WRITES.fetch_add(1, Ordering::Relaxed);
+    queue.write_buffer(buffer, offset, data);
+}
+
+/// `write_buffer` through `belt`'s staging memory, the copy recorded on `encoder`. Counted
+/// the same.
+pub fn write_staged(
+    belt: &mut StagingBelt,
+    encoder: &mut wgpu::CommandEncoder,
+    device: &wgpu::Device,
+    buffer: &wgpu::Buffer,
+    data: &[u8],
+) {
+    let Some(size) = wgpu::BufferSize::new(data.len() as u64) else {
+        return;
+    };
+    BUFFER_WRITES.fetch_add(1, Ordering::Relaxed);
+    belt.write_buffer(encoder, buffer, 0, size, device)
+        .copy_from_slice(data);
+}
+
+/// A render pass that counts its pipeline, bind group and draw commands. Anything else goes
+/// straight through to the wgpu pass.
+pub struct CountedPass<'a> {
+    pass: wgpu::RenderPass<'a>,
+}
+
+impl<'a> CountedPass<'a> {
+    pub fn begin(
+        encoder: &'a mut wgpu::CommandEncoder,
+        desc: &wgpu::RenderPassDescriptor<'a, '_>,
+    ) -> Self {
+        Self {
+            pass: encoder.begin_render_pass(desc),
+        }
+    }
+
+    pub fn set_pipeline(&mut self, pipeline: &'a wgpu::RenderPipeline) {
+        PIPELINE_SWITCHES.fetch_add(1, Ordering::Relaxed);
+        self.pass.set_pipeline(pipeline);
+    }
+
+    pub fn set_bind_group(&mut self, index: u32, bind_group: &'a wgpu::BindGroup, offsets: &[u32]) {
+        BIND_GROUP_SETS.fetch_add(1, Ordering::Relaxed);
+        self.pass.set_bind_group(index, bind_group, offsets);
+    }
+
+    pub fn draw(&mut self, vertices: Range<u32>, instances: Range<u32>) {
+        count_draw(vertices.len(), instances.len());
+        self.pass.draw(vertices, instances);
+    }
+
+    pub fn draw_indexed(&mut self, indices: Range<u32>, base_vertex: i32, instances: Range<u32>) {
+        count_draw(indices.len(), instances.len());
+        self.pass.draw_indexed(indices, base_vertex, instances);
+    }
+
+    /// The instances and triangles are in `indirect_buffer`, only the draw is counted.
+    pub fn draw_indexed_indirect(&mut self, indirect_buffer: &'a wgpu::Buffer, offset: u64) {
+        DRAW_CALLS.fetch_add(1, Ordering::Relaxed);
+        self.pass.draw_indexed_indirect(indirect_buffer, offset);
+    }
+
+    /// Counted as `count` draws, like the loop it stands in for.
+    pub fn multi_draw_indexed_indirect(
+        &mut self,
+        indirect_buffer: &'a wgpu::Buffer,
+        offset: u64,
+        count: u32,
+    ) {
+        DRAW_CALLS.fetch_add(count, Ordering::Relaxed);
+        self.pass
+            .multi_draw_indexed_indirect(indirect_buffer, offset, count);
+    }
+}
+
+impl<'a> Deref for CountedPass<'a> {
+    type Target = wgpu::RenderPass<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.pass
+    }
+}
+
+impl<'a> DerefMut for CountedPass<'a> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.pass
+    }
+}
+
+fn count_draw(vertices: usize, instances: usize) {
+    DRAW_CALLS.fetch_add(1, Ordering::Relaxed);
+    INSTANCES.fetch_add(instances as u32, Ordering::Relaxed);
+    TRIANGLES.fetch_add((vertices / 3 * instances) as u64, Ordering::Relaxed);
+}
@@ -0,0 +1,95 @@
+use cgmath::{InnerSpace, Matrix4, Point3, Vector4};
+
+/// The six planes bounding what a view projection sees, normals pointing inside and of
+/// unit length, so a plane's `dot(xyz, p) + w` is a point's distance in front of it.
+#[derive(Debug, Clone, Copy)]
+pub struct Frustum {
+    // left, right, bottom, top, near, far
+    pub planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    /// From `view_proj`'s rows (Gribb and Hartmann), for wgpu's clip space where z runs
+    /// 0 to 1.
+    pub fn from_view_proj(view_proj: &Matrix4<f32>) -> Self {
+        let row = |i: usize| {
+            Vector4::new(
+                view_proj.x[i],
+                view_proj.y[i],
+                view_proj.z[i],
+                view_proj.w[i],
+            )
+        };
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+        let planes = [w + x, w - x, w + y, w - y, z, w - z].map(|plane| {
+            let length = plane.truncate().magnitude();
+            if length > 0.0 {
+                plane / length
+            } else {
+                plane
+            }
+        });
+        Self { planes }
+    }
+
+    pub fn to_uniform(self) -> [[f32; 4]; 6] {
+        self.planes.map(Into::into)
+    }
+
+    /// False only when `aabb` is wholly behind one of the planes. Boxes near a corner of
+    /// the frustum can pass without being in it, they're drawn for nothing.
+    pub fn intersects(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // the box's corner furthest along the plane's normal
+            let x = if plane.x >= 0.0 {
+                aabb.max.x
+            } else {
+                aabb.min.x
+            };
+            let y = if plane.y >= 0.0 {
+                aabb.max.y
+            } else {
+                aabb.min.y
+            };
+            let z = if plane.z >= 0.0 {
+                aabb.max.z
+            } else {
+                aabb.min.z
+            };
+            plane.x * x + plane.y * y + plane.z * z + plane.w >= 0.0
+        })
+    }
+}
+
+/// An axis aligned bounding box.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl Aabb {
+    /// None without any points.
+    pub fn from_points(points: impl IntoIterator<Item = Point3<f32>>) -> Option<Self> {
+        points.into_iter().fold(None, |aabb, p| {
+            Some(match aabb {
+                Some(Aabb { min, max }) => Aabb {
+                    min: Point3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z)),
+                    max: Point3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z)),
+                },
+                None => Aabb { min: p, max: p },
+            })
+        })
+    }
+
+    pub fn corners(&self) -> [Point3<f32>; 8] {
+        let (min, max) = (self.min, self.max);
+        std::array::from_fn(|corner| {
+            Point3::new(
+                if corner & 1 == 0 { min.x } else { max.x },
+                if corner & 2 == 0 { min.y } else { max.y },
+                if corner & 4 == 0 { min.z } else { max.z },
+            )
+        })
+    }
+}
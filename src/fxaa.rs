@@ -0,0 +1,272 @@
+use serde::{Deserialize, Serialize};
+use wgpu::{
+    BindGroupLayout, Buffer, PipelineCompilationOptions, RenderPipeline, Sampler, TextureFormat,
+    TextureView,
+};
+
+use crate::{
+    frame_stats::{self, CountedPass},
+    hot_reload::wgsl,
+};
+
+/// FXAA 3.11's quality presets, trading the edge search's length and how much contrast
+/// counts as an edge for speed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum FxaaQuality {
+    Low,
+    #[default]
+    Medium,
+    High,
+    Extreme,
+}
+
+impl FxaaQuality {
+    pub fn next(self) -> Self {
+        match self {
+            FxaaQuality::Low => FxaaQuality::Medium,
+            FxaaQuality::Medium => FxaaQuality::High,
+            FxaaQuality::High => FxaaQuality::Extreme,
+            FxaaQuality::Extreme => FxaaQuality::Low,
+        }
+    }
+
+    /// Edge threshold, its floor in the dark, and the subpixel amount.
+    fn thresholds(self) -> (f32, f32, f32) {
+        match self {
+            FxaaQuality::Low => (0.25, 0.0833, 0.75),
+            FxaaQuality::Medium => (0.166, 0.0625, 0.75),
+            FxaaQuality::High => (0.125, 0.0312, 0.75),
+            FxaaQuality::Extreme => (0.063, 0.0312, 1.0),
+        }
+    }
+
+    /// Texels each step of the edge search moves on, the presets 10, 15, 29 and 39.
+    fn steps(self) -> &'static [f32] {
+        match self {
+            FxaaQuality::Low => &[1.5, 3.0, 12.0],
+            FxaaQuality::Medium => &[1.0, 1.5, 2.0, 2.0, 4.0, 12.0],
+            FxaaQuality::High => &[1.0, 1.5, 2.0, 2.0, 2.0, 2.0, 4.0, 8.0],
+            FxaaQuality::Extreme => &[1.0, 1.0, 1.0, 1.0, 1.0, 1.5, 2.0, 2.0, 2.0, 2.0, 4.0, 8.0],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FxaaSettings {
+    pub enabled: bool,
+    pub quality: FxaaQuality,
+    /// write luma into alpha in a pass of its own first, instead of working it out from
+    /// the color at every tap; the frame's alpha is lost, so not for transparent windows
+    pub luma_pass: bool,
+}
+
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct FxaaParams {
+    edge_threshold: f32,
+    edge_threshold_min: f32,
+    subpixel: f32,
+    search_steps: u32,
+    // the views decode sRGB, luma is taken in gamma 2 instead of linear light
+    perceptual: u32,
+    luma_in_alpha: u32,
+    _pad: [u32; 2],
+    steps: [f32; 12],
+}
+
+/// Fast approximate antialiasing on the post chain's output, before it's upscaled or
+/// sharpened. Cheaper than MSAA and it catches shader aliasing too, at the cost of
+/// softening texture detail a little.
+pub struct Fxaa {
+    format: TextureFormat,
+    params_buffer: Buffer,
+    sampler: Sampler,
+    bind_group_layout: BindGroupLayout,
+    luma_pipeline: RenderPipeline,
+    pipeline: RenderPipeline,
+}
+
+impl Fxaa {
+    /// `format` is what the post chain writes, the surface's view format.
+    pub fn new(device: &wgpu::Device, format: TextureFormat) -> Self {
+        let code = wgsl!("fxaa.wgsl");
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("fxaa shader"),
+            source: wgpu::ShaderSource::Wgsl(code),
+        });
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("fxaa params"),
+            size: std::mem::size_of::<FxaaParams>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        // the edge search and the final tap land between texels
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("fxaa sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("fxaa bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("fxaa pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let make_pipeline = |entry_point: &str| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "fullscreen_vs",
+                    buffers: &[],
+                    compilation_options: PipelineCompilationOptions::default(),
+                },
+                primitive: wgpu::PrimitiveState::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point,
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                    compilation_options: PipelineCompilationOptions::default(),
+                }),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+        let luma_pipeline = make_pipeline("luma_fs");
+        let pipeline = make_pipeline("fxaa_fs");
+
+        Self {
+            format,
+            params_buffer,
+            sampler,
+            bind_group_layout,
+            luma_pipeline,
+            pipeline,
+        }
+    }
+
+    /// Copies `input` onto `output` with its luma in alpha, for `encode` with
+    /// `luma_pass` on.
+    pub fn luma(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &TextureView,
+        output: &TextureView,
+    ) {
+        self.draw(device, encoder, &self.luma_pipeline, input, output);
+    }
+
+    /// Antialiases `input` onto `output`. With `luma_pass` on, `input` is what `luma`
+    /// wrote.
+    pub fn encode(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        queue: &wgpu::Queue,
+        settings: &FxaaSettings,
+        input: &TextureView,
+        output: &TextureView,
+    ) {
+        let (edge_threshold, edge_threshold_min, subpixel) = settings.quality.thresholds();
+        let quality_steps = settings.quality.steps();
+        let mut steps = [0.0; 12];
+        steps[..quality_steps.len()].copy_from_slice(quality_steps);
+        let params = FxaaParams {
+            edge_threshold,
+            edge_threshold_min,
+            subpixel,
+            search_steps: quality_steps.len() as u32,
+            perceptual: self.format.is_srgb() as u32,
+            luma_in_alpha: settings.luma_pass as u32,
+            _pad: [0; 2],
+            steps,
+        };
+        frame_stats::write_buffer(queue, &self.params_buffer, 0, bytemuck::bytes_of(&params));
+        self.draw(device, encoder, &self.pipeline, input, output);
+    }
+
+    fn draw(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline: &RenderPipeline,
+        input: &TextureView,
+        output: &TextureView,
+    ) {
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("fxaa bind group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(input),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.params_buffer.as_entire_binding(),
+                },
+            ],
+        });
+        let mut render_pass = CountedPass::begin(
+            encoder,
+            &wgpu::RenderPassDescriptor {
+                label: Some("fxaa pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: output,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                ..Default::default()
+            },
+        );
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
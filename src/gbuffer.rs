@@ -0,0 +1,113 @@
+use wgpu::{Texture, TextureFormat, TextureView};
+
+/// world space normal + smoothness, zero where nothing was hit (sky)
+pub const SURFACE_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+/// NDC depth written by the scene shaders, 1 = far plane / sky
+pub const DEPTH_FORMAT: TextureFormat = TextureFormat::R32Float;
+/// which object the pixel shows, 0 = sky, 1 = ground, 2 + i = box i
+pub const ID_FORMAT: TextureFormat = TextureFormat::R32Uint;
+
+/// Extra outputs of the scene pass for the screen space passes that run after it.
+pub struct GBuffer {
+    pub surface: Texture,
+    pub surface_view: TextureView,
+    pub depth: Texture,
+    pub depth_view: TextureView,
+    pub id: Texture,
+    pub id_view: TextureView,
+}
+
+impl GBuffer {
+    pub fn new(device: &wgpu::Device, width: u32, height: u32) -> Self {
+        let (surface, surface_view) =
+            Self::create_target(device, "gbuffer surface", SURFACE_FORMAT, width, height);
+        let (depth, depth_view) =
+            Self::create_target(device, "gbuffer depth", DEPTH_FORMAT, width, height);
+        let (id, id_view) = Self::create_target(device, "gbuffer id", ID_FORMAT, width, height);
+        Self {
+            surface,
+            surface_view,
+            depth,
+            depth_view,
+            id,
+            id_view,
+        }
+    }
+
+    fn create_target(
+        device: &wgpu::Device,
+        label: &str,
+        format: TextureFormat,
+        width: u32,
+        height: u32,
+    ) -> (Texture, TextureView) {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: width.max(1),
+                height: height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            // copied from by the pixel inspector
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        (texture, view)
+    }
+
+    pub fn color_targets() -> [Option<wgpu::ColorTargetState>; 3] {
+        [SURFACE_FORMAT, DEPTH_FORMAT, ID_FORMAT].map(|format| {
+            Some(wgpu::ColorTargetState {
+                format,
+                blend: None,
+                write_mask: wgpu::ColorWrites::ALL,
+            })
+        })
+    }
+
+    pub fn color_attachments(&self) -> [Option<wgpu::RenderPassColorAttachment<'_>>; 3] {
+        self.attachments(true)
+    }
+
+    /// For passes after the display pass that draw more into what it left.
+    pub fn load_attachments(&self) -> [Option<wgpu::RenderPassColorAttachment<'_>>; 3] {
+        self.attachments(false)
+    }
+
+    fn attachments(&self, clear: bool) -> [Option<wgpu::RenderPassColorAttachment<'_>>; 3] {
+        [
+            (&self.surface_view, wgpu::Color::TRANSPARENT),
+            (
+                &self.depth_view,
+                wgpu::Color {
+                    r: 1.0,
+                    g: 1.0,
+                    b: 1.0,
+                    a: 1.0,
+                },
+            ),
+            (&self.id_view, wgpu::Color::TRANSPARENT),
+        ]
+        .map(|(view, clear_value)| {
+            Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: if clear {
+                        wgpu::LoadOp::Clear(clear_value)
+                    } else {
+                        wgpu::LoadOp::Load
+                    },
+                    store: wgpu::StoreOp::Store,
+                },
+            })
+        })
+    }
+}
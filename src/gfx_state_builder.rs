@@ -0,0 +1,170 @@
+use std::{
+    path::PathBuf,
+    sync::{atomic::AtomicBool, Arc},
+};
+
+use winit::{
+    dpi::PhysicalSize,
+    event_loop::{ActiveEventLoop, EventLoopProxy},
+    window::WindowAttributes,
+};
+
+use crate::{config::Config, error, GfxState, UserEvent, WindowState};
+
+/// The device and surface choices `GfxState` was built with. Kept on it so a device
+/// rebuilt after a loss comes back the same.
+#[derive(Debug, Clone, Default)]
+pub struct DeviceRequest {
+    /// on top of the optional ones `GpuFeatures` asks for; the device fails without them
+    pub required_features: wgpu::Features,
+    /// replaces the config's limits profile
+    pub limits: Option<wgpu::Limits>,
+    /// used when the surface offers it, over the config's SDR/HDR pick
+    pub surface_format: Option<wgpu::TextureFormat>,
+    /// used when the surface offers it, Fifo otherwise
+    pub present_mode: Option<wgpu::PresentMode>,
+    /// where wgpu writes an API trace, with its `trace` feature
+    pub trace_path: Option<PathBuf>,
+    pub force_fallback_adapter: bool,
+}
+
+/// How a `GfxState` gets set up. The defaults are the app's own choices, derived from
+/// `config`; programs embedding the renderer change what they need before building:
+///
+///     let app = GfxStateBuilder::new(config)
+///         .present_mode(wgpu::PresentMode::Mailbox)
+///         .build(event_loop, event_proxy)
+///         .await?;
+pub struct GfxStateBuilder {
+    config: Config,
+    window_attributes: WindowAttributes,
+    request: DeviceRequest,
+}
+
+// the setters are for embedding programs, the app itself only uses some
+impl GfxStateBuilder {
+    pub fn new(config: Config) -> Self {
+        // small until the first resize, so the first frame isn't a big one at a wrong size
+        let window_attributes = WindowAttributes::default()
+            .with_active(false)
+            .with_transparent(config.window.transparent)
+            .with_inner_size(PhysicalSize::new(128, 128));
+        Self {
+            config,
+            window_attributes,
+            request: DeviceRequest::default(),
+        }
+    }
+
+    pub fn window_attributes(mut self, attributes: WindowAttributes) -> Self {
+        self.window_attributes = attributes;
+        self
+    }
+
+    pub fn required_features(mut self, features: wgpu::Features) -> Self {
+        self.request.required_features = features;
+        self
+    }
+
+    pub fn limits(mut self, limits: wgpu::Limits) -> Self {
+        self.request.limits = Some(limits);
+        self
+    }
+
+    pub fn surface_format(mut self, format: wgpu::TextureFormat) -> Self {
+        self.request.surface_format = Some(format);
+        self
+    }
+
+    pub fn present_mode(mut self, present_mode: wgpu::PresentMode) -> Self {
+        self.request.present_mode = Some(present_mode);
+        self
+    }
+
+    pub fn trace_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.request.trace_path = Some(path.into());
+        self
+    }
+
+    pub fn force_fallback_adapter(mut self, force: bool) -> Self {
+        self.request.force_fallback_adapter = force;
+        self
+    }
+
+    /// Opens the window, picks an adapter that can present to it and configures the
+    /// surface. The GPU factory is left for the caller to create.
+    pub async fn build(
+        self,
+        event_loop: &ActiveEventLoop,
+        event_proxy: EventLoopProxy<UserEvent>,
+    ) -> error::Result<GfxState> {
+        let window = Arc::new(event_loop.create_window(self.window_attributes)?);
+        let instance = wgpu::Instance::default();
+        let surface = instance.create_surface(window.clone())?;
+        let device_lost = Arc::new(AtomicBool::new(false));
+        let (gpu, adapter) = GfxState::request_device(
+            &instance,
+            Some(&surface),
+            Some(window.clone()),
+            &device_lost,
+            &self.config,
+            &self.request,
+        )
+        .await?;
+        let size = window.inner_size();
+        let surface_config = GfxState::surface_config(
+            &adapter,
+            &surface,
+            size.width,
+            size.height,
+            &self.config,
+            &self.request,
+        )?;
+        let mut window_state = WindowState {
+            handle: Some(window),
+            event_proxy: Some(event_proxy),
+            instance,
+            surface: Some(surface),
+            surface_config,
+            offscreen: None,
+        };
+        window_state.configure(&gpu.device);
+        Ok(GfxState::from_parts(
+            window_state,
+            gpu,
+            self.config,
+            self.request,
+        ))
+    }
+
+    /// No window, surface or event loop: frames render into `window.offscreen`, a
+    /// `width` x `height` texture. The surface format and present mode aren't used.
+    pub async fn build_headless(self, width: u32, height: u32) -> error::Result<GfxState> {
+        let instance = wgpu::Instance::default();
+        let device_lost = Arc::new(AtomicBool::new(false));
+        let (gpu, _) = GfxState::request_device(
+            &instance,
+            None,
+            None,
+            &device_lost,
+            &self.config,
+            &self.request,
+        )
+        .await?;
+        let mut window_state = WindowState {
+            handle: None,
+            event_proxy: None,
+            instance,
+            surface: None,
+            surface_config: WindowState::offscreen_config(width, height),
+            offscreen: None,
+        };
+        window_state.configure(&gpu.device);
+        Ok(GfxState::from_parts(
+            window_state,
+            gpu,
+            self.config,
+            self.request,
+        ))
+    }
+}
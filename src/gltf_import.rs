@@ -0,0 +1,218 @@
+use std::path::Path;
+
+use anyhow::{bail, Context};
+use cgmath::{InnerSpace, Matrix, Matrix3, Matrix4, SquareMatrix, Vector3, Vector4};
+
+use crate::{
+    asset_loader::DecodedTexture,
+    material::{Blend, MaterialParams},
+    model::{self, ImportedMaterial, ImportedMesh},
+    vertex::MeshVertex,
+};
+
+/// Reads a .gltf (with its buffers and images) or a .glb. Triangle primitives of the
+/// default scene come back in world space; other primitive modes are skipped.
+pub fn import(path: &Path) -> anyhow::Result<Vec<ImportedMesh>> {
+    let (document, buffers, images) =
+        gltf::import(path).with_context(|| format!("can't import {}", path.display()))?;
+    let Some(scene) = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+    else {
+        bail!("{} has no scene", path.display());
+    };
+    let mut meshes = vec![];
+    for node in scene.nodes() {
+        import_node(&node, Matrix4::identity(), &buffers, &images, &mut meshes)?;
+    }
+    Ok(meshes)
+}
+
+fn import_node(
+    node: &gltf::Node,
+    parent: Matrix4<f32>,
+    buffers: &[gltf::buffer::Data],
+    images: &[gltf::image::Data],
+    meshes: &mut Vec<ImportedMesh>,
+) -> anyhow::Result<()> {
+    let transform = parent * Matrix4::from(node.transform().matrix());
+    if let Some(mesh) = node.mesh() {
+        for primitive in mesh.primitives() {
+            if primitive.mode() != gltf::mesh::Mode::Triangles {
+                println!(
+                    "Skipped a {:?} primitive of {}",
+                    primitive.mode(),
+                    mesh.name().unwrap_or("a mesh")
+                );
+                continue;
+            }
+            let name = format!("{} {}", mesh.name().unwrap_or("mesh"), primitive.index());
+            meshes.push(import_primitive(
+                name, &primitive, transform, buffers, images,
+            )?);
+        }
+    }
+    for child in node.children() {
+        import_node(&child, transform, buffers, images, meshes)?;
+    }
+    Ok(())
+}
+
+fn import_primitive(
+    name: String,
+    primitive: &gltf::Primitive,
+    transform: Matrix4<f32>,
+    buffers: &[gltf::buffer::Data],
+    images: &[gltf::image::Data],
+) -> anyhow::Result<ImportedMesh> {
+    let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+    let positions: Vec<[f32; 3]> = reader
+        .read_positions()
+        .with_context(|| format!("{} has no positions", name))?
+        .collect();
+    let mut indices: Vec<u32> = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect(),
+        None => (0..positions.len() as u32).collect(),
+    };
+    let normals: Vec<[f32; 3]> = match reader.read_normals() {
+        Some(normals) => normals.collect(),
+        None => model::smooth_normals(&positions, &indices),
+    };
+    let uvs: Vec<[f32; 2]> = match reader.read_tex_coords(0) {
+        Some(uvs) => uvs.into_f32().collect(),
+        None => vec![[0.0; 2]; positions.len()],
+    };
+    let tangents: Option<Vec<[f32; 4]>> = reader.read_tangents().map(Iterator::collect);
+
+    // normals go through the inverse transpose, so non uniform scales keep them
+    // perpendicular to the surface
+    let linear = Matrix3::from_cols(
+        transform.x.truncate(),
+        transform.y.truncate(),
+        transform.z.truncate(),
+    );
+    let normal_matrix = linear.invert().unwrap_or(Matrix3::identity()).transpose();
+    let mirrored = linear.determinant() < 0.0;
+    let mut vertices: Vec<MeshVertex> = positions
+        .iter()
+        .zip(&normals)
+        .zip(&uvs)
+        .enumerate()
+        .map(|(i, ((&position, &normal), &uv))| {
+            let position = transform * Vector4::new(position[0], position[1], position[2], 1.0);
+            let normal = (normal_matrix * Vector3::from(normal)).normalize();
+            // tangents lie in the surface, so they take the transform itself; a mirror
+            // flips the bitangent the shader rebuilds from them
+            let tangent = tangents.as_ref().map_or([0.0; 4], |tangents| {
+                let [x, y, z, w] = tangents[i];
+                let tangent = (linear * Vector3::new(x, y, z)).normalize();
+                [
+                    tangent.x,
+                    tangent.y,
+                    tangent.z,
+                    if mirrored { -w } else { w },
+                ]
+            });
+            MeshVertex {
+                position: position.truncate().into(),
+                normal: normal.into(),
+                uv,
+                tangent,
+            }
+        })
+        .collect();
+    // a mirroring transform turns the triangles inside out
+    if mirrored {
+        for triangle in indices.chunks_exact_mut(3) {
+            triangle.swap(1, 2);
+        }
+    }
+    if tangents.is_none() {
+        model::generate_tangents(&mut vertices, &indices);
+    }
+
+    let material = primitive.material();
+    let pbr = material.pbr_metallic_roughness();
+    let texture = |texture: Option<gltf::texture::Texture>, srgb: bool, slot: &str| {
+        texture
+            .map(|texture| {
+                texels(&images[texture.source().index()], srgb)
+                    .with_context(|| format!("{} texture of {}", slot, name))
+            })
+            .transpose()
+    };
+    let normal = material.normal_texture();
+    let occlusion = material.occlusion_texture();
+    let imported = ImportedMaterial {
+        params: MaterialParams {
+            base_color: pbr.base_color_factor(),
+            emissive: material.emissive_factor(),
+            metallic: pbr.metallic_factor(),
+            roughness: pbr.roughness_factor(),
+            normal_scale: normal.as_ref().map_or(1.0, |normal| normal.scale()),
+            occlusion_strength: occlusion
+                .as_ref()
+                .map_or(1.0, |occlusion| occlusion.strength()),
+            alpha_cutoff: match material.alpha_mode() {
+                gltf::material::AlphaMode::Mask => material.alpha_cutoff().unwrap_or(0.5),
+                _ => 0.0,
+            },
+        },
+        base_color: texture(
+            pbr.base_color_texture().map(|info| info.texture()),
+            true,
+            "base color",
+        )?,
+        metallic_roughness: texture(
+            pbr.metallic_roughness_texture().map(|info| info.texture()),
+            false,
+            "metallic roughness",
+        )?,
+        normal: texture(normal.map(|info| info.texture()), false, "normal")?,
+        occlusion: texture(occlusion.map(|info| info.texture()), false, "occlusion")?,
+        // masked materials draw opaque with their cutoff
+        blend: match material.alpha_mode() {
+            gltf::material::AlphaMode::Blend => Blend::Alpha,
+            _ => Blend::Opaque,
+        },
+        double_sided: material.double_sided(),
+    };
+    Ok(ImportedMesh {
+        name,
+        vertices,
+        indices,
+        material: imported,
+    })
+}
+
+/// A glTF image as rgba8; whether it's sRGB depends on the slot it's used in.
+fn texels(image: &gltf::image::Data, srgb: bool) -> anyhow::Result<DecodedTexture> {
+    use gltf::image::Format;
+    let texel = |t: &[u8]| match image.format {
+        Format::R8G8B8A8 => [t[0], t[1], t[2], t[3]],
+        Format::R8G8B8 => [t[0], t[1], t[2], 255],
+        Format::R8G8 => [t[0], t[0], t[0], t[1]],
+        _ => [t[0], t[0], t[0], 255],
+    };
+    let texel_size = match image.format {
+        Format::R8G8B8A8 => 4,
+        Format::R8G8B8 => 3,
+        Format::R8G8 => 2,
+        Format::R8 => 1,
+        format => bail!("{:?} texels aren't supported", format),
+    };
+    Ok(DecodedTexture {
+        width: image.width,
+        height: image.height,
+        format: if srgb {
+            wgpu::TextureFormat::Rgba8UnormSrgb
+        } else {
+            wgpu::TextureFormat::Rgba8Unorm
+        },
+        texels: image
+            .pixels
+            .chunks_exact(texel_size)
+            .flat_map(texel)
+            .collect(),
+    })
+}
@@ -0,0 +1,214 @@
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4};
+use wgpu::{
+    util::{DeviceExt, DrawIndexedIndirectArgs},
+    BindGroup, BindGroupLayout, Buffer, ComputePipeline, PipelineCompilationOptions,
+};
+
+use crate::{
+    frame_stats,
+    frustum::{Aabb, Frustum},
+    hot_reload::wgsl,
+    indirect::IndirectDraws,
+};
+
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct MeshCull {
+    center: [f32; 3],
+    radius: f32,
+    mesh_index: u32,
+    instance_count: u32,
+    _pad: [u32; 2],
+}
+
+/// What the culling needs of one mesh: its instances and its box before they move it.
+pub struct CullMesh<'a> {
+    pub instance_buffer: &'a Buffer,
+    pub instance_count: u32,
+    pub bounds: Aabb,
+}
+
+struct MeshCullBuffers {
+    instance_count: u32,
+    // the instances left in view, packed at the front; what the draws read
+    culled: Buffer,
+    // only reached through the bind group, kept so it lives as long as it does
+    _params_buffer: Buffer,
+    bind_group: BindGroup,
+}
+
+/// Frustum culling of the mesh instances on the GPU, ahead of the indirect draws: a
+/// compute pass tests each instance's bounding sphere against the camera's planes and
+/// packs the ones in view into a buffer of the mesh's own, counting them into its draw's
+/// `instance_count`. Off-screen instances then cost nothing past that test.
+pub struct GpuCulling {
+    frustum_buffer: Buffer,
+    bind_group_layout: BindGroupLayout,
+    pipeline: ComputePipeline,
+    meshes: Vec<MeshCullBuffers>,
+    // the draws' arguments with no instances, written over them before each cull
+    reset: Vec<u8>,
+}
+
+impl GpuCulling {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let code = wgsl!("cull.wgsl");
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("cull shader"),
+            source: wgpu::ShaderSource::Wgsl(code),
+        });
+        let frustum_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("cull frustum"),
+            size: std::mem::size_of::<[[f32; 4]; 6]>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("cull bind group layout"),
+            entries: &[
+                entry(0, wgpu::BufferBindingType::Uniform),
+                entry(1, wgpu::BufferBindingType::Uniform),
+                entry(2, wgpu::BufferBindingType::Storage { read_only: true }),
+                entry(3, wgpu::BufferBindingType::Storage { read_only: false }),
+                entry(4, wgpu::BufferBindingType::Storage { read_only: false }),
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("cull pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("cull"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "cull",
+            compilation_options: PipelineCompilationOptions::default(),
+        });
+        Self {
+            frustum_buffer,
+            bind_group_layout,
+            pipeline,
+            meshes: vec![],
+            reset: vec![],
+        }
+    }
+
+    /// Rebuilds everything per mesh after the meshes, their instances or `draws` changed.
+    /// `args` are the draws' arguments, in the same order as `meshes`.
+    pub fn set_meshes<'a>(
+        &mut self,
+        device: &wgpu::Device,
+        draws: &IndirectDraws,
+        args: &[DrawIndexedIndirectArgs],
+        meshes: impl Iterator<Item = CullMesh<'a>>,
+    ) {
+        self.meshes = meshes
+            .enumerate()
+            .map(|(mesh_index, mesh)| {
+                let Aabb { min, max } = mesh.bounds;
+                let params = MeshCull {
+                    center: min.midpoint(max).into(),
+                    radius: (max - min).magnitude() * 0.5,
+                    mesh_index: mesh_index as u32,
+                    instance_count: mesh.instance_count,
+                    _pad: [0; 2],
+                };
+                let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("cull mesh params"),
+                    contents: bytemuck::bytes_of(&params),
+                    usage: wgpu::BufferUsages::UNIFORM,
+                });
+                let culled = device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("culled instances"),
+                    size: mesh.instance_buffer.size(),
+                    usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE,
+                    mapped_at_creation: false,
+                });
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("cull bind group"),
+                    layout: &self.bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: self.frustum_buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: params_buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: mesh.instance_buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 3,
+                            resource: culled.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 4,
+                            resource: draws.buffer.as_entire_binding(),
+                        },
+                    ],
+                });
+                MeshCullBuffers {
+                    instance_count: mesh.instance_count,
+                    culled,
+                    _params_buffer: params_buffer,
+                    bind_group,
+                }
+            })
+            .collect();
+        self.reset = args
+            .iter()
+            .flat_map(|args| {
+                DrawIndexedIndirectArgs {
+                    instance_count: 0,
+                    ..*args
+                }
+                .as_bytes()
+                .to_vec()
+            })
+            .collect();
+    }
+
+    /// The instances of `mesh` in view this frame, in place of its instance buffer.
+    pub fn culled(&self, mesh: usize) -> &Buffer {
+        &self.meshes[mesh].culled
+    }
+
+    /// Culls every mesh's instances against `view_proj` into `draws`.
+    pub fn encode(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        queue: &wgpu::Queue,
+        draws: &IndirectDraws,
+        view_proj: &Matrix4<f32>,
+    ) {
+        if self.meshes.is_empty() {
+            return;
+        }
+        let frustum = Frustum::from_view_proj(view_proj).to_uniform();
+        frame_stats::write_buffer(queue, &self.frustum_buffer, 0, bytemuck::bytes_of(&frustum));
+        frame_stats::write_buffer(queue, &draws.buffer, 0, &self.reset);
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("frustum cull"),
+            timestamp_writes: None,
+        });
+        compute_pass.set_pipeline(&self.pipeline);
+        for mesh in self.meshes.iter().filter(|mesh| mesh.instance_count > 0) {
+            compute_pass.set_bind_group(0, &mesh.bind_group, &[]);
+            compute_pass.dispatch_workgroups(mesh.instance_count.div_ceil(64), 1, 1);
+        }
+    }
+}
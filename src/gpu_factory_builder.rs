@@ -0,0 +1,449 @@
+use std::{collections::HashSet, path::PathBuf};
+
+use anyhow::{bail, Context};
+use wgpu::ShaderStages;
+
+use crate::{
+    error,
+    pipeline_cache::{PipelineCache, PipelineKey},
+    registry::{BindGroupHandle, BindGroupLayoutHandle, PipelineHandle, ResourceRegistry},
+    scene::Scene,
+    shadertoy::{self, ShadertoyUniform},
+    texture::{self, ImageTexture},
+    tonemap::HDR_FORMAT,
+    GfxState,
+    GpuFatory::{GpuFactory, RESERVED_NAMES},
+};
+
+/// How a pipeline from `GpuFactoryBuilder::with_pipeline` draws: `vertex_count` vertices
+/// without any buffers, over the scene after the factory's own passes, so a fullscreen
+/// triangle by default.
+#[derive(Debug, Clone)]
+pub struct CustomPipelineDesc {
+    /// finds its pipeline and bind group in the factory's registry
+    pub name: &'static str,
+    pub vertex_entry: &'static str,
+    pub fragment_entry: &'static str,
+    /// None replaces the scene where it draws
+    pub blend: Option<wgpu::BlendState>,
+    pub vertex_count: u32,
+    /// bytes of `var<push_constant>` its vertex and fragment stages share, 0 without any.
+    /// Needs Features::PUSH_CONSTANTS, the pipeline is left out on devices without it.
+    pub push_constant_size: u32,
+    /// the shader is a toy's `mainImage`, wrapped by `shadertoy::wrap`
+    pub shadertoy: bool,
+}
+
+impl CustomPipelineDesc {
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            vertex_entry: "vs_main",
+            fragment_entry: "fs_main",
+            blend: None,
+            vertex_count: 3,
+            push_constant_size: 0,
+            shadertoy: false,
+        }
+    }
+
+    /// Push constants the size of `T`, set per draw with `GpuFactory::set_draw_constants`.
+    // for embedding programs, like the builder
+    pub fn with_push_constants<T: bytemuck::Pod>(mut self) -> Self {
+        self.push_constant_size = std::mem::size_of::<T>() as u32;
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+enum CustomBinding {
+    Uniform {
+        name: &'static str,
+        size: u64,
+        visibility: ShaderStages,
+    },
+    // the texture and then its sampler, both for the fragment stage
+    Texture {
+        name: &'static str,
+        path: PathBuf,
+        srgb: bool,
+    },
+}
+
+impl CustomBinding {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Uniform { name, .. } | Self::Texture { name, .. } => name,
+        }
+    }
+}
+
+// a pipeline with the shader and bindings declared ahead of it, None without a shader
+// for `build` to report
+#[derive(Debug, Clone)]
+struct CustomPipeline {
+    shader: Option<PathBuf>,
+    bindings: Vec<CustomBinding>,
+    desc: CustomPipelineDesc,
+}
+
+/// A custom pipeline built into the factory's registry, drawn every frame.
+pub struct CustomDraw {
+    pub name: &'static str,
+    pub pipeline: PipelineHandle,
+    pub bind_group: BindGroupHandle,
+    pub vertex_count: u32,
+    pub push_constant_size: u32,
+    pub shadertoy: bool,
+    /// the push constants of each draw, one draw of zeros until they're set
+    pub draws: Vec<Vec<u8>>,
+}
+
+/// Pipelines of the user's own on top of the factory's, each from a WGSL file and the
+/// uniforms and textures declared after it:
+///
+///     GpuFactoryBuilder::new()
+///         .with_shader("asset/rings.wgsl")
+///         .with_uniform::<RingsUniform>("rings", wgpu::ShaderStages::FRAGMENT)
+///         .with_texture("rings noise", "asset/noise.png", false)
+///         .with_pipeline(CustomPipelineDesc::new("rings"))
+///         .build(&mut app)?;
+///
+/// Their shaders see the camera's `CameraUniform` at group 0 and the bindings at group 1,
+/// numbered in the order they were declared, a texture taking two: its view, then its
+/// sampler. Kept on `GfxState`, so the factory rebuilt after a device loss, a scene load
+/// or a shader reload comes back with them.
+#[derive(Debug, Clone, Default)]
+pub struct GpuFactoryBuilder {
+    shader: Option<PathBuf>,
+    bindings: Vec<CustomBinding>,
+    pipelines: Vec<CustomPipeline>,
+}
+
+// for embedding programs, the app itself has no pipelines of its own to add
+impl GpuFactoryBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The WGSL the pipelines after it are built from, relative to the crate like the
+    /// scene's paths. Starts their bindings over.
+    pub fn with_shader(mut self, path: impl Into<PathBuf>) -> Self {
+        self.shader = Some(path.into());
+        self.bindings.clear();
+        self
+    }
+
+    /// A uniform buffer the size of `T`, written with `GpuFactory::write_uniform`. Pipelines
+    /// declaring the same name share it.
+    pub fn with_uniform<T: bytemuck::Pod>(
+        mut self,
+        name: &'static str,
+        visibility: ShaderStages,
+    ) -> Self {
+        self.bindings.push(CustomBinding::Uniform {
+            name,
+            size: std::mem::size_of::<T>() as u64,
+            visibility,
+        });
+        self
+    }
+
+    /// A PNG or JPEG, decoded when the factory is built. Colors are `srgb`, data isn't.
+    pub fn with_texture(
+        mut self,
+        name: &'static str,
+        path: impl Into<PathBuf>,
+        srgb: bool,
+    ) -> Self {
+        self.bindings.push(CustomBinding::Texture {
+            name,
+            path: path.into(),
+            srgb,
+        });
+        self
+    }
+
+    /// A Shadertoy-style shader from `path`, its `mainImage` run for every pixel of the
+    /// scene with the iTime, iMouse and the rest of the `shadertoy` uniform bound. Starts
+    /// the bindings over like `with_shader`.
+    pub fn with_shadertoy(self, name: &'static str, path: impl Into<PathBuf>) -> Self {
+        self.with_shader(path)
+            .with_uniform::<ShadertoyUniform>(shadertoy::UNIFORM_NAME, ShaderStages::FRAGMENT)
+            .with_pipeline(CustomPipelineDesc {
+                vertex_entry: "shadertoy_vs",
+                fragment_entry: "shadertoy_fs",
+                shadertoy: true,
+                ..CustomPipelineDesc::new(name)
+            })
+    }
+
+    /// A pipeline from the last `with_shader` with the bindings declared since. Without
+    /// one `build` fails.
+    pub fn with_pipeline(mut self, desc: CustomPipelineDesc) -> Self {
+        self.pipelines.push(CustomPipeline {
+            shader: self.shader.clone(),
+            bindings: self.bindings.clone(),
+            desc,
+        });
+        self
+    }
+
+    /// Puts these pipelines on `app` and rebuilds its factory with them. If that fails
+    /// `app` keeps its pipelines and factory as they were.
+    pub fn build(self, app: &mut GfxState) -> error::Result<()> {
+        self.check()?;
+        let previous = std::mem::replace(&mut app.factory_builder, self);
+        match GpuFactory::new(app) {
+            Ok(gpu_factory) => {
+                app.gpu_factory = Some(gpu_factory);
+                Ok(())
+            }
+            Err(e) => {
+                app.factory_builder = previous;
+                Err(e)
+            }
+        }
+    }
+
+    /// What the registry would refuse or mix up with the factory's own: a pipeline without
+    /// a shader, two pipelines of one name, or a name in `RESERVED_NAMES`.
+    fn check(&self) -> error::Result<()> {
+        let mut names = HashSet::new();
+        for pipeline in &self.pipelines {
+            let name = pipeline.desc.name;
+            if pipeline.shader.is_none() {
+                return Err(error::Error::Builder(format!(
+                    "pipeline {} has no with_shader ahead of it",
+                    name
+                )));
+            }
+            if !names.insert(name) {
+                return Err(error::Error::Builder(format!(
+                    "two pipelines named {}",
+                    name
+                )));
+            }
+            let bindings = pipeline.bindings.iter().map(CustomBinding::name);
+            if let Some(reserved) = std::iter::once(name)
+                .chain(bindings)
+                .find(|name| RESERVED_NAMES.contains(name))
+            {
+                return Err(error::Error::Builder(format!(
+                    "{} is taken by the factory's own resources",
+                    reserved
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Builds the pipelines into `registry`, sharing what's alike through `cache`. One
+    /// whose shader or textures can't be read is left out, the rest still draw.
+    pub(crate) fn build_pipelines(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        registry: &mut ResourceRegistry,
+        cache: &mut PipelineCache,
+        camera_layout: BindGroupLayoutHandle,
+    ) -> Vec<CustomDraw> {
+        self.pipelines
+            .iter()
+            .filter_map(|pipeline| {
+                pipeline
+                    .build(device, queue, registry, cache, camera_layout)
+                    .map_err(|e| println!("Pipeline {} left out: {:#}", pipeline.desc.name, e))
+                    .ok()
+            })
+            .collect()
+    }
+}
+
+impl CustomPipeline {
+    fn build(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        registry: &mut ResourceRegistry,
+        cache: &mut PipelineCache,
+        camera_layout: BindGroupLayoutHandle,
+    ) -> anyhow::Result<CustomDraw> {
+        let Some(shader) = &self.shader else {
+            bail!("no with_shader ahead of it");
+        };
+        let path = Scene::resolve_path(shader);
+        let mut code = std::fs::read_to_string(&path)
+            .with_context(|| format!("can't read {}", path.display()))?;
+        if self.desc.shadertoy {
+            code = shadertoy::wrap(&code);
+        }
+        let name = self.desc.name;
+        let push_constant_size = self.desc.push_constant_size;
+        let push_constant_ranges = match push_constant_size {
+            0 => vec![],
+            size => {
+                if !device.features().contains(wgpu::Features::PUSH_CONSTANTS) {
+                    bail!("the device has no push constants");
+                }
+                let max = device.limits().max_push_constant_size;
+                if size > max || size % wgpu::PUSH_CONSTANT_ALIGNMENT != 0 {
+                    bail!(
+                        "{} bytes of push constants, the device takes up to {} in 4s",
+                        size,
+                        max
+                    );
+                }
+                vec![wgpu::PushConstantRange {
+                    stages: ShaderStages::VERTEX_FRAGMENT,
+                    range: 0..size,
+                }]
+            }
+        };
+        let mut layout_entries = vec![];
+        for binding in &self.bindings {
+            let next = layout_entries.len() as u32;
+            match binding {
+                CustomBinding::Uniform { visibility, .. } => {
+                    layout_entries.push(wgpu::BindGroupLayoutEntry {
+                        binding: next,
+                        visibility: *visibility,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    })
+                }
+                CustomBinding::Texture { .. } => layout_entries.extend([
+                    wgpu::BindGroupLayoutEntry {
+                        binding: next,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: next + 1,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ]),
+            }
+        }
+
+        // uniforms and textures another pipeline declared too are shared
+        let mut buffers = vec![];
+        let mut textures = vec![];
+        for binding in &self.bindings {
+            match binding {
+                CustomBinding::Uniform { name, size, .. } => {
+                    let buffer = registry.find(name).unwrap_or_else(|| {
+                        registry.add_named(
+                            name,
+                            device.create_buffer(&wgpu::BufferDescriptor {
+                                label: Some(name),
+                                size: *size,
+                                usage: wgpu::BufferUsages::UNIFORM
+                                    | wgpu::BufferUsages::COPY_DST
+                                    | wgpu::BufferUsages::COPY_SRC,
+                                mapped_at_creation: false,
+                            }),
+                        )
+                    });
+                    buffers.push(buffer);
+                }
+                CustomBinding::Texture { name, path, srgb } => {
+                    let texture = match registry.find(name) {
+                        Some(texture) => texture,
+                        None => {
+                            let decoded = texture::decode(&Scene::resolve_path(path), *srgb)?;
+                            let texture = ImageTexture::from_decoded(device, queue, name, &decoded);
+                            registry.add_named(name, texture)
+                        }
+                    };
+                    textures.push(texture);
+                }
+            }
+        }
+        let layout = cache.bind_group_layout(device, registry, name, &layout_entries);
+        let shader = cache.shader(device, registry, name, code);
+        let pipeline_layout = cache.pipeline_layout(
+            device,
+            registry,
+            name,
+            &[camera_layout, layout],
+            &push_constant_ranges,
+        );
+        let pipeline = cache.render_pipeline(
+            device,
+            registry,
+            name,
+            PipelineKey {
+                layout: pipeline_layout,
+                shader,
+                vertex_entry: self.desc.vertex_entry,
+                vertex_buffers: vec![],
+                primitive: wgpu::PrimitiveState::default(),
+                fragment_entry: Some(self.desc.fragment_entry),
+                targets: vec![Some(wgpu::ColorTargetState {
+                    format: HDR_FORMAT,
+                    blend: self.desc.blend,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                constants: vec![],
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+            },
+        );
+        let (mut buffers, mut textures) = (buffers.into_iter(), textures.into_iter());
+        let mut entries = vec![];
+        for binding in &self.bindings {
+            let next = entries.len() as u32;
+            match binding {
+                CustomBinding::Uniform { .. } => entries.push(wgpu::BindGroupEntry {
+                    binding: next,
+                    resource: registry[buffers.next().unwrap()].as_entire_binding(),
+                }),
+                CustomBinding::Texture { .. } => {
+                    let texture = &registry[textures.next().unwrap()];
+                    entries.extend([
+                        wgpu::BindGroupEntry {
+                            binding: next,
+                            resource: wgpu::BindingResource::TextureView(&texture.view),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: next + 1,
+                            resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                        },
+                    ]);
+                }
+            }
+        }
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(name),
+            layout: &registry[layout],
+            entries: &entries,
+        });
+        // named after the pipeline even where another one shares them
+        registry.name(name, shader);
+        registry.name(name, layout);
+        registry.name(name, pipeline_layout);
+        registry.name(name, pipeline);
+        Ok(CustomDraw {
+            name,
+            pipeline,
+            bind_group: registry.add_named(name, bind_group),
+            vertex_count: self.desc.vertex_count,
+            push_constant_size,
+            shadertoy: self.desc.shadertoy,
+            draws: vec![vec![0; push_constant_size as usize]],
+        })
+    }
+}
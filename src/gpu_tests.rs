@@ -0,0 +1,1481 @@
+// Builds the renderer on a headless device and draws a frame offscreen, so pipeline and
+// bind group layout mismatches fail `cargo test` without a display. Validation errors come
+// back as `error::Error`s, which fail the test. Machines without a software
+// adapter (no lavapipe / WARP / llvmpipe) skip the tests rather than fail them.
+
+use std::collections::HashSet;
+
+use cgmath::InnerSpace;
+use winit::{dpi::PhysicalSize, event::MouseButton};
+
+use crate::{
+    asset_loader::DecodedTexture,
+    boids::BoidsSettings,
+    compute::{self, ComputeBinding, ComputeJob, ComputeStage},
+    config::Config,
+    debug_view::ViewMode,
+    deferred::RenderPath,
+    error::Error,
+    frame_context::FRAMES_IN_FLIGHT,
+    frame_stats::CullStats,
+    fxaa::{FxaaQuality, FxaaSettings},
+    gfx_state_builder::GfxStateBuilder,
+    gpu_factory_builder::{CustomPipelineDesc, GpuFactoryBuilder},
+    indirect::IndirectDraws,
+    instance::Instance,
+    light::LocalLight,
+    material::{Blend, MaterialDesc, MaterialParams, MaterialShader, Materials},
+    particles::ParticleSettings,
+    readback,
+    registry::{MeshDraw, MeshHandle},
+    render_thread::FrameInput,
+    shadertoy,
+    texture::{self, ImageTexture},
+    vertex::MeshVertex,
+    GfxState, GpuFactory,
+};
+#[cfg(feature = "post")]
+use crate::{
+    bloom::BloomSettings,
+    post_chain::{PostEffect, PostPassSettings},
+};
+#[cfg(feature = "ui")]
+use crate::{console::Console, scene::Scene};
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 48;
+
+fn headless() -> Option<GfxState> {
+    let builder = GfxStateBuilder::new(Config::default()).force_fallback_adapter(true);
+    match pollster::block_on(builder.build_headless(WIDTH, HEIGHT)) {
+        Ok(app) => Some(app),
+        Err(e) => {
+            println!("Skipped, no fallback adapter: {:#}", e);
+            None
+        }
+    }
+}
+
+#[test]
+fn builds_pipelines_and_bind_groups() {
+    let Some(mut app) = headless() else {
+        return;
+    };
+    app.gpu_factory = Some(GpuFactory::new(&app).unwrap());
+    app.gpu.device.poll(wgpu::Maintain::Wait);
+    app.gpu.check_errors().unwrap();
+}
+
+#[test]
+fn stops_a_benchmark_frame_over_budget() {
+    let Some(mut app) = headless() else {
+        return;
+    };
+    app.config.render.benchmark = true;
+    app.config.render.stats_budget.draw_calls = 0;
+    app.gpu_factory = Some(GpuFactory::new(&app).unwrap());
+    match app.redraw() {
+        Err(Error::OverBudget(counters)) => {
+            assert!(counters.iter().any(|c| c.starts_with("draw calls")))
+        }
+        other => panic!("expected the frame over budget, got {:?}", other),
+    }
+}
+
+#[test]
+fn renders_a_frame_offscreen() {
+    let Some(mut app) = headless() else {
+        return;
+    };
+    app.gpu_factory = Some(GpuFactory::new(&app).unwrap());
+    app.redraw().unwrap();
+
+    let offscreen = app.window.offscreen.as_ref().unwrap();
+    let image = readback::read_texture(&app.gpu.device, &app.gpu.queue, offscreen).unwrap();
+    assert_eq!((image.width, image.height), (WIDTH, HEIGHT));
+    // the sky covers the whole frame, so nothing drawn means a pass didn't run
+    assert!(image
+        .pixels
+        .iter()
+        .any(|pixel| pixel[..3].iter().any(|&c| c > 0.0)));
+}
+
+#[cfg(feature = "post")]
+#[test]
+fn runs_the_post_chain_onto_the_frame() {
+    let Some(mut app) = headless() else {
+        return;
+    };
+    // the vignette only darkens, the grayscale after it has to see its output
+    app.scene.post = vec![
+        PostPassSettings {
+            effect: PostEffect::Vignette,
+            enabled: true,
+            strength: 1.0,
+        },
+        PostPassSettings {
+            effect: PostEffect::Grayscale,
+            enabled: true,
+            strength: 1.0,
+        },
+    ];
+    app.gpu_factory = Some(GpuFactory::new(&app).unwrap());
+    app.redraw().unwrap();
+    app.gpu.check_errors().unwrap();
+
+    let offscreen = app.window.offscreen.as_ref().unwrap();
+    let image = readback::read_texture(&app.gpu.device, &app.gpu.queue, offscreen).unwrap();
+    let gray = |[r, g, b, _]: [f32; 4]| (r - g).abs() < 0.01 && (g - b).abs() < 0.01;
+    assert!(image.pixels.iter().all(|&pixel| gray(pixel)));
+    let corner = image.pixels[0][1];
+    let center = image.pixels[(image.height / 2 * image.width + image.width / 2) as usize][1];
+    assert!(corner < center, "{} {}", corner, center);
+}
+
+#[cfg(feature = "post")]
+#[test]
+fn blooms_the_hdr_target() {
+    let Some(mut app) = headless() else {
+        return;
+    };
+    app.config.deterministic.enabled = true;
+    // no threshold, so everything blooms and adds to the target
+    let mut draw = |enabled| {
+        app.scene.bloom = BloomSettings {
+            enabled,
+            threshold: 0.0,
+            knee: 0.0,
+            intensity: 1.0,
+            radius: 1.0,
+        };
+        app.gpu_factory = Some(GpuFactory::new(&app).unwrap());
+        app.redraw().unwrap();
+        app.gpu.check_errors().unwrap();
+        let hdr = &app.gpu_factory.as_ref().unwrap().tonemap.hdr.texture;
+        let image = readback::read_texture(&app.gpu.device, &app.gpu.queue, hdr).unwrap();
+        image.pixels.iter().map(|pixel| pixel[1]).sum::<f32>()
+    };
+    let plain = draw(false);
+    let bloomed = draw(true);
+    assert!(bloomed > plain * 1.5, "{} {}", plain, bloomed);
+}
+
+#[test]
+fn antialiases_with_fxaa() {
+    let Some(mut app) = headless() else {
+        return;
+    };
+    for luma_pass in [false, true] {
+        app.config.render.fxaa = FxaaSettings {
+            enabled: true,
+            quality: FxaaQuality::Extreme,
+            luma_pass,
+        };
+        app.gpu_factory = Some(GpuFactory::new(&app).unwrap());
+        app.redraw().unwrap();
+        app.gpu.check_errors().unwrap();
+
+        let offscreen = app.window.offscreen.as_ref().unwrap();
+        let image = readback::read_texture(&app.gpu.device, &app.gpu.queue, offscreen).unwrap();
+        assert!(image.pixels.iter().all(|pixel| pixel[3] > 0.0));
+    }
+}
+
+#[test]
+fn encodes_passes_in_parallel_like_on_one_thread() {
+    // the same first frame of a new app, recorded both ways
+    let draw = |parallel| {
+        let mut app = headless()?;
+        app.config.deterministic.enabled = true;
+        // tonemap, fxaa and sharpen, a run of thread safe passes
+        app.config.render.fxaa.enabled = true;
+        app.config.render.sharpen.enabled = true;
+        app.config.render.parallel_encoding = parallel;
+        app.gpu_factory = Some(GpuFactory::new(&app).unwrap());
+        assert!(app.gpu_factory.as_ref().unwrap().encoders.is_some());
+        app.redraw().unwrap();
+        app.gpu.check_errors().unwrap();
+
+        let offscreen = app.window.offscreen.as_ref().unwrap();
+        let image = readback::read_texture(&app.gpu.device, &app.gpu.queue, offscreen).unwrap();
+        Some(image.pixels)
+    };
+    let Some(serial) = draw(false) else {
+        return;
+    };
+    assert_eq!(Some(serial), draw(true));
+}
+
+#[test]
+fn adds_particles_onto_the_scene() {
+    let Some(mut app) = headless() else {
+        return;
+    };
+    app.config.deterministic.enabled = true;
+    let camera = &app.frame.camera;
+    let emitter = camera.eye + (camera.target - camera.eye).normalize() * 3.0;
+    let mut draw = |enabled| {
+        // big, bright and slow, so they stay in view for the frames drawn
+        app.scene.particles = ParticleSettings {
+            enabled,
+            emitter: emitter.into(),
+            spawn_rate: 2000.0,
+            gravity: [0.0; 3],
+            speed: 0.2,
+            size: 0.2,
+            intensity: 10.0,
+            ..Default::default()
+        };
+        app.gpu_factory = Some(GpuFactory::new(&app).unwrap());
+        // particles are None where compute isn't available
+        app.gpu_factory.as_ref().unwrap().particles.as_ref()?;
+        for _ in 0..3 {
+            app.redraw().unwrap();
+        }
+        app.gpu.check_errors().unwrap();
+        let hdr = &app.gpu_factory.as_ref().unwrap().tonemap.hdr.texture;
+        let image = readback::read_texture(&app.gpu.device, &app.gpu.queue, hdr).unwrap();
+        Some(image.pixels.iter().map(|pixel| pixel[0]).sum::<f32>())
+    };
+    let (Some(plain), Some(with_particles)) = (draw(false), draw(true)) else {
+        return;
+    };
+    assert!(with_particles > plain * 1.1, "{} {}", plain, with_particles);
+}
+
+#[test]
+fn ray_marches_the_sdf_scene() {
+    let Some(mut app) = headless() else {
+        return;
+    };
+    let mut gpu_factory = GpuFactory::new(&app).unwrap();
+    gpu_factory.sdf_mode = true;
+    app.gpu_factory = Some(gpu_factory);
+    app.redraw().unwrap();
+    app.gpu.check_errors().unwrap();
+
+    let hdr = &app.gpu_factory.as_ref().unwrap().tonemap.hdr.texture;
+    let image = readback::read_texture(&app.gpu.device, &app.gpu.queue, hdr).unwrap();
+    assert!(image
+        .pixels
+        .iter()
+        .all(|pixel| pixel.iter().all(|c| c.is_finite())));
+    assert!(image.pixels.iter().any(|pixel| pixel[0] > 0.0));
+}
+
+#[test]
+fn draws_pipelines_added_by_the_builder() {
+    let Some(mut app) = headless() else {
+        return;
+    };
+    let directory = std::env::temp_dir().join("factory builder test");
+    std::fs::create_dir_all(&directory).unwrap();
+    let shader = directory.join("fill.wgsl");
+    std::fs::write(
+        &shader,
+        "
+        @group(1) @binding(0) var<uniform> fill: vec4f;
+        @vertex
+        fn vs_main(@builtin(vertex_index) vid: u32) -> @builtin(position) vec4f {
+            let uv = vec2f(f32((vid << 1u) & 2u), f32(vid & 2u));
+            return vec4f(uv * 2.0 - 1.0, 0.0, 1.0);
+        }
+        @fragment
+        fn fs_main() -> @location(0) vec4f {
+            return fill;
+        }
+        ",
+    )
+    .unwrap();
+    GpuFactoryBuilder::new()
+        .with_shader(&shader)
+        .with_uniform::<[f32; 4]>("fill", wgpu::ShaderStages::FRAGMENT)
+        .with_pipeline(CustomPipelineDesc::new("fill"))
+        .with_shader(directory.join("missing.wgsl"))
+        .with_pipeline(CustomPipelineDesc::new("missing"))
+        .build(&mut app)
+        .unwrap();
+    let gpu_factory = app.gpu_factory.as_ref().unwrap();
+    // the one without a shader is left out
+    assert_eq!(gpu_factory.custom_draws.len(), 1);
+    assert!(gpu_factory
+        .write_uniform(&app.gpu.queue, "missing", &0u32)
+        .is_err());
+    assert!(gpu_factory
+        .write_uniform(&app.gpu.queue, "fill", &0u32)
+        .is_err());
+    gpu_factory
+        .write_uniform(&app.gpu.queue, "fill", &[0.0f32, 4.0, 0.0, 1.0])
+        .unwrap();
+    app.redraw().unwrap();
+    app.gpu.check_errors().unwrap();
+
+    let hdr = &app.gpu_factory.as_ref().unwrap().tonemap.hdr.texture;
+    let image = readback::read_texture(&app.gpu.device, &app.gpu.queue, hdr).unwrap();
+    let center = image.pixels[(image.height / 2 * image.width + image.width / 2) as usize];
+    assert!(center[1] > 1.0 && center[0] < 0.5, "{:?}", center);
+    // and it comes back with the factory
+    app.gpu_factory = Some(GpuFactory::new(&app).unwrap());
+    assert_eq!(app.gpu_factory.as_ref().unwrap().custom_draws.len(), 1);
+}
+
+#[test]
+fn samples_textures_added_by_the_builder() {
+    let Some(mut app) = headless() else {
+        return;
+    };
+    let directory = std::env::temp_dir().join("builder texture test");
+    std::fs::create_dir_all(&directory).unwrap();
+    let image = directory.join("green.png");
+    image::RgbaImage::from_pixel(2, 2, image::Rgba([0, 255, 0, 255]))
+        .save(&image)
+        .unwrap();
+    let shader = directory.join("textured.wgsl");
+    std::fs::write(
+        &shader,
+        "
+        @group(1) @binding(0) var color: texture_2d<f32>;
+        @group(1) @binding(1) var color_sampler: sampler;
+        @vertex
+        fn vs_main(@builtin(vertex_index) vid: u32) -> @builtin(position) vec4f {
+            let uv = vec2f(f32((vid << 1u) & 2u), f32(vid & 2u));
+            return vec4f(uv * 2.0 - 1.0, 0.0, 1.0);
+        }
+        @fragment
+        fn fs_main() -> @location(0) vec4f {
+            return textureSampleLevel(color, color_sampler, vec2(0.5), 0.0) * 4.0;
+        }
+        ",
+    )
+    .unwrap();
+    GpuFactoryBuilder::new()
+        .with_shader(&shader)
+        .with_texture("green", &image, false)
+        .with_pipeline(CustomPipelineDesc::new("textured"))
+        .build(&mut app)
+        .unwrap();
+    app.redraw().unwrap();
+    app.gpu.check_errors().unwrap();
+
+    let hdr = &app.gpu_factory.as_ref().unwrap().tonemap.hdr.texture;
+    let image = readback::read_texture(&app.gpu.device, &app.gpu.queue, hdr).unwrap();
+    let center = image.pixels[(image.height / 2 * image.width + image.width / 2) as usize];
+    assert!(center[1] > 1.0 && center[0] < 0.5, "{:?}", center);
+}
+
+#[test]
+fn builds_alike_custom_pipelines_once() {
+    let Some(mut app) = headless() else {
+        return;
+    };
+    let directory = std::env::temp_dir().join("pipeline cache test");
+    std::fs::create_dir_all(&directory).unwrap();
+    let shader = directory.join("grey.wgsl");
+    std::fs::write(
+        &shader,
+        "
+        @vertex
+        fn vs_main(@builtin(vertex_index) vid: u32) -> @builtin(position) vec4f {
+            let uv = vec2f(f32((vid << 1u) & 2u), f32(vid & 2u));
+            return vec4f(uv * 2.0 - 1.0, 0.0, 1.0);
+        }
+        @fragment
+        fn fs_main() -> @location(0) vec4f {
+            return vec4f(0.5);
+        }
+        ",
+    )
+    .unwrap();
+    // what the factory's materials ask the cache for
+    let materials = GpuFactory::new(&app).unwrap().pipeline_cache.stats();
+    GpuFactoryBuilder::new()
+        .with_shader(&shader)
+        .with_pipeline(CustomPipelineDesc::new("grey"))
+        .with_pipeline(CustomPipelineDesc::new("grey again"))
+        .build(&mut app)
+        .unwrap();
+    let gpu_factory = app.gpu_factory.as_ref().unwrap();
+    let registry = &gpu_factory.registry;
+    assert_eq!(
+        registry.find::<wgpu::RenderPipeline>("grey"),
+        registry.find::<wgpu::RenderPipeline>("grey again")
+    );
+    // the second one's layouts, shader and pipeline
+    let stats = gpu_factory.pipeline_cache.stats();
+    assert_eq!(
+        (stats.hits - materials.hits, stats.misses - materials.misses),
+        (4, 4)
+    );
+    app.redraw().unwrap();
+    app.gpu.check_errors().unwrap();
+}
+
+#[test]
+fn draws_a_custom_pipeline_once_per_push_constant() {
+    let Some(mut app) = headless() else {
+        return;
+    };
+    if !app.gpu.features.push_constants() {
+        println!("Skipped, no push constants");
+        return;
+    }
+    let directory = std::env::temp_dir().join("push constants test");
+    std::fs::create_dir_all(&directory).unwrap();
+    let shader = directory.join("halves.wgsl");
+    std::fs::write(
+        &shader,
+        "
+        // where the half starts in x, then its color
+        var<push_constant> half: vec4f;
+        @vertex
+        fn vs_main(@builtin(vertex_index) vid: u32) -> @builtin(position) vec4f {
+            var corners = array(
+                vec2f(0.0, 0.0), vec2f(1.0, 0.0), vec2f(0.0, 1.0),
+                vec2f(0.0, 1.0), vec2f(1.0, 0.0), vec2f(1.0, 1.0),
+            );
+            let xy = vec2f(half.x, -1.0) + corners[vid] * vec2f(1.0, 2.0);
+            return vec4f(xy, 0.0, 1.0);
+        }
+        @fragment
+        fn fs_main() -> @location(0) vec4f {
+            return vec4f(half.yzw, 1.0);
+        }
+        ",
+    )
+    .unwrap();
+    let mut desc = CustomPipelineDesc::new("halves").with_push_constants::<[f32; 4]>();
+    desc.vertex_count = 6;
+    GpuFactoryBuilder::new()
+        .with_shader(&shader)
+        .with_pipeline(desc)
+        .build(&mut app)
+        .unwrap();
+    let gpu_factory = app.gpu_factory.as_mut().unwrap();
+    assert!(gpu_factory.set_draw_constants("missing", &[0u32]).is_err());
+    assert!(gpu_factory.set_draw_constants("halves", &[0u32]).is_err());
+    gpu_factory
+        .set_draw_constants("halves", &[[-1.0f32, 4.0, 0.0, 0.0], [0.0, 0.0, 0.0, 4.0]])
+        .unwrap();
+    app.redraw().unwrap();
+    app.gpu.check_errors().unwrap();
+
+    let hdr = &app.gpu_factory.as_ref().unwrap().tonemap.hdr.texture;
+    let image = readback::read_texture(&app.gpu.device, &app.gpu.queue, hdr).unwrap();
+    let row = image.height / 2 * image.width;
+    let left = image.pixels[(row + image.width / 4) as usize];
+    let right = image.pixels[(row + image.width * 3 / 4) as usize];
+    assert!(left[0] > 1.0 && left[2] < 0.5, "{:?}", left);
+    assert!(right[2] > 1.0 && right[0] < 0.5, "{:?}", right);
+}
+
+#[test]
+fn renders_more_frames_than_are_in_flight() {
+    let Some(mut app) = headless() else {
+        return;
+    };
+    app.gpu_factory = Some(GpuFactory::new(&app).unwrap());
+    // each frame's uniforms, with the view mode changing under them, go to the next
+    // context, which waits on the frame that used it before
+    let mut view_mode = ViewMode::Final;
+    for _ in 0..FRAMES_IN_FLIGHT * 2 + 1 {
+        app.gpu_factory.as_mut().unwrap().view_mode = view_mode;
+        app.redraw().unwrap();
+        view_mode = view_mode.next();
+    }
+    app.gpu.check_errors().unwrap();
+}
+
+#[cfg(feature = "ui")]
+#[test]
+fn recycles_freed_regions_of_the_buffer_pool() {
+    use crate::buffer_pool::BufferPool;
+
+    let Some(app) = headless() else {
+        return;
+    };
+    let device = &app.gpu.device;
+    let mut pool = BufferPool::new(device, "test pool", wgpu::BufferUsages::VERTEX, 1024);
+    let [a, b, c] = [100, 200, 300].map(|size| pool.alloc(device, size));
+    assert_eq!((a.offset, b.offset, c.offset), (0, 100, 300));
+    pool.free(b);
+    // the first free range it fits in
+    assert_eq!(pool.alloc(device, 60).offset, 100);
+    let d = pool.alloc(device, 140);
+    assert_eq!(d.offset, 160);
+    // bigger than what's left, so a block of its own
+    let e = pool.alloc(device, 2048);
+    assert_eq!((e.offset, pool.bytes()), (0, 1024 + 2048));
+    pool.free(e);
+    let frame = pool.alloc_for_frame(device, 400);
+    assert_eq!(frame.offset, 600);
+    pool.end_frame();
+    assert_eq!(pool.alloc(device, 424).offset, 600);
+    pool.write(&app.gpu.queue, a, &[1; 100]);
+    app.gpu.device.poll(wgpu::Maintain::Wait);
+    app.gpu.check_errors().unwrap();
+}
+
+#[test]
+fn keeps_the_old_factory_when_a_shader_doesnt_compile() {
+    let Some(mut app) = headless() else {
+        return;
+    };
+    app.gpu_factory = Some(GpuFactory::new(&app).unwrap());
+    let directory = std::env::temp_dir().join("shader error test");
+    std::fs::create_dir_all(&directory).unwrap();
+    let shader = directory.join("broken.wgsl");
+    std::fs::write(
+        &shader,
+        "
+        @vertex
+        fn vs_main() -> @builtin(position) vec4f {
+            return vec4f(undeclared);
+        }
+        ",
+    )
+    .unwrap();
+    let built = GpuFactoryBuilder::new()
+        .with_shader(&shader)
+        .with_pipeline(CustomPipelineDesc::new("broken"))
+        .build(&mut app);
+    assert!(built.is_err());
+    let gpu_factory = app.gpu_factory.as_ref().unwrap();
+    assert!(gpu_factory
+        .registry
+        .find::<wgpu::RenderPipeline>("broken")
+        .is_none());
+    app.redraw().unwrap();
+    app.gpu.check_errors().unwrap();
+}
+
+#[test]
+fn refuses_custom_pipelines_the_registry_cant_take() {
+    let Some(mut app) = headless() else {
+        return;
+    };
+    app.gpu_factory = Some(GpuFactory::new(&app).unwrap());
+    let shader = "asset/missing.wgsl";
+    let builders = [
+        // the factory's own names
+        GpuFactoryBuilder::new()
+            .with_shader(shader)
+            .with_pipeline(CustomPipelineDesc::new("sky")),
+        GpuFactoryBuilder::new()
+            .with_shader(shader)
+            .with_uniform::<[f32; 4]>("first uniform", wgpu::ShaderStages::FRAGMENT)
+            .with_pipeline(CustomPipelineDesc::new("tint")),
+        GpuFactoryBuilder::new()
+            .with_shader(shader)
+            .with_pipeline(CustomPipelineDesc::new("tint"))
+            .with_pipeline(CustomPipelineDesc::new("tint")),
+        GpuFactoryBuilder::new().with_pipeline(CustomPipelineDesc::new("tint")),
+    ];
+    for builder in builders {
+        let built = builder.build(&mut app);
+        assert!(matches!(built, Err(Error::Builder(_))), "{:?}", built);
+    }
+    // the factory is left as it was
+    assert!(app.gpu_factory.as_ref().unwrap().custom_draws.is_empty());
+    app.redraw().unwrap();
+    app.gpu.check_errors().unwrap();
+}
+
+#[cfg(feature = "ui")]
+#[test]
+fn keeps_the_scene_when_a_loaded_one_cant_be_built() {
+    let Some(mut app) = headless() else {
+        return;
+    };
+    app.gpu_factory = Some(GpuFactory::new(&app).unwrap());
+    let directory = std::env::temp_dir().join("console_load_test");
+    std::fs::create_dir_all(&directory).unwrap();
+    let file = directory.join("bright.ron");
+    let bright = Scene {
+        exposure_ev: 3.0,
+        ..Default::default()
+    };
+    bright.save(&file).unwrap();
+    let line = format!("load {}", file.display());
+    let exposure = app.scene.exposure_ev;
+    let scene_path = app.config.scene.clone();
+
+    // a custom pipeline that doesn't compile fails the new factory
+    let shader = directory.join("broken.wgsl");
+    std::fs::write(
+        &shader,
+        "@vertex fn vs_main() -> @builtin(position) vec4f { return x; }",
+    )
+    .unwrap();
+    app.factory_builder = GpuFactoryBuilder::new()
+        .with_shader(&shader)
+        .with_pipeline(CustomPipelineDesc::new("broken"));
+    Console::run(&mut app, &line);
+    assert_eq!(app.scene.exposure_ev, exposure);
+    assert_eq!(app.config.scene, scene_path);
+    assert!(app.gpu_factory.is_some());
+    app.redraw().unwrap();
+
+    app.factory_builder = GpuFactoryBuilder::new();
+    Console::run(&mut app, &line);
+    assert_eq!(app.scene.exposure_ev, 3.0);
+    assert_eq!(app.config.scene, file);
+    app.redraw().unwrap();
+    app.gpu.check_errors().unwrap();
+}
+
+// a big triangle facing the camera, halfway to what it looks at; returns it and its center
+fn add_facing_triangle(
+    app: &GfxState,
+    gpu_factory: &mut GpuFactory,
+) -> (MeshHandle, cgmath::Point3<f32>) {
+    let camera = &app.frame.camera;
+    let to_target = camera.target - camera.eye;
+    let forward = to_target.normalize();
+    let right = forward.cross(camera.up).normalize();
+    let up = right.cross(forward);
+    let center = camera.eye + to_target * 0.5;
+    let size = to_target.magnitude() * 4.0;
+    let vertices = [-right - up, right - up, up * 2.0].map(|offset| MeshVertex {
+        position: (center + offset * size).into(),
+        normal: (-forward).into(),
+        uv: [0.0; 2],
+        tangent: [right.x, right.y, right.z, 1.0],
+    });
+    let mesh = gpu_factory.add_mesh(
+        &app.gpu.device,
+        &vertices,
+        &[0u16, 1, 2],
+        Materials::DEFAULT,
+    );
+    (mesh, center)
+}
+
+#[test]
+fn draws_a_mesh_over_the_ray_cast_scene() {
+    let Some(mut app) = headless() else {
+        return;
+    };
+    let mut gpu_factory = GpuFactory::new(&app).unwrap();
+    add_facing_triangle(&app, &mut gpu_factory);
+    app.gpu_factory = Some(gpu_factory);
+    app.redraw().unwrap();
+
+    let id = &app.gpu_factory.as_ref().unwrap().gbuffer.id;
+    let image = readback::read_texture(&app.gpu.device, &app.gpu.queue, id).unwrap();
+    let center = image.pixels[(image.height / 2 * image.width + image.width / 2) as usize];
+    // mesh.wgsl's MESH_ID
+    assert_eq!(center[0], 18.0);
+}
+
+#[test]
+fn leaves_meshes_out_of_the_debug_views() {
+    let Some(mut app) = headless() else {
+        return;
+    };
+    let mut gpu_factory = GpuFactory::new(&app).unwrap();
+    add_facing_triangle(&app, &mut gpu_factory);
+    gpu_factory.view_mode = ViewMode::Normals;
+    app.gpu_factory = Some(gpu_factory);
+    app.redraw().unwrap();
+    app.gpu.check_errors().unwrap();
+
+    let id = &app.gpu_factory.as_ref().unwrap().gbuffer.id;
+    let image = readback::read_texture(&app.gpu.device, &app.gpu.queue, id).unwrap();
+    let center = image.pixels[(image.height / 2 * image.width + image.width / 2) as usize];
+    assert_ne!(center[0], 18.0);
+}
+
+#[test]
+fn draws_instances_of_a_mesh() {
+    let Some(mut app) = headless() else {
+        return;
+    };
+    app.config.deterministic.enabled = true;
+    let mut draw = |instances: Option<&[Instance]>| {
+        let mut gpu_factory = GpuFactory::new(&app).unwrap();
+        let (mesh, _) = add_facing_triangle(&app, &mut gpu_factory);
+        if let Some(instances) = instances {
+            gpu_factory.set_instances(&app.gpu.device, mesh, instances);
+        }
+        app.gpu_factory = Some(gpu_factory);
+        app.redraw().unwrap();
+        app.gpu.check_errors().unwrap();
+        let gpu_factory = app.gpu_factory.as_ref().unwrap();
+        let center = |texture: &wgpu::Texture| {
+            let image = readback::read_texture(&app.gpu.device, &app.gpu.queue, texture).unwrap();
+            image.pixels[(image.height / 2 * image.width + image.width / 2) as usize]
+        };
+        (
+            center(&gpu_factory.tonemap.hdr.texture),
+            center(&gpu_factory.gbuffer.id),
+        )
+    };
+    let (plain, _) = draw(None);
+    // one instance where the mesh is, tinted green
+    let green = Instance {
+        color: [0.0, 1.0, 0.0, 1.0],
+        ..Default::default()
+    };
+    let (tinted, id) = draw(Some(&[green]));
+    assert_eq!(id[0], 18.0);
+    assert!(
+        tinted[0] < plain[0] * 0.05 + 1e-3,
+        "{:?} {:?}",
+        plain,
+        tinted
+    );
+    assert!(
+        (tinted[1] - plain[1]).abs() <= plain[1] * 0.01 + 1e-3,
+        "{:?} {:?}",
+        plain,
+        tinted
+    );
+    // no instances, nothing drawn
+    let (_, id) = draw(Some(&[]));
+    assert_ne!(id[0], 18.0);
+}
+
+#[test]
+fn draws_meshes_from_indirect_args() {
+    let Some(mut app) = headless() else {
+        return;
+    };
+    app.config.render.indirect_draws = true;
+    let mut gpu_factory = GpuFactory::new(&app).unwrap();
+    let (mesh, _) = add_facing_triangle(&app, &mut gpu_factory);
+    assert!(gpu_factory.mesh_draws.is_some() || !app.gpu.features.indirect_execution);
+    app.gpu_factory = Some(gpu_factory);
+    let center_id = |app: &mut GfxState| {
+        app.redraw().unwrap();
+        app.gpu.check_errors().unwrap();
+        let id = &app.gpu_factory.as_ref().unwrap().gbuffer.id;
+        let image = readback::read_texture(&app.gpu.device, &app.gpu.queue, id).unwrap();
+        image.pixels[(image.height / 2 * image.width + image.width / 2) as usize][0]
+    };
+    assert_eq!(center_id(&mut app), 18.0);
+    // the instance count comes from the buffer too
+    let gpu_factory = app.gpu_factory.as_mut().unwrap();
+    gpu_factory.set_instances(&app.gpu.device, mesh, &[]);
+    assert_ne!(center_id(&mut app), 18.0);
+}
+
+#[test]
+fn registers_meshes_and_named_resources() {
+    let Some(app) = headless() else {
+        return;
+    };
+    let mut gpu_factory = GpuFactory::new(&app).unwrap();
+    assert!(!gpu_factory.registry.is_empty::<wgpu::RenderPipeline>());
+    let before = gpu_factory.registry.len::<MeshDraw>();
+    let (first, _) = add_facing_triangle(&app, &mut gpu_factory);
+    let (second, _) = add_facing_triangle(&app, &mut gpu_factory);
+    assert_eq!(gpu_factory.registry.len::<MeshDraw>(), before + 2);
+    assert_eq!(second.index(), first.index() + 1);
+    let registry = &gpu_factory.registry;
+    assert_ne!(
+        registry[first].vertex_buffer,
+        registry[second].vertex_buffer
+    );
+    assert!(registry.find::<wgpu::RenderPipeline>("display").is_some());
+    assert!(registry.find::<wgpu::BindGroup>("scene").is_some());
+    assert!(registry.find::<wgpu::BindGroup>("display").is_none());
+}
+
+#[test]
+fn culls_meshes_out_of_view_on_the_cpu() {
+    let Some(mut app) = headless() else {
+        return;
+    };
+    let mut gpu_factory = GpuFactory::new(&app).unwrap();
+    add_facing_triangle(&app, &mut gpu_factory);
+    let (mesh, _) = add_facing_triangle(&app, &mut gpu_factory);
+    let camera = &app.frame.camera;
+    let behind = camera.eye - camera.target;
+    gpu_factory.set_instances(
+        &app.gpu.device,
+        mesh,
+        &[Instance {
+            position: behind * 40.0,
+            ..Default::default()
+        }],
+    );
+    app.gpu_factory = Some(gpu_factory);
+    app.redraw().unwrap();
+    app.gpu.check_errors().unwrap();
+    let gpu_factory = app.gpu_factory.as_ref().unwrap();
+    assert_eq!(
+        gpu_factory.cull_stats(),
+        CullStats {
+            drawn: 1,
+            culled: 1
+        }
+    );
+    let image =
+        readback::read_texture(&app.gpu.device, &app.gpu.queue, &gpu_factory.gbuffer.id).unwrap();
+    assert_eq!(
+        image.pixels[(image.height / 2 * image.width + image.width / 2) as usize][0],
+        18.0
+    );
+}
+
+#[test]
+fn culls_instances_out_of_view_on_the_gpu() {
+    let Some(mut app) = headless() else {
+        return;
+    };
+    app.config.render.indirect_draws = true;
+    app.config.render.gpu_culling = true;
+    let mut gpu_factory = GpuFactory::new(&app).unwrap();
+    if gpu_factory.gpu_culling.is_none() {
+        println!("Skipped, no indirect draws or compute");
+        return;
+    }
+    let (mesh, _) = add_facing_triangle(&app, &mut gpu_factory);
+    let camera = &app.frame.camera;
+    let behind = camera.eye - camera.target;
+    gpu_factory.set_instances(
+        &app.gpu.device,
+        mesh,
+        &[
+            Instance::default(),
+            // well behind the camera, past the triangle's bounding sphere
+            Instance {
+                position: behind * 40.0,
+                ..Default::default()
+            },
+        ],
+    );
+    app.gpu_factory = Some(gpu_factory);
+    app.redraw().unwrap();
+    app.gpu.check_errors().unwrap();
+
+    let gpu_factory = app.gpu_factory.as_ref().unwrap();
+    let image =
+        readback::read_texture(&app.gpu.device, &app.gpu.queue, &gpu_factory.gbuffer.id).unwrap();
+    assert_eq!(
+        image.pixels[(image.height / 2 * image.width + image.width / 2) as usize][0],
+        18.0
+    );
+    let offset = mesh.index() as u64 * IndirectDraws::ARGS_SIZE;
+    let args = readback::wait(
+        &app.gpu.device,
+        readback::read_buffer(
+            &app.gpu.device,
+            &app.gpu.queue,
+            &gpu_factory.mesh_draws.as_ref().unwrap().buffer,
+            offset..offset + IndirectDraws::ARGS_SIZE,
+        ),
+    )
+    .unwrap();
+    let instance_count = u32::from_le_bytes(args[4..8].try_into().unwrap());
+    // only the one in front is left
+    assert_eq!(instance_count, 1);
+}
+
+#[test]
+fn runs_compute_jobs_in_the_frame() {
+    let Some(mut app) = headless() else {
+        return;
+    };
+    let mut gpu_factory = GpuFactory::new(&app).unwrap();
+    let device = &app.gpu.device;
+    let output = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("compute job output"),
+        size: 64 * 4,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+    let layout = compute::bind_group_layout(
+        device,
+        "compute job layout",
+        wgpu::ShaderStages::COMPUTE,
+        &[ComputeBinding::StorageBuffer { read_only: false }],
+    );
+    let code = "
+        @group(0) @binding(0) var<storage, read_write> output: array<u32>;
+        @compute @workgroup_size(64)
+        fn double(@builtin(global_invocation_id) id: vec3u) {
+            output[id.x] = id.x * 2u;
+        }
+    ";
+    let Some(pipeline) =
+        gpu_factory.add_compute_pipeline(&app.gpu, "double", code, "double", &[&layout])
+    else {
+        println!("Skipped, no compute");
+        return;
+    };
+    let bind_group = compute::bind_group(
+        device,
+        "compute job bind group",
+        &layout,
+        &[output.as_entire_binding()],
+    );
+    gpu_factory.add_compute_job(ComputeJob {
+        label: "double",
+        pipeline,
+        bind_groups: vec![bind_group],
+        workgroups: [1, 1, 1],
+        stage: ComputeStage::BeforeRender,
+        enabled: true,
+    });
+    app.gpu_factory = Some(gpu_factory);
+    app.redraw().unwrap();
+    app.gpu.check_errors().unwrap();
+    let bytes = readback::wait(
+        &app.gpu.device,
+        readback::read_buffer(&app.gpu.device, &app.gpu.queue, &output, 0..64 * 4),
+    )
+    .unwrap();
+    let values: Vec<u32> = bytes
+        .chunks_exact(4)
+        .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()))
+        .collect();
+    assert_eq!(values, (0..64).map(|i| i * 2).collect::<Vec<u32>>());
+}
+
+#[test]
+fn runs_compute_jobs_on_textures() {
+    let Some(mut app) = headless() else {
+        return;
+    };
+    let mut gpu_factory = GpuFactory::new(&app).unwrap();
+    let device = &app.gpu.device;
+    let directory = std::env::temp_dir().join("compute texture test");
+    std::fs::create_dir_all(&directory).unwrap();
+    let path = directory.join("input.png");
+    image::RgbaImage::from_pixel(4, 4, image::Rgba([255, 0, 255, 255]))
+        .save(&path)
+        .unwrap();
+    let input = ImageTexture::load(device, &app.gpu.queue, &path, false).unwrap();
+    // the texture on its own, as a single textured draw would bind it
+    input.bind_group(device, &texture::texture_bind_group_layout(device));
+    let output = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("compute job output"),
+        size: wgpu::Extent3d {
+            width: 4,
+            height: 4,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+    let output_view = output.create_view(&wgpu::TextureViewDescriptor::default());
+    let layout = compute::bind_group_layout(
+        device,
+        "compute texture layout",
+        wgpu::ShaderStages::COMPUTE,
+        &[
+            ComputeBinding::Texture,
+            ComputeBinding::Sampler,
+            ComputeBinding::StorageTexture {
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                access: wgpu::StorageTextureAccess::WriteOnly,
+            },
+        ],
+    );
+    let code = "
+        @group(0) @binding(0) var input: texture_2d<f32>;
+        @group(0) @binding(1) var input_sampler: sampler;
+        @group(0) @binding(2) var output: texture_storage_2d<rgba8unorm, write>;
+        @compute @workgroup_size(4, 4)
+        fn swizzle(@builtin(global_invocation_id) id: vec3u) {
+            let uv = (vec2f(id.xy) + 0.5) / 4.0;
+            let color = textureSampleLevel(input, input_sampler, uv, 0.0);
+            textureStore(output, id.xy, color.gbra);
+        }
+    ";
+    let Some(pipeline) =
+        gpu_factory.add_compute_pipeline(&app.gpu, "swizzle", code, "swizzle", &[&layout])
+    else {
+        println!("Skipped, no compute");
+        return;
+    };
+    let bind_group = compute::bind_group(
+        device,
+        "compute texture bind group",
+        &layout,
+        &[
+            wgpu::BindingResource::TextureView(&input.view),
+            wgpu::BindingResource::Sampler(&input.sampler),
+            wgpu::BindingResource::TextureView(&output_view),
+        ],
+    );
+    gpu_factory.add_compute_job(ComputeJob {
+        label: "swizzle",
+        pipeline,
+        bind_groups: vec![bind_group],
+        workgroups: [1, 1, 1],
+        stage: ComputeStage::AfterRender,
+        enabled: true,
+    });
+    app.gpu_factory = Some(gpu_factory);
+    app.redraw().unwrap();
+    app.gpu.check_errors().unwrap();
+    let image = readback::read_texture(&app.gpu.device, &app.gpu.queue, &output).unwrap();
+    assert!(image
+        .pixels
+        .iter()
+        .all(|&pixel| pixel == [0.0, 1.0, 1.0, 1.0]));
+}
+
+#[test]
+fn flies_boids_through_the_instancing_path() {
+    let Some(mut app) = headless() else {
+        return;
+    };
+    app.config.deterministic.enabled = true;
+    let camera = &app.frame.camera;
+    let center = camera.eye + (camera.target - camera.eye).normalize() * 6.0;
+    // a tight flock of big cones, so some cover pixels whichever way they fly
+    app.scene.boids = BoidsSettings {
+        enabled: true,
+        count: 256,
+        center: center.into(),
+        extent: [1.0; 3],
+        size: 0.6,
+        ..Default::default()
+    };
+    app.gpu_factory = Some(GpuFactory::new(&app).unwrap());
+    if app.gpu_factory.as_ref().unwrap().boids.is_none() {
+        println!("Skipped, no compute");
+        return;
+    }
+    for _ in 0..3 {
+        app.redraw().unwrap();
+    }
+    app.gpu.check_errors().unwrap();
+
+    let id = &app.gpu_factory.as_ref().unwrap().gbuffer.id;
+    let image = readback::read_texture(&app.gpu.device, &app.gpu.queue, id).unwrap();
+    // mesh.wgsl's MESH_ID
+    assert!(image.pixels.iter().any(|pixel| pixel[0] == 18.0));
+}
+
+#[test]
+fn lights_meshes_on_the_deferred_path() {
+    let Some(mut app) = headless() else {
+        return;
+    };
+    app.config.deterministic.enabled = true;
+    let mut draw = |render_path, lights: Vec<LocalLight>| {
+        app.config.render.render_path = render_path;
+        let mut gpu_factory = GpuFactory::new(&app).unwrap();
+        let (_, center) = add_facing_triangle(&app, &mut gpu_factory);
+        // in front of the triangle, between it and the camera
+        let toward_camera = (app.frame.camera.eye - center).normalize();
+        app.scene.lights = lights
+            .into_iter()
+            .map(|light| LocalLight {
+                position: (center + toward_camera).into(),
+                ..light
+            })
+            .collect();
+        app.gpu_factory = Some(gpu_factory);
+        app.redraw().unwrap();
+        app.gpu.check_errors().unwrap();
+        let hdr = &app.gpu_factory.as_ref().unwrap().tonemap.hdr.texture;
+        let image = readback::read_texture(&app.gpu.device, &app.gpu.queue, hdr).unwrap();
+        image.pixels[(image.height / 2 * image.width + image.width / 2) as usize]
+    };
+    let forward = draw(RenderPath::Forward, vec![]);
+    let deferred = draw(RenderPath::Deferred, vec![]);
+    // the same shading, only the albedo is stored at 8 bits
+    for (f, d) in forward[..3].iter().zip(&deferred[..3]) {
+        assert!(
+            (f - d).abs() <= f * 0.02 + 1e-3,
+            "{:?} {:?}",
+            forward,
+            deferred
+        );
+    }
+    let lit = draw(RenderPath::Deferred, vec![LocalLight::default()]);
+    assert!(lit[1] > deferred[1] + 1.0, "{:?} {:?}", deferred, lit);
+}
+
+#[test]
+fn shows_every_graph_texture_in_the_debug_view() {
+    let Some(mut app) = headless() else {
+        return;
+    };
+    // the passes that import the G-buffer, the reflection and the deferred targets
+    app.config.render.render_path = RenderPath::Deferred;
+    app.scene.water.enabled = true;
+    app.scene.ssr.enabled = true;
+    let mut gpu_factory = GpuFactory::new(&app).unwrap();
+    add_facing_triangle(&app, &mut gpu_factory);
+    app.gpu_factory = Some(gpu_factory);
+    app.redraw().unwrap();
+    let mut shown = vec![];
+    loop {
+        let gpu_factory = app.gpu_factory.as_mut().unwrap();
+        gpu_factory.cycle_debug_texture();
+        let Some(texture) = gpu_factory.debug_texture else {
+            break;
+        };
+        shown.push(texture);
+        app.redraw().unwrap();
+        app.gpu.check_errors().unwrap();
+    }
+    assert!(shown.contains(&"gbuffer depth"), "{:?}", shown);
+    // integer targets can't be blitted
+    assert!(!shown.contains(&"gbuffer id"), "{:?}", shown);
+}
+
+#[test]
+fn builds_every_material_pipeline() {
+    let Some(app) = headless() else {
+        return;
+    };
+    let mut gpu_factory = GpuFactory::new(&app).unwrap();
+    for shader in [
+        MaterialShader::Mesh,
+        MaterialShader::Lit,
+        MaterialShader::Pbr,
+    ] {
+        for blend in [Blend::Opaque, Blend::Alpha] {
+            gpu_factory.materials.add(
+                &app.gpu.device,
+                &app.gpu.queue,
+                &mut gpu_factory.registry,
+                &mut gpu_factory.pipeline_cache,
+                MaterialDesc {
+                    name: format!("{:?} {:?}", shader, blend),
+                    shader,
+                    params: MaterialParams::default(),
+                    textures: Default::default(),
+                    blend,
+                    cull_mode: None,
+                },
+            );
+        }
+    }
+    // and the default material's, which culls back faces
+    let pipelines: HashSet<_> = gpu_factory
+        .materials
+        .materials
+        .iter()
+        .map(|material| material.pipeline)
+        .collect();
+    assert_eq!(pipelines.len(), 1 + 6);
+    app.gpu.device.poll(wgpu::Maintain::Wait);
+    app.gpu.check_errors().unwrap();
+}
+
+#[test]
+fn draws_materials_at_their_offsets_in_the_shared_params_buffer() {
+    let Some(mut app) = headless() else {
+        return;
+    };
+    let mut gpu_factory = GpuFactory::new(&app).unwrap();
+    let (mesh, _) = add_facing_triangle(&app, &mut gpu_factory);
+    // past the buffer's first 16, so it grows and the bind groups are made again
+    let mut last = Materials::DEFAULT;
+    for i in 0..40 {
+        let base_color = match i {
+            39 => [0.0, 1.0, 0.0, 1.0],
+            _ => [1.0, 0.0, 0.0, 1.0],
+        };
+        last = gpu_factory.materials.add(
+            &app.gpu.device,
+            &app.gpu.queue,
+            &mut gpu_factory.registry,
+            &mut gpu_factory.pipeline_cache,
+            MaterialDesc {
+                name: format!("material {}", i),
+                shader: MaterialShader::Mesh,
+                params: MaterialParams {
+                    base_color,
+                    ..MaterialParams::default()
+                },
+                textures: Default::default(),
+                blend: Blend::Opaque,
+                cull_mode: Some(wgpu::Face::Back),
+            },
+        );
+    }
+    gpu_factory.registry[mesh].material = last;
+    // none of them has textures, so they all bind the same group
+    let (first, _) = gpu_factory.materials.bind_group(Materials::DEFAULT);
+    let (bind_group, offset) = gpu_factory.materials.bind_group(last);
+    assert!(std::ptr::eq(first, bind_group));
+    assert!(offset > 0);
+    app.gpu_factory = Some(gpu_factory);
+    app.redraw().unwrap();
+    app.gpu.check_errors().unwrap();
+
+    let hdr = &app.gpu_factory.as_ref().unwrap().tonemap.hdr.texture;
+    let image = readback::read_texture(&app.gpu.device, &app.gpu.queue, hdr).unwrap();
+    let center = image.pixels[(image.height / 2 * image.width + image.width / 2) as usize];
+    assert!(center[1] > center[0], "{:?}", center);
+}
+
+#[test]
+fn draws_a_skybox_where_the_ray_cast_sees_sky() {
+    let Some(mut app) = headless() else {
+        return;
+    };
+    let directory = std::env::temp_dir().join("skybox test");
+    std::fs::create_dir_all(&directory).unwrap();
+    app.scene.skybox.faces = (0..6)
+        .map(|i| {
+            let path = directory.join(format!("face {}.png", i));
+            image::RgbaImage::from_pixel(2, 2, image::Rgba([255, 0, 0, 255]))
+                .save(&path)
+                .unwrap();
+            path
+        })
+        .collect();
+    app.gpu_factory = Some(GpuFactory::new(&app).unwrap());
+    assert!(app.gpu_factory.as_ref().unwrap().skybox.is_some());
+    app.redraw().unwrap();
+
+    let gpu_factory = app.gpu_factory.as_ref().unwrap();
+    let read = |texture| readback::read_texture(&app.gpu.device, &app.gpu.queue, texture).unwrap();
+    let ids = read(&gpu_factory.gbuffer.id);
+    let hdr = read(&gpu_factory.tonemap.hdr.texture);
+    // sky is id 0, the skybox only covers those pixels
+    let sky = ids.pixels.iter().position(|id| id[0] == 0.0).unwrap();
+    let ground = ids.pixels.iter().position(|id| id[0] == 1.0).unwrap();
+    // later passes add a little on top
+    let red = |[r, g, b, _]: [f32; 4]| r > 0.9 && g < 0.01 && b < 0.01;
+    assert!(red(hdr.pixels[sky]), "{:?}", hdr.pixels[sky]);
+    assert!(!red(hdr.pixels[ground]), "{:?}", hdr.pixels[ground]);
+}
+
+#[test]
+fn lights_pbr_meshes_from_an_hdr_environment_map() {
+    let Some(mut app) = headless() else {
+        return;
+    };
+    let directory = std::env::temp_dir().join("ibl test");
+    std::fs::create_dir_all(&directory).unwrap();
+    let path = directory.join("uniform.hdr");
+    let radiance = [0.5, 1.0, 2.0];
+    image::Rgb32FImage::from_pixel(16, 8, image::Rgb(radiance))
+        .save(&path)
+        .unwrap();
+    app.scene.ibl.path = Some(path);
+
+    // GL can't copy cube maps out, so the maps are checked through what they light:
+    // the HDR target under a rough white triangle facing the camera, and the sky
+    let mut draw = |intensity| {
+        app.scene.ibl.intensity = intensity;
+        let mut gpu_factory = GpuFactory::new(&app).unwrap();
+        assert!(gpu_factory.environment_map.loaded());
+        let material = gpu_factory.materials.add(
+            &app.gpu.device,
+            &app.gpu.queue,
+            &mut gpu_factory.registry,
+            &mut gpu_factory.pipeline_cache,
+            MaterialDesc {
+                name: "rough".to_string(),
+                shader: MaterialShader::Pbr,
+                params: MaterialParams {
+                    roughness: 1.0,
+                    ..MaterialParams::default()
+                },
+                textures: Default::default(),
+                blend: Blend::Opaque,
+                cull_mode: None,
+            },
+        );
+        let camera = &app.frame.camera;
+        let forward = (camera.target - camera.eye).normalize();
+        let right = forward.cross(camera.up).normalize();
+        let up = right.cross(forward);
+        let center = camera.eye + forward * 0.5;
+        let vertices = [-right - up, right - up, up * 2.0].map(|offset| MeshVertex {
+            position: (center + offset * 0.1).into(),
+            normal: (-forward).into(),
+            uv: [0.0; 2],
+            tangent: [right.x, right.y, right.z, 1.0],
+        });
+        gpu_factory.add_mesh(&app.gpu.device, &vertices, &[0u16, 1, 2], material);
+        app.gpu_factory = Some(gpu_factory);
+        app.redraw().unwrap();
+        app.gpu.check_errors().unwrap();
+
+        let gpu_factory = app.gpu_factory.as_ref().unwrap();
+        let read =
+            |texture| readback::read_texture(&app.gpu.device, &app.gpu.queue, texture).unwrap();
+        let hdr = read(&gpu_factory.tonemap.hdr.texture);
+        let lut = read(&gpu_factory.environment_map.brdf_lut);
+        let ids = read(&gpu_factory.gbuffer.id);
+        (hdr, lut, ids)
+    };
+    let (unlit, _, _) = draw(0.0);
+    let (lit, lut, ids) = draw(1.0);
+
+    // the same light from everywhere: the background is the image, and the rough
+    // dielectric gets about all of it back, diffuse and specular together
+    let sky = lit.pixels[ids.pixels.iter().position(|id| id[0] == 0.0).unwrap()];
+    let center = (lit.height / 2 * lit.width + lit.width / 2) as usize;
+    for (i, expected) in radiance.into_iter().enumerate() {
+        assert!((sky[i] - expected).abs() < expected * 0.05, "{:?}", sky);
+        let added = lit.pixels[center][i] - unlit.pixels[center][i];
+        assert!(
+            (added - expected).abs() < expected * 0.15,
+            "{:?}",
+            lit.pixels[center]
+        );
+    }
+    // looking straight down a smooth surface nearly all the light comes back
+    let [scale, bias, ..] = lut.pixels[(lut.width - 1) as usize];
+    assert!((0.9..1.05).contains(&(scale + bias)), "{} {}", scale, bias);
+}
+
+#[test]
+fn uploads_and_reads_back_an_image_texture() {
+    let Some(app) = headless() else {
+        return;
+    };
+    // 3 texels wide, so rows aren't a multiple of the copy alignment
+    let decoded = DecodedTexture {
+        width: 3,
+        height: 2,
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        texels: (0..24).map(|i| i * 10).collect(),
+    };
+    let texture = ImageTexture::from_decoded(&app.gpu.device, &app.gpu.queue, "test", &decoded);
+    let image = readback::read_texture(&app.gpu.device, &app.gpu.queue, &texture.texture).unwrap();
+    assert_eq!((image.width, image.height), (3, 2));
+    let texels: Vec<u8> = image
+        .pixels
+        .iter()
+        .flatten()
+        .map(|&c| (c * 255.0).round() as u8)
+        .collect();
+    assert_eq!(texels, decoded.texels);
+}
+
+#[test]
+fn tracks_the_left_button_as_shadertoys_imouse() {
+    let Some(mut app) = headless() else {
+        return;
+    };
+    app.gpu_factory = Some(GpuFactory::new(&app).unwrap());
+    // moving without the button held leaves it where it was
+    app.handle_input(FrameInput::CursorMoved(10.0, 20.0));
+    assert_eq!(app.frame.mouse.uniform(), [0.0; 4]);
+    app.handle_input(FrameInput::MouseInput(MouseButton::Left, true));
+    app.handle_input(FrameInput::CursorMoved(30.0, 40.0));
+    assert_eq!(app.frame.mouse.uniform(), [30.0, 40.0, 10.0, 20.0]);
+    app.redraw().unwrap();
+    assert_eq!(app.frame.mouse.uniform(), [30.0, 40.0, 10.0, -20.0]);
+    app.handle_input(FrameInput::MouseInput(MouseButton::Right, false));
+    app.handle_input(FrameInput::MouseInput(MouseButton::Left, false));
+    app.handle_input(FrameInput::CursorMoved(50.0, 60.0));
+    assert_eq!(app.frame.mouse.uniform(), [30.0, 40.0, -10.0, -20.0]);
+}
+
+#[test]
+fn reads_buffers_back_on_a_later_frame() {
+    let Some(mut app) = headless() else {
+        return;
+    };
+    app.gpu_factory = Some(GpuFactory::new(&app).unwrap());
+    app.redraw().unwrap();
+    let gpu_factory = app.gpu_factory.as_ref().unwrap();
+    let uniform = gpu_factory
+        .registry
+        .find::<wgpu::Buffer>("first uniform")
+        .unwrap();
+    let size = gpu_factory.registry[uniform].size();
+    let past_end = gpu_factory.read_buffer(&app, uniform, size + 4..size + 8);
+    assert!(readback::wait(&app.gpu.device, past_end).is_err());
+    let expected = readback::wait(
+        &app.gpu.device,
+        gpu_factory.read_buffer(&app, uniform, 0..16),
+    )
+    .unwrap();
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    gpu_factory.readbacks.push(
+        gpu_factory.read_buffer(&app, uniform, 0..16),
+        move |bytes| sender.send(bytes.unwrap()).unwrap(),
+    );
+    // nothing waits on the GPU, the frames' polls land it
+    for _ in 0..FRAMES_IN_FLIGHT + 1 {
+        app.redraw().unwrap();
+    }
+    assert_eq!(receiver.try_recv().unwrap(), expected);
+    assert!(app.gpu_factory.as_ref().unwrap().readbacks.is_empty());
+}
+
+#[test]
+fn keeps_the_last_size_while_minimized() {
+    let Some(mut app) = headless() else {
+        return;
+    };
+    app.gpu_factory = Some(GpuFactory::new(&app).unwrap());
+    app.handle_input(FrameInput::Resized(PhysicalSize::new(0, 0)));
+    let config = &app.window.surface_config;
+    assert_eq!((config.width, config.height), (WIDTH, HEIGHT));
+    app.redraw().unwrap();
+    assert!(app.frame.camera.aspect.is_finite());
+
+    app.handle_input(FrameInput::Resized(PhysicalSize::new(32, 16)));
+    let offscreen = app.window.offscreen.as_ref().unwrap();
+    assert_eq!((offscreen.width(), offscreen.height()), (32, 16));
+    app.redraw().unwrap();
+    assert_eq!(app.frame.camera.aspect, 2.0);
+    app.gpu.check_errors().unwrap();
+}
+
+#[test]
+fn runs_a_shadertoy_main_image_over_the_scene() {
+    let Some(mut app) = headless() else {
+        return;
+    };
+    let directory = std::env::temp_dir().join("shadertoy test");
+    std::fs::create_dir_all(&directory).unwrap();
+    let shader = directory.join("gradient.wgsl");
+    std::fs::write(
+        &shader,
+        "
+        fn mainImage(fragColor: ptr<function, vec4f>, fragCoord: vec2f) {
+            *fragColor = vec4(fragCoord / iResolution.xy, 0.0, 1.0);
+        }
+        ",
+    )
+    .unwrap();
+    // the example toy is drawn first and covered, the two share the uniform
+    GpuFactoryBuilder::new()
+        .with_shadertoy("example", "asset/shadertoy_example.wgsl")
+        .with_shadertoy("gradient", &shader)
+        .build(&mut app)
+        .unwrap();
+    assert!(app.gpu_factory.as_ref().unwrap().runs_shadertoy());
+    app.redraw().unwrap();
+    app.gpu.check_errors().unwrap();
+
+    let gpu_factory = app.gpu_factory.as_ref().unwrap();
+    let uniform = gpu_factory
+        .registry
+        .find::<wgpu::Buffer>(shadertoy::UNIFORM_NAME)
+        .unwrap();
+    let resolution = readback::wait(
+        &app.gpu.device,
+        gpu_factory.read_buffer(&app, uniform, 0..8),
+    )
+    .unwrap();
+    assert_eq!(
+        bytemuck::pod_read_unaligned::<[f32; 2]>(&resolution),
+        [WIDTH as f32, HEIGHT as f32]
+    );
+    let hdr = &gpu_factory.tonemap.hdr.texture;
+    let image = readback::read_texture(&app.gpu.device, &app.gpu.queue, hdr).unwrap();
+    // fragCoord counts up from the bottom left
+    let top_right = image.pixels[(image.width - 1) as usize];
+    let bottom_left = image.pixels[((image.height - 1) * image.width) as usize];
+    assert!(top_right[0] > 0.9 && top_right[1] > 0.9, "{:?}", top_right);
+    assert!(
+        bottom_left[0] < 0.1 && bottom_left[1] < 0.1,
+        "{:?}",
+        bottom_left
+    );
+}
@@ -0,0 +1,213 @@
+use std::{
+    cell::{Cell, RefCell},
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use wgpu::{Buffer, QuerySet};
+
+use crate::{
+    features::GpuFeatures,
+    profiler::{self, GpuZones, PlotName},
+};
+
+// readback states, written by the map callback
+const PENDING: u8 = 0;
+const MAPPED: u8 = 1;
+const FAILED: u8 = 2;
+
+// render graph passes timed per frame, the rest only count towards the whole frame
+pub const MAX_TIMED_PASSES: u32 = 32;
+// the frame's start and end come first, then a start and end per pass
+const QUERY_COUNT: u32 = 2 + 2 * MAX_TIMED_PASSES;
+
+/// Measures how long the GPU spends on a frame with a timestamp at the start and one at
+/// the end of its command encoder, and on each render graph pass with a pair around it.
+/// The result is read back without waiting: while one readback is in flight, frames go
+/// unmeasured, and the latest values are whatever the last finished readback held.
+pub struct GpuTimer {
+    query_set: QuerySet,
+    resolve_buffer: Buffer,
+    readback_buffer: Buffer,
+    // nanoseconds per tick
+    period: f32,
+    in_flight: Cell<bool>,
+    // set by the map callback, the buffer is read on the next frame
+    map_state: Arc<AtomicU8>,
+    latest_ms: Cell<Option<f32>>,
+    // passes of the frame being recorded, by query pair; written from the graph's threads
+    pass_names: Mutex<Vec<&'static str>>,
+    // passes of the frame being read back
+    measured_passes: RefCell<Vec<&'static str>>,
+    latest_passes: RefCell<Vec<(&'static str, f32)>>,
+    // measured frames for Tracy, with the tracy feature
+    gpu_zones: GpuZones,
+}
+
+impl GpuTimer {
+    /// None when the adapter can't write timestamps from a command encoder.
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, features: &GpuFeatures) -> Option<Self> {
+        if !features.encoder_timestamps() {
+            println!("GPU timer disabled: no timestamp queries inside encoders");
+            return None;
+        }
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("frame timestamps"),
+            ty: wgpu::QueryType::Timestamp,
+            count: QUERY_COUNT,
+        });
+        let size = QUERY_COUNT as u64 * wgpu::QUERY_SIZE as u64;
+        let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("frame timestamps resolve"),
+            size,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("frame timestamps readback"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Some(Self {
+            query_set,
+            resolve_buffer,
+            readback_buffer,
+            period: queue.get_timestamp_period(),
+            in_flight: Cell::new(false),
+            map_state: Arc::new(AtomicU8::new(PENDING)),
+            latest_ms: Cell::new(None),
+            pass_names: Mutex::new(vec![]),
+            measured_passes: RefCell::new(vec![]),
+            latest_passes: RefCell::new(vec![]),
+            gpu_zones: GpuZones::default(),
+        })
+    }
+
+    /// GPU milliseconds of the most recently measured frame.
+    pub fn latest_ms(&self) -> Option<f32> {
+        self.latest_ms.get()
+    }
+
+    /// GPU milliseconds of each timed pass of the most recently measured frame, in the
+    /// order they ran.
+    #[cfg(feature = "ui")]
+    pub fn latest_passes(&self) -> Vec<(&'static str, f32)> {
+        self.latest_passes.borrow().clone()
+    }
+
+    /// Where the render graph writes its pass timestamps; only valid between a `begin`
+    /// that returned true and its `end`.
+    pub fn pass_queries(&self) -> PassQueries<'_> {
+        PassQueries {
+            query_set: &self.query_set,
+            names: &self.pass_names,
+        }
+    }
+
+    /// Picks up a finished readback. True when this frame can be measured.
+    fn collect(&self) -> bool {
+        if !self.in_flight.get() {
+            return true;
+        }
+        match self.map_state.swap(PENDING, Ordering::SeqCst) {
+            PENDING => return false,
+            FAILED => {
+                self.in_flight.set(false);
+                return true;
+            }
+            _ => {}
+        }
+        {
+            let data = self.readback_buffer.slice(..).get_mapped_range();
+            let ticks: &[u64] = bytemuck::cast_slice(&data);
+            let elapsed = ticks[1].wrapping_sub(ticks[0]);
+            let ms = elapsed as f32 * self.period / 1_000_000.0;
+            self.latest_ms.set(Some(ms));
+            self.gpu_zones.frame(ticks[0], ticks[1], self.period);
+            profiler::plot(PlotName::GpuFrameMs, ms as f64);
+            let passes = self
+                .measured_passes
+                .borrow()
+                .iter()
+                .enumerate()
+                .map(|(index, name)| {
+                    let elapsed = ticks[3 + 2 * index].wrapping_sub(ticks[2 + 2 * index]);
+                    (*name, elapsed as f32 * self.period / 1_000_000.0)
+                })
+                .collect();
+            *self.latest_passes.borrow_mut() = passes;
+        }
+        self.readback_buffer.unmap();
+        self.in_flight.set(false);
+        true
+    }
+
+    /// Call first thing on the frame's encoder. Returns whether `end` should be called.
+    pub fn begin(&self, encoder: &mut wgpu::CommandEncoder) -> bool {
+        if !self.collect() {
+            return false;
+        }
+        self.pass_names.lock().unwrap().clear();
+        encoder.write_timestamp(&self.query_set, 0);
+        true
+    }
+
+    /// Call last thing on the encoder that `begin` returned true for.
+    pub fn end(&self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.write_timestamp(&self.query_set, 1);
+        let passes = std::mem::take(&mut *self.pass_names.lock().unwrap());
+        let count = 2 + 2 * passes.len() as u32;
+        *self.measured_passes.borrow_mut() = passes;
+        encoder.resolve_query_set(&self.query_set, 0..count, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(
+            &self.resolve_buffer,
+            0,
+            &self.readback_buffer,
+            0,
+            count as u64 * wgpu::QUERY_SIZE as u64,
+        );
+    }
+
+    /// Call after submitting the measured encoder; the callback fires on a later poll.
+    pub fn request_readback(&self) {
+        self.in_flight.set(true);
+        let map_state = self.map_state.clone();
+        self.readback_buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let state = if result.is_ok() { MAPPED } else { FAILED };
+                map_state.store(state, Ordering::SeqCst);
+            });
+    }
+}
+
+/// The timer's pass queries as the render graph sees them, shareable with the threads
+/// that encode passes in parallel.
+#[derive(Clone, Copy)]
+pub struct PassQueries<'a> {
+    query_set: &'a QuerySet,
+    names: &'a Mutex<Vec<&'static str>>,
+}
+
+impl PassQueries<'_> {
+    /// Claims the next query pair for `name`; None once every pair is taken.
+    pub fn claim(&self, name: &'static str) -> Option<u32> {
+        let mut names = self.names.lock().unwrap();
+        if names.len() as u32 == MAX_TIMED_PASSES {
+            return None;
+        }
+        names.push(name);
+        Some(names.len() as u32 - 1)
+    }
+
+    pub fn begin(&self, encoder: &mut wgpu::CommandEncoder, pass: u32) {
+        encoder.write_timestamp(self.query_set, 2 + 2 * pass);
+    }
+
+    pub fn end(&self, encoder: &mut wgpu::CommandEncoder, pass: u32) {
+        encoder.write_timestamp(self.query_set, 3 + 2 * pass);
+    }
+}
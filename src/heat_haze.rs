@@ -0,0 +1,274 @@
+use serde::{Deserialize, Serialize};
+use wgpu::{
+    BindGroup, BindGroupLayout, Buffer, PipelineCompilationOptions, RenderPipeline, Sampler,
+    Texture, TextureView,
+};
+
+use crate::{
+    frame_stats::{self, CountedPass},
+    hot_reload::wgsl,
+    tonemap::HDR_FORMAT,
+    GfxState,
+};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HeatHazeSettings {
+    pub enabled: bool,
+    /// 0..1 at full daylight, fades out as the sun goes down
+    pub strength: f32,
+}
+
+impl Default for HeatHazeSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            strength: 0.5,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct HeatHazeParams {
+    strength: f32,
+    time: f32,
+    wind: [f32; 2],
+}
+
+/// Shimmer over the distant ground. The HDR target is copied aside mid-chain and drawn
+/// back with noise driven UV offsets, so it can't read and write the same texture.
+pub struct HeatHaze {
+    params_buffer: Buffer,
+    scene_copy: Texture,
+    sampler: Sampler,
+    bind_group_layout: BindGroupLayout,
+    bind_group: BindGroup,
+    pipeline: RenderPipeline,
+}
+
+impl HeatHaze {
+    pub fn new(app: &GfxState, camera_buffer: &Buffer) -> Self {
+        let device = &app.gpu.device;
+        let code = wgsl!("heat_haze.wgsl");
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("heat haze shader"),
+            source: wgpu::ShaderSource::Wgsl(code),
+        });
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("heat haze params"),
+            size: std::mem::size_of::<HeatHazeParams>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let scene_copy = Self::create_copy(
+            device,
+            app.window.surface_config.width,
+            app.window.surface_config.height,
+        );
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("heat haze sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let uniform_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("heat haze bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                uniform_entry(2),
+                uniform_entry(3),
+            ],
+        });
+        let bind_group = Self::create_bind_group(
+            device,
+            &bind_group_layout,
+            &scene_copy,
+            &sampler,
+            camera_buffer,
+            &params_buffer,
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("heat haze pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("heat haze pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "fullscreen_vs",
+                buffers: &[],
+                compilation_options: PipelineCompilationOptions::default(),
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "heat_haze_fs",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: HDR_FORMAT,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: PipelineCompilationOptions::default(),
+            }),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            params_buffer,
+            scene_copy,
+            sampler,
+            bind_group_layout,
+            bind_group,
+            pipeline,
+        }
+    }
+
+    fn create_copy(device: &wgpu::Device, width: u32, height: u32) -> Texture {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("heat haze scene copy"),
+            size: wgpu::Extent3d {
+                width: width.max(1),
+                height: height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: HDR_FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        })
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &BindGroupLayout,
+        scene_copy: &Texture,
+        sampler: &Sampler,
+        camera_buffer: &Buffer,
+        params_buffer: &Buffer,
+    ) -> BindGroup {
+        let view = scene_copy.create_view(&wgpu::TextureViewDescriptor::default());
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("heat haze bind group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: camera_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: params_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        camera_buffer: &Buffer,
+        width: u32,
+        height: u32,
+    ) {
+        self.scene_copy = Self::create_copy(device, width, height);
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.bind_group_layout,
+            &self.scene_copy,
+            &self.sampler,
+            camera_buffer,
+            &self.params_buffer,
+        );
+    }
+
+    /// Copies `scene` aside and draws it back distorted.
+    pub fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        app: &GfxState,
+        scene: &Texture,
+        target: &TextureView,
+    ) {
+        // heat needs sun: fades in over the first 20 degrees of elevation, clouds dampen it
+        let sky = app.scene.weathered_sky();
+        let daylight = (sky.sun_elevation / 20.0).clamp(0.0, 1.0) * (1.0 - sky.cloud_coverage);
+        let wind = app.scene.wind.velocity(app.frame.time);
+        let params = HeatHazeParams {
+            strength: app.scene.heat_haze.strength * daylight,
+            time: app.frame.time,
+            wind: [wind.x, wind.z],
+        };
+        frame_stats::write_buffer(
+            &app.gpu.queue,
+            &self.params_buffer,
+            0,
+            bytemuck::bytes_of(&params),
+        );
+
+        encoder.copy_texture_to_texture(
+            scene.as_image_copy(),
+            self.scene_copy.as_image_copy(),
+            self.scene_copy.size(),
+        );
+        let mut render_pass = CountedPass::begin(
+            encoder,
+            &wgpu::RenderPassDescriptor {
+                label: Some("heat haze pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                ..Default::default()
+            },
+        );
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
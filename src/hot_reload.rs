@@ -0,0 +1,138 @@
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Receiver},
+        Arc, Mutex, OnceLock,
+    },
+};
+
+use notify::{EventKind, RecursiveMode, Watcher};
+use winit::event_loop::EventLoopProxy;
+
+use crate::UserEvent;
+
+/// The source of `asset/<file>`, as compiled in, or as last read from disk once the
+/// watcher saw it change.
+macro_rules! wgsl {
+    ($file:literal) => {
+        $crate::hot_reload::source(
+            $file,
+            include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/asset/", $file)),
+        )
+    };
+}
+pub(crate) use wgsl;
+
+// by file name, only sources that built
+fn reloaded() -> &'static Mutex<HashMap<String, Arc<str>>> {
+    static RELOADED: OnceLock<Mutex<HashMap<String, Arc<str>>>> = OnceLock::new();
+    RELOADED.get_or_init(Default::default)
+}
+
+pub fn source(file: &str, baked: &'static str) -> Cow<'static, str> {
+    match reloaded().lock().unwrap().get(file) {
+        Some(code) => Cow::Owned(code.to_string()),
+        None => Cow::Borrowed(baked),
+    }
+}
+
+fn asset_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("asset")
+}
+
+/// Watches asset/ for saved WGSL files, waking the event loop for a redraw each time. The
+/// render thread takes the changes before the frame and rebuilds what uses them.
+pub struct ShaderWatcher {
+    // watches until dropped
+    _watcher: notify::RecommendedWatcher,
+    changes: Receiver<PathBuf>,
+}
+
+impl ShaderWatcher {
+    pub fn new(event_proxy: EventLoopProxy<UserEvent>) -> notify::Result<Self> {
+        let (sender, changes) = mpsc::channel();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                let Ok(event) = event else {
+                    return;
+                };
+                if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                    return;
+                }
+                for path in event.paths {
+                    if path
+                        .extension()
+                        .is_some_and(|extension| extension == "wgsl")
+                    {
+                        let _ = sender.send(path);
+                        let _ = event_proxy.send_event(UserEvent::ShaderChanged);
+                    }
+                }
+            })?;
+        watcher.watch(&asset_dir(), RecursiveMode::NonRecursive)?;
+        Ok(Self {
+            _watcher: watcher,
+            changes,
+        })
+    }
+
+    /// The files saved since the last call, each once, with their new source in place for
+    /// `wgsl!` until the changes are reverted.
+    pub fn take_changes(&self) -> ShaderChanges {
+        // an editor's save is often several events
+        reload(self.changes.try_iter().collect::<BTreeSet<_>>())
+    }
+}
+
+/// Reads `paths` into the sources `wgsl!` hands out, by file name. One that can't be read
+/// keeps the source it had.
+pub fn reload(paths: BTreeSet<PathBuf>) -> ShaderChanges {
+    let mut previous = BTreeMap::new();
+    for path in paths {
+        let Some(file) = path.file_name().and_then(|file| file.to_str()) else {
+            continue;
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(code) => {
+                let replaced = reloaded()
+                    .lock()
+                    .unwrap()
+                    .insert(file.to_string(), code.into());
+                previous.entry(file.to_string()).or_insert(replaced);
+            }
+            Err(e) => println!("Can't read {}, kept as it was: {}", path.display(), e),
+        }
+    }
+    ShaderChanges { previous }
+}
+
+/// Saved files `wgsl!` has the new source of. If what uses them doesn't build, `revert`
+/// puts back the sources that did, so later rebuilds (a device recovery, a scene load)
+/// don't trip over them.
+pub struct ShaderChanges {
+    // by file name, what `wgsl!` had before: a reloaded source, or None for the baked one
+    previous: BTreeMap<String, Option<Arc<str>>>,
+}
+
+impl ShaderChanges {
+    pub fn is_empty(&self) -> bool {
+        self.previous.is_empty()
+    }
+
+    /// The changed files' names, comma separated.
+    pub fn files(&self) -> String {
+        self.previous.keys().cloned().collect::<Vec<_>>().join(", ")
+    }
+
+    pub fn revert(self) {
+        let mut reloaded = reloaded().lock().unwrap();
+        for (file, previous) in self.previous {
+            match previous {
+                Some(code) => reloaded.insert(file, code),
+                None => reloaded.remove(&file),
+            };
+        }
+    }
+}
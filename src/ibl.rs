@@ -0,0 +1,565 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use wgpu::{util::DeviceExt, Buffer, RenderPipeline, Sampler, Texture, TextureView};
+
+use crate::{
+    asset_loader::DecodedTexture, frame_stats, hot_reload::wgsl, scene::Scene,
+    texture::ImageTexture, tonemap::HDR_FORMAT,
+};
+
+/// Face edge of the cube the equirectangular image is converted into, capped by the
+/// device's texture size.
+pub const ENVIRONMENT_SIZE: u32 = 512;
+const IRRADIANCE_SIZE: u32 = 32;
+const PREFILTERED_SIZE: u32 = 128;
+/// Roughness 0, 0.25, .. 1, one per mip of the prefiltered cube.
+pub const PREFILTERED_MIPS: u32 = 5;
+const BRDF_LUT_SIZE: u32 = 128;
+const BRDF_LUT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rg16Float;
+// the irradiance convolution reads the environment's mip about this big, a coarse
+// enough one that its fixed steps don't miss small bright spots
+const IRRADIANCE_SOURCE_SIZE: u32 = 64;
+
+/// Image based lighting for the PBR models from an HDR environment map. Without one
+/// they keep the procedural sky's flat ambient.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IblSettings {
+    /// an equirectangular Radiance .hdr image, the top row looking straight up
+    pub path: Option<PathBuf>,
+    /// scales the image's radiance into the scene's light levels
+    pub intensity: f32,
+    /// also draws the image behind the scene, unless the skybox names its own faces
+    pub background: bool,
+}
+
+impl Default for IblSettings {
+    fn default() -> Self {
+        Self {
+            path: None,
+            intensity: 1.0,
+            background: true,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct IblUniform {
+    intensity: f32,
+    // mip of the prefiltered cube for roughness 1
+    max_lod: f32,
+    // 0 without an environment map, pbr_fs then uses the flat ambient
+    enabled: f32,
+    _pad: f32,
+}
+
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct BakeParams {
+    face: u32,
+    roughness: f32,
+    source_size: f32,
+    source_lod: f32,
+}
+
+/// Reads a Radiance .hdr image into rgba32 float texels.
+pub fn decode_hdr(path: &Path) -> anyhow::Result<DecodedTexture> {
+    let image = image::open(path)
+        .with_context(|| format!("can't read {}", path.display()))?
+        .into_rgba32f();
+    Ok(DecodedTexture {
+        width: image.width(),
+        height: image.height(),
+        format: wgpu::TextureFormat::Rgba32Float,
+        texels: bytemuck::cast_slice(image.as_raw()).to_vec(),
+    })
+}
+
+/// An HDR environment map baked for image based lighting, with the split-sum
+/// approximation: the environment as a cube map with its mip chain, its cosine
+/// convolution for the diffuse term, a cube prefiltered with the GGX lobe at
+/// `PREFILTERED_MIPS` roughnesses for the specular term, and the BRDF lookup table
+/// those are scaled by.
+///
+/// It is baked once, when it is built, with render passes so it works without compute.
+/// The material pipelines read it through the light bind group (`light::Light`), after
+/// the shadow map. Without an image it is a placeholder of 1x1 black textures that
+/// `pbr_fs` is told to skip.
+pub struct HdrEnvironment {
+    /// the environment as a cube, for drawing it as the background
+    pub environment_view: TextureView,
+    // for readbacks
+    pub irradiance: Texture,
+    irradiance_view: TextureView,
+    prefiltered_view: TextureView,
+    pub brdf_lut: Texture,
+    brdf_lut_view: TextureView,
+    /// trilinear and clamped, for all of the above
+    pub sampler: Sampler,
+    uniform_buffer: Buffer,
+    loaded: bool,
+}
+
+fn cube(
+    device: &wgpu::Device,
+    label: &str,
+    size: u32,
+    mip_level_count: u32,
+) -> (Texture, TextureView) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 6,
+        },
+        mip_level_count,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: HDR_FORMAT,
+        // copied from by readbacks
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+            | wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor {
+        label: Some(label),
+        dimension: Some(wgpu::TextureViewDimension::Cube),
+        ..Default::default()
+    });
+    (texture, view)
+}
+
+fn face_view(texture: &Texture, face: u32, mip: u32) -> TextureView {
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        label: Some("environment face view"),
+        dimension: Some(wgpu::TextureViewDimension::D2),
+        base_mip_level: mip,
+        mip_level_count: Some(1),
+        base_array_layer: face,
+        array_layer_count: Some(1),
+        ..Default::default()
+    })
+}
+
+fn brdf_lut(device: &wgpu::Device, size: u32) -> (Texture, TextureView) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("brdf lut"),
+        size: wgpu::Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: BRDF_LUT_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+            | wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    (texture, view)
+}
+
+impl HdrEnvironment {
+    /// The placeholder when the settings name no image; an error when it can't be read.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        settings: &IblSettings,
+    ) -> anyhow::Result<Self> {
+        let Some(path) = &settings.path else {
+            return Ok(Self::placeholder(device));
+        };
+        let equirect = decode_hdr(&Scene::resolve_path(path))?;
+        let max_size = device.limits().max_texture_dimension_2d;
+        anyhow::ensure!(
+            equirect.width <= max_size && equirect.height <= max_size,
+            "environment map is over {} texels",
+            max_size
+        );
+        let environment = Self::bake(device, queue, &equirect);
+        println!(
+            "Baked a {}x{} environment map for image based lighting",
+            equirect.width, equirect.height
+        );
+        Ok(environment)
+    }
+
+    /// Bound in place of an environment map, so the layouts don't depend on having one.
+    pub fn placeholder(device: &wgpu::Device) -> Self {
+        let (_, environment_view) = cube(device, "environment placeholder", 1, 1);
+        let (irradiance, irradiance_view) = cube(device, "irradiance placeholder", 1, 1);
+        let (_, prefiltered_view) = cube(device, "prefiltered placeholder", 1, 1);
+        let (brdf_lut, brdf_lut_view) = brdf_lut(device, 1);
+        Self {
+            environment_view,
+            irradiance,
+            irradiance_view,
+            prefiltered_view,
+            brdf_lut,
+            brdf_lut_view,
+            sampler: Self::create_sampler(device),
+            uniform_buffer: Self::create_uniform_buffer(device),
+            loaded: false,
+        }
+    }
+
+    fn create_sampler(device: &wgpu::Device) -> Sampler {
+        device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("ibl sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        })
+    }
+
+    fn create_uniform_buffer(device: &wgpu::Device) -> Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("ibl uniform"),
+            size: std::mem::size_of::<IblUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Converts `equirect` (rgba32 float) and bakes everything from it. The passes are
+    /// submitted before this returns, without waiting on them.
+    pub fn bake(device: &wgpu::Device, queue: &wgpu::Queue, equirect: &DecodedTexture) -> Self {
+        let source = ImageTexture::from_decoded(device, queue, "equirect environment", equirect);
+        let size = ENVIRONMENT_SIZE.min(device.limits().max_texture_dimension_2d);
+        let mips = size.ilog2() + 1;
+        let (environment, environment_view) = cube(device, "environment", size, mips);
+        let (irradiance, irradiance_view) = cube(device, "irradiance", IRRADIANCE_SIZE, 1);
+        let (prefiltered, prefiltered_view) = cube(
+            device,
+            "prefiltered environment",
+            PREFILTERED_SIZE,
+            PREFILTERED_MIPS,
+        );
+        let (brdf_lut, brdf_lut_view) = brdf_lut(device, BRDF_LUT_SIZE);
+        let sampler = Self::create_sampler(device);
+
+        let code = wgsl!("ibl.wgsl");
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("ibl shader"),
+            source: wgpu::ShaderSource::Wgsl(code),
+        });
+        let params_entry = wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let equirect_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("equirect bind group layout"),
+            entries: &[
+                params_entry,
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let convolve_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("environment convolution bind group layout"),
+            entries: &[
+                params_entry,
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let make_pipeline =
+            |layout: &[&wgpu::BindGroupLayout], entry_point: &str, format: wgpu::TextureFormat| {
+                let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some(entry_point),
+                    bind_group_layouts: layout,
+                    push_constant_ranges: &[],
+                });
+                device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some(entry_point),
+                    layout: Some(&layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader,
+                        entry_point: "bake_vs",
+                        buffers: &[],
+                        compilation_options: wgpu::PipelineCompilationOptions::default(),
+                    },
+                    primitive: wgpu::PrimitiveState::default(),
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader,
+                        entry_point,
+                        targets: &[Some(format.into())],
+                        compilation_options: wgpu::PipelineCompilationOptions::default(),
+                    }),
+                    depth_stencil: None,
+                    multisample: wgpu::MultisampleState::default(),
+                    multiview: None,
+                })
+            };
+        let equirect_pipeline = make_pipeline(&[&equirect_layout], "equirect_fs", HDR_FORMAT);
+        let downsample_pipeline = make_pipeline(&[&convolve_layout], "downsample_fs", HDR_FORMAT);
+        let irradiance_pipeline = make_pipeline(&[&convolve_layout], "irradiance_fs", HDR_FORMAT);
+        let prefilter_pipeline = make_pipeline(&[&convolve_layout], "prefilter_fs", HDR_FORMAT);
+        let brdf_pipeline = make_pipeline(&[], "brdf_fs", BRDF_LUT_FORMAT);
+
+        let params_buffer = |params: BakeParams| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("ibl bake params"),
+                contents: bytemuck::bytes_of(&params),
+                usage: wgpu::BufferUsages::UNIFORM,
+            })
+        };
+        let convolve_bind_group = |params: &Buffer, source: &TextureView| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("environment convolution bind group"),
+                layout: &convolve_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: params.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::TextureView(source),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: wgpu::BindingResource::Sampler(&sampler),
+                    },
+                ],
+            })
+        };
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("ibl bake encoder"),
+        });
+        let mut draw = |pipeline: &RenderPipeline,
+                        bind_group: Option<&wgpu::BindGroup>,
+                        target: &TextureView| {
+            let mut render_pass = frame_stats::CountedPass::begin(
+                &mut encoder,
+                &wgpu::RenderPassDescriptor {
+                    label: Some("ibl bake pass"),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: target,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                            store: wgpu::StoreOp::Store,
+                        },
+                    })],
+                    ..Default::default()
+                },
+            );
+            render_pass.set_pipeline(pipeline);
+            if let Some(bind_group) = bind_group {
+                render_pass.set_bind_group(0, bind_group, &[]);
+            }
+            render_pass.draw(0..3, 0..1);
+        };
+
+        for face in 0..6 {
+            let params = params_buffer(BakeParams {
+                face,
+                roughness: 0.0,
+                source_size: 0.0,
+                source_lod: 0.0,
+            });
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("equirect bind group"),
+                layout: &equirect_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: params.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(&source.view),
+                    },
+                ],
+            });
+            draw(
+                &equirect_pipeline,
+                Some(&bind_group),
+                &face_view(&environment, face, 0),
+            );
+        }
+        // each mip from the one above, which is all the pass binds so it can render
+        // into the same texture
+        for mip in 1..mips {
+            let above = environment.create_view(&wgpu::TextureViewDescriptor {
+                label: Some("environment mip view"),
+                dimension: Some(wgpu::TextureViewDimension::Cube),
+                base_mip_level: mip - 1,
+                mip_level_count: Some(1),
+                ..Default::default()
+            });
+            for face in 0..6 {
+                let params = params_buffer(BakeParams {
+                    face,
+                    roughness: 0.0,
+                    source_size: 0.0,
+                    source_lod: 0.0,
+                });
+                draw(
+                    &downsample_pipeline,
+                    Some(&convolve_bind_group(&params, &above)),
+                    &face_view(&environment, face, mip),
+                );
+            }
+        }
+        let irradiance_lod = (size / IRRADIANCE_SOURCE_SIZE).max(1).ilog2() as f32;
+        for face in 0..6 {
+            let params = params_buffer(BakeParams {
+                face,
+                roughness: 0.0,
+                source_size: size as f32,
+                source_lod: irradiance_lod,
+            });
+            draw(
+                &irradiance_pipeline,
+                Some(&convolve_bind_group(&params, &environment_view)),
+                &face_view(&irradiance, face, 0),
+            );
+        }
+        for mip in 0..PREFILTERED_MIPS {
+            for face in 0..6 {
+                let params = params_buffer(BakeParams {
+                    face,
+                    roughness: mip as f32 / (PREFILTERED_MIPS - 1) as f32,
+                    source_size: size as f32,
+                    source_lod: 0.0,
+                });
+                draw(
+                    &prefilter_pipeline,
+                    Some(&convolve_bind_group(&params, &environment_view)),
+                    &face_view(&prefiltered, face, mip),
+                );
+            }
+        }
+        draw(&brdf_pipeline, None, &brdf_lut_view);
+        queue.submit(Some(encoder.finish()));
+
+        Self {
+            environment_view,
+            irradiance,
+            irradiance_view,
+            prefiltered_view,
+            brdf_lut,
+            brdf_lut_view,
+            sampler,
+            uniform_buffer: Self::create_uniform_buffer(device),
+            loaded: true,
+        }
+    }
+
+    /// Whether an image was baked, rather than this being the placeholder.
+    pub fn loaded(&self) -> bool {
+        self.loaded
+    }
+
+    pub fn write_uniform(&self, queue: &wgpu::Queue, settings: &IblSettings) {
+        let uniform = IblUniform {
+            intensity: settings.intensity,
+            max_lod: (PREFILTERED_MIPS - 1) as f32,
+            enabled: if self.loaded { 1.0 } else { 0.0 },
+            _pad: 0.0,
+        };
+        frame_stats::write_buffer(queue, &self.uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+    }
+
+    /// The light bind group's entries after the shadow map: the uniform, the irradiance
+    /// and prefiltered cubes, the BRDF lookup table and the sampler, from
+    /// `first_binding` on.
+    pub fn bind_group_layout_entries(first_binding: u32) -> [wgpu::BindGroupLayoutEntry; 5] {
+        let texture = |binding, view_dimension| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension,
+                multisampled: false,
+            },
+            count: None,
+        };
+        [
+            wgpu::BindGroupLayoutEntry {
+                binding: first_binding,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            texture(first_binding + 1, wgpu::TextureViewDimension::Cube),
+            texture(first_binding + 2, wgpu::TextureViewDimension::Cube),
+            texture(first_binding + 3, wgpu::TextureViewDimension::D2),
+            wgpu::BindGroupLayoutEntry {
+                binding: first_binding + 4,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+        ]
+    }
+
+    /// What `bind_group_layout_entries` describes, for the light bind group.
+    pub fn bind_group_entries(&self, first_binding: u32) -> [wgpu::BindGroupEntry<'_>; 5] {
+        [
+            wgpu::BindGroupEntry {
+                binding: first_binding,
+                resource: self.uniform_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: first_binding + 1,
+                resource: wgpu::BindingResource::TextureView(&self.irradiance_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: first_binding + 2,
+                resource: wgpu::BindingResource::TextureView(&self.prefiltered_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: first_binding + 3,
+                resource: wgpu::BindingResource::TextureView(&self.brdf_lut_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: first_binding + 4,
+                resource: wgpu::BindingResource::Sampler(&self.sampler),
+            },
+        ]
+    }
+}
@@ -0,0 +1,147 @@
+use std::path::Path;
+
+use anyhow::{bail, Context};
+use image::{ImageFormat, Rgba32FImage, RgbaImage};
+use wgpu::TextureFormat;
+
+use crate::readback::f16_to_f32;
+
+/// A texture read back to the CPU as linear RGBA floats, rows from the top. sRGB formats
+/// are decoded, single channel formats (depth included) are spread to gray, integer
+/// formats keep their values.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageData {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<[f32; 4]>,
+}
+
+impl ImageData {
+    /// Converts texels of `format` as packed by `readback::read_texture_bytes`.
+    pub fn decode(
+        width: u32,
+        height: u32,
+        format: TextureFormat,
+        bytes: &[u8],
+    ) -> anyhow::Result<Self> {
+        let texel_size: usize = match format {
+            TextureFormat::R8Unorm => 1,
+            TextureFormat::Rg8Unorm | TextureFormat::R16Float | TextureFormat::Depth16Unorm => 2,
+            TextureFormat::Rgba8Unorm
+            | TextureFormat::Rgba8UnormSrgb
+            | TextureFormat::Bgra8Unorm
+            | TextureFormat::Bgra8UnormSrgb
+            | TextureFormat::Rgb10a2Unorm
+            | TextureFormat::Rg16Float
+            | TextureFormat::R32Float
+            | TextureFormat::R32Uint
+            | TextureFormat::Depth32Float => 4,
+            TextureFormat::Rgba16Float => 8,
+            TextureFormat::Rgba32Float => 16,
+            _ => bail!("can't convert {:?} texels", format),
+        };
+        if bytes.len() != width as usize * height as usize * texel_size {
+            bail!(
+                "{} bytes don't make a {}x{} {:?} image",
+                bytes.len(),
+                width,
+                height,
+                format
+            );
+        }
+        let unorm8 = |b: u8| b as f32 / 255.0;
+        let half = |b: &[u8]| f16_to_f32(u16::from_le_bytes([b[0], b[1]]));
+        let float = |b: &[u8]| f32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+        let gray = |v: f32| [v, v, v, 1.0];
+        let pixels = bytes
+            .chunks_exact(texel_size)
+            .map(|t| match format {
+                TextureFormat::R8Unorm => gray(unorm8(t[0])),
+                TextureFormat::Rg8Unorm => [unorm8(t[0]), unorm8(t[1]), 0.0, 1.0],
+                TextureFormat::Rgba8Unorm => {
+                    [unorm8(t[0]), unorm8(t[1]), unorm8(t[2]), unorm8(t[3])]
+                }
+                TextureFormat::Bgra8Unorm => {
+                    [unorm8(t[2]), unorm8(t[1]), unorm8(t[0]), unorm8(t[3])]
+                }
+                TextureFormat::Rgba8UnormSrgb => [
+                    srgb_to_linear(unorm8(t[0])),
+                    srgb_to_linear(unorm8(t[1])),
+                    srgb_to_linear(unorm8(t[2])),
+                    unorm8(t[3]),
+                ],
+                TextureFormat::Bgra8UnormSrgb => [
+                    srgb_to_linear(unorm8(t[2])),
+                    srgb_to_linear(unorm8(t[1])),
+                    srgb_to_linear(unorm8(t[0])),
+                    unorm8(t[3]),
+                ],
+                TextureFormat::Rgb10a2Unorm => {
+                    let v = u32::from_le_bytes([t[0], t[1], t[2], t[3]]);
+                    [
+                        (v & 0x3ff) as f32 / 1023.0,
+                        ((v >> 10) & 0x3ff) as f32 / 1023.0,
+                        ((v >> 20) & 0x3ff) as f32 / 1023.0,
+                        (v >> 30) as f32 / 3.0,
+                    ]
+                }
+                TextureFormat::R16Float => gray(half(t)),
+                TextureFormat::Rg16Float => [half(t), half(&t[2..]), 0.0, 1.0],
+                TextureFormat::Rgba16Float => {
+                    [half(t), half(&t[2..]), half(&t[4..]), half(&t[6..])]
+                }
+                TextureFormat::Depth16Unorm => {
+                    gray(u16::from_le_bytes([t[0], t[1]]) as f32 / 65535.0)
+                }
+                TextureFormat::R32Float | TextureFormat::Depth32Float => gray(float(t)),
+                TextureFormat::R32Uint => gray(u32::from_le_bytes([t[0], t[1], t[2], t[3]]) as f32),
+                _ => [float(t), float(&t[4..]), float(&t[8..]), float(&t[12..])],
+            })
+            .collect();
+        Ok(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// 8 bit RGBA PNG, sRGB encoded and clamped to [0, 1].
+    pub fn save_png(&self, path: &Path) -> anyhow::Result<()> {
+        let unorm8 = |v: f32| (v * 255.0).round() as u8;
+        let srgb8 = |v: f32| unorm8(linear_to_srgb(v.clamp(0.0, 1.0)));
+        let bytes = self
+            .pixels
+            .iter()
+            .flat_map(|&[r, g, b, a]| [srgb8(r), srgb8(g), srgb8(b), unorm8(a.clamp(0.0, 1.0))])
+            .collect();
+        RgbaImage::from_raw(self.width, self.height, bytes)
+            .context("pixels don't fill the image")?
+            .save_with_format(path, ImageFormat::Png)
+            .with_context(|| format!("can't write {}", path.display()))
+    }
+
+    /// 32 bit float RGBA OpenEXR, values as they are.
+    pub fn save_exr(&self, path: &Path) -> anyhow::Result<()> {
+        let floats = self.pixels.iter().flatten().copied().collect();
+        Rgba32FImage::from_raw(self.width, self.height, floats)
+            .context("pixels don't fill the image")?
+            .save_with_format(path, ImageFormat::OpenExr)
+            .with_context(|| format!("can't write {}", path.display()))
+    }
+}
+
+pub(crate) fn srgb_to_linear(v: f32) -> f32 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub(crate) fn linear_to_srgb(v: f32) -> f32 {
+    if v <= 0.0031308 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
+}
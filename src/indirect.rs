@@ -0,0 +1,66 @@
+use std::ops::Range;
+
+use wgpu::{
+    util::{DeviceExt, DrawIndexedIndirectArgs},
+    Buffer,
+};
+
+use crate::frame_stats::CountedPass;
+
+/// Indexed draws whose arguments live in a GPU buffer, so compute passes can write them
+/// instead of the CPU. A run of draws sharing vertex and index buffers goes out as one
+/// multi-draw where the device has MULTI_DRAW_INDIRECT, as a loop of indirect draws where
+/// it doesn't.
+pub struct IndirectDraws {
+    // tightly packed `DrawIndexedIndirectArgs`, STORAGE for compute passes to write,
+    // COPY_SRC to read back what they wrote
+    pub buffer: Buffer,
+    multi_draw: bool,
+}
+
+impl IndirectDraws {
+    pub const ARGS_SIZE: u64 = std::mem::size_of::<DrawIndexedIndirectArgs>() as u64;
+
+    pub fn new(device: &wgpu::Device, multi_draw: bool, args: &[DrawIndexedIndirectArgs]) -> Self {
+        Self {
+            buffer: Self::create_buffer(device, args),
+            multi_draw,
+        }
+    }
+
+    /// Replaces every draw's arguments.
+    pub fn set(&mut self, device: &wgpu::Device, args: &[DrawIndexedIndirectArgs]) {
+        self.buffer = Self::create_buffer(device, args);
+    }
+
+    fn create_buffer(device: &wgpu::Device, args: &[DrawIndexedIndirectArgs]) -> Buffer {
+        let mut contents: Vec<u8> = args.iter().flat_map(|a| a.as_bytes()).copied().collect();
+        // a buffer can't be empty
+        if contents.is_empty() {
+            contents.resize(Self::ARGS_SIZE as usize, 0);
+        }
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("indirect draw args"),
+            contents: &contents,
+            usage: wgpu::BufferUsages::INDIRECT
+                | wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
+        })
+    }
+
+    /// Draws `draws`, with the vertex and index buffers they index into already set.
+    pub fn draw<'a>(&'a self, render_pass: &mut CountedPass<'a>, draws: Range<u32>) {
+        if self.multi_draw {
+            render_pass.multi_draw_indexed_indirect(
+                &self.buffer,
+                draws.start as u64 * Self::ARGS_SIZE,
+                draws.len() as u32,
+            );
+        } else {
+            for i in draws {
+                render_pass.draw_indexed_indirect(&self.buffer, i as u64 * Self::ARGS_SIZE);
+            }
+        }
+    }
+}
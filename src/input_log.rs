@@ -0,0 +1,187 @@
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::{config::DeterministicConfig, render_thread::FrameInput, scene::Scene, GfxState};
+
+/// Where a recording starts: the first line of the file.
+#[derive(Serialize, Deserialize)]
+pub struct Header {
+    scene: Scene,
+    eye: [f32; 3],
+    target: [f32; 3],
+    size: (u32, u32),
+    // the replay runs deterministic with these, whether the recording was or not
+    deterministic: DeterministicConfig,
+}
+
+/// Every other line: an input and the frame it arrived before.
+#[derive(Serialize, Deserialize)]
+pub struct Event {
+    frame: u64,
+    input: FrameInput,
+}
+
+/// `--record file` writes the starting state and then every input with its frame number,
+/// one RON value per line and flushed as it goes, so a crash still leaves a usable file.
+/// `--replay file` restores that state and feeds the inputs back on the same frames in
+/// deterministic mode, ignoring live input until it runs out. A replay of a deterministic
+/// recording repeats it exactly; otherwise the fixed dt makes anything timed play out
+/// differently from what was seen while recording.
+pub enum InputLog {
+    Recording {
+        file: BufWriter<File>,
+        path: PathBuf,
+    },
+    Replaying {
+        // boxed, the scene in it dwarfs the recording variant
+        header: Option<Box<Header>>,
+        events: VecDeque<Event>,
+    },
+}
+
+impl InputLog {
+    /// Reads `--record file` or `--replay file` off the command line.
+    pub fn from_args() -> anyhow::Result<Option<Self>> {
+        let args: Vec<String> = std::env::args().collect();
+        let value = |flag: &str| {
+            let index = args.iter().position(|arg| arg == flag)?;
+            Some(
+                args.get(index + 1)
+                    .map(PathBuf::from)
+                    .context(format!("{} needs a file", flag)),
+            )
+        };
+        match (value("--record"), value("--replay")) {
+            (Some(_), Some(_)) => bail!("--record and --replay can't be used together"),
+            (Some(path), None) => Ok(Some(Self::record(path?)?)),
+            (None, Some(path)) => Ok(Some(Self::replay(&path?)?)),
+            (None, None) => Ok(None),
+        }
+    }
+
+    fn record(path: PathBuf) -> anyhow::Result<Self> {
+        let file =
+            File::create(&path).with_context(|| format!("can't create {}", path.display()))?;
+        Ok(Self::Recording {
+            file: BufWriter::new(file),
+            path,
+        })
+    }
+
+    fn replay(path: &Path) -> anyhow::Result<Self> {
+        let file = File::open(path).with_context(|| format!("can't open {}", path.display()))?;
+        let mut lines = BufReader::new(file).lines();
+        let header = match lines.next() {
+            Some(line) => ron::from_str(&line?).context("bad recording header")?,
+            None => bail!("{} is empty", path.display()),
+        };
+        let events = lines
+            .enumerate()
+            .map(|(index, line)| {
+                ron::from_str(&line?).with_context(|| format!("bad event on line {}", index + 2))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self::Replaying {
+            header: Some(Box::new(header)),
+            events,
+        })
+    }
+
+    pub fn replaying(&self) -> bool {
+        matches!(self, Self::Replaying { .. })
+    }
+
+    /// Writes the header for a recording, or puts the app into a replay's starting state.
+    pub fn start(app: &mut GfxState) -> anyhow::Result<()> {
+        match app.input_log.as_mut() {
+            Some(Self::Recording { file, path }) => {
+                let camera = &app.frame.camera;
+                let header = Header {
+                    scene: app.scene.clone(),
+                    eye: camera.eye.into(),
+                    target: camera.target.into(),
+                    size: (
+                        app.window.surface_config.width,
+                        app.window.surface_config.height,
+                    ),
+                    deterministic: app.config.deterministic,
+                };
+                writeln!(file, "{}", ron::to_string(&header)?)?;
+                file.flush()?;
+                println!("Recording input to {}", path.display());
+            }
+            Some(Self::Replaying { header, events }) => {
+                let Some(header) = header.take() else {
+                    return Ok(());
+                };
+                println!("Replaying {} inputs", events.len());
+                app.scene = header.scene;
+                app.frame.camera.eye = header.eye.into();
+                app.frame.camera.target = header.target.into();
+                app.config.deterministic = header.deterministic;
+                request_size(app, header.size);
+            }
+            None => {}
+        }
+        Ok(())
+    }
+
+    /// Appends `input` to a recording, as arriving before frame `frame`.
+    pub fn record_input(&mut self, frame: u64, input: &FrameInput) {
+        let Self::Recording { file, .. } = self else {
+            return;
+        };
+        let event = Event {
+            frame,
+            input: input.clone(),
+        };
+        let written = ron::to_string(&event)
+            .map_err(anyhow::Error::from)
+            .and_then(|line| {
+                writeln!(file, "{}", line)?;
+                file.flush()?;
+                Ok(())
+            });
+        if let Err(e) = written {
+            println!("Input recording failed: {:#}", e);
+        }
+    }
+
+    /// Takes the replayed inputs due before frame `frame`.
+    pub fn due(&mut self, frame: u64) -> Vec<FrameInput> {
+        let Self::Replaying { events, .. } = self else {
+            return vec![];
+        };
+        let mut due = vec![];
+        while events.front().is_some_and(|event| event.frame <= frame) {
+            due.extend(events.pop_front().map(|event| event.input));
+        }
+        due
+    }
+
+    pub fn finished(&self) -> bool {
+        match self {
+            Self::Recording { .. } => false,
+            Self::Replaying { events, .. } => events.is_empty(),
+        }
+    }
+}
+
+/// Asks for the recorded window size; the resize it causes comes back as live input.
+/// Headless there's no window to ask, the offscreen target keeps its size.
+pub fn request_size(app: &GfxState, (width, height): (u32, u32)) {
+    let Some(handle) = &app.window.handle else {
+        return;
+    };
+    let size = winit::dpi::PhysicalSize::new(width, height);
+    if handle.inner_size() != size {
+        let _ = handle.request_inner_size(size);
+    }
+}
@@ -0,0 +1,73 @@
+use cgmath::{Matrix4, Quaternion, Vector3};
+use wgpu::util::DeviceExt;
+
+use crate::vertex::Vertex;
+
+/// One copy of a mesh: where it stands and a tint on its material's base color. The
+/// scale is the same along every axis, so the model matrix carries normals as it is.
+#[derive(Debug, Clone, Copy)]
+pub struct Instance {
+    pub position: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: f32,
+    /// linear, multiplies the base color, alpha included
+    pub color: [f32; 4],
+}
+
+impl Default for Instance {
+    /// Where the mesh's vertices are, untinted.
+    fn default() -> Self {
+        Self {
+            position: Vector3::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::new(1.0, 0.0, 0.0, 0.0),
+            scale: 1.0,
+            color: [1.0; 4],
+        }
+    }
+}
+
+impl Instance {
+    pub fn model(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.position)
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_scale(self.scale)
+    }
+
+    pub fn to_raw(self) -> InstanceRaw {
+        InstanceRaw {
+            model: self.model().into(),
+            color: self.color,
+        }
+    }
+}
+
+/// An `Instance` as the material and shadow pipelines read it, from the second vertex
+/// buffer, one step per instance.
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct InstanceRaw {
+    // by columns
+    model: [[f32; 4]; 4],
+    color: [f32; 4],
+}
+
+impl Vertex for InstanceRaw {
+    // after `MeshVertex`'s
+    const ATTRIBUTES: &'static [wgpu::VertexAttribute] = &wgpu::vertex_attr_array![
+        4 => Float32x4,
+        5 => Float32x4,
+        6 => Float32x4,
+        7 => Float32x4,
+        8 => Float32x4,
+    ];
+    const STEP_MODE: wgpu::VertexStepMode = wgpu::VertexStepMode::Instance;
+}
+
+/// The second vertex buffer of a mesh's draws, which the GPU culling reads from too.
+pub fn create_instance_buffer(device: &wgpu::Device, instances: &[InstanceRaw]) -> wgpu::Buffer {
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("mesh instances"),
+        contents: bytemuck::cast_slice(instances),
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE,
+    })
+}
@@ -0,0 +1,330 @@
+use std::cell::Cell;
+
+use cgmath::Point3;
+use serde::{Deserialize, Serialize};
+use wgpu::{BindGroup, Buffer, ComputePipeline, PipelineCompilationOptions, RenderPipeline};
+
+use crate::{environment::EnvironmentMap, frame_stats, hot_reload::wgsl, GfxState};
+
+/// Probes the sky shader can interpolate between, so the uniform stays under 16k.
+pub const MAX_IRRADIANCE_PROBES: usize = 64;
+/// L2 spherical harmonics, rgb per coefficient
+const SH_COEFFICIENTS: usize = 9;
+/// face edge of the cube map each probe is captured into before projecting it
+const CAPTURE_SIZE: u32 = 32;
+
+/// A regular grid of diffuse lighting probes, baked from the scene as seen from every
+/// grid point and used instead of the flat ambient term.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IrradianceGridSettings {
+    pub enabled: bool,
+    /// world position of the first probe
+    pub origin: [f32; 3],
+    /// probes along x, y and z
+    pub counts: [u32; 3],
+    /// meters between neighbouring probes
+    pub spacing: f32,
+    pub intensity: f32,
+}
+
+impl Default for IrradianceGridSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            origin: [-16.0, 1.0, -36.0],
+            counts: [5, 2, 5],
+            spacing: 8.0,
+            intensity: 1.0,
+        }
+    }
+}
+
+impl IrradianceGridSettings {
+    fn probe_count(&self) -> usize {
+        self.counts.iter().map(|&c| c as usize).product()
+    }
+
+    /// Grid order: x fastest, then y, then z.
+    fn probe_position(&self, index: usize) -> Point3<f32> {
+        let [cx, cy, _] = self.counts.map(|c| c as usize);
+        let (x, y, z) = (index % cx, index / cx % cy, index / (cx * cy));
+        Point3::new(
+            self.origin[0] + x as f32 * self.spacing,
+            self.origin[1] + y as f32 * self.spacing,
+            self.origin[2] + z as f32 * self.spacing,
+        )
+    }
+
+    /// Whether the sky shader should use the grid at all; an empty or oversized grid
+    /// leaves it on the flat ambient.
+    fn usable(&self) -> bool {
+        let count = self.probe_count();
+        self.enabled && count > 0 && count <= MAX_IRRADIANCE_PROBES && self.spacing > 0.0
+    }
+}
+
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct IrradianceGridHeader {
+    origin: [f32; 3],
+    spacing: f32,
+    counts: [u32; 3],
+    /// 0 = grid not baked or disabled, use the flat ambient
+    intensity: f32,
+}
+
+/// Offset of the coefficients in the uniform, right after the header.
+const COEFFICIENTS_OFFSET: u64 = std::mem::size_of::<IrradianceGridHeader>() as u64;
+const COEFFICIENTS_SIZE: u64 = (MAX_IRRADIANCE_PROBES * SH_COEFFICIENTS * 16) as u64;
+
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct BakeParams {
+    probe: u32,
+    _pad: [u32; 3],
+}
+
+struct Bake {
+    capture: EnvironmentMap,
+    // what the compute pass writes, copied into the uniform once all probes are done
+    coefficients: Buffer,
+    params_buffer: Buffer,
+    bind_group: BindGroup,
+    pipeline: ComputePipeline,
+}
+
+/// The baked grid, as a uniform the sky shader reads (group 0 binding 5).
+/// Baking renders each probe's surroundings into a small cube map and projects it onto
+/// spherical harmonics in a compute pass. It happens at load and again on request, so
+/// it falls behind a running time of day.
+pub struct IrradianceGrid {
+    pub uniform_buffer: Buffer,
+    // None without compute: the grid stays empty and the ground keeps the flat ambient
+    bake: Option<Bake>,
+    bake_pending: Cell<bool>,
+}
+
+impl IrradianceGrid {
+    pub fn new(
+        app: &GfxState,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        sky_buffer: &Buffer,
+    ) -> Self {
+        let device = &app.gpu.device;
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("irradiance grid"),
+            size: COEFFICIENTS_OFFSET + COEFFICIENTS_SIZE,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bake = app.gpu.budget.compute.then(|| {
+            let code = wgsl!("irradiance.wgsl");
+            let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("irradiance shader"),
+                source: wgpu::ShaderSource::Wgsl(code),
+            });
+            let capture = EnvironmentMap::with_size(device, camera_bind_group_layout, CAPTURE_SIZE);
+            let faces_view = capture.texture.create_view(&wgpu::TextureViewDescriptor {
+                label: Some("irradiance capture faces"),
+                dimension: Some(wgpu::TextureViewDimension::D2Array),
+                ..Default::default()
+            });
+            let coefficients = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("irradiance coefficients"),
+                size: COEFFICIENTS_SIZE,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            });
+            let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("irradiance bake params"),
+                size: std::mem::size_of::<BakeParams>() as u64,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+
+            let uniform_entry = |binding| wgpu::BindGroupLayoutEntry {
+                binding,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            };
+            let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("irradiance bake layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            view_dimension: wgpu::TextureViewDimension::D2Array,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    uniform_entry(1),
+                    uniform_entry(2),
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("irradiance bake bind group"),
+                layout: &layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&faces_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: sky_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: params_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: coefficients.as_entire_binding(),
+                    },
+                ],
+            });
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("irradiance bake pipeline layout"),
+                bind_group_layouts: &[&layout],
+                push_constant_ranges: &[],
+            });
+            let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("irradiance project"),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point: "project",
+                compilation_options: PipelineCompilationOptions::default(),
+            });
+            Bake {
+                capture,
+                coefficients,
+                params_buffer,
+                bind_group,
+                pipeline,
+            }
+        });
+
+        Self {
+            uniform_buffer,
+            bake,
+            bake_pending: Cell::new(true),
+        }
+    }
+
+    /// Bakes the grid again before the next frame.
+    pub fn request_bake(&self) {
+        self.bake_pending.set(true);
+    }
+
+    /// Bakes the grid if requested. `pipeline` and `scene_bind_group` render the scene
+    /// into the probes' cube map, see `EnvironmentMap::capture`. Every probe is its own
+    /// submit since they share the capture cube map and its cameras.
+    pub fn bake(&self, app: &GfxState, pipeline: &RenderPipeline, scene_bind_group: &BindGroup) {
+        if !self.bake_pending.replace(false) {
+            return;
+        }
+        let settings = &app.scene.irradiance;
+        let mut header = IrradianceGridHeader {
+            origin: settings.origin,
+            spacing: settings.spacing,
+            counts: settings.counts,
+            intensity: 0.0,
+        };
+        let Some(bake) = self.bake.as_ref().filter(|_| settings.usable()) else {
+            if settings.enabled {
+                println!(
+                    "Irradiance grid not baked ({} probes, at most {}, compute needed)",
+                    settings.probe_count(),
+                    MAX_IRRADIANCE_PROBES
+                );
+            }
+            frame_stats::write_buffer(
+                &app.gpu.queue,
+                &self.uniform_buffer,
+                0,
+                bytemuck::bytes_of(&header),
+            );
+            return;
+        };
+
+        let count = settings.probe_count();
+        println!("Baking {} irradiance probes", count);
+        for probe in 0..count {
+            let params = BakeParams {
+                probe: probe as u32,
+                _pad: [0; 3],
+            };
+            frame_stats::write_buffer(
+                &app.gpu.queue,
+                &bake.params_buffer,
+                0,
+                bytemuck::bytes_of(&params),
+            );
+            let mut encoder =
+                app.gpu
+                    .device
+                    .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                        label: Some("irradiance bake"),
+                    });
+            bake.capture.capture(
+                &mut encoder,
+                &app.gpu.queue,
+                settings.probe_position(probe),
+                pipeline,
+                scene_bind_group,
+            );
+            {
+                let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some("irradiance project"),
+                    timestamp_writes: None,
+                });
+                compute_pass.set_pipeline(&bake.pipeline);
+                compute_pass.set_bind_group(0, &bake.bind_group, &[]);
+                compute_pass.dispatch_workgroups(1, 1, 1);
+            }
+            app.gpu.queue.submit(Some(encoder.finish()));
+        }
+
+        let mut encoder = app
+            .gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("irradiance upload"),
+            });
+        encoder.copy_buffer_to_buffer(
+            &bake.coefficients,
+            0,
+            &self.uniform_buffer,
+            COEFFICIENTS_OFFSET,
+            COEFFICIENTS_SIZE,
+        );
+        header.intensity = settings.intensity;
+        frame_stats::write_buffer(
+            &app.gpu.queue,
+            &self.uniform_buffer,
+            0,
+            bytemuck::bytes_of(&header),
+        );
+        app.gpu.queue.submit(Some(encoder.finish()));
+    }
+}
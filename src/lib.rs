@@ -0,0 +1,1585 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+#[allow(non_snake_case)]
+mod GpuFatory;
+#[cfg(feature = "path_tracing")]
+mod accumulation;
+mod ao_bake;
+mod asset_loader;
+mod auto_exposure;
+pub mod bindings;
+#[cfg(feature = "post")]
+mod bloom;
+mod boids;
+#[cfg(feature = "ui")]
+mod buffer_pool;
+#[cfg(feature = "path_tracing")]
+mod bvh;
+#[cfg(feature = "ui")]
+use anyhow::anyhow;
+use camera::{Camera, CameraController};
+pub use config::Config;
+#[cfg(feature = "ui")]
+use console::{console_var, Console};
+#[cfg(feature = "ui")]
+use debug_overlay::DebugOverlay;
+use features::GpuFeatures;
+pub use gfx_state_builder::{DeviceRequest, GfxStateBuilder};
+pub use gpu_factory_builder::GpuFactoryBuilder;
+use hot_reload::ShaderWatcher;
+use input_log::InputLog;
+use limits::RenderBudget;
+use mouse::Mouse;
+use profiler::{profile_scope, Profiler};
+use render_thread::{FrameInput, KeyInput, RenderThread};
+use scene::Scene;
+use shortcuts::{Chord, Shortcuts};
+#[cfg(feature = "ui")]
+use sky::SkySettings;
+use time_of_day::DirectionalLight;
+use wgpu::Adapter;
+#[cfg(feature = "ui")]
+use wind::Wind;
+use winit::{
+    application::ApplicationHandler,
+    event::{ElementState, MouseButton, WindowEvent},
+    event_loop::{ActiveEventLoop, EventLoop, EventLoopProxy},
+    keyboard::{KeyCode, ModifiersState},
+    window::Window,
+};
+pub use GpuFatory::GpuFactory;
+mod camera;
+pub mod compute;
+mod config;
+#[cfg(feature = "ui")]
+mod console;
+#[cfg(test)]
+mod cpu_tests;
+mod debug_blit;
+#[cfg(feature = "ui")]
+mod debug_overlay;
+mod debug_view;
+mod deferred;
+#[cfg(feature = "path_tracing")]
+mod denoise;
+mod depth_buffer;
+mod device_poll;
+mod dynamic_resolution;
+mod dynamic_uniforms;
+mod environment;
+mod error;
+mod features;
+#[cfg(feature = "post")]
+mod flare;
+mod frame_context;
+mod frame_stats;
+mod frustum;
+mod fxaa;
+mod gbuffer;
+mod gfx_state_builder;
+mod gltf_import;
+mod gpu_culling;
+mod gpu_factory_builder;
+#[cfg(test)]
+mod gpu_tests;
+mod gpu_timer;
+#[cfg(feature = "post")]
+mod heat_haze;
+mod hot_reload;
+mod ibl;
+#[cfg(any(feature = "ui", test))]
+mod image_data;
+mod indirect;
+mod input_log;
+mod instance;
+mod irradiance;
+mod light;
+mod lightmap;
+mod limits;
+mod material;
+mod model;
+mod mouse;
+mod obj_import;
+mod particles;
+#[cfg(feature = "path_tracing")]
+mod path_tracer;
+mod pipeline_cache;
+mod pixel_inspector;
+mod planar_reflection;
+#[cfg(feature = "post")]
+mod post_chain;
+mod profiler;
+mod readback;
+mod reflection_probe;
+pub mod registry;
+mod render_graph;
+mod render_scale;
+mod render_target;
+mod render_thread;
+mod scene;
+mod sdf_scene;
+mod shadertoy;
+mod shadow_map;
+mod sharpen;
+mod shortcuts;
+mod sky;
+mod skybox;
+mod ssr;
+mod static_geometry;
+mod surface;
+#[cfg(feature = "ui")]
+mod text;
+pub mod texture;
+mod time_of_day;
+mod tonemap;
+mod upscale;
+mod uv_unwrap;
+mod vertex;
+mod weather;
+#[cfg(test)]
+mod wgsl_harness;
+mod wgsl_preprocessor;
+#[cfg(test)]
+mod wgsl_tests;
+mod wind;
+
+/// The app: a bake when the first argument names one, otherwise the window and its
+/// render thread until the window closes.
+pub fn run() {
+    // headless bake commands, no window
+    let bake = match std::env::args().nth(1).as_deref() {
+        Some("bake-lightmap") => Some(lightmap::bake_command()),
+        Some("bake-ao") => Some(ao_bake::bake_command()),
+        _ => None,
+    };
+    if let Some(result) = bake {
+        if let Err(e) = result {
+            println!("Bake failed: {:#}", e);
+        }
+        return;
+    }
+    if let Err(e) = run_event_loop() {
+        error::report_fatal(&e);
+        std::process::exit(1);
+    }
+}
+
+fn run_event_loop() -> error::Result<()> {
+    let event_loop = EventLoop::<UserEvent>::with_user_event().build()?;
+    let mut app_entry = EntryOn::Loading(event_loop.create_proxy());
+    event_loop.run_app(&mut app_entry)?;
+    Ok(())
+}
+
+/// Wakeups sent to the event loop from other threads.
+#[derive(Debug, Clone, Copy)]
+pub enum UserEvent {
+    // a queued readback's map finished, its callback can run
+    ReadbackReady,
+    // the render thread stopped on a fatal error, already reported
+    Exit,
+    // a WGSL file under asset/ was saved, the render thread picks it up with a redraw
+    ShaderChanged,
+}
+
+/// Everything tied to the window: the surface it presents to and the way back into the
+/// event loop. Headless (`GfxStateBuilder::build_headless`) there is no window, surface or
+/// event loop, and frames go to an offscreen texture described by `surface_config`.
+pub struct WindowState {
+    pub handle: Option<Arc<Window>>,
+    pub event_proxy: Option<EventLoopProxy<UserEvent>>,
+    // the surface outlives device rebuilds, so it and its instance live here
+    pub instance: wgpu::Instance,
+    pub surface: Option<wgpu::Surface<'static>>,
+    pub surface_config: wgpu::SurfaceConfiguration,
+    pub offscreen: Option<wgpu::Texture>,
+}
+
+impl WindowState {
+    pub fn request_redraw(&self) {
+        if let Some(handle) = &self.handle {
+            handle.request_redraw();
+        }
+    }
+
+    /// Applies `surface_config`: configures the surface, or recreates the offscreen target.
+    pub fn configure(&mut self, device: &wgpu::Device) {
+        if let Some(surface) = &self.surface {
+            surface.configure(device, &self.surface_config);
+            return;
+        }
+        let config = &self.surface_config;
+        self.offscreen = Some(device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("offscreen frame"),
+            size: wgpu::Extent3d {
+                width: config.width.max(1),
+                height: config.height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: config.format,
+            usage: config.usage,
+            view_formats: &config.view_formats,
+        }));
+    }
+
+    /// What a surface would typically be configured as, for headless runs. COPY_SRC so tests
+    /// can read frames back. sRGB itself rather than through a view format, which GL
+    /// backends can't do.
+    fn offscreen_config(width: u32, height: u32) -> wgpu::SurfaceConfiguration {
+        wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            width,
+            height,
+            present_mode: wgpu::PresentMode::Fifo,
+            desired_maximum_frame_latency: 2,
+            alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+            view_formats: vec![],
+        }
+    }
+}
+
+/// The device and what was granted with it. Cheap to clone, so loaders and other threads
+/// can hold on to it without borrowing the app; a device rebuild replaces the whole thing.
+#[derive(Clone)]
+pub struct GpuContext {
+    #[cfg(feature = "ui")]
+    pub adapter_info: wgpu::AdapterInfo,
+    pub device: Arc<wgpu::Device>,
+    pub queue: Arc<wgpu::Queue>,
+    pub features: GpuFeatures,
+    pub limits: wgpu::Limits,
+    pub budget: RenderBudget,
+    // set from the device lost callback, the rebuild itself happens on the event loop
+    pub device_lost: Arc<AtomicBool>,
+    // the first validation error no error scope caught, fatal once the frame is done
+    pub gpu_error: Arc<Mutex<Option<String>>>,
+}
+
+impl GpuContext {
+    /// Fails with the first uncaptured GPU error since the device was made.
+    pub fn check_errors(&self) -> error::Result<()> {
+        match self.gpu_error.lock().ok().and_then(|error| error.clone()) {
+            Some(message) => Err(error::Error::Gpu(message)),
+            None => Ok(()),
+        }
+    }
+
+    /// Runs `create` in an error scope, so a shader or pipeline that doesn't validate
+    /// comes back as an error here instead of reaching the uncaptured error handler.
+    pub fn scoped<T>(&self, create: impl FnOnce() -> T) -> error::Result<T> {
+        self.device.push_error_scope(wgpu::ErrorFilter::OutOfMemory);
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let value = create();
+        let validation = pollster::block_on(self.device.pop_error_scope());
+        let out_of_memory = pollster::block_on(self.device.pop_error_scope());
+        match validation.or(out_of_memory) {
+            Some(e) => Err(e.into()),
+            None => Ok(value),
+        }
+    }
+}
+
+/// CPU side state that moves from one frame to the next.
+pub struct FrameState {
+    pub last_frame: Instant,
+    // seconds since the previous RedrawRequested
+    pub dt: f32,
+    // seconds since start, drives anything procedural
+    pub time: f32,
+    pub sun_light: DirectionalLight,
+    // how far the cloud layer has drifted
+    pub cloud_offset: [f32; 2],
+    pub camera_controller: CameraController,
+    pub camera: Camera,
+    // held right now, for shortcut chords
+    pub modifiers: ModifiersState,
+    pub mouse: Mouse,
+    // frames updated so far; input recordings are keyed on it
+    pub count: u64,
+}
+
+pub struct GfxState {
+    pub window: WindowState,
+    pub gpu: GpuContext,
+    pub frame: FrameState,
+    pub config: Config,
+    // what the device and surface were set up with, to rebuild them the same way
+    pub device_request: DeviceRequest,
+    // pipelines of an embedding program's own, rebuilt with the factory
+    pub factory_builder: GpuFactoryBuilder,
+    pub scene: Scene,
+    pub gpu_factory: Option<GpuFactory>,
+    // puffin scopes for the overlay, off until F12
+    pub profiler: Profiler,
+    pub shortcuts: Shortcuts,
+    #[cfg(feature = "ui")]
+    pub console: Console,
+    // --record or --replay
+    pub input_log: Option<InputLog>,
+    // None headless, or with `hot_reload_shaders` off
+    pub shader_watcher: Option<ShaderWatcher>,
+    // why the last shader reload failed, on screen until one succeeds
+    pub shader_error: Option<String>,
+}
+
+enum EntryOn {
+    Loading(EventLoopProxy<UserEvent>),
+    // the app state lives on the render thread from here on
+    Ready(RenderThread),
+    // shut down, or never started because of a fatal error
+    Closed,
+}
+
+impl EntryOn {
+    /// Stops the event loop over a fatal error. Dropping the render thread shuts the app
+    /// down before the loop returns.
+    fn fail(&mut self, event_loop: &ActiveEventLoop, error: &error::Error) {
+        error::report_fatal(error);
+        *self = Self::Closed;
+        event_loop.exit();
+    }
+}
+
+impl ApplicationHandler<UserEvent> for EntryOn {
+    fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+        if let Self::Loading(event_proxy) = self {
+            let event_proxy = event_proxy.clone();
+            let mut config = Config::load();
+            if std::env::args().any(|arg| arg == "--deterministic") {
+                config.deterministic.enabled = true;
+            }
+            let mut builder = GfxStateBuilder::new(config);
+            // API trace for reproducing GPU bugs, needs wgpu's trace feature
+            if let Some(index) = std::env::args().position(|arg| arg == "--trace") {
+                match std::env::args().nth(index + 1) {
+                    Some(path) => builder = builder.trace_path(path),
+                    None => println!("--trace needs a directory"),
+                }
+            }
+            let started = pollster::block_on(async move {
+                println!("async block");
+                let mut gfx_state = builder.build(event_loop, event_proxy).await?;
+                // a model to look at over the scene's own, `--model <file.gltf|file.glb|file.obj>`
+                if let Some(index) = std::env::args().position(|arg| arg == "--model") {
+                    match std::env::args().nth(index + 1) {
+                        Some(path) => gfx_state.scene.models.push(path.into()),
+                        None => println!("--model needs a .gltf, .glb or .obj file"),
+                    }
+                }
+                // a Shadertoy-style shader drawn over the scene, `--shadertoy <file.wgsl>`
+                if let Some(index) = std::env::args().position(|arg| arg == "--shadertoy") {
+                    match std::env::args().nth(index + 1) {
+                        Some(path) => {
+                            gfx_state.factory_builder =
+                                GpuFactoryBuilder::new().with_shadertoy("shadertoy", path)
+                        }
+                        None => println!("--shadertoy needs a .wgsl file with a mainImage"),
+                    }
+                }
+                // before the factory, a replay brings its own scene
+                match InputLog::from_args() {
+                    Ok(input_log) => gfx_state.input_log = input_log,
+                    Err(e) => println!("No input recording or replay: {:#}", e),
+                }
+                if let Err(e) = InputLog::start(&mut gfx_state) {
+                    println!("Input log failed to start: {:#}", e);
+                    gfx_state.input_log = None;
+                }
+                gfx_state.gpu_factory = Some(GpuFactory::new(&gfx_state)?);
+                Ok(gfx_state)
+            });
+            match started {
+                Ok(gfx_state) => {
+                    *self = EntryOn::Ready(RenderThread::spawn(gfx_state));
+                    println!("Ready now!");
+                }
+                Err(e) => self.fail(event_loop, &e),
+            }
+        }
+    }
+
+    fn window_event(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+        _window_id: winit::window::WindowId,
+        event: winit::event::WindowEvent,
+    ) {
+        let Self::Ready(render_thread) = self else {
+            println!("Not ready yet! in Loading");
+            return;
+        };
+        let input = match event {
+            WindowEvent::Resized(size) => FrameInput::Resized(size),
+            WindowEvent::RedrawRequested => FrameInput::Redraw,
+            WindowEvent::KeyboardInput { event, .. } => match KeyInput::from_event(&event) {
+                Some(key) => FrameInput::Key(key),
+                None => return,
+            },
+            WindowEvent::ModifiersChanged(modifiers) => FrameInput::Modifiers(modifiers.state()),
+            WindowEvent::CursorMoved { position, .. } => {
+                FrameInput::CursorMoved(position.x, position.y)
+            }
+            WindowEvent::CursorLeft { .. } => FrameInput::CursorLeft,
+            WindowEvent::MouseInput { state, button, .. } => {
+                FrameInput::MouseInput(button, state == ElementState::Pressed)
+            }
+            WindowEvent::CloseRequested => {
+                println!("CloseRequested");
+                event_loop.exit();
+                return;
+            }
+            _ => return,
+        };
+        if !render_thread.send(input) {
+            self.fail(event_loop, &error::Error::RenderThread);
+        }
+    }
+
+    fn user_event(&mut self, event_loop: &ActiveEventLoop, event: UserEvent) {
+        let Self::Ready(render_thread) = self else {
+            return;
+        };
+        match event {
+            UserEvent::ReadbackReady => {
+                render_thread.send(FrameInput::ReadbackReady);
+            }
+            UserEvent::Exit => {
+                *self = Self::Closed;
+                event_loop.exit();
+            }
+            UserEvent::ShaderChanged => {
+                render_thread.send(FrameInput::Redraw);
+            }
+        }
+    }
+
+    fn exiting(&mut self, _event_loop: &ActiveEventLoop) {
+        // joins the render thread, which shuts the app down in order, while the window
+        // the surface was made from is still open
+        *self = Self::Closed;
+        println!("Exited");
+    }
+}
+
+impl GfxState {
+    fn from_parts(
+        window: WindowState,
+        gpu: GpuContext,
+        config: Config,
+        device_request: DeviceRequest,
+    ) -> Self {
+        println!("Render budget: {:?}", gpu.budget);
+        println!("Gfx State Ready");
+        let surface_config = &window.surface_config;
+
+        // camera
+        let camera = Camera {
+            // position the camera 1 unit up and 2 units back
+            // +z is out of the screen
+            eye: (0.0, 1.0, 2.0).into(),
+            // have it look at the origin
+            target: (0.0, 0.0, 0.0).into(),
+            // which way is "up"
+            up: cgmath::Vector3::unit_y(),
+            aspect: surface_config.width as f32 / surface_config.height as f32,
+            fovy: 45.0,
+            znear: 0.1,
+            zfar: 100.0,
+        };
+        let camera_controller = CameraController::new(10.);
+        let scene = Scene::load(&config.scene);
+        let mut sun_light = DirectionalLight::from_sky(&scene.weathered_sky());
+        scene.weather.dim_light(&mut sun_light);
+        let shader_watcher = match &window.event_proxy {
+            Some(event_proxy) if config.render.hot_reload_shaders => {
+                ShaderWatcher::new(event_proxy.clone())
+                    .map_err(|e| println!("Not watching asset/ for shader edits: {}", e))
+                    .ok()
+            }
+            _ => None,
+        };
+
+        Self {
+            window,
+            gpu,
+            frame: FrameState {
+                last_frame: Instant::now(),
+                dt: 0.0,
+                time: 0.0,
+                sun_light,
+                cloud_offset: [0.0; 2],
+                camera_controller,
+                camera,
+                modifiers: ModifiersState::empty(),
+                mouse: Mouse::default(),
+                count: 0,
+            },
+            shortcuts: Self::register_shortcuts(&config),
+            #[cfg(feature = "ui")]
+            console: Self::register_console(),
+            input_log: None,
+            config,
+            device_request,
+            factory_builder: GpuFactoryBuilder::default(),
+            scene,
+            gpu_factory: None,
+            profiler: Profiler::new(),
+            shader_watcher,
+            shader_error: None,
+        }
+    }
+
+    /// Picks an adapter, one that can present to `surface` if there is one, and creates the
+    /// device. Used both at startup and when rebuilding after the device was lost; a lost
+    /// device wakes `window` so the rebuild happens.
+    async fn request_device(
+        wgpu_instance: &wgpu::Instance,
+        surface: Option<&wgpu::Surface<'static>>,
+        window: Option<Arc<Window>>,
+        device_lost: &Arc<AtomicBool>,
+        config: &Config,
+        request: &DeviceRequest,
+    ) -> error::Result<(GpuContext, Adapter)> {
+        let adapter = wgpu_instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::util::power_preference_from_env()
+                    .unwrap_or(wgpu::PowerPreference::HighPerformance),
+                force_fallback_adapter: request.force_fallback_adapter,
+                compatible_surface: surface,
+            })
+            .await
+            .ok_or(error::Error::NoAdapter)?;
+        let adapter_info = adapter.get_info();
+        println!("Using {} ({:?})", adapter_info.name, adapter_info.backend);
+        let features = GpuFeatures::negotiate(&adapter, request.required_features);
+        let mut required_limits = match &request.limits {
+            Some(limits) => limits.clone(),
+            None => config.limits.resolve(&adapter),
+        };
+        if features.push_constants() {
+            required_limits.max_push_constant_size =
+                adapter.limits().max_push_constant_size.min(128);
+        }
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: None,
+                    required_features: features.granted,
+                    required_limits: required_limits.clone(),
+                },
+                request.trace_path.as_deref(),
+            )
+            .await?;
+        println!("Device created : {:?}", device.global_id());
+
+        // driver reset / TDR: only raise the flag and wake the loop here,
+        // the callback may run on any thread
+        let lost_flag = device_lost.clone();
+        device.set_device_lost_callback(move |reason, message| {
+            // dropping the old device during a rebuild also ends up here
+            if matches!(reason, wgpu::DeviceLostReason::Dropped) {
+                return;
+            }
+            println!("Device lost ({:?}): {}", reason, message);
+            lost_flag.store(true, Ordering::SeqCst);
+            if let Some(window) = &window {
+                window.request_redraw();
+            }
+        });
+        // validation errors outside an error scope would panic on whichever thread hit
+        // them; keep the first for the render thread to shut down over instead
+        let gpu_error = Arc::new(Mutex::new(None));
+        let first_error = gpu_error.clone();
+        device.on_uncaptured_error(Box::new(move |error| {
+            println!("Uncaptured GPU error: {}", error);
+            if let Ok(mut first) = first_error.lock() {
+                first.get_or_insert(error.to_string());
+            }
+        }));
+
+        let gpu = GpuContext {
+            #[cfg(feature = "ui")]
+            adapter_info,
+            device: Arc::new(device),
+            queue: Arc::new(queue),
+            features,
+            budget: RenderBudget::from_limits(&required_limits),
+            limits: required_limits,
+            device_lost: device_lost.clone(),
+            gpu_error,
+        };
+        Ok((gpu, adapter))
+    }
+
+    /// The surface's configuration for `adapter`: format and alpha mode from the window
+    /// settings unless `request` prefers others, with an sRGB view when the surface format
+    /// isn't one.
+    fn surface_config(
+        adapter: &Adapter,
+        surface: &wgpu::Surface<'static>,
+        width: u32,
+        height: u32,
+        config: &Config,
+        request: &DeviceRequest,
+    ) -> error::Result<wgpu::SurfaceConfiguration> {
+        let mut surface_config = surface
+            .get_default_config(adapter, width, height)
+            .ok_or(error::Error::UnsupportedSurface)?;
+        let surface_caps = surface.get_capabilities(adapter);
+        surface_config.alpha_mode = surface::pick_alpha_mode(&surface_caps, &config.window);
+        surface_config.format = match request.surface_format {
+            Some(format) if surface_caps.formats.contains(&format) => format,
+            Some(format) => {
+                println!("Surface format {:?} not offered, picking one", format);
+                surface::pick_format(&surface_caps, &config.window)
+            }
+            None => surface::pick_format(&surface_caps, &config.window),
+        };
+        if let Some(present_mode) = request.present_mode {
+            if surface_caps.present_modes.contains(&present_mode) {
+                surface_config.present_mode = present_mode;
+            } else {
+                println!("Present mode {:?} not offered, using Fifo", present_mode);
+            }
+        }
+        // all shading is linear, let an sRGB view do the encoding for non sRGB surfaces
+        if let Some(srgb) = surface::srgb_view_format(surface_config.format) {
+            if srgb != surface_config.format {
+                surface_config.view_formats.push(srgb);
+            }
+        }
+        println!(
+            "Surface format: {:?} (view {:?}), alpha mode: {:?}",
+            surface_config.format,
+            surface::output_view_format(&surface_config),
+            surface_config.alpha_mode
+        );
+        Ok(surface_config)
+    }
+
+    /// Throws away every GPU object and rebuilds them from the CPU side state
+    /// (window, camera, controller), keeping the surface and the instance.
+    async fn recover_device(&mut self) -> error::Result<()> {
+        println!("Recovering from device lost");
+        self.gpu_factory = None;
+        let (gpu, adapter) = Self::request_device(
+            &self.window.instance,
+            self.window.surface.as_ref(),
+            self.window.handle.clone(),
+            &self.gpu.device_lost,
+            &self.config,
+            &self.device_request,
+        )
+        .await?;
+        if let Some(surface) = &self.window.surface {
+            let config = &self.window.surface_config;
+            self.window.surface_config = Self::surface_config(
+                &adapter,
+                surface,
+                config.width,
+                config.height,
+                &self.config,
+                &self.device_request,
+            )?;
+        }
+        self.window.configure(&gpu.device);
+        self.gpu = gpu;
+        self.frame.camera.aspect =
+            self.window.surface_config.width as f32 / self.window.surface_config.height as f32;
+        self.gpu.device_lost.store(false, Ordering::SeqCst);
+        self.gpu_factory = Some(GpuFactory::new(self)?);
+        println!("Device recovered");
+        Ok(())
+    }
+
+    /// Everything forwarded from the window except redraws, on the render thread. Recorded
+    /// when recording; while replaying, live input other than resizes is dropped.
+    fn handle_input(&mut self, input: FrameInput) {
+        if let Some(input_log) = self.input_log.as_mut() {
+            let from_user = !matches!(input, FrameInput::Redraw | FrameInput::ReadbackReady);
+            if !input_log.replaying() && from_user {
+                input_log.record_input(self.frame.count, &input);
+            } else if from_user && !matches!(input, FrameInput::Resized(_)) {
+                return;
+            }
+        }
+        self.apply_input(input);
+    }
+
+    fn apply_input(&mut self, input: FrameInput) {
+        match input {
+            // minimizing sends 0x0, which no surface can be configured with; the last good
+            // config stays until the window comes back
+            FrameInput::Resized(size) if size.width == 0 || size.height == 0 => {}
+            FrameInput::Resized(size) => {
+                println!("Resized");
+                self.window.surface_config.width = size.width;
+                self.window.surface_config.height = size.height;
+                self.window.configure(&self.gpu.device);
+                self.frame.camera.aspect = size.width as f32 / size.height as f32;
+                if let Some(gpu_factory) = self.gpu_factory.as_mut() {
+                    gpu_factory.resize(&self.gpu.device, &self.window.surface_config);
+                }
+                self.window.request_redraw();
+            }
+            FrameInput::Key(event) => {
+                println!("KeyboardInput: {:?}", event.key);
+                #[cfg(feature = "ui")]
+                if self.console.open {
+                    self.console_key(&event);
+                    self.window.request_redraw();
+                    return;
+                }
+                if self.process_hotkeys(&event)
+                    || self.frame.camera_controller.process_events(&event)
+                {
+                    self.window.request_redraw();
+                }
+            }
+            FrameInput::Modifiers(modifiers) => self.frame.modifiers = modifiers,
+            FrameInput::CursorMoved(x, y) => {
+                if self.frame.mouse.moved(x, y) {
+                    self.window.request_redraw();
+                }
+                if let Some(gpu_factory) = self.gpu_factory.as_mut() {
+                    gpu_factory.pixel_inspector.cursor = Some((x, y));
+                    if gpu_factory.inspect_pixel {
+                        self.window.request_redraw();
+                    }
+                }
+            }
+            FrameInput::CursorLeft => {
+                self.frame.mouse.left_window();
+                if let Some(gpu_factory) = self.gpu_factory.as_mut() {
+                    gpu_factory.pixel_inspector.cursor = None;
+                }
+            }
+            FrameInput::MouseInput(button, pressed) => {
+                if button == MouseButton::Left && self.frame.mouse.button(pressed) {
+                    self.window.request_redraw();
+                }
+            }
+            FrameInput::ReadbackReady => {
+                if let Some(gpu_factory) = self.gpu_factory.as_ref() {
+                    gpu_factory.readbacks.service();
+                }
+            }
+            // the render thread draws, once per batch of inputs, and stops on its errors
+            FrameInput::Redraw => {}
+        }
+    }
+
+    /// Rebuilds what uses the WGSL files saved since the last frame: the sky's pipelines for
+    /// sky.wgsl, the whole factory for any other file, as that can be in any number of
+    /// pipelines. A shader that doesn't compile leaves everything as it was.
+    fn reload_changed_shaders(&mut self) {
+        let Some(shader_watcher) = &self.shader_watcher else {
+            return;
+        };
+        let changes = shader_watcher.take_changes();
+        if changes.is_empty() {
+            return;
+        }
+        let files = changes.files();
+        let Some(gpu_factory) = self.gpu_factory.as_mut() else {
+            return;
+        };
+        let reloaded = if files == "sky.wgsl" {
+            gpu_factory.reload_sky(&self.gpu)
+        } else {
+            self.rebuild_shaders()
+        };
+        if reloaded.is_err() {
+            changes.revert();
+        }
+        self.shader_reloaded(&files, reloaded);
+    }
+
+    /// Rebuilds the factory, keeping the one there if the new one fails.
+    fn rebuild_shaders(&mut self) -> error::Result<()> {
+        self.gpu_factory = Some(GpuFactory::new(self)?);
+        Ok(())
+    }
+
+    fn shader_reloaded(&mut self, what: &str, reloaded: error::Result<()>) {
+        match reloaded {
+            Ok(()) => {
+                println!("Reloaded {}", what);
+                self.shader_error = None;
+            }
+            Err(e) => {
+                println!("{} doesn't compile, kept the old shaders: {}", what, e);
+                self.shader_error = Some(e.to_string());
+            }
+        }
+    }
+
+    /// One frame. Errors are fatal: the device couldn't be rebuilt, or the frame hit a GPU
+    /// error.
+    fn redraw(&mut self) -> error::Result<()> {
+        println!("RedrawRequested");
+        if self.gpu.device_lost.load(Ordering::SeqCst) {
+            pollster::block_on(self.recover_device())?;
+        }
+        self.profiler.new_frame();
+        self.replay_inputs();
+        self.update();
+        let Some(gpu_factory) = self.gpu_factory.as_mut() else {
+            return Ok(());
+        };
+        gpu_factory
+            .camera_uniform
+            .update_view_proj(&self.frame.camera);
+        gpu_factory.update_assets(&self.gpu, &self.scene);
+        if let Some(gpu_factory) = self.gpu_factory.as_ref() {
+            gpu_factory.render(self)?;
+        }
+        self.frame.mouse.end_frame();
+        self.gpu.check_errors()?;
+        // frame times would pick the resolution, which isn't repeatable
+        let deterministic = self.deterministic();
+        if let Some(gpu_factory) = self.gpu_factory.as_mut().filter(|_| !deterministic) {
+            gpu_factory.adapt_resolution(
+                &self.gpu.device,
+                &self.window.surface_config,
+                &self.config.render.dynamic_resolution,
+                self.frame.dt,
+            );
+        }
+        if self.needs_continuous_redraw() {
+            self.window.request_redraw();
+        }
+        Ok(())
+    }
+
+    /// Tears the app down in dependency order once the frames stop: everything made from
+    /// the device, then the surface, then the device itself, and the window last.
+    fn shutdown(mut self) {
+        println!("Shutting down");
+        // flushes a recording
+        self.input_log = None;
+        let _ = self.gpu.device.poll(wgpu::Maintain::Wait);
+        self.gpu_factory = None;
+        self.window.offscreen = None;
+        self.window.surface = None;
+        drop(self.gpu);
+        println!("Shut down");
+    }
+
+    /// Feeds a replay's inputs due before this frame, as they arrived when recording.
+    fn replay_inputs(&mut self) {
+        let Some(input_log) = self.input_log.as_mut() else {
+            return;
+        };
+        for input in input_log.due(self.frame.count) {
+            match input {
+                // the window's own resize event follows, like when it was recorded
+                FrameInput::Resized(size) => {
+                    input_log::request_size(self, (size.width, size.height))
+                }
+                input => self.apply_input(input),
+            }
+        }
+        if self
+            .input_log
+            .as_ref()
+            .is_some_and(|log| log.replaying() && log.finished())
+        {
+            println!("Replay finished at frame {}", self.frame.count);
+            self.input_log = None;
+        }
+    }
+
+    /// CPU side per frame work before rendering.
+    fn update(&mut self) {
+        profile_scope!("update");
+        let now = Instant::now();
+        self.frame.dt = (now - self.frame.last_frame).as_secs_f32().min(0.25);
+        self.frame.last_frame = now;
+        self.frame.count += 1;
+        if self.deterministic() {
+            self.frame.dt = self.config.deterministic.dt;
+            // from the counter rather than summed, the same on every run however long it goes
+            self.frame.time = self.frame.count as f32 * self.frame.dt;
+        } else {
+            self.frame.time += self.frame.dt;
+        }
+
+        self.frame
+            .camera_controller
+            .update_camera(&mut self.frame.camera);
+
+        let day_night = &mut self.scene.day_night;
+        day_night.update(self.frame.dt);
+        if day_night.enabled {
+            day_night.apply(&mut self.scene.sky);
+        }
+        self.frame.sun_light = DirectionalLight::from_sky(&self.scene.weathered_sky());
+        self.scene.weather.dim_light(&mut self.frame.sun_light);
+        // clouds drift along the wind at their own (altitude) speed
+        let drift = self.scene.wind.direction_vector() * self.scene.sky.cloud_speed * self.frame.dt;
+        self.frame.cloud_offset[0] += drift.x;
+        self.frame.cloud_offset[1] += drift.z;
+    }
+
+    /// Fixed dt and time from the frame counter, see `DeterministicConfig`. Replays always
+    /// run this way.
+    fn deterministic(&self) -> bool {
+        self.config.deterministic.enabled
+            || self.input_log.as_ref().is_some_and(InputLog::replaying)
+    }
+
+    /// Anything animating on its own keeps the redraw loop going.
+    fn needs_continuous_redraw(&self) -> bool {
+        let day_night = &self.scene.day_night;
+        self.config.render.continuous_redraw
+            || self.input_log.as_ref().is_some_and(InputLog::replaying)
+            || self.scene.auto_exposure.enabled
+            || (day_night.enabled && !day_night.paused)
+            || self.scene.sky.cloud_speed != 0.0
+            || self.scene.weather.precipitating()
+            || self.gpu_factory.as_ref().is_some_and(|g| g.sdf_mode)
+            || self
+                .gpu_factory
+                .as_ref()
+                .is_some_and(GpuFactory::runs_shadertoy)
+            // readbacks only land on a later frame's poll
+            || self.gpu_factory.as_ref().is_some_and(|g| g.inspect_pixel)
+            // uploads only move on with frames
+            || self
+                .gpu_factory
+                .as_ref()
+                .is_some_and(|g| !g.asset_loader.progress().finished())
+            || self.optional_continuous_redraw()
+    }
+
+    /// `needs_continuous_redraw` for the subsystems behind cargo features.
+    fn optional_continuous_redraw(&self) -> bool {
+        #[cfg(feature = "post")]
+        if self.scene.heat_haze.enabled {
+            return true;
+        }
+        if self
+            .gpu_factory
+            .as_ref()
+            .is_some_and(GpuFactory::path_tracing)
+        {
+            return true;
+        }
+        #[cfg(feature = "ui")]
+        if self
+            .gpu_factory
+            .as_ref()
+            .is_some_and(|g| g.debug_overlay.visible)
+        {
+            return true;
+        }
+        false
+    }
+
+    /// Every shortcut the app handles, then the config's rebinds on top. Subsystems that read
+    /// keys themselves register theirs first so nothing is bound over them.
+    fn register_shortcuts(config: &Config) -> Shortcuts {
+        let mut shortcuts = Shortcuts::default();
+        CameraController::register_shortcuts(&mut shortcuts);
+        #[cfg(feature = "ui")]
+        {
+            DebugOverlay::register_shortcuts(&mut shortcuts);
+            shortcuts.register("app", "console", &[Chord::key(KeyCode::Backquote)]);
+            shortcuts.register("app", "shortcuts page", &[Chord::key(KeyCode::F1)]);
+        }
+        shortcuts.register("app", "profiler", &[Chord::key(KeyCode::F12)]);
+        shortcuts.register("scene", "save scene", &[Chord::ctrl(KeyCode::KeyS)]);
+        shortcuts.register("render", "reload shaders", &[Chord::ctrl(KeyCode::KeyR)]);
+        for (action, key) in [
+            ("gamma down", KeyCode::BracketLeft),
+            ("gamma up", KeyCode::BracketRight),
+            ("brightness down", KeyCode::Semicolon),
+            ("brightness up", KeyCode::Quote),
+            ("contrast down", KeyCode::Comma),
+            ("contrast up", KeyCode::Period),
+        ] {
+            shortcuts.register("display", action, &[Chord::key(key)]);
+        }
+        shortcuts.register(
+            "scene",
+            "exposure down",
+            &[
+                Chord::key(KeyCode::Minus),
+                Chord::key(KeyCode::NumpadSubtract),
+            ],
+        );
+        shortcuts.register(
+            "scene",
+            "exposure up",
+            &[Chord::key(KeyCode::Equal), Chord::key(KeyCode::NumpadAdd)],
+        );
+        for (action, key) in [
+            ("auto exposure", KeyCode::KeyE),
+            ("tonemap operator", KeyCode::KeyM),
+            ("day/night cycle", KeyCode::KeyN),
+            ("next weather", KeyCode::KeyR),
+            ("wind direction", KeyCode::KeyG),
+            ("wind strength", KeyCode::KeyH),
+            ("pause time of day", KeyCode::KeyP),
+            ("time forward", KeyCode::KeyT),
+            ("time back", KeyCode::KeyY),
+        ] {
+            shortcuts.register("scene", action, &[Chord::key(key)]);
+        }
+        for (action, key) in [
+            ("wireframe", KeyCode::F2),
+            ("encoding debug", KeyCode::F4),
+            ("supersampling", KeyCode::F6),
+            ("upscale quality", KeyCode::F7),
+            ("sharpening", KeyCode::F8),
+            ("select pass", KeyCode::F9),
+            ("toggle pass", KeyCode::F10),
+            ("debug texture", KeyCode::F11),
+            ("view mode", KeyCode::KeyV),
+            ("pixel inspector", KeyCode::KeyI),
+            ("recapture probes", KeyCode::KeyC),
+            ("fxaa", KeyCode::KeyF),
+            ("fxaa quality", KeyCode::KeyJ),
+            ("continuous redraw", KeyCode::KeyL),
+        ] {
+            shortcuts.register("render", action, &[Chord::key(key)]);
+        }
+        #[cfg(feature = "path_tracing")]
+        shortcuts.register("render", "path tracing", &[Chord::key(KeyCode::F5)]);
+        shortcuts.register("render", "sdf scene", &[Chord::ctrl(KeyCode::F5)]);
+        #[cfg(feature = "post")]
+        shortcuts.register("scene", "bloom", &[Chord::key(KeyCode::KeyB)]);
+        shortcuts.rebind_all(&config.shortcuts);
+        shortcuts
+    }
+
+    /// The console's app wide commands, then the variables subsystems expose.
+    #[cfg(feature = "ui")]
+    fn register_console() -> Console {
+        let mut console = Console::default();
+        console.command(
+            "load",
+            "load <scene.ron>, next to the current scene",
+            |app, args| {
+                let [file] = args else {
+                    anyhow::bail!("load <scene.ron>");
+                };
+                let path = app
+                    .config
+                    .scene
+                    .parent()
+                    .unwrap_or(std::path::Path::new(""))
+                    .join(file);
+                if !Scene::resolve_path(&path).exists() {
+                    anyhow::bail!("no scene at {}", path.display());
+                }
+                // static geometry and the bakes come from the scene, so it gets a new
+                // factory; the old scene and factory stay if that fails
+                let scene = std::mem::replace(&mut app.scene, Scene::load(&path));
+                let previous = std::mem::replace(&mut app.config.scene, path);
+                match GpuFactory::new(app) {
+                    Ok(gpu_factory) => app.gpu_factory = Some(gpu_factory),
+                    Err(e) => {
+                        app.scene = scene;
+                        app.config.scene = previous;
+                        return Err(e.into());
+                    }
+                }
+                Ok(format!("Loaded {}", app.config.scene.display()))
+            },
+        );
+        console.command("save", "save the scene", |app, _| {
+            app.scene.save(&app.config.scene)?;
+            Ok(format!("Saved {}", app.config.scene.display()))
+        });
+        console.command(
+            "screenshot",
+            "screenshot [file.png|file.exr], the HDR scene before tonemapping",
+            |app, args| {
+                let file = match args {
+                    [file] => file.to_string(),
+                    [] => format!(
+                        "screenshot-{}.png",
+                        std::time::SystemTime::now()
+                            .duration_since(std::time::UNIX_EPOCH)?
+                            .as_secs()
+                    ),
+                    _ => anyhow::bail!("screenshot [file.png|file.exr]"),
+                };
+                let gpu_factory = app
+                    .gpu_factory
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("nothing rendered yet"))?;
+                let path = Scene::resolve_path(std::path::Path::new(&file));
+                gpu_factory.readbacks.read_texture(
+                    &app.gpu.device,
+                    &app.gpu.queue,
+                    &gpu_factory.tonemap.hdr.texture,
+                    move |image| {
+                        let saved = image.and_then(|image| {
+                            if path.extension().is_some_and(|e| e == "exr") {
+                                image.save_exr(&path)
+                            } else {
+                                image.save_png(&path)
+                            }
+                        });
+                        match saved {
+                            Ok(()) => println!("Screenshot saved to {}", path.display()),
+                            Err(e) => println!("Screenshot failed: {:#}", e),
+                        }
+                    },
+                )?;
+                Ok(format!("Saving {} once the GPU is done", file))
+            },
+        );
+        console.command(
+            "buffer",
+            "buffer <name> [start] [end], a named buffer's bytes as floats",
+            |app, args| {
+                let (name, start, end) = match args {
+                    [name] => (name, None, None),
+                    [name, start] => (name, Some(start.parse()?), None),
+                    [name, start, end] => (name, Some(start.parse()?), Some(end.parse()?)),
+                    _ => anyhow::bail!("buffer <name> [start] [end]"),
+                };
+                let gpu_factory = app
+                    .gpu_factory
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("nothing rendered yet"))?;
+                let buffer = gpu_factory
+                    .registry
+                    .find::<wgpu::Buffer>(name)
+                    .ok_or_else(|| anyhow!("no buffer named {}", name))?;
+                let size = gpu_factory.registry[buffer].size();
+                let start = start.unwrap_or(0);
+                if start >= size {
+                    anyhow::bail!("{} is {} bytes, {} is past its end", name, size, start);
+                }
+                // a screenful unless asked for more
+                let end = end.unwrap_or((start + 64).min(size));
+                let label = format!("{} {}..{}", name, start, end);
+                gpu_factory.readbacks.push(
+                    gpu_factory.read_buffer(app, buffer, start..end),
+                    move |bytes| match bytes {
+                        Ok(bytes) => {
+                            let floats: Vec<String> = bytes
+                                .chunks_exact(4)
+                                .map(|word| {
+                                    f32::from_le_bytes(word.try_into().unwrap()).to_string()
+                                })
+                                .collect();
+                            println!("{}: {}", label, floats.join(" "));
+                        }
+                        Err(e) => println!("Reading {} failed: {:#}", label, e),
+                    },
+                );
+                Ok(format!(
+                    "Reading {} {}..{} once the GPU is done",
+                    name, start, end
+                ))
+            },
+        );
+        console.variable(
+            "exposure",
+            "stops",
+            |app| app.scene.exposure_ev.to_string(),
+            |app, value| {
+                app.scene.exposure_ev = value.parse()?;
+                if let Some(gpu_factory) = app.gpu_factory.as_mut() {
+                    gpu_factory.tonemap.uniform.exposure_ev = app.scene.exposure_ev;
+                }
+                Ok(())
+            },
+        );
+        console_var!(
+            console,
+            "auto_exposure",
+            "bool",
+            scene.auto_exposure.enabled
+        );
+        console_var!(
+            console,
+            "render.parallel_encoding",
+            "bool",
+            config.render.parallel_encoding
+        );
+        console_var!(console, "render.benchmark", "bool", config.render.benchmark);
+        console_var!(
+            console,
+            "render.continuous_redraw",
+            "bool",
+            config.render.continuous_redraw
+        );
+        SkySettings::register_console(&mut console);
+        Wind::register_console(&mut console);
+        particles::ParticleSettings::register_console(&mut console);
+        #[cfg(feature = "post")]
+        bloom::BloomSettings::register_console(&mut console);
+        console
+    }
+
+    /// Keys while the console is open: the console key closes it, the rest edit the line.
+    #[cfg(feature = "ui")]
+    fn console_key(&mut self, event: &KeyInput) {
+        // movement keys held when it opened still need to be let go of
+        if !event.pressed {
+            self.frame.camera_controller.process_events(event);
+        }
+        let chord = Chord::pressed(event.key, self.frame.modifiers);
+        if event.pressed && self.shortcuts.action(chord) == Some("console") {
+            self.console.open = false;
+            return;
+        }
+        if let Some(line) = self.console.key(event) {
+            Console::run(self, &line);
+        }
+    }
+
+    /// Runs the action the pressed chord is bound to. Returns true when the key was consumed.
+    fn process_hotkeys(&mut self, event: &KeyInput) -> bool {
+        if !event.pressed || event.repeat {
+            return false;
+        }
+        match self
+            .shortcuts
+            .action(Chord::pressed(event.key, self.frame.modifiers))
+        {
+            Some(action) => self.run_action(action),
+            None => false,
+        }
+    }
+
+    /// A toggle's new state, on the console's log (which prints it too) so it shows in the
+    /// window, or on stdout without the console.
+    fn status(&mut self, line: &str) {
+        #[cfg(feature = "ui")]
+        self.console.print(line);
+        #[cfg(not(feature = "ui"))]
+        println!("{}", line);
+    }
+
+    /// Runs the shortcut action named `action`, from a key press or the console's `toggle`.
+    /// Returns false for names it doesn't handle.
+    fn run_action(&mut self, action: &str) -> bool {
+        match action {
+            #[cfg(feature = "ui")]
+            "console" => {
+                self.console.open = !self.console.open;
+                true
+            }
+            #[cfg(feature = "ui")]
+            "shortcuts page" => {
+                self.shortcuts.page_visible = !self.shortcuts.page_visible;
+                true
+            }
+            "save scene" => {
+                match self.scene.save(&self.config.scene) {
+                    Ok(()) => println!("Saved scene to {}", self.config.scene.display()),
+                    Err(e) => println!("Failed to save scene: {}", e),
+                }
+                true
+            }
+            "reload shaders" => {
+                // recompiles every shader module and pipeline from the WGSL compiled into the
+                // binary, or from asset/ for the files the shader watcher saw saved
+                let rebuilt = self.rebuild_shaders();
+                self.shader_reloaded("shaders and pipelines", rebuilt);
+                true
+            }
+            "continuous redraw" => {
+                let render = &mut self.config.render;
+                render.continuous_redraw = !render.continuous_redraw;
+                let status = format!("Continuous redraw: {}", render.continuous_redraw);
+                self.status(&status);
+                true
+            }
+            "wireframe" => {
+                if !self.gpu.features.wireframe() {
+                    println!("Wireframe not available on this adapter");
+                    return true;
+                }
+                if let Some(gpu_factory) = self.gpu_factory.as_mut() {
+                    gpu_factory.wireframe = !gpu_factory.wireframe;
+                    println!("Wireframe: {}", gpu_factory.wireframe);
+                }
+                true
+            }
+            "gamma down" | "gamma up" | "brightness down" | "brightness up" | "contrast down"
+            | "contrast up" => {
+                let display = &mut self.config.display;
+                match action {
+                    "gamma down" => display.gamma = (display.gamma - 0.05).max(0.1),
+                    "gamma up" => display.gamma += 0.05,
+                    "brightness down" => display.brightness -= 0.02,
+                    "brightness up" => display.brightness += 0.02,
+                    "contrast down" => display.contrast = (display.contrast - 0.05).max(0.0),
+                    _ => display.contrast += 0.05,
+                }
+                println!("Display: {:?}", display);
+                if let Some(gpu_factory) = self.gpu_factory.as_mut() {
+                    gpu_factory.tonemap.set_display(&self.config.display);
+                }
+                if let Err(e) = self.config.save() {
+                    println!("Failed to save config: {}", e);
+                }
+                true
+            }
+            "exposure down" | "exposure up" => {
+                let step = if action == "exposure down" {
+                    -0.25
+                } else {
+                    0.25
+                };
+                self.scene.exposure_ev += step;
+                println!("Exposure: {:+.2} EV", self.scene.exposure_ev);
+                if let Some(gpu_factory) = self.gpu_factory.as_mut() {
+                    gpu_factory.tonemap.uniform.exposure_ev = self.scene.exposure_ev;
+                }
+                if let Err(e) = self.scene.save(&self.config.scene) {
+                    println!("Failed to save scene: {}", e);
+                }
+                true
+            }
+            "tonemap operator" => {
+                self.scene.tonemap = self.scene.tonemap.next();
+                println!("Tonemap operator: {:?}", self.scene.tonemap);
+                if let Some(gpu_factory) = self.gpu_factory.as_mut() {
+                    gpu_factory.tonemap.uniform.curve = self.scene.tonemap as u32;
+                }
+                if let Err(e) = self.scene.save(&self.config.scene) {
+                    println!("Failed to save scene: {}", e);
+                }
+                true
+            }
+            "auto exposure" => {
+                let auto_exposure = &mut self.scene.auto_exposure;
+                auto_exposure.enabled = !auto_exposure.enabled;
+                println!("Auto exposure: {}", auto_exposure.enabled);
+                if let Err(e) = self.scene.save(&self.config.scene) {
+                    println!("Failed to save scene: {}", e);
+                }
+                true
+            }
+            #[cfg(feature = "post")]
+            "bloom" => {
+                let bloom = &mut self.scene.bloom;
+                bloom.enabled = !bloom.enabled;
+                println!("Bloom: {}", bloom.enabled);
+                if let Err(e) = self.scene.save(&self.config.scene) {
+                    println!("Failed to save scene: {}", e);
+                }
+                true
+            }
+            "day/night cycle" => {
+                let day_night = &mut self.scene.day_night;
+                day_night.enabled = !day_night.enabled;
+                println!("Day/night cycle: {}", day_night.enabled);
+                if let Err(e) = self.scene.save(&self.config.scene) {
+                    println!("Failed to save scene: {}", e);
+                }
+                true
+            }
+            "next weather" => {
+                let weather = &mut self.scene.weather;
+                weather.next_kind();
+                println!("Weather: {:?}", weather.kind);
+                if let Err(e) = self.scene.save(&self.config.scene) {
+                    println!("Failed to save scene: {}", e);
+                }
+                true
+            }
+            "wind direction" | "wind strength" => {
+                let wind = &mut self.scene.wind;
+                if action == "wind direction" {
+                    wind.direction = (wind.direction + 45.0).rem_euclid(360.0);
+                } else {
+                    // calm, breeze, strong, storm
+                    wind.strength = match wind.strength {
+                        s if s < 2.0 => 2.0,
+                        s if s < 6.0 => 6.0,
+                        s if s < 12.0 => 12.0,
+                        _ => 0.0,
+                    };
+                }
+                println!("Wind: {:?}", wind);
+                if let Err(e) = self.scene.save(&self.config.scene) {
+                    println!("Failed to save scene: {}", e);
+                }
+                true
+            }
+            "recapture probes" => {
+                // both are baked from the current sky and go stale together
+                if let Some(gpu_factory) = self.gpu_factory.as_ref() {
+                    gpu_factory.reflection_probes.request_capture();
+                    gpu_factory.irradiance.request_bake();
+                }
+                true
+            }
+            "pause time of day" => {
+                let day_night = &mut self.scene.day_night;
+                day_night.paused = !day_night.paused;
+                println!("Day/night paused: {}", day_night.paused);
+                true
+            }
+            "time forward" | "time back" => {
+                let day_night = &mut self.scene.day_night;
+                day_night.scrub(if action == "time forward" { 0.5 } else { -0.5 });
+                if day_night.enabled {
+                    day_night.apply(&mut self.scene.sky);
+                }
+                println!("Time of day: {:.1}h", self.scene.day_night.time);
+                true
+            }
+            #[cfg(feature = "path_tracing")]
+            "path tracing" => {
+                if let Some(gpu_factory) = self.gpu_factory.as_mut() {
+                    if gpu_factory.path_tracer.is_none() {
+                        println!("Path tracing not available on this adapter");
+                    } else {
+                        gpu_factory.path_tracing = !gpu_factory.path_tracing;
+                        if let Some(path_tracer) = &gpu_factory.path_tracer {
+                            path_tracer.reset_accumulation();
+                        }
+                        println!("Path tracing: {}", gpu_factory.path_tracing);
+                    }
+                }
+                true
+            }
+            "sdf scene" => {
+                if let Some(gpu_factory) = self.gpu_factory.as_mut() {
+                    gpu_factory.sdf_mode = !gpu_factory.sdf_mode;
+                    if gpu_factory.sdf_mode && gpu_factory.path_tracing() {
+                        println!("The path tracer still shows until path tracing is off");
+                    }
+                    println!("SDF scene: {}", gpu_factory.sdf_mode);
+                }
+                true
+            }
+            "supersampling" => {
+                if let Some(gpu_factory) = self.gpu_factory.as_mut() {
+                    gpu_factory.supersample =
+                        gpu_factory.supersample % render_scale::MAX_SUPERSAMPLE + 1;
+                    self.config.render.supersample = gpu_factory.supersample;
+                    gpu_factory.resize(&self.gpu.device, &self.window.surface_config);
+                }
+                true
+            }
+            "upscale quality" => {
+                if let Some(gpu_factory) = self.gpu_factory.as_mut() {
+                    gpu_factory.upscale_quality = gpu_factory.upscale_quality.next();
+                    self.config.render.upscale.quality = gpu_factory.upscale_quality;
+                    gpu_factory.resize(&self.gpu.device, &self.window.surface_config);
+                }
+                true
+            }
+            "sharpening" => {
+                if let Some(gpu_factory) = self.gpu_factory.as_mut() {
+                    gpu_factory.sharpen = !gpu_factory.sharpen;
+                    self.config.render.sharpen.enabled = gpu_factory.sharpen;
+                    println!("Sharpening: {}", gpu_factory.sharpen);
+                }
+                true
+            }
+            "fxaa" => {
+                if let Some(gpu_factory) = self.gpu_factory.as_mut() {
+                    gpu_factory.antialias = !gpu_factory.antialias;
+                    self.config.render.fxaa.enabled = gpu_factory.antialias;
+                    println!("FXAA: {}", gpu_factory.antialias);
+                }
+                true
+            }
+            "fxaa quality" => {
+                let fxaa = &mut self.config.render.fxaa;
+                fxaa.quality = fxaa.quality.next();
+                println!("FXAA quality: {:?}", fxaa.quality);
+                true
+            }
+            "select pass" => {
+                if let Some(gpu_factory) = self.gpu_factory.as_mut() {
+                    gpu_factory.select_next_pass();
+                }
+                true
+            }
+            "toggle pass" => {
+                if let Some(gpu_factory) = self.gpu_factory.as_mut() {
+                    gpu_factory.toggle_selected_pass();
+                }
+                true
+            }
+            "debug texture" => {
+                if let Some(gpu_factory) = self.gpu_factory.as_mut() {
+                    gpu_factory.cycle_debug_texture();
+                }
+                true
+            }
+            "view mode" => {
+                if let Some(gpu_factory) = self.gpu_factory.as_mut() {
+                    gpu_factory.view_mode = gpu_factory.view_mode.next();
+                    gpu_factory.tonemap.uniform.passthrough =
+                        (gpu_factory.view_mode != debug_view::ViewMode::Final) as u32;
+                    if gpu_factory.path_tracing() {
+                        println!("View modes only apply to the display pass, not path tracing");
+                    }
+                    println!("View mode: {:?}", gpu_factory.view_mode);
+                }
+                true
+            }
+            "pixel inspector" => {
+                if let Some(gpu_factory) = self.gpu_factory.as_mut() {
+                    gpu_factory.inspect_pixel = !gpu_factory.inspect_pixel;
+                    println!("Pixel inspector: {}", gpu_factory.inspect_pixel);
+                }
+                true
+            }
+            "profiler" => {
+                self.profiler.toggle();
+                true
+            }
+            #[cfg(feature = "ui")]
+            "debug overlay" => {
+                if let Some(gpu_factory) = self.gpu_factory.as_mut() {
+                    let overlay = &mut gpu_factory.debug_overlay;
+                    overlay.visible = !overlay.visible;
+                    println!("Debug overlay: {}", overlay.visible);
+                }
+                true
+            }
+            #[cfg(feature = "ui")]
+            "overlay section 1" | "overlay section 2" | "overlay section 3"
+            | "overlay section 4" | "overlay section 5" | "overlay section 6"
+            | "overlay section 7" => {
+                let Some(overlay) = self
+                    .gpu_factory
+                    .as_mut()
+                    .map(|g| &mut g.debug_overlay)
+                    .filter(|overlay| overlay.visible)
+                else {
+                    return false;
+                };
+                // "overlay section N", 1 based
+                let index = action[action.len() - 1..].parse::<usize>().unwrap() - 1;
+                overlay.toggle_section(index);
+                true
+            }
+            "encoding debug" => {
+                if let Some(gpu_factory) = self.gpu_factory.as_mut() {
+                    let uniform = &mut gpu_factory.tonemap.uniform;
+                    uniform.debug_encoding = 1 - uniform.debug_encoding;
+                    println!("Color encoding debug view: {}", uniform.debug_encoding);
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+}
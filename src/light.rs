@@ -0,0 +1,185 @@
+use serde::{Deserialize, Serialize};
+use wgpu::{util::DeviceExt, BindGroup, BindGroupLayout, Buffer};
+
+use crate::{camera::Camera, frame_stats, ibl::HdrEnvironment, shadow_map::ShadowMap};
+
+/// A point light on the lit meshes, on top of the sun (or moon) and the ambient light
+/// the whole scene gets. The Blinn-Phong terms apply to every light the meshes see.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PointLightSettings {
+    pub enabled: bool,
+    pub position: [f32; 3],
+    /// puts the light at the camera instead of `position`, a headlamp that shows off
+    /// normal maps wherever the camera looks from
+    pub follow_camera: bool,
+    pub color: [f32; 3],
+    /// falls off with the square of the distance, so this is the brightness at 1m
+    pub intensity: f32,
+    /// scales the scene's ambient light
+    pub ambient: f32,
+    pub diffuse: f32,
+    pub specular: f32,
+    /// Blinn-Phong exponent, higher is a smaller, sharper highlight
+    pub shininess: f32,
+}
+
+impl Default for PointLightSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            position: [2.0, 3.0, 2.0],
+            follow_camera: false,
+            color: [1.0, 0.9, 0.8],
+            intensity: 10.0,
+            ambient: 1.0,
+            diffuse: 1.0,
+            specular: 0.5,
+            shininess: 32.0,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct LightUniform {
+    position: [f32; 3],
+    // 0 when disabled
+    intensity: f32,
+    color: [f32; 3],
+    ambient: f32,
+    diffuse: f32,
+    specular: f32,
+    shininess: f32,
+    _pad: f32,
+}
+
+impl PointLightSettings {
+    pub fn uniform(&self, camera: &Camera) -> LightUniform {
+        LightUniform {
+            position: if self.follow_camera {
+                camera.eye.into()
+            } else {
+                self.position
+            },
+            intensity: if self.enabled { self.intensity } else { 0.0 },
+            color: self.color,
+            ambient: self.ambient,
+            diffuse: self.diffuse,
+            specular: self.specular,
+            shininess: self.shininess,
+            _pad: 0.0,
+        }
+    }
+}
+
+/// A point light of the scene's `lights`, which only the deferred path draws: one
+/// fullscreen pass lights every pixel with all of them, so there can be many.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LocalLight {
+    pub position: [f32; 3],
+    pub color: [f32; 3],
+    /// falls off with the square of the distance, so this is the brightness at 1m
+    pub intensity: f32,
+    /// where the light has faded out completely, and pixels further away skip it
+    pub range: f32,
+}
+
+impl Default for LocalLight {
+    fn default() -> Self {
+        Self {
+            position: [0.0, 2.0, 0.0],
+            color: [1.0; 3],
+            intensity: 5.0,
+            range: 10.0,
+        }
+    }
+}
+
+/// One light of the deferred lighting pass's array, a vec4 position + vec4 color.
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct LocalLightUniform {
+    position: [f32; 3],
+    range: f32,
+    color: [f32; 3],
+    intensity: f32,
+}
+
+impl LocalLight {
+    pub fn uniform(&self) -> LocalLightUniform {
+        LocalLightUniform {
+            position: self.position,
+            range: self.range,
+            color: self.color,
+            intensity: self.intensity,
+        }
+    }
+}
+
+/// The light uniform and its bind group, group 3 of the material pipelines. The sun's
+/// shadow map comes after the point light in the same group, from binding 1 on, and the
+/// image based lighting after that, from binding 4 on.
+pub struct Light {
+    pub buffer: Buffer,
+    pub bind_group_layout: BindGroupLayout,
+    pub bind_group: BindGroup,
+}
+
+impl Light {
+    pub fn new(
+        device: &wgpu::Device,
+        settings: &PointLightSettings,
+        camera: &Camera,
+        shadow_map: &ShadowMap,
+        environment: &HdrEnvironment,
+    ) -> Self {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("light buffer"),
+            contents: bytemuck::bytes_of(&settings.uniform(camera)),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let mut layout_entries = vec![wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }];
+        layout_entries.extend(ShadowMap::bind_group_layout_entries(1));
+        layout_entries.extend(HdrEnvironment::bind_group_layout_entries(4));
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("light bind group layout"),
+            entries: &layout_entries,
+        });
+        let mut entries = vec![wgpu::BindGroupEntry {
+            binding: 0,
+            resource: buffer.as_entire_binding(),
+        }];
+        entries.extend(shadow_map.bind_group_entries(1));
+        entries.extend(environment.bind_group_entries(4));
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("light bind group"),
+            layout: &bind_group_layout,
+            entries: &entries,
+        });
+        Self {
+            buffer,
+            bind_group_layout,
+            bind_group,
+        }
+    }
+
+    pub fn write(&self, queue: &wgpu::Queue, settings: &PointLightSettings, camera: &Camera) {
+        frame_stats::write_buffer(
+            queue,
+            &self.buffer,
+            0,
+            bytemuck::bytes_of(&settings.uniform(camera)),
+        );
+    }
+}
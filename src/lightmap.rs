@@ -0,0 +1,479 @@
+use std::{
+    borrow::Cow,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+use wgpu::{util::DeviceExt, PipelineCompilationOptions, Texture, TextureView};
+
+use crate::{
+    asset_loader::{AssetLoader, DecodedTexture, Priority},
+    config::Config,
+    hot_reload::wgsl,
+    readback,
+    scene::Scene,
+    static_geometry::{self, StaticBox, StaticGeometryUniform, MAX_STATIC_BOXES},
+    time_of_day::DirectionalLight,
+    uv_unwrap::{self, Unwrap},
+};
+
+/// Format of everything baked. The lightmap has indirect light in rgb (same units as
+/// the ambient term) and sun visibility in a.
+const BAKED_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+const TEXEL_SIZE: u32 = 8;
+const FILE_MAGIC: &[u8; 4] = b"LMAP";
+
+/// The static geometry's baked lighting, path traced offline so the ground and the boxes
+/// get each other's shadows and bounce light without tracing per frame. The texture has
+/// a square of the ground plane on top and below it the boxes' faces, unwrapped into an
+/// atlas as wide as the square.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LightmapSettings {
+    pub enabled: bool,
+    /// baked file, relative to the crate directory
+    pub path: PathBuf,
+    /// world xz of the center of the map
+    pub center: [f32; 2],
+    /// meters covered along x and z
+    pub extent: f32,
+    /// texels along each side of the ground square
+    pub resolution: u32,
+    /// on the boxes' faces
+    pub texels_per_meter: f32,
+    /// indirect rays per texel
+    pub samples: u32,
+}
+
+impl Default for LightmapSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            path: PathBuf::from("asset/lightmap.bin"),
+            center: [0.0, -20.0],
+            extent: 64.0,
+            resolution: 256,
+            texels_per_meter: 8.0,
+            samples: 256,
+        }
+    }
+}
+
+impl LightmapSettings {
+    /// center xz, extent, in use; as packed into the static geometry uniform
+    pub fn uniform(&self, baked: bool) -> [f32; 4] {
+        [
+            self.center[0],
+            self.center[1],
+            self.extent,
+            (self.enabled && baked) as u32 as f32,
+        ]
+    }
+
+    fn ground_resolution(&self) -> u32 {
+        self.resolution.max(8)
+    }
+
+    /// The boxes' faces in charts under the ground square; a box is a mesh of its own.
+    pub fn unwrap(&self, boxes: &[StaticBox]) -> anyhow::Result<Unwrap> {
+        let meshes: Vec<_> = boxes
+            .iter()
+            .take(MAX_STATIC_BOXES)
+            .map(|b| {
+                static_geometry::face_triangles(b)
+                    .into_iter()
+                    .flatten()
+                    .collect()
+            })
+            .collect();
+        uv_unwrap::unwrap(
+            &meshes,
+            self.texels_per_meter.max(1e-3),
+            self.ground_resolution(),
+        )
+    }
+
+    /// Each box face's rectangle in a baked texture of `size` texels, x, y, width and
+    /// height in the order of `StaticGeometryUniform::lightmap_charts`, the face's texel
+    /// axes those of the AO tiles. None when these settings and boxes bake to another
+    /// size, a stale file.
+    pub fn charts(
+        &self,
+        boxes: &[StaticBox],
+        size: (u32, u32),
+    ) -> Option<[[f32; 4]; MAX_STATIC_BOXES * 6]> {
+        let unwrap = self.unwrap(boxes).ok()?;
+        let ground = self.ground_resolution();
+        if size != (ground, ground + unwrap.height) {
+            return None;
+        }
+        let mut charts = [[0.0; 4]; MAX_STATIC_BOXES * 6];
+        for (index, b) in boxes.iter().take(MAX_STATIC_BOXES).enumerate() {
+            for (face, triangles) in static_geometry::face_triangles(b).iter().enumerate() {
+                let chart = unwrap
+                    .charts
+                    .iter()
+                    .find(|chart| chart.mesh == index && chart.triangles.contains(&(face * 2)))?;
+                let mut min = [f32::MAX; 2];
+                let mut max = [f32::MIN; 2];
+                for p in triangles.iter().flatten() {
+                    let texel = chart.texel(*p);
+                    for i in 0..2 {
+                        min[i] = min[i].min(texel[i]);
+                        max[i] = max[i].max(texel[i]);
+                    }
+                }
+                charts[index * 6 + face] = [
+                    min[0],
+                    min[1] + ground as f32,
+                    max[0] - min[0],
+                    max[1] - min[1],
+                ];
+            }
+        }
+        Some(charts)
+    }
+}
+
+/// A baked texture, a 1x1 stand in until its file is loaded or when there is none.
+pub struct BakedTexture {
+    // only the overlay's memory readout looks at it, the view keeps it alive
+    #[cfg(feature = "ui")]
+    pub texture: Texture,
+    pub view: TextureView,
+    pub size: (u32, u32),
+    pub baked: bool,
+}
+
+impl BakedTexture {
+    pub fn placeholder(device: &wgpu::Device, queue: &wgpu::Queue, label: &str) -> Self {
+        let texture = device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width: 1,
+                    height: 1,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: BAKED_FORMAT,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+            wgpu::util::TextureDataOrder::LayerMajor,
+            &[0; TEXEL_SIZE as usize],
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Self {
+            #[cfg(feature = "ui")]
+            texture,
+            view,
+            size: (1, 1),
+            baked: false,
+        }
+    }
+
+    /// Queues the baked file at `path` on `loader`; it comes back from `upload` as `label`.
+    pub fn request(loader: &mut AssetLoader, label: &str, path: &Path, priority: Priority) {
+        let path = Scene::resolve_path(path);
+        loader.request(label, priority, move || {
+            let (width, height, texels) = read_file(&path)?;
+            Ok(DecodedTexture {
+                width,
+                height,
+                format: BAKED_FORMAT,
+                texels,
+            })
+        });
+    }
+
+    pub fn loaded(texture: Texture) -> Self {
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Self {
+            size: (texture.width(), texture.height()),
+            #[cfg(feature = "ui")]
+            texture,
+            view,
+            baked: true,
+        }
+    }
+}
+
+/// "LMAP", width and height as little endian u32, then the rgba16f texels row by row.
+pub fn write_file(path: &Path, width: u32, height: u32, texels: &[u8]) -> anyhow::Result<()> {
+    let mut file = std::fs::File::create(path)?;
+    file.write_all(FILE_MAGIC)?;
+    file.write_all(&width.to_le_bytes())?;
+    file.write_all(&height.to_le_bytes())?;
+    file.write_all(texels)?;
+    Ok(())
+}
+
+fn read_file(path: &Path) -> anyhow::Result<(u32, u32, Vec<u8>)> {
+    let bytes = std::fs::read(path).with_context(|| format!("can't read {}", path.display()))?;
+    if bytes.len() < 12 || &bytes[0..4] != FILE_MAGIC {
+        bail!("{} is not a lightmap", path.display());
+    }
+    let width = u32::from_le_bytes(bytes[4..8].try_into()?);
+    let height = u32::from_le_bytes(bytes[8..12].try_into()?);
+    let data = bytes[12..].to_vec();
+    if width == 0 || height == 0 || data.len() != (width * height * TEXEL_SIZE) as usize {
+        bail!("{} has a bad size", path.display());
+    }
+    Ok((width, height, data))
+}
+
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct BakeParams {
+    center: [f32; 2],
+    extent: f32,
+    samples: u32,
+    light_direction: [f32; 3],
+    // radians, spread of the shadow rays
+    sun_radius: f32,
+    // color * intensity
+    light_color: [f32; 3],
+    _pad0: f32,
+    ambient: [f32; 3],
+    _pad1: f32,
+}
+
+// one for every face of every box
+const MAX_CHARTS: usize = MAX_STATIC_BOXES * 6;
+
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct BakeChart {
+    // x, y, width, height in the baked texture
+    rect: [u32; 4],
+    // xyz, w unused; see uv_unwrap::Chart
+    origin: [f32; 4],
+    texel_x: [f32; 4],
+    texel_y: [f32; 4],
+    normal: [f32; 4],
+}
+
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct BakeCharts {
+    count: u32,
+    _pad: [u32; 3],
+    charts: [BakeChart; MAX_CHARTS],
+}
+
+impl BakeCharts {
+    fn new(unwrap: &Unwrap, top: u32) -> Self {
+        let mut charts: Self = bytemuck::Zeroable::zeroed();
+        charts.count = unwrap.charts.len().min(MAX_CHARTS) as u32;
+        let vec4 = |[x, y, z]: [f32; 3]| [x, y, z, 0.0];
+        for (data, chart) in charts.charts.iter_mut().zip(&unwrap.charts) {
+            *data = BakeChart {
+                rect: [
+                    chart.offset[0],
+                    chart.offset[1] + top,
+                    chart.size[0],
+                    chart.size[1],
+                ],
+                origin: vec4(chart.origin),
+                texel_x: vec4(chart.texel_axes[0]),
+                texel_y: vec4(chart.texel_axes[1]),
+                normal: vec4(chart.normal),
+            };
+        }
+        charts
+    }
+}
+
+/// A headless device for the bake commands.
+pub struct Baker {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+}
+
+impl Baker {
+    pub async fn new() -> anyhow::Result<Self> {
+        let instance = wgpu::Instance::default();
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                force_fallback_adapter: false,
+                compatible_surface: None,
+            })
+            .await
+            .context("No adapter to bake with")?;
+        let adapter_info = adapter.get_info();
+        println!(
+            "Baking on {} ({:?})",
+            adapter_info.name, adapter_info.backend
+        );
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: Some("bake"),
+                    required_features: wgpu::Features::empty(),
+                    required_limits: wgpu::Limits::default(),
+                },
+                None,
+            )
+            .await?;
+        Ok(Self { device, queue })
+    }
+
+    pub fn max_size(&self) -> u32 {
+        self.device.limits().max_texture_dimension_2d
+    }
+
+    pub fn uniform(&self, label: &str, contents: &[u8]) -> wgpu::Buffer {
+        self.device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents,
+                usage: wgpu::BufferUsages::UNIFORM,
+            })
+    }
+
+    /// Runs the `bake` entry point of `code` once per texel of a `width` x `height`
+    /// rgba16f storage texture and reads the result back. Group 0 has `uniforms` at
+    /// bindings 0.., the texture right after them. Workgroups are 8x8.
+    pub fn run(
+        &self,
+        label: &str,
+        code: &str,
+        uniforms: &[&wgpu::Buffer],
+        width: u32,
+        height: u32,
+    ) -> anyhow::Result<Vec<u8>> {
+        let device = &self.device;
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: BAKED_FORMAT,
+            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(label),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(code)),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some(label),
+            layout: None,
+            module: &shader,
+            entry_point: "bake",
+            compilation_options: PipelineCompilationOptions::default(),
+        });
+        let mut entries: Vec<wgpu::BindGroupEntry> = uniforms
+            .iter()
+            .enumerate()
+            .map(|(i, buffer)| wgpu::BindGroupEntry {
+                binding: i as u32,
+                resource: buffer.as_entire_binding(),
+            })
+            .collect();
+        entries.push(wgpu::BindGroupEntry {
+            binding: uniforms.len() as u32,
+            resource: wgpu::BindingResource::TextureView(&view),
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(label),
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &entries,
+        });
+
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some(label) });
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some(label),
+                timestamp_writes: None,
+            });
+            compute_pass.set_pipeline(&pipeline);
+            compute_pass.set_bind_group(0, &bind_group, &[]);
+            compute_pass.dispatch_workgroups(width.div_ceil(8), height.div_ceil(8), 1);
+        }
+        self.queue.submit(Some(encoder.finish()));
+        let texels = readback::read_texture_bytes(device, &self.queue, &texture)?;
+        Ok(texels)
+    }
+}
+
+/// `cargo run -- bake-lightmap`: bakes the lightmap of the configured scene's ground and
+/// boxes without opening a window and writes it to the path in the scene's settings.
+pub fn bake_command() -> anyhow::Result<()> {
+    let config = Config::load();
+    let scene = Scene::load(&config.scene);
+    pollster::block_on(bake(&scene))
+}
+
+async fn bake(scene: &Scene) -> anyhow::Result<()> {
+    let settings = &scene.lightmap;
+    let baker = Baker::new().await?;
+    let ground = settings.ground_resolution();
+    let unwrap = settings.unwrap(&scene.static_boxes)?;
+    let (width, height) = (ground, ground + unwrap.height);
+    if width > baker.max_size() || height > baker.max_size() {
+        bail!("{}x{} lightmap is too big for the adapter", width, height);
+    }
+    // the bake is for the sun as it is in the scene file, it doesn't follow the time of day
+    let sky = scene.weathered_sky();
+    let mut light = DirectionalLight::from_sky(&sky);
+    scene.weather.dim_light(&mut light);
+    let params = BakeParams {
+        center: settings.center,
+        extent: settings.extent,
+        samples: settings.samples.max(1),
+        light_direction: light.direction.into(),
+        sun_radius: sky.sun_size.to_radians(),
+        light_color: light.color.map(|c| c * light.intensity),
+        _pad0: 0.0,
+        ambient: light.ambient,
+        _pad1: 0.0,
+    };
+    let params_buffer = baker.uniform("lightmap bake params", bytemuck::bytes_of(&params));
+    let geometry_buffer = baker.uniform(
+        "lightmap bake geometry",
+        bytemuck::bytes_of(&StaticGeometryUniform::new(&scene.static_boxes)),
+    );
+
+    let charts_buffer = baker.uniform(
+        "lightmap bake charts",
+        bytemuck::bytes_of(&BakeCharts::new(&unwrap, ground)),
+    );
+
+    println!(
+        "Baking {}x{} texels, {} samples, {} static boxes in {} charts",
+        width,
+        height,
+        params.samples,
+        scene.static_boxes.len(),
+        unwrap.charts.len()
+    );
+    let code = wgsl!("lightmap.wgsl");
+    let texels = baker.run(
+        "lightmap bake",
+        &code,
+        &[&params_buffer, &geometry_buffer, &charts_buffer],
+        width,
+        height,
+    )?;
+
+    let path = Scene::resolve_path(&settings.path);
+    write_file(&path, width, height, &texels)?;
+    println!("Lightmap written to {}", path.display());
+    Ok(())
+}
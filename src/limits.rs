@@ -0,0 +1,114 @@
+use serde::{Deserialize, Serialize};
+use wgpu::{Adapter, Backend, Limits};
+
+/// Which set of limits to ask the device for.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub enum LimitsProfile {
+    #[default]
+    Default,
+    /// what WebGL2 / the GL backend / old hardware can do
+    DownlevelWebgl2,
+    /// downlevel defaults with some values overridden from the config
+    Custom(CustomLimits),
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CustomLimits {
+    pub max_texture_dimension_2d: Option<u32>,
+    pub max_bind_groups: Option<u32>,
+    pub max_uniform_buffer_binding_size: Option<u32>,
+    pub max_storage_buffers_per_shader_stage: Option<u32>,
+    pub max_storage_buffer_binding_size: Option<u32>,
+    pub max_compute_workgroups_per_dimension: Option<u32>,
+}
+
+impl LimitsProfile {
+    fn limits(&self) -> Limits {
+        match self {
+            Self::Default => Limits::default(),
+            Self::DownlevelWebgl2 => Limits::downlevel_webgl2_defaults(),
+            Self::Custom(custom) => {
+                let mut limits = Limits::downlevel_defaults();
+                if let Some(v) = custom.max_texture_dimension_2d {
+                    limits.max_texture_dimension_2d = v;
+                }
+                if let Some(v) = custom.max_bind_groups {
+                    limits.max_bind_groups = v;
+                }
+                if let Some(v) = custom.max_uniform_buffer_binding_size {
+                    limits.max_uniform_buffer_binding_size = v;
+                }
+                if let Some(v) = custom.max_storage_buffers_per_shader_stage {
+                    limits.max_storage_buffers_per_shader_stage = v;
+                }
+                if let Some(v) = custom.max_storage_buffer_binding_size {
+                    limits.max_storage_buffer_binding_size = v;
+                }
+                if let Some(v) = custom.max_compute_workgroups_per_dimension {
+                    limits.max_compute_workgroups_per_dimension = v;
+                }
+                limits
+            }
+        }
+    }
+
+    /// Limits to request from `adapter`: the profile, checked against what the adapter
+    /// offers. The GL backend always gets the webgl2 profile, and a profile the adapter
+    /// can't satisfy falls back to it too.
+    pub fn resolve(&self, adapter: &Adapter) -> Limits {
+        let adapter_limits = adapter.limits();
+        let profile = match (self, adapter.get_info().backend) {
+            (Self::Default, Backend::Gl) => &Self::DownlevelWebgl2,
+            (profile, _) => profile,
+        };
+        let wanted = profile.limits().using_resolution(adapter_limits.clone());
+        if wanted.check_limits(&adapter_limits) {
+            println!("Limits profile: {:?}", profile);
+            wanted
+        } else {
+            println!(
+                "Limits profile {:?} exceeds the adapter, falling back to DownlevelWebgl2",
+                profile
+            );
+            Limits::downlevel_webgl2_defaults().using_resolution(adapter_limits)
+        }
+    }
+}
+
+/// How much work subsystems may ask for under the granted limits.
+#[derive(Debug, Clone, Copy)]
+pub struct RenderBudget {
+    /// storage buffers are unavailable on webgl2, subsystems fall back to uniforms
+    pub storage_buffers: bool,
+    pub compute: bool,
+    pub max_storage_buffer_size: u64,
+    pub max_texture_size: u32,
+    pub max_lights: u32,
+}
+
+impl RenderBudget {
+    // one light is a vec4 position + vec4 color in a uniform array
+    const LIGHT_SIZE: u32 = 32;
+
+    pub fn from_limits(limits: &Limits) -> Self {
+        let storage_buffers = limits.max_storage_buffers_per_shader_stage > 0;
+        let compute = storage_buffers && limits.max_compute_workgroups_per_dimension > 0;
+        let max_lights = if storage_buffers {
+            1024
+        } else {
+            (limits.max_uniform_buffer_binding_size / Self::LIGHT_SIZE).min(16)
+        };
+        Self {
+            storage_buffers,
+            compute,
+            max_storage_buffer_size: if storage_buffers {
+                limits.max_storage_buffer_binding_size as u64
+            } else {
+                0
+            },
+            max_texture_size: limits.max_texture_dimension_2d,
+            max_lights,
+        }
+    }
+}
@@ -0,0 +1,425 @@
+use wgpu::{BindGroup, BindGroupLayout, Sampler};
+
+use crate::{
+    deferred::Deferred,
+    depth_buffer::DepthBuffer,
+    dynamic_uniforms::DynamicUniforms,
+    error,
+    gbuffer::GBuffer,
+    instance::InstanceRaw,
+    pipeline_cache::{PipelineCache, PipelineKey},
+    registry::{PipelineHandle, PipelineLayoutHandle, ResourceRegistry, ShaderHandle},
+    texture::ImageTexture,
+    tonemap::HDR_FORMAT,
+    vertex::{MeshVertex, Vertex},
+    wgsl_preprocessor,
+};
+
+/// The WGSL a material's pipeline is built from. Every material shader reads the scene
+/// bind group at 0, the camera at 1, its material's bind group
+/// (`Materials::bind_group_layout`) at 2 and the light at 3, and writes the display pass's
+/// targets. It declares `override alpha_blend: bool`, set for blended materials, and a
+/// `deferred_fs` entry point writing the deferred geometry pass's targets with
+/// `override shading: u32` set to the shader's discriminant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaterialShader {
+    // asset/mesh.wgsl, sun and ambient light on a base color
+    Mesh = 0,
+    // asset/mesh.wgsl, Blinn-Phong from the sun and the point light (`light::Light`)
+    Lit = 1,
+    // asset/mesh.wgsl, glTF's metallic-roughness model under the sun and the point light
+    Pbr = 2,
+}
+
+impl MaterialShader {
+    // in discriminant order
+    const ALL: [Self; 3] = [Self::Mesh, Self::Lit, Self::Pbr];
+
+    fn source(self) -> error::Result<String> {
+        let file = match self {
+            Self::Mesh | Self::Lit | Self::Pbr => "mesh.wgsl",
+        };
+        wgsl_preprocessor::code(file, &[])
+    }
+
+    fn entry_points(self) -> (&'static str, &'static str) {
+        match self {
+            Self::Mesh => ("mesh_vs", "mesh_fs"),
+            Self::Lit => ("mesh_vs", "lit_fs"),
+            Self::Pbr => ("mesh_vs", "pbr_fs"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Blend {
+    Opaque,
+    // over what's behind by alpha; drawn after the opaque meshes, without depth writes,
+    // and leaves the G-buffer to what's behind
+    Alpha,
+}
+
+/// The factors of a material, as glTF's metallic-roughness material has them. Each one
+/// scales what its texture holds, so without a texture the factor is the value.
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct MaterialParams {
+    /// linear, alpha is coverage for blended materials
+    pub base_color: [f32; 4],
+    /// linear, added on top of the lighting
+    pub emissive: [f32; 3],
+    pub metallic: f32,
+    pub roughness: f32,
+    pub normal_scale: f32,
+    /// how much of the ambient light the occlusion texture takes away, 0 to 1
+    pub occlusion_strength: f32,
+    /// texels with less alpha are cut out, 0 for none
+    pub alpha_cutoff: f32,
+}
+
+impl Default for MaterialParams {
+    fn default() -> Self {
+        Self {
+            base_color: [1.0; 4],
+            emissive: [0.0; 3],
+            metallic: 0.0,
+            roughness: 1.0,
+            normal_scale: 1.0,
+            occlusion_strength: 1.0,
+            alpha_cutoff: 0.0,
+        }
+    }
+}
+
+/// The textures of a material; a slot left `None` reads a 1x1 default that leaves its
+/// factor as it is (white, or a flat normal).
+#[derive(Default)]
+pub struct MaterialTextures {
+    /// sRGB
+    pub base_color: Option<ImageTexture>,
+    /// linear, roughness in g and metallic in b
+    pub metallic_roughness: Option<ImageTexture>,
+    /// linear, tangent space
+    pub normal: Option<ImageTexture>,
+    /// linear, in r
+    pub occlusion: Option<ImageTexture>,
+}
+
+impl MaterialTextures {
+    fn is_empty(&self) -> bool {
+        self.base_color.is_none()
+            && self.metallic_roughness.is_none()
+            && self.normal.is_none()
+            && self.occlusion.is_none()
+    }
+}
+
+/// What a material is made of. Materials sharing a shader, blend and cull mode share a
+/// pipeline, the `PipelineCache` builds it once.
+pub struct MaterialDesc {
+    /// labels its bind group, when it has textures of its own
+    pub name: String,
+    pub shader: MaterialShader,
+    pub params: MaterialParams,
+    pub textures: MaterialTextures,
+    pub blend: Blend,
+    // None for double sided
+    pub cull_mode: Option<wgpu::Face>,
+}
+
+pub struct Material {
+    // in the factory's registry
+    pub pipeline: PipelineHandle,
+    pub blend: Blend,
+    // into `Materials::bind_groups`
+    bind_group: usize,
+}
+
+/// Every material of the meshes, and the pipelines they draw with, built through the
+/// factory's `PipelineCache` the first time a material needs one. On the deferred path the opaque materials' pipelines draw the
+/// geometry pass instead of shading. Their `MaterialParams` share one buffer, each
+/// material's at its own dynamic offset, so the materials without textures share a bind
+/// group too.
+pub struct Materials {
+    /// `MaterialParams` at binding 0 with a dynamic offset, the sampler at 1, then the
+    /// textures of `MaterialTextures` in its order from 2 to 5
+    pub bind_group_layout: BindGroupLayout,
+    pipeline_layout: PipelineLayoutHandle,
+    deferred: bool,
+    sampler: Sampler,
+    // what the empty slots of `MaterialTextures` read
+    white_srgb: ImageTexture,
+    white_linear: ImageTexture,
+    flat_normal: ImageTexture,
+    // indexed by `MaterialShader`
+    shaders: Vec<ShaderHandle>,
+    // indexed like `materials`
+    params: DynamicUniforms<MaterialParams>,
+    // textures and the bind group over them and `params`; the first has none and is
+    // shared by every material without textures
+    bind_groups: Vec<(String, MaterialTextures, BindGroup)>,
+    pub materials: Vec<Material>,
+}
+
+impl Materials {
+    /// Opaque white with the mesh shader, material 0 of every `Materials`.
+    pub const DEFAULT: usize = 0;
+
+    /// `group_layouts` are the scene's, the camera's and the light's, for groups 0, 1
+    /// and 3.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        registry: &mut ResourceRegistry,
+        cache: &mut PipelineCache,
+        group_layouts: [&BindGroupLayout; 3],
+        deferred: bool,
+    ) -> error::Result<Self> {
+        let bind_group_layout = Self::create_bind_group_layout(device);
+        let [scene_layout, camera_layout, light_layout] = group_layouts;
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("material pipeline layout"),
+            bind_group_layouts: &[
+                scene_layout,
+                camera_layout,
+                &bind_group_layout,
+                light_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+        // the shaders share their source, the cache builds it once
+        let shaders = MaterialShader::ALL
+            .iter()
+            .map(|shader| Ok(cache.shader(device, registry, "material shader", shader.source()?)))
+            .collect::<error::Result<_>>()?;
+        let solid = |label, format, texel| ImageTexture::solid(device, queue, label, format, texel);
+        let mut materials = Self {
+            bind_group_layout,
+            pipeline_layout: registry.add(pipeline_layout),
+            deferred,
+            sampler: device.create_sampler(&wgpu::SamplerDescriptor {
+                label: Some("material sampler"),
+                address_mode_u: wgpu::AddressMode::Repeat,
+                address_mode_v: wgpu::AddressMode::Repeat,
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                ..Default::default()
+            }),
+            white_srgb: solid(
+                "white texture",
+                wgpu::TextureFormat::Rgba8UnormSrgb,
+                [255; 4],
+            ),
+            white_linear: solid("white texture", wgpu::TextureFormat::Rgba8Unorm, [255; 4]),
+            flat_normal: solid(
+                "flat normal texture",
+                wgpu::TextureFormat::Rgba8Unorm,
+                [128, 128, 255, 255],
+            ),
+            shaders,
+            params: DynamicUniforms::new(device, "material params buffer", 16),
+            bind_groups: vec![],
+            materials: vec![],
+        };
+        let label = "untextured materials".to_string();
+        let untextured = materials.create_bind_group(device, &label, &MaterialTextures::default());
+        materials
+            .bind_groups
+            .push((label, MaterialTextures::default(), untextured));
+        materials.add(
+            device,
+            queue,
+            registry,
+            cache,
+            MaterialDesc {
+                name: "default".to_string(),
+                shader: MaterialShader::Mesh,
+                params: MaterialParams::default(),
+                textures: MaterialTextures::default(),
+                blend: Blend::Opaque,
+                cull_mode: Some(wgpu::Face::Back),
+            },
+        );
+        Ok(materials)
+    }
+
+    /// Returns the new material's index, for `GpuFactory::add_mesh`.
+    pub fn add(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        registry: &mut ResourceRegistry,
+        cache: &mut PipelineCache,
+        desc: MaterialDesc,
+    ) -> usize {
+        let pipeline = cache.render_pipeline(
+            device,
+            registry,
+            "material pipeline",
+            self.pipeline_key(desc.shader, desc.blend, desc.cull_mode),
+        );
+        let (_, grown) = self.params.push(device, queue, desc.params);
+        if grown {
+            for i in 0..self.bind_groups.len() {
+                let (label, textures, _) = &self.bind_groups[i];
+                self.bind_groups[i].2 = self.create_bind_group(device, label, textures);
+            }
+        }
+        let bind_group = match desc.textures.is_empty() {
+            true => 0,
+            false => {
+                let bind_group = self.create_bind_group(device, &desc.name, &desc.textures);
+                self.bind_groups
+                    .push((desc.name, desc.textures, bind_group));
+                self.bind_groups.len() - 1
+            }
+        };
+        self.materials.push(Material {
+            pipeline,
+            blend: desc.blend,
+            bind_group,
+        });
+        self.materials.len() - 1
+    }
+
+    pub fn pipeline(&self, material: usize) -> PipelineHandle {
+        self.materials[material].pipeline
+    }
+
+    /// What a draw with `material` sets at group 2, with its dynamic offset.
+    pub fn bind_group(&self, material: usize) -> (&BindGroup, u32) {
+        let bind_group = &self.bind_groups[self.materials[material].bind_group].2;
+        (bind_group, self.params.offset(material))
+    }
+
+    /// Changes a material's factors from the next frame on.
+    // for embedding programs, the viewer's materials come from their files
+    pub fn write_params(&mut self, queue: &wgpu::Queue, material: usize, params: &MaterialParams) {
+        self.params.write(queue, material, params);
+    }
+
+    fn create_bind_group(
+        &self,
+        device: &wgpu::Device,
+        label: &str,
+        textures: &MaterialTextures,
+    ) -> BindGroup {
+        let views = [
+            textures.base_color.as_ref().unwrap_or(&self.white_srgb),
+            textures
+                .metallic_roughness
+                .as_ref()
+                .unwrap_or(&self.white_linear),
+            textures.normal.as_ref().unwrap_or(&self.flat_normal),
+            textures.occlusion.as_ref().unwrap_or(&self.white_linear),
+        ]
+        .map(|texture| &texture.view);
+        let mut entries = vec![
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: self.params.binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&self.sampler),
+            },
+        ];
+        entries.extend(
+            views
+                .iter()
+                .enumerate()
+                .map(|(i, view)| wgpu::BindGroupEntry {
+                    binding: 2 + i as u32,
+                    resource: wgpu::BindingResource::TextureView(view),
+                }),
+        );
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(label),
+            layout: &self.bind_group_layout,
+            entries: &entries,
+        })
+    }
+
+    fn create_bind_group_layout(device: &wgpu::Device) -> BindGroupLayout {
+        let mut entries = vec![
+            DynamicUniforms::<MaterialParams>::layout_entry(0, wgpu::ShaderStages::FRAGMENT),
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+        ];
+        entries.extend((2..6).map(|binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        }));
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("material bind group layout"),
+            entries: &entries,
+        })
+    }
+
+    fn pipeline_key(
+        &self,
+        shader: MaterialShader,
+        blend: Blend,
+        cull_mode: Option<wgpu::Face>,
+    ) -> PipelineKey {
+        let blended = blend == Blend::Alpha;
+        let deferred = self.deferred && !blended;
+        let (vertex_entry, fragment_entry) = match shader.entry_points() {
+            (vertex_entry, _) if deferred => (vertex_entry, "deferred_fs"),
+            entry_points => entry_points,
+        };
+        let (blend, gbuffer_writes) = if blended {
+            (
+                Some(wgpu::BlendState::ALPHA_BLENDING),
+                wgpu::ColorWrites::empty(),
+            )
+        } else {
+            (None, wgpu::ColorWrites::ALL)
+        };
+        let mut targets = vec![Some(wgpu::ColorTargetState {
+            format: HDR_FORMAT,
+            blend,
+            write_mask: wgpu::ColorWrites::ALL,
+        })];
+        targets.extend(GBuffer::color_targets().map(|target| {
+            target.map(|target| wgpu::ColorTargetState {
+                write_mask: gbuffer_writes,
+                ..target
+            })
+        }));
+        let mut constants = vec![("alpha_blend", blended as u32)];
+        if deferred {
+            targets.extend(Deferred::color_targets());
+            constants.push(("shading", shader as u32));
+        }
+        let mut depth_stencil = DepthBuffer::depth_stencil_state();
+        depth_stencil.depth_write_enabled = !blended;
+        PipelineKey {
+            layout: self.pipeline_layout,
+            shader: self.shaders[shader as usize],
+            vertex_entry,
+            vertex_buffers: vec![MeshVertex::layout(), InstanceRaw::layout()],
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode,
+                ..Default::default()
+            },
+            fragment_entry: Some(fragment_entry),
+            targets,
+            constants,
+            depth_stencil: Some(depth_stencil),
+            multisample: wgpu::MultisampleState::default(),
+        }
+    }
+}
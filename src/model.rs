@@ -0,0 +1,122 @@
+use std::path::Path;
+
+use anyhow::bail;
+use cgmath::{InnerSpace, Vector3};
+
+use crate::{
+    asset_loader::DecodedTexture,
+    gltf_import,
+    material::{Blend, MaterialParams},
+    obj_import,
+    vertex::MeshVertex,
+};
+
+/// One draw of a model file in world space, ready for `GpuFactory::add_mesh`: a glTF
+/// primitive placed by its node, or an OBJ group of one material. Static meshes only.
+pub struct ImportedMesh {
+    pub name: String,
+    pub vertices: Vec<MeshVertex>,
+    pub indices: Vec<u32>,
+    pub material: ImportedMaterial,
+}
+
+/// A mesh's material as its file has it, for `material::MaterialDesc`. The textures are
+/// decoded but not uploaded; their factors stay in `params`.
+pub struct ImportedMaterial {
+    pub params: MaterialParams,
+    /// sRGB
+    pub base_color: Option<DecodedTexture>,
+    /// linear, roughness in g and metallic in b
+    pub metallic_roughness: Option<DecodedTexture>,
+    /// linear
+    pub normal: Option<DecodedTexture>,
+    /// linear, in r
+    pub occlusion: Option<DecodedTexture>,
+    pub blend: Blend,
+    pub double_sided: bool,
+}
+
+/// Reads a model by its extension: .gltf and .glb, or .obj with its .mtl.
+pub fn import(path: &Path) -> anyhow::Result<Vec<ImportedMesh>> {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("gltf" | "glb") => gltf_import::import(path),
+        Some("obj") => obj_import::import(path),
+        _ => bail!("{} isn't a .gltf, .glb or .obj file", path.display()),
+    }
+}
+
+/// Vertex normals as the average of the faces around them, for files without normals.
+pub fn smooth_normals(positions: &[[f32; 3]], indices: &[u32]) -> Vec<[f32; 3]> {
+    let mut normals = vec![Vector3::new(0.0, 0.0, 0.0); positions.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| Vector3::from(positions[triangle[i] as usize]));
+        // area weighted
+        let face = (b - a).cross(c - a);
+        for &i in triangle {
+            normals[i as usize] += face;
+        }
+    }
+    normals
+        .into_iter()
+        .map(|normal| {
+            if normal.magnitude2() > 0.0 {
+                normal.normalize().into()
+            } else {
+                [0.0, 1.0, 0.0]
+            }
+        })
+        .collect()
+}
+
+/// Per-vertex tangents from the uv layout of the triangles around each vertex, for files
+/// without tangents: each triangle's u and v directions are summed at its corners, then
+/// the tangent is made perpendicular to the vertex normal. Vertices whose triangles have
+/// no usable uvs get any tangent perpendicular to the normal.
+pub fn generate_tangents(vertices: &mut [MeshVertex], indices: &[u32]) {
+    let zero = Vector3::new(0.0, 0.0, 0.0);
+    let mut tangents = vec![zero; vertices.len()];
+    let mut bitangents = vec![zero; vertices.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| &vertices[triangle[i] as usize]);
+        let edge1 = Vector3::from(b.position) - Vector3::from(a.position);
+        let edge2 = Vector3::from(c.position) - Vector3::from(a.position);
+        let (du1, dv1) = (b.uv[0] - a.uv[0], b.uv[1] - a.uv[1]);
+        let (du2, dv2) = (c.uv[0] - a.uv[0], c.uv[1] - a.uv[1]);
+        let det = du1 * dv2 - du2 * dv1;
+        if det.abs() < f32::EPSILON {
+            continue;
+        }
+        // not normalized, so larger triangles weigh more
+        let tangent = (edge1 * dv2 - edge2 * dv1) / det;
+        let bitangent = (edge2 * du1 - edge1 * du2) / det;
+        for &i in triangle {
+            tangents[i as usize] += tangent;
+            bitangents[i as usize] += bitangent;
+        }
+    }
+    for ((vertex, tangent), bitangent) in vertices.iter_mut().zip(tangents).zip(bitangents) {
+        let normal = Vector3::from(vertex.normal);
+        let mut tangent = tangent - normal * normal.dot(tangent);
+        if tangent.magnitude2() < f32::EPSILON {
+            // any axis not along the normal
+            let axis = if normal.x.abs() < 0.9 {
+                Vector3::unit_x()
+            } else {
+                Vector3::unit_y()
+            };
+            tangent = axis - normal * normal.dot(axis);
+        }
+        let tangent = tangent.normalize();
+        // uvs mirrored across the triangle flip the bitangent
+        let w = if normal.cross(tangent).dot(bitangent) < 0.0 {
+            -1.0
+        } else {
+            1.0
+        };
+        vertex.tangent = [tangent.x, tangent.y, tangent.z, w];
+    }
+}
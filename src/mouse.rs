@@ -0,0 +1,65 @@
+/// The pointer as Shadertoy's iMouse has it, for shaders that react to it. In window
+/// pixels from the top left, like a fragment's position:
+///
+///     xy  where the pointer is while the left button is held, where it was let go after
+///     z   x of the last click, negative once the button is up again
+///     w   y of the last click, negative after the first frame drawn with it
+///
+/// All zero until the first click.
+#[derive(Debug, Default)]
+pub struct Mouse {
+    // None outside the window
+    cursor: Option<[f32; 2]>,
+    held: bool,
+    drag: [f32; 2],
+    click: [f32; 2],
+    // the click hasn't been drawn yet
+    clicked: bool,
+}
+
+impl Mouse {
+    /// Whether it moved iMouse, which it only does with the button held.
+    pub fn moved(&mut self, x: f64, y: f64) -> bool {
+        let cursor = [x as f32, y as f32];
+        self.cursor = Some(cursor);
+        if self.held {
+            self.drag = cursor;
+        }
+        self.held
+    }
+
+    pub fn left_window(&mut self) {
+        self.cursor = None;
+    }
+
+    /// The left button going down or up, returning whether iMouse changed.
+    pub fn button(&mut self, pressed: bool) -> bool {
+        if pressed == self.held {
+            return false;
+        }
+        self.held = pressed;
+        if pressed {
+            let cursor = self.cursor.unwrap_or(self.drag);
+            self.drag = cursor;
+            self.click = cursor;
+            self.clicked = true;
+        }
+        true
+    }
+
+    /// After a frame is drawn, which has shown the click if there was one.
+    pub fn end_frame(&mut self) {
+        self.clicked = false;
+    }
+
+    /// iMouse.
+    pub fn uniform(&self) -> [f32; 4] {
+        let [x, y] = self.click;
+        [
+            self.drag[0],
+            self.drag[1],
+            if self.held { x } else { -x },
+            if self.clicked { y } else { -y },
+        ]
+    }
+}
@@ -0,0 +1,107 @@
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::{
+    material::{Blend, MaterialParams},
+    model::{self, ImportedMaterial, ImportedMesh},
+    texture,
+    vertex::MeshVertex,
+};
+
+/// Reads an .obj and the .mtl files it names. tobj splits every object or group at
+/// material changes, so each mesh that comes back draws with one material: its diffuse
+/// color (`Kd`) and dissolve (`d`) as the base color, times its diffuse map (`map_Kd`)
+/// when it has one.
+pub fn import(path: &Path) -> anyhow::Result<Vec<ImportedMesh>> {
+    let (models, materials) = tobj::load_obj(
+        path,
+        &tobj::LoadOptions {
+            triangulate: true,
+            // one index for position, normal and uv, like the vertex buffers want
+            single_index: true,
+            ..Default::default()
+        },
+    )
+    .with_context(|| format!("can't import {}", path.display()))?;
+    let materials = materials.unwrap_or_else(|e| {
+        println!("No materials for {}: {}", path.display(), e);
+        vec![]
+    });
+    // texture paths in the .mtl are relative to it, which sits next to the .obj
+    let directory = path.parent().unwrap_or(Path::new(""));
+
+    let mut meshes = vec![];
+    for obj_model in models {
+        let mesh = obj_model.mesh;
+        let positions: Vec<[f32; 3]> = mesh
+            .positions
+            .chunks_exact(3)
+            .map(|p| [p[0], p[1], p[2]])
+            .collect();
+        let normals: Vec<[f32; 3]> = if mesh.normals.len() == mesh.positions.len() {
+            mesh.normals
+                .chunks_exact(3)
+                .map(|n| [n[0], n[1], n[2]])
+                .collect()
+        } else {
+            model::smooth_normals(&positions, &mesh.indices)
+        };
+        let mut vertices: Vec<MeshVertex> = positions
+            .iter()
+            .zip(&normals)
+            .enumerate()
+            .map(|(i, (&position, &normal))| MeshVertex {
+                position,
+                normal,
+                // OBJ's v goes up from the bottom of the image
+                uv: mesh
+                    .texcoords
+                    .get(i * 2..i * 2 + 2)
+                    .map_or([0.0; 2], |uv| [uv[0], 1.0 - uv[1]]),
+                tangent: [0.0; 4],
+            })
+            .collect();
+        model::generate_tangents(&mut vertices, &mesh.indices);
+
+        let material = mesh.material_id.and_then(|id| materials.get(id));
+        let diffuse = material
+            .and_then(|material| material.diffuse)
+            .unwrap_or([1.0; 3]);
+        // `d`, 1 is opaque
+        let dissolve = material
+            .and_then(|material| material.dissolve)
+            .unwrap_or(1.0);
+        let map = material.and_then(|material| material.diffuse_texture.as_ref());
+        let base_color = match map.map(|map| texture::decode(&directory.join(map), true)) {
+            Some(Ok(decoded)) => Some(decoded),
+            Some(Err(e)) => {
+                println!("{} left without its texture: {:#}", obj_model.name, e);
+                None
+            }
+            None => None,
+        };
+        meshes.push(ImportedMesh {
+            name: obj_model.name,
+            vertices,
+            indices: mesh.indices,
+            material: ImportedMaterial {
+                params: MaterialParams {
+                    base_color: [diffuse[0], diffuse[1], diffuse[2], dissolve],
+                    ..Default::default()
+                },
+                base_color,
+                metallic_roughness: None,
+                normal: None,
+                occlusion: None,
+                blend: if dissolve < 1.0 {
+                    Blend::Alpha
+                } else {
+                    Blend::Opaque
+                },
+                double_sided: false,
+            },
+        });
+    }
+    Ok(meshes)
+}
@@ -0,0 +1,345 @@
+use std::cell::Cell;
+
+use serde::{Deserialize, Serialize};
+use wgpu::{BindGroup, Buffer, ComputePipeline, PipelineCompilationOptions, RenderPipeline};
+
+#[cfg(feature = "ui")]
+use crate::console::{console_var, Console};
+use crate::{
+    compute::{self, ComputeBinding},
+    depth_buffer::DepthBuffer,
+    frame_stats::{self, CountedPass},
+    hot_reload::wgsl,
+    tonemap::HDR_FORMAT,
+    vertex::Vertex,
+    GfxState,
+};
+
+/// A fountain of glowing particles thrown up from one point and pulled down by gravity.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ParticleSettings {
+    pub enabled: bool,
+    /// world position the particles are thrown from
+    pub emitter: [f32; 3],
+    /// particles per second; past what the buffer holds the oldest are replaced early
+    pub spawn_rate: f32,
+    /// seconds, the longest a particle lives; each lives between half of it and all of it
+    pub lifetime: f32,
+    /// m/s², world space
+    pub gravity: [f32; 3],
+    /// m/s, up from the emitter
+    pub speed: f32,
+    /// how far from straight up they're thrown, 1 = up to 45 degrees
+    pub spread: f32,
+    /// half size of a particle's billboard, meters
+    pub size: f32,
+    /// linear
+    pub color: [f32; 3],
+    /// HDR multiplier on the color
+    pub intensity: f32,
+}
+
+impl Default for ParticleSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            emitter: [0.0, 0.5, -4.0],
+            spawn_rate: 800.0,
+            lifetime: 2.5,
+            gravity: [0.0, -9.8, 0.0],
+            speed: 6.0,
+            spread: 0.3,
+            size: 0.03,
+            color: [1.0, 0.55, 0.2],
+            intensity: 4.0,
+        }
+    }
+}
+
+impl ParticleSettings {
+    #[cfg(feature = "ui")]
+    pub fn register_console(console: &mut Console) {
+        console_var!(console, "particles", "bool", scene.particles.enabled);
+        console_var!(
+            console,
+            "particles.spawn_rate",
+            "per second",
+            scene.particles.spawn_rate
+        );
+        console_var!(
+            console,
+            "particles.lifetime",
+            "seconds",
+            scene.particles.lifetime
+        );
+        console_var!(console, "particles.speed", "m/s", scene.particles.speed);
+        console_var!(
+            console,
+            "particles.spread",
+            "0 .. 1",
+            scene.particles.spread
+        );
+        console_var!(
+            console,
+            "particles.intensity",
+            "",
+            scene.particles.intensity
+        );
+    }
+}
+
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct ParticleParams {
+    emitter: [f32; 3],
+    dt: f32,
+    gravity: [f32; 3],
+    lifetime: f32,
+    color: [f32; 3],
+    size: f32,
+    emit_start: u32,
+    emit_count: u32,
+    particle_count: u32,
+    seed: u32,
+    speed: f32,
+    spread: f32,
+    time: f32,
+    _pad: f32,
+}
+
+/// One particle as the update writes it and the billboards read it, per instance. Dead
+/// once `age` reaches `lifetime`, so the zeroed buffer starts with none alive.
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct Particle {
+    position: [f32; 3],
+    age: f32,
+    velocity: [f32; 3],
+    lifetime: f32,
+}
+
+impl Vertex for Particle {
+    const ATTRIBUTES: &'static [wgpu::VertexAttribute] = &wgpu::vertex_attr_array![
+        0 => Float32x4,
+        1 => Float32x4,
+    ];
+    const STEP_MODE: wgpu::VertexStepMode = wgpu::VertexStepMode::Instance;
+}
+
+const MAX_PARTICLES: u32 = 16384;
+
+/// GPU particles: a compute pass emits new ones into a ring buffer, integrates the living
+/// and ages them out, then the same buffer feeds camera facing billboards as instances,
+/// added onto the scene and tested against its depth.
+pub struct Particles {
+    particle_count: u32,
+    params_buffer: Buffer,
+    particle_buffer: Buffer,
+    uniforms_bind_group: BindGroup,
+    compute_bind_group: BindGroup,
+    update_pipeline: ComputePipeline,
+    render_pipeline: RenderPipeline,
+    // where the next emitted particle goes
+    emit_cursor: Cell<u32>,
+    // fractions of a particle owed from earlier frames
+    spawn_debt: Cell<f32>,
+}
+
+impl Particles {
+    /// None when the limits don't give us compute and storage buffers.
+    pub fn new(app: &GfxState, camera_buffer: &Buffer) -> Option<Self> {
+        if !app.gpu.budget.compute {
+            println!("Particles disabled: no compute support");
+            return None;
+        }
+        let device = &app.gpu.device;
+        let particle_size = std::mem::size_of::<Particle>() as u64;
+        let particle_count = MAX_PARTICLES.min(
+            (app.gpu.budget.max_storage_buffer_size / particle_size).min(u32::MAX as u64) as u32,
+        );
+
+        let code = wgsl!("particles.wgsl");
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("particle params"),
+            size: std::mem::size_of::<ParticleParams>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let particle_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("particles"),
+            size: particle_count as u64 * particle_size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::VERTEX,
+            mapped_at_creation: false,
+        });
+
+        let uniforms_layout = compute::bind_group_layout(
+            device,
+            "particle uniforms layout",
+            wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT | wgpu::ShaderStages::COMPUTE,
+            &[ComputeBinding::Uniform, ComputeBinding::Uniform],
+        );
+        let compute_layout = compute::bind_group_layout(
+            device,
+            "particle compute layout",
+            wgpu::ShaderStages::COMPUTE,
+            &[ComputeBinding::StorageBuffer { read_only: false }],
+        );
+        let uniforms_bind_group = compute::bind_group(
+            device,
+            "particle uniforms",
+            &uniforms_layout,
+            &[
+                camera_buffer.as_entire_binding(),
+                params_buffer.as_entire_binding(),
+            ],
+        );
+        let compute_bind_group = compute::bind_group(
+            device,
+            "particles rw",
+            &compute_layout,
+            &[particle_buffer.as_entire_binding()],
+        );
+        let update_pipeline = compute::create_pipeline(
+            device,
+            "particle update",
+            &code,
+            "update",
+            &[&uniforms_layout, &compute_layout],
+        );
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("particle shader"),
+            source: wgpu::ShaderSource::Wgsl(code),
+        });
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("particle render pipeline layout"),
+                bind_group_layouts: &[&uniforms_layout],
+                push_constant_ranges: &[],
+            });
+        let additive = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        };
+        // seen behind the scene's surfaces, not hiding each other
+        let mut depth_stencil = DepthBuffer::depth_stencil_state();
+        depth_stencil.depth_write_enabled = false;
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("particle billboards"),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "particle_vs",
+                buffers: &[Particle::layout()],
+                compilation_options: PipelineCompilationOptions::default(),
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "particle_fs",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: HDR_FORMAT,
+                    blend: Some(wgpu::BlendState {
+                        color: additive,
+                        alpha: additive,
+                    }),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: PipelineCompilationOptions::default(),
+            }),
+            depth_stencil: Some(depth_stencil),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Some(Self {
+            particle_count,
+            params_buffer,
+            particle_buffer,
+            uniforms_bind_group,
+            compute_bind_group,
+            update_pipeline,
+            render_pipeline,
+            emit_cursor: Cell::new(0),
+            spawn_debt: Cell::new(0.0),
+        })
+    }
+
+    /// This frame's parameters, taking the particles it emits off the spawn debt.
+    fn params(&self, app: &GfxState) -> ParticleParams {
+        let settings = &app.scene.particles;
+        let dt = app.frame.dt;
+        let owed = self.spawn_debt.get() + settings.spawn_rate.max(0.0) * dt;
+        let emit_count = (owed.floor() as u32).min(self.particle_count);
+        self.spawn_debt.set(owed.fract());
+        let emit_start = self.emit_cursor.get();
+        self.emit_cursor
+            .set((emit_start + emit_count) % self.particle_count);
+        ParticleParams {
+            emitter: settings.emitter,
+            dt,
+            gravity: settings.gravity,
+            lifetime: settings.lifetime,
+            color: settings.color.map(|c| c * settings.intensity),
+            size: settings.size,
+            emit_start,
+            emit_count,
+            particle_count: self.particle_count,
+            seed: app.config.deterministic.seed,
+            speed: settings.speed,
+            spread: settings.spread,
+            time: app.frame.time,
+            _pad: 0.0,
+        }
+    }
+
+    /// Steps the particles and adds them onto `target`, behind what `depth_buffer` holds.
+    pub fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        app: &GfxState,
+        target: &wgpu::TextureView,
+        depth_buffer: &DepthBuffer,
+    ) {
+        let params = self.params(app);
+        frame_stats::write_buffer(
+            &app.gpu.queue,
+            &self.params_buffer,
+            0,
+            bytemuck::bytes_of(&params),
+        );
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("particle update"),
+                timestamp_writes: None,
+            });
+            compute_pass.set_pipeline(&self.update_pipeline);
+            compute_pass.set_bind_group(0, &self.uniforms_bind_group, &[]);
+            compute_pass.set_bind_group(1, &self.compute_bind_group, &[]);
+            compute_pass.dispatch_workgroups(self.particle_count.div_ceil(64), 1, 1);
+        }
+
+        let mut render_pass = CountedPass::begin(
+            encoder,
+            &wgpu::RenderPassDescriptor {
+                label: Some("particle pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(depth_buffer.load_attachment()),
+                ..Default::default()
+            },
+        );
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.uniforms_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.particle_buffer.slice(..));
+        render_pass.draw(0..6, 0..self.particle_count);
+    }
+}
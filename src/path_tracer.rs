@@ -0,0 +1,487 @@
+use std::{borrow::Cow, cell::Cell, hash::Hash};
+
+use serde::{Deserialize, Serialize};
+use wgpu::{
+    util::DeviceExt, BindGroup, BindGroupLayout, Buffer, ComputePipeline,
+    PipelineCompilationOptions, Sampler, Texture, TextureView,
+};
+
+use crate::{
+    accumulation::{AccumulationSettings, Accumulator},
+    bvh::{Bvh, BvhNode},
+    denoise::{DenoiseSettings, Denoiser},
+    error, frame_stats,
+    static_geometry::{self, StaticBox, Triangle},
+    tonemap::HDR_FORMAT,
+    wgsl_preprocessor, GfxState,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PathTracerSettings {
+    /// paths per pixel each frame
+    pub samples_per_frame: u32,
+    /// diffuse bounces after the first hit
+    pub max_bounces: u32,
+    pub accumulation: AccumulationSettings,
+    pub denoise: DenoiseSettings,
+    /// fixed when the renderer is built
+    pub spheres: Vec<TracedSphere>,
+}
+
+impl Default for PathTracerSettings {
+    fn default() -> Self {
+        Self {
+            samples_per_frame: 1,
+            max_bounces: 4,
+            accumulation: AccumulationSettings::default(),
+            denoise: DenoiseSettings::default(),
+            spheres: vec![],
+        }
+    }
+}
+
+/// A diffuse sphere only the path tracer sees, next to the static boxes' triangles. One
+/// that glows lights the scene by the paths that happen to hit it, slowly converging.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TracedSphere {
+    pub center: [f32; 3],
+    pub radius: f32,
+    /// sRGB
+    pub color: [f32; 3],
+    /// linear radiance it gives off, black for none
+    pub emission: [f32; 3],
+}
+
+impl Default for TracedSphere {
+    fn default() -> Self {
+        Self {
+            center: [0.0, 1.0, 0.0],
+            radius: 1.0,
+            color: [0.6, 0.6, 0.6],
+            emission: [0.0; 3],
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct SphereData {
+    center_radius: [f32; 4],
+    // linear, w unused
+    albedo: [f32; 4],
+    emission: [f32; 4],
+}
+
+impl TracedSphere {
+    fn data(&self) -> SphereData {
+        let [r, g, b] = self.color.map(static_geometry::srgb_to_linear);
+        let [x, y, z] = self.center;
+        let [er, eg, eb] = self.emission;
+        SphereData {
+            center_radius: [x, y, z, self.radius.max(0.0)],
+            albedo: [r, g, b, 1.0],
+            emission: [er, eg, eb, 0.0],
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct PathTracerParams {
+    width: u32,
+    height: u32,
+    frame: u32,
+    samples: u32,
+    max_bounces: u32,
+    seed: u32,
+    sphere_count: u32,
+    _pad: u32,
+}
+
+/// Alternative to the ray cast display pass: one compute kernel follows whole diffuse
+/// paths through the ground, a BVH over the static geometry's triangles and the traced
+/// spheres, escaping into the environment map. Its output is copied into the HDR target
+/// so everything after the display pass, the tonemap blit included, works unchanged. The
+/// first hit's albedo, normal and depth are written alongside to guide the denoiser,
+/// which runs on the average of the frames since the view last changed.
+pub struct PathTracer {
+    params_buffer: Buffer,
+    // only reached through the bind group, kept so they live as long as it does
+    triangle_buffer: Buffer,
+    node_buffer: Buffer,
+    sphere_buffer: Buffer,
+    sphere_count: u32,
+    output: Texture,
+    albedo: Texture,
+    normal_depth: Texture,
+    accumulator: Accumulator,
+    denoiser: Denoiser,
+    sampler: Sampler,
+    bind_group_layout: BindGroupLayout,
+    bind_group: BindGroup,
+    pipeline: ComputePipeline,
+    frame: Cell<u32>,
+}
+
+impl PathTracer {
+    /// None when the limits don't give us compute and storage buffers.
+    pub fn new(
+        app: &GfxState,
+        camera_buffer: &Buffer,
+        sky_buffer: &Buffer,
+        environment_view: &TextureView,
+        boxes: &[StaticBox],
+        spheres: &[TracedSphere],
+    ) -> error::Result<Option<Self>> {
+        if !app.gpu.budget.compute {
+            println!("Path tracer disabled: no compute support");
+            return Ok(None);
+        }
+        let device = &app.gpu.device;
+        let code = wgsl_preprocessor::code("path_tracer.wgsl", &[])?;
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("path tracer shader"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Owned(code)),
+        });
+
+        let Bvh { nodes, triangles } = Bvh::build(static_geometry::triangles(boxes));
+        println!(
+            "Path tracer BVH: {} triangles, {} nodes",
+            triangles.len(),
+            nodes.len()
+        );
+        let node_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("path tracer bvh nodes"),
+            contents: bytemuck::cast_slice::<BvhNode, u8>(&nodes),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let triangle_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("path tracer triangles"),
+            contents: bytemuck::cast_slice::<Triangle, u8>(&triangles),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let mut sphere_data: Vec<SphereData> = spheres.iter().map(TracedSphere::data).collect();
+        // a buffer can't be empty, the shader only reads `sphere_count` of them
+        if sphere_data.is_empty() {
+            sphere_data.push(TracedSphere::default().data());
+        }
+        let sphere_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("path tracer spheres"),
+            contents: bytemuck::cast_slice(&sphere_data),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("path tracer params"),
+            size: std::mem::size_of::<PathTracerParams>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let (width, height) = (
+            app.window.surface_config.width,
+            app.window.surface_config.height,
+        );
+        let [output, albedo, normal_depth] = Self::create_targets(device, width, height);
+        let accumulator = Accumulator::new(
+            device,
+            &output.create_view(&Default::default()),
+            width,
+            height,
+        );
+        let denoiser = Denoiser::new(
+            device,
+            &accumulator.result.create_view(&Default::default()),
+            &albedo.create_view(&Default::default()),
+            &normal_depth.create_view(&Default::default()),
+            width,
+            height,
+        );
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("path tracer environment sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let buffer_entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let uniform_entry = |binding| buffer_entry(binding, wgpu::BufferBindingType::Uniform);
+        let storage_entry = |binding| {
+            buffer_entry(
+                binding,
+                wgpu::BufferBindingType::Storage { read_only: true },
+            )
+        };
+        let storage_texture_entry = |binding, format| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::StorageTexture {
+                access: wgpu::StorageTextureAccess::WriteOnly,
+                format,
+                view_dimension: wgpu::TextureViewDimension::D2,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("path tracer bind group layout"),
+            entries: &[
+                uniform_entry(0),
+                uniform_entry(1),
+                uniform_entry(2),
+                storage_entry(3),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                storage_texture_entry(6, HDR_FORMAT),
+                storage_entry(7),
+                storage_texture_entry(8, wgpu::TextureFormat::Rgba8Unorm),
+                storage_texture_entry(9, HDR_FORMAT),
+                storage_entry(10),
+            ],
+        });
+        let bind_group = Self::create_bind_group(
+            device,
+            &bind_group_layout,
+            [camera_buffer, sky_buffer, &params_buffer, &triangle_buffer],
+            environment_view,
+            &sampler,
+            [&output, &albedo, &normal_depth],
+            [&node_buffer, &sphere_buffer],
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("path tracer pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("path tracer pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "trace_paths",
+            compilation_options: PipelineCompilationOptions::default(),
+        });
+
+        Ok(Some(Self {
+            params_buffer,
+            triangle_buffer,
+            node_buffer,
+            sphere_buffer,
+            sphere_count: spheres.len() as u32,
+            output,
+            albedo,
+            normal_depth,
+            accumulator,
+            denoiser,
+            sampler,
+            bind_group_layout,
+            bind_group,
+            pipeline,
+            frame: Cell::new(0),
+        }))
+    }
+
+    /// Radiance, first hit albedo and first hit normal + distance.
+    fn create_targets(device: &wgpu::Device, width: u32, height: u32) -> [Texture; 3] {
+        [
+            ("path tracer output", HDR_FORMAT),
+            ("path tracer albedo", wgpu::TextureFormat::Rgba8Unorm),
+            ("path tracer normal depth", HDR_FORMAT),
+        ]
+        .map(|(label, format)| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width: width.max(1),
+                    height: height.max(1),
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::STORAGE_BINDING
+                    | wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_SRC,
+                view_formats: &[],
+            })
+        })
+    }
+
+    /// `buffers` are camera, sky, params and triangles, in binding order, `storage` the BVH
+    /// nodes and the spheres.
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &BindGroupLayout,
+        buffers: [&Buffer; 4],
+        environment_view: &TextureView,
+        sampler: &Sampler,
+        targets: [&Texture; 3],
+        storage: [&Buffer; 2],
+    ) -> BindGroup {
+        let [node_buffer, sphere_buffer] = storage;
+        let [output_view, albedo_view, normal_depth_view] =
+            targets.map(|target| target.create_view(&wgpu::TextureViewDescriptor::default()));
+        let mut entries: Vec<wgpu::BindGroupEntry> = buffers
+            .iter()
+            .enumerate()
+            .map(|(binding, buffer)| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource: buffer.as_entire_binding(),
+            })
+            .collect();
+        entries.extend([
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: wgpu::BindingResource::TextureView(environment_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 5,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 6,
+                resource: wgpu::BindingResource::TextureView(&output_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 7,
+                resource: node_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 8,
+                resource: wgpu::BindingResource::TextureView(&albedo_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 9,
+                resource: wgpu::BindingResource::TextureView(&normal_depth_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 10,
+                resource: sphere_buffer.as_entire_binding(),
+            },
+        ]);
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("path tracer bind group"),
+            layout,
+            entries: &entries,
+        })
+    }
+
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        camera_buffer: &Buffer,
+        sky_buffer: &Buffer,
+        environment_view: &TextureView,
+        width: u32,
+        height: u32,
+    ) {
+        [self.output, self.albedo, self.normal_depth] = Self::create_targets(device, width, height);
+        self.accumulator.resize(
+            device,
+            &self.output.create_view(&Default::default()),
+            width,
+            height,
+        );
+        self.denoiser.resize(
+            device,
+            &self.accumulator.result.create_view(&Default::default()),
+            &self.albedo.create_view(&Default::default()),
+            &self.normal_depth.create_view(&Default::default()),
+            width,
+            height,
+        );
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.bind_group_layout,
+            [
+                camera_buffer,
+                sky_buffer,
+                &self.params_buffer,
+                &self.triangle_buffer,
+            ],
+            environment_view,
+            &self.sampler,
+            [&self.output, &self.albedo, &self.normal_depth],
+            [&self.node_buffer, &self.sphere_buffer],
+        );
+    }
+
+    /// Drops the accumulated frames, for when the tracer comes back after being off.
+    pub fn reset_accumulation(&self) {
+        self.accumulator.reset();
+    }
+
+    /// Traces a frame, folds it into the accumulated average, denoises that if enabled and
+    /// copies the result into `target`, which must match the output's size. `view_key`
+    /// covers everything the image depends on, see Accumulator::encode. `seed` offsets the
+    /// random sequence, see `DeterministicConfig`.
+    pub fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        queue: &wgpu::Queue,
+        settings: &PathTracerSettings,
+        seed: u32,
+        view_key: impl Hash,
+        target: &Texture,
+    ) {
+        let (width, height) = (self.output.width(), self.output.height());
+        let frame = self.frame.get();
+        self.frame.set(frame.wrapping_add(1));
+        let params = PathTracerParams {
+            width,
+            height,
+            frame,
+            samples: settings.samples_per_frame.max(1),
+            max_bounces: settings.max_bounces,
+            seed,
+            sphere_count: self.sphere_count,
+            _pad: 0,
+        };
+        frame_stats::write_buffer(queue, &self.params_buffer, 0, bytemuck::bytes_of(&params));
+
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("path tracer pass"),
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &self.bind_group, &[]);
+            pass.dispatch_workgroups(width.div_ceil(8), height.div_ceil(8), 1);
+        }
+        self.accumulator
+            .encode(encoder, queue, &settings.accumulation, view_key);
+        let result = if settings.denoise.enabled {
+            self.denoiser.encode(encoder, queue, &settings.denoise)
+        } else {
+            &self.accumulator.result
+        };
+        encoder.copy_texture_to_texture(
+            result.as_image_copy(),
+            target.as_image_copy(),
+            result.size(),
+        );
+    }
+}
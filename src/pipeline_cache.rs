@@ -0,0 +1,184 @@
+use std::{borrow::Cow, collections::HashMap, hash::Hash};
+
+use wgpu::PipelineCompilationOptions;
+
+use crate::registry::{
+    BindGroupLayoutHandle, Handle, PipelineHandle, PipelineLayoutHandle, Registered,
+    ResourceRegistry, ShaderHandle,
+};
+
+/// Everything a render pipeline is built from, by the handles of what the cache already
+/// built, so two equal keys would build the same pipeline.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PipelineKey {
+    pub layout: PipelineLayoutHandle,
+    pub shader: ShaderHandle,
+    pub vertex_entry: &'static str,
+    pub vertex_buffers: Vec<wgpu::VertexBufferLayout<'static>>,
+    pub primitive: wgpu::PrimitiveState,
+    /// None without a fragment stage
+    pub fragment_entry: Option<&'static str>,
+    pub targets: Vec<Option<wgpu::ColorTargetState>>,
+    /// the fragment stage's `override`s by name, which are all bools and integers in the
+    /// shaders so far
+    pub constants: Vec<(&'static str, u32)>,
+    pub depth_stencil: Option<wgpu::DepthStencilState>,
+    pub multisample: wgpu::MultisampleState,
+}
+
+/// How often the cache had what was asked for, counted over every kind it keeps.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u32,
+    pub misses: u32,
+}
+
+/// Shaders, layouts and render pipelines by what they are built from, each built once
+/// into the registry and its handle given out to everything asking for an equal one. The
+/// first label asked for is the one it gets.
+#[derive(Default)]
+pub struct PipelineCache {
+    shaders: HashMap<String, ShaderHandle>,
+    bind_group_layouts: HashMap<Vec<wgpu::BindGroupLayoutEntry>, BindGroupLayoutHandle>,
+    pipeline_layouts:
+        HashMap<(Vec<BindGroupLayoutHandle>, Vec<wgpu::PushConstantRange>), PipelineLayoutHandle>,
+    pipelines: HashMap<PipelineKey, PipelineHandle>,
+    stats: CacheStats,
+}
+
+impl PipelineCache {
+    /// A WGSL module, told apart by its source.
+    pub fn shader(
+        &mut self,
+        device: &wgpu::Device,
+        registry: &mut ResourceRegistry,
+        label: &str,
+        code: String,
+    ) -> ShaderHandle {
+        Self::get_or_create(&mut self.stats, &mut self.shaders, code, registry, |code| {
+            device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some(label),
+                source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(code.as_str())),
+            })
+        })
+    }
+
+    pub fn bind_group_layout(
+        &mut self,
+        device: &wgpu::Device,
+        registry: &mut ResourceRegistry,
+        label: &str,
+        entries: &[wgpu::BindGroupLayoutEntry],
+    ) -> BindGroupLayoutHandle {
+        Self::get_or_create(
+            &mut self.stats,
+            &mut self.bind_group_layouts,
+            entries.to_vec(),
+            registry,
+            |entries| {
+                device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some(label),
+                    entries,
+                })
+            },
+        )
+    }
+
+    /// A pipeline layout of `bind_group_layouts` in group order. Any `push_constant_ranges`
+    /// need a device with Features::PUSH_CONSTANTS.
+    pub fn pipeline_layout(
+        &mut self,
+        device: &wgpu::Device,
+        registry: &mut ResourceRegistry,
+        label: &str,
+        bind_group_layouts: &[BindGroupLayoutHandle],
+        push_constant_ranges: &[wgpu::PushConstantRange],
+    ) -> PipelineLayoutHandle {
+        let key = (bind_group_layouts.to_vec(), push_constant_ranges.to_vec());
+        if let Some(&layout) = self.pipeline_layouts.get(&key) {
+            self.stats.hits += 1;
+            return layout;
+        }
+        let layouts = bind_group_layouts
+            .iter()
+            .map(|&layout| &registry[layout])
+            .collect::<Vec<_>>();
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(label),
+            bind_group_layouts: &layouts,
+            push_constant_ranges,
+        });
+        self.stats.misses += 1;
+        let layout = registry.add(layout);
+        self.pipeline_layouts.insert(key, layout);
+        layout
+    }
+
+    pub fn render_pipeline(
+        &mut self,
+        device: &wgpu::Device,
+        registry: &mut ResourceRegistry,
+        label: &str,
+        key: PipelineKey,
+    ) -> PipelineHandle {
+        if let Some(&pipeline) = self.pipelines.get(&key) {
+            self.stats.hits += 1;
+            return pipeline;
+        }
+        let shader = &registry[key.shader];
+        let constants = key
+            .constants
+            .iter()
+            .map(|&(name, value)| (name.to_string(), value as f64))
+            .collect();
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(&registry[key.layout]),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: key.vertex_entry,
+                buffers: &key.vertex_buffers,
+                compilation_options: PipelineCompilationOptions::default(),
+            },
+            primitive: key.primitive,
+            fragment: key.fragment_entry.map(|entry_point| wgpu::FragmentState {
+                module: shader,
+                entry_point,
+                targets: &key.targets,
+                compilation_options: PipelineCompilationOptions {
+                    constants: &constants,
+                    ..Default::default()
+                },
+            }),
+            depth_stencil: key.depth_stencil.clone(),
+            multisample: key.multisample,
+            multiview: None,
+        });
+        self.stats.misses += 1;
+        let pipeline = registry.add(pipeline);
+        self.pipelines.insert(key, pipeline);
+        pipeline
+    }
+
+    #[cfg(any(feature = "ui", test))]
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    fn get_or_create<K: Hash + Eq, T: Registered>(
+        stats: &mut CacheStats,
+        cache: &mut HashMap<K, Handle<T>>,
+        key: K,
+        registry: &mut ResourceRegistry,
+        create: impl FnOnce(&K) -> T,
+    ) -> Handle<T> {
+        if let Some(&handle) = cache.get(&key) {
+            stats.hits += 1;
+            return handle;
+        }
+        stats.misses += 1;
+        let handle = registry.add(create(&key));
+        cache.insert(key, handle);
+        handle
+    }
+}
@@ -0,0 +1,218 @@
+use std::{
+    cell::Cell,
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Arc,
+    },
+};
+
+use wgpu::{Buffer, Texture};
+
+use crate::readback::f16_to_f32;
+
+// readback states, written by the map callback
+const PENDING: u8 = 0;
+const MAPPED: u8 = 1;
+const FAILED: u8 = 2;
+
+// each copy starts on its own row pitch boundary
+const COLOR_OFFSET: u64 = 0;
+const DEPTH_OFFSET: u64 = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as u64;
+const ID_OFFSET: u64 = 2 * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as u64;
+
+/// What the scene pass wrote into one pixel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PixelSample {
+    // in scene pixels
+    pub pixel: (u32, u32),
+    // linear HDR, before the post chain
+    pub color: [f32; 4],
+    // NDC, 1 = sky
+    pub depth: f32,
+    // 0 = sky, 1 = ground, 2 + i = box i
+    pub id: u32,
+}
+
+/// Reads back the scene color, depth and object id of the pixel under the cursor. Like the
+/// GPU timer it never waits: while a readback is in flight no new copy is made, and the
+/// latest sample is whatever the last finished readback held.
+pub struct PixelInspector {
+    readback_buffer: Buffer,
+    // window pixel under the cursor, None when it's outside the window
+    pub cursor: Option<(f64, f64)>,
+    // scene pixel of the copy in flight
+    pixel: Cell<(u32, u32)>,
+    // a copy went into this frame's encoder and still needs its readback requested
+    copied: Cell<bool>,
+    in_flight: Cell<bool>,
+    // set by the map callback, the buffer is read on the next frame
+    map_state: Arc<AtomicU8>,
+    latest: Cell<Option<PixelSample>>,
+}
+
+impl PixelInspector {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("pixel inspector readback"),
+            size: ID_OFFSET + 4,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Self {
+            readback_buffer,
+            cursor: None,
+            pixel: Cell::new((0, 0)),
+            copied: Cell::new(false),
+            in_flight: Cell::new(false),
+            map_state: Arc::new(AtomicU8::new(PENDING)),
+            latest: Cell::new(None),
+        }
+    }
+
+    #[cfg(feature = "ui")]
+    pub fn latest(&self) -> Option<PixelSample> {
+        self.latest.get()
+    }
+
+    /// The scene pixel under the cursor, for a scene of `scene_size` stretched over a
+    /// window of `window_size`.
+    pub fn scene_pixel(
+        &self,
+        window_size: (u32, u32),
+        scene_size: (u32, u32),
+    ) -> Option<(u32, u32)> {
+        let (x, y) = self.cursor?;
+        if x < 0.0 || y < 0.0 || x >= window_size.0 as f64 || y >= window_size.1 as f64 {
+            return None;
+        }
+        let scale_x = scene_size.0 as f64 / window_size.0.max(1) as f64;
+        let scale_y = scene_size.1 as f64 / window_size.1.max(1) as f64;
+        Some((
+            ((x * scale_x) as u32).min(scene_size.0 - 1),
+            ((y * scale_y) as u32).min(scene_size.1 - 1),
+        ))
+    }
+
+    /// Picks up a finished readback. True when a new copy can be made.
+    fn collect(&self) -> bool {
+        if !self.in_flight.get() {
+            return true;
+        }
+        match self.map_state.swap(PENDING, Ordering::SeqCst) {
+            PENDING => return false,
+            FAILED => {
+                self.in_flight.set(false);
+                return true;
+            }
+            _ => {}
+        }
+        {
+            let data = self.readback_buffer.slice(..).get_mapped_range();
+            let color = &data[COLOR_OFFSET as usize..COLOR_OFFSET as usize + 8];
+            let half = |i: usize| f16_to_f32(u16::from_le_bytes([color[i * 2], color[i * 2 + 1]]));
+            let word = |offset: u64| {
+                let offset = offset as usize;
+                [
+                    data[offset],
+                    data[offset + 1],
+                    data[offset + 2],
+                    data[offset + 3],
+                ]
+            };
+            let sample = PixelSample {
+                pixel: self.pixel.get(),
+                color: [half(0), half(1), half(2), half(3)],
+                depth: f32::from_le_bytes(word(DEPTH_OFFSET)),
+                id: u32::from_le_bytes(word(ID_OFFSET)),
+            };
+            if self.latest.get() != Some(sample) {
+                println!(
+                    "Pixel {:?}: color [{:.4}, {:.4}, {:.4}, {:.4}], depth {:.6}, id {}",
+                    sample.pixel,
+                    sample.color[0],
+                    sample.color[1],
+                    sample.color[2],
+                    sample.color[3],
+                    sample.depth,
+                    describe_id(sample.id),
+                );
+            }
+            self.latest.set(Some(sample));
+        }
+        self.readback_buffer.unmap();
+        self.in_flight.set(false);
+        true
+    }
+
+    /// Copies `pixel` out of the scene color and the G-buffer depth and id, unless the last
+    /// copy is still being read back.
+    pub fn encode(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        pixel: (u32, u32),
+        color: &Texture,
+        depth: &Texture,
+        id: &Texture,
+    ) {
+        if !self.collect() {
+            return;
+        }
+        for (texture, offset) in [
+            (color, COLOR_OFFSET),
+            (depth, DEPTH_OFFSET),
+            (id, ID_OFFSET),
+        ] {
+            encoder.copy_texture_to_buffer(
+                wgpu::ImageCopyTexture {
+                    texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d {
+                        x: pixel.0,
+                        y: pixel.1,
+                        z: 0,
+                    },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                wgpu::ImageCopyBuffer {
+                    buffer: &self.readback_buffer,
+                    layout: wgpu::ImageDataLayout {
+                        offset,
+                        bytes_per_row: None,
+                        rows_per_image: None,
+                    },
+                },
+                wgpu::Extent3d {
+                    width: 1,
+                    height: 1,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+        self.pixel.set(pixel);
+        self.copied.set(true);
+    }
+
+    /// Call after submitting the frame's encoder; does nothing unless `encode` made a copy.
+    /// The callback fires on a later poll.
+    pub fn request_readback(&self) {
+        if !self.copied.replace(false) {
+            return;
+        }
+        self.in_flight.set(true);
+        let map_state = self.map_state.clone();
+        self.readback_buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let state = if result.is_ok() { MAPPED } else { FAILED };
+                map_state.store(state, Ordering::SeqCst);
+            });
+    }
+}
+
+pub fn describe_id(id: u32) -> String {
+    match id {
+        0 => "sky".to_string(),
+        1 => "ground".to_string(),
+        _ => format!("box {}", id - 2),
+    }
+}
@@ -0,0 +1,203 @@
+use cgmath::{Matrix, Matrix4, SquareMatrix, Vector4};
+use serde::{Deserialize, Serialize};
+use wgpu::{util::DeviceExt, BindGroup, Buffer, Texture, TextureView};
+
+use crate::{
+    camera::{Camera, CameraUniform, OPENGL_TO_WGPU_MATRIX},
+    frame_stats::{self, CountedPass},
+    tonemap::HDR_FORMAT,
+};
+
+/// A pond on the ground plane, reflecting the scene through the planar reflection.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WaterSettings {
+    pub enabled: bool,
+    /// world xz
+    pub center: [f32; 2],
+    /// meters
+    pub radius: f32,
+    /// reflection target size relative to the window
+    pub reflection_scale: f32,
+}
+
+impl Default for WaterSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            center: [0.0, -20.0],
+            radius: 12.0,
+            reflection_scale: 0.5,
+        }
+    }
+}
+
+impl WaterSettings {
+    /// center xz, radius, enabled; as packed into the sky uniform
+    pub fn uniform(&self) -> [f32; 4] {
+        [
+            self.center[0],
+            self.center[1],
+            self.radius,
+            self.enabled as u32 as f32,
+        ]
+    }
+}
+
+/// Replaces the near plane of an OpenGL style projection with `plane` (view space),
+/// so everything on the camera's side of it gets clipped. Lengyel's oblique frustum.
+fn oblique_near_plane(mut proj: Matrix4<f32>, plane: Vector4<f32>) -> Matrix4<f32> {
+    let corner = proj.invert().unwrap_or(Matrix4::identity())
+        * Vector4::new(plane.x.signum(), plane.y.signum(), 1.0, 1.0);
+    let c = plane * (2.0 / cgmath::dot(plane, corner));
+    // third row becomes c - fourth row
+    proj.x.z = c.x - proj.x.w;
+    proj.y.z = c.y - proj.y.w;
+    proj.z.z = c.z - proj.z.w;
+    proj.w.z = c.w - proj.w.w;
+    proj
+}
+
+/// `camera` mirrored about the horizontal plane at `level`, clipping everything below it.
+pub fn mirrored_camera(camera: &Camera, level: f32) -> CameraUniform {
+    #[rustfmt::skip]
+    let reflect = Matrix4::new(
+        1.0, 0.0, 0.0, 0.0,
+        0.0, -1.0, 0.0, 0.0,
+        0.0, 0.0, 1.0, 0.0,
+        0.0, 2.0 * level, 0.0, 1.0,
+    );
+    let view = Matrix4::look_at_rh(camera.eye, camera.target, camera.up) * reflect;
+    let proj = cgmath::perspective(
+        cgmath::Deg(camera.fovy),
+        camera.aspect,
+        camera.znear,
+        camera.zfar,
+    );
+    let plane = Vector4::new(0.0, 1.0, 0.0, -level);
+    let plane_view = view.invert().unwrap_or(Matrix4::identity()).transpose() * plane;
+    let view_proj = OPENGL_TO_WGPU_MATRIX * oblique_near_plane(proj, plane_view) * view;
+    let eye = camera.eye;
+    CameraUniform {
+        view_proj: view_proj.into(),
+        inv_view_proj: view_proj.invert().unwrap_or(Matrix4::identity()).into(),
+        view_position: [eye.x, 2.0 * level - eye.y, eye.z, 1.0],
+    }
+}
+
+/// The scene rendered a second time from the camera mirrored about the water plane.
+/// Sampled in screen space by the water, which lines up because the mirrored camera
+/// projects a reflected point to the same pixel as the real one.
+pub struct PlanarReflection {
+    pub texture: Texture,
+    pub view: TextureView,
+    /// 1x1 stand in bound while rendering the reflection itself (and other passes
+    /// that can't use it), the shader falls back to the sky there
+    pub placeholder_view: TextureView,
+    camera_buffer: Buffer,
+    camera_bind_group: BindGroup,
+    scale: f32,
+}
+
+impl PlanarReflection {
+    pub fn new(
+        device: &wgpu::Device,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        width: u32,
+        height: u32,
+        scale: f32,
+    ) -> Self {
+        let (texture, view) = Self::create_target(device, width, height, scale);
+        let (_, placeholder_view) = Self::create_target(device, 1, 1, 1.0);
+        let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("reflection camera"),
+            contents: bytemuck::bytes_of(&CameraUniform::new()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("reflection camera bind group"),
+            layout: camera_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: camera_buffer.as_entire_binding(),
+            }],
+        });
+        Self {
+            texture,
+            view,
+            placeholder_view,
+            camera_buffer,
+            camera_bind_group,
+            scale,
+        }
+    }
+
+    fn create_target(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        scale: f32,
+    ) -> (Texture, TextureView) {
+        let scale = scale.clamp(0.1, 1.0);
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("planar reflection"),
+            size: wgpu::Extent3d {
+                width: ((width as f32 * scale) as u32).max(1),
+                height: ((height as f32 * scale) as u32).max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: HDR_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        (texture, view)
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        let (texture, view) = Self::create_target(device, width, height, self.scale);
+        self.texture = texture;
+        self.view = view;
+    }
+
+    /// Renders the mirrored view with `pipeline`, a color only sky pipeline whose group 0
+    /// is `scene_bind_group` (bound to the placeholder, not to this target).
+    pub fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        queue: &wgpu::Queue,
+        camera: &Camera,
+        level: f32,
+        pipeline: &wgpu::RenderPipeline,
+        scene_bind_group: &BindGroup,
+    ) {
+        frame_stats::write_buffer(
+            queue,
+            &self.camera_buffer,
+            0,
+            bytemuck::bytes_of(&mirrored_camera(camera, level)),
+        );
+        let mut render_pass = CountedPass::begin(
+            encoder,
+            &wgpu::RenderPassDescriptor {
+                label: Some("planar reflection pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &self.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                ..Default::default()
+            },
+        );
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, scene_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,271 @@
+use serde::{Deserialize, Serialize};
+use wgpu::{
+    BindGroupLayout, Buffer, PipelineCompilationOptions, RenderPipeline, Sampler, TextureFormat,
+    TextureView,
+};
+
+use crate::{
+    frame_stats::{self, CountedPass},
+    hot_reload::wgsl,
+};
+
+/// Passes past this many in the scene's list are left out.
+pub const MAX_PASSES: usize = 4;
+/// The chain's passes in the render graph, by position in the list.
+pub const PASS_NAMES: [&str; MAX_PASSES] = ["post 0", "post 1", "post 2", "post 3"];
+/// What each pass reads, by position among the passes running this frame; the first is
+/// the tonemap's output.
+pub const INPUT_NAMES: [&str; MAX_PASSES] = [
+    "post 0 input",
+    "post 1 input",
+    "post 2 input",
+    "post 3 input",
+];
+
+/// The fullscreen effects a post chain pass can run, entry points of post.wgsl.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PostEffect {
+    Vignette,
+    Grayscale,
+    ChromaticAberration,
+    FilmGrain,
+}
+
+impl PostEffect {
+    fn entry_point(self) -> &'static str {
+        match self {
+            Self::Vignette => "vignette_fs",
+            Self::Grayscale => "grayscale_fs",
+            Self::ChromaticAberration => "chromatic_aberration_fs",
+            Self::FilmGrain => "film_grain_fs",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PostPassSettings {
+    pub effect: PostEffect,
+    pub enabled: bool,
+    /// 0 to 1
+    pub strength: f32,
+}
+
+impl Default for PostPassSettings {
+    fn default() -> Self {
+        Self {
+            effect: PostEffect::Vignette,
+            enabled: true,
+            strength: 0.5,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct PostParams {
+    strength: f32,
+    time: f32,
+    _pad: [f32; 2],
+}
+
+struct PostPass {
+    effect: PostEffect,
+    params_buffer: Buffer,
+    pipeline: RenderPipeline,
+}
+
+/// The scene's list of fullscreen effects, run in order between the tonemap and the
+/// upscaler (or the surface). Each pass reads the one before it through its own
+/// pipeline and bind group, and writes the next; the render graph hands them the
+/// targets in between.
+///
+/// The passes are made from the list when the renderer is built. Later edits to a
+/// pass's `enabled` and `strength` apply from the next frame, a changed list from the
+/// next rebuild.
+pub struct PostChain {
+    passes: Vec<PostPass>,
+    sampler: Sampler,
+    bind_group_layout: BindGroupLayout,
+}
+
+impl PostChain {
+    /// `format` is what the chain reads and writes, the surface's view format.
+    pub fn new(
+        device: &wgpu::Device,
+        format: TextureFormat,
+        settings: &[PostPassSettings],
+    ) -> Self {
+        let code = wgsl!("post.wgsl");
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("post shader"),
+            source: wgpu::ShaderSource::Wgsl(code),
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("post sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("post bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("post pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        if settings.len() > MAX_PASSES {
+            println!(
+                "Post chain has {} passes, running the first {}",
+                settings.len(),
+                MAX_PASSES
+            );
+        }
+        let passes = settings
+            .iter()
+            .take(MAX_PASSES)
+            .map(|pass| {
+                let entry_point = pass.effect.entry_point();
+                let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some(entry_point),
+                    layout: Some(&pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader,
+                        entry_point: "fullscreen_vs",
+                        buffers: &[],
+                        compilation_options: PipelineCompilationOptions::default(),
+                    },
+                    primitive: wgpu::PrimitiveState::default(),
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader,
+                        entry_point,
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format,
+                            blend: None,
+                            write_mask: wgpu::ColorWrites::ALL,
+                        })],
+                        compilation_options: PipelineCompilationOptions::default(),
+                    }),
+                    depth_stencil: None,
+                    multisample: wgpu::MultisampleState::default(),
+                    multiview: None,
+                });
+                let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("post params"),
+                    size: std::mem::size_of::<PostParams>() as u64,
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                });
+                PostPass {
+                    effect: pass.effect,
+                    params_buffer,
+                    pipeline,
+                }
+            })
+            .collect();
+        Self {
+            passes,
+            sampler,
+            bind_group_layout,
+        }
+    }
+
+    /// Indices of the passes to run this frame, in order: those still in `settings` as
+    /// they were built and enabled there.
+    pub fn active(&self, settings: &[PostPassSettings]) -> Vec<usize> {
+        self.passes
+            .iter()
+            .zip(settings)
+            .enumerate()
+            .filter(|(_, (pass, settings))| settings.enabled && settings.effect == pass.effect)
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// Runs pass `index` from `input` onto `output`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn encode(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        queue: &wgpu::Queue,
+        index: usize,
+        settings: &PostPassSettings,
+        time: f32,
+        input: &TextureView,
+        output: &TextureView,
+    ) {
+        let pass = &self.passes[index];
+        let params = PostParams {
+            strength: settings.strength.clamp(0.0, 1.0),
+            time,
+            _pad: [0.0; 2],
+        };
+        frame_stats::write_buffer(queue, &pass.params_buffer, 0, bytemuck::bytes_of(&params));
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("post bind group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(input),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: pass.params_buffer.as_entire_binding(),
+                },
+            ],
+        });
+        let mut render_pass = CountedPass::begin(
+            encoder,
+            &wgpu::RenderPassDescriptor {
+                label: Some("post pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: output,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                ..Default::default()
+            },
+        );
+        render_pass.set_pipeline(&pass.pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,196 @@
+/// CPU profiling with puffin. The scopes around update, encoding, submission and asset work
+/// cost next to nothing while it's off; switched on, the recent frames' scopes are kept
+/// for the debug overlay to show (`latest_scopes`).
+///
+/// Built with the `tracy` feature, the same scopes are also Tracy zones, the GPU timer's
+/// frames become GPU zones, and a few per-frame values are plotted. Tracy is always on
+/// then; its UI connects on its own port.
+pub struct Profiler {
+    // collects each finished frame's scopes while on
+    view: Option<puffin::GlobalFrameView>,
+}
+
+// frames the overlay's timings are averaged over
+#[cfg(feature = "ui")]
+const RECENT_FRAMES: usize = 30;
+// scopes nested deeper than this are left out of the overlay
+#[cfg(feature = "ui")]
+const MAX_DEPTH: usize = 3;
+
+/// A puffin scope, and with the `tracy` feature a Tracy zone of the same name, both closing
+/// at the end of the enclosing block. `data` is shown next to the name, a pass name say.
+macro_rules! profile_scope {
+    ($name:expr) => {
+        $crate::profiler::profile_scope!($name, "")
+    };
+    ($name:expr, $data:expr) => {
+        puffin::profile_scope!($name, $data);
+        #[cfg(feature = "tracy")]
+        let _tracy_zone = $crate::profiler::tracy_zone($name, $data, file!(), line!());
+    };
+}
+pub(crate) use profile_scope;
+
+impl Profiler {
+    pub fn new() -> Self {
+        #[cfg(feature = "tracy")]
+        tracy_client::Client::start();
+        Self { view: None }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.view.is_some()
+    }
+
+    pub fn toggle(&mut self) {
+        if self.view.take().is_some() {
+            puffin::set_scopes_on(false);
+            println!("Profiler off");
+            return;
+        }
+        let view = puffin::GlobalFrameView::default();
+        #[cfg(feature = "ui")]
+        view.lock().set_max_recent(RECENT_FRAMES);
+        self.view = Some(view);
+        puffin::set_scopes_on(true);
+        println!("Profiler on, its scopes are in the F3 overlay");
+    }
+
+    /// Closes the last frame's scopes; call at the start of every frame.
+    pub fn new_frame(&self) {
+        if self.enabled() {
+            puffin::GlobalProfiler::lock().new_frame();
+        }
+        #[cfg(feature = "tracy")]
+        tracy_client::frame_mark();
+    }
+
+    /// Each thread's scopes over the recent frames, merged by name and in the order they
+    /// ran: (depth, label, milliseconds per frame). A thread is a line of depth 0 with
+    /// the time its top scopes took. Empty while off.
+    #[cfg(feature = "ui")]
+    pub fn latest_scopes(&self) -> Vec<(usize, String, f32)> {
+        let Some(view) = self.view.as_ref() else {
+            return vec![];
+        };
+        let view = view.lock();
+        let frames: Vec<_> = view
+            .recent_frames()
+            .filter_map(|frame| frame.unpacked().ok())
+            .collect();
+        let threads: std::collections::BTreeSet<_> = frames
+            .iter()
+            .flat_map(|frame| frame.thread_streams.keys())
+            .collect();
+        let mut lines = vec![];
+        for thread in threads {
+            let Ok(scopes) =
+                puffin::merge_scopes_for_thread(view.scope_collection(), &frames, thread)
+            else {
+                continue;
+            };
+            let total = scopes.iter().map(|scope| scope.duration_per_frame_ns).sum();
+            lines.push((0, thread.name.clone(), ms(total)));
+            push_scopes(&mut lines, view.scope_collection(), &scopes, 1);
+        }
+        lines
+    }
+}
+
+#[cfg(feature = "ui")]
+fn push_scopes(
+    lines: &mut Vec<(usize, String, f32)>,
+    collection: &puffin::ScopeCollection,
+    scopes: &[puffin::MergeScope],
+    depth: usize,
+) {
+    if depth > MAX_DEPTH {
+        return;
+    }
+    let mut scopes: Vec<_> = scopes.iter().collect();
+    scopes.sort_by_key(|scope| scope.relative_start_ns);
+    for scope in scopes {
+        let name = collection
+            .fetch_by_id(&scope.id)
+            .map_or("?".to_string(), |details| details.name().to_string());
+        let label = match scope.data.as_ref() {
+            "" => name,
+            data => format!("{} {}", name, data),
+        };
+        lines.push((depth, label, ms(scope.duration_per_frame_ns)));
+        push_scopes(lines, collection, &scope.children, depth + 1);
+    }
+}
+
+#[cfg(feature = "ui")]
+fn ms(ns: puffin::NanoSecond) -> f32 {
+    ns as f32 / 1e6
+}
+
+#[cfg(feature = "tracy")]
+pub fn tracy_zone(name: &str, data: &str, file: &str, line: u32) -> Option<tracy_client::Span> {
+    let span = tracy_client::Client::running()?.span_alloc(Some(name), "", file, line, 0);
+    if !data.is_empty() {
+        span.emit_text(data);
+    }
+    Some(span)
+}
+
+/// Per-frame values for Tracy's plots; does nothing without the `tracy` feature.
+#[allow(unused_variables)]
+pub fn plot(name: PlotName, value: f64) {
+    #[cfg(feature = "tracy")]
+    if let Some(client) = tracy_client::Client::running() {
+        let name = match name {
+            PlotName::DrawCalls => tracy_client::plot_name!("draw calls"),
+            PlotName::EncodeMs => tracy_client::plot_name!("encode ms"),
+            PlotName::GpuFrameMs => tracy_client::plot_name!("gpu frame ms"),
+            PlotName::TexturePoolMb => tracy_client::plot_name!("texture pool MB"),
+        };
+        client.plot(name, value);
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum PlotName {
+    DrawCalls,
+    EncodeMs,
+    GpuFrameMs,
+    TexturePoolMb,
+}
+
+/// Hands the GPU timer's measured frames to Tracy as GPU zones on one timeline.
+#[derive(Default)]
+pub struct GpuZones {
+    #[cfg(feature = "tracy")]
+    context: std::cell::OnceCell<Option<tracy_client::GpuContext>>,
+}
+
+impl GpuZones {
+    /// `start` and `end` in raw timestamp ticks of `period` nanoseconds.
+    #[allow(unused_variables)]
+    pub fn frame(&self, start: u64, end: u64, period: f32) {
+        #[cfg(feature = "tracy")]
+        {
+            // the first measured frame calibrates the GPU clock against the CPU one
+            let context = self.context.get_or_init(|| {
+                tracy_client::Client::running()?
+                    .new_gpu_context(
+                        Some("wgpu"),
+                        tracy_client::GpuContextType::Invalid,
+                        start as i64,
+                        period,
+                    )
+                    .ok()
+            });
+            let Some(context) = context else {
+                return;
+            };
+            if let Ok(mut span) = context.span_alloc("frame", "", file!(), line!()) {
+                span.end_zone();
+                span.upload_timestamp_start(start as i64);
+                span.upload_timestamp_end(end as i64);
+            }
+        }
+    }
+}
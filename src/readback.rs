@@ -0,0 +1,359 @@
+use std::{
+    cell::RefCell,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context as TaskContext, Poll, Wake, Waker},
+};
+
+use anyhow::{bail, Context};
+use wgpu::{Buffer, Texture};
+
+#[cfg(any(feature = "ui", test))]
+use crate::image_data::ImageData;
+
+/// Where the bytes a readback asked for sit in its staging buffer.
+enum Layout {
+    // a span of a buffer copy
+    #[cfg(any(feature = "ui", test))]
+    Bytes(std::ops::Range<usize>),
+    // texture rows padded to the copy alignment: bytes per row wanted, per row copied
+    Rows(usize, usize),
+}
+
+/// A copy into a buffer the CPU can map.
+struct Staging {
+    buffer: Buffer,
+    layout: Layout,
+}
+
+impl Staging {
+    /// Records a copy of `range` of `source`, widened to the 4 byte alignment copies need.
+    #[cfg(any(feature = "ui", test))]
+    fn for_buffer(
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        source: &Buffer,
+        range: std::ops::Range<u64>,
+    ) -> anyhow::Result<Self> {
+        if range.start >= range.end || range.end > source.size() {
+            bail!(
+                "readback range {:?} is empty or outside a {} byte buffer",
+                range,
+                source.size()
+            );
+        }
+        if !source.usage().contains(wgpu::BufferUsages::COPY_SRC) {
+            bail!("readback source buffer has no COPY_SRC usage");
+        }
+        let start = range.start - range.start % wgpu::COPY_BUFFER_ALIGNMENT;
+        let end = range.end.next_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT);
+        if end > source.size() {
+            bail!(
+                "readback range {:?} can't be widened to 4 bytes in a {} byte buffer",
+                range,
+                source.size()
+            );
+        }
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("readback staging"),
+            size: end - start,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        encoder.copy_buffer_to_buffer(source, start, &buffer, 0, end - start);
+        let offset = (range.start - start) as usize;
+        Ok(Self {
+            buffer,
+            layout: Layout::Bytes(offset..offset + (range.end - range.start) as usize),
+        })
+    }
+
+    /// Records a copy of mip 0 of a 2D `texture`, its depth aspect for depth formats.
+    fn for_texture(
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &Texture,
+    ) -> anyhow::Result<Self> {
+        if !texture.usage().contains(wgpu::TextureUsages::COPY_SRC) {
+            bail!("readback source texture has no COPY_SRC usage");
+        }
+        let format = texture.format();
+        let aspect = if format.is_depth_stencil_format() {
+            wgpu::TextureAspect::DepthOnly
+        } else {
+            wgpu::TextureAspect::All
+        };
+        let texel_size = format
+            .block_copy_size(Some(aspect))
+            .with_context(|| format!("{:?} can't be copied out of a texture", format))?;
+        let (width, height) = (texture.width(), texture.height());
+        // rows of a texture to buffer copy are padded to 256 bytes
+        let row_bytes = width * texel_size;
+        let padded_row_bytes = row_bytes.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("texture readback staging"),
+            size: (padded_row_bytes * height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row_bytes),
+                    rows_per_image: Some(height),
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+        Ok(Self {
+            buffer,
+            layout: Layout::Rows(row_bytes as usize, padded_row_bytes as usize),
+        })
+    }
+
+    /// The wanted bytes out of the mapped buffer, unmapping it after.
+    fn take(&self) -> Vec<u8> {
+        // the mapped view borrows the buffer and has to be gone before the unmap
+        let bytes = {
+            let data = self.buffer.slice(..).get_mapped_range();
+            match &self.layout {
+                #[cfg(any(feature = "ui", test))]
+                Layout::Bytes(range) => data[range.clone()].to_vec(),
+                Layout::Rows(row_bytes, padded_row_bytes) => data
+                    .chunks(*padded_row_bytes)
+                    .flat_map(|row| &row[..*row_bytes])
+                    .copied()
+                    .collect(),
+            }
+        };
+        self.buffer.unmap();
+        bytes
+    }
+
+    /// Submits `encoder`, holding the copy, and requests the map.
+    fn submit(self, queue: &wgpu::Queue, encoder: wgpu::CommandEncoder) -> Mapping {
+        queue.submit(Some(encoder.finish()));
+        let state = Arc::new(Mutex::new(MapState::default()));
+        let callback_state = state.clone();
+        self.buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let mut state = callback_state.lock().unwrap();
+                state.result = Some(result);
+                if let Some(waker) = state.waker.take() {
+                    waker.wake();
+                }
+            });
+        Mapping {
+            staging: self,
+            state,
+        }
+    }
+}
+
+#[derive(Default)]
+struct MapState {
+    // set by the map callback
+    result: Option<Result<(), wgpu::BufferAsyncError>>,
+    waker: Option<Waker>,
+}
+
+/// A staging buffer on its way to being mapped, resolving to the wanted bytes. The map
+/// callback only fires from a device poll; the future never polls or waits itself, the
+/// frame's poll or `wait` moves it on.
+struct Mapping {
+    staging: Staging,
+    state: Arc<Mutex<MapState>>,
+}
+
+impl Future for Mapping {
+    type Output = anyhow::Result<Vec<u8>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Self::Output> {
+        let mut state = self.state.lock().unwrap();
+        match state.result.take() {
+            Some(Ok(())) => Poll::Ready(Ok(self.staging.take())),
+            Some(Err(e)) => Poll::Ready(Err(e).context("readback map failed")),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+fn readback_encoder(device: &wgpu::Device) -> wgpu::CommandEncoder {
+    device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("readback"),
+    })
+}
+
+/// Copies `range` of `source` into a staging buffer and reads it back. `source` needs
+/// COPY_SRC; the range may be anywhere in it, the copy is widened to the 4 byte alignment
+/// copies need and trimmed again afterwards.
+///
+/// The copy is submitted right away, the future resolves once a device poll has let its
+/// map finish: a later frame's, with the future on a `ReadbackQueue`, or `wait`'s for
+/// tools, bakes and tests.
+#[cfg(any(feature = "ui", test))]
+pub fn read_buffer(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    source: &Buffer,
+    range: std::ops::Range<u64>,
+) -> impl Future<Output = anyhow::Result<Vec<u8>>> + Send + 'static {
+    let mapping = if range.is_empty() && range.end <= source.size() {
+        Ok(None)
+    } else {
+        let mut encoder = readback_encoder(device);
+        Staging::for_buffer(device, &mut encoder, source, range)
+            .map(|staging| Some(staging.submit(queue, encoder)))
+    };
+    async move {
+        match mapping? {
+            Some(mapping) => mapping.await,
+            None => Ok(vec![]),
+        }
+    }
+}
+
+/// Finishes the work submitted so far and runs a readback future to the end, blocking.
+/// For tools, bakes and tests, a frame should put the future on a `ReadbackQueue`.
+pub fn wait<T>(device: &wgpu::Device, readback: impl Future<Output = T>) -> T {
+    device.poll(wgpu::Maintain::Wait);
+    pollster::block_on(readback)
+}
+
+/// The raw texels of mip 0 of a 2D `texture`, rows packed tight. `texture` needs COPY_SRC;
+/// depth formats read their depth aspect. Blocks like `wait`.
+pub fn read_texture_bytes(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &Texture,
+) -> anyhow::Result<Vec<u8>> {
+    let mut encoder = readback_encoder(device);
+    let staging = Staging::for_texture(device, &mut encoder, texture)?;
+    wait(device, staging.submit(queue, encoder))
+}
+
+/// Mip 0 of a 2D `texture` converted to RGBA floats, for tests comparing frames. The app
+/// reads textures through a `ReadbackQueue`, see the console's `screenshot`.
+#[cfg(test)]
+pub fn read_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &Texture,
+) -> anyhow::Result<ImageData> {
+    let bytes = read_texture_bytes(device, queue, texture)?;
+    ImageData::decode(texture.width(), texture.height(), texture.format(), &bytes)
+}
+
+// Send so the queue, and the factory holding it, can live on the render thread
+type PendingReadback = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Wakes whoever services the queue, from the map callback on whatever thread polled.
+struct QueueWaker(Box<dyn Fn() + Send + Sync>);
+
+impl Wake for QueueWaker {
+    fn wake(self: Arc<Self>) {
+        (self.0)()
+    }
+}
+
+/// Readbacks that never wait. Each one is copied and submitted right away and its map is
+/// requested; the device poll at the start of every frame lets the maps finish, and
+/// `service` hands the bytes of the finished ones to their callbacks on the thread that
+/// calls it. `wake` runs from the map callback, on whatever thread polled, so an idle
+/// render thread gets to `service` too.
+pub struct ReadbackQueue {
+    pending: RefCell<Vec<PendingReadback>>,
+    waker: Waker,
+}
+
+impl ReadbackQueue {
+    pub fn new(wake: impl Fn() + Send + Sync + 'static) -> Self {
+        Self {
+            pending: RefCell::new(vec![]),
+            waker: Waker::from(Arc::new(QueueWaker(Box::new(wake)))),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.borrow().is_empty()
+    }
+
+    /// Reads mip 0 of `texture` as it is once the work submitted so far is done.
+    #[cfg(feature = "ui")]
+    pub fn read_texture(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        texture: &Texture,
+        on_done: impl FnOnce(anyhow::Result<ImageData>) + Send + 'static,
+    ) -> anyhow::Result<()> {
+        let mut encoder = readback_encoder(device);
+        let mapping = Staging::for_texture(device, &mut encoder, texture)?.submit(queue, encoder);
+        let (width, height, format) = (texture.width(), texture.height(), texture.format());
+        self.push(mapping, move |bytes| {
+            on_done(bytes.and_then(|bytes| ImageData::decode(width, height, format, &bytes)))
+        });
+        Ok(())
+    }
+
+    /// Hands what `readback`, a `read_buffer` say, resolves to to `on_done` once it's done.
+    #[cfg(any(feature = "ui", test))]
+    pub fn push<T>(
+        &self,
+        readback: impl Future<Output = T> + Send + 'static,
+        on_done: impl FnOnce(T) + Send + 'static,
+    ) {
+        self.pending
+            .borrow_mut()
+            .push(Box::pin(async move { on_done(readback.await) }));
+    }
+
+    /// Runs the callbacks of the readbacks whose maps finished since the last call. Call
+    /// after a device poll.
+    pub fn service(&self) {
+        let mut cx = TaskContext::from_waker(&self.waker);
+        // callbacks may queue more readbacks, the list isn't borrowed while they run
+        let pending = std::mem::take(&mut *self.pending.borrow_mut());
+        let waiting: Vec<PendingReadback> = pending
+            .into_iter()
+            .filter_map(|mut readback| {
+                readback
+                    .as_mut()
+                    .poll(&mut cx)
+                    .is_pending()
+                    .then_some(readback)
+            })
+            .collect();
+        self.pending.borrow_mut().extend(waiting);
+    }
+}
+
+/// IEEE half to single precision, for reading back 16 bit float formats.
+pub fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32;
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0.0 => sign * f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
@@ -0,0 +1,136 @@
+use std::cell::Cell;
+
+use cgmath::Point3;
+use serde::{Deserialize, Serialize};
+use wgpu::{util::DeviceExt, BindGroup, Buffer, TextureView};
+
+use crate::{environment::EnvironmentMap, frame_stats};
+
+/// How many probes the reflection passes can bind at once, the rest is ignored.
+pub const MAX_PROBES: usize = 4;
+
+/// A cube map captured at `position`, used for reflections of points inside its box.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReflectionProbeSettings {
+    pub position: [f32; 3],
+    /// half size of the box around `position`, meters. Reflection rays are intersected
+    /// with it so the cube map lines up with the surroundings (parallax correction).
+    pub extent: [f32; 3],
+}
+
+impl Default for ReflectionProbeSettings {
+    fn default() -> Self {
+        Self {
+            position: [0.0, 1.0, 0.0],
+            extent: [20.0, 20.0, 20.0],
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct ReflectionProbeUniform {
+    // xyz, w unused
+    position: [[f32; 4]; MAX_PROBES],
+    extent: [[f32; 4]; MAX_PROBES],
+    count: u32,
+    _pad: [u32; 3],
+}
+
+impl ReflectionProbeUniform {
+    fn new(probes: &[ReflectionProbeSettings]) -> Self {
+        let mut uniform = Self {
+            position: [[0.0; 4]; MAX_PROBES],
+            extent: [[0.0; 4]; MAX_PROBES],
+            count: probes.len().min(MAX_PROBES) as u32,
+            _pad: [0; 3],
+        };
+        for (i, probe) in probes.iter().take(MAX_PROBES).enumerate() {
+            let [x, y, z] = probe.position;
+            let [ex, ey, ez] = probe.extent;
+            uniform.position[i] = [x, y, z, 0.0];
+            uniform.extent[i] = [ex.max(0.01), ey.max(0.01), ez.max(0.01), 0.0];
+        }
+        uniform
+    }
+}
+
+/// The scene's placed reflection probes. Unlike the global environment map they are
+/// not updated every frame: they are captured once at load and again on request.
+pub struct ReflectionProbes {
+    maps: Vec<EnvironmentMap>,
+    pub uniform_buffer: Buffer,
+    capture_pending: Cell<bool>,
+}
+
+impl ReflectionProbes {
+    pub fn new(
+        device: &wgpu::Device,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        probes: &[ReflectionProbeSettings],
+    ) -> Self {
+        if probes.len() > MAX_PROBES {
+            println!(
+                "{} reflection probes in the scene, only the first {} are used",
+                probes.len(),
+                MAX_PROBES
+            );
+        }
+        let maps = probes
+            .iter()
+            .take(MAX_PROBES)
+            .map(|_| EnvironmentMap::new(device, camera_bind_group_layout))
+            .collect();
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("reflection probes"),
+            contents: bytemuck::bytes_of(&ReflectionProbeUniform::new(probes)),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        Self {
+            maps,
+            uniform_buffer,
+            capture_pending: Cell::new(true),
+        }
+    }
+
+    /// Captures all probes again with the next frame.
+    pub fn request_capture(&self) {
+        self.capture_pending.set(true);
+    }
+
+    /// One cube view per binding slot, slots without a probe get `fallback`.
+    pub fn views<'a>(&'a self, fallback: &'a TextureView) -> [&'a TextureView; MAX_PROBES] {
+        std::array::from_fn(|i| self.maps.get(i).map_or(fallback, |map| &map.view))
+    }
+
+    /// Renders the probes if a capture is pending, see `EnvironmentMap::capture`.
+    pub fn capture(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        queue: &wgpu::Queue,
+        probes: &[ReflectionProbeSettings],
+        pipeline: &wgpu::RenderPipeline,
+        scene_bind_group: &BindGroup,
+    ) {
+        if !self.capture_pending.replace(false) {
+            return;
+        }
+        println!("Capturing {} reflection probes", self.maps.len());
+        frame_stats::write_buffer(
+            queue,
+            &self.uniform_buffer,
+            0,
+            bytemuck::bytes_of(&ReflectionProbeUniform::new(probes)),
+        );
+        for (map, probe) in self.maps.iter().zip(probes) {
+            map.capture(
+                encoder,
+                queue,
+                Point3::from(probe.position),
+                pipeline,
+                scene_bind_group,
+            );
+        }
+    }
+}
@@ -0,0 +1,220 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+    ops::{Index, IndexMut},
+};
+
+use wgpu::{
+    BindGroup, BindGroupLayout, Buffer, ComputePipeline, PipelineLayout, RenderPipeline,
+    ShaderModule,
+};
+
+use crate::{frustum::Aabb, texture::ImageTexture};
+
+/// A resource in a `ResourceRegistry`, by its kind and the order it was added in. It
+/// stays valid for the registry's life, what it names can only be replaced.
+pub struct Handle<T> {
+    index: u32,
+    kind: PhantomData<fn() -> T>,
+}
+
+pub type BufferHandle = Handle<Buffer>;
+pub type BindGroupHandle = Handle<BindGroup>;
+pub type BindGroupLayoutHandle = Handle<BindGroupLayout>;
+pub type PipelineLayoutHandle = Handle<PipelineLayout>;
+pub type ShaderHandle = Handle<ShaderModule>;
+pub type PipelineHandle = Handle<RenderPipeline>;
+pub type ComputePipelineHandle = Handle<ComputePipeline>;
+pub type TextureHandle = Handle<ImageTexture>;
+pub type MeshHandle = Handle<MeshDraw>;
+
+impl<T> Handle<T> {
+    /// Its place among the registry's resources of its kind, counting from 0.
+    pub fn index(self) -> usize {
+        self.index as usize
+    }
+}
+
+// by hand, derives would want `T` to have them too
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Handle<T> {}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Handle({})", self.index)
+    }
+}
+
+/// One mesh for the factory to draw: `index_count` indices of `index_format` in
+/// `index_buffer`, into `vertex_buffer`, once for each of the `instance_count` transforms
+/// in `instance_buffer`.
+pub struct MeshDraw {
+    pub vertex_buffer: BufferHandle,
+    pub index_buffer: BufferHandle,
+    pub index_format: wgpu::IndexFormat,
+    pub index_count: u32,
+    pub instance_buffer: BufferHandle,
+    pub instance_count: u32,
+    /// the mesh's own bounds, before its instances move it
+    pub bounds: Aabb,
+    /// around all of its instances, None without any
+    pub world_bounds: Option<Aabb>,
+    /// what it draws with, into `Materials::materials`
+    pub material: usize,
+}
+
+/// The resources of one kind, in the order they were added, some of them named.
+pub struct Slots<T> {
+    items: Vec<T>,
+    names: HashMap<&'static str, Handle<T>>,
+}
+
+impl<T> Default for Slots<T> {
+    fn default() -> Self {
+        Self {
+            items: vec![],
+            names: HashMap::new(),
+        }
+    }
+}
+
+/// What a `ResourceRegistry` holds.
+pub trait Registered: Sized {
+    fn slots(registry: &ResourceRegistry) -> &Slots<Self>;
+    fn slots_mut(registry: &mut ResourceRegistry) -> &mut Slots<Self>;
+}
+
+macro_rules! registered {
+    ($($kind:ty => $field:ident),* $(,)?) => {
+        $(impl Registered for $kind {
+            fn slots(registry: &ResourceRegistry) -> &Slots<Self> {
+                &registry.$field
+            }
+
+            fn slots_mut(registry: &mut ResourceRegistry) -> &mut Slots<Self> {
+                &mut registry.$field
+            }
+        })*
+    };
+}
+
+/// The factory's buffers, bind groups, pipelines, textures and meshes, each behind a typed
+/// handle so a handle of one kind can't index another, and whatever was given a name
+/// can be found by it.
+#[derive(Default)]
+pub struct ResourceRegistry {
+    buffers: Slots<Buffer>,
+    bind_groups: Slots<BindGroup>,
+    bind_group_layouts: Slots<BindGroupLayout>,
+    pipeline_layouts: Slots<PipelineLayout>,
+    shaders: Slots<ShaderModule>,
+    pipelines: Slots<RenderPipeline>,
+    compute_pipelines: Slots<ComputePipeline>,
+    textures: Slots<ImageTexture>,
+    meshes: Slots<MeshDraw>,
+}
+
+registered! {
+    Buffer => buffers,
+    BindGroup => bind_groups,
+    BindGroupLayout => bind_group_layouts,
+    PipelineLayout => pipeline_layouts,
+    ShaderModule => shaders,
+    RenderPipeline => pipelines,
+    ComputePipeline => compute_pipelines,
+    ImageTexture => textures,
+    MeshDraw => meshes,
+}
+
+impl ResourceRegistry {
+    pub fn add<T: Registered>(&mut self, item: T) -> Handle<T> {
+        let slots = T::slots_mut(self);
+        slots.items.push(item);
+        Handle {
+            index: slots.items.len() as u32 - 1,
+            kind: PhantomData,
+        }
+    }
+
+    /// Adds `item` for `find` to look up by `name`. Names are per kind, one already
+    /// taken is a mistake in the caller.
+    pub fn add_named<T: Registered>(&mut self, name: &'static str, item: T) -> Handle<T> {
+        let handle = self.add(item);
+        self.name(name, handle);
+        handle
+    }
+
+    /// Gives what `handle` points at one more name, for a resource shared under the
+    /// names of everything using it.
+    pub fn name<T: Registered>(&mut self, name: &'static str, handle: Handle<T>) {
+        let previous = T::slots_mut(self).names.insert(name, handle);
+        assert!(previous.is_none(), "registry: {} added twice", name);
+    }
+
+    /// Puts `item` where `handle` points, returning what was there.
+    pub fn replace<T: Registered>(&mut self, handle: Handle<T>, item: T) -> T {
+        std::mem::replace(&mut self[handle], item)
+    }
+
+    pub fn find<T: Registered>(&self, name: &str) -> Option<Handle<T>> {
+        T::slots(self).names.get(name).copied()
+    }
+
+    pub fn len<T: Registered>(&self) -> usize {
+        T::slots(self).items.len()
+    }
+
+    pub fn is_empty<T: Registered>(&self) -> bool {
+        T::slots(self).items.is_empty()
+    }
+
+    /// Every resource of a kind, in the order they were added.
+    pub fn iter<'a, T: Registered + 'a>(&'a self) -> impl Iterator<Item = (Handle<T>, &'a T)> {
+        T::slots(self)
+            .items
+            .iter()
+            .enumerate()
+            .map(|(index, item)| {
+                let handle = Handle {
+                    index: index as u32,
+                    kind: PhantomData,
+                };
+                (handle, item)
+            })
+    }
+}
+
+impl<T: Registered> Index<Handle<T>> for ResourceRegistry {
+    type Output = T;
+
+    fn index(&self, handle: Handle<T>) -> &T {
+        &T::slots(self).items[handle.index()]
+    }
+}
+
+impl<T: Registered> IndexMut<Handle<T>> for ResourceRegistry {
+    fn index_mut(&mut self, handle: Handle<T>) -> &mut T {
+        &mut T::slots_mut(self).items[handle.index()]
+    }
+}
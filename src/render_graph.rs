@@ -0,0 +1,420 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+};
+
+use rayon::{prelude::*, ThreadPool};
+use wgpu::{Texture, TextureFormat, TextureView};
+
+use crate::{gpu_timer::PassQueries, profiler::profile_scope};
+
+/// What a transient texture looks like; textures with equal descriptions are
+/// interchangeable, so one can stand in for another whose lifetime has ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TransientDesc {
+    pub width: u32,
+    pub height: u32,
+    pub format: TextureFormat,
+}
+
+enum Resource<'a> {
+    // lives outside the graph, always counts as used
+    Imported(&'a Texture, &'a TextureView),
+    Transient(TransientDesc),
+}
+
+type PassFn<'a> = Box<dyn FnOnce(&mut wgpu::CommandEncoder, &GraphResources) + 'a>;
+type SendPassFn<'a> = Box<dyn FnOnce(&mut wgpu::CommandEncoder, &GraphResources) + Send + 'a>;
+
+enum PassRun<'a> {
+    // records into the frame's encoder on the calling thread
+    Local(PassFn<'a>),
+    // only holds thread safe data, so it can get an encoder of its own on another thread
+    Send(SendPassFn<'a>),
+}
+
+/// One step of the frame, with the named textures it reads and writes.
+pub struct Pass<'a> {
+    name: &'static str,
+    reads: Vec<&'static str>,
+    writes: Vec<&'static str>,
+    // writes something the graph can't see (a buffer, the exposure), never culled
+    side_effects: bool,
+    // only adds to what's already in its textures, so it can be switched off at runtime
+    optional: bool,
+    run: PassRun<'a>,
+}
+
+impl<'a> Pass<'a> {
+    pub fn new(
+        name: &'static str,
+        run: impl FnOnce(&mut wgpu::CommandEncoder, &GraphResources) + 'a,
+    ) -> Self {
+        Self {
+            name,
+            reads: vec![],
+            writes: vec![],
+            side_effects: false,
+            optional: false,
+            run: PassRun::Local(Box::new(run)),
+        }
+    }
+
+    /// A pass that only touches thread safe data. Runs of these may be encoded in
+    /// parallel, each into its own command buffer, submitted in the order added.
+    pub fn new_send(
+        name: &'static str,
+        run: impl FnOnce(&mut wgpu::CommandEncoder, &GraphResources) + Send + 'a,
+    ) -> Self {
+        Self {
+            name,
+            reads: vec![],
+            writes: vec![],
+            side_effects: false,
+            optional: false,
+            run: PassRun::Send(Box::new(run)),
+        }
+    }
+
+    pub fn read(mut self, resource: &'static str) -> Self {
+        self.reads.push(resource);
+        self
+    }
+
+    pub fn write(mut self, resource: &'static str) -> Self {
+        self.writes.push(resource);
+        self
+    }
+
+    pub fn side_effects(mut self) -> Self {
+        self.side_effects = true;
+        self
+    }
+
+    pub fn optional(mut self) -> Self {
+        self.optional = true;
+        self
+    }
+}
+
+/// The textures a running pass can look up by name.
+pub struct GraphResources<'a> {
+    textures: HashMap<&'static str, (&'a Texture, &'a TextureView)>,
+}
+
+impl GraphResources<'_> {
+    pub fn texture(&self, name: &str) -> &Texture {
+        self.get(name).0
+    }
+
+    pub fn view(&self, name: &str) -> &TextureView {
+        self.get(name).1
+    }
+
+    fn get(&self, name: &str) -> (&Texture, &TextureView) {
+        *self
+            .textures
+            .get(name)
+            .unwrap_or_else(|| panic!("render graph: no texture named {}", name))
+    }
+}
+
+/// Transient textures kept between frames, grouped by description. A frame takes the
+/// first few of each group; whatever a frame didn't need is dropped after it.
+#[derive(Default)]
+pub struct TexturePool {
+    textures: RefCell<HashMap<TransientDesc, Vec<(Texture, TextureView)>>>,
+}
+
+impl TexturePool {
+    /// Memory held by the pooled textures, going by their texel sizes.
+    pub fn bytes(&self) -> u64 {
+        self.textures
+            .borrow()
+            .iter()
+            .map(|(desc, textures)| {
+                let texel_size = desc.format.block_copy_size(None).unwrap_or(0) as u64;
+                desc.width as u64 * desc.height as u64 * texel_size * textures.len() as u64
+            })
+            .sum()
+    }
+}
+
+/// A frame's passes over named textures. Passes run in the order they're added, those
+/// whose writes nothing later reads are culled, and transient textures are only
+/// allocated for the span of passes that use them, sharing memory when the spans
+/// don't overlap.
+#[derive(Default)]
+pub struct RenderGraph<'a> {
+    resources: HashMap<&'static str, Resource<'a>>,
+    passes: Vec<Pass<'a>>,
+    // optional passes switched off for this frame
+    disabled: HashSet<&'static str>,
+}
+
+impl<'a> RenderGraph<'a> {
+    /// A texture that outlives the frame, like the HDR target or the surface.
+    pub fn import(&mut self, name: &'static str, texture: &'a Texture, view: &'a TextureView) {
+        self.resources
+            .insert(name, Resource::Imported(texture, view));
+    }
+
+    /// A texture that only lives inside the frame, taken from the pool. It starts out
+    /// with whatever an earlier user left in it, the first pass writing it should clear.
+    pub fn create(&mut self, name: &'static str, desc: TransientDesc) {
+        self.resources.insert(name, Resource::Transient(desc));
+    }
+
+    /// Switches off the optional passes among `names`; the others run regardless.
+    pub fn disable(&mut self, names: impl IntoIterator<Item = &'static str>) {
+        self.disabled.extend(names);
+    }
+
+    pub fn has_texture(&self, name: &str) -> bool {
+        self.resources.contains_key(name)
+    }
+
+    pub fn texture_format(&self, name: &str) -> Option<TextureFormat> {
+        self.resources.get(name).map(|resource| match resource {
+            Resource::Imported(texture, _) => texture.format(),
+            Resource::Transient(desc) => desc.format,
+        })
+    }
+
+    /// Every texture name declared so far, sorted.
+    pub fn texture_names(&self) -> Vec<&'static str> {
+        let mut names: Vec<_> = self.resources.keys().copied().collect();
+        names.sort();
+        names
+    }
+
+    /// Names of the passes that can be switched off, in the order they were added.
+    pub fn optional_passes(&self) -> Vec<&'static str> {
+        self.passes
+            .iter()
+            .filter(|pass| pass.optional)
+            .map(|pass| pass.name)
+            .collect()
+    }
+
+    pub fn add_pass(&mut self, pass: Pass<'a>) {
+        for name in pass.reads.iter().chain(&pass.writes) {
+            assert!(
+                self.resources.contains_key(name),
+                "render graph: pass {} uses undeclared texture {}",
+                pass.name,
+                name
+            );
+        }
+        self.passes.push(pass);
+    }
+
+    /// Walks back from the imported textures, keeping the enabled passes that contribute
+    /// to them.
+    fn live_passes(&self) -> Vec<bool> {
+        let mut needed: HashSet<&str> = self
+            .resources
+            .iter()
+            .filter(|(_, resource)| matches!(resource, Resource::Imported(..)))
+            .map(|(name, _)| *name)
+            .collect();
+        let mut live = vec![false; self.passes.len()];
+        for (index, pass) in self.passes.iter().enumerate().rev() {
+            if pass.optional && self.disabled.contains(pass.name) {
+                continue;
+            }
+            if pass.side_effects || pass.writes.iter().any(|name| needed.contains(name)) {
+                live[index] = true;
+                needed.extend(pass.reads.iter().copied());
+            }
+        }
+        live
+    }
+
+    /// Records the live passes, into `encoder` unless `workers` are given to encode runs
+    /// of two or more `new_send` passes side by side, each between a pair of `timestamps`
+    /// when given. Returns the command buffers to submit ahead of the encoder that's
+    /// handed back, which has whatever came last.
+    pub fn execute(
+        self,
+        device: &wgpu::Device,
+        mut encoder: wgpu::CommandEncoder,
+        pool: &TexturePool,
+        workers: Option<&ThreadPool>,
+        timestamps: Option<PassQueries>,
+    ) -> (Vec<wgpu::CommandBuffer>, wgpu::CommandEncoder) {
+        let live = self.live_passes();
+
+        // first and last live pass touching each transient
+        let mut spans: HashMap<&'static str, (usize, usize)> = HashMap::new();
+        for (index, pass) in self.passes.iter().enumerate().filter(|(i, _)| live[*i]) {
+            for name in pass.reads.iter().chain(&pass.writes) {
+                if let Some(Resource::Transient(_)) = self.resources.get(name) {
+                    spans
+                        .entry(*name)
+                        .and_modify(|span| span.1 = index)
+                        .or_insert((index, index));
+                }
+            }
+        }
+
+        // hand out pool slots in order of first use, reusing slots whose span has ended
+        let mut by_start: Vec<_> = spans.iter().map(|(name, span)| (*name, *span)).collect();
+        by_start.sort_by_key(|(name, (first, _))| (*first, *name));
+        let mut slots: HashMap<&'static str, (TransientDesc, usize)> = HashMap::new();
+        // per description, when each slot becomes free again
+        let mut busy_until: HashMap<TransientDesc, Vec<usize>> = HashMap::new();
+        for (name, (first, last)) in by_start {
+            let Some(Resource::Transient(desc)) = self.resources.get(name) else {
+                continue;
+            };
+            let slots_of_desc = busy_until.entry(*desc).or_default();
+            let slot = match slots_of_desc.iter().position(|until| *until < first) {
+                Some(slot) => slot,
+                None => {
+                    slots_of_desc.push(0);
+                    slots_of_desc.len() - 1
+                }
+            };
+            slots_of_desc[slot] = last;
+            slots.insert(name, (*desc, slot));
+        }
+
+        {
+            let mut textures = pool.textures.borrow_mut();
+            // descriptions this frame didn't use belong to an old size or a pass that's off
+            textures.retain(|desc, _| busy_until.contains_key(desc));
+            for (desc, slots_of_desc) in &busy_until {
+                let entry = textures.entry(*desc).or_default();
+                entry.truncate(slots_of_desc.len());
+                while entry.len() < slots_of_desc.len() {
+                    entry.push(create_transient(device, desc));
+                }
+            }
+        }
+
+        let textures = pool.textures.borrow();
+        let mut resources = GraphResources {
+            textures: HashMap::new(),
+        };
+        for (name, resource) in &self.resources {
+            match resource {
+                Resource::Imported(texture, view) => {
+                    resources.textures.insert(*name, (*texture, *view));
+                }
+                Resource::Transient(_) => {
+                    if let Some((desc, slot)) = slots.get(name) {
+                        let (texture, view) = &textures[desc][*slot];
+                        resources.textures.insert(*name, (texture, view));
+                    }
+                }
+            }
+        }
+        let mut command_buffers = vec![];
+        let mut passes = self
+            .passes
+            .into_iter()
+            .zip(live)
+            .filter_map(|(pass, live)| live.then_some(pass))
+            .peekable();
+        while let Some(pass) = passes.next() {
+            let run = match pass.run {
+                PassRun::Local(run) => {
+                    let query = claim(timestamps, pass.name);
+                    record(pass.name, run, &mut encoder, &resources, query);
+                    continue;
+                }
+                PassRun::Send(run) => run,
+            };
+            let mut batch = vec![(pass.name, run)];
+            while let Some(next) = passes.next_if(|next| matches!(next.run, PassRun::Send(_))) {
+                if let PassRun::Send(run) = next.run {
+                    batch.push((next.name, run));
+                }
+            }
+            let workers = match workers {
+                Some(workers) if batch.len() >= 2 => workers,
+                _ => {
+                    for (name, run) in batch {
+                        let query = claim(timestamps, name);
+                        record(name, run, &mut encoder, &resources, query);
+                    }
+                    continue;
+                }
+            };
+            // everything before the batch goes first, the batch's buffers keep their order
+            let before = std::mem::replace(&mut encoder, create_encoder(device, "render graph"));
+            command_buffers.push(before.finish());
+            // claimed here so the pairs follow the pass order, not the workers'
+            let batch: Vec<_> = batch
+                .into_iter()
+                .map(|(name, run)| (name, run, claim(timestamps, name)))
+                .collect();
+            let resources = &resources;
+            let finished: Vec<_> = workers.install(|| {
+                batch
+                    .into_par_iter()
+                    .map(|(name, run, query)| {
+                        let mut encoder = create_encoder(device, name);
+                        record(name, run, &mut encoder, resources, query);
+                        encoder.finish()
+                    })
+                    .collect()
+            });
+            command_buffers.extend(finished);
+        }
+        (command_buffers, encoder)
+    }
+}
+
+fn claim<'a>(
+    timestamps: Option<PassQueries<'a>>,
+    name: &'static str,
+) -> Option<(PassQueries<'a>, u32)> {
+    let timestamps = timestamps?;
+    Some((timestamps, timestamps.claim(name)?))
+}
+
+fn record(
+    name: &'static str,
+    run: impl FnOnce(&mut wgpu::CommandEncoder, &GraphResources),
+    encoder: &mut wgpu::CommandEncoder,
+    resources: &GraphResources,
+    query: Option<(PassQueries, u32)>,
+) {
+    profile_scope!("pass", name);
+    if let Some((timestamps, pass)) = query {
+        timestamps.begin(encoder, pass);
+    }
+    run(encoder, resources);
+    if let Some((timestamps, pass)) = query {
+        timestamps.end(encoder, pass);
+    }
+}
+
+fn create_encoder(device: &wgpu::Device, label: &str) -> wgpu::CommandEncoder {
+    device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some(label) })
+}
+
+fn create_transient(device: &wgpu::Device, desc: &TransientDesc) -> (Texture, TextureView) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("render graph transient"),
+        size: wgpu::Extent3d {
+            width: desc.width.max(1),
+            height: desc.height.max(1),
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: desc.format,
+        // anything a pass might do with an intermediate target
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+            | wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::COPY_SRC
+            | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    (texture, view)
+}
@@ -0,0 +1,193 @@
+use wgpu::{BindGroupLayout, Buffer, PipelineCompilationOptions, RenderPipeline, TextureView};
+
+use crate::{
+    frame_stats::{self, CountedPass},
+    hot_reload::wgsl,
+    tonemap::HDR_FORMAT,
+};
+
+pub const MAX_SUPERSAMPLE: u32 = 4;
+// below this the upscale stops looking like the scene
+const MIN_SCALE: f32 = 0.25;
+
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct ResampleParams {
+    // scene pixels per output pixel, per axis
+    scale: [f32; 2],
+    _pad: [f32; 2],
+}
+
+/// Renders the scene at a different size than the window and resamples it into the post
+/// chain's HDR target. Above 1 that's SSAA filtered down, below 1 it's dynamic resolution
+/// stretched back up.
+pub struct RenderScale {
+    scale: f32,
+    scene_size: (u32, u32),
+    window_size: (u32, u32),
+    max_texture_size: u32,
+    params_buffer: Buffer,
+    bind_group_layout: BindGroupLayout,
+    pipeline: RenderPipeline,
+}
+
+impl RenderScale {
+    pub fn new(device: &wgpu::Device, max_texture_size: u32) -> Self {
+        let code = wgsl!("resample.wgsl");
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("resample shader"),
+            source: wgpu::ShaderSource::Wgsl(code),
+        });
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("resample params"),
+            size: std::mem::size_of::<ResampleParams>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("resample bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("resample pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("resample pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "fullscreen_vs",
+                buffers: &[],
+                compilation_options: PipelineCompilationOptions::default(),
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "resample_fs",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: HDR_FORMAT,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: PipelineCompilationOptions::default(),
+            }),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            scale: 1.0,
+            scene_size: (1, 1),
+            window_size: (1, 1),
+            max_texture_size,
+            params_buffer,
+            bind_group_layout,
+            pipeline,
+        }
+    }
+
+    /// Size the scene renders at, as picked by the last resize.
+    pub fn scene_size(&self) -> (u32, u32) {
+        self.scene_size
+    }
+
+    /// Whether the scene needs its own target and a resample, rather than drawing straight
+    /// into the HDR target.
+    pub fn active(&self) -> bool {
+        self.scene_size != self.window_size
+    }
+
+    /// Sizes the scene at `scale` times a window of `width` x `height` on each axis,
+    /// lowered when that wouldn't fit the texture limit.
+    pub fn resize(&mut self, scale: f32, width: u32, height: u32) {
+        let (width, height) = (width.max(1), height.max(1));
+        let fits = self.max_texture_size as f32 / width.max(height) as f32;
+        let scale = scale.clamp(MIN_SCALE, MAX_SUPERSAMPLE as f32).min(fits);
+        if scale != self.scale {
+            println!("Render scale: {:.2}", scale);
+        }
+        self.scale = scale;
+        self.window_size = (width, height);
+        self.scene_size = (
+            ((width as f32 * scale).round() as u32).max(1),
+            ((height as f32 * scale).round() as u32).max(1),
+        );
+    }
+
+    /// Resamples the scene in `input`, sized as picked by the last resize, into `output`.
+    pub fn resample(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        queue: &wgpu::Queue,
+        input: &TextureView,
+        output: &TextureView,
+    ) {
+        let (width, height) = self.scene_size;
+        let (window_width, window_height) = self.window_size;
+        let params = ResampleParams {
+            scale: [
+                width as f32 / window_width as f32,
+                height as f32 / window_height as f32,
+            ],
+            _pad: [0.0; 2],
+        };
+        frame_stats::write_buffer(queue, &self.params_buffer, 0, bytemuck::bytes_of(&params));
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("resample bind group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(input),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.params_buffer.as_entire_binding(),
+                },
+            ],
+        });
+        let mut render_pass = CountedPass::begin(
+            encoder,
+            &wgpu::RenderPassDescriptor {
+                label: Some("resample pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: output,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                ..Default::default()
+            },
+        );
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,61 @@
+use wgpu::{Texture, TextureFormat, TextureUsages, TextureView};
+
+/// A 2D texture something renders into and later passes read, with the view they use.
+/// It keeps what it was made with, so `resize` can make it again at a new size.
+pub struct RenderTarget {
+    pub texture: Texture,
+    pub view: TextureView,
+    pub format: TextureFormat,
+    pub width: u32,
+    pub height: u32,
+    label: &'static str,
+    usage: TextureUsages,
+}
+
+impl RenderTarget {
+    /// Sizes are clamped to at least 1, a minimized window still gets a target.
+    pub fn new(
+        device: &wgpu::Device,
+        label: &'static str,
+        format: TextureFormat,
+        usage: TextureUsages,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let (width, height) = (width.max(1), height.max(1));
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Self {
+            texture,
+            view,
+            format,
+            width,
+            height,
+            label,
+            usage,
+        }
+    }
+
+    /// Makes the texture again at the new size; false, and nothing changes, when it
+    /// already is that size. Bind groups holding the old view have to be made again too.
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) -> bool {
+        if (width.max(1), height.max(1)) == (self.width, self.height) {
+            return false;
+        }
+        *self = Self::new(device, self.label, self.format, self.usage, width, height);
+        true
+    }
+}
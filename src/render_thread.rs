@@ -0,0 +1,156 @@
+use std::{
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use winit::{
+    dpi::PhysicalSize,
+    event::{ElementState, KeyEvent, MouseButton},
+    keyboard::{KeyCode, ModifiersState, PhysicalKey},
+};
+
+use crate::{error, GfxState, UserEvent};
+
+// how often an otherwise idle render thread polls the device for pending readbacks
+const READBACK_POLL_INTERVAL: Duration = Duration::from_millis(2);
+
+/// What the winit thread hands the render thread. Everything that touches the GPU, the
+/// scene or the camera happens over there, the winit thread only forwards. Serializable so
+/// input recordings can store it as is.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum FrameInput {
+    Resized(PhysicalSize<u32>),
+    Redraw,
+    Key(KeyInput),
+    // sent before the key events they apply to
+    Modifiers(ModifiersState),
+    // window pixels
+    CursorMoved(f64, f64),
+    CursorLeft,
+    // pressed or released
+    MouseInput(MouseButton, bool),
+    // a queued readback's map finished
+    ReadbackReady,
+}
+
+/// The parts of a key event the app reacts to. Unlike winit's `KeyEvent` it can be built
+/// outside winit, which replays need.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyInput {
+    pub key: KeyCode,
+    pub pressed: bool,
+    pub repeat: bool,
+    /// what the key typed with the current layout, for text entry
+    pub text: Option<String>,
+}
+
+impl KeyInput {
+    /// None for keys winit couldn't identify.
+    pub fn from_event(event: &KeyEvent) -> Option<Self> {
+        let PhysicalKey::Code(key) = event.physical_key else {
+            return None;
+        };
+        Some(Self {
+            key,
+            pressed: event.state == ElementState::Pressed,
+            repeat: event.repeat,
+            text: event.text.as_ref().map(|text| text.to_string()),
+        })
+    }
+}
+
+/// Owns the app state on its own thread so a slow frame never holds up the event loop.
+/// Dropping it closes the channel and waits for the thread to finish its current frame.
+pub struct RenderThread {
+    sender: Option<Sender<FrameInput>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl RenderThread {
+    pub fn spawn(app: GfxState) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let handle = std::thread::Builder::new()
+            .name("render".to_string())
+            .spawn(move || run(app, receiver))
+            .expect("Failed to spawn the render thread");
+        Self {
+            sender: Some(sender),
+            handle: Some(handle),
+        }
+    }
+
+    /// False once the render thread has stopped.
+    pub fn send(&self, input: FrameInput) -> bool {
+        let sent = self
+            .sender
+            .as_ref()
+            .is_some_and(|sender| sender.send(input).is_ok());
+        if !sent {
+            println!("Render thread is gone, input dropped");
+        }
+        sent
+    }
+}
+
+impl Drop for RenderThread {
+    fn drop(&mut self) {
+        self.sender = None;
+        if let Some(handle) = self.handle.take() {
+            if handle.join().is_err() {
+                error::report_fatal(&error::Error::RenderThread);
+            }
+        }
+    }
+}
+
+fn run(mut app: GfxState, receiver: Receiver<FrameInput>) {
+    if let Err(e) = frames(&mut app, &receiver) {
+        error::report_fatal(&e);
+        if let Some(event_proxy) = &app.window.event_proxy {
+            let _ = event_proxy.send_event(UserEvent::Exit);
+        }
+    }
+    app.shutdown();
+}
+
+/// Runs frames until the event loop hangs up, or a fatal error.
+fn frames(app: &mut GfxState, receiver: &Receiver<FrameInput>) -> error::Result<()> {
+    loop {
+        // map callbacks only fire from a poll; without frames coming, keep polling now and
+        // then until the queued readbacks are done
+        let waiting = app
+            .gpu_factory
+            .as_ref()
+            .is_some_and(|gpu_factory| !gpu_factory.readbacks.is_empty());
+        let first = if waiting {
+            match receiver.recv_timeout(READBACK_POLL_INTERVAL) {
+                Ok(input) => input,
+                Err(RecvTimeoutError::Timeout) => {
+                    app.gpu.device.poll(wgpu::Maintain::Poll);
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+        } else {
+            match receiver.recv() {
+                Ok(input) => input,
+                Err(_) => return Ok(()),
+            }
+        };
+        // everything that piled up during the last frame goes in before the next one, and
+        // any number of redraw requests make a single frame
+        let mut redraw = false;
+        for input in std::iter::once(first).chain(receiver.try_iter()) {
+            match input {
+                FrameInput::Redraw => redraw = true,
+                input => app.handle_input(input),
+            }
+        }
+        if redraw {
+            app.reload_changed_shaders();
+            app.redraw()?;
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "post")]
+use crate::bloom::BloomSettings;
+#[cfg(feature = "post")]
+use crate::heat_haze::HeatHazeSettings;
+#[cfg(feature = "path_tracing")]
+use crate::path_tracer::PathTracerSettings;
+#[cfg(feature = "post")]
+use crate::post_chain::PostPassSettings;
+use crate::{
+    ao_bake::AoBakeSettings,
+    auto_exposure::AutoExposureSettings,
+    boids::BoidsSettings,
+    ibl::IblSettings,
+    irradiance::IrradianceGridSettings,
+    light::{LocalLight, PointLightSettings},
+    lightmap::LightmapSettings,
+    particles::ParticleSettings,
+    planar_reflection::WaterSettings,
+    reflection_probe::ReflectionProbeSettings,
+    sdf_scene::SdfSettings,
+    shadow_map::ShadowSettings,
+    sky::{SkySettings, SkyUniform},
+    skybox::SkyboxSettings,
+    ssr::SsrSettings,
+    static_geometry::StaticBox,
+    time_of_day::{DayNightCycle, DirectionalLight},
+    tonemap::TonemapOperator,
+    weather::WeatherSettings,
+    wind::Wind,
+};
+
+/// Per scene settings, stored as ron next to the assets so every scene can be
+/// balanced on its own. Settings of subsystems left out of the build are skipped when
+/// loading, and lost if the scene is saved from that build.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Scene {
+    /// manual exposure in stops, 0 = scene values as is.
+    /// With auto exposure on this is the compensation on top of it.
+    pub exposure_ev: f32,
+    /// the curve into the SDR range, not used on an HDR surface
+    pub tonemap: TonemapOperator,
+    pub auto_exposure: AutoExposureSettings,
+    pub sky: SkySettings,
+    pub day_night: DayNightCycle,
+    pub weather: WeatherSettings,
+    pub wind: Wind,
+    pub particles: ParticleSettings,
+    pub boids: BoidsSettings,
+    #[cfg(feature = "post")]
+    pub heat_haze: HeatHazeSettings,
+    #[cfg(feature = "post")]
+    pub bloom: BloomSettings,
+    /// fullscreen effects after the tonemap, in order
+    #[cfg(feature = "post")]
+    pub post: Vec<PostPassSettings>,
+    pub ssr: SsrSettings,
+    pub water: WaterSettings,
+    pub reflection_probes: Vec<ReflectionProbeSettings>,
+    pub irradiance: IrradianceGridSettings,
+    pub static_boxes: Vec<StaticBox>,
+    /// glTF (.gltf, .glb) or OBJ files drawn as meshes, static and with their base colors
+    pub models: Vec<PathBuf>,
+    /// lights the models, not the ray cast scene
+    pub point_light: PointLightSettings,
+    /// more point lights on the models, only drawn on the deferred render path
+    pub lights: Vec<LocalLight>,
+    /// the sun's shadows on the models
+    pub shadows: ShadowSettings,
+    /// six images in place of the procedural sky
+    pub skybox: SkyboxSettings,
+    /// an HDR environment map lighting the PBR models, and behind the scene when the
+    /// skybox has no faces
+    pub ibl: IblSettings,
+    pub lightmap: LightmapSettings,
+    pub ao: AoBakeSettings,
+    #[cfg(feature = "path_tracing")]
+    pub path_tracer: PathTracerSettings,
+    /// the ray marched scene shown in place of this one, Ctrl+F5
+    pub sdf: SdfSettings,
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Self {
+            exposure_ev: 0.0,
+            tonemap: TonemapOperator::default(),
+            auto_exposure: AutoExposureSettings::default(),
+            sky: SkySettings::default(),
+            day_night: DayNightCycle::default(),
+            weather: WeatherSettings::default(),
+            wind: Wind::default(),
+            particles: ParticleSettings::default(),
+            boids: BoidsSettings::default(),
+            #[cfg(feature = "post")]
+            heat_haze: HeatHazeSettings::default(),
+            #[cfg(feature = "post")]
+            bloom: BloomSettings::default(),
+            #[cfg(feature = "post")]
+            post: vec![],
+            ssr: SsrSettings::default(),
+            water: WaterSettings::default(),
+            reflection_probes: vec![],
+            irradiance: IrradianceGridSettings::default(),
+            static_boxes: vec![],
+            models: vec![],
+            point_light: PointLightSettings::default(),
+            lights: vec![],
+            shadows: ShadowSettings::default(),
+            skybox: SkyboxSettings::default(),
+            ibl: IblSettings::default(),
+            lightmap: LightmapSettings::default(),
+            ao: AoBakeSettings::default(),
+            #[cfg(feature = "path_tracing")]
+            path_tracer: PathTracerSettings::default(),
+            sdf: SdfSettings::default(),
+        }
+    }
+}
+
+impl Scene {
+    /// The sky as rendered, with the weather on top of the configured settings.
+    pub fn weathered_sky(&self) -> SkySettings {
+        self.weather.sky(&self.sky)
+    }
+
+    pub fn sky_uniform(&self, light: &DirectionalLight, cloud_offset: [f32; 2]) -> SkyUniform {
+        let mut uniform = self.weathered_sky().uniform(light, cloud_offset);
+        uniform.water = self.water.uniform();
+        uniform
+    }
+
+    /// Relative paths are resolved against the crate directory.
+    pub fn resolve_path(path: &Path) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(path)
+    }
+
+    pub fn load(path: &Path) -> Self {
+        let path = Self::resolve_path(path);
+        match std::fs::read_to_string(&path) {
+            Ok(text) => match ron::from_str(&text) {
+                Ok(scene) => scene,
+                Err(e) => {
+                    println!("Bad scene {}: {}, using defaults", path.display(), e);
+                    Self::default()
+                }
+            },
+            Err(_) => {
+                println!("No scene at {}, using defaults", path.display());
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+        std::fs::write(Self::resolve_path(path), text)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,180 @@
+use std::borrow::Cow;
+
+use serde::{Deserialize, Serialize};
+use wgpu::{BindGroup, Buffer, PipelineCompilationOptions, RenderPipeline};
+
+use crate::{
+    error,
+    frame_stats::{self, CountedPass},
+    time_of_day::DirectionalLight,
+    tonemap::HDR_FORMAT,
+    wgsl_preprocessor,
+};
+
+/// How the SDF scene is marched.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SdfSettings {
+    /// steps along a ray before it counts as a miss
+    pub max_steps: u32,
+    /// meters, where a ray gives up and shows the background
+    pub max_distance: f32,
+    /// the soft shadows' penumbra, higher is sharper
+    pub shadow_hardness: f32,
+}
+
+impl Default for SdfSettings {
+    fn default() -> Self {
+        Self {
+            max_steps: 128,
+            max_distance: 80.0,
+            shadow_hardness: 12.0,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct SdfUniform {
+    resolution: [f32; 2],
+    time: f32,
+    max_steps: u32,
+    sun_direction: [f32; 3],
+    max_distance: f32,
+    sun_color: [f32; 3],
+    shadow_hardness: f32,
+    ambient: [f32; 3],
+    _pad: f32,
+}
+
+/// A fullscreen pass ray marching a scene of signed distance fields, in place of the display
+/// pass. It sees the camera through its inverse view projection and is lit by the sun,
+/// with nothing of the ray cast scene or the meshes.
+pub struct SdfScene {
+    uniform_buffer: Buffer,
+    bind_group: BindGroup,
+    pipeline: RenderPipeline,
+}
+
+impl SdfScene {
+    pub fn new(device: &wgpu::Device, camera_buffer: &Buffer) -> error::Result<Self> {
+        let code = wgsl_preprocessor::code("sdf_scene.wgsl", &[])?;
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("sdf scene shader"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Owned(code)),
+        });
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("sdf scene uniform"),
+            size: std::mem::size_of::<SdfUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let uniform_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("sdf scene bind group layout"),
+            entries: &[uniform_entry(0), uniform_entry(1)],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("sdf scene bind group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: camera_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("sdf scene pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("sdf scene"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "sdf_vs",
+                buffers: &[],
+                compilation_options: PipelineCompilationOptions::default(),
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "sdf_fs",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: HDR_FORMAT,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: PipelineCompilationOptions::default(),
+            }),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+        Ok(Self {
+            uniform_buffer,
+            bind_group,
+            pipeline,
+        })
+    }
+
+    /// Marches every pixel of `target`, `size` texels, at `time` seconds into the scene's
+    /// animation.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        queue: &wgpu::Queue,
+        settings: &SdfSettings,
+        light: &DirectionalLight,
+        time: f32,
+        target: &wgpu::TextureView,
+        size: (u32, u32),
+    ) {
+        let uniform = SdfUniform {
+            resolution: [size.0 as f32, size.1 as f32],
+            time,
+            max_steps: settings.max_steps,
+            sun_direction: light.direction.into(),
+            max_distance: settings.max_distance,
+            sun_color: light.color.map(|c| c * light.intensity),
+            shadow_hardness: settings.shadow_hardness,
+            ambient: light.ambient,
+            _pad: 0.0,
+        };
+        frame_stats::write_buffer(queue, &self.uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+        let mut render_pass = CountedPass::begin(
+            encoder,
+            &wgpu::RenderPassDescriptor {
+                label: Some("sdf scene pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                ..Default::default()
+            },
+        );
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
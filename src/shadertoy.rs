@@ -0,0 +1,61 @@
+use crate::{hot_reload::wgsl, GfxState};
+
+/// The uniform every Shadertoy-style pipeline shares, written each frame by the factory.
+pub const UNIFORM_NAME: &str = "shadertoy";
+
+/// `code`, a toy's `mainImage` and whatever it calls, with the entry points and the
+/// iResolution, iTime, iTimeDelta, iFrame and iMouse it reads put in front. The GLSL
+/// `out vec4 fragColor` becomes a pointer:
+///
+///     fn mainImage(fragColor: ptr<function, vec4f>, fragCoord: vec2f) {
+///         *fragColor = vec4(fragCoord / iResolution.xy, 0.0, 1.0);
+///     }
+///
+/// `fragCoord` and iMouse count pixels up from the bottom left as on Shadertoy, over the
+/// scene's size rather than the window's.
+pub fn wrap(code: &str) -> String {
+    format!("{}\n{}", wgsl!("shadertoy.wgsl"), code)
+}
+
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct ShadertoyUniform {
+    resolution: [f32; 3],
+    time: f32,
+    mouse: [f32; 4],
+    time_delta: f32,
+    frame: i32,
+    _pad: [u32; 2],
+}
+
+impl ShadertoyUniform {
+    /// For toys drawn over a scene of `size` pixels.
+    pub fn new(app: &GfxState, size: (u32, u32)) -> Self {
+        Self {
+            resolution: [size.0 as f32, size.1 as f32, 1.0],
+            time: app.frame.time,
+            mouse: Self::mouse(app, size),
+            time_delta: app.frame.dt,
+            // from 0 like Shadertoy's, `count` already takes this frame in
+            frame: app.frame.count.saturating_sub(1) as i32,
+            _pad: [0; 2],
+        }
+    }
+
+    // `Mouse` counts window pixels down from the top left
+    fn mouse(app: &GfxState, size: (u32, u32)) -> [f32; 4] {
+        let mouse = app.frame.mouse.uniform();
+        // no click yet
+        if mouse == [0.0; 4] {
+            return mouse;
+        }
+        let window = &app.window.surface_config;
+        let scale = [
+            size.0 as f32 / window.width as f32,
+            size.1 as f32 / window.height as f32,
+        ];
+        let up = |y: f32| (window.height as f32 - y) * scale[1];
+        let [x, y, z, w] = mouse;
+        [x * scale[0], up(y), z * scale[0], up(w.abs()).copysign(w)]
+    }
+}
@@ -0,0 +1,335 @@
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, Vector3};
+use serde::{Deserialize, Serialize};
+use wgpu::{BindGroup, Buffer, RenderPipeline, Sampler, Texture, TextureView};
+
+use crate::{
+    camera::OPENGL_TO_WGPU_MATRIX,
+    frame_stats,
+    hot_reload::wgsl,
+    instance::InstanceRaw,
+    vertex::{MeshVertex, Vertex},
+};
+
+pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+/// The sun's (or moon's) shadows on the meshes. The ray cast scene traces its own shadows
+/// and doesn't read the shadow map.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShadowSettings {
+    pub enabled: bool,
+    /// texels along each side of the shadow map, read when the renderer is built
+    pub resolution: u32,
+    /// depth the compare is pushed towards the light by, against acne
+    pub bias: f32,
+    /// meters along the normal the lookup starts from, against acne on grazing surfaces
+    pub normal_offset: f32,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            resolution: 2048,
+            bias: 0.002,
+            normal_offset: 0.02,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct ShadowUniform {
+    view_proj: [[f32; 4]; 4],
+    bias: f32,
+    normal_offset: f32,
+    texel_size: f32,
+    // 0 when there's nothing to shadow or shadows are off, the shaders then skip the map
+    enabled: f32,
+}
+
+/// A depth map of the meshes as the directional light sees them, drawn by its own pass
+/// before the display pass. The light's orthographic box is fitted around every mesh
+/// added, so the whole map goes to what can cast a shadow.
+///
+/// The material pipelines read it through the light bind group (`light::Light`): the
+/// uniform, the depth texture and the comparison sampler.
+pub struct ShadowMap {
+    pub texture: Texture,
+    pub view: TextureView,
+    pub sampler: Sampler,
+    pub uniform_buffer: Buffer,
+    resolution: u32,
+    // world space box around the meshes, None before the first one
+    bounds: Option<(Point3<f32>, Point3<f32>)>,
+    pass_bind_group: BindGroup,
+    pipeline: RenderPipeline,
+}
+
+impl ShadowMap {
+    pub fn new(device: &wgpu::Device, settings: &ShadowSettings) -> Self {
+        let resolution = settings
+            .resolution
+            .clamp(1, device.limits().max_texture_dimension_2d);
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("shadow map"),
+            size: wgpu::Extent3d {
+                width: resolution,
+                height: resolution,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("shadow sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("shadow uniform"),
+            size: std::mem::size_of::<ShadowUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let pass_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("shadow pass bind group layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            });
+        let pass_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("shadow pass bind group"),
+            layout: &pass_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+        let code = wgsl!("shadow.wgsl");
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("shadow shader"),
+            source: wgpu::ShaderSource::Wgsl(code),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("shadow pipeline layout"),
+            bind_group_layouts: &[&pass_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("shadow pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "shadow_vs",
+                buffers: &[MeshVertex::layout(), InstanceRaw::layout()],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            // both faces, so open and double sided meshes cast shadows too
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                cull_mode: None,
+                ..Default::default()
+            },
+            fragment: None,
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState {
+                    constant: 2,
+                    slope_scale: 2.0,
+                    clamp: 0.0,
+                },
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+        Self {
+            texture,
+            view,
+            sampler,
+            uniform_buffer,
+            resolution,
+            bounds: None,
+            pass_bind_group,
+            pipeline,
+        }
+    }
+
+    /// Grows the light's box to take in `points`, the vertices of each mesh added where
+    /// its instances put them.
+    pub fn include(&mut self, points: impl IntoIterator<Item = Point3<f32>>) {
+        for p in points {
+            self.bounds = Some(match self.bounds {
+                Some((min, max)) => (
+                    Point3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z)),
+                    Point3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z)),
+                ),
+                None => (p, p),
+            });
+        }
+    }
+
+    /// Whether there's anything to draw into the map this frame.
+    pub fn active(&self, settings: &ShadowSettings) -> bool {
+        settings.enabled && self.bounds.is_some()
+    }
+
+    /// Aims the map along `light_direction`, which points at the light.
+    pub fn write_uniform(
+        &self,
+        queue: &wgpu::Queue,
+        settings: &ShadowSettings,
+        light_direction: [f32; 3],
+    ) {
+        let view_proj = match self.bounds {
+            Some((min, max)) => Self::view_proj(min, max, light_direction.into()),
+            None => Matrix4::from_scale(1.0),
+        };
+        let uniform = ShadowUniform {
+            view_proj: view_proj.into(),
+            bias: settings.bias,
+            normal_offset: settings.normal_offset,
+            texel_size: 1.0 / self.resolution as f32,
+            enabled: if self.active(settings) { 1.0 } else { 0.0 },
+        };
+        frame_stats::write_buffer(queue, &self.uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+    }
+
+    /// An orthographic view down the light direction, just big enough for the sphere
+    /// around the box.
+    fn view_proj(
+        min: Point3<f32>,
+        max: Point3<f32>,
+        light_direction: Vector3<f32>,
+    ) -> Matrix4<f32> {
+        let center = min.midpoint(max);
+        // a little margin so nothing sits right on the near and far planes
+        let radius = (max - min).magnitude() * 0.5 + 0.1;
+        let direction = light_direction.normalize();
+        let up = if direction.y.abs() > 0.99 {
+            Vector3::unit_z()
+        } else {
+            Vector3::unit_y()
+        };
+        let eye = center + direction * radius * 2.0;
+        let view = Matrix4::look_at_rh(eye, center, up);
+        let proj = cgmath::ortho(-radius, radius, -radius, radius, radius, radius * 3.0);
+        OPENGL_TO_WGPU_MATRIX * proj * view
+    }
+
+    /// The light bind group's entries after the point light: the shadow uniform, the depth
+    /// texture and the comparison sampler, from `first_binding` on.
+    pub fn bind_group_layout_entries(first_binding: u32) -> [wgpu::BindGroupLayoutEntry; 3] {
+        [
+            wgpu::BindGroupLayoutEntry {
+                binding: first_binding,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: first_binding + 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Depth,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: first_binding + 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                count: None,
+            },
+        ]
+    }
+
+    /// What `bind_group_layout_entries` describes, for the light bind group.
+    pub fn bind_group_entries(&self, first_binding: u32) -> [wgpu::BindGroupEntry<'_>; 3] {
+        [
+            wgpu::BindGroupEntry {
+                binding: first_binding,
+                resource: self.uniform_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: first_binding + 1,
+                resource: wgpu::BindingResource::TextureView(&self.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: first_binding + 2,
+                resource: wgpu::BindingResource::Sampler(&self.sampler),
+            },
+        ]
+    }
+
+    /// Draws the opaque meshes into the map; `meshes` gives each one's vertex buffer, index
+    /// buffer and format, index count, instance buffer and instance count.
+    pub fn render<'a>(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        meshes: impl Iterator<
+            Item = (
+                &'a Buffer,
+                &'a Buffer,
+                wgpu::IndexFormat,
+                u32,
+                &'a Buffer,
+                u32,
+            ),
+        >,
+    ) {
+        let mut render_pass = frame_stats::CountedPass::begin(
+            encoder,
+            &wgpu::RenderPassDescriptor {
+                label: Some("shadow pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                ..Default::default()
+            },
+        );
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.pass_bind_group, &[]);
+        for (vertex_buffer, index_buffer, index_format, index_count, instance_buffer, instances) in
+            meshes
+        {
+            render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+            render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
+            render_pass.set_index_buffer(index_buffer.slice(..), index_format);
+            render_pass.draw_indexed(0..index_count, 0, 0..instances);
+        }
+    }
+}
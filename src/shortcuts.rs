@@ -0,0 +1,338 @@
+use std::{collections::BTreeMap, fmt};
+
+use anyhow::{anyhow, bail};
+use winit::keyboard::{KeyCode, ModifiersState};
+
+#[cfg(feature = "ui")]
+use crate::text::TextRenderer;
+
+#[cfg(feature = "ui")]
+const HEADER_COLOR: [f32; 4] = [1.0, 0.85, 0.3, 1.0];
+#[cfg(feature = "ui")]
+const TEXT_COLOR: [f32; 4] = [0.95, 0.95, 0.95, 1.0];
+#[cfg(feature = "ui")]
+const FIXED_COLOR: [f32; 4] = [0.6, 0.6, 0.6, 1.0];
+#[cfg(feature = "ui")]
+const PANEL_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.6];
+// pixels around the page's text
+#[cfg(feature = "ui")]
+const MARGIN: f32 = 8.0;
+
+/// A key plus the modifiers held with it, written "Ctrl+Shift+S" in the config and the
+/// bindings page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chord {
+    pub key: KeyCode,
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+}
+
+impl Chord {
+    pub const fn key(key: KeyCode) -> Self {
+        Self {
+            key,
+            ctrl: false,
+            shift: false,
+            alt: false,
+        }
+    }
+
+    pub const fn ctrl(key: KeyCode) -> Self {
+        Self {
+            ctrl: true,
+            ..Self::key(key)
+        }
+    }
+
+    /// The chord a key press makes with the modifiers currently held. Super is left out, the
+    /// window manager tends to own it.
+    pub fn pressed(key: KeyCode, modifiers: ModifiersState) -> Self {
+        Self {
+            key,
+            ctrl: modifiers.control_key(),
+            shift: modifiers.shift_key(),
+            alt: modifiers.alt_key(),
+        }
+    }
+
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        // modifiers are peeled off the front so keys like "Num+" keep their plus
+        let mut rest = text.trim();
+        let mut chord = Self::key(KeyCode::Escape);
+        loop {
+            let lower = rest.to_ascii_lowercase();
+            let modifier = if lower.starts_with("ctrl+") {
+                &mut chord.ctrl
+            } else if lower.starts_with("shift+") {
+                &mut chord.shift
+            } else if lower.starts_with("alt+") {
+                &mut chord.alt
+            } else {
+                break;
+            };
+            *modifier = true;
+            rest = rest[lower.find('+').unwrap() + 1..].trim_start();
+        }
+        chord.key = KEY_NAMES
+            .iter()
+            .find(|(_, name)| name.eq_ignore_ascii_case(rest))
+            .map(|(key, _)| *key)
+            .ok_or_else(|| anyhow!("\"{}\": unknown key {:?}", text, rest))?;
+        Ok(chord)
+    }
+}
+
+impl fmt::Display for Chord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.ctrl {
+            write!(f, "Ctrl+")?;
+        }
+        if self.shift {
+            write!(f, "Shift+")?;
+        }
+        if self.alt {
+            write!(f, "Alt+")?;
+        }
+        match KEY_NAMES.iter().find(|(key, _)| *key == self.key) {
+            Some((_, name)) => write!(f, "{}", name),
+            None => write!(f, "{:?}", self.key),
+        }
+    }
+}
+
+/// One action and the chords that trigger it.
+pub struct Binding {
+    pub action: &'static str,
+    /// the subsystem that registered it, for the bindings page and conflict messages
+    pub owner: &'static str,
+    pub chords: Vec<Chord>,
+    /// read straight off the key state by its owner (camera movement), listed so nothing
+    /// else takes the keys but not rebindable
+    pub fixed: bool,
+}
+
+/// Every shortcut subsystems registered, looked up by chord on key presses. A chord maps to
+/// at most one action: registering or rebinding onto a taken chord is a conflict, reported
+/// and refused, so the first registration wins.
+#[derive(Default)]
+pub struct Shortcuts {
+    bindings: Vec<Binding>,
+    /// the bindings page, toggled with F1
+    #[cfg(feature = "ui")]
+    pub page_visible: bool,
+}
+
+impl Shortcuts {
+    pub fn register(&mut self, owner: &'static str, action: &'static str, chords: &[Chord]) {
+        self.add(owner, action, chords, false);
+    }
+
+    /// Keys `owner` reads itself, claimed so no shortcut is registered over them.
+    pub fn register_fixed(&mut self, owner: &'static str, action: &'static str, chords: &[Chord]) {
+        self.add(owner, action, chords, true);
+    }
+
+    fn add(&mut self, owner: &'static str, action: &'static str, chords: &[Chord], fixed: bool) {
+        if self.bindings.iter().any(|binding| binding.action == action) {
+            println!(
+                "Shortcut conflict: {} registers \"{}\" twice, ignored",
+                owner, action
+            );
+            return;
+        }
+        let mut free = vec![];
+        for chord in chords {
+            match self.binding_for(*chord) {
+                Some(taken) => println!(
+                    "Shortcut conflict: {} for \"{}\" ({}) is already \"{}\" ({})",
+                    chord, action, owner, taken.action, taken.owner
+                ),
+                None => free.push(*chord),
+            }
+        }
+        self.bindings.push(Binding {
+            action,
+            owner,
+            chords: free,
+            fixed,
+        });
+    }
+
+    /// Replaces all of `action`'s chords with `chord`.
+    pub fn rebind(&mut self, action: &str, chord: Chord) -> anyhow::Result<()> {
+        if let Some(taken) = self
+            .binding_for(chord)
+            .filter(|binding| binding.action != action)
+        {
+            bail!(
+                "{} is already \"{}\" ({})",
+                chord,
+                taken.action,
+                taken.owner
+            );
+        }
+        let binding = self
+            .bindings
+            .iter_mut()
+            .find(|binding| binding.action == action)
+            .ok_or_else(|| anyhow!("no action \"{}\"", action))?;
+        if binding.fixed {
+            bail!("\"{}\" is read by {} directly", action, binding.owner);
+        }
+        binding.chords = vec![chord];
+        Ok(())
+    }
+
+    /// Applies the config's `action: "chord"` overrides, skipping the ones that don't parse
+    /// or conflict.
+    pub fn rebind_all(&mut self, overrides: &BTreeMap<String, String>) {
+        for (action, chord) in overrides {
+            if let Err(e) = Chord::parse(chord).and_then(|chord| self.rebind(action, chord)) {
+                println!("Shortcut for \"{}\" not rebound: {:#}", action, e);
+            }
+        }
+    }
+
+    /// The action `chord` triggers; fixed bindings never match, their owners read the keys.
+    pub fn action(&self, chord: Chord) -> Option<&'static str> {
+        self.binding_for(chord)
+            .filter(|binding| !binding.fixed)
+            .map(|binding| binding.action)
+    }
+
+    fn binding_for(&self, chord: Chord) -> Option<&Binding> {
+        self.bindings
+            .iter()
+            .find(|binding| binding.chords.contains(&chord))
+    }
+
+    /// Queues the bindings page, grouped by owner, against the right edge of a `width`
+    /// pixels wide target.
+    #[cfg(feature = "ui")]
+    pub fn draw_page(&self, text: &TextRenderer, width: f32) {
+        let mut owners: Vec<&str> = vec![];
+        for binding in &self.bindings {
+            if !owners.contains(&binding.owner) {
+                owners.push(binding.owner);
+            }
+        }
+        let mut lines = vec![("Shortcuts (F1)".to_string(), HEADER_COLOR)];
+        for owner in owners {
+            lines.push((owner.to_string(), HEADER_COLOR));
+            for binding in self.bindings.iter().filter(|b| b.owner == owner) {
+                let chords = binding
+                    .chords
+                    .iter()
+                    .map(Chord::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                lines.push((
+                    format!(
+                        "    {:<14} {}",
+                        if chords.is_empty() { "-" } else { &chords },
+                        binding.action
+                    ),
+                    if binding.fixed {
+                        FIXED_COLOR
+                    } else {
+                        TEXT_COLOR
+                    },
+                ));
+            }
+        }
+
+        let columns = lines.iter().map(|(line, _)| line.len()).max().unwrap_or(0);
+        let panel_width = columns as f32 * text.char_width() + 2.0 * MARGIN;
+        let x = (width - panel_width).max(0.0);
+        text.rect(
+            x,
+            0.0,
+            panel_width,
+            lines.len() as f32 * text.line_height() + 2.0 * MARGIN,
+            PANEL_COLOR,
+        );
+        for (row, (line, color)) in lines.iter().enumerate() {
+            text.text(
+                x + MARGIN,
+                MARGIN + row as f32 * text.line_height(),
+                line,
+                *color,
+            );
+        }
+    }
+}
+
+// the keys chords can be written with; anything else shows as its KeyCode
+const KEY_NAMES: &[(KeyCode, &str)] = &[
+    (KeyCode::KeyA, "A"),
+    (KeyCode::KeyB, "B"),
+    (KeyCode::KeyC, "C"),
+    (KeyCode::KeyD, "D"),
+    (KeyCode::KeyE, "E"),
+    (KeyCode::KeyF, "F"),
+    (KeyCode::KeyG, "G"),
+    (KeyCode::KeyH, "H"),
+    (KeyCode::KeyI, "I"),
+    (KeyCode::KeyJ, "J"),
+    (KeyCode::KeyK, "K"),
+    (KeyCode::KeyL, "L"),
+    (KeyCode::KeyM, "M"),
+    (KeyCode::KeyN, "N"),
+    (KeyCode::KeyO, "O"),
+    (KeyCode::KeyP, "P"),
+    (KeyCode::KeyQ, "Q"),
+    (KeyCode::KeyR, "R"),
+    (KeyCode::KeyS, "S"),
+    (KeyCode::KeyT, "T"),
+    (KeyCode::KeyU, "U"),
+    (KeyCode::KeyV, "V"),
+    (KeyCode::KeyW, "W"),
+    (KeyCode::KeyX, "X"),
+    (KeyCode::KeyY, "Y"),
+    (KeyCode::KeyZ, "Z"),
+    (KeyCode::Digit0, "0"),
+    (KeyCode::Digit1, "1"),
+    (KeyCode::Digit2, "2"),
+    (KeyCode::Digit3, "3"),
+    (KeyCode::Digit4, "4"),
+    (KeyCode::Digit5, "5"),
+    (KeyCode::Digit6, "6"),
+    (KeyCode::Digit7, "7"),
+    (KeyCode::Digit8, "8"),
+    (KeyCode::Digit9, "9"),
+    (KeyCode::F1, "F1"),
+    (KeyCode::F2, "F2"),
+    (KeyCode::F3, "F3"),
+    (KeyCode::F4, "F4"),
+    (KeyCode::F5, "F5"),
+    (KeyCode::F6, "F6"),
+    (KeyCode::F7, "F7"),
+    (KeyCode::F8, "F8"),
+    (KeyCode::F9, "F9"),
+    (KeyCode::F10, "F10"),
+    (KeyCode::F11, "F11"),
+    (KeyCode::F12, "F12"),
+    (KeyCode::Minus, "-"),
+    (KeyCode::Equal, "="),
+    (KeyCode::BracketLeft, "["),
+    (KeyCode::BracketRight, "]"),
+    (KeyCode::Semicolon, ";"),
+    (KeyCode::Quote, "'"),
+    (KeyCode::Comma, ","),
+    (KeyCode::Period, "."),
+    (KeyCode::Slash, "/"),
+    (KeyCode::Backslash, "\\"),
+    (KeyCode::Backquote, "`"),
+    (KeyCode::NumpadAdd, "Num+"),
+    (KeyCode::NumpadSubtract, "Num-"),
+    (KeyCode::ArrowUp, "Up"),
+    (KeyCode::ArrowDown, "Down"),
+    (KeyCode::ArrowLeft, "Left"),
+    (KeyCode::ArrowRight, "Right"),
+    (KeyCode::Space, "Space"),
+    (KeyCode::Enter, "Enter"),
+    (KeyCode::Tab, "Tab"),
+    (KeyCode::Escape, "Esc"),
+    (KeyCode::Backspace, "Backspace"),
+];
@@ -0,0 +1,164 @@
+use cgmath::{InnerSpace, Vector3};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "ui")]
+use crate::console::{console_var, Console};
+use crate::{time_of_day::DirectionalLight, weather::SNOW_COVER_EXTENT};
+
+/// Sun and atmosphere for the analytic (Preetham) sky. Everything that needs the sun
+/// (sky, fog, lights) takes its direction from here so they stay consistent.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SkySettings {
+    /// degrees above the horizon
+    pub sun_elevation: f32,
+    /// degrees, 0 looks down -z, 90 down +x
+    pub sun_azimuth: f32,
+    /// haziness of the atmosphere, 2 is very clear, 10 is hazy
+    pub turbidity: f32,
+    /// scale from the model's kcd/m² to scene values
+    pub intensity: f32,
+    /// angular radius of the moon disk in degrees, exaggerated by default
+    pub moon_size: f32,
+    pub star_brightness: f32,
+    /// angular radius of the sun disk in degrees
+    pub sun_size: f32,
+    pub flare_intensity: f32,
+    /// 0 clear sky .. 1 overcast
+    pub cloud_coverage: f32,
+    /// meters per second the cloud layer drifts
+    pub cloud_speed: f32,
+    /// altitude of the cloud layer in meters
+    pub cloud_height: f32,
+    /// 0 dry .. 1 soaked, wet ground darkens and turns reflective
+    pub ground_wetness: f32,
+}
+
+impl Default for SkySettings {
+    fn default() -> Self {
+        Self {
+            sun_elevation: 30.0,
+            sun_azimuth: 0.0,
+            turbidity: 3.0,
+            intensity: 0.1,
+            moon_size: 1.5,
+            star_brightness: 1.0,
+            sun_size: 0.5,
+            flare_intensity: 1.0,
+            cloud_coverage: 0.45,
+            cloud_speed: 20.0,
+            cloud_height: 1500.0,
+            ground_wetness: 0.0,
+        }
+    }
+}
+
+impl SkySettings {
+    /// `sky.*` console variables; the sky uniform is rebuilt from them every frame. With the
+    /// day/night cycle on it overrides the sun's position.
+    #[cfg(feature = "ui")]
+    pub fn register_console(console: &mut Console) {
+        console_var!(
+            console,
+            "sky.sun_elevation",
+            "degrees",
+            scene.sky.sun_elevation
+        );
+        console_var!(console, "sky.sun_azimuth", "degrees", scene.sky.sun_azimuth);
+        console_var!(
+            console,
+            "sky.turbidity",
+            "2 clear .. 10 hazy",
+            scene.sky.turbidity
+        );
+        console_var!(console, "sky.intensity", "", scene.sky.intensity);
+        console_var!(
+            console,
+            "sky.cloud_coverage",
+            "0 .. 1",
+            scene.sky.cloud_coverage
+        );
+        console_var!(console, "sky.cloud_speed", "m/s", scene.sky.cloud_speed);
+        console_var!(
+            console,
+            "sky.ground_wetness",
+            "0 .. 1",
+            scene.sky.ground_wetness
+        );
+    }
+
+    /// unit vector pointing towards the sun
+    pub fn sun_direction(&self) -> Vector3<f32> {
+        let elevation = self.sun_elevation.to_radians();
+        let azimuth = self.sun_azimuth.to_radians();
+        Vector3::new(
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+            -elevation.cos() * azimuth.cos(),
+        )
+        .normalize()
+    }
+
+    /// The moon sits opposite the sun, a little off so it isn't always straight below
+    /// the sunset.
+    pub fn moon_direction(&self) -> Vector3<f32> {
+        let elevation = (-self.sun_elevation + 10.0).to_radians();
+        let azimuth = (self.sun_azimuth + 160.0).to_radians();
+        Vector3::new(
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+            -elevation.cos() * azimuth.cos(),
+        )
+        .normalize()
+    }
+
+    /// `light` is the scene's main light (lights the ground and the clouds),
+    /// `cloud_offset` how far the cloud layer has drifted so far.
+    pub fn uniform(&self, light: &DirectionalLight, cloud_offset: [f32; 2]) -> SkyUniform {
+        SkyUniform {
+            sun_direction: self.sun_direction().into(),
+            turbidity: self.turbidity.clamp(1.7, 10.0),
+            moon_direction: self.moon_direction().into(),
+            intensity: self.intensity,
+            moon_radius: self.moon_size.to_radians(),
+            star_brightness: self.star_brightness,
+            sun_radius: self.sun_size.to_radians(),
+            cloud_coverage: self.cloud_coverage.clamp(0.0, 1.0),
+            light_color: light.color,
+            light_intensity: light.intensity,
+            light_direction: light.direction.into(),
+            cloud_height: self.cloud_height,
+            ambient: light.ambient,
+            snow_extent: SNOW_COVER_EXTENT,
+            cloud_offset,
+            ground_wetness: self.ground_wetness.clamp(0.0, 1.0),
+            _pad1: 0.0,
+            water: [0.0; 4],
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct SkyUniform {
+    pub sun_direction: [f32; 3],
+    pub turbidity: f32,
+    pub moon_direction: [f32; 3],
+    pub intensity: f32,
+    pub moon_radius: f32,
+    pub star_brightness: f32,
+    pub sun_radius: f32,
+    pub cloud_coverage: f32,
+    pub light_color: [f32; 3],
+    pub light_intensity: f32,
+    pub light_direction: [f32; 3],
+    pub cloud_height: f32,
+    pub ambient: [f32; 3],
+    /// meters covered by the snow cover texture
+    pub snow_extent: f32,
+    pub cloud_offset: [f32; 2],
+    pub ground_wetness: f32,
+    _pad1: f32,
+    /// center xz, radius, enabled; see WaterSettings::uniform
+    pub water: [f32; 4],
+}
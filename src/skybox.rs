@@ -0,0 +1,239 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, ensure};
+use serde::{Deserialize, Serialize};
+use wgpu::{BindGroup, BindGroupLayout, Buffer, RenderPipeline, Sampler, TextureView};
+
+use crate::{
+    asset_loader::DecodedTexture,
+    depth_buffer::DepthBuffer,
+    frame_stats::{self, CountedPass},
+    gbuffer::GBuffer,
+    hot_reload::wgsl,
+    scene::Scene,
+    texture::{self, ImageTexture},
+    tonemap::HDR_FORMAT,
+};
+
+/// A sky from six images instead of the procedural one. Only what the camera sees
+/// changes: the sun, the ambient light and the reflections still come from the
+/// procedural sky.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SkyboxSettings {
+    /// the faces in the +X, -X, +Y, -Y, +Z, -Z order, square and all the same size;
+    /// empty for the procedural sky
+    pub faces: Vec<PathBuf>,
+    /// scales the images' colors into the scene's light levels, also those of an
+    /// environment map drawn as the background
+    pub intensity: f32,
+}
+
+impl Default for SkyboxSettings {
+    fn default() -> Self {
+        Self {
+            faces: vec![],
+            intensity: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct SkyboxUniform {
+    intensity: f32,
+    _pad: [f32; 3],
+}
+
+/// A cube map drawn behind everything in the display pass. It goes after the ray cast
+/// scene and the opaque meshes at the far plane, with a less-equal depth test against
+/// the depth buffer, so it only lands where the ray cast left sky. The G-buffer keeps
+/// what the ray cast wrote there.
+pub struct Skybox {
+    uniform_buffer: Buffer,
+    bind_group: BindGroup,
+    pipeline: RenderPipeline,
+}
+
+/// Reads six face images for a cube map, sRGB.
+pub fn decode_faces(paths: &[PathBuf]) -> anyhow::Result<[DecodedTexture; 6]> {
+    ensure!(
+        paths.len() == 6,
+        "a cube map needs 6 faces, got {}",
+        paths.len()
+    );
+    let faces: Vec<DecodedTexture> = paths
+        .iter()
+        .map(|path| texture::decode(&Scene::resolve_path(path), true))
+        .collect::<anyhow::Result<_>>()?;
+    let (width, height) = (faces[0].width, faces[0].height);
+    if width != height || faces.iter().any(|f| (f.width, f.height) != (width, height)) {
+        bail!("cube map faces have to be square and the same size");
+    }
+    Ok(faces
+        .try_into()
+        .unwrap_or_else(|_| unreachable!("checked for 6 faces")))
+}
+
+impl Skybox {
+    /// None when the settings name no faces; an error when the faces can't be read.
+    /// `scene_layout` and `camera_layout` are the display pipeline's groups 0 and 1, so
+    /// the pass keeps them bound for the skybox.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        settings: &SkyboxSettings,
+        scene_layout: &BindGroupLayout,
+        camera_layout: &BindGroupLayout,
+    ) -> anyhow::Result<Option<Self>> {
+        if settings.faces.is_empty() {
+            return Ok(None);
+        }
+        let faces = decode_faces(&settings.faces)?;
+        let max_size = device.limits().max_texture_dimension_2d;
+        ensure!(
+            faces[0].width <= max_size,
+            "cube map faces are over {} texels",
+            max_size
+        );
+        let cube = ImageTexture::cube_from_decoded(device, queue, "skybox", &faces);
+        println!("Loaded a {}² skybox", faces[0].width);
+        Ok(Some(Self::from_cube(
+            device,
+            &cube.view,
+            &cube.sampler,
+            scene_layout,
+            camera_layout,
+        )))
+    }
+
+    /// Draws a cube map made elsewhere, e.g. the `ibl::HdrEnvironment`'s.
+    pub fn from_cube(
+        device: &wgpu::Device,
+        view: &TextureView,
+        sampler: &Sampler,
+        scene_layout: &BindGroupLayout,
+        camera_layout: &BindGroupLayout,
+    ) -> Self {
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("skybox uniform"),
+            size: std::mem::size_of::<SkyboxUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("skybox bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("skybox bind group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+        });
+
+        let code = wgsl!("skybox.wgsl");
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("skybox shader"),
+            source: wgpu::ShaderSource::Wgsl(code),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("skybox pipeline layout"),
+            bind_group_layouts: &[scene_layout, camera_layout, &bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let mut targets = vec![Some(wgpu::ColorTargetState {
+            format: HDR_FORMAT,
+            blend: None,
+            write_mask: wgpu::ColorWrites::ALL,
+        })];
+        targets.extend(GBuffer::color_targets().map(|target| {
+            target.map(|target| wgpu::ColorTargetState {
+                write_mask: wgpu::ColorWrites::empty(),
+                ..target
+            })
+        }));
+        let mut depth_stencil = DepthBuffer::depth_stencil_state();
+        depth_stencil.depth_write_enabled = false;
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("skybox pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "skybox_vs",
+                buffers: &[],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "skybox_fs",
+                targets: &targets,
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            depth_stencil: Some(depth_stencil),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+        Self {
+            uniform_buffer,
+            bind_group,
+            pipeline,
+        }
+    }
+
+    pub fn write_uniform(&self, queue: &wgpu::Queue, settings: &SkyboxSettings) {
+        let uniform = SkyboxUniform {
+            intensity: settings.intensity,
+            _pad: [0.0; 3],
+        };
+        frame_stats::write_buffer(queue, &self.uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+    }
+
+    /// Draws into the display pass, with the scene and camera bind groups still bound at
+    /// 0 and 1.
+    pub fn draw<'a>(&'a self, render_pass: &mut CountedPass<'a>) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(2, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
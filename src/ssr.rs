@@ -0,0 +1,343 @@
+use serde::{Deserialize, Serialize};
+use wgpu::{
+    BindGroup, BindGroupLayout, Buffer, PipelineCompilationOptions, RenderPipeline, Sampler,
+    Texture, TextureView,
+};
+
+use crate::{
+    frame_stats::{self, CountedPass},
+    gbuffer::GBuffer,
+    hot_reload::wgsl,
+    reflection_probe::{ReflectionProbes, MAX_PROBES},
+    tonemap::HDR_FORMAT,
+    GfxState,
+};
+
+/// first of the probe cube maps, followed by the probe uniform
+const PROBE_BINDING: u32 = 7;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SsrSettings {
+    pub enabled: bool,
+    pub max_steps: u32,
+    /// meters a reflection ray travels before giving up on the screen
+    pub max_distance: f32,
+    /// meters behind the depth buffer that still count as a hit
+    pub thickness: f32,
+    /// surfaces below this don't reflect at all
+    pub min_smoothness: f32,
+    pub intensity: f32,
+}
+
+impl Default for SsrSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_steps: 48,
+            max_distance: 40.0,
+            thickness: 0.5,
+            min_smoothness: 0.3,
+            intensity: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct SsrParams {
+    max_steps: u32,
+    max_distance: f32,
+    thickness: f32,
+    min_smoothness: f32,
+    intensity: f32,
+    _pad: [f32; 3],
+}
+
+/// What the reflections are traced against and fall back to, rebound on resize.
+pub struct SsrInputs<'a> {
+    pub gbuffer: &'a GBuffer,
+    pub environment_view: &'a TextureView,
+    pub probes: &'a ReflectionProbes,
+    pub camera_buffer: &'a Buffer,
+}
+
+/// Screen space reflections on smooth surfaces. Rays are marched against the scene
+/// depth from the G-buffer; where they leave the screen or hit nothing the nearest
+/// reflection probe, or the environment cube map outside of all probes, is used instead.
+/// The result is added on top of the HDR target.
+pub struct Ssr {
+    params_buffer: Buffer,
+    scene_copy: Texture,
+    sampler: Sampler,
+    bind_group_layout: BindGroupLayout,
+    bind_group: BindGroup,
+    pipeline: RenderPipeline,
+}
+
+impl Ssr {
+    pub fn new(app: &GfxState, inputs: &SsrInputs) -> Self {
+        let device = &app.gpu.device;
+        let code = wgsl!("ssr.wgsl");
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("ssr shader"),
+            source: wgpu::ShaderSource::Wgsl(code),
+        });
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("ssr params"),
+            size: std::mem::size_of::<SsrParams>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let scene_copy = Self::create_copy(
+            device,
+            app.window.surface_config.width,
+            app.window.surface_config.height,
+        );
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("ssr sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let texture_entry = |binding, filterable, view_dimension| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable },
+                view_dimension,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let uniform_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let mut entries = vec![
+            texture_entry(0, true, wgpu::TextureViewDimension::D2),
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+            texture_entry(2, false, wgpu::TextureViewDimension::D2),
+            // R32Float isn't filterable, only loaded
+            texture_entry(3, false, wgpu::TextureViewDimension::D2),
+            texture_entry(4, true, wgpu::TextureViewDimension::Cube),
+            uniform_entry(5),
+            uniform_entry(6),
+        ];
+        entries.extend(
+            (0..MAX_PROBES as u32)
+                .map(|i| texture_entry(PROBE_BINDING + i, true, wgpu::TextureViewDimension::Cube)),
+        );
+        entries.push(uniform_entry(PROBE_BINDING + MAX_PROBES as u32));
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("ssr bind group layout"),
+            entries: &entries,
+        });
+        let bind_group = Self::create_bind_group(
+            device,
+            &bind_group_layout,
+            &scene_copy,
+            &sampler,
+            inputs,
+            &params_buffer,
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("ssr pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let additive = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        };
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("ssr pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "fullscreen_vs",
+                buffers: &[],
+                compilation_options: PipelineCompilationOptions::default(),
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "ssr_fs",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: HDR_FORMAT,
+                    blend: Some(wgpu::BlendState {
+                        color: additive,
+                        alpha: additive,
+                    }),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: PipelineCompilationOptions::default(),
+            }),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            params_buffer,
+            scene_copy,
+            sampler,
+            bind_group_layout,
+            bind_group,
+            pipeline,
+        }
+    }
+
+    fn create_copy(device: &wgpu::Device, width: u32, height: u32) -> Texture {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("ssr scene copy"),
+            size: wgpu::Extent3d {
+                width: width.max(1),
+                height: height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: HDR_FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        })
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &BindGroupLayout,
+        scene_copy: &Texture,
+        sampler: &Sampler,
+        inputs: &SsrInputs,
+        params_buffer: &Buffer,
+    ) -> BindGroup {
+        let SsrInputs {
+            gbuffer,
+            environment_view,
+            probes,
+            camera_buffer,
+        } = *inputs;
+        let scene_view = scene_copy.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut entries = vec![
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&scene_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(&gbuffer.surface_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::TextureView(&gbuffer.depth_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: wgpu::BindingResource::TextureView(environment_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 5,
+                resource: camera_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 6,
+                resource: params_buffer.as_entire_binding(),
+            },
+        ];
+        let probe_views = probes.views(environment_view);
+        entries.extend(
+            probe_views
+                .iter()
+                .enumerate()
+                .map(|(i, view)| wgpu::BindGroupEntry {
+                    binding: PROBE_BINDING + i as u32,
+                    resource: wgpu::BindingResource::TextureView(view),
+                }),
+        );
+        entries.push(wgpu::BindGroupEntry {
+            binding: PROBE_BINDING + MAX_PROBES as u32,
+            resource: probes.uniform_buffer.as_entire_binding(),
+        });
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("ssr bind group"),
+            layout,
+            entries: &entries,
+        })
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, inputs: &SsrInputs, width: u32, height: u32) {
+        self.scene_copy = Self::create_copy(device, width, height);
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.bind_group_layout,
+            &self.scene_copy,
+            &self.sampler,
+            inputs,
+            &self.params_buffer,
+        );
+    }
+
+    /// Copies `scene` aside and adds the reflections back into `target`.
+    pub fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        queue: &wgpu::Queue,
+        settings: &SsrSettings,
+        scene: &Texture,
+        target: &TextureView,
+    ) {
+        let params = SsrParams {
+            max_steps: settings.max_steps.max(1),
+            max_distance: settings.max_distance,
+            thickness: settings.thickness,
+            min_smoothness: settings.min_smoothness,
+            intensity: settings.intensity,
+            _pad: [0.0; 3],
+        };
+        frame_stats::write_buffer(queue, &self.params_buffer, 0, bytemuck::bytes_of(&params));
+
+        encoder.copy_texture_to_texture(
+            scene.as_image_copy(),
+            self.scene_copy.as_image_copy(),
+            self.scene_copy.size(),
+        );
+        let mut render_pass = CountedPass::begin(
+            encoder,
+            &wgpu::RenderPassDescriptor {
+                label: Some("ssr pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                ..Default::default()
+            },
+        );
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,176 @@
+use serde::{Deserialize, Serialize};
+
+/// Boxes the sky shader ray casts next to the ground plane, at most this many.
+pub const MAX_STATIC_BOXES: usize = 16;
+
+/// An axis aligned box standing in the scene. Static: it never moves, so lighting
+/// on and around it can be baked.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StaticBox {
+    pub center: [f32; 3],
+    /// full size in meters
+    pub size: [f32; 3],
+    /// sRGB
+    pub color: [f32; 3],
+}
+
+impl Default for StaticBox {
+    fn default() -> Self {
+        Self {
+            center: [0.0, 1.0, 0.0],
+            size: [2.0, 2.0, 2.0],
+            color: [0.6, 0.6, 0.6],
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct StaticBoxData {
+    // xyz, w unused
+    center: [f32; 4],
+    half_size: [f32; 4],
+    albedo: [f32; 4],
+}
+
+/// The boxes as the shaders see them, plus how the baked maps are laid out.
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct StaticGeometryUniform {
+    count: u32,
+    _pad: [u32; 3],
+    /// center xz, extent, enabled; see LightmapSettings::uniform
+    pub lightmap: [f32; 4],
+    /// see AoBakeSettings::uniform
+    pub ao: [f32; 4],
+    boxes: [StaticBoxData; MAX_STATIC_BOXES],
+    /// per box face, where it is in the lightmap; see LightmapSettings::charts
+    pub lightmap_charts: [[f32; 4]; MAX_STATIC_BOXES * 6],
+}
+
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c < 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+impl StaticGeometryUniform {
+    pub fn new(boxes: &[StaticBox]) -> Self {
+        if boxes.len() > MAX_STATIC_BOXES {
+            println!(
+                "{} static boxes in the scene, only the first {} are used",
+                boxes.len(),
+                MAX_STATIC_BOXES
+            );
+        }
+        let mut uniform = Self {
+            count: boxes.len().min(MAX_STATIC_BOXES) as u32,
+            _pad: [0; 3],
+            lightmap: [0.0; 4],
+            ao: [0.0; 4],
+            boxes: [StaticBoxData {
+                center: [0.0; 4],
+                half_size: [0.0; 4],
+                albedo: [0.0; 4],
+            }; MAX_STATIC_BOXES],
+            lightmap_charts: [[0.0; 4]; MAX_STATIC_BOXES * 6],
+        };
+        for (data, b) in uniform.boxes.iter_mut().zip(boxes) {
+            let [x, y, z] = b.center;
+            let [sx, sy, sz] = b.size.map(|s| s.abs() * 0.5);
+            let [r, g, b] = b.color.map(srgb_to_linear);
+            *data = StaticBoxData {
+                center: [x, y, z, 0.0],
+                half_size: [sx, sy, sz, 0.0],
+                albedo: [r, g, b, 0.0],
+            };
+        }
+        uniform
+    }
+}
+
+/// One triangle of the static geometry as the path tracer reads it from a storage buffer.
+#[cfg(feature = "path_tracing")]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct Triangle {
+    // xyz, w unused
+    pub v0: [f32; 4],
+    pub v1: [f32; 4],
+    pub v2: [f32; 4],
+    /// linear, w unused
+    pub albedo: [f32; 4],
+}
+
+// corner signs per face, in the +X -X +Y -Y +Z -Z order the shaders use
+const FACES: [[[f32; 3]; 4]; 6] = [
+    [
+        [1.0, -1.0, 1.0],
+        [1.0, -1.0, -1.0],
+        [1.0, 1.0, -1.0],
+        [1.0, 1.0, 1.0],
+    ],
+    [
+        [-1.0, -1.0, -1.0],
+        [-1.0, -1.0, 1.0],
+        [-1.0, 1.0, 1.0],
+        [-1.0, 1.0, -1.0],
+    ],
+    [
+        [-1.0, 1.0, 1.0],
+        [1.0, 1.0, 1.0],
+        [1.0, 1.0, -1.0],
+        [-1.0, 1.0, -1.0],
+    ],
+    [
+        [-1.0, -1.0, -1.0],
+        [1.0, -1.0, -1.0],
+        [1.0, -1.0, 1.0],
+        [-1.0, -1.0, 1.0],
+    ],
+    [
+        [-1.0, -1.0, 1.0],
+        [1.0, -1.0, 1.0],
+        [1.0, 1.0, 1.0],
+        [-1.0, 1.0, 1.0],
+    ],
+    [
+        [1.0, -1.0, -1.0],
+        [-1.0, -1.0, -1.0],
+        [-1.0, 1.0, -1.0],
+        [1.0, 1.0, -1.0],
+    ],
+];
+
+/// The box's faces in the +X -X +Y -Y +Z -Z order the shaders use, two triangles each,
+/// wound counter clockwise seen from outside.
+pub fn face_triangles(b: &StaticBox) -> [[[[f32; 3]; 3]; 2]; 6] {
+    let half = b.size.map(|s| s.abs() * 0.5);
+    let corner = |sign: [f32; 3]| [0, 1, 2].map(|i| b.center[i] + sign[i] * half[i]);
+    FACES.map(|face| {
+        let [a, c1, c2, c3] = face.map(corner);
+        [[a, c1, c2], [a, c2, c3]]
+    })
+}
+
+/// The boxes split into triangles, two per face, wound counter clockwise seen from outside.
+#[cfg(feature = "path_tracing")]
+pub fn triangles(boxes: &[StaticBox]) -> Vec<Triangle> {
+    let mut triangles = Vec::with_capacity(boxes.len() * 12);
+    for b in boxes {
+        let [r, g, bl] = b.color.map(srgb_to_linear);
+        let vertex = |[x, y, z]: [f32; 3]| [x, y, z, 0.0];
+        for [v0, v1, v2] in face_triangles(b).into_iter().flatten() {
+            triangles.push(Triangle {
+                v0: vertex(v0),
+                v1: vertex(v1),
+                v2: vertex(v2),
+                albedo: [r, g, bl, 0.0],
+            });
+        }
+    }
+    triangles
+}
@@ -0,0 +1,86 @@
+use serde::{Deserialize, Serialize};
+use wgpu::{CompositeAlphaMode, SurfaceCapabilities, SurfaceConfiguration, TextureFormat};
+
+use crate::config::WindowConfig;
+
+/// Serializable mirror of `wgpu::CompositeAlphaMode` for the config file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum AlphaMode {
+    #[default]
+    Auto,
+    Opaque,
+    PreMultiplied,
+    PostMultiplied,
+    Inherit,
+}
+
+impl From<AlphaMode> for CompositeAlphaMode {
+    fn from(mode: AlphaMode) -> Self {
+        match mode {
+            AlphaMode::Auto => CompositeAlphaMode::Auto,
+            AlphaMode::Opaque => CompositeAlphaMode::Opaque,
+            AlphaMode::PreMultiplied => CompositeAlphaMode::PreMultiplied,
+            AlphaMode::PostMultiplied => CompositeAlphaMode::PostMultiplied,
+            AlphaMode::Inherit => CompositeAlphaMode::Inherit,
+        }
+    }
+}
+
+/// The configured alpha mode if the surface supports it, otherwise the first mode that
+/// can actually show the desktop through (for transparent windows) or the surface default.
+pub fn pick_alpha_mode(caps: &SurfaceCapabilities, window: &WindowConfig) -> CompositeAlphaMode {
+    println!("Supported alpha modes: {:?}", caps.alpha_modes);
+    let wanted: CompositeAlphaMode = window.alpha_mode.into();
+    if wanted != CompositeAlphaMode::Auto {
+        if caps.alpha_modes.contains(&wanted) {
+            return wanted;
+        }
+        println!("Alpha mode {:?} not supported by the surface", wanted);
+    }
+    if window.transparent {
+        for mode in [
+            CompositeAlphaMode::PreMultiplied,
+            CompositeAlphaMode::PostMultiplied,
+            CompositeAlphaMode::Inherit,
+        ] {
+            if caps.alpha_modes.contains(&mode) {
+                return mode;
+            }
+        }
+        println!("No transparent alpha mode on this surface, the window stays opaque");
+    }
+    caps.alpha_modes[0]
+}
+
+/// Rgba16Float when HDR output is asked for and available, otherwise an sRGB format.
+pub fn pick_format(caps: &SurfaceCapabilities, window: &WindowConfig) -> TextureFormat {
+    println!("Supported surface formats: {:?}", caps.formats);
+    if window.hdr {
+        if caps.formats.contains(&TextureFormat::Rgba16Float) {
+            return TextureFormat::Rgba16Float;
+        }
+        println!("No HDR surface format available, falling back to SDR");
+    }
+    caps.formats
+        .iter()
+        .copied()
+        .find(|format| format.is_srgb())
+        .unwrap_or(caps.formats[0])
+}
+
+/// sRGB variant of `format` if one exists (the format itself when already sRGB).
+/// Rgba16Float and friends have none and stay linear.
+pub fn srgb_view_format(format: TextureFormat) -> Option<TextureFormat> {
+    let srgb = format.add_srgb_suffix();
+    srgb.is_srgb().then_some(srgb)
+}
+
+/// Format of the view the final pass renders into. When the surface itself isn't sRGB
+/// we render through an sRGB view of it so the encode still happens in hardware.
+pub fn output_view_format(config: &SurfaceConfiguration) -> TextureFormat {
+    config
+        .view_formats
+        .first()
+        .copied()
+        .unwrap_or(config.format)
+}
@@ -0,0 +1,241 @@
+use std::path::Path;
+
+use anyhow::Context;
+use wgpu::{BindGroup, BindGroupLayout, Sampler, TextureView};
+
+use crate::asset_loader::DecodedTexture;
+
+/// An image on the GPU with the view and sampler shaders read it through. Bound as
+/// `texture_bind_group_layout`: the texture at binding 0, the sampler at 1.
+pub struct ImageTexture {
+    pub texture: wgpu::Texture,
+    pub view: TextureView,
+    pub sampler: Sampler,
+}
+
+/// Reads a PNG or JPEG into rgba8 texels. Colors are `srgb`; data such as normal maps
+/// isn't, and is sampled as it is stored.
+pub fn decode(path: &Path, srgb: bool) -> anyhow::Result<DecodedTexture> {
+    let image = image::open(path)
+        .with_context(|| format!("can't read {}", path.display()))?
+        .to_rgba8();
+    Ok(DecodedTexture {
+        width: image.width(),
+        height: image.height(),
+        format: if srgb {
+            wgpu::TextureFormat::Rgba8UnormSrgb
+        } else {
+            wgpu::TextureFormat::Rgba8Unorm
+        },
+        texels: image.into_raw(),
+    })
+}
+
+impl ImageTexture {
+    /// Decodes and uploads `path` before returning; large textures for a scene go through
+    /// the `AssetLoader` instead.
+    // for embedding programs, models bring their textures decoded
+    pub fn load(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: &Path,
+        srgb: bool,
+    ) -> anyhow::Result<Self> {
+        let decoded = decode(path, srgb)?;
+        Ok(Self::from_decoded(
+            device,
+            queue,
+            &path.display().to_string(),
+            &decoded,
+        ))
+    }
+
+    pub fn from_decoded(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        label: &str,
+        decoded: &DecodedTexture,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: decoded.width,
+                height: decoded.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: decoded.format,
+            // copied from by readbacks
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let texture = Self::from_texture(device, texture);
+        texture.write(queue, decoded);
+        texture
+    }
+
+    /// A cube map from its faces in the +X, -X, +Y, -Y, +Z, -Z layer order, which have to
+    /// be square and share a size and format.
+    pub fn cube_from_decoded(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        label: &str,
+        faces: &[DecodedTexture; 6],
+    ) -> Self {
+        let size = faces[0].width;
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 6,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: faces[0].format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let texel_size = faces[0].format.block_copy_size(None).unwrap_or(4);
+        for (layer, face) in faces.iter().enumerate() {
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: layer as u32,
+                    },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                &face.texels,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(size * texel_size),
+                    rows_per_image: Some(size),
+                },
+                wgpu::Extent3d {
+                    width: size,
+                    height: size,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some(label),
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("cube texture sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        Self {
+            texture,
+            view,
+            sampler,
+        }
+    }
+
+    /// Wraps a texture uploaded elsewhere, e.g. one the `AssetLoader` finished.
+    pub fn from_texture(device: &wgpu::Device, texture: wgpu::Texture) -> Self {
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("image texture sampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        Self {
+            texture,
+            view,
+            sampler,
+        }
+    }
+
+    /// 1x1 of one texel, what a material samples in a slot without a texture.
+    pub fn solid(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        label: &str,
+        format: wgpu::TextureFormat,
+        texel: [u8; 4],
+    ) -> Self {
+        let decoded = DecodedTexture {
+            width: 1,
+            height: 1,
+            format,
+            texels: texel.to_vec(),
+        };
+        Self::from_decoded(device, queue, label, &decoded)
+    }
+
+    /// Replaces the whole texture's texels; `decoded` has to be its size and format.
+    pub fn write(&self, queue: &wgpu::Queue, decoded: &DecodedTexture) {
+        let texel_size = decoded.format.block_copy_size(None).unwrap_or(4);
+        queue.write_texture(
+            self.texture.as_image_copy(),
+            &decoded.texels,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(decoded.width * texel_size),
+                rows_per_image: Some(decoded.height),
+            },
+            self.texture.size(),
+        );
+    }
+
+    // for embedding programs drawing a single texture; materials bind theirs in
+    // `Materials::add`
+    pub fn bind_group(&self, device: &wgpu::Device, layout: &BindGroupLayout) -> BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("image texture bind group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&self.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        })
+    }
+}
+
+/// A filterable 2D texture and its sampler, for the fragment stage.
+pub fn texture_bind_group_layout(device: &wgpu::Device) -> BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("image texture bind group layout"),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+        ],
+    })
+}
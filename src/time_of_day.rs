@@ -0,0 +1,103 @@
+use cgmath::{InnerSpace, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::sky::SkySettings;
+
+/// Drives the sun (and with it sky, light and ambient) through a day.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DayNightCycle {
+    pub enabled: bool,
+    pub paused: bool,
+    /// hours, 0..24
+    pub time: f32,
+    /// real seconds per in game day
+    pub day_length: f32,
+    /// elevation of the sun at noon, degrees
+    pub noon_elevation: f32,
+}
+
+impl Default for DayNightCycle {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            paused: false,
+            time: 9.0,
+            day_length: 120.0,
+            noon_elevation: 60.0,
+        }
+    }
+}
+
+/// The main light of the scene, derived from the sun or the moon.
+#[derive(Debug, Clone, Copy)]
+pub struct DirectionalLight {
+    /// towards the light
+    pub direction: Vector3<f32>,
+    pub color: [f32; 3],
+    pub intensity: f32,
+    pub ambient: [f32; 3],
+}
+
+impl DayNightCycle {
+    pub fn update(&mut self, dt: f32) {
+        if self.enabled && !self.paused {
+            self.scrub(dt / self.day_length.max(1.0) * 24.0);
+        }
+    }
+
+    pub fn scrub(&mut self, hours: f32) {
+        self.time = (self.time + hours).rem_euclid(24.0);
+    }
+
+    /// Sun rises at 6 in the east (+x), peaks at noon, sets at 18.
+    pub fn apply(&self, sky: &mut SkySettings) {
+        let hour_angle = (self.time - 12.0) / 24.0 * std::f32::consts::TAU;
+        let sin_elevation = hour_angle.cos() * self.noon_elevation.to_radians().sin();
+        sky.sun_elevation = sin_elevation.asin().to_degrees();
+        sky.sun_azimuth = 90.0 + (self.time - 6.0) / 12.0 * 180.0;
+        // 早晚空气更浑一点
+        let low_sun = 1.0 - sin_elevation.abs().min(1.0);
+        sky.turbidity = 2.5 + 1.5 * low_sun;
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn mix(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3] {
+    [
+        a[0] + (b[0] - a[0]) * t,
+        a[1] + (b[1] - a[1]) * t,
+        a[2] + (b[2] - a[2]) * t,
+    ]
+}
+
+impl DirectionalLight {
+    /// Sunlight while the sun is up, a faint blue moonlight otherwise.
+    pub fn from_sky(sky: &SkySettings) -> Self {
+        let sun = sky.sun_direction();
+        let day = smoothstep(-0.05, 0.1, sun.y);
+        let warm = smoothstep(0.0, 0.4, sun.y);
+        let ambient_day = smoothstep(-0.15, 0.3, sun.y);
+        let ambient = mix([0.01, 0.015, 0.03], [0.25, 0.3, 0.4], ambient_day);
+        if day > 0.0 {
+            Self {
+                direction: sun,
+                color: mix([1.0, 0.5, 0.25], [1.0, 0.97, 0.92], warm),
+                intensity: 3.0 * day,
+                ambient,
+            }
+        } else {
+            let moon = sky.moon_direction();
+            Self {
+                direction: moon.normalize(),
+                color: [0.6, 0.7, 1.0],
+                intensity: 0.05 * smoothstep(0.0, 0.1, moon.y),
+                ambient,
+            }
+        }
+    }
+}
@@ -0,0 +1,285 @@
+use serde::{Deserialize, Serialize};
+use wgpu::{
+    util::DeviceExt, BindGroup, BindGroupLayout, Buffer, PipelineCompilationOptions,
+    RenderPipeline, Sampler, TextureFormat, TextureView,
+};
+
+use crate::{
+    config::DisplayConfig,
+    frame_stats::{self, CountedPass},
+    hot_reload::wgsl,
+    render_target::RenderTarget,
+    surface, GfxState,
+};
+
+/// The scene is rendered into this format and tonemapped onto the surface.
+pub const HDR_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+
+/// The curve bringing the exposed HDR values into the SDR range. An HDR surface gets
+/// them without one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum TonemapOperator {
+    /// Narkowicz's fit, filmic with a toe and a hard shoulder
+    #[default]
+    Aces,
+    /// x / (1 + x) per channel, softer and never quite white
+    Reinhard,
+}
+
+impl TonemapOperator {
+    pub fn next(self) -> Self {
+        match self {
+            TonemapOperator::Aces => TonemapOperator::Reinhard,
+            TonemapOperator::Reinhard => TonemapOperator::Aces,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct TonemapUniform {
+    pub output_mode: u32,
+    pub paper_white: f32,
+    /// the output view isn't sRGB, encode in the shader
+    pub encode_srgb: u32,
+    /// split view: left half shows sRGB data mistakenly treated as linear,
+    /// NaN/negative inputs in magenta
+    pub debug_encoding: u32,
+    pub gamma: f32,
+    pub brightness: f32,
+    pub contrast: f32,
+    /// stops, applied before the curve
+    pub exposure_ev: f32,
+    /// written on the GPU by the auto exposure pass, 0 when it's off
+    pub auto_exposure_ev: f32,
+    /// show the HDR target's values as they are, for the debug views
+    pub passthrough: u32,
+    /// a `TonemapOperator`
+    pub curve: u32,
+    _pad: u32,
+}
+
+impl TonemapUniform {
+    pub const AUTO_EXPOSURE_OFFSET: u64 =
+        std::mem::offset_of!(TonemapUniform, auto_exposure_ev) as u64;
+}
+
+/// Offscreen HDR scene target plus the fullscreen pass that maps it onto the surface.
+/// On an HDR surface the values are passed through scene referred, otherwise they get
+/// tonemapped into the SDR range.
+pub struct Tonemap {
+    pub hdr: RenderTarget,
+    pub uniform: TonemapUniform,
+    pub uniform_buffer: Buffer,
+    sampler: Sampler,
+    bind_group_layout: BindGroupLayout,
+    bind_group: BindGroup,
+    pipeline: RenderPipeline,
+}
+
+impl Tonemap {
+    pub fn new(app: &GfxState) -> Self {
+        let device = &app.gpu.device;
+        let code = wgsl!("tonemap.wgsl");
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("tonemap shader"),
+            source: wgpu::ShaderSource::Wgsl(code),
+        });
+
+        let output_format = surface::output_view_format(&app.window.surface_config);
+        let hdr_output = output_format == TextureFormat::Rgba16Float;
+        let uniform = TonemapUniform {
+            output_mode: hdr_output as u32,
+            paper_white: app.config.window.hdr_paper_white_nits / 80.0,
+            encode_srgb: (!hdr_output && !output_format.is_srgb()) as u32,
+            debug_encoding: 0,
+            gamma: app.config.display.gamma,
+            brightness: app.config.display.brightness,
+            contrast: app.config.display.contrast,
+            exposure_ev: app.scene.exposure_ev,
+            auto_exposure_ev: 0.0,
+            passthrough: 0,
+            curve: app.scene.tonemap as u32,
+            _pad: 0,
+        };
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("tonemap uniform"),
+            contents: bytemuck::bytes_of(&uniform),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let hdr = RenderTarget::new(
+            device,
+            "hdr scene target",
+            HDR_FORMAT,
+            // COPY_SRC for passes that need to read the scene while drawing into it,
+            // COPY_DST for the path tracer's output
+            wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::COPY_DST,
+            app.window.surface_config.width,
+            app.window.surface_config.height,
+        );
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("tonemap sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("tonemap bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = Self::create_bind_group(
+            device,
+            &bind_group_layout,
+            &hdr.view,
+            &sampler,
+            &uniform_buffer,
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("tonemap pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("tonemap pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "fullscreen_vs",
+                buffers: &[],
+                compilation_options: PipelineCompilationOptions::default(),
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "tonemap_fs",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: output_format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: PipelineCompilationOptions::default(),
+            }),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            hdr,
+            uniform,
+            uniform_buffer,
+            sampler,
+            bind_group_layout,
+            bind_group,
+            pipeline,
+        }
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &BindGroupLayout,
+        hdr_view: &TextureView,
+        sampler: &Sampler,
+        uniform_buffer: &Buffer,
+    ) -> BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("tonemap bind group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(hdr_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        if self.hdr.resize(device, width, height) {
+            self.bind_group = Self::create_bind_group(
+                device,
+                &self.bind_group_layout,
+                &self.hdr.view,
+                &self.sampler,
+                &self.uniform_buffer,
+            );
+        }
+    }
+
+    pub fn set_display(&mut self, display: &DisplayConfig) {
+        self.uniform.gamma = display.gamma;
+        self.uniform.brightness = display.brightness;
+        self.uniform.contrast = display.contrast;
+    }
+
+    pub fn write_uniform(&self, queue: &wgpu::Queue) {
+        frame_stats::write_buffer(
+            queue,
+            &self.uniform_buffer,
+            0,
+            bytemuck::bytes_of(&self.uniform),
+        );
+    }
+
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, target: &TextureView) {
+        let mut render_pass = CountedPass::begin(
+            encoder,
+            &wgpu::RenderPassDescriptor {
+                label: Some("tonemap pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                ..Default::default()
+            },
+        );
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,294 @@
+use serde::{Deserialize, Serialize};
+use wgpu::{
+    BindGroup, BindGroupLayout, Buffer, PipelineCompilationOptions, RenderPipeline, TextureFormat,
+    TextureView,
+};
+
+use crate::{
+    frame_stats::{self, CountedPass},
+    hot_reload::wgsl,
+};
+
+/// How far below the window the post chain runs before it's upscaled, named after the
+/// FSR 1.0 presets.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum UpscaleQuality {
+    #[default]
+    Off,
+    UltraQuality,
+    Quality,
+    Balanced,
+    Performance,
+}
+
+impl UpscaleQuality {
+    /// Window size over input size, per axis.
+    pub fn ratio(self) -> f32 {
+        match self {
+            UpscaleQuality::Off => 1.0,
+            UpscaleQuality::UltraQuality => 1.3,
+            UpscaleQuality::Quality => 1.5,
+            UpscaleQuality::Balanced => 1.7,
+            UpscaleQuality::Performance => 2.0,
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            UpscaleQuality::Off => UpscaleQuality::UltraQuality,
+            UpscaleQuality::UltraQuality => UpscaleQuality::Quality,
+            UpscaleQuality::Quality => UpscaleQuality::Balanced,
+            UpscaleQuality::Balanced => UpscaleQuality::Performance,
+            UpscaleQuality::Performance => UpscaleQuality::Off,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UpscaleSettings {
+    pub quality: UpscaleQuality,
+    /// RCAS sharpening in stops below the maximum, 0 is sharpest
+    pub sharpness: f32,
+}
+
+impl Default for UpscaleSettings {
+    fn default() -> Self {
+        Self {
+            quality: UpscaleQuality::Off,
+            sharpness: 0.2,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct UpscaleParams {
+    // input pixels per output pixel
+    scale: [f32; 2],
+    sharpness: f32,
+    // the views decode sRGB, filter in gamma 2 instead of linear light
+    perceptual: u32,
+}
+
+/// FSR 1.0 style spatial upscaling at the very end of the post chain: the tonemap pass
+/// draws into a smaller input target, EASU resamples it to the window along the local
+/// edge direction, and RCAS sharpens the result onto the surface.
+pub struct Upscaler {
+    quality: UpscaleQuality,
+    input_size: (u32, u32),
+    output_size: (u32, u32),
+    format: TextureFormat,
+    params_buffer: Buffer,
+    bind_group_layout: BindGroupLayout,
+    easu_pipeline: RenderPipeline,
+    rcas_pipeline: RenderPipeline,
+}
+
+impl Upscaler {
+    /// `format` is what the tonemap pass writes, the surface's view format.
+    pub fn new(device: &wgpu::Device, format: TextureFormat) -> Self {
+        let code = wgsl!("upscale.wgsl");
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("upscale shader"),
+            source: wgpu::ShaderSource::Wgsl(code),
+        });
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("upscale params"),
+            size: std::mem::size_of::<UpscaleParams>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("upscale bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("upscale pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let create_pipeline = |label, entry_point| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "fullscreen_vs",
+                    buffers: &[],
+                    compilation_options: PipelineCompilationOptions::default(),
+                },
+                primitive: wgpu::PrimitiveState::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point,
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                    compilation_options: PipelineCompilationOptions::default(),
+                }),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+        let easu_pipeline = create_pipeline("easu pipeline", "easu_fs");
+        let rcas_pipeline = create_pipeline("rcas pipeline", "rcas_fs");
+
+        Self {
+            quality: UpscaleQuality::Off,
+            input_size: (1, 1),
+            output_size: (1, 1),
+            format,
+            params_buffer,
+            bind_group_layout,
+            easu_pipeline,
+            rcas_pipeline,
+        }
+    }
+
+    /// Size the post chain runs at, as picked by the last resize.
+    pub fn input_size(&self) -> (u32, u32) {
+        self.input_size
+    }
+
+    /// Whether the post chain runs below window size and needs upscaling.
+    pub fn active(&self) -> bool {
+        self.quality != UpscaleQuality::Off
+    }
+
+    /// Sizes the input for a window of `width` x `height` at `quality`.
+    pub fn resize(&mut self, quality: UpscaleQuality, width: u32, height: u32) {
+        if quality != self.quality {
+            println!("Upscaling: {:?}", quality);
+        }
+        self.quality = quality;
+        let (width, height) = (width.max(1), height.max(1));
+        let ratio = quality.ratio();
+        self.input_size = (
+            ((width as f32 / ratio).round() as u32).max(1),
+            ((height as f32 / ratio).round() as u32).max(1),
+        );
+        self.output_size = (width, height);
+    }
+
+    fn create_bind_group(&self, device: &wgpu::Device, input: &TextureView) -> BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("upscale bind group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(input),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.params_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    fn draw(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        label: &str,
+        pipeline: &RenderPipeline,
+        input: &TextureView,
+        output: &TextureView,
+    ) {
+        let bind_group = self.create_bind_group(device, input);
+        let mut render_pass = CountedPass::begin(
+            encoder,
+            &wgpu::RenderPassDescriptor {
+                label: Some(label),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: output,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                ..Default::default()
+            },
+        );
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    /// Resamples the input-sized `input` up into the window-sized `output`. Also writes
+    /// the parameters the RCAS pass after it shares.
+    pub fn easu(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        queue: &wgpu::Queue,
+        settings: &UpscaleSettings,
+        input: &TextureView,
+        output: &TextureView,
+    ) {
+        let (input_width, input_height) = self.input_size;
+        let (output_width, output_height) = self.output_size;
+        let params = UpscaleParams {
+            scale: [
+                input_width as f32 / output_width as f32,
+                input_height as f32 / output_height as f32,
+            ],
+            sharpness: (-settings.sharpness.max(0.0)).exp2(),
+            perceptual: self.format.is_srgb() as u32,
+        };
+        frame_stats::write_buffer(queue, &self.params_buffer, 0, bytemuck::bytes_of(&params));
+        self.draw(
+            device,
+            encoder,
+            "easu pass",
+            &self.easu_pipeline,
+            input,
+            output,
+        );
+    }
+
+    /// Sharpens EASU's result in `input` onto `output`.
+    pub fn rcas(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &TextureView,
+        output: &TextureView,
+    ) {
+        self.draw(
+            device,
+            encoder,
+            "rcas pass",
+            &self.rcas_pipeline,
+            input,
+            output,
+        );
+    }
+}
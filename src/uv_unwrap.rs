@@ -0,0 +1,232 @@
+use std::collections::HashMap;
+
+use anyhow::bail;
+
+/// Texels around every chart, so bilinear filtering at its edges doesn't reach its
+/// neighbours.
+pub const PADDING: u32 = 1;
+
+/// A lightmap unwrap: the meshes split into planar charts, each projected onto its plane
+/// and packed into one atlas, `texels_per_meter` on every chart.
+pub struct Unwrap {
+    pub width: u32,
+    pub height: u32,
+    pub charts: Vec<Chart>,
+}
+
+/// Connected triangles of one mesh that share a plane, and where they went in the atlas.
+pub struct Chart {
+    pub mesh: usize,
+    /// into the mesh's triangles
+    pub triangles: Vec<usize>,
+    /// top left texel of the chart's rectangle in the atlas and its size, padding included
+    pub offset: [u32; 2],
+    pub size: [u32; 2],
+    /// the point of the plane at the rectangle's top left corner, and where one texel
+    /// along the atlas' x and y moves to on the plane
+    pub origin: [f32; 3],
+    pub texel_axes: [[f32; 3]; 2],
+    pub normal: [f32; 3],
+}
+
+impl Chart {
+    /// Atlas texel coordinates of a point on the chart's plane.
+    pub fn texel(&self, p: [f32; 3]) -> [f32; 2] {
+        let d = sub(p, self.origin);
+        [0, 1].map(|i| {
+            let axis = self.texel_axes[i];
+            self.offset[i] as f32 + dot(d, axis) / dot(axis, axis)
+        })
+    }
+}
+
+/// Unwraps `meshes`, each a list of triangles, into an atlas `width` texels wide; it's as
+/// tall as the charts need. Triangles are connected where they share corners at exactly
+/// the same position, charts never span two meshes. A chart wider than the atlas is an
+/// error.
+pub fn unwrap(
+    meshes: &[Vec<[[f32; 3]; 3]>],
+    texels_per_meter: f32,
+    width: u32,
+) -> anyhow::Result<Unwrap> {
+    let mut charts = vec![];
+    for (mesh, triangles) in meshes.iter().enumerate() {
+        for group in planar_groups(triangles) {
+            charts.push(project(mesh, triangles, group, texels_per_meter));
+        }
+    }
+
+    // shelves, tallest charts first
+    let mut order: Vec<usize> = (0..charts.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(charts[i].size[1]));
+    let (mut x, mut y, mut shelf) = (0, 0, 0);
+    for i in order {
+        let chart = &mut charts[i];
+        let [w, h] = chart.size;
+        if w > width {
+            bail!(
+                "a {}x{} texel chart doesn't fit the {} texel wide atlas",
+                w,
+                h,
+                width
+            );
+        }
+        if x + w > width {
+            (x, y, shelf) = (0, y + shelf, 0);
+        }
+        chart.offset = [x, y];
+        x += w;
+        shelf = shelf.max(h);
+    }
+    Ok(Unwrap {
+        width,
+        height: y + shelf,
+        charts,
+    })
+}
+
+// connected triangles with the same plane, each a list of triangle indices
+fn planar_groups(triangles: &[[[f32; 3]; 3]]) -> Vec<Vec<usize>> {
+    // corners welded by position, then the triangles on each edge
+    let mut vertices = HashMap::new();
+    let corners: Vec<[usize; 3]> = triangles
+        .iter()
+        .map(|triangle| {
+            triangle.map(|p| {
+                let next = vertices.len();
+                *vertices.entry(p.map(f32::to_bits)).or_insert(next)
+            })
+        })
+        .collect();
+    let mut edges: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+    for (index, [a, b, c]) in corners.iter().copied().enumerate() {
+        for (u, v) in [(a, b), (b, c), (c, a)] {
+            edges.entry((u.min(v), u.max(v))).or_default().push(index);
+        }
+    }
+    let normals: Vec<[f32; 3]> = triangles.iter().map(triangle_normal).collect();
+    let coplanar = |a: usize, b: usize| {
+        dot(normals[a], normals[b]) > 0.9999
+            && dot(normals[a], sub(triangles[b][0], triangles[a][0])).abs() < 1e-4
+    };
+
+    let mut group_of = vec![None; triangles.len()];
+    let mut groups = vec![];
+    for start in 0..triangles.len() {
+        if group_of[start].is_some() {
+            continue;
+        }
+        group_of[start] = Some(groups.len());
+        let mut group = vec![start];
+        let mut next = 0;
+        while next < group.len() {
+            let index = group[next];
+            next += 1;
+            let [a, b, c] = corners[index];
+            for (u, v) in [(a, b), (b, c), (c, a)] {
+                for &other in &edges[&(u.min(v), u.max(v))] {
+                    if group_of[other].is_none() && coplanar(start, other) {
+                        group_of[other] = Some(groups.len());
+                        group.push(other);
+                    }
+                }
+            }
+        }
+        groups.push(group);
+    }
+    groups
+}
+
+// the group's triangles flattened onto their plane, the chart not placed yet
+fn project(
+    mesh: usize,
+    triangles: &[[[f32; 3]; 3]],
+    group: Vec<usize>,
+    texels_per_meter: f32,
+) -> Chart {
+    let normal = triangle_normal(&triangles[group[0]]);
+    // the plane's own axes closest to the two world axes across the normal's largest one,
+    // so faces facing along a world axis keep its orientation
+    let dominant = (0..3)
+        .max_by(|&a, &b| normal[a].abs().total_cmp(&normal[b].abs()))
+        .unwrap();
+    let [a, b] = match dominant {
+        0 => [2, 1],
+        1 => [0, 2],
+        _ => [0, 1],
+    };
+    let unit = |axis: usize| {
+        let mut v = [0.0; 3];
+        v[axis] = 1.0;
+        v
+    };
+    let u = normalize(sub(unit(a), scale(normal, normal[a])));
+    let v = normalize(sub(
+        sub(unit(b), scale(normal, normal[b])),
+        scale(u, dot(unit(b), u)),
+    ));
+
+    let mut min = [f32::MAX; 2];
+    let mut max = [f32::MIN; 2];
+    for &index in &group {
+        for p in triangles[index] {
+            let projected = [dot(p, u), dot(p, v)];
+            for i in 0..2 {
+                min[i] = min[i].min(projected[i]);
+                max[i] = max[i].max(projected[i]);
+            }
+        }
+    }
+    let size =
+        [0, 1].map(|i| ((max[i] - min[i]) * texels_per_meter).ceil().max(1.0) as u32 + 2 * PADDING);
+    let pad = PADDING as f32 / texels_per_meter;
+    let plane = scale(normal, dot(triangles[group[0]][0], normal));
+    let origin = add(plane, add(scale(u, min[0] - pad), scale(v, min[1] - pad)));
+    Chart {
+        mesh,
+        triangles: group,
+        offset: [0, 0],
+        size,
+        origin,
+        texel_axes: [
+            scale(u, 1.0 / texels_per_meter),
+            scale(v, 1.0 / texels_per_meter),
+        ],
+        normal,
+    }
+}
+
+fn triangle_normal([a, b, c]: &[[f32; 3]; 3]) -> [f32; 3] {
+    let (e1, e2) = (sub(*b, *a), sub(*c, *a));
+    normalize([
+        e1[1] * e2[2] - e1[2] * e2[1],
+        e1[2] * e2[0] - e1[0] * e2[2],
+        e1[0] * e2[1] - e1[1] * e2[0],
+    ])
+}
+
+fn add(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn scale(a: [f32; 3], s: f32) -> [f32; 3] {
+    a.map(|c| c * s)
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn normalize(a: [f32; 3]) -> [f32; 3] {
+    let length = dot(a, a).sqrt();
+    if length > 0.0 {
+        scale(a, 1.0 / length)
+    } else {
+        // a degenerate triangle, a chart of its own either way
+        [0.0, 1.0, 0.0]
+    }
+}
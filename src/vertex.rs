@@ -0,0 +1,75 @@
+use wgpu::util::DeviceExt;
+
+/// A vertex type a pipeline can read from a vertex buffer. The attributes are numbered
+/// from shader location 0, in the order of the struct's fields; per instance types go on
+/// from where the vertex type they're drawn with stops.
+pub trait Vertex: bytemuck::Pod {
+    const ATTRIBUTES: &'static [wgpu::VertexAttribute];
+    const STEP_MODE: wgpu::VertexStepMode = wgpu::VertexStepMode::Vertex;
+
+    fn layout() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as u64,
+            step_mode: Self::STEP_MODE,
+            attributes: Self::ATTRIBUTES,
+        }
+    }
+}
+
+/// What the mesh pipeline draws: a lit surface that can be textured and normal mapped.
+#[derive(Debug, Copy, Clone, Default, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct MeshVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub uv: [f32; 2],
+    /// along +u, with w the sign of the bitangent (+v) against normal x tangent, as glTF
+    /// has it; `model::generate_tangents` fills it in for files without tangents
+    pub tangent: [f32; 4],
+}
+
+impl Vertex for MeshVertex {
+    const ATTRIBUTES: &'static [wgpu::VertexAttribute] = &wgpu::vertex_attr_array![
+        0 => Float32x3,
+        1 => Float32x3,
+        2 => Float32x2,
+        3 => Float32x4,
+    ];
+}
+
+/// An index type for indexed draws, u16 for meshes of up to 65536 vertices.
+pub trait Index: bytemuck::Pod {
+    const FORMAT: wgpu::IndexFormat;
+}
+
+impl Index for u16 {
+    const FORMAT: wgpu::IndexFormat = wgpu::IndexFormat::Uint16;
+}
+
+impl Index for u32 {
+    const FORMAT: wgpu::IndexFormat = wgpu::IndexFormat::Uint32;
+}
+
+pub fn create_vertex_buffer<V: Vertex>(
+    device: &wgpu::Device,
+    label: &str,
+    vertices: &[V],
+) -> wgpu::Buffer {
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some(label),
+        contents: bytemuck::cast_slice(vertices),
+        usage: wgpu::BufferUsages::VERTEX,
+    })
+}
+
+pub fn create_index_buffer<I: Index>(
+    device: &wgpu::Device,
+    label: &str,
+    indices: &[I],
+) -> wgpu::Buffer {
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some(label),
+        contents: bytemuck::cast_slice(indices),
+        usage: wgpu::BufferUsages::INDEX,
+    })
+}
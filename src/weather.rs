@@ -0,0 +1,601 @@
+use serde::{Deserialize, Serialize};
+use wgpu::{
+    BindGroup, Buffer, ComputePipeline, PipelineCompilationOptions, RenderPipeline, Sampler,
+    Texture, TextureView,
+};
+
+use crate::{
+    frame_stats::{self, CountedPass},
+    hot_reload::wgsl,
+    sky::SkySettings,
+    time_of_day::DirectionalLight,
+    tonemap::HDR_FORMAT,
+    GfxState,
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum WeatherKind {
+    #[default]
+    Clear,
+    Rain,
+    Snow,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WeatherSettings {
+    pub kind: WeatherKind,
+    /// 0..1
+    pub intensity: f32,
+}
+
+impl Default for WeatherSettings {
+    fn default() -> Self {
+        Self {
+            kind: WeatherKind::Clear,
+            intensity: 0.7,
+        }
+    }
+}
+
+impl WeatherSettings {
+    pub fn precipitating(&self) -> bool {
+        self.kind != WeatherKind::Clear && self.intensity > 0.0
+    }
+
+    pub fn next_kind(&mut self) {
+        self.kind = match self.kind {
+            WeatherKind::Clear => WeatherKind::Rain,
+            WeatherKind::Rain => WeatherKind::Snow,
+            WeatherKind::Snow => WeatherKind::Clear,
+        };
+    }
+
+    /// how much the weather closes the sky, 0..1
+    fn overcast(&self) -> f32 {
+        match self.kind {
+            WeatherKind::Clear => 0.0,
+            WeatherKind::Rain => self.intensity.clamp(0.0, 1.0),
+            WeatherKind::Snow => self.intensity.clamp(0.0, 1.0) * 0.8,
+        }
+    }
+
+    /// The sky as the weather makes it look: more clouds, hazier, darker.
+    pub fn sky(&self, sky: &SkySettings) -> SkySettings {
+        let overcast = self.overcast();
+        let mut sky = *sky;
+        sky.cloud_coverage += (0.95 - sky.cloud_coverage).max(0.0) * overcast;
+        sky.turbidity += (8.0 - sky.turbidity).max(0.0) * overcast * 0.6;
+        sky.intensity *= 1.0 - 0.6 * overcast;
+        sky.flare_intensity *= 1.0 - overcast;
+        if self.kind == WeatherKind::Rain {
+            sky.ground_wetness = sky.ground_wetness.max(self.intensity);
+        }
+        sky
+    }
+
+    pub fn dim_light(&self, light: &mut DirectionalLight) {
+        let overcast = self.overcast();
+        light.intensity *= 1.0 - 0.8 * overcast;
+        for channel in light.ambient.iter_mut() {
+            *channel *= 1.0 - 0.4 * overcast;
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct PrecipitationParams {
+    camera_position: [f32; 3],
+    dt: f32,
+    wind: [f32; 3],
+    intensity: f32,
+    box_size: f32,
+    fall_speed: f32,
+    time: f32,
+    streak_length: f32,
+    light: [f32; 3],
+    aspect: f32,
+    ambient: [f32; 3],
+    particle_count: u32,
+    flake_size: f32,
+    /// 1 = round drifting flakes instead of streaks
+    snow: u32,
+    seed: u32,
+    _pad: u32,
+}
+
+// position + seed, velocity + pad
+const PARTICLE_SIZE: u64 = 32;
+const MAX_PARTICLES: u32 = 32768;
+
+/// GPU particles falling in a box that follows the camera: updated in a compute pass,
+/// drawn as velocity stretched billboards, plus droplets on the "lens".
+pub struct Precipitation {
+    particle_count: u32,
+    params_buffer: Buffer,
+    // only reached through the bind groups, kept so it lives as long as they do
+    _particle_buffer: Buffer,
+    uniforms_bind_group: BindGroup,
+    compute_bind_group: BindGroup,
+    render_bind_group: BindGroup,
+    update_pipeline: ComputePipeline,
+    streak_pipeline: RenderPipeline,
+    droplet_pipeline: RenderPipeline,
+}
+
+impl Precipitation {
+    /// None when the limits don't give us compute and storage buffers.
+    pub fn new(app: &GfxState, camera_buffer: &Buffer) -> Option<Self> {
+        if !app.gpu.budget.compute {
+            println!("Precipitation disabled: no compute support");
+            return None;
+        }
+        let device = &app.gpu.device;
+        let particle_count = MAX_PARTICLES.min(
+            (app.gpu.budget.max_storage_buffer_size / PARTICLE_SIZE).min(u32::MAX as u64) as u32,
+        );
+
+        let code = wgsl!("precipitation.wgsl");
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("precipitation shader"),
+            source: wgpu::ShaderSource::Wgsl(code),
+        });
+
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("precipitation params"),
+            size: std::mem::size_of::<PrecipitationParams>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        // all zero: every particle starts on the ground and gets respawned on the first update
+        let particle_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("precipitation particles"),
+            size: particle_count as u64 * PARTICLE_SIZE,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let uniform_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::VERTEX
+                | wgpu::ShaderStages::FRAGMENT
+                | wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let storage_entry = |binding, visibility, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let uniforms_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("precipitation uniforms layout"),
+            entries: &[uniform_entry(0), uniform_entry(1)],
+        });
+        let compute_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("precipitation compute layout"),
+            entries: &[storage_entry(0, wgpu::ShaderStages::COMPUTE, false)],
+        });
+        let render_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("precipitation render layout"),
+            entries: &[storage_entry(1, wgpu::ShaderStages::VERTEX, true)],
+        });
+
+        let uniforms_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("precipitation uniforms"),
+            layout: &uniforms_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: camera_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: params_buffer.as_entire_binding(),
+                },
+            ],
+        });
+        let compute_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("precipitation particles rw"),
+            layout: &compute_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: particle_buffer.as_entire_binding(),
+            }],
+        });
+        let render_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("precipitation particles ro"),
+            layout: &render_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 1,
+                resource: particle_buffer.as_entire_binding(),
+            }],
+        });
+
+        let compute_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("precipitation compute pipeline layout"),
+                bind_group_layouts: &[&uniforms_layout, &compute_layout],
+                push_constant_ranges: &[],
+            });
+        let update_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("precipitation update"),
+            layout: Some(&compute_pipeline_layout),
+            module: &shader,
+            entry_point: "update",
+            compilation_options: PipelineCompilationOptions::default(),
+        });
+
+        let streak_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("precipitation render pipeline layout"),
+                bind_group_layouts: &[&uniforms_layout, &render_layout],
+                push_constant_ranges: &[],
+            });
+        let droplet_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("droplet pipeline layout"),
+                bind_group_layouts: &[&uniforms_layout],
+                push_constant_ranges: &[],
+            });
+        let make_pipeline = |label, layout, vs_entry, fs_entry| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: vs_entry,
+                    buffers: &[],
+                    compilation_options: PipelineCompilationOptions::default(),
+                },
+                primitive: wgpu::PrimitiveState::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: fs_entry,
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: HDR_FORMAT,
+                        blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                    compilation_options: PipelineCompilationOptions::default(),
+                }),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+        let streak_pipeline = make_pipeline(
+            "precipitation streaks",
+            &streak_pipeline_layout,
+            "streak_vs",
+            "streak_fs",
+        );
+        let droplet_pipeline = make_pipeline(
+            "screen droplets",
+            &droplet_pipeline_layout,
+            "fullscreen_vs",
+            "droplets_fs",
+        );
+
+        Some(Self {
+            particle_count,
+            params_buffer,
+            _particle_buffer: particle_buffer,
+            uniforms_bind_group,
+            compute_bind_group,
+            render_bind_group,
+            update_pipeline,
+            streak_pipeline,
+            droplet_pipeline,
+        })
+    }
+
+    fn params(&self, app: &GfxState) -> PrecipitationParams {
+        let weather = &app.scene.weather;
+        let light = &app.frame.sun_light;
+        let (fall_speed, streak_length, flake_size) = match weather.kind {
+            WeatherKind::Clear | WeatherKind::Rain => (9.0, 0.4, 0.0),
+            WeatherKind::Snow => (1.2, 0.0, 0.03),
+        };
+        PrecipitationParams {
+            camera_position: app.frame.camera.eye.into(),
+            dt: app.frame.dt,
+            wind: app.scene.wind.velocity(app.frame.time).into(),
+            intensity: weather.intensity,
+            box_size: 30.0,
+            fall_speed,
+            time: app.frame.time,
+            streak_length,
+            light: light.color.map(|c| c * light.intensity),
+            aspect: app.window.surface_config.width as f32
+                / app.window.surface_config.height.max(1) as f32,
+            ambient: light.ambient,
+            particle_count: self.particle_count,
+            flake_size,
+            snow: (weather.kind == WeatherKind::Snow) as u32,
+            seed: app.config.deterministic.seed,
+            _pad: 0,
+        }
+    }
+
+    /// Moves the particles and draws them into `target`.
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, app: &GfxState, target: &TextureView) {
+        let params = self.params(app);
+        frame_stats::write_buffer(
+            &app.gpu.queue,
+            &self.params_buffer,
+            0,
+            bytemuck::bytes_of(&params),
+        );
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("precipitation update"),
+                timestamp_writes: None,
+            });
+            compute_pass.set_pipeline(&self.update_pipeline);
+            compute_pass.set_bind_group(0, &self.uniforms_bind_group, &[]);
+            compute_pass.set_bind_group(1, &self.compute_bind_group, &[]);
+            compute_pass.dispatch_workgroups(self.particle_count.div_ceil(64), 1, 1);
+        }
+
+        let mut render_pass = CountedPass::begin(
+            encoder,
+            &wgpu::RenderPassDescriptor {
+                label: Some("precipitation pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                ..Default::default()
+            },
+        );
+        render_pass.set_pipeline(&self.streak_pipeline);
+        render_pass.set_bind_group(0, &self.uniforms_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.render_bind_group, &[]);
+        render_pass.draw(0..6, 0..self.particle_count);
+    }
+
+    /// Water on the lens, drawn last over the scene.
+    pub fn render_droplets(&self, encoder: &mut wgpu::CommandEncoder, target: &TextureView) {
+        let mut render_pass = CountedPass::begin(
+            encoder,
+            &wgpu::RenderPassDescriptor {
+                label: Some("droplet pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                ..Default::default()
+            },
+        );
+        render_pass.set_pipeline(&self.droplet_pipeline);
+        render_pass.set_bind_group(0, &self.uniforms_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct SnowCoverParams {
+    center: [f32; 2],
+    extent: f32,
+    dt: f32,
+    rate: f32,
+    time: f32,
+    _pad: [f32; 2],
+}
+
+const SNOW_COVER_SIZE: u32 = 256;
+/// Meters of ground covered by the accumulation texture, centered on the world origin.
+pub const SNOW_COVER_EXTENT: f32 = 128.0;
+const SNOW_COVER_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+struct Accumulate {
+    // written by the compute pass, then copied back into the sampled texture
+    scratch: Texture,
+    params_buffer: Buffer,
+    bind_group: BindGroup,
+    pipeline: ComputePipeline,
+}
+
+/// World space snow depth on the ground, sampled by the ground shading to whiten
+/// upward facing surfaces. Grows while it snows and melts otherwise.
+pub struct SnowCover {
+    pub view: TextureView,
+    pub sampler: Sampler,
+    texture: Texture,
+    // None without compute: the cover stays bare
+    accumulate: Option<Accumulate>,
+}
+
+impl SnowCover {
+    pub fn new(app: &GfxState) -> Self {
+        let device = &app.gpu.device;
+        let size = wgpu::Extent3d {
+            width: SNOW_COVER_SIZE,
+            height: SNOW_COVER_SIZE,
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("snow cover"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: SNOW_COVER_FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("snow cover sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let accumulate = app.gpu.budget.compute.then(|| {
+            let code = wgsl!("snow_cover.wgsl");
+            let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("snow cover shader"),
+                source: wgpu::ShaderSource::Wgsl(code),
+            });
+            let scratch = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("snow cover scratch"),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: SNOW_COVER_FORMAT,
+                usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
+                view_formats: &[],
+            });
+            let scratch_view = scratch.create_view(&wgpu::TextureViewDescriptor::default());
+            let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("snow cover params"),
+                size: std::mem::size_of::<SnowCoverParams>() as u64,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+
+            let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("snow cover layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::StorageTexture {
+                            access: wgpu::StorageTextureAccess::WriteOnly,
+                            format: SNOW_COVER_FORMAT,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("snow cover bind group"),
+                layout: &layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: params_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(&view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::TextureView(&scratch_view),
+                    },
+                ],
+            });
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("snow cover pipeline layout"),
+                bind_group_layouts: &[&layout],
+                push_constant_ranges: &[],
+            });
+            let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("snow cover accumulate"),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point: "accumulate",
+                compilation_options: PipelineCompilationOptions::default(),
+            });
+            Accumulate {
+                scratch,
+                params_buffer,
+                bind_group,
+                pipeline,
+            }
+        });
+
+        Self {
+            view,
+            sampler,
+            texture,
+            accumulate,
+        }
+    }
+
+    /// Snow falls in, or melts away, before the ground samples it this frame.
+    pub fn encode(&self, encoder: &mut wgpu::CommandEncoder, app: &GfxState) {
+        let Some(accumulate) = &self.accumulate else {
+            return;
+        };
+        let weather = &app.scene.weather;
+        // about a minute to fully cover at full intensity, a bit longer to melt
+        let rate = if weather.kind == WeatherKind::Snow {
+            weather.intensity / 60.0
+        } else {
+            -1.0 / 90.0
+        };
+        let params = SnowCoverParams {
+            center: [0.0; 2],
+            extent: SNOW_COVER_EXTENT,
+            dt: app.frame.dt,
+            rate,
+            time: app.frame.time,
+            _pad: [0.0; 2],
+        };
+        frame_stats::write_buffer(
+            &app.gpu.queue,
+            &accumulate.params_buffer,
+            0,
+            bytemuck::bytes_of(&params),
+        );
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("snow cover accumulate"),
+                timestamp_writes: None,
+            });
+            compute_pass.set_pipeline(&accumulate.pipeline);
+            compute_pass.set_bind_group(0, &accumulate.bind_group, &[]);
+            compute_pass.dispatch_workgroups(SNOW_COVER_SIZE / 8, SNOW_COVER_SIZE / 8, 1);
+        }
+        encoder.copy_texture_to_texture(
+            accumulate.scratch.as_image_copy(),
+            self.texture.as_image_copy(),
+            self.texture.size(),
+        );
+    }
+}
@@ -0,0 +1,212 @@
+// Runs WGSL functions on the GPU so tests can compare them with CPU references. The
+// functions are cut out of the real shader sources with `extract`, wrapped in a compute
+// shader that calls a test body once per input, and fed through storage buffers:
+//
+//     let source = extract(SKY, &["srgb_to_linear"]);
+//     let out = harness.run(&source, "return vec4(srgb_to_linear(x.rgb), x.a);", &inputs);
+//
+// Inputs and outputs are a vec4f each, the body sees its input as `x`.
+
+use anyhow::{bail, Context};
+use std::borrow::Cow;
+use wgpu::util::DeviceExt;
+
+use crate::readback;
+
+const WORKGROUP_SIZE: u32 = 64;
+
+pub struct WgslHarness {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+}
+
+impl WgslHarness {
+    /// A device on the fallback (software) adapter, so results don't depend on the GPU.
+    /// None where there isn't one, tests skip then.
+    pub fn new() -> Option<Self> {
+        match pollster::block_on(Self::request()) {
+            Ok(harness) => Some(harness),
+            Err(e) => {
+                println!("Skipped, no fallback adapter: {:#}", e);
+                None
+            }
+        }
+    }
+
+    async fn request() -> anyhow::Result<Self> {
+        let instance = wgpu::Instance::default();
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::default(),
+                force_fallback_adapter: true,
+                compatible_surface: None,
+            })
+            .await
+            .context("no fallback adapter")?;
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: Some("wgsl harness"),
+                    required_features: wgpu::Features::empty(),
+                    required_limits: wgpu::Limits::downlevel_defaults(),
+                },
+                None,
+            )
+            .await?;
+        Ok(Self { device, queue })
+    }
+
+    /// Runs `body`, the body of a `fn(x: vec4f) -> vec4f`, over `inputs` with `source`'s
+    /// declarations in scope. Shader errors panic through wgpu's error handler.
+    pub fn run(&self, source: &str, body: &str, inputs: &[[f32; 4]]) -> Vec<[f32; 4]> {
+        let code = format!(
+            "@group(0) @binding(0) var<storage, read> inputs: array<vec4f>;
+@group(0) @binding(1) var<storage, read_write> outputs: array<vec4f>;
+
+{source}
+
+fn test_body(x: vec4f) -> vec4f {{
+{body}
+}}
+
+@compute @workgroup_size({WORKGROUP_SIZE})
+fn main(@builtin(global_invocation_id) id: vec3u) {{
+    if id.x < arrayLength(&inputs) {{
+        outputs[id.x] = test_body(inputs[id.x]);
+    }}
+}}
+"
+        );
+        let device = &self.device;
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("wgsl harness"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Owned(code)),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("wgsl harness"),
+            layout: None,
+            module: &module,
+            entry_point: "main",
+            compilation_options: Default::default(),
+        });
+
+        let input = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("wgsl harness inputs"),
+            contents: bytemuck::cast_slice(inputs),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let size = std::mem::size_of_val(inputs) as u64;
+        let output = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("wgsl harness outputs"),
+            size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("wgsl harness"),
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: input.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: output.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("wgsl harness"),
+        });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("wgsl harness"),
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(&pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups((inputs.len() as u32).div_ceil(WORKGROUP_SIZE), 1, 1);
+        }
+        self.queue.submit([encoder.finish()]);
+
+        let bytes = readback::wait(
+            device,
+            readback::read_buffer(device, &self.queue, &output, 0..size),
+        )
+        .unwrap();
+        bytes
+            .chunks_exact(16)
+            .map(bytemuck::pod_read_unaligned)
+            .collect()
+    }
+}
+
+/// Cuts the named top level `fn`, `struct` and `const` declarations out of `shader`, in
+/// the order given. A test can leave out a dependency and declare its own stand-in.
+pub fn extract(shader: &str, names: &[&str]) -> String {
+    names
+        .iter()
+        .map(|name| declaration(shader, name).unwrap_or_else(|e| panic!("{:#}", e)))
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+fn declaration<'a>(shader: &'a str, name: &str) -> anyhow::Result<&'a str> {
+    let start = ["fn", "struct", "const"]
+        .iter()
+        .find_map(|keyword| {
+            let head = format!("{} {}", keyword, name);
+            shader.match_indices(&head).map(|(i, _)| i).find(|&i| {
+                // the whole name, at the start of a line
+                let after = shader[i + head.len()..].chars().next();
+                (i == 0 || shader[..i].ends_with('\n'))
+                    && !after.is_some_and(|c| c.is_alphanumeric() || c == '_')
+            })
+        })
+        .with_context(|| format!("no declaration of {}", name))?;
+    let text = &shader[start..];
+    if text.starts_with("const") {
+        let end = text.find(';').context("const without ;")?;
+        return Ok(&text[..=end]);
+    }
+    let open = text.find('{').context("declaration without a body")?;
+    let mut depth = 0;
+    for (i, c) in text[open..].char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(&text[..open + i + 1]);
+                }
+            }
+            _ => {}
+        }
+    }
+    bail!("unbalanced braces in {}", name)
+}
+
+/// Panics on the first component further than `tolerance` from the reference, relative for
+/// values above 1.
+pub fn assert_close(
+    what: &str,
+    inputs: &[[f32; 4]],
+    gpu: &[[f32; 4]],
+    cpu: &[[f32; 4]],
+    tolerance: f32,
+) {
+    assert_eq!(gpu.len(), cpu.len(), "{}: output count", what);
+    for ((input, gpu), cpu) in inputs.iter().zip(gpu).zip(cpu) {
+        let close = gpu
+            .iter()
+            .zip(cpu)
+            .all(|(g, c)| (g - c).abs() <= tolerance * c.abs().max(1.0));
+        assert!(
+            close,
+            "{} of {:?}: gpu {:?}, cpu {:?}",
+            what, input, gpu, cpu
+        );
+    }
+}
@@ -0,0 +1,197 @@
+use std::{borrow::Cow, collections::HashSet};
+
+use crate::{error, hot_reload::wgsl};
+
+/// A WGSL file from asset/ with its directives resolved, one per line:
+///
+///     #include "camera.wgsl"    the file's code, baked in from asset/, once per shader
+///     #define NAME              on top of the defines the loader was given
+///     #ifdef NAME / #ifndef NAME / #else / #endif
+///
+/// Every line of `code` remembers the file and line it came from, for errors.
+#[derive(Debug, Default)]
+pub struct Preprocessed {
+    pub code: String,
+    files: Vec<String>,
+    // into `files`, and the line there counting from 1, for each line of `code`
+    origins: Vec<(usize, u32)>,
+}
+
+impl Preprocessed {
+    /// Where line `line` of `code`, counting from 1, came from.
+    pub fn origin(&self, line: u32) -> Option<(&str, u32)> {
+        let &(file, line) = self.origins.get((line as usize).checked_sub(1)?)?;
+        Some((&self.files[file], line))
+    }
+
+    /// Parses `code` as wgpu will, an error pointing into the file the line came from.
+    pub fn check(&self) -> Result<(), String> {
+        let Err(e) = wgpu::naga::front::wgsl::parse_str(&self.code) else {
+            return Ok(());
+        };
+        let location = e
+            .location(&self.code)
+            .and_then(|location| {
+                let (file, line) = self.origin(location.line_number)?;
+                Some(format!("{}:{}:{}", file, line, location.line_position))
+            })
+            .unwrap_or_else(|| self.files[0].clone());
+        Err(format!("{}: {}", location, e.message()))
+    }
+}
+
+/// Preprocesses `asset/<file>` with `defines` set.
+pub fn load(file: &str, defines: &[&str]) -> Result<Preprocessed, String> {
+    let mut preprocessor = Preprocessor {
+        defines: defines.iter().map(|define| define.to_string()).collect(),
+        included: HashSet::new(),
+        out: Preprocessed::default(),
+    };
+    preprocessor.include(file, None)?;
+    Ok(preprocessor.out)
+}
+
+/// The code of `asset/<file>` preprocessed with `defines` and parsed, for a shader
+/// module. An error names the file and line it went wrong in.
+pub fn code(file: &str, defines: &[&str]) -> error::Result<String> {
+    let preprocessed = load(file, defines).map_err(error::Error::Shader)?;
+    preprocessed.check().map_err(error::Error::Shader)?;
+    Ok(preprocessed.code)
+}
+
+// every file of asset/, baked in through `wgsl!` so a hot reloaded one shows; a new file
+// has to be added to the list
+macro_rules! baked {
+    ($file:expr, [$($name:literal,)*]) => {
+        match $file {
+            $($name => Some(wgsl!($name)),)*
+            _ => None,
+        }
+    };
+}
+
+fn read(file: &str) -> Option<Cow<'static, str>> {
+    baked!(
+        file,
+        [
+            "accumulate.wgsl",
+            "ao_bake.wgsl",
+            "auto_exposure.wgsl",
+            "bloom.wgsl",
+            "boids.wgsl",
+            "bvh.wgsl",
+            "camera.wgsl",
+            "cull.wgsl",
+            "debug_blit.wgsl",
+            "deferred.wgsl",
+            "denoise.wgsl",
+            "flare.wgsl",
+            "fxaa.wgsl",
+            "heat_haze.wgsl",
+            "ibl.wgsl",
+            "irradiance.wgsl",
+            "lighting.wgsl",
+            "lightmap.wgsl",
+            "mesh.wgsl",
+            "particles.wgsl",
+            "path_tracer.wgsl",
+            "post.wgsl",
+            "precipitation.wgsl",
+            "resample.wgsl",
+            "sdf.wgsl",
+            "sdf_scene.wgsl",
+            "shadertoy.wgsl",
+            "shadertoy_example.wgsl",
+            "shadow.wgsl",
+            "sharpen.wgsl",
+            "sky.wgsl",
+            "skybox.wgsl",
+            "snow_cover.wgsl",
+            "ssr.wgsl",
+            "text.wgsl",
+            "tonemap.wgsl",
+            "upscale.wgsl",
+        ]
+    )
+}
+
+struct Preprocessor {
+    defines: HashSet<String>,
+    // a file included again adds nothing, so shared chunks can include what they need
+    included: HashSet<String>,
+    out: Preprocessed,
+}
+
+impl Preprocessor {
+    // `from` is the file and line of the #include
+    fn include(&mut self, file: &str, from: Option<(&str, u32)>) -> Result<(), String> {
+        if !self.included.insert(file.to_string()) {
+            return Ok(());
+        }
+        let source = read(file).ok_or_else(|| match from {
+            Some((from, line)) => format!("{}:{}: can't read {}", from, line, file),
+            None => format!("can't read {}", file),
+        })?;
+        let index = self.out.files.len();
+        self.out.files.push(file.to_string());
+        // for each open #ifdef, whether its lines are kept and whether its #else came
+        let mut conditions: Vec<(bool, bool)> = vec![];
+        for (number, line) in source.lines().enumerate() {
+            let number = number as u32 + 1;
+            let at = |message: &str| format!("{}:{}: {}", file, number, message);
+            let active = conditions.iter().all(|&(kept, _)| kept);
+            let Some(directive) = line.trim_start().strip_prefix('#') else {
+                if active {
+                    self.out.code.push_str(line);
+                    self.out.code.push('\n');
+                    self.out.origins.push((index, number));
+                }
+                continue;
+            };
+            let (name, argument) = directive
+                .split_once(char::is_whitespace)
+                .map(|(name, argument)| (name, argument.trim()))
+                .unwrap_or((directive.trim(), ""));
+            match name {
+                "ifdef" | "ifndef" if argument.is_empty() => {
+                    return Err(at(&format!("#{} needs a name", name)))
+                }
+                "ifdef" | "ifndef" => {
+                    let defined = self.defines.contains(argument);
+                    conditions.push((defined == (name == "ifdef"), false));
+                }
+                "else" => match conditions.last_mut() {
+                    Some((_, true)) => return Err(at("a second #else")),
+                    Some((kept, seen_else)) => {
+                        *kept = !*kept;
+                        *seen_else = true;
+                    }
+                    None => return Err(at("#else without #ifdef")),
+                },
+                "endif" => {
+                    if conditions.pop().is_none() {
+                        return Err(at("#endif without #ifdef"));
+                    }
+                }
+                // left out along with the lines around them
+                _ if !active => {}
+                "define" if argument.is_empty() => return Err(at("#define needs a name")),
+                "define" => {
+                    self.defines.insert(argument.to_string());
+                }
+                "include" => {
+                    let included = argument
+                        .strip_prefix('"')
+                        .and_then(|argument| argument.strip_suffix('"'))
+                        .ok_or_else(|| at("#include needs a \"file\""))?;
+                    self.include(included, Some((file, number)))?;
+                }
+                _ => return Err(at(&format!("unknown directive #{}", name))),
+            }
+        }
+        match conditions.is_empty() {
+            true => Ok(()),
+            false => Err(format!("{}: #ifdef without #endif", file)),
+        }
+    }
+}
@@ -0,0 +1,550 @@
+// The sky model, tonemapping curves, noise, SDF primitives and BVH traversal run on the
+// GPU through `WgslHarness` and checked against CPU versions of the same formulas.
+
+#[cfg(feature = "path_tracing")]
+use crate::{bvh::Bvh, cpu_tests::boxes_in_a_row, static_geometry};
+use crate::{
+    image_data,
+    wgsl_harness::{assert_close, extract, WgslHarness},
+    wgsl_preprocessor,
+};
+
+const SKY: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/asset/sky.wgsl"));
+const TONEMAP: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/asset/tonemap.wgsl"));
+
+fn map(inputs: &[[f32; 4]], f: impl Fn([f32; 4]) -> [f32; 4]) -> Vec<[f32; 4]> {
+    inputs.iter().copied().map(f).collect()
+}
+
+fn rgb(f: impl Fn(f32) -> f32, x: [f32; 4]) -> [f32; 4] {
+    [f(x[0]), f(x[1]), f(x[2]), x[3]]
+}
+
+#[test]
+fn srgb_curves_match_the_cpu() {
+    let Some(harness) = WgslHarness::new() else {
+        return;
+    };
+    let inputs: Vec<[f32; 4]> = (0..=64)
+        .map(|i| {
+            let v = i as f32 / 64.0;
+            [v, v * v, 0.002 * v, 1.0]
+        })
+        .collect();
+
+    let gpu = harness.run(
+        &extract(SKY, &["srgb_to_linear"]),
+        "return vec4(srgb_to_linear(x.rgb), x.a);",
+        &inputs,
+    );
+    let cpu = map(&inputs, |x| rgb(image_data::srgb_to_linear, x));
+    assert_close("srgb_to_linear", &inputs, &gpu, &cpu, 1e-5);
+
+    let gpu = harness.run(
+        &extract(TONEMAP, &["linear_to_srgb"]),
+        "return vec4(linear_to_srgb(x.rgb), x.a);",
+        &inputs,
+    );
+    let cpu = map(&inputs, |x| rgb(image_data::linear_to_srgb, x));
+    assert_close("linear_to_srgb", &inputs, &gpu, &cpu, 1e-5);
+}
+
+fn aces_fitted(x: f32) -> f32 {
+    let (a, b, c, d, e) = (2.51, 0.03, 2.43, 0.59, 0.14);
+    ((x * (a * x + b)) / (x * (c * x + d) + e)).clamp(0.0, 1.0)
+}
+
+#[test]
+fn aces_matches_the_cpu() {
+    let Some(harness) = WgslHarness::new() else {
+        return;
+    };
+    // across the HDR range the exposure can leave
+    let inputs: Vec<[f32; 4]> = (0..=128)
+        .map(|i| {
+            let v = (i as f32 / 8.0 - 10.0).exp2();
+            [v, v * 0.5, v * 4.0, 0.0]
+        })
+        .collect();
+    let gpu = harness.run(
+        &extract(TONEMAP, &["aces_fitted"]),
+        "return vec4(aces_fitted(x.rgb), x.a);",
+        &inputs,
+    );
+    let cpu = map(&inputs, |x| rgb(aces_fitted, x));
+    assert_close("aces_fitted", &inputs, &gpu, &cpu, 1e-5);
+}
+
+#[test]
+fn reinhard_matches_the_cpu() {
+    let Some(harness) = WgslHarness::new() else {
+        return;
+    };
+    let inputs: Vec<[f32; 4]> = (0..=128)
+        .map(|i| {
+            let v = (i as f32 / 8.0 - 10.0).exp2();
+            [v, v * 0.5, v * 4.0, 0.0]
+        })
+        .collect();
+    let gpu = harness.run(
+        &extract(TONEMAP, &["reinhard"]),
+        "return vec4(reinhard(x.rgb), x.a);",
+        &inputs,
+    );
+    let cpu = map(&inputs, |x| rgb(|v| v / (1.0 + v), x));
+    assert_close("reinhard", &inputs, &gpu, &cpu, 1e-5);
+}
+
+// ---- Preetham sky, as in sky.wgsl ----
+
+type Perez = [[f32; 3]; 5];
+
+fn perez_coefficients(t: f32) -> Perez {
+    [
+        [
+            0.1787 * t - 1.4630,
+            -0.0193 * t - 0.2592,
+            -0.0167 * t - 0.2608,
+        ],
+        [
+            -0.3554 * t + 0.4275,
+            -0.0665 * t + 0.0008,
+            -0.0950 * t + 0.0092,
+        ],
+        [
+            -0.0227 * t + 5.3251,
+            -0.0004 * t + 0.2125,
+            -0.0079 * t + 0.2102,
+        ],
+        [
+            0.1206 * t - 2.5771,
+            -0.0641 * t - 0.8989,
+            -0.0441 * t - 1.6537,
+        ],
+        [
+            -0.0670 * t + 0.3703,
+            -0.0033 * t + 0.0452,
+            -0.0109 * t + 0.0529,
+        ],
+    ]
+}
+
+fn perez(p: &Perez, cos_theta: f32, gamma: f32, cos_gamma: f32) -> [f32; 3] {
+    let [a, b, c, d, e] = p;
+    [0usize, 1, 2].map(|i| {
+        (1.0 + a[i] * (b[i] / cos_theta).exp())
+            * (1.0 + c[i] * (d[i] * gamma).exp() + e[i] * cos_gamma * cos_gamma)
+    })
+}
+
+fn zenith_yxy(t: f32, theta_s: f32) -> [f32; 3] {
+    let chi = (4.0 / 9.0 - t / 120.0) * (std::f32::consts::PI - 2.0 * theta_s);
+    let big_y = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+    let th = [theta_s.powi(3), theta_s * theta_s, theta_s, 1.0];
+    let dot = |v: [f32; 4]| v.iter().zip(th).map(|(a, b)| a * b).sum::<f32>();
+    let x = t * t * dot([0.00166, -0.00375, 0.00209, 0.0])
+        + t * dot([-0.02903, 0.06377, -0.03202, 0.00394])
+        + dot([0.11693, -0.21196, 0.06052, 0.25886]);
+    let y = t * t * dot([0.00275, -0.00610, 0.00317, 0.0])
+        + t * dot([-0.04214, 0.08970, -0.04153, 0.00516])
+        + dot([0.15346, -0.26756, 0.06670, 0.26688]);
+    [big_y, x, y]
+}
+
+fn yxy_to_linear_srgb([big_y, x, y]: [f32; 3]) -> [f32; 3] {
+    let xyz = [x / y * big_y, big_y, (1.0 - x - y) / y * big_y];
+    let dot = |v: [f32; 3]| v.iter().zip(xyz).map(|(a, b)| a * b).sum::<f32>();
+    [
+        dot([3.2406, -1.5372, -0.4986]),
+        dot([-0.9689, 1.8758, 0.0415]),
+        dot([0.0557, -0.2040, 1.0570]),
+    ]
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn normalize(v: [f32; 3]) -> [f32; 3] {
+    let length = v.iter().map(|c| c * c).sum::<f32>().sqrt();
+    v.map(|c| c / length)
+}
+
+fn sky_radiance(dir: [f32; 3], sun: [f32; 3], turbidity: f32, intensity: f32) -> [f32; 3] {
+    let theta_s = sun[1].clamp(0.01, 1.0).acos();
+    let cos_theta = dir[1].max(0.001);
+    let cos_gamma = dir
+        .iter()
+        .zip(sun)
+        .map(|(a, b)| a * b)
+        .sum::<f32>()
+        .clamp(-1.0, 1.0);
+    let gamma = cos_gamma.acos();
+
+    let p = perez_coefficients(turbidity);
+    let zenith = zenith_yxy(turbidity, theta_s);
+    let view = perez(&p, cos_theta, gamma, cos_gamma);
+    let at_zenith = perez(&p, 1.0, theta_s, theta_s.cos());
+    let mut yxy = [0usize, 1, 2].map(|i| zenith[i] * view[i] / at_zenith[i]);
+    yxy[0] *= intensity * smoothstep(-0.12, 0.02, sun[1]);
+    yxy_to_linear_srgb(yxy).map(|c| c.max(0.0))
+}
+
+#[test]
+fn sky_model_matches_the_cpu() {
+    let Some(harness) = WgslHarness::new() else {
+        return;
+    };
+    // the sky uniform reduced to what sky_radiance reads
+    let source = format!(
+        "struct Sky {{
+    sun_direction: vec3f,
+    turbidity: f32,
+    intensity: f32,
+}}
+var<private> sky: Sky;
+
+{}",
+        extract(
+            SKY,
+            &[
+                "PI",
+                "Perez",
+                "perez_coefficients",
+                "perez",
+                "zenith_yxy",
+                "yxy_to_linear_srgb",
+                "sun_direction",
+                "sky_radiance",
+            ],
+        )
+    );
+    let inputs: Vec<[f32; 4]> = (0..8)
+        .flat_map(|azimuth| {
+            (0..8).map(move |elevation| {
+                let azimuth = azimuth as f32 / 8.0 * std::f32::consts::TAU;
+                let elevation = 0.02 + elevation as f32 / 8.0 * 1.5;
+                [
+                    azimuth.cos() * elevation.cos(),
+                    elevation.sin(),
+                    azimuth.sin() * elevation.cos(),
+                    0.0,
+                ]
+            })
+        })
+        .collect();
+
+    for (sun, turbidity) in [
+        ([0.3, 0.6, 0.2], 2.5),
+        ([0.0, 1.0, 0.1], 2.0),
+        ([-0.8, 0.1, 0.3], 6.0),
+        ([0.5, 0.03, -0.5], 4.0),
+    ] {
+        let gpu = harness.run(
+            &source,
+            &format!(
+                "sky = Sky(vec3({:?}, {:?}, {:?}), {:?}, 1.0);
+    return vec4(sky_radiance(normalize(x.xyz)), 0.0);",
+                sun[0], sun[1], sun[2], turbidity
+            ),
+            &inputs,
+        );
+        let sun = normalize(sun);
+        let cpu = map(&inputs, |x| {
+            let [r, g, b] = sky_radiance(normalize([x[0], x[1], x[2]]), sun, turbidity, 1.0);
+            [r, g, b, 0.0]
+        });
+        assert_close(
+            &format!("sky_radiance, sun {:?} turbidity {}", sun, turbidity),
+            &inputs,
+            &gpu,
+            &cpu,
+            2e-3,
+        );
+    }
+}
+
+// ---- value noise and fbm ----
+
+// hash2 is fract(sin(large) * 43758.5), where sin's precision decides every digit that
+// survives, so GPUs disagree with each other and the CPU on it. It's checked for its
+// range, and the noise built on it is compared with this smooth hash standing in.
+const STAND_IN_HASH: &str = "fn hash2(p: vec2f) -> f32 {
+    return fract(p.x * 0.1234 + p.y * 0.5678);
+}";
+
+fn hash2(p: [f32; 2]) -> f32 {
+    let v = p[0] * 0.1234 + p[1] * 0.5678;
+    v - v.floor()
+}
+
+fn mix(a: f32, b: f32, t: f32) -> f32 {
+    a * (1.0 - t) + b * t
+}
+
+fn value_noise(p: [f32; 2]) -> f32 {
+    let i = p.map(f32::floor);
+    let f = [p[0] - i[0], p[1] - i[1]];
+    let u = f.map(|f| f * f * (3.0 - 2.0 * f));
+    mix(
+        mix(hash2(i), hash2([i[0] + 1.0, i[1]]), u[0]),
+        mix(
+            hash2([i[0], i[1] + 1.0]),
+            hash2([i[0] + 1.0, i[1] + 1.0]),
+            u[0],
+        ),
+        u[1],
+    )
+}
+
+fn fbm(p: [f32; 2]) -> f32 {
+    let mut value = 0.0;
+    let mut amplitude = 0.5;
+    let mut q = p;
+    for _ in 0..5 {
+        value += amplitude * value_noise(q);
+        q = [q[0] * 2.03 + 17.1, q[1] * 2.03 + 9.2];
+        amplitude *= 0.5;
+    }
+    value
+}
+
+fn noise_inputs() -> Vec<[f32; 4]> {
+    (0..32)
+        .flat_map(|i| {
+            (0..32).map(move |j| [i as f32 * 0.37 - 5.0, j as f32 * 0.29 - 4.0, 0.0, 0.0])
+        })
+        .collect()
+}
+
+#[test]
+fn hash_stays_in_range() {
+    let Some(harness) = WgslHarness::new() else {
+        return;
+    };
+    let inputs = noise_inputs();
+    let gpu = harness.run(
+        &extract(SKY, &["hash2"]),
+        "return vec4(hash2(x.xy), hash2(floor(x.xy * 40.0)), 0.0, 0.0);",
+        &inputs,
+    );
+    for (input, output) in inputs.iter().zip(&gpu) {
+        assert!(
+            output[..2].iter().all(|h| (0.0..=1.0).contains(h)),
+            "hash2 of {:?}: {:?}",
+            input,
+            output
+        );
+    }
+}
+
+#[test]
+fn noise_matches_the_cpu() {
+    let Some(harness) = WgslHarness::new() else {
+        return;
+    };
+    let inputs = noise_inputs();
+    let source = format!(
+        "{}\n\n{}",
+        STAND_IN_HASH,
+        extract(SKY, &["value_noise", "fbm"])
+    );
+    let gpu = harness.run(
+        &source,
+        "return vec4(value_noise(x.xy), fbm(x.xy), 0.0, 0.0);",
+        &inputs,
+    );
+    let cpu = map(&inputs, |x| {
+        let p = [x[0], x[1]];
+        [value_noise(p), fbm(p), 0.0, 0.0]
+    });
+    assert_close("value_noise, fbm", &inputs, &gpu, &cpu, 1e-3);
+}
+
+fn length(v: [f32; 3]) -> f32 {
+    (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt()
+}
+
+fn sd_box(p: [f32; 3], half_size: [f32; 3]) -> f32 {
+    let q: [f32; 3] = std::array::from_fn(|i| p[i].abs() - half_size[i]);
+    length(q.map(|q| q.max(0.0))) + q[0].max(q[1]).max(q[2]).min(0.0)
+}
+
+fn sd_torus(p: [f32; 3], radii: [f32; 2]) -> f32 {
+    let ring = (p[0] * p[0] + p[2] * p[2]).sqrt() - radii[0];
+    (ring * ring + p[1] * p[1]).sqrt() - radii[1]
+}
+
+fn op_smooth_union(a: f32, b: f32, k: f32) -> f32 {
+    let h = (0.5 + 0.5 * (b - a) / k).clamp(0.0, 1.0);
+    mix(b, a, h) - k * h * (1.0 - h)
+}
+
+#[test]
+fn sdf_primitives_match_the_cpu() {
+    let Some(harness) = WgslHarness::new() else {
+        return;
+    };
+    // inside, on and outside the shapes, along the axes and off them
+    let inputs: Vec<[f32; 4]> = (0..125)
+        .map(|i| {
+            let axis = |n: i32| (n - 2) as f32 * 0.8;
+            [axis(i % 5), axis(i / 5 % 5), axis(i / 25), 0.0]
+        })
+        .collect();
+    let gpu = harness.run(
+        &wgsl_preprocessor::load("sdf.wgsl", &[]).unwrap().code,
+        "let p = x.xyz;
+        return vec4(
+            sd_sphere(p, 1.0),
+            sd_box(p, vec3(0.5, 1.0, 1.5)),
+            sd_torus(p, vec2(1.0, 0.25)),
+            op_smooth_union(sd_sphere(p, 1.0), sd_box(p - vec3(1.0, 0.0, 0.0), vec3(0.5)), 0.3),
+        );",
+        &inputs,
+    );
+    let cpu = map(&inputs, |x| {
+        let p = [x[0], x[1], x[2]];
+        let sphere = length(p) - 1.0;
+        let shifted = [p[0] - 1.0, p[1], p[2]];
+        [
+            sphere,
+            sd_box(p, [0.5, 1.0, 1.5]),
+            sd_torus(p, [1.0, 0.25]),
+            op_smooth_union(sphere, sd_box(shifted, [0.5; 3]), 0.3),
+        ]
+    });
+    assert_close("sdf primitives", &inputs, &gpu, &cpu, 1e-4);
+}
+
+#[test]
+fn sky_includes_the_camera_once_and_parses() {
+    let sky = wgsl_preprocessor::load("sky.wgsl", &[]).unwrap();
+    sky.check().unwrap();
+    assert_eq!(sky.code.matches("struct CameraUniform").count(), 1);
+    // errors in it point into the chunk it came from
+    let line = sky
+        .code
+        .lines()
+        .position(|line| line.starts_with("struct CameraUniform"))
+        .unwrap();
+    assert_eq!(sky.origin(line as u32 + 1), Some(("camera.wgsl", 2)));
+}
+
+#[test]
+fn bakes_every_asset_shader() {
+    let asset = concat!(env!("CARGO_MANIFEST_DIR"), "/asset");
+    for entry in std::fs::read_dir(asset).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|e| e == "wgsl") {
+            let file = path.file_name().unwrap().to_str().unwrap();
+            assert!(
+                wgsl_preprocessor::load(file, &[]).is_ok(),
+                "{} isn't baked",
+                file
+            );
+        }
+    }
+    // through lighting.wgsl, which includes the camera too
+    let mesh = wgsl_preprocessor::load("mesh.wgsl", &[]).unwrap();
+    mesh.check().unwrap();
+    assert_eq!(mesh.code.matches("struct CameraUniform").count(), 1);
+    wgsl_preprocessor::load("lightmap.wgsl", &[])
+        .unwrap()
+        .check()
+        .unwrap();
+}
+
+// the tree as private arrays, where the path tracer binds storage buffers
+#[cfg(feature = "path_tracing")]
+fn bvh_arrays(bvh: &Bvh) -> String {
+    let triangles: Vec<String> = bvh
+        .triangles
+        .iter()
+        .map(|t| {
+            let v = |v: [f32; 4]| format!("vec4f({:?}, {:?}, {:?}, {:?})", v[0], v[1], v[2], v[3]);
+            format!(
+                "Triangle({}, {}, {}, {})",
+                v(t.v0),
+                v(t.v1),
+                v(t.v2),
+                v(t.albedo)
+            )
+        })
+        .collect();
+    let nodes: Vec<String> = bvh
+        .nodes
+        .iter()
+        .map(|n| {
+            format!(
+                "BvhNode(vec3f({:?}, {:?}, {:?}), {}u, vec3f({:?}, {:?}, {:?}), {}u)",
+                n.min[0], n.min[1], n.min[2], n.left_first, n.max[0], n.max[1], n.max[2], n.count
+            )
+        })
+        .collect();
+    format!(
+        "var<private> triangles: array<Triangle, {}> = array({});
+var<private> bvh_nodes: array<BvhNode, {}> = array({});",
+        triangles.len(),
+        triangles.join(", "),
+        nodes.len(),
+        nodes.join(", ")
+    )
+}
+
+#[cfg(feature = "path_tracing")]
+#[test]
+fn bvh_traversal_finds_the_nearest_box() {
+    let Some(harness) = WgslHarness::new() else {
+        return;
+    };
+    let boxes = boxes_in_a_row();
+    let bvh = Bvh::build(static_geometry::triangles(&boxes));
+    let traversal = wgsl_preprocessor::load("bvh.wgsl", &[]).unwrap().code;
+    let source = format!(
+        "{}\n\n{}\n\n{}",
+        extract(
+            &traversal,
+            &["Triangle", "BvhNode", "NO_HIT", "BVH_STACK_SIZE", "BvhHit"]
+        ),
+        bvh_arrays(&bvh),
+        extract(
+            &traversal,
+            &["intersect_triangle", "intersect_node", "bvh_trace"]
+        ),
+    );
+    // rays down +z from in front of, between and past the boxes, some beside them; off
+    // the faces' diagonals so no ray grazes two triangles
+    let inputs: Vec<[f32; 4]> = [-10.0, -2.0, 2.0, 7.0]
+        .into_iter()
+        .flat_map(|z| {
+            [[0.3, 0.6], [-0.5, 0.2], [1.5, 0.0], [0.0, -1.7]].map(|[x, y]| [x, y, z, 0.0])
+        })
+        .collect();
+    let gpu = harness.run(
+        &source,
+        "let hit = bvh_trace(x.xyz, vec3(0.0, 0.0, 1.0), NO_HIT);
+        let albedo = select(-1.0, triangles[hit.triangle].albedo.x, hit.t < NO_HIT);
+        return vec4(hit.t, albedo, 0.0, 0.0);",
+        &inputs,
+    );
+    let cpu = map(&inputs, |[x, y, z, _]| {
+        let beside = x.abs() > 1.0 || y.abs() > 1.0;
+        // the first box whose front face is still ahead
+        let ahead = boxes
+            .iter()
+            .find(|b| b.center[2] - 1.0 > z)
+            .filter(|_| !beside);
+        match ahead {
+            Some(b) => [
+                b.center[2] - 1.0 - z,
+                image_data::srgb_to_linear(b.color[0]),
+                0.0,
+                0.0,
+            ],
+            None => [1e30, -1.0, 0.0, 0.0],
+        }
+    });
+    assert_close("bvh_trace", &inputs, &gpu, &cpu, 1e-4);
+}
@@ -0,0 +1,82 @@
+use cgmath::Vector3;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "ui")]
+use crate::console::{console_var, Console};
+
+/// Scene wide wind. Particles, clouds and anything that sways take it from here so they
+/// all agree on where the wind blows.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Wind {
+    /// degrees, where the wind blows towards. Same convention as the sun azimuth:
+    /// 0 = -z, 90 = +x
+    pub direction: f32,
+    /// m/s
+    pub strength: f32,
+    /// 0..1, how much the strength swings with gusts
+    pub gustiness: f32,
+}
+
+impl Default for Wind {
+    fn default() -> Self {
+        Self {
+            direction: 45.0,
+            strength: 2.0,
+            gustiness: 0.3,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct WindUniform {
+    /// horizontal, normalized
+    pub direction: [f32; 3],
+    /// with the current gust applied
+    pub strength: f32,
+    pub gustiness: f32,
+    /// current gust factor, around 1
+    pub gust: f32,
+    pub time: f32,
+    _pad: f32,
+}
+
+impl Wind {
+    #[cfg(feature = "ui")]
+    pub fn register_console(console: &mut Console) {
+        console_var!(console, "wind.direction", "degrees", scene.wind.direction);
+        console_var!(console, "wind.strength", "m/s", scene.wind.strength);
+        console_var!(console, "wind.gustiness", "0 .. 1", scene.wind.gustiness);
+    }
+
+    pub fn direction_vector(&self) -> Vector3<f32> {
+        let azimuth = self.direction.to_radians();
+        Vector3::new(azimuth.sin(), 0.0, -azimuth.cos())
+    }
+
+    /// A few detuned sines: slow swells with quicker gusts on top, averages to 1.
+    pub fn gust(&self, time: f32) -> f32 {
+        let swing = (time * 0.37).sin() * 0.5
+            + (time * 1.13 + 1.3).sin() * 0.3
+            + (time * 2.9 + 0.7).sin() * 0.2;
+        (1.0 + self.gustiness.clamp(0.0, 1.0) * swing).max(0.0)
+    }
+
+    /// m/s at `time`, gusts included.
+    pub fn velocity(&self, time: f32) -> Vector3<f32> {
+        self.direction_vector() * self.strength * self.gust(time)
+    }
+
+    pub fn uniform(&self, time: f32) -> WindUniform {
+        let gust = self.gust(time);
+        WindUniform {
+            direction: self.direction_vector().into(),
+            strength: self.strength * gust,
+            gustiness: self.gustiness,
+            gust,
+            time,
+            _pad: 0.0,
+        }
+    }
+}